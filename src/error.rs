@@ -1,7 +1,7 @@
 
 
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use thiserror::Error;
 
@@ -18,10 +18,22 @@ pub enum McError {
     RegionDataTooLarge,
     #[error("Invalid Compression value: {0}")]
     InvalidCompressionScheme(u8),
+    #[error("Unsupported custom compression scheme: {0}")]
+    UnsupportedCustomCompression(String),
     #[error("Out of range error.")]
     OutOfRange,
+    #[error("Y coordinate {0} is outside of the chunk's height range.")]
+    YOutOfRange(i64),
+    #[error("Block coordinate {0:?} is not in chunk {1:?}.")]
+    CoordOutOfChunk(crate::math::coord::BlockPos, crate::math::coord::ChunkPos),
     #[error("Failed to convert to UTF-8 string.")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
+    #[error("String is not valid Modified UTF-8.")]
+    InvalidModifiedUtf8,
+    #[error("String is {0} bytes long, but NBT strings can be at most 65535 bytes.")]
+    StringTooLong(usize),
+    #[error("NBT {0} length {1} exceeds the read limit of {2}.")]
+    LengthLimitExceeded(&'static str, usize, usize),
     #[error("Unsupported Tag ID: {0}")]
     UnsupportedTagId(u8),
     #[error("Encountered the End Tag ID marker.")]
@@ -30,8 +42,20 @@ pub enum McError {
     StreamSectorBoundaryError,
     #[error("Failed to allocate RegionSector.")]
     RegionAllocationFailure,
+    #[cfg(feature = "region")]
+    #[error("Attempted to free a sector that overlaps the header or is past the end of the used sectors: {0:?}")]
+    SectorOutOfBounds(crate::world::io::region::RegionSector),
+    #[cfg(feature = "region")]
+    #[error("Attempted to free a sector that is already free: {0:?}")]
+    SectorDoubleFree(crate::world::io::region::RegionSector),
     #[error("Region file is too small to contain a header.")]
     InvalidRegionFile,
+    #[error("Unsupported linear region format version: {0}")]
+    UnsupportedLinearVersion(u8),
+    #[cfg(feature = "region")]
+    #[error("{} chunk(s) in {0} do not match their checksum: {1:?}", .1.len())]
+    ChecksumMismatch(PathBuf, Vec<crate::world::io::region::RegionCoord>),
+    #[cfg(feature = "nbt")]
     #[error("Parse Error: {0}")]
     ParseError(#[from] crate::nbt::snbt::ParseError),
     #[error("There was an error decoding the NBT Tag.")]
@@ -40,8 +64,30 @@ pub enum McError {
     NotFoundInCompound(String),
     #[error("World Directory not found. {0}")]
     WorldDirectoryNotFound(PathBuf),
+    #[error("The world is in use by another process: {0}")]
+    WorldLocked(PathBuf),
+    #[error("Another process has taken the session lock: {0}")]
+    SessionLockLost(PathBuf),
+    #[error("A region file doesn't match the journal of the interrupted transaction: {0}")]
+    TransactionMismatch(PathBuf),
     #[error("Failed to save chunk.")]
     FailedToSaveChunk,
+    #[error("Chunk not found: {0:?}")]
+    ChunkNotFound(crate::math::coord::WorldCoord),
+    #[cfg(feature = "nbt")]
+    #[error("Nothing was found at tag path: {0}")]
+    TagPathNotFound(crate::nbt::tagpath::TagPath),
+    #[cfg(feature = "nbt")]
+    #[error("Expected {0:?} tag, found {1:?} tag.")]
+    TagTypeMismatch(crate::nbt::tag::TagID, crate::nbt::tag::TagID),
+    #[error("Key already exists in Compound.\n\"{0}\"")]
+    DuplicateKey(String),
+    /// An error with the chunk that it happened in (see [McResultExt::with_chunk]).
+    #[error("In chunk {0:?}: {1}")]
+    InChunk(crate::math::coord::WorldCoord, Box<McError>),
+    /// An error with the region file that it happened in (see [McResultExt::with_region]).
+    #[error("In region file {}: {1}", .0.display())]
+    InRegion(PathBuf, Box<McError>),
 }
 
 impl McError {
@@ -61,6 +107,140 @@ impl McError {
     pub fn custom<T, S: AsRef<str>>(msg: S) -> Result<T,Self> {
         Err(McError::Custom(msg.as_ref().to_owned()))
     }
+
+    /// Wraps the error with the chunk that it happened in.
+    pub fn with_chunk(self, coord: crate::math::coord::WorldCoord) -> Self {
+        McError::InChunk(coord, Box::new(self))
+    }
+
+    /// Wraps the error with the region file that it happened in.
+    pub fn with_region<P: AsRef<Path>>(self, path: P) -> Self {
+        McError::InRegion(path.as_ref().to_owned(), Box::new(self))
+    }
+
+    /// The error without the context added by [McError::with_chunk] and [McError::with_region].
+    pub fn root(&self) -> &McError {
+        match self {
+            McError::InChunk(_, err) | McError::InRegion(_, err) => err.root(),
+            err => err,
+        }
+    }
+
+    /// The chunk that the error happened in, if it was added with [McError::with_chunk].
+    pub fn chunk(&self) -> Option<crate::math::coord::WorldCoord> {
+        match self {
+            McError::InChunk(coord, _) => Some(*coord),
+            McError::InRegion(_, err) => err.chunk(),
+            _ => None,
+        }
+    }
+
+    /// The region file that the error happened in, if it was added with [McError::with_region].
+    pub fn region_file(&self) -> Option<&Path> {
+        match self {
+            McError::InRegion(path, _) => Some(path),
+            McError::InChunk(_, err) => err.region_file(),
+            _ => None,
+        }
+    }
+
+    /// The kind of the IO error, if this is one.
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self.root() {
+            McError::IoError(err) => Some(err.kind()),
+            _ => None,
+        }
+    }
+
+    /// True for a missing file, chunk, world, or tag.
+    pub fn is_not_found(&self) -> bool {
+        match self.root() {
+            McError::RegionDataNotFound
+            | McError::ChunkNotFound(_)
+            | McError::WorldDirectoryNotFound(_)
+            | McError::NotFoundInCompound(_) => true,
+            #[cfg(feature = "nbt")]
+            McError::TagPathNotFound(_) => true,
+            _ => self.io_kind() == Some(std::io::ErrorKind::NotFound),
+        }
+    }
+
+    /// True for data that can't be decoded: corrupt region files, chunks, and NBT.
+    pub fn is_corrupt(&self) -> bool {
+        match self.root() {
+            McError::InvalidRegionFile
+            | McError::InvalidCompressionScheme(_)
+            | McError::NbtDecodeError
+            | McError::UnsupportedTagId(_)
+            | McError::EndTagMarker
+            | McError::InvalidModifiedUtf8
+            | McError::LengthLimitExceeded(..) => true,
+            #[cfg(feature = "region")]
+            McError::ChecksumMismatch(..) => true,
+            #[cfg(feature = "nbt")]
+            McError::TagTypeMismatch(..) => true,
+            _ => self.io_kind() == Some(std::io::ErrorKind::UnexpectedEof),
+        }
+    }
+
+    /// True if the world is (or was) open in another process.
+    pub fn is_locked(&self) -> bool {
+        matches!(self.root(), McError::WorldLocked(_) | McError::SessionLockLost(_))
+    }
+}
+
+pub type McResult<T> = Result<T,McError>;
+
+/// Combinators for [McResult].
+pub trait McResultExt<T> {
+    /// Wraps the error with the chunk that it happened in (see [McError::with_chunk]).
+    fn with_chunk(self, coord: crate::math::coord::WorldCoord) -> McResult<T>;
+
+    /// Wraps the error with the region file that it happened in (see [McError::with_region]).
+    fn with_region<P: AsRef<Path>>(self, path: P) -> McResult<T>;
+
+    /// Turns an error that [is_not_found](McError::is_not_found) into `Ok(None)`.
+    fn optional(self) -> McResult<Option<T>>;
+}
+
+impl<T> McResultExt<T> for McResult<T> {
+    fn with_chunk(self, coord: crate::math::coord::WorldCoord) -> McResult<T> {
+        self.map_err(|err| err.with_chunk(coord))
+    }
+
+    fn with_region<P: AsRef<Path>>(self, path: P) -> McResult<T> {
+        self.map_err(|err| err.with_region(path))
+    }
+
+    fn optional(self) -> McResult<Option<T>> {
+        match self {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.is_not_found() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
 }
 
-pub type McResult<T> = Result<T,McError>;
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::coord::WorldCoord;
+
+    #[test]
+    fn context_test() {
+        let coord = WorldCoord::overworld(3, -2);
+        let result: McResult<()> = Err(McError::RegionDataNotFound);
+        let err = result.with_chunk(coord).with_region("region/r.0.-1.mca").unwrap_err();
+        assert!(err.is_not_found() && !err.is_corrupt());
+        assert!(matches!(err.root(), McError::RegionDataNotFound));
+        assert_eq!(err.chunk(), Some(coord));
+        assert_eq!(err.region_file(), Some(Path::new("region/r.0.-1.mca")));
+        assert!(err.to_string().starts_with("In region file region/r.0.-1.mca: In chunk"));
+
+        let missing: McResult<()> = Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        assert!(matches!(missing.optional(), Ok(None)));
+        let corrupt: McResult<()> = Err(McError::InvalidRegionFile);
+        assert!(corrupt.with_chunk(coord).optional().is_err_and(|err| err.is_corrupt()));
+        assert!(McError::WorldLocked(PathBuf::new()).is_locked());
+    }
+}
\ No newline at end of file
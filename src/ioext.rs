@@ -1,11 +1,69 @@
-use std::io::{
-    Cursor, Read, Seek, SeekFrom, Write
+use std::{
+    fs::File,
+    io::{
+        Cursor, Read, Seek, SeekFrom, Write
+    },
+    path::{Path, PathBuf},
 };
 
-use crate::McResult;
+use flate2::{
+    Compression,
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+};
+
+use crate::{McError, McResult};
+use crate::world::io::region::CompressionScheme;
 
+/// The default size of IO buffers. See [IoConfig].
 pub const BUFFERSIZE: usize = 8192;
 
+/// The sizes of the buffers used for reading and writing files.
+///
+/// [IoConfig::default] is [BUFFERSIZE] for both, unless the `MCUTIL_READ_BUF` or
+/// `MCUTIL_WRITE_BUF` environment variables are set to a size in bytes. The environment
+/// is read once, the first time a default is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoConfig {
+    /// The size of read buffers.
+    pub read_buf: usize,
+    /// The size of write buffers.
+    pub write_buf: usize,
+}
+
+impl IoConfig {
+    pub const READ_BUF_VAR: &'static str = "MCUTIL_READ_BUF";
+    pub const WRITE_BUF_VAR: &'static str = "MCUTIL_WRITE_BUF";
+
+    pub const fn new(read_buf: usize, write_buf: usize) -> Self {
+        Self { read_buf, write_buf }
+    }
+
+    /// Reads the sizes from the environment (ignoring the cached default).
+    pub fn from_env() -> Self {
+        Self::from_vars(
+            std::env::var(Self::READ_BUF_VAR).ok(),
+            std::env::var(Self::WRITE_BUF_VAR).ok(),
+        )
+    }
+
+    /// Sizes that are missing, zero, or not numbers fall back to [BUFFERSIZE].
+    fn from_vars(read_buf: Option<String>, write_buf: Option<String>) -> Self {
+        let parse = |value: Option<String>| value
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .filter(|&size| size > 0)
+            .unwrap_or(BUFFERSIZE);
+        Self::new(parse(read_buf), parse(write_buf))
+    }
+}
+
+impl Default for IoConfig {
+    fn default() -> Self {
+        static DEFAULT: std::sync::OnceLock<IoConfig> = std::sync::OnceLock::new();
+        *DEFAULT.get_or_init(Self::from_env)
+    }
+}
+
 /// For types that can be written to a writer.
 pub trait Writable {
     fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize>;
@@ -27,6 +85,9 @@ pub trait Seekable: Sized {
 
 pub trait WriteExt: Write + Sized {
     fn write_value<T: Writable>(&mut self, value: T) -> McResult<usize>;
+    /// Like [WriteExt::write_value], but takes the value by reference, so it also
+    /// works for unsized values such as `[u8]` and `str`.
+    fn write_all_value<T: Writable + ?Sized>(&mut self, value: &T) -> McResult<usize>;
 }
 
 pub trait ReadExt: Read + Sized {
@@ -37,6 +98,10 @@ impl<W: Write + Sized> WriteExt for W {
     fn write_value<T: Writable>(&mut self, value: T) -> McResult<usize> {
         value.write_to(self)
     }
+
+    fn write_all_value<T: Writable + ?Sized>(&mut self, value: &T) -> McResult<usize> {
+        value.write_to(self)
+    }
 }
 
 impl<R: Read + Sized> ReadExt for R {
@@ -45,6 +110,39 @@ impl<R: Read + Sized> ReadExt for R {
     }
 }
 
+// With the `nbt` feature, primitives are Readable and Writable through NbtRead and
+// NbtWrite. Without it, they are given the same big-endian implementations here.
+#[cfg(not(feature = "nbt"))]
+macro_rules! primitive_readwrite {
+    ($($primitive:ident)+) => {
+        $(
+            impl Readable for $primitive {
+                fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+                    let mut buf = [0u8; std::mem::size_of::<$primitive>()];
+                    reader.read_exact(&mut buf)?;
+                    Ok(Self::from_be_bytes(buf))
+                }
+            }
+
+            impl Writable for $primitive {
+                fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+                    writer.write_all(&self.to_be_bytes())?;
+                    Ok(std::mem::size_of::<$primitive>())
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(not(feature = "nbt"))]
+primitive_readwrite![
+    i8 u8
+    i16 u16
+    i32 u32 f32
+    i64 u64 f64
+    i128 u128
+];
+
 #[test]
 fn quick() {
     let mut buffer = Cursor::new(Vec::new());
@@ -67,11 +165,261 @@ impl<T: Seek + Sized> SeekExt for T {
     }
 }
 
+/// Decompresses a stream that was compressed with any [CompressionScheme], so that
+/// region chunks, `level.dat`, player files, and other files can share the same dispatch.
+pub enum MultiDecoder<R: Read> {
+    GZip(GzDecoder<R>),
+    ZLib(ZlibDecoder<R>),
+    Uncompressed(R),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, std::io::BufReader<R>>),
+}
+
+impl<R: Read> MultiDecoder<R> {
+    /// Creates a decoder for `scheme`. For [CompressionScheme::Custom], the name of the
+    /// scheme is read first (prefixed with its length as a u16, as in region files).
+    /// The only custom scheme that is supported is `minecraft:zstd`, with the `zstd` feature.
+    pub fn new(scheme: CompressionScheme, mut reader: R) -> McResult<Self> {
+        Ok(match scheme {
+            CompressionScheme::GZip => Self::GZip(GzDecoder::new(reader)),
+            CompressionScheme::ZLib => Self::ZLib(ZlibDecoder::new(reader)),
+            CompressionScheme::Uncompressed => Self::Uncompressed(reader),
+            CompressionScheme::Custom => {
+                let name_length: u16 = reader.read_value()?;
+                let mut name = vec![0u8; name_length as usize];
+                reader.read_exact(&mut name)?;
+                let name = String::from_utf8(name)?;
+                match name.as_str() {
+                    #[cfg(feature = "zstd")]
+                    crate::util::zstd::CUSTOM_SCHEME_NAME => Self::Zstd(zstd::stream::read::Decoder::new(reader)?),
+                    _ => return Err(McError::UnsupportedCustomCompression(name)),
+                }
+            },
+        })
+    }
+}
+
+impl<R: Read> Read for MultiDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            MultiDecoder::GZip(reader) => reader.read(buf),
+            MultiDecoder::ZLib(reader) => reader.read(buf),
+            MultiDecoder::Uncompressed(reader) => reader.read(buf),
+            #[cfg(feature = "zstd")]
+            MultiDecoder::Zstd(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Compresses a stream with any [CompressionScheme]. This is the counterpart of [MultiDecoder].
+/// Call [MultiEncoder::finish] when done, since compressed streams have to be terminated.
+pub enum MultiEncoder<W: Write> {
+    GZip(GzEncoder<W>),
+    ZLib(ZlibEncoder<W>),
+    Uncompressed(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> MultiEncoder<W> {
+    /// Creates an encoder for `scheme`. For [CompressionScheme::Custom], the name of the
+    /// scheme is written first (prefixed with its length as a u16, as in region files), and
+    /// the data is compressed with `minecraft:zstd` at its default level, which requires the
+    /// `zstd` feature. `compression` is the level of GZip and ZLib.
+    pub fn new(scheme: CompressionScheme, mut writer: W, compression: Compression) -> McResult<Self> {
+        Ok(match scheme {
+            CompressionScheme::GZip => Self::GZip(GzEncoder::new(writer, compression)),
+            CompressionScheme::ZLib => Self::ZLib(ZlibEncoder::new(writer, compression)),
+            CompressionScheme::Uncompressed => Self::Uncompressed(writer),
+            #[cfg(feature = "zstd")]
+            CompressionScheme::Custom => {
+                use crate::util::zstd::{CUSTOM_SCHEME_NAME, DEFAULT_LEVEL};
+                writer.write_value(CUSTOM_SCHEME_NAME.len() as u16)?;
+                writer.write_all(CUSTOM_SCHEME_NAME.as_bytes())?;
+                Self::Zstd(zstd::stream::write::Encoder::new(writer, DEFAULT_LEVEL)?)
+            },
+            #[cfg(not(feature = "zstd"))]
+            CompressionScheme::Custom => {
+                let _ = &mut writer;
+                return Err(McError::UnsupportedCustomCompression("minecraft:zstd".to_owned()));
+            },
+        })
+    }
+
+    /// Finishes the compressed stream and returns the writer.
+    pub fn finish(self) -> McResult<W> {
+        Ok(match self {
+            MultiEncoder::GZip(writer) => writer.finish()?,
+            MultiEncoder::ZLib(writer) => writer.finish()?,
+            MultiEncoder::Uncompressed(writer) => writer,
+            #[cfg(feature = "zstd")]
+            MultiEncoder::Zstd(writer) => writer.finish()?,
+        })
+    }
+}
+
+impl<W: Write> Write for MultiEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            MultiEncoder::GZip(writer) => writer.write(buf),
+            MultiEncoder::ZLib(writer) => writer.write(buf),
+            MultiEncoder::Uncompressed(writer) => writer.write(buf),
+            #[cfg(feature = "zstd")]
+            MultiEncoder::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            MultiEncoder::GZip(writer) => writer.flush(),
+            MultiEncoder::ZLib(writer) => writer.flush(),
+            MultiEncoder::Uncompressed(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            MultiEncoder::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// A compressed stream that has to be finished (terminated) once everything is written.
+pub trait FinishWrite: Write {
+    /// Finishes the stream, writing anything that is still buffered.
+    fn finish_write(self: Box<Self>) -> McResult<()>;
+}
+
+impl<W: Write> FinishWrite for MultiEncoder<W> {
+    fn finish_write(self: Box<Self>) -> McResult<()> {
+        self.finish()?;
+        Ok(())
+    }
+}
+
+/// A compression format for whole files, such as NBT files (`level.dat`, player data) and
+/// backup archives. The file helpers take a `&dyn Codec`, so that a format other than the
+/// built in [SchemeCodec]s can be plugged in.
+pub trait Codec: std::fmt::Debug {
+    /// Wraps `writer` in a stream that compresses what is written to it.
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> McResult<Box<dyn FinishWrite + 'a>>;
+
+    /// Wraps `reader` in a stream that decompresses what is read from it.
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> McResult<Box<dyn Read + 'a>>;
+}
+
+/// A [Codec] for a [CompressionScheme] (through [MultiEncoder] and [MultiDecoder]), with the
+/// level used by GZip and ZLib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemeCodec {
+    pub scheme: CompressionScheme,
+    pub compression: Compression,
+}
+
+impl SchemeCodec {
+    pub const fn new(scheme: CompressionScheme, compression: Compression) -> Self {
+        Self { scheme, compression }
+    }
+
+    pub const fn gzip(compression: Compression) -> Self {
+        Self::new(CompressionScheme::GZip, compression)
+    }
+
+    pub const fn zlib(compression: Compression) -> Self {
+        Self::new(CompressionScheme::ZLib, compression)
+    }
+
+    pub const fn uncompressed() -> Self {
+        Self::new(CompressionScheme::Uncompressed, Compression::new(0))
+    }
+}
+
+impl Codec for SchemeCodec {
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> McResult<Box<dyn FinishWrite + 'a>> {
+        Ok(Box::new(MultiEncoder::new(self.scheme, writer, self.compression)?))
+    }
+
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> McResult<Box<dyn Read + 'a>> {
+        Ok(Box::new(MultiDecoder::new(self.scheme, reader)?))
+    }
+}
+
+/// The scheme at the default level.
+impl Codec for CompressionScheme {
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> McResult<Box<dyn FinishWrite + 'a>> {
+        SchemeCodec::new(*self, Compression::default()).encoder(writer)
+    }
+
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> McResult<Box<dyn Read + 'a>> {
+        SchemeCodec::new(*self, Compression::default()).decoder(reader)
+    }
+}
+
 /// Copies bytes from a reader into a writer
 pub fn copy_bytes<R: Read, W: Write>(reader: &mut R, writer: &mut W, count: u64) -> std::io::Result<u64> {
     std::io::copy(&mut reader.take(count), writer)
 }
 
+/// The temporary file that [atomic_replace] writes before replacing `path`:
+/// `.{file name}.tmp` in the same directory.
+pub fn temp_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Replaces the file at `path` with what `write` writes, so that readers see either
+/// the old file or the new one, never a partial file.
+///
+/// `write` writes to a temporary file in the same directory ([temp_path]), which is
+/// synced and then renamed over `path`. If the rename fails because the two are on
+/// different devices, the temporary file is copied over `path` instead (which is not atomic).
+/// If `write` fails, the temporary file is removed and `path` is left alone.
+pub fn atomic_replace<P, R, F>(path: P, write: F) -> McResult<R>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> McResult<R>,
+{
+    let path = path.as_ref();
+    let temp = temp_path(path);
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        let result = write(&mut file)?;
+        file.sync_all()?;
+        Ok(result)
+    })();
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            return Err(err);
+        }
+    };
+    match std::fs::rename(&temp, path) {
+        Ok(()) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(&temp, path)?;
+            std::fs::remove_file(&temp)?;
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            return Err(err.into());
+        }
+    }
+    // Sync the directory so that the rename itself is durable.
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        sync_directory(parent)?;
+    }
+    Ok(result)
+}
+
+/// Syncs a directory, so that files created, renamed, or removed in it stay that way after a crash.
+/// Does nothing outside of Unix, where directories can't be synced this way.
+pub fn sync_directory<P: AsRef<Path>>(directory: P) -> McResult<()> {
+    #[cfg(unix)]
+    File::open(directory)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = directory;
+    Ok(())
+}
 
 pub trait WriteZeroes {
     fn write_zeroes(&mut self, count: u64) -> std::io::Result<u64>;
@@ -103,4 +451,181 @@ impl Writable for WriteNothing {
     fn write_to<W: Write>(&self, _: &mut W) -> Result<usize,crate::McError> {
         Ok(0)
     }
-}
\ No newline at end of file
+}
+// Byte payloads are written raw, with no length. Use [LengthPrefixed] to store
+// the length along with the bytes. (Note that `String` and `&str` are already
+// [Readable]/[Writable] through NBT, which writes them with a 16-bit length.
+// Use [RawString] to write a string without a length.)
+
+impl Writable for [u8] {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        writer.write_all(self)?;
+        Ok(self.len())
+    }
+}
+
+impl Writable for &[u8] {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        (**self).write_to(writer)
+    }
+}
+
+impl Writable for Vec<u8> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        self.as_slice().write_to(writer)
+    }
+}
+
+impl Readable for Vec<u8> {
+    /// Reads until the end of the reader.
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl<T: Writable, const N: usize> Writable for [T; N] {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        self.iter().try_fold(0, |size, value| {
+            Ok(size + value.write_to(writer)?)
+        })
+    }
+}
+
+impl<T: Readable, const N: usize> Readable for [T; N] {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        let values = (0..N).map(|_| T::read_from(reader))
+            .collect::<McResult<Vec<T>>>()?;
+        // The length is always N.
+        Ok(values.try_into().ok().expect("Length is N."))
+    }
+}
+
+/// A UTF-8 string that is written as raw bytes, without a length.
+/// When read, it consumes the rest of the reader.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RawString(pub String);
+
+impl Writable for RawString {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        self.0.as_bytes().write_to(writer)
+    }
+}
+
+impl Readable for RawString {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        Ok(RawString(String::from_utf8(Vec::<u8>::read_from(reader)?)?))
+    }
+}
+
+/// Writes a value preceded by its size in bytes as a big-endian `u32`.
+/// When read, the inner value is only allowed to read that many bytes, and
+/// any bytes that it doesn't read are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct LengthPrefixed<T>(pub T);
+
+impl<T> LengthPrefixed<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Writable> Writable for LengthPrefixed<T> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        let mut buffer = Vec::new();
+        self.0.write_to(&mut buffer)?;
+        let length = u32::try_from(buffer.len()).map_err(|_| crate::McError::OutOfRange)?;
+        writer.write_value(length)?;
+        writer.write_all(&buffer)?;
+        Ok(buffer.len() + 4)
+    }
+}
+
+impl<T: Readable> Readable for LengthPrefixed<T> {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        let length: u32 = reader.read_value()?;
+        let mut limited = reader.take(length as u64);
+        let value = T::read_from(&mut limited)?;
+        // Skip anything that the value didn't read.
+        std::io::copy(&mut limited, &mut std::io::sink())?;
+        Ok(LengthPrefixed(value))
+    }
+}
+
+#[test]
+fn std_impls_test() -> McResult<()> {
+    let mut buffer = Vec::new();
+    buffer.write_value(LengthPrefixed(vec![1u8, 2, 3]))?;
+    buffer.write_value(LengthPrefixed(7u32))?;
+    buffer.write_value([5u16, 6])?;
+    buffer.write_all_value(b"raw".as_slice())?;
+    let mut reader = buffer.as_slice();
+    assert_eq!(reader.read_value::<LengthPrefixed<Vec<u8>>>()?.into_inner(), vec![1, 2, 3]);
+    assert_eq!(reader.read_value::<LengthPrefixed<u32>>()?.0, 7);
+    assert_eq!(reader.read_value::<[u16; 2]>()?, [5, 6]);
+    assert_eq!(reader.read_value::<RawString>()?.0, "raw");
+    Ok(())
+}
+
+#[test]
+fn io_config_test() {
+    assert_eq!(IoConfig::from_vars(None, None), IoConfig::new(BUFFERSIZE, BUFFERSIZE));
+    assert_eq!(IoConfig::from_vars(Some("65536".to_owned()), Some("0".to_owned())), IoConfig::new(65536, BUFFERSIZE));
+    assert_eq!(IoConfig::from_vars(Some("big".to_owned()), Some(" 1024 ".to_owned())), IoConfig::new(BUFFERSIZE, 1024));
+}
+
+#[test]
+fn atomic_replace_test() -> McResult<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("level.dat");
+    std::fs::write(&path, b"old")?;
+    let failed: McResult<()> = atomic_replace(&path, |file| {
+        file.write_all(b"partial")?;
+        crate::McError::custom("Failed.")
+    });
+    assert!(failed.is_err());
+    assert_eq!(std::fs::read(&path)?, b"old");
+    assert!(!temp_path(&path).exists());
+    assert_eq!(atomic_replace(&path, |file| file.write_value(7u32))?, 4);
+    assert_eq!(std::fs::read(&path)?, [0, 0, 0, 7]);
+    assert!(!temp_path(&path).exists());
+    Ok(())
+}
+
+#[test]
+fn multi_codec_test() -> McResult<()> {
+    let schemes = [
+        CompressionScheme::GZip,
+        CompressionScheme::ZLib,
+        CompressionScheme::Uncompressed,
+        #[cfg(feature = "zstd")]
+        CompressionScheme::Custom,
+    ];
+    for scheme in schemes {
+        let mut encoder = MultiEncoder::new(scheme, Vec::new(), Compression::default())?;
+        encoder.write_all_value(b"level.dat".as_slice())?;
+        let compressed = encoder.finish()?;
+        let mut decoded = Vec::new();
+        MultiDecoder::new(scheme, compressed.as_slice())?.read_to_end(&mut decoded)?;
+        assert_eq!(decoded, b"level.dat");
+    }
+    let unknown = [&7u16.to_be_bytes()[..], b"foo:bar"].concat();
+    assert!(matches!(MultiDecoder::new(CompressionScheme::Custom, unknown.as_slice()), Err(McError::UnsupportedCustomCompression(name)) if name == "foo:bar"));
+    Ok(())
+}
+
+#[test]
+fn codec_test() -> McResult<()> {
+    let codecs: [&dyn Codec; 3] = [&SchemeCodec::gzip(Compression::best()), &CompressionScheme::ZLib, &SchemeCodec::uncompressed()];
+    for codec in codecs {
+        let mut compressed = Vec::new();
+        let mut encoder = codec.encoder(Box::new(&mut compressed))?;
+        encoder.write_all_value(b"player.dat".as_slice())?;
+        encoder.finish_write()?;
+        let mut decoded = Vec::new();
+        codec.decoder(Box::new(compressed.as_slice()))?.read_to_end(&mut decoded)?;
+        assert_eq!(decoded, b"player.dat");
+    }
+    Ok(())
+}
@@ -1,14 +1,36 @@
+//! Reading and writing Minecraft: Java Edition data: NBT, region files, and worlds.
+//!
+//! Most code only needs [prelude]. The modules that are compiled depend on the features:
+//! - `nbt` (default): reading, writing, and editing NBT ([nbt]).
+//! - `region` (default): region files ([region], which is [world::io::region]). Implies `nbt`.
+//! - `world` (default): chunks, blocks, entities, level.dat, and the rest of [world]. Implies `region`.
+//! - `render` (default): map tiles and chunk images (`render`). Implies `world`.
+//! - `bedrock`: Bedrock Edition's little-endian NBT (`nbt::bedrock`). Implies `nbt`.
+//!
+//! [ioext], [math], [util], and [error] are always available.
+//! Modules that are hidden from the documentation are internal, and may change in any release.
+
+#[cfg(feature = "nbt")]
 pub mod nbt;
 pub mod world;
 pub mod ioext;
+#[doc(hidden)]
 pub mod data;
 pub mod error;
 pub mod math;
+#[doc(hidden)]
 pub mod macros;
 pub mod util;
+#[doc(hidden)]
 pub mod meshing;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod prelude;
 
 pub use flate2;
+pub use math::bit;
+pub use world::io::region;
 
 pub use error::McError;
-pub use error::McResult;
\ No newline at end of file
+pub use error::McResult;
+pub use error::McResultExt;
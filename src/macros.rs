@@ -38,6 +38,24 @@ macro_rules! make_table {
     };
 }
 
+/// Applies `#[cfg(feature = ...)]` to every item in the block, so that a group of
+/// modules and re-exports behind the same feature is gated in one place.
+/// ```ignore
+/// cfg_feature!("world" {
+///     pub mod chunk;
+///     pub use chunk::Chunk;
+/// });
+/// ```
+macro_rules! cfg_feature {
+    ($feature:literal { $($item:item)* }) => {
+        $(
+            #[cfg(feature = $feature)]
+            $item
+        )*
+    };
+}
+pub(crate) use cfg_feature;
+
 /// Measures the execution time of some set of instructions.
 #[macro_export]
 macro_rules! measure_time {
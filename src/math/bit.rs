@@ -1,4 +1,11 @@
 //! Module for bit level manipulation.
+//!
+//! The traits in this module are implemented for every primitive integer type
+//! (including `u128`/`i128`). This module is re-exported at the crate root as
+//! `mcutil::bit`.
+//!
+//! There are also helpers for nibble arrays (two 4-bit values per byte), which
+//! is how Minecraft stores light levels.
 #![allow(unused)]
 
 use std::ops::{
@@ -8,26 +15,31 @@ use std::ops::{
 
 use crate::for_each_int_type;
 
+/// The size of a type in bits.
 pub trait BitSize {
     const BITSIZE: usize;
 }
 
+/// The number of bits required to represent a value, which is the index of the
+/// highest `1` bit plus one. `0` has a bit length of `0`, and negative values
+/// have a bit length equal to their [BitSize].
 pub trait BitLength {
     fn bit_length(self) -> u32;
 }
 
-impl<T: GetBit + BitSize + Copy> BitLength for T {
-    fn bit_length(self) -> u32 {
-        // This one looks off when you first look at it,
-        // but it should work as intended.
-        (1..=Self::BITSIZE)
-            .rev()
-            .find(|&n| {
-                self.get_bit(n - 1)
-            }).unwrap_or(0) as u32
-    }
+macro_rules! __bitlength_impls {
+    ($type:ty) => {
+        impl BitLength for $type {
+            #[inline(always)]
+            fn bit_length(self) -> u32 {
+                <$type>::BITS - self.leading_zeros()
+            }
+        }
+    };
 }
 
+for_each_int_type!(__bitlength_impls);
+
 pub trait ShiftIndex: Copy {
     /// A `u32` value that represents an index that a `1` bit can be shifted to.
     /// This simply converts the value to u32.
@@ -57,15 +69,21 @@ macro_rules! __bitsize_impls {
 
 for_each_int_type!(__bitsize_impls);
 
+/// Returns a copy of a value with a single bit set or cleared.
+/// Indices that are out of range leave the value unchanged.
 pub trait SetBit {
     fn set_bit<I: ShiftIndex>(self, index: I, on: bool) -> Self;
 }
 
+/// Reads single bits or ranges of bits from a value.
+/// Indices that are out of range read as `false`.
 pub trait GetBit {
     fn get_bit<I: ShiftIndex>(self, index: I) -> bool;
+    /// Extracts the bits in `mask`, shifted down so that `mask.start` is bit `0`.
     fn get_bitmask(self, mask: Range<usize>) -> Self;
 }
 
+/// Returns a copy of a value with a single bit flipped.
 pub trait InvertBit {
     fn invert_bit<I: ShiftIndex>(self, index: I) -> Self;
 }
@@ -118,6 +136,69 @@ macro_rules! __get_set_impl {
 
 crate::for_each_int_type!(__get_set_impl);
 
+/// An iterator over the bits of a value, from least significant to most significant.
+#[derive(Debug, Clone)]
+pub struct BitIter<T> {
+    value: T,
+    range: Range<usize>,
+}
+
+impl<T: GetBit + Copy> Iterator for BitIter<T> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|index| self.value.get_bit(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<T: GetBit + Copy> DoubleEndedIterator for BitIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|index| self.value.get_bit(index))
+    }
+}
+
+impl<T: GetBit + Copy> ExactSizeIterator for BitIter<T> {}
+
+/// Iteration over the bits of a value.
+pub trait IterBits: GetBit + BitSize + Copy {
+    /// Iterates over every bit, from least significant to most significant.
+    fn iter_bits(self) -> BitIter<Self> {
+        BitIter {
+            value: self,
+            range: 0..Self::BITSIZE,
+        }
+    }
+
+    /// Iterates over the indices of the bits that are set.
+    fn ones(self) -> impl Iterator<Item = usize> {
+        self.iter_bits().enumerate()
+            .filter_map(|(index, on)| on.then_some(index))
+    }
+}
+
+impl<T: GetBit + BitSize + Copy> IterBits for T {}
+
+/// Gets the 4-bit value at `index` from a nibble array.
+/// Even indices are stored in the low bits of a byte and odd indices in the high bits.
+#[inline(always)]
+pub fn get_nibble(data: &[u8], index: usize) -> u8 {
+    (data[index / 2] >> ((index & 1) * 4)) & 0xf
+}
+
+/// Sets the 4-bit value at `index` in a nibble array, returning the old value.
+/// Bits of `value` above the low 4 bits are discarded.
+#[inline(always)]
+pub fn set_nibble(data: &mut [u8], index: usize, value: u8) -> u8 {
+    let offset = (index & 1) * 4;
+    let byte = data[index / 2];
+    data[index / 2] = (byte & !(0xf << offset)) | ((value & 0xf) << offset);
+    (byte >> offset) & 0xf
+}
+
 /// To allow polymorphism for iterators of different integer types or references to integer types.
 pub trait MoveBitsIteratorItem {
     fn translate(self) -> usize;
@@ -164,6 +245,20 @@ impl<T: BitSize + GetBit + SetBit + Copy> MoveBits for T {
     }
 }
 
+#[test]
+fn bit_api_test() {
+    assert_eq!(0u128.bit_length(), 0);
+    assert_eq!((1u128 << 100).bit_length(), 101);
+    assert_eq!((-1i8).bit_length(), 8);
+    assert_eq!(0b1011u8.ones().collect::<Vec<_>>(), vec![0, 1, 3]);
+    assert_eq!(u128::MAX.iter_bits().len(), 128);
+    let mut nibbles = [0u8; 2];
+    assert_eq!(set_nibble(&mut nibbles, 1, 0xa), 0);
+    set_nibble(&mut nibbles, 2, 0x5);
+    assert_eq!(nibbles, [0xa0, 0x05]);
+    assert_eq!(get_nibble(&nibbles, 1), 0xa);
+}
+
 #[test]
 fn move_bits_test() {
     use super::*;// 76543210
@@ -4,6 +4,27 @@ use std::{borrow::Borrow, num::NonZeroU64};
 use super::coord::*;
 use glam::{i64::I64Vec2, i64vec2, I64Vec3, i64vec3};
 
+/// The order in which the coordinates of a [Bounds3] are visited.
+/// Picking the order that matches the storage layout of what is being
+/// iterated over (such as chunk sections, which are stored in YZX order)
+/// keeps the traversal cache friendly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IterOrder {
+    /// Y is the outermost axis and X is the innermost.
+    #[default]
+    YZX,
+    /// X is the outermost axis and Y is the innermost (column by column).
+    XZY,
+    /// Visits one 16x16 chunk column at a time (ordered by chunk Z, then chunk X),
+    /// in YZX order within each chunk.
+    ChunkMajor,
+}
+
+/// Rounds `min` down and `max` up to multiples of 16, so that the range covers whole chunks.
+fn snap_range(min: i64, max: i64) -> (i64, i64) {
+    (min.div_euclid(16) * 16, max.div_euclid(16) * 16 + 15)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Bounds2 {
     pub min: I64Vec2,
@@ -52,13 +73,98 @@ impl Bounds2 {
         R::from(i64vec2(x, y))
     }
 
-    pub fn for_each<F: FnMut(I64Vec2) -> ()>(&self, mut f: F) {
-        (self.min.y..self.max.y).for_each(|y| {
-            (self.min.x..self.max.x).for_each(|x| {
-                f(i64vec2(x, y));
-            })
+    /// Visits every coordinate (inclusive), row by row.
+    pub fn for_each<F: FnMut(I64Vec2) -> ()>(&self, f: F) {
+        self.iter().for_each(f);
+    }
+
+    /// Iterates over every coordinate (inclusive), row by row.
+    pub fn iter(&self) -> impl Iterator<Item = I64Vec2> {
+        let (min, max) = (self.min, self.max);
+        (min.y..=max.y).flat_map(move |y| {
+            (min.x..=max.x).map(move |x| i64vec2(x, y))
+        })
+    }
+
+    /// Iterates over every coordinate (inclusive), visiting one 16x16 chunk at a time.
+    pub fn iter_chunk_major(&self) -> impl Iterator<Item = I64Vec2> {
+        let bounds = *self;
+        self.chunk_bounds().iter().flat_map(move |chunk| {
+            let chunk_area = Bounds2::new(chunk * 16, chunk * 16 + 15);
+            bounds.intersection(&chunk_area).into_iter().flat_map(|area| area.iter())
+        })
+    }
+
+    /// The number of coordinates within the bounds.
+    pub fn area(&self) -> u64 {
+        let size: I64Vec2 = self.size();
+        size.x as u64 * size.y as u64
+    }
+
+    pub fn contains<T: Into<I64Vec2>>(&self, point: T) -> bool {
+        let point: I64Vec2 = point.into();
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// Returns true if `other` is entirely within these bounds.
+    pub fn contains_bounds(&self, other: &Self) -> bool {
+        self.contains(other.min) && self.contains(other.max)
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// The area that is shared by both bounds, if any.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        self.intersects(other).then(|| Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
         })
     }
+
+    /// The smallest bounds that contain both bounds.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Grows the bounds by `amount` in every direction.
+    pub fn expand(&self, amount: u64) -> Self {
+        let amount = I64Vec2::splat(amount as i64);
+        Self {
+            min: self.min - amount,
+            max: self.max + amount,
+        }
+    }
+
+    /// Shrinks the bounds by `amount` in every direction.
+    /// Returns `None` if nothing would be left.
+    pub fn contract(&self, amount: u64) -> Option<Self> {
+        let amount = I64Vec2::splat(amount as i64);
+        let (min, max) = (self.min + amount, self.max - amount);
+        min.cmple(max).all().then_some(Self { min, max })
+    }
+
+    /// Expands the bounds (in block coordinates) to the edges of the chunks that they touch.
+    pub fn snap_to_chunks(&self) -> Self {
+        let (min_x, max_x) = snap_range(self.min.x, self.max.x);
+        let (min_y, max_y) = snap_range(self.min.y, self.max.y);
+        Self {
+            min: i64vec2(min_x, min_y),
+            max: i64vec2(max_x, max_y),
+        }
+    }
+
+    /// The bounds (in chunk coordinates) of the chunks that these bounds (in block coordinates) touch.
+    pub fn chunk_bounds(&self) -> Self {
+        Self {
+            min: i64vec2(self.min.x.div_euclid(16), self.min.y.div_euclid(16)),
+            max: i64vec2(self.max.x.div_euclid(16), self.max.y.div_euclid(16)),
+        }
+    }
 }
 
 impl<T: Into<I64Vec2>> From<(T, T)> for Bounds2 {
@@ -137,6 +243,110 @@ impl Bounds3 {
         })
     }
 
+    /// Iterates over every coordinate (inclusive) in the given order.
+    pub fn iter(&self, order: IterOrder) -> Box<dyn Iterator<Item = I64Vec3>> {
+        let (min, max) = (self.min, self.max);
+        match order {
+            IterOrder::YZX => Box::new((min.y..=max.y).flat_map(move |y| {
+                (min.z..=max.z).flat_map(move |z| {
+                    (min.x..=max.x).map(move |x| i64vec3(x, y, z))
+                })
+            })),
+            IterOrder::XZY => Box::new((min.x..=max.x).flat_map(move |x| {
+                (min.z..=max.z).flat_map(move |z| {
+                    (min.y..=max.y).map(move |y| i64vec3(x, y, z))
+                })
+            })),
+            IterOrder::ChunkMajor => {
+                let bounds = *self;
+                Box::new(self.chunk_bounds().iter().flat_map(move |chunk| {
+                    let column = Bounds3::new(
+                        i64vec3(chunk.x * 16, min.y, chunk.y * 16),
+                        i64vec3(chunk.x * 16 + 15, max.y, chunk.y * 16 + 15),
+                    );
+                    bounds.intersection(&column).into_iter()
+                        .flat_map(|area| area.iter(IterOrder::YZX))
+                }))
+            }
+        }
+    }
+
+    /// Visits every coordinate (inclusive) in the given order.
+    pub fn for_each_ordered<F: FnMut(I64Vec3)>(&self, order: IterOrder, f: F) {
+        self.iter(order).for_each(f);
+    }
+
+    /// The number of coordinates within the bounds.
+    pub fn volume(&self) -> u64 {
+        let size: I64Vec3 = self.size();
+        size.x as u64 * size.y as u64 * size.z as u64
+    }
+
+    pub fn contains<T: Into<I64Vec3>>(&self, point: T) -> bool {
+        let point: I64Vec3 = point.into();
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// Returns true if `other` is entirely within these bounds.
+    pub fn contains_bounds(&self, other: &Self) -> bool {
+        self.contains(other.min) && self.contains(other.max)
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// The volume that is shared by both bounds, if any.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        self.intersects(other).then(|| Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        })
+    }
+
+    /// The smallest bounds that contain both bounds.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Grows the bounds by `amount` in every direction.
+    pub fn expand(&self, amount: u64) -> Self {
+        let amount = I64Vec3::splat(amount as i64);
+        Self {
+            min: self.min - amount,
+            max: self.max + amount,
+        }
+    }
+
+    /// Shrinks the bounds by `amount` in every direction.
+    /// Returns `None` if nothing would be left.
+    pub fn contract(&self, amount: u64) -> Option<Self> {
+        let amount = I64Vec3::splat(amount as i64);
+        let (min, max) = (self.min + amount, self.max - amount);
+        min.cmple(max).all().then_some(Self { min, max })
+    }
+
+    /// Expands the X and Z axes to the edges of the chunks that the bounds touch.
+    /// The Y axis is left unchanged.
+    pub fn snap_to_chunks(&self) -> Self {
+        let (min_x, max_x) = snap_range(self.min.x, self.max.x);
+        let (min_z, max_z) = snap_range(self.min.z, self.max.z);
+        Self {
+            min: i64vec3(min_x, self.min.y, min_z),
+            max: i64vec3(max_x, self.max.y, max_z),
+        }
+    }
+
+    /// The bounds (in chunk coordinates, X and Z) of the chunks that these bounds touch.
+    pub fn chunk_bounds(&self) -> Bounds2 {
+        Bounds2 {
+            min: i64vec2(self.min.x.div_euclid(16), self.min.z.div_euclid(16)),
+            max: i64vec2(self.max.x.div_euclid(16), self.max.z.div_euclid(16)),
+        }
+    }
 }
 
 // impl<T: Into<I64Vec2>,  It: IntoIterator<Item = T>> From<It> for Bounds2 {
@@ -149,4 +359,29 @@ impl Bounds3 {
 // 			}
 // 		});
 // 	}
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_test() {
+        let a = Bounds2::new((0, 0), (3, 3));
+        assert_eq!(a.iter().count(), 16);
+        let b = Bounds2::new((2, 2), (5, 5));
+        assert_eq!(a.intersection(&b), Some(Bounds2::new((2, 2), (3, 3))));
+        assert_eq!(a.union(&b), Bounds2::new((0, 0), (5, 5)));
+        assert_eq!(a.contract(2), None);
+        assert_eq!(Bounds2::new((-1, 17), (3, 20)).snap_to_chunks(), Bounds2::new((-16, 16), (15, 31)));
+
+        let volume = Bounds3::new((-8, 0, 0), (23, 1, 3));
+        assert!(volume.contains((23, 1, 3)));
+        assert!(!volume.contains((24, 1, 3)));
+        for order in [IterOrder::YZX, IterOrder::XZY, IterOrder::ChunkMajor] {
+            assert_eq!(volume.iter(order).count() as u64, volume.volume());
+        }
+        let first_chunk = volume.iter(IterOrder::ChunkMajor).take(16).all(|coord| coord.x < 0);
+        assert!(first_chunk);
+    }
+}
@@ -1,5 +1,6 @@
 use glam::I64Vec3;
 
+#[cfg(feature = "world")]
 use crate::world::block::CubeDirection;
 
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -319,6 +320,7 @@ impl BlockCoord {
         }
     }
 
+    #[cfg(feature = "world")]
     #[inline(always)]
     pub fn neighbor(self, direction: CubeDirection) -> Self {
         let (x,y,z) = direction.coord();
@@ -426,6 +428,7 @@ impl std::ops::Sub<I64Vec3> for BlockCoord {
     }
 }
 
+#[cfg(feature = "world")]
 impl std::ops::Add<CubeDirection> for BlockCoord {
     type Output = BlockCoord;
 
@@ -436,6 +439,7 @@ impl std::ops::Add<CubeDirection> for BlockCoord {
     }
 }
 
+#[cfg(feature = "world")]
 impl std::ops::Sub<CubeDirection> for BlockCoord {
     type Output = BlockCoord;
 
@@ -444,4 +448,263 @@ impl std::ops::Sub<CubeDirection> for BlockCoord {
         let (x,y,z) = rhs.coord();
         Self::new(self.x - x, self.y - y, self.z - z, self.dimension)
     }
-}
\ No newline at end of file
+}
+/// A block position, without a dimension. (See [BlockCoord] for the dimension-tagged variant.)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+/// The position of a chunk (16x16 block column), without a dimension.
+/// (See [WorldCoord] for the dimension-tagged variant.)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkPos {
+    pub x: i64,
+    pub z: i64,
+}
+
+/// The position of a 16x16x16 chunk section.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SectionPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+/// The position of a region (32x32 chunks), without a dimension.
+/// (See [WorldCoord] for the dimension-tagged variant.)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionPos {
+    pub x: i64,
+    pub z: i64,
+}
+
+impl BlockPos {
+    #[inline(always)]
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    #[inline(always)]
+    pub const fn xyz(self) -> (i64, i64, i64) {
+        (self.x, self.y, self.z)
+    }
+
+    /// The chunk that contains this block.
+    #[inline(always)]
+    pub const fn chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x.div_euclid(16), self.z.div_euclid(16))
+    }
+
+    /// The section that contains this block.
+    #[inline(always)]
+    pub const fn section(self) -> SectionPos {
+        SectionPos::new(self.x.div_euclid(16), self.y.div_euclid(16), self.z.div_euclid(16))
+    }
+
+    /// The region that contains this block.
+    #[inline(always)]
+    pub const fn region(self) -> RegionPos {
+        RegionPos::new(self.x.div_euclid(512), self.z.div_euclid(512))
+    }
+
+    /// The position of this block within its section (each axis is `0..16`).
+    #[inline(always)]
+    pub const fn section_local(self) -> (i64, i64, i64) {
+        (self.x.rem_euclid(16), self.y.rem_euclid(16), self.z.rem_euclid(16))
+    }
+
+    /// Tags this position with a dimension.
+    #[inline(always)]
+    pub const fn in_dimension(self, dimension: Dimension) -> BlockCoord {
+        BlockCoord { x: self.x, y: self.y, z: self.z, dimension }
+    }
+}
+
+impl ChunkPos {
+    #[inline(always)]
+    pub const fn new(x: i64, z: i64) -> Self {
+        Self { x, z }
+    }
+
+    /// The region that contains this chunk.
+    #[inline(always)]
+    pub const fn region(self) -> RegionPos {
+        RegionPos::new(self.x.div_euclid(32), self.z.div_euclid(32))
+    }
+
+    /// The position of this chunk within its region file.
+    #[cfg(feature = "region")]
+    #[inline(always)]
+    pub fn region_local(self) -> crate::world::io::region::RegionCoord {
+        crate::world::io::region::RegionCoord::new(self.x.rem_euclid(32) as u16, self.z.rem_euclid(32) as u16)
+    }
+
+    /// The block with the lowest X and Z in this chunk, at `y`.
+    #[inline(always)]
+    pub const fn min_block(self, y: i64) -> BlockPos {
+        BlockPos::new(self.x * 16, y, self.z * 16)
+    }
+
+    /// The section at section Y coordinate `y` in this chunk.
+    #[inline(always)]
+    pub const fn section(self, y: i64) -> SectionPos {
+        SectionPos::new(self.x, y, self.z)
+    }
+
+    /// Tags this position with a dimension.
+    #[inline(always)]
+    pub const fn in_dimension(self, dimension: Dimension) -> WorldCoord {
+        WorldCoord { x: self.x, z: self.z, dimension }
+    }
+}
+
+impl SectionPos {
+    #[inline(always)]
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The chunk that contains this section.
+    #[inline(always)]
+    pub const fn chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x, self.z)
+    }
+
+    /// The block with the lowest coordinates in this section.
+    #[inline(always)]
+    pub const fn min_block(self) -> BlockPos {
+        BlockPos::new(self.x * 16, self.y * 16, self.z * 16)
+    }
+}
+
+impl RegionPos {
+    #[inline(always)]
+    pub const fn new(x: i64, z: i64) -> Self {
+        Self { x, z }
+    }
+
+    /// The chunk with the lowest X and Z in this region.
+    #[inline(always)]
+    pub const fn min_chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x * 32, self.z * 32)
+    }
+
+    /// The chunk at `coord` within this region.
+    #[cfg(feature = "region")]
+    #[inline(always)]
+    pub fn chunk(self, coord: crate::world::io::region::RegionCoord) -> ChunkPos {
+        ChunkPos::new(self.x * 32 + coord.x() as i64, self.z * 32 + coord.z() as i64)
+    }
+
+    /// Tags this position with a dimension.
+    #[inline(always)]
+    pub const fn in_dimension(self, dimension: Dimension) -> WorldCoord {
+        WorldCoord { x: self.x, z: self.z, dimension }
+    }
+}
+
+impl From<(i64, i64, i64)> for BlockPos {
+    #[inline(always)]
+    fn from(value: (i64, i64, i64)) -> Self {
+        Self::new(value.0, value.1, value.2)
+    }
+}
+
+impl From<BlockPos> for (i64, i64, i64) {
+    #[inline(always)]
+    fn from(value: BlockPos) -> Self {
+        value.xyz()
+    }
+}
+
+impl From<BlockCoord> for BlockPos {
+    #[inline(always)]
+    fn from(value: BlockCoord) -> Self {
+        Self::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<Coord3> for BlockPos {
+    #[inline(always)]
+    fn from(value: Coord3) -> Self {
+        Self::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<I64Vec3> for BlockPos {
+    #[inline(always)]
+    fn from(value: I64Vec3) -> Self {
+        Self::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<(i64, i64)> for ChunkPos {
+    #[inline(always)]
+    fn from(value: (i64, i64)) -> Self {
+        Self::new(value.0, value.1)
+    }
+}
+
+impl From<WorldCoord> for ChunkPos {
+    #[inline(always)]
+    fn from(value: WorldCoord) -> Self {
+        Self::new(value.x, value.z)
+    }
+}
+
+impl From<(i64, i64)> for RegionPos {
+    #[inline(always)]
+    fn from(value: (i64, i64)) -> Self {
+        Self::new(value.0, value.1)
+    }
+}
+
+impl From<BlockCoord> for SectionPos {
+    #[inline(always)]
+    fn from(value: BlockCoord) -> Self {
+        BlockPos::from(value).section()
+    }
+}
+
+impl BlockCoord {
+    /// Drops the dimension.
+    #[inline(always)]
+    pub fn pos(self) -> BlockPos {
+        BlockPos::from(self)
+    }
+}
+
+impl WorldCoord {
+    /// Drops the dimension, treating this as a chunk coordinate.
+    #[inline(always)]
+    pub fn chunk_pos(self) -> ChunkPos {
+        ChunkPos::from(self)
+    }
+
+    /// Drops the dimension, treating this as a region coordinate.
+    #[inline(always)]
+    pub fn region_pos(self) -> RegionPos {
+        RegionPos::new(self.x, self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pos_conversion_test() {
+        let pos = BlockPos::new(-1, -65, 513);
+        assert_eq!(pos.chunk(), ChunkPos::new(-1, 32));
+        assert_eq!(pos.section(), SectionPos::new(-1, -5, 32));
+        assert_eq!(pos.region(), RegionPos::new(-1, 1));
+        assert_eq!(pos.section_local(), (15, 15, 1));
+        assert_eq!(pos.chunk().region(), pos.region());
+        let local = pos.chunk().region_local();
+        assert_eq!(pos.region().chunk(local), pos.chunk());
+        assert_eq!(pos.in_dimension(Dimension::Nether).pos(), pos);
+    }
+}
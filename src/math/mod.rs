@@ -2,4 +2,5 @@ pub mod geometry;
 pub mod bit;
 pub mod grid;
 pub mod coord;
-pub mod bounds;
\ No newline at end of file
+pub mod bounds;
+pub mod packed;
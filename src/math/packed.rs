@@ -0,0 +1,293 @@
+//! Arrays of fixed-width values packed into 64-bit longs.
+//!
+//! This is how Minecraft stores block states, biomes, and heightmaps.
+//! Since 1.16 values never span two longs; if `64 / bits` values don't fill a
+//! long, the remaining high bits are left unused. From 1.13 to 1.15 values were
+//! packed end to end, so a value could start in one long and end in the next.
+//! See [Packing].
+
+use crate::{
+    McError, McResult,
+    math::bit::BitLength,
+};
+
+/// The layout of values within the longs of a packed array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Packing {
+    /// Values never span two longs (1.16+).
+    #[default]
+    Aligned,
+    /// Values are packed end to end and may span two longs (1.13 - 1.15).
+    Spanning,
+}
+
+impl Packing {
+    /// The first DataVersion (20w17a) that uses [Packing::Aligned].
+    pub const ALIGNED_DATA_VERSION: i32 = 2529;
+
+    /// Selects the packing used by a world saved with `data_version`.
+    pub fn for_data_version(data_version: i32) -> Self {
+        if data_version < Self::ALIGNED_DATA_VERSION {
+            Packing::Spanning
+        } else {
+            Packing::Aligned
+        }
+    }
+
+    /// The number of longs required to hold `len` values of `bits` bits.
+    pub const fn long_count(self, bits: u32, len: usize) -> usize {
+        match self {
+            Packing::Aligned => long_count(bits, len),
+            Packing::Spanning => (len * bits as usize).div_ceil(64),
+        }
+    }
+
+    pub fn get(self, data: &[i64], bits: u32, index: usize) -> u64 {
+        match self {
+            Packing::Aligned => get_packed(data, bits, index),
+            Packing::Spanning => get_spanning(data, bits, index),
+        }
+    }
+
+    pub fn set(self, data: &mut [i64], bits: u32, index: usize, value: u64) -> u64 {
+        match self {
+            Packing::Aligned => set_packed(data, bits, index, value),
+            Packing::Spanning => set_spanning(data, bits, index, value),
+        }
+    }
+}
+
+/// The number of values that fit in a single long.
+#[inline(always)]
+pub const fn values_per_long(bits: u32) -> usize {
+    (64 / bits) as usize
+}
+
+/// The number of longs required to hold `len` values of `bits` bits.
+#[inline(always)]
+pub const fn long_count(bits: u32, len: usize) -> usize {
+    len.div_ceil(values_per_long(bits))
+}
+
+/// The number of bits needed to store indices into a palette of `palette_len`
+/// entries, but never less than `min_bits`.
+/// (4 for block states, 1 for biomes.)
+pub fn palette_bits(palette_len: usize, min_bits: u32) -> u32 {
+    // The largest index is `palette_len - 1`.
+    palette_len.saturating_sub(1).bit_length().max(min_bits)
+}
+
+#[inline(always)]
+const fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    }
+}
+
+/// Gets the value at `index` from longs packed with `bits` bits per value.
+pub fn get_packed(data: &[i64], bits: u32, index: usize) -> u64 {
+    let vpl = values_per_long(bits);
+    let offset = (index % vpl) as u32 * bits;
+    (data[index / vpl] as u64 >> offset) & mask(bits)
+}
+
+/// Sets the value at `index` in longs packed with `bits` bits per value,
+/// returning the old value. Bits of `value` above `bits` are discarded.
+pub fn set_packed(data: &mut [i64], bits: u32, index: usize, value: u64) -> u64 {
+    let vpl = values_per_long(bits);
+    let offset = (index % vpl) as u32 * bits;
+    let slot = data[index / vpl] as u64;
+    let old = (slot >> offset) & mask(bits);
+    let new = (slot & !(mask(bits) << offset)) | ((value & mask(bits)) << offset);
+    data[index / vpl] = new as i64;
+    old
+}
+
+/// Gets the value at `index` from longs packed end to end (pre-1.16).
+pub fn get_spanning(data: &[i64], bits: u32, index: usize) -> u64 {
+    let bit_index = index * bits as usize;
+    let (long, offset) = (bit_index / 64, (bit_index % 64) as u32);
+    let mut value = data[long] as u64 >> offset;
+    if offset + bits > 64 {
+        value |= (data[long + 1] as u64) << (64 - offset);
+    }
+    value & mask(bits)
+}
+
+/// Sets the value at `index` in longs packed end to end (pre-1.16),
+/// returning the old value.
+pub fn set_spanning(data: &mut [i64], bits: u32, index: usize, value: u64) -> u64 {
+    let old = get_spanning(data, bits, index);
+    let value = value & mask(bits);
+    let bit_index = index * bits as usize;
+    let (long, offset) = (bit_index / 64, (bit_index % 64) as u32);
+    let low = data[long] as u64;
+    data[long] = ((low & !(mask(bits) << offset)) | (value << offset)) as i64;
+    if offset + bits > 64 {
+        let high_bits = offset + bits - 64;
+        let high = data[long + 1] as u64;
+        data[long + 1] = ((high & !mask(high_bits)) | (value >> (64 - offset))) as i64;
+    }
+    old
+}
+
+/// A fixed length array of values packed into longs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedArray {
+    bits: u32,
+    len: usize,
+    packing: Packing,
+    data: Vec<i64>,
+}
+
+impl PackedArray {
+    /// Creates an array of `len` zeroes with `bits` bits per value.
+    pub fn new(bits: u32, len: usize) -> Self {
+        Self::with_packing(bits, len, Packing::Aligned)
+    }
+
+    /// Creates an array of `len` zeroes with `bits` bits per value using the given packing.
+    pub fn with_packing(bits: u32, len: usize, packing: Packing) -> Self {
+        assert!((1..=64).contains(&bits), "bits must be between 1 and 64.");
+        Self {
+            bits,
+            len,
+            packing,
+            data: vec![0; packing.long_count(bits, len)],
+        }
+    }
+
+    /// Wraps existing packed data, checking that it is the right length.
+    pub fn from_longs(bits: u32, len: usize, data: Vec<i64>) -> McResult<Self> {
+        Self::from_longs_with_packing(bits, len, data, Packing::Aligned)
+    }
+
+    /// Wraps existing packed data with the given packing, checking that it is the right length.
+    pub fn from_longs_with_packing(bits: u32, len: usize, data: Vec<i64>, packing: Packing) -> McResult<Self> {
+        if !(1..=64).contains(&bits) || data.len() != packing.long_count(bits, len) {
+            return Err(McError::OutOfRange);
+        }
+        Ok(Self {
+            bits,
+            len,
+            packing,
+            data,
+        })
+    }
+
+    /// Packs `values` using the smallest bit width that fits the largest value
+    /// (but never less than `min_bits`).
+    pub fn from_values(values: &[u64], min_bits: u32) -> Self {
+        let max = values.iter().copied().max().unwrap_or(0);
+        let mut array = Self::new(max.bit_length().max(min_bits).max(1), values.len());
+        values.iter().enumerate().for_each(|(index, &value)| {
+            array.set(index, value);
+        });
+        array
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn packing(&self) -> Packing {
+        self.packing
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The largest value that can be stored.
+    pub fn max_value(&self) -> u64 {
+        mask(self.bits)
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        assert!(index < self.len, "index out of bounds.");
+        self.packing.get(&self.data, self.bits, index)
+    }
+
+    /// Sets the value at `index`, returning the old value.
+    /// Panics if `value` does not fit in [PackedArray::bits].
+    pub fn set(&mut self, index: usize, value: u64) -> u64 {
+        assert!(index < self.len, "index out of bounds.");
+        assert!(value <= self.max_value(), "value does not fit in {} bits.", self.bits);
+        self.packing.set(&mut self.data, self.bits, index, value)
+    }
+
+    /// Sets the value at `index`, widening the array first if the value doesn't fit.
+    /// This is what should be used when a palette grows.
+    pub fn set_grow(&mut self, index: usize, value: u64) -> u64 {
+        let required = value.bit_length();
+        if required > self.bits {
+            self.resize(required);
+        }
+        self.set(index, value)
+    }
+
+    /// Repacks the array with a different number of bits per value.
+    /// Panics if a value does not fit in the new width.
+    pub fn resize(&mut self, bits: u32) {
+        if bits == self.bits {
+            return;
+        }
+        let mut resized = Self::with_packing(bits, self.len, self.packing);
+        self.iter().enumerate().for_each(|(index, value)| {
+            resized.set(index, value);
+        });
+        *self = resized;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len).map(|index| self.packing.get(&self.data, self.bits, index))
+    }
+
+    pub fn as_longs(&self) -> &[i64] {
+        &self.data
+    }
+
+    pub fn into_longs(self) -> Vec<i64> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_test() {
+        let mut array = PackedArray::new(5, 4096);
+        // 12 values per long, so 4096 values need 342 longs.
+        assert_eq!(array.as_longs().len(), 342);
+        (0..4096).for_each(|i| { array.set(i, (i % 32) as u64); });
+        assert!(array.iter().enumerate().all(|(i, value)| value == (i % 32) as u64));
+        array.set_grow(7, 100);
+        assert_eq!(array.bits(), 7);
+        assert_eq!(array.get(7), 100);
+        assert_eq!(array.get(8), 8);
+        assert_eq!(palette_bits(17, 4), 5);
+        assert_eq!(palette_bits(1, 4), 4);
+        assert_eq!(palette_bits(2, 1), 1);
+    }
+
+    #[test]
+    fn spanning_test() {
+        let mut array = PackedArray::with_packing(5, 4096, Packing::Spanning);
+        assert_eq!(array.as_longs().len(), 320);
+        (0..4096).for_each(|i| { array.set(i, (i * 7 % 32) as u64); });
+        assert!(array.iter().enumerate().all(|(i, value)| value == (i * 7 % 32) as u64));
+        // The 13th value (index 12) starts at bit 60 and spans the first two longs.
+        let data = array.as_longs();
+        assert_eq!(get_spanning(data, 5, 12), (12 * 7 % 32) as u64);
+        assert_eq!(Packing::for_data_version(2230), Packing::Spanning);
+        assert_eq!(Packing::for_data_version(2586), Packing::Aligned);
+    }
+}
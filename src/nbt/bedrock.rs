@@ -0,0 +1,261 @@
+//! Bedrock Edition's NBT, which has the same tags as Java Edition's, but stores numbers
+//! and lengths in little-endian byte order and strings as plain UTF-8.
+//!
+//! Bedrock's `level.dat` is uncompressed, and starts with an 8 byte header: the storage
+//! version and the length of the NBT that follows, both little-endian 32-bit integers.
+//! See [read_level_dat] and [write_level_dat].
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    ioext::{IoConfig, atomic_replace},
+    McError, McResult,
+};
+
+use super::{
+    Map,
+    io::ReadLimits,
+    tag::{ListTag, NamedTag, Tag, TagID},
+    tag_info_table,
+};
+
+/// Values that can be read and written as little-endian NBT.
+trait LittleEndian: Sized {
+    fn read_le<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<Self>;
+    fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize>;
+}
+
+macro_rules! primitive_le {
+    ($($primitive:ident)+) => {
+        $(
+            impl LittleEndian for $primitive {
+                fn read_le<R: Read>(reader: &mut R, _limits: &ReadLimits) -> McResult<Self> {
+                    let mut buf = [0u8; std::mem::size_of::<$primitive>()];
+                    reader.read_exact(&mut buf)?;
+                    Ok(Self::from_le_bytes(buf))
+                }
+
+                fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+                    writer.write_all(&self.to_le_bytes())?;
+                    Ok(std::mem::size_of::<$primitive>())
+                }
+            }
+        )+
+    };
+}
+
+primitive_le![i8 u8 i16 u16 i32 f32 i64 f64];
+
+/// Returns `length` as a [usize] if it is within `max`.
+fn check_length(kind: &'static str, length: usize, max: usize) -> McResult<usize> {
+    if length > max {
+        return Err(McError::LengthLimitExceeded(kind, length, max));
+    }
+    Ok(length)
+}
+
+impl LittleEndian for String {
+    fn read_le<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<Self> {
+        let length = check_length("string", u16::read_le(reader, limits)? as usize, limits.max_string_len)?;
+        let mut bytes = vec![0u8; length];
+        reader.read_exact(&mut bytes)?;
+        if limits.lossy_strings {
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        } else {
+            Ok(String::from_utf8(bytes)?)
+        }
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        write_string(writer, self)
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, text: &str) -> McResult<usize> {
+    let length = u16::try_from(text.len()).map_err(|_| McError::StringTooLong(text.len()))?;
+    length.write_le(writer)?;
+    writer.write_all(text.as_bytes())?;
+    Ok(text.len() + 2)
+}
+
+// Arrays and the elements of Lists are both a 32-bit length followed by the values.
+impl<T: LittleEndian> LittleEndian for Vec<T> {
+    fn read_le<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<Self> {
+        let length = i32::read_le(reader, limits)?.max(0) as usize;
+        let length = check_length("array", length, limits.max_array_len)?;
+        (0..length).map(|_| T::read_le(reader, limits)).collect()
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        let length = i32::try_from(self.len()).map_err(|_| McError::OutOfRange)?;
+        self.iter().try_fold(length.write_le(writer)?, |size, value| Ok(size + value.write_le(writer)?))
+    }
+}
+
+macro_rules! tag_le {
+    ($($id:literal $title:ident $type:path [$($impl:path)?])+) => {
+        /// Reads the payload of a tag with the given ID.
+        fn read_payload<R: Read>(reader: &mut R, id: TagID, limits: &ReadLimits) -> McResult<Tag> {
+            Ok(match id {
+                $(
+                    TagID::$title => Tag::$title(<$type>::read_le(reader, limits)?),
+                )+
+            })
+        }
+
+        /// Writes the payload of a tag, without its ID.
+        fn write_payload<W: Write>(writer: &mut W, tag: &Tag) -> McResult<usize> {
+            match tag {
+                $(
+                    Tag::$title(value) => value.write_le(writer),
+                )+
+            }
+        }
+
+        impl LittleEndian for ListTag {
+            fn read_le<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<Self> {
+                let id = u8::read_le(reader, limits)?;
+                if id == 0 {
+                    i32::read_le(reader, limits)?;
+                    return Ok(ListTag::Empty);
+                }
+                let length = i32::read_le(reader, limits)?.max(0) as usize;
+                let length = check_length("list", length, limits.max_list_len)?;
+                match TagID::try_from(id)? {
+                    $(
+                        TagID::$title => Ok(ListTag::$title(
+                            (0..length).map(|_| <$type>::read_le(reader, limits)).collect::<McResult<_>>()?
+                        )),
+                    )+
+                }
+            }
+
+            fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+                match self {
+                    $(
+                        ListTag::$title(list) => {
+                            (TagID::$title.value() as u8).write_le(writer)?;
+                            list.write_le(writer).map(|size| size + 1)
+                        },
+                    )+
+                    ListTag::Empty => {
+                        0u8.write_le(writer)?;
+                        0i32.write_le(writer)?;
+                        Ok(5)
+                    },
+                }
+            }
+        }
+    };
+}
+
+tag_info_table!(tag_le);
+
+impl LittleEndian for Map {
+    fn read_le<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<Self> {
+        let mut map = Map::new();
+        loop {
+            let id = match TagID::try_from(u8::read_le(reader, limits)?) {
+                Ok(id) => id,
+                Err(McError::EndTagMarker) => return Ok(map),
+                Err(err) => return Err(err),
+            };
+            let name = String::read_le(reader, limits)?;
+            map.insert(name, read_payload(reader, id, limits)?);
+        }
+    }
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        let size = self.iter().try_fold(0, |size, (name, tag)| {
+            Ok::<_, McError>(size + write_named(writer, name, tag)?)
+        })?;
+        0u8.write_le(writer)?;
+        Ok(size + 1)
+    }
+}
+
+fn write_named<W: Write>(writer: &mut W, name: &str, tag: &Tag) -> McResult<usize> {
+    (tag.id().value() as u8).write_le(writer)?;
+    let name_size = write_string(writer, name)?;
+    Ok(1 + name_size + write_payload(writer, tag)?)
+}
+
+/// Reads a little-endian named tag, such as the root of a Bedrock NBT file.
+pub fn read_named_tag<R: Read>(reader: &mut R) -> McResult<NamedTag> {
+    read_named_tag_with_limits(reader, &ReadLimits::DEFAULT)
+}
+
+/// Like [read_named_tag], but checks lengths against `limits` instead of [ReadLimits::DEFAULT].
+pub fn read_named_tag_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> McResult<NamedTag> {
+    let id = TagID::try_from(u8::read_le(reader, limits)?)?;
+    let name = String::read_le(reader, limits)?;
+    let tag = read_payload(reader, id, limits)?;
+    Ok(NamedTag::with_name(name, tag))
+}
+
+/// Writes a little-endian named tag, returning the number of bytes written.
+pub fn write_named_tag<W: Write>(writer: &mut W, root: &NamedTag) -> McResult<usize> {
+    write_named(writer, root.name(), root.tag())
+}
+
+/// Reads a Bedrock `level.dat`, returning its storage version and root tag.
+pub fn read_level_dat<P: AsRef<Path>>(path: P) -> McResult<(i32, NamedTag)> {
+    let mut reader = BufReader::with_capacity(IoConfig::default().read_buf, File::open(path)?);
+    let limits = ReadLimits::DEFAULT;
+    let version = i32::read_le(&mut reader, &limits)?;
+    let length = i32::read_le(&mut reader, &limits)?.max(0) as u64;
+    let root = read_named_tag(&mut reader.take(length))?;
+    Ok((version, root))
+}
+
+/// Writes a Bedrock `level.dat` with the given storage version, replacing the file atomically.
+/// Returns the number of bytes written, including the header.
+pub fn write_level_dat<P: AsRef<Path>>(path: P, version: i32, root: &NamedTag) -> McResult<usize> {
+    let mut nbt = Vec::new();
+    write_named_tag(&mut nbt, root)?;
+    let length = i32::try_from(nbt.len()).map_err(|_| McError::OutOfRange)?;
+    atomic_replace(path, |file| {
+        version.write_le(file)?;
+        length.write_le(file)?;
+        file.write_all(&nbt)?;
+        Ok(nbt.len() + 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bedrock_test() -> McResult<()> {
+        let mut buffer = Vec::new();
+        write_named_tag(&mut buffer, &NamedTag::with_name("a", Tag::Int(1)))?;
+        assert_eq!(buffer, [3, 1, 0, b'a', 1, 0, 0, 0]);
+
+        let mut map = Map::new();
+        map.insert("LevelName".to_owned(), Tag::string("Bedrock level"));
+        map.insert("Time".to_owned(), Tag::Long(-12345));
+        map.insert("lastOpenedWithVersion".to_owned(), Tag::List(ListTag::Int(vec![1, 21, 0, 3])));
+        map.insert("experiments".to_owned(), Tag::Compound(Map::from([("gametest".to_owned(), Tag::Byte(1))])));
+        map.insert("empty".to_owned(), Tag::List(ListTag::Empty));
+        map.insert("ints".to_owned(), Tag::IntArray(vec![-1, 2]));
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("level.dat");
+        let size = write_level_dat(&path, 10, &NamedTag::new(Tag::Compound(map)))?;
+        assert_eq!(std::fs::metadata(&path)?.len() as usize, size);
+        let (version, root) = read_level_dat(&path)?;
+        assert_eq!(version, 10);
+        let Tag::Compound(map) = root.tag() else { panic!("Expected a compound.") };
+        assert!(matches!(map.get("LevelName"), Some(Tag::String(name)) if name == "Bedrock level"));
+        assert!(matches!(map.get("Time"), Some(Tag::Long(-12345))));
+        assert!(matches!(map.get("lastOpenedWithVersion"), Some(Tag::List(ListTag::Int(version))) if version == &vec![1, 21, 0, 3]));
+        assert!(matches!(map.get("experiments"), Some(Tag::Compound(experiments)) if matches!(experiments.get("gametest"), Some(Tag::Byte(1)))));
+        assert!(matches!(map.get("empty"), Some(Tag::List(ListTag::Empty))));
+        assert!(matches!(map.get("ints"), Some(Tag::IntArray(ints)) if ints == &vec![-1, 2]));
+        Ok(())
+    }
+}
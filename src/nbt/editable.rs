@@ -9,9 +9,13 @@ use chumsky::primitive::todo;
 
 use crate::nbt::{
     MapType,
+    for_each_tag_type,
     tag::*,
     tagtype::*,
+    tagpath::{TagPath, TagPathPart},
+    tagref::{ValueRef, ValueRefMut},
 };
+use crate::{McError, McResult};
 
 pub struct ValueEditorArgs<'a, T> {
     // ui: &mut egui::Ui,
@@ -19,8 +23,11 @@ pub struct ValueEditorArgs<'a, T> {
     value: Rc<T>
 }
 
+/// Implemented by widgets that can edit a value of type `T`.
+/// Implementations for the NBT types are in [crate::nbt::editor] (requires the `egui` feature).
 pub trait ValueEditor<T> {
-    // fn edit_value(&mut self, ui: &mut egui::Ui, node: &mut Editable<T>, value: Rc<T>) -> egui::Response;
+    #[cfg(feature = "egui")]
+    fn edit_value(&mut self, ui: &mut egui::Ui, node: &mut Editable<T>, value: Rc<T>) -> egui::Response;
 }
 
 pub struct EditWidget<T> {
@@ -68,6 +75,14 @@ impl<T> Editable<T> {
         }
     }
 
+    /// Replaces the value, keeping the editor (if any).
+    pub fn set(&mut self, value: T) {
+        match self {
+            Editable::Value(obj) => *obj = Rc::new(value),
+            Editable::Editor(widget) => widget.value = Rc::new(value),
+        }
+    }
+
     pub fn editing(&self) -> bool {
         matches!(self, Editable::Editor(_))
     }
@@ -134,44 +149,17 @@ pub enum EditableListTag {
 
 impl EditableTag {
     pub fn id(&self) -> TagID {
-        match self {
-            EditableTag::Byte(_) => TagID::Byte,
-            EditableTag::Short(_) => TagID::Short,
-            EditableTag::Int(_) => TagID::Int,
-            EditableTag::Long(_) => TagID::Long,
-            EditableTag::Float(_) => TagID::Float,
-            EditableTag::Double(_) => TagID::Double,
-            EditableTag::ByteArray(_) => TagID::ByteArray,
-            EditableTag::String(_) => TagID::String,
-            EditableTag::List(_) => TagID::List,
-            EditableTag::Compound(_) => TagID::Compound,
-            EditableTag::IntArray(_) => TagID::IntArray,
-            EditableTag::LongArray(_) => TagID::LongArray
-        }
+        for_each_tag_type!(self; EditableTag(_) => TagID)
     }
 }
 
 impl EditableListTag {
     pub fn id(&self) -> TagID {
-        match self {
-            EditableListTag::Empty => TagID::Byte,
-            EditableListTag::Byte(_) => TagID::Byte,
-            EditableListTag::Short(_) => TagID::Short,
-            EditableListTag::Int(_) => TagID::Int,
-            EditableListTag::Long(_) => TagID::Long,
-            EditableListTag::Float(_) => TagID::Float,
-            EditableListTag::Double(_) => TagID::Double,
-            EditableListTag::ByteArray(_) => TagID::ByteArray,
-            EditableListTag::String(_) => TagID::String,
-            EditableListTag::List(_) => TagID::List,
-            EditableListTag::Compound(_) => TagID::Compound,
-            EditableListTag::IntArray(_) => TagID::IntArray,
-            EditableListTag::LongArray(_) => TagID::LongArray
-        }
+        for_each_tag_type!(self; EditableListTag(_) => TagID, EditableListTag::Empty => TagID::Byte)
     }
 }
 
-fn map_to_editable(map: &MapType<Tag>) -> EditableMap {
+pub(crate) fn map_to_editable(map: &MapType<Tag>) -> EditableMap {
     let mut result = EditableMap::new();
     map.iter().for_each(|(key, tag)| {
         result.insert(key.to_owned(), Editable::new(EditableTag::from(tag.clone())));
@@ -262,79 +250,81 @@ impl From<&Vec<MapType<Tag>>> for EditableVec<EditableMap> {
 
 impl From<Tag> for EditableTag {
     fn from(value: Tag) -> Self {
-        match value {
-            Tag::Byte(value) => EditableTag::Byte(value.into()),
-            Tag::Short(value) => EditableTag::Short(value.into()),
-            Tag::Int(value) => EditableTag::Int(value.into()),
-            Tag::Long(value) => EditableTag::Long(value.into()),
-            Tag::Float(value) => EditableTag::Float(value.into()),
-            Tag::Double(value) => EditableTag::Double(value.into()),
-            Tag::ByteArray(value) => EditableTag::ByteArray(value.into()),
-            Tag::String(value) => EditableTag::String(value.into()),
-            Tag::List(value) => EditableTag::List(value.into()),
-            Tag::Compound(value) => EditableTag::Compound(value.into()),
-            Tag::IntArray(value) => EditableTag::IntArray(value.into()),
-            Tag::LongArray(value) => EditableTag::LongArray(value.into()),
-        }
+        for_each_tag_type!(value; Tag(value) => EditableTag(value.into()))
     }
 }
 
 impl From<&Tag> for EditableTag {
     fn from(value: &Tag) -> Self {
-        match value {
-            Tag::Byte(value) => EditableTag::Byte(value.into()),
-            Tag::Short(value) => EditableTag::Short(value.into()),
-            Tag::Int(value) => EditableTag::Int(value.into()),
-            Tag::Long(value) => EditableTag::Long(value.into()),
-            Tag::Float(value) => EditableTag::Float(value.into()),
-            Tag::Double(value) => EditableTag::Double(value.into()),
-            Tag::ByteArray(value) => EditableTag::ByteArray(value.into()),
-            Tag::String(value) => EditableTag::String(value.into()),
-            Tag::List(value) => EditableTag::List(value.into()),
-            Tag::Compound(value) => EditableTag::Compound(value.into()),
-            Tag::IntArray(value) => EditableTag::IntArray(value.into()),
-            Tag::LongArray(value) => EditableTag::LongArray(value.into()),
-        }
+        for_each_tag_type!(value; Tag(value) => EditableTag(value.into()))
     }
 }
 
 impl From<ListTag> for EditableListTag {
     fn from(value: ListTag) -> Self {
-        match value {
-            ListTag::Empty => EditableListTag::Empty,
-            ListTag::Byte(list) => EditableListTag::Byte(list.into()),
-            ListTag::Short(list) => EditableListTag::Short(list.into()),
-            ListTag::Int(list) => EditableListTag::Int(list.into()),
-            ListTag::Long(list) => EditableListTag::Long(list.into()),
-            ListTag::Float(list) => EditableListTag::Float(list.into()),
-            ListTag::Double(list) => EditableListTag::Double(list.into()),
-            ListTag::ByteArray(list) => EditableListTag::ByteArray(list.into()),
-            ListTag::String(list) => EditableListTag::String(list.into()),
-            ListTag::List(list) => EditableListTag::List(list.into()),
-            ListTag::Compound(list) => EditableListTag::Compound(list.into()),
-            ListTag::IntArray(list) => EditableListTag::IntArray(list.into()),
-            ListTag::LongArray(list) => EditableListTag::LongArray(list.into()),
-        }
+        for_each_tag_type!(value; ListTag(list) => EditableListTag(list.into()), ListTag::Empty => EditableListTag::Empty)
     }
 }
 
 impl From<&ListTag> for EditableListTag {
     fn from(value: &ListTag) -> Self {
-        match value {
-            ListTag::Empty => EditableListTag::Empty,
-            ListTag::Byte(list) => EditableListTag::Byte(list.into()),
-            ListTag::Short(list) => EditableListTag::Short(list.into()),
-            ListTag::Int(list) => EditableListTag::Int(list.into()),
-            ListTag::Long(list) => EditableListTag::Long(list.into()),
-            ListTag::Float(list) => EditableListTag::Float(list.into()),
-            ListTag::Double(list) => EditableListTag::Double(list.into()),
-            ListTag::ByteArray(list) => EditableListTag::ByteArray(list.into()),
-            ListTag::String(list) => EditableListTag::String(list.into()),
-            ListTag::List(list) => EditableListTag::List(list.into()),
-            ListTag::Compound(list) => EditableListTag::Compound(list.into()),
-            ListTag::IntArray(list) => EditableListTag::IntArray(list.into()),
-            ListTag::LongArray(list) => EditableListTag::LongArray(list.into()),
-        }
+        for_each_tag_type!(value; ListTag(list) => EditableListTag(list.into()), ListTag::Empty => EditableListTag::Empty)
+    }
+}
+
+/// Converts an editable value back into the value that it edits.
+pub trait ToValue {
+    type Output;
+    fn to_value(&self) -> Self::Output;
+}
+
+macro_rules! to_value_clone {
+    ($($type:ty)+) => {
+        $(
+            impl ToValue for $type {
+                type Output = $type;
+                fn to_value(&self) -> Self::Output {
+                    self.clone()
+                }
+            }
+        )+
+    };
+}
+
+to_value_clone!(Byte Short Int Long Float Double ByteArray String IntArray LongArray);
+
+impl<T: ToValue> ToValue for Editable<T> {
+    type Output = T::Output;
+    fn to_value(&self) -> Self::Output {
+        self.as_ref().to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<Editable<T>> {
+    type Output = Vec<T::Output>;
+    fn to_value(&self) -> Self::Output {
+        self.iter().map(ToValue::to_value).collect()
+    }
+}
+
+impl ToValue for EditableMap {
+    type Output = MapType<Tag>;
+    fn to_value(&self) -> Self::Output {
+        self.iter().map(|(key, tag)| (key.to_owned(), tag.to_value())).collect()
+    }
+}
+
+impl ToValue for EditableTag {
+    type Output = Tag;
+    fn to_value(&self) -> Self::Output {
+        for_each_tag_type!(self; EditableTag(value) => Tag(value.to_value()))
+    }
+}
+
+impl ToValue for EditableListTag {
+    type Output = ListTag;
+    fn to_value(&self) -> Self::Output {
+        for_each_tag_type!(self; EditableListTag(list) => ListTag(list.to_value()), EditableListTag::Empty => ListTag::Empty)
     }
 }
 
@@ -376,4 +366,337 @@ impl From<&Vec<ListTag>> for EditableVec<EditableListTag> {
             .map(|item| Editable::new(EditableListTag::from(item)))
             .collect::<Vec<Editable<EditableListTag>>>())
     }
-}
\ No newline at end of file
+}
+/// A single recorded change to an [NbtDocument].
+/// Negative indices in the path are resolved before the edit is recorded, so the path
+/// always refers to the element that was changed.
+#[derive(Clone, Debug)]
+pub enum TagEdit {
+    /// An existing value was replaced.
+    Set {
+        path: TagPath,
+        old: Tag,
+        new: Tag,
+    },
+    /// A value was inserted into a Compound, List, or array.
+    Insert {
+        path: TagPath,
+        value: Tag,
+    },
+    /// A value was removed from a Compound, List, or array.
+    Delete {
+        path: TagPath,
+        old: Tag,
+    },
+}
+
+impl TagEdit {
+    /// The path that this edit was applied to.
+    pub fn path(&self) -> &TagPath {
+        match self {
+            TagEdit::Set { path, .. } => path,
+            TagEdit::Insert { path, .. } => path,
+            TagEdit::Delete { path, .. } => path,
+        }
+    }
+}
+
+/// An NBT document that records every edit made to it so that the edits can be
+/// inspected, accepted, or rolled back.
+/// This holds no UI state, so it can be used as the model for any NBT editor.
+#[derive(Clone, Debug)]
+pub struct NbtDocument {
+    root: Tag,
+    changes: Vec<TagEdit>,
+    undone: Vec<TagEdit>,
+}
+
+impl NbtDocument {
+    pub fn new(root: Tag) -> Self {
+        Self {
+            root,
+            changes: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    /// The current state of the document, including any pending changes.
+    pub fn root(&self) -> &Tag {
+        &self.root
+    }
+
+    pub fn into_root(self) -> Tag {
+        self.root
+    }
+
+    /// Returns true if there are changes that have not been applied or reverted.
+    pub fn is_dirty(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// The pending changes, in the order that they were made.
+    pub fn changes(&self) -> &[TagEdit] {
+        &self.changes
+    }
+
+    /// Gets the value at `path`. An empty path refers to the root.
+    pub fn get(&self, path: &TagPath) -> Option<ValueRef<'_>> {
+        if path.path().is_empty() {
+            Some(ValueRef::from(&self.root))
+        } else {
+            self.root.find_child(path.path())
+        }
+    }
+
+    /// Replaces the value at `path`, which must already exist.
+    /// An empty path replaces the root.
+    pub fn set<T: Into<Tag>>(&mut self, path: TagPath, value: T) -> McResult<()> {
+        let path = resolve_path(&self.root, &path);
+        let new: Tag = value.into();
+        let old = set_at(&mut self.root, &path, new.clone())?;
+        self.record(TagEdit::Set { path, old, new });
+        Ok(())
+    }
+
+    /// Inserts a value at `path`. See [ValueRefMut::insert_child].
+    pub fn insert<T: Into<Tag>>(&mut self, path: TagPath, value: T) -> McResult<()> {
+        let path = resolve_path(&self.root, &path);
+        let value: Tag = value.into();
+        insert_at(&mut self.root, &path, value.clone())?;
+        self.record(TagEdit::Insert { path, value });
+        Ok(())
+    }
+
+    /// Deletes the value at `path`, returning it.
+    pub fn delete(&mut self, path: TagPath) -> McResult<Tag> {
+        let path = resolve_path(&self.root, &path);
+        let old = delete_at(&mut self.root, &path)?;
+        self.record(TagEdit::Delete { path, old: old.clone() });
+        Ok(old)
+    }
+
+    /// Records a new change, which discards the changes that could be redone.
+    fn record(&mut self, edit: TagEdit) {
+        self.undone.clear();
+        self.changes.push(edit);
+    }
+
+    /// Reverts the most recent change and returns it.
+    /// If the change can't be reverted, it stays recorded and the error is returned.
+    pub fn undo(&mut self) -> McResult<Option<TagEdit>> {
+        let Some(edit) = self.changes.last() else {
+            return Ok(None);
+        };
+        match edit {
+            TagEdit::Set { path, old, .. } => { set_at(&mut self.root, path, old.clone())?; },
+            TagEdit::Insert { path, .. } => { delete_at(&mut self.root, path)?; },
+            TagEdit::Delete { path, old } => insert_at(&mut self.root, path, old.clone())?,
+        }
+        let edit = self.changes.pop().expect("The change was found above.");
+        self.undone.push(edit.clone());
+        Ok(Some(edit))
+    }
+
+    /// Reapplies the most recently undone change and returns it.
+    /// Making a new change discards the changes that could be redone.
+    pub fn redo(&mut self) -> McResult<Option<TagEdit>> {
+        let Some(edit) = self.undone.last() else {
+            return Ok(None);
+        };
+        match edit {
+            TagEdit::Set { path, new, .. } => { set_at(&mut self.root, path, new.clone())?; },
+            TagEdit::Insert { path, value } => insert_at(&mut self.root, path, value.clone())?,
+            TagEdit::Delete { path, .. } => { delete_at(&mut self.root, path)?; },
+        }
+        let edit = self.undone.pop().expect("The change was found above.");
+        self.changes.push(edit.clone());
+        Ok(Some(edit))
+    }
+
+    /// Accepts all pending changes, returning them.
+    /// After this, the document is no longer dirty and nothing can be redone.
+    pub fn apply(&mut self) -> Vec<TagEdit> {
+        self.undone.clear();
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Reverts all pending changes, returning the document to the state
+    /// it was in when it was created or last applied.
+    pub fn revert(&mut self) -> McResult<()> {
+        while self.undo()?.is_some() {}
+        Ok(())
+    }
+}
+
+impl From<Tag> for NbtDocument {
+    fn from(value: Tag) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Finds the parent of the node at `path` and returns it along with the last path part.
+fn parent_mut<'a>(root: &'a mut Tag, path: &'a TagPath) -> McResult<(ValueRefMut<'a>, &'a TagPathPart)> {
+    let Some((last, parent)) = path.path().split_last() else {
+        return Err(McError::TagPathNotFound(path.clone()));
+    };
+    let node = if parent.is_empty() {
+        ValueRefMut::from(root)
+    } else {
+        root.find_child_mut(parent).ok_or_else(|| McError::TagPathNotFound(path.clone()))?
+    };
+    Ok((node, last))
+}
+
+/// The number of elements in a List or array.
+fn element_count(node: ValueRef<'_>) -> Option<usize> {
+    match node {
+        ValueRef::ByteArray(array) => Some(array.len()),
+        ValueRef::IntArray(array) => Some(array.len()),
+        ValueRef::LongArray(array) => Some(array.len()),
+        ValueRef::List(list) => Some(list.len()),
+        _ => None,
+    }
+}
+
+/// Replaces the negative indices in `path` with the indices that they refer to in `root`.
+/// Indices that don't resolve to an element are left as they are, so that applying the
+/// path fails the same way that it would have.
+fn resolve_path(root: &Tag, path: &TagPath) -> TagPath {
+    let mut node = Some(ValueRef::from(root));
+    let mut resolved = Vec::with_capacity(path.path().len());
+    for part in path.path() {
+        let part = match (part, node.and_then(element_count)) {
+            (&TagPathPart::AtIndex(index), Some(len)) if index < 0 && len as i64 + index >= 0 => {
+                TagPathPart::AtIndex(len as i64 + index)
+            },
+            _ => part.clone(),
+        };
+        node = node.and_then(|node| node.get_child(&part));
+        resolved.push(part);
+    }
+    TagPath(resolved)
+}
+
+pub(crate) fn set_at(root: &mut Tag, path: &TagPath, value: Tag) -> McResult<Tag> {
+    if path.path().is_empty() {
+        return Ok(std::mem::replace(root, value));
+    }
+    let old = root.find_child(path.path())
+        .map(Tag::from)
+        .ok_or_else(|| McError::TagPathNotFound(path.clone()))?;
+    let (old_id, new_id) = (old.id(), value.id());
+    root.set_child(path.path(), value)
+        .map_err(|_| McError::TagTypeMismatch(old_id, new_id))?;
+    Ok(old)
+}
+
+fn insert_at(root: &mut Tag, path: &TagPath, value: Tag) -> McResult<()> {
+    let (node, last) = parent_mut(root, path)?;
+    node.insert_child(last, value)
+}
+
+fn delete_at(root: &mut Tag, path: &TagPath) -> McResult<Tag> {
+    let (node, last) = parent_mut(root, path)?;
+    node.remove_child(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::Map;
+
+    #[test]
+    fn document_test() -> McResult<()> {
+        let mut root = Map::new();
+        root.insert("name".to_owned(), Tag::string("Steve"));
+        root.insert("values".to_owned(), Tag::IntArray(vec![1, 2, 3]));
+        let mut doc = NbtDocument::new(Tag::Compound(root));
+        assert!(!doc.is_dirty());
+
+        doc.set(TagPath::parse("name").unwrap(), "Alex")?;
+        doc.insert(TagPath::parse("values[1]").unwrap(), 7i32)?;
+        doc.delete(TagPath::parse("values[0]").unwrap())?;
+        doc.insert(TagPath::parse("tags").unwrap(), ListTag::Empty)?;
+        doc.insert(TagPath::parse("tags[0]").unwrap(), "one")?;
+        assert!(doc.is_dirty());
+        assert_eq!(doc.changes().len(), 5);
+        assert!(matches!(doc.insert(TagPath::parse("values[0]").unwrap(), 1i64), Err(McError::TagTypeMismatch(TagID::Int, TagID::Long))));
+        assert_eq!(doc.get(&TagPath::parse("name").unwrap()).and_then(ValueRef::as_str), Some("Alex"));
+        assert!(matches!(doc.get(&TagPath::parse("values").unwrap()), Some(ValueRef::IntArray(values)) if values == &vec![7, 2, 3]));
+
+        doc.revert()?;
+        assert!(!doc.is_dirty());
+        assert_eq!(doc.get(&TagPath::parse("name").unwrap()).and_then(ValueRef::as_str), Some("Steve"));
+        assert!(matches!(doc.get(&TagPath::parse("values").unwrap()), Some(ValueRef::IntArray(values)) if values == &vec![1, 2, 3]));
+        assert!(doc.get(&TagPath::parse("tags").unwrap()).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn negative_index_test() -> McResult<()> {
+        let mut root = Map::new();
+        root.insert("values".to_owned(), Tag::IntArray(vec![1, 2, 3]));
+        root.insert("names".to_owned(), Tag::List(ListTag::String(vec!["a".to_owned(), "b".to_owned()])));
+        let mut doc = NbtDocument::new(Tag::Compound(root));
+        let values = |doc: &NbtDocument| match doc.get(&TagPath::parse("values").unwrap()) {
+            Some(ValueRef::IntArray(values)) => values.clone(),
+            _ => panic!("Expected an IntArray."),
+        };
+
+        // Inserting at -1 inserts before the last element, at index 2.
+        doc.insert(TagPath::parse("values[-1]").unwrap(), 7i32)?;
+        assert_eq!(values(&doc), vec![1, 2, 7, 3]);
+        assert!(matches!(doc.changes()[0].path().path(), [TagPathPart::AtKey(_), TagPathPart::AtIndex(2)]));
+        doc.delete(TagPath::parse("values[-1]").unwrap())?;
+        assert_eq!(values(&doc), vec![1, 2, 7]);
+        doc.set(TagPath::parse("names[-1]").unwrap(), "c")?;
+        assert!(matches!(doc.changes()[2].path().path(), [TagPathPart::AtKey(_), TagPathPart::AtIndex(1)]));
+
+        doc.undo()?;
+        assert_eq!(doc.get(&TagPath::parse("names[1]").unwrap()).and_then(ValueRef::as_str), Some("b"));
+        doc.undo()?;
+        assert_eq!(values(&doc), vec![1, 2, 7, 3]);
+        doc.undo()?;
+        assert_eq!(values(&doc), vec![1, 2, 3]);
+        assert!(!doc.is_dirty());
+        assert!(doc.undo()?.is_none());
+
+        doc.redo()?;
+        assert_eq!(values(&doc), vec![1, 2, 7, 3]);
+        doc.redo()?;
+        assert_eq!(values(&doc), vec![1, 2, 7]);
+        doc.redo()?;
+        assert_eq!(doc.get(&TagPath::parse("names[1]").unwrap()).and_then(ValueRef::as_str), Some("c"));
+        assert!(doc.redo()?.is_none());
+        assert_eq!(doc.changes().len(), 3);
+
+        // A change that can't be undone stays recorded.
+        doc.undo()?;
+        doc.undo()?;
+        let mut stuck = doc.clone();
+        stuck.root = Tag::Compound(Map::new());
+        assert!(stuck.undo().is_err());
+        assert_eq!(stuck.changes().len(), 1);
+        // A new change discards what could be redone.
+        doc.insert(TagPath::parse("values[0]").unwrap(), 0i32)?;
+        assert!(doc.redo()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn to_value_test() {
+        let mut root = Map::new();
+        root.insert("name".to_owned(), Tag::string("Steve"));
+        root.insert("pos".to_owned(), Tag::List(ListTag::Double(vec![1.0, 64.0, -2.5])));
+        root.insert("items".to_owned(), Tag::List(ListTag::Compound(vec![Map::from_iter([("Count".to_owned(), Tag::Byte(3))])])));
+        root.insert("nested".to_owned(), Tag::List(ListTag::List(vec![ListTag::Empty, ListTag::Int(vec![4])])));
+        let editable = EditableTag::from(Tag::Compound(root));
+        let Tag::Compound(map) = editable.to_value() else {
+            panic!("Expected a compound.");
+        };
+        assert!(matches!(map.get("name"), Some(Tag::String(name)) if name == "Steve"));
+        assert!(matches!(map.get("pos"), Some(Tag::List(ListTag::Double(pos))) if pos == &vec![1.0, 64.0, -2.5]));
+        assert!(matches!(map.get("items"), Some(Tag::List(ListTag::Compound(items))) if matches!(items[0].get("Count"), Some(Tag::Byte(3)))));
+        assert!(matches!(map.get("nested"), Some(Tag::List(ListTag::List(lists))) if matches!(lists.as_slice(), [ListTag::Empty, ListTag::Int(ints)] if ints == &vec![4])));
+    }
+}
@@ -0,0 +1,244 @@
+//! egui widgets for editing NBT (requires the `egui` feature).
+//! [ValueEditor] is implemented for every tag type so that an [Editable] can be
+//! edited in place (lists and compounds with [TreeEditor]), and [nbt_inspector] draws an entire [NbtDocument] as a tree
+//! that records every edit in the document.
+
+use std::rc::Rc;
+
+use egui::{
+    CollapsingHeader,
+    DragValue,
+    Response,
+    Ui,
+    emath::Numeric,
+};
+
+use crate::nbt::{
+    Map,
+    editable::*,
+    tag::*,
+    tagpath::TagPath,
+    tagtype::*,
+};
+
+/// Edits a single number with a [DragValue].
+#[derive(Default)]
+pub struct NumberEditor;
+
+/// Edits a [String] with a single line text edit.
+#[derive(Default)]
+pub struct TextEditor;
+
+/// Edits an array of numbers with a [DragValue] for each element, and buttons
+/// to insert and remove elements.
+#[derive(Default)]
+pub struct ArrayEditor;
+
+/// Edits a list or compound as a tree, with an editor for each of its values.
+#[derive(Default)]
+pub struct TreeEditor;
+
+/// Draws each element of `array` with buttons for removing elements and appending
+/// a new element. The response is marked as changed if the array was changed.
+fn edit_array<T: Numeric + Default>(ui: &mut Ui, array: &mut Vec<T>) -> Response {
+    let mut remove = None;
+    let mut response = ui.vertical(|ui| {
+        let mut response = ui.label(format!("{} elements", array.len()));
+        for (index, item) in array.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("[{index}]"));
+                response |= ui.add(DragValue::new(item));
+                if ui.small_button("-").clicked() {
+                    remove = Some(index);
+                }
+            });
+        }
+        if ui.small_button("+").clicked() {
+            array.push(T::default());
+            response.mark_changed();
+        }
+        response
+    }).inner;
+    if let Some(index) = remove {
+        array.remove(index);
+        response.mark_changed();
+    }
+    response
+}
+
+macro_rules! number_editor {
+    ($($type:ty)+) => {
+        $(
+            impl ValueEditor<$type> for NumberEditor {
+                fn edit_value(&mut self, ui: &mut Ui, node: &mut Editable<$type>, value: Rc<$type>) -> Response {
+                    let mut edit = *value;
+                    let response = ui.add(DragValue::new(&mut edit));
+                    if response.changed() {
+                        node.set(edit);
+                    }
+                    response
+                }
+            }
+        )+
+    };
+}
+
+number_editor!(Byte Short Int Long Float Double);
+
+impl ValueEditor<String> for TextEditor {
+    fn edit_value(&mut self, ui: &mut Ui, node: &mut Editable<String>, value: Rc<String>) -> Response {
+        let mut edit = value.as_ref().clone();
+        let response = ui.text_edit_singleline(&mut edit);
+        if response.changed() {
+            node.set(edit);
+        }
+        response
+    }
+}
+
+macro_rules! array_editor {
+    ($($type:ty)+) => {
+        $(
+            impl ValueEditor<$type> for ArrayEditor {
+                fn edit_value(&mut self, ui: &mut Ui, node: &mut Editable<$type>, value: Rc<$type>) -> Response {
+                    let mut edit = value.as_ref().clone();
+                    let response = edit_array(ui, &mut edit);
+                    if response.changed() {
+                        node.set(edit);
+                    }
+                    response
+                }
+            }
+        )+
+    };
+}
+
+array_editor!(ByteArray IntArray LongArray);
+
+/// Draws `tag` as a tree and applies the first edit made in it.
+/// The response is marked as changed if `tag` was changed.
+fn edit_tree(ui: &mut Ui, tag: &mut Tag) -> Response {
+    let mut edit = None;
+    let mut response = inspect_tag(ui, String::from("value"), TagPath(Vec::new()), tag, &mut edit);
+    if let Some((path, value)) = edit {
+        if set_at(tag, &path, value).is_ok() {
+            response.mark_changed();
+        }
+    }
+    response
+}
+
+impl ValueEditor<EditableListTag> for TreeEditor {
+    fn edit_value(&mut self, ui: &mut Ui, node: &mut Editable<EditableListTag>, value: Rc<EditableListTag>) -> Response {
+        let mut tag = Tag::List(value.to_value());
+        let response = edit_tree(ui, &mut tag);
+        if response.changed() {
+            if let Tag::List(list) = tag {
+                node.set(list.into());
+            }
+        }
+        response
+    }
+}
+
+impl ValueEditor<EditableMap> for TreeEditor {
+    fn edit_value(&mut self, ui: &mut Ui, node: &mut Editable<EditableMap>, value: Rc<EditableMap>) -> Response {
+        let mut tag = Tag::Compound(value.to_value());
+        let response = edit_tree(ui, &mut tag);
+        if response.changed() {
+            if let Tag::Compound(map) = tag {
+                node.set(map_to_editable(&map));
+            }
+        }
+        response
+    }
+}
+
+/// Draws `tag` and its children, writing the first edit made into `edit`.
+fn inspect_tag(ui: &mut Ui, label: String, path: TagPath, tag: &Tag, edit: &mut Option<(TagPath, Tag)>) -> Response {
+    macro_rules! edit_number {
+        ($variant:ident $value:ident) => {
+            {
+                let mut value = *$value;
+                ui.horizontal(|ui| {
+                    ui.label(&label);
+                    let response = ui.add(DragValue::new(&mut value));
+                    if response.changed() {
+                        *edit = Some((path.clone(), Tag::$variant(value)));
+                    }
+                    response
+                }).inner
+            }
+        };
+    }
+    macro_rules! edit_array_tag {
+        ($variant:ident $value:ident) => {
+            CollapsingHeader::new(format!("{label}: {:?}", TagID::$variant))
+                .id_source(path.to_string())
+                .show(ui, |ui| {
+                    let mut value = $value.clone();
+                    let response = edit_array(ui, &mut value);
+                    if response.changed() {
+                        *edit = Some((path.clone(), Tag::$variant(value)));
+                    }
+                    response
+                }).header_response
+        };
+    }
+    match tag {
+        Tag::Byte(value) => edit_number!(Byte value),
+        Tag::Short(value) => edit_number!(Short value),
+        Tag::Int(value) => edit_number!(Int value),
+        Tag::Long(value) => edit_number!(Long value),
+        Tag::Float(value) => edit_number!(Float value),
+        Tag::Double(value) => edit_number!(Double value),
+        Tag::String(value) => ui.horizontal(|ui| {
+            ui.label(&label);
+            let mut value = value.clone();
+            let response = ui.text_edit_singleline(&mut value);
+            if response.changed() {
+                *edit = Some((path.clone(), Tag::String(value)));
+            }
+            response
+        }).inner,
+        Tag::ByteArray(value) => edit_array_tag!(ByteArray value),
+        Tag::IntArray(value) => edit_array_tag!(IntArray value),
+        Tag::LongArray(value) => edit_array_tag!(LongArray value),
+        Tag::List(_) => CollapsingHeader::new(format!("{label}: List"))
+            .id_source(path.to_string())
+            .show(ui, |ui| {
+                (0i64..).map_while(|index| tag.get_child(&index.into()).map(|child| (index, Tag::from(child))))
+                    .for_each(|(index, child)| {
+                        inspect_tag(ui, format!("[{index}]"), path.join(index), &child, edit);
+                    });
+            }).header_response,
+        Tag::Compound(map) => inspect_compound(ui, label, path, map, edit),
+    }
+}
+
+fn inspect_compound(ui: &mut Ui, label: String, path: TagPath, map: &Map, edit: &mut Option<(TagPath, Tag)>) -> Response {
+    CollapsingHeader::new(format!("{label}: Compound"))
+        .id_source(path.to_string())
+        .default_open(path.path().is_empty())
+        .show(ui, |ui| {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            keys.into_iter().for_each(|key| {
+                inspect_tag(ui, key.to_owned(), path.join(key.as_str()), &map[key], edit);
+            });
+        }).header_response
+}
+
+/// Draws an inspector panel for `document`.
+/// Edits made in the panel are recorded in the document, so they can be
+/// reviewed with [NbtDocument::changes] and rolled back with [NbtDocument::revert].
+pub fn nbt_inspector(ui: &mut Ui, document: &mut NbtDocument) -> Response {
+    let mut edit = None;
+    let mut response = inspect_tag(ui, String::from("root"), TagPath(Vec::new()), document.root(), &mut edit);
+    if let Some((path, value)) = edit {
+        if document.set(path, value).is_ok() {
+            response.mark_changed();
+        }
+    }
+    response
+}
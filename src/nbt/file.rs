@@ -0,0 +1,86 @@
+//! Reading and writing whole NBT files (such as `level.dat`), which may be
+//! GZip compressed, ZLib compressed, or uncompressed.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use flate2::Compression;
+
+use crate::{
+    ioext::{Codec, IoConfig, ReadExt, SchemeCodec, atomic_replace},
+    world::io::region::CompressionScheme,
+    McError, McResult,
+};
+
+use super::{
+    io::NbtWrite,
+    tag::NamedTag,
+};
+
+/// Reads the root tag of an NBT file, detecting the compression from the first bytes.
+pub fn read_nbt_file<P: AsRef<Path>>(path: P) -> McResult<NamedTag> {
+    read_nbt_file_detect(path).map(|(root, _)| root)
+}
+
+/// Reads the root tag of an NBT file like [read_nbt_file], and also returns the compression
+/// that the file was written with ([CompressionScheme::GZip], [CompressionScheme::ZLib], or
+/// [CompressionScheme::Uncompressed]), so that it can be written back the same way.
+pub fn read_nbt_file_detect<P: AsRef<Path>>(path: P) -> McResult<(NamedTag, CompressionScheme)> {
+    let mut file = File::open(path)?;
+    let mut header = Vec::with_capacity(2);
+    (&mut file).take(2).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    // Anything that isn't GZip or ZLib is read as an uncompressed root.
+    let scheme = match CompressionScheme::detect(&header) {
+        Some(CompressionScheme::GZip) => CompressionScheme::GZip,
+        Some(CompressionScheme::ZLib) => CompressionScheme::ZLib,
+        _ => CompressionScheme::Uncompressed,
+    };
+    Ok((read_codec(file, &scheme)?, scheme))
+}
+
+/// Reads the root tag of an NBT file that was compressed with `codec`.
+pub fn read_nbt_file_with_codec<P: AsRef<Path>>(path: P, codec: &dyn Codec) -> McResult<NamedTag> {
+    read_codec(File::open(path)?, codec)
+}
+
+fn read_codec(file: File, codec: &dyn Codec) -> McResult<NamedTag> {
+    let reader = BufReader::with_capacity(IoConfig::default().read_buf, file);
+    codec.decoder(Box::new(reader))?.read_value()
+}
+
+/// Writes the root tag of an NBT file with GZip compression (the format used by the game),
+/// or uncompressed if `compression` is [Compression::none()].
+pub fn write_nbt_file<P: AsRef<Path>>(path: P, root: &NamedTag, compression: Compression) -> McResult<usize> {
+    let scheme = if compression == Compression::none() {
+        CompressionScheme::Uncompressed
+    } else {
+        CompressionScheme::GZip
+    };
+    write_nbt_file_with(path, root, scheme, compression)
+}
+
+/// Writes the root tag of an NBT file with the given compression scheme, such as the one
+/// returned by [read_nbt_file_detect]. `compression` is the level used by GZip and ZLib.
+/// Returns [McError::UnsupportedCustomCompression] for [CompressionScheme::Custom].
+pub fn write_nbt_file_with<P: AsRef<Path>>(path: P, root: &NamedTag, scheme: CompressionScheme, compression: Compression) -> McResult<usize> {
+    if scheme == CompressionScheme::Custom {
+        return Err(McError::UnsupportedCustomCompression("NBT files can't use a custom compression scheme.".to_owned()));
+    }
+    write_nbt_file_with_codec(path, root, &SchemeCodec::new(scheme, compression))
+}
+
+/// Writes the root tag of an NBT file compressed with `codec`, replacing the file atomically.
+pub fn write_nbt_file_with_codec<P: AsRef<Path>>(path: P, root: &NamedTag, codec: &dyn Codec) -> McResult<usize> {
+    atomic_replace(path, |file| {
+        let mut writer = BufWriter::with_capacity(IoConfig::default().write_buf, file);
+        let mut encoder = codec.encoder(Box::new(&mut writer))?;
+        let size = root.nbt_write(&mut encoder)?;
+        encoder.finish_write()?;
+        writer.flush()?;
+        Ok(size)
+    })
+}
@@ -12,6 +12,7 @@ use crate::{
             NamedTag,
         },
         family::*,
+        mutf8,
         tag_info_table,
     },
     ioext::*,
@@ -19,6 +20,59 @@ use crate::{
 };
 use std::io::{ Read, Write };
 
+/// The maximum lengths that are accepted when reading NBT. A length field that is
+/// larger than its limit (which usually means that the data is corrupt) fails with
+/// [McError::LengthLimitExceeded] before anything is allocated for it.
+///
+/// The limits are passed to each read, see [ReadNbt::read_nbt_with_limits].
+/// Reads that don't take limits use [ReadLimits::DEFAULT].
+/// They also choose whether strings that aren't valid Modified UTF-8 fail the read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadLimits {
+    /// The maximum length of a string in bytes.
+    pub max_string_len: usize,
+    /// The maximum number of elements in a ByteArray, IntArray, or LongArray.
+    pub max_array_len: usize,
+    /// The maximum number of elements in a List.
+    pub max_list_len: usize,
+    /// Read strings that aren't valid Modified UTF-8 with invalid sequences replaced
+    /// by `U+FFFD` instead of failing with [McError::InvalidModifiedUtf8].
+    pub lossy_strings: bool,
+}
+
+impl ReadLimits {
+    /// Limits that are far above what the game writes.
+    pub const DEFAULT: Self = Self {
+        max_string_len: u16::MAX as usize,
+        max_array_len: 1 << 26,
+        max_list_len: 1 << 24,
+        lossy_strings: false,
+    };
+
+    /// No limits, other than what fits in the length fields.
+    pub const UNLIMITED: Self = Self {
+        max_string_len: usize::MAX,
+        max_array_len: usize::MAX,
+        max_list_len: usize::MAX,
+        lossy_strings: false,
+    };
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Returns `length` as a [usize] if it is within `max`.
+fn check_length(kind: &'static str, length: u32, max: usize) -> Result<usize, McError> {
+    let length = length as usize;
+    if length > max {
+        return Err(McError::LengthLimitExceeded(kind, length, max));
+    }
+    Ok(length)
+}
+
 /// Trait that gives the serialization size in bytes of various values.
 /// This size may include a 2 or 4 byte length, or a single byte end marker in addition to the payload.
 pub trait NbtSize {
@@ -30,6 +84,8 @@ pub trait NbtSize {
 pub trait ReadNbt: Read {
     /// Read NBT (anything that implements NbtRead).
     fn read_nbt<T: NbtRead>(&mut self) -> Result<T, McError>;
+    /// Read NBT, failing with [McError::LengthLimitExceeded] if a length is over `limits`.
+    fn read_nbt_with_limits<T: NbtRead>(&mut self, limits: &ReadLimits) -> Result<T, McError>;
 }
 
 // std::io::Read extension method read_nbt implementation.
@@ -38,6 +94,11 @@ impl<Reader: Read> ReadNbt for Reader {
     fn read_nbt<T: NbtRead>(&mut self) -> Result<T, McError> {
         T::nbt_read(self)
     }
+
+    /// Read NBT, failing with [McError::LengthLimitExceeded] if a length is over `limits`.
+    fn read_nbt_with_limits<T: NbtRead>(&mut self, limits: &ReadLimits) -> Result<T, McError> {
+        T::nbt_read_limited(self, limits)
+    }
 }
 
 /// Trait applied to all writers for NBT extensions.
@@ -63,6 +124,12 @@ impl<Writer: Write> WriteNbt for Writer {
 pub trait NbtRead: Sized {
     /// Attempt to read a value from a reader.
     fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError>;
+
+    /// Attempt to read a value from a reader, checking any lengths against `limits`.
+    /// Types without a length read the same as [NbtRead::nbt_read].
+    fn nbt_read_limited<R: Read>(reader: &mut R, _limits: &ReadLimits) -> Result<Self, McError> {
+        Self::nbt_read(reader)
+    }
 }
 
 impl<T: NbtRead> Readable for T {
@@ -122,12 +189,17 @@ macro_rules! tag_io {
         There is no restriction on what type this tag can be, though.
         "]
         pub fn read_named_tag<R: Read>(reader: &mut R) -> Result<(String, Tag), McError> {
+            read_named_tag_with_limits(reader, &ReadLimits::DEFAULT)
+        }
+
+        #[doc = "Like [read_named_tag], but checks lengths against `limits` instead of [ReadLimits::DEFAULT]."]
+        pub fn read_named_tag_with_limits<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<(String, Tag), McError> {
             let id = TagID::nbt_read(reader)?;
-            let name = String::nbt_read(reader)?;
+            let name = String::nbt_read_limited(reader, limits)?;
             let tag = match id {
                 $(
                     TagID::$title => {
-                        Tag::$title(<$type>::nbt_read(reader)?)
+                        Tag::$title(<$type>::nbt_read_limited(reader, limits)?)
                     }
                 )+
             };
@@ -160,13 +232,18 @@ macro_rules! tag_io {
         impl NbtRead for ListTag {
             #[doc = "Attempt to read a [ListTag] from a reader."]
             fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError> {
+                Self::nbt_read_limited(reader, &ReadLimits::DEFAULT)
+            }
+
+            #[doc = "Attempt to read a [ListTag] from a reader, checking lengths against `limits`."]
+            fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
                 let id = TagID::nbt_read(reader);
                 match id {
                     $(
                         Ok(TagID::$title) => {
-                            let length = u32::nbt_read(reader)?;
+                            let length = check_length("list", u32::nbt_read(reader)?, limits.max_list_len)?;
                             Ok(ListTag::$title(
-                                read_array(reader, length as usize)?
+                                read_array(reader, length, limits)?
                             ))
                         },
                     )+
@@ -203,6 +280,11 @@ macro_rules! tag_io {
         impl NbtRead for Map {
             #[doc = "Attempt to read a [Map] from a reader."]
             fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError> {
+                Self::nbt_read_limited(reader, &ReadLimits::DEFAULT)
+            }
+
+            #[doc = "Attempt to read a [Map] from a reader, checking lengths against `limits`."]
+            fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
                 // Reading goes like this:
                 // Read TagID
                 // if TagID is not End or Unsupported,
@@ -215,10 +297,10 @@ macro_rules! tag_io {
                 while !matches!(id, Err($crate::McError::EndTagMarker)) {
                     match id {
                         Ok(id) => {
-                            let name = String::nbt_read(reader)?;
+                            let name = String::nbt_read_limited(reader, limits)?;
                             let tag = match id {
                                 $(
-                                    TagID::$title => Tag::$title(<$type>::nbt_read(reader)?),
+                                    TagID::$title => Tag::$title(<$type>::nbt_read_limited(reader, limits)?),
                                 )+
                             };
                             map.insert(name, tag);
@@ -244,6 +326,13 @@ macro_rules! tag_io {
     };
 }
 
+/// Writes a tag the way it is sent over the network since 1.20.2: the [Tag]'s ID followed by
+/// the tag itself, without the name that [write_named_tag] writes.
+pub fn write_network_tag<W: Write>(writer: &mut W, tag: &Tag) -> Result<usize, McError> {
+    tag.id().nbt_write(writer)?;
+    tag.nbt_write(writer).map(|size| size + 1)
+}
+
 /// Blanket implementations for reading and writing primitives (scalar types).
 macro_rules! primitive_io {
     ($($primitive:ident)+) => {
@@ -291,12 +380,12 @@ fn write_bytes<W: Write>(writer: &mut W, data: &[u8]) -> Result<usize, McError>
 }
 
 /// Reads a certain number of elements from a reader.
-fn read_array<R, T>(reader: &mut R, length: usize) -> Result<Vec<T>, McError>
+fn read_array<R, T>(reader: &mut R, length: usize, limits: &ReadLimits) -> Result<Vec<T>, McError>
 where
     R: Read,
     T: NbtRead,
 {
-    (0..length).map(|_| T::nbt_read(reader)).collect()
+    (0..length).map(|_| T::nbt_read_limited(reader, limits)).collect()
 }
 
 /// Writes elements to a writer, returning the total number of bytes written.
@@ -325,7 +414,7 @@ impl<T: Primitive + Sized> NbtSize for Vec<T> {
 impl NbtSize for String {
     /// Get the number of bytes that this data will serialize to.
     fn nbt_size(&self) -> usize {
-        /*2 bytes for the length*/ 2usize + self.len()
+        /*2 bytes for the length*/ 2usize + mutf8::encoded_len(self)
     }
 }
 
@@ -382,21 +471,36 @@ impl<S: From<String>, T: From<Tag>> NbtRead for (S, T) {
         let (name, tag) = read_named_tag(reader)?;
         Ok((S::from(name), T::from(tag)))
     }
+
+    fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
+        let (name, tag) = read_named_tag_with_limits(reader, limits)?;
+        Ok((S::from(name), T::from(tag)))
+    }
 }
 
 impl<T: NbtRead + NonByte> NbtRead for Vec<T> {
     /// Read a [Vec] from a reader.
     fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError> {
-        let length = u32::nbt_read(reader)?;
-        read_array(reader, length as usize)
+        Self::nbt_read_limited(reader, &ReadLimits::DEFAULT)
+    }
+
+    /// Read a [Vec] from a reader, checking lengths against `limits`.
+    fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
+        let length = check_length("array", u32::nbt_read(reader)?, limits.max_array_len)?;
+        read_array(reader, length, limits)
     }
 }
 
 impl NbtRead for Vec<i8> {
     /// Read a bytearray from a reader.
     fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError> {
-        let length = u32::nbt_read(reader)?;
-        let bytes = read_bytes(reader, length as usize)?;
+        Self::nbt_read_limited(reader, &ReadLimits::DEFAULT)
+    }
+
+    /// Read a bytearray from a reader, checking its length against `limits`.
+    fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
+        let length = check_length("array", u32::nbt_read(reader)?, limits.max_array_len)?;
+        let bytes = read_bytes(reader, length)?;
         // Use compiler magic to convert Vec<u8> to Vec<i8>
         Ok(
             bytes.into_iter()
@@ -409,15 +513,24 @@ impl NbtRead for Vec<i8> {
 impl NbtRead for String {
     /// Read a String from a reader.
     fn nbt_read<R: Read>(reader: &mut R) -> Result<Self, McError> {
+        Self::nbt_read_limited(reader, &ReadLimits::DEFAULT)
+    }
+
+    /// Read a String from a reader, checking its length against `limits`.
+    fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Self, McError> {
         // 🦆 <-- Frank
         // Frank: How does this function work, eh?
         // Me: Well, you see, to read a string in NBT format, we first
         //     need to read a 16-bit unsigned big endian integer, that
         //     signifies our length. We then read that number of bytes
-        //     and interpret those bytes as a utf-8 string.
-        let length: u16 = u16::nbt_read(reader)?;
-        let strbytes = read_bytes(reader, length as usize)?;
-        Ok(String::from_utf8(strbytes)?)
+        //     and interpret those bytes as a Modified UTF-8 string.
+        let length = check_length("string", u16::nbt_read(reader)? as u32, limits.max_string_len)?;
+        let strbytes = read_bytes(reader, length)?;
+        if limits.lossy_strings {
+            Ok(mutf8::decode_lossy(&strbytes))
+        } else {
+            mutf8::decode(&strbytes)
+        }
     }
 }
 
@@ -447,14 +560,21 @@ impl NbtRead for NamedTag {
     fn nbt_read<R: Read>(reader: &mut R) -> Result<NamedTag, McError> {
         Ok(read_named_tag(reader)?.into())
     }
+
+    #[doc = "Attempt to read a [NamedTag] from a reader, checking lengths against `limits`."]
+    fn nbt_read_limited<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<NamedTag, McError> {
+        Ok(read_named_tag_with_limits(reader, limits)?.into())
+    }
 }
 
 impl NbtWrite for &str {
-    /// Write a string to a writer.
+    /// Write a string to a writer as Modified UTF-8.
+    /// Returns [McError::StringTooLong] if it is longer than 65535 bytes when encoded.
     fn nbt_write<W: Write>(&self, writer: &mut W) -> Result<usize, McError> {
-        let length: u16 = self.len() as u16;
+        let bytes = mutf8::encode(self);
+        let length: u16 = u16::try_from(bytes.len()).map_err(|_| McError::StringTooLong(bytes.len()))?;
         length.nbt_write(writer)?;
-        Ok(writer.write_all(self.as_bytes()).map(|_| self.len() + 2)?)
+        Ok(writer.write_all(&bytes).map(|_| bytes.len() + 2)?)
     }
 }
 
@@ -569,4 +689,33 @@ mod tests {
         compound.insert("Compound".to_owned(), Tag::Compound(mapclone));
         Tag::Compound(compound)
     }
+
+    #[test]
+    fn read_limits_test() -> Result<(), McError> {
+        let mut buffer = Vec::new();
+        NamedTag::new(test_tag()).nbt_write(&mut buffer)?;
+        assert!(NamedTag::nbt_read(&mut buffer.as_slice()).is_ok());
+        assert!(buffer.as_slice().read_nbt_with_limits::<NamedTag>(&ReadLimits::UNLIMITED).is_ok());
+        // Limits only apply to the read they are passed to.
+        let tight = ReadLimits { max_string_len: 8, ..ReadLimits::DEFAULT };
+        assert!(matches!(
+            buffer.as_slice().read_nbt_with_limits::<NamedTag>(&tight),
+            Err(McError::LengthLimitExceeded("string", _, 8))
+        ));
+        let tight = ReadLimits { max_array_len: 4, ..ReadLimits::DEFAULT };
+        assert!(matches!(
+            buffer.as_slice().read_nbt_with_limits::<NamedTag>(&tight),
+            Err(McError::LengthLimitExceeded("array", _, 4))
+        ));
+        assert!(NamedTag::nbt_read(&mut buffer.as_slice()).is_ok());
+        // A corrupt length is rejected instead of being allocated.
+        let corrupt = [&[TagID::LongArray.value() as u8, 0, 0], &u32::MAX.to_be_bytes()[..]].concat();
+        assert!(matches!(
+            NamedTag::nbt_read(&mut corrupt.as_slice()),
+            Err(McError::LengthLimitExceeded("array", len, max)) if len == u32::MAX as usize && max == ReadLimits::DEFAULT.max_array_len
+        ));
+        let corrupt = [&[TagID::List.value() as u8, 0, 0, TagID::Compound.value() as u8], &u32::MAX.to_be_bytes()[..]].concat();
+        assert!(matches!(NamedTag::nbt_read(&mut corrupt.as_slice()), Err(McError::LengthLimitExceeded("list", _, _))));
+        Ok(())
+    }
 }
\ No newline at end of file
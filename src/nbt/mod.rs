@@ -1,16 +1,25 @@
 #![allow(unused)]
+#[doc(hidden)]
 pub mod family;
 pub mod io;
+pub mod mutf8;
 pub(crate) mod table;
 pub mod tag;
 pub mod macros;
 pub mod snbt;
 pub mod tagtype;
 // format is incomplete, and I have no need to finish it, so it will remain incomplete until it is needed.
+#[doc(hidden)]
 pub mod format;
 pub mod tagpath;
 pub mod tagref;
 pub mod editable;
+pub mod file;
+pub mod stats;
+#[cfg(feature = "egui")]
+pub mod editor;
+#[cfg(feature = "bedrock")]
+pub mod bedrock;
 
 // /// This is the Error type returned from NbtRead and NbtWrite operations that fail.
 // #[derive(thiserror::Error, Debug)]
@@ -38,9 +47,12 @@ pub mod editable;
 use indexmap::IndexMap;
 
 pub(crate) use crate::tag_info_table;
+pub(crate) use crate::for_each_tag_type;
 #[cfg(feature = "preserve_order")]
 pub type MapType<V> = IndexMap<String, V>;
 #[cfg(not(feature = "preserve_order"))]
 pub type MapType<V> = std::collections::HashMap<String, V>;
 
+/// The map of a compound tag. Without the `preserve_order` feature, iterating over it (and
+/// so writing it) visits the keys in an unspecified order that can change between runs.
 pub type Map = MapType<tag::Tag>;
\ No newline at end of file
@@ -0,0 +1,145 @@
+//! Java's Modified UTF-8, which is the encoding of strings in NBT.
+//!
+//! Modified UTF-8 differs from UTF-8 in two ways: the null character is encoded as the
+//! two bytes `0xC0 0x80`, and characters outside of the Basic Multilingual Plane are
+//! encoded as a surrogate pair, with each half as its own three byte sequence (CESU-8).
+//! Strings that have neither are encoded the same in both.
+//!
+//! Decoding also accepts plain UTF-8 (including four byte sequences), since some tools
+//! write it. When a string can't be decoded, reading fails unless the read was given
+//! [ReadLimits](crate::nbt::io::ReadLimits) with `lossy_strings` set, in which case
+//! invalid sequences are replaced with `U+FFFD`.
+
+use std::borrow::Cow;
+
+use crate::{McError, McResult};
+
+/// Returns true if `text` is encoded the same in Modified UTF-8 as in UTF-8.
+fn is_plain(text: &str) -> bool {
+    text.bytes().all(|byte| byte != 0 && byte < 0xF0)
+}
+
+/// The number of bytes that `text` encodes to.
+pub fn encoded_len(text: &str) -> usize {
+    text.chars().map(|c| match c as u32 {
+        0 => 2,
+        0x10000.. => 6,
+        _ => c.len_utf8(),
+    }).sum()
+}
+
+/// Encodes `text` as Modified UTF-8. This only allocates if the encoding differs from UTF-8.
+pub fn encode(text: &str) -> Cow<'_, [u8]> {
+    if is_plain(text) {
+        return Cow::Borrowed(text.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(encoded_len(text));
+    let mut units = [0u16; 2];
+    for c in text.chars() {
+        match c as u32 {
+            0 => bytes.extend_from_slice(&[0xC0, 0x80]),
+            0x10000.. => c.encode_utf16(&mut units).iter().for_each(|&unit| {
+                bytes.extend_from_slice(&[
+                    0xE0 | (unit >> 12) as u8,
+                    0x80 | ((unit >> 6) & 0x3F) as u8,
+                    0x80 | (unit & 0x3F) as u8,
+                ]);
+            }),
+            _ => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+/// Decodes the UTF-16 code units of `bytes`. A `None` is an invalid sequence.
+fn decode_units(bytes: &[u8]) -> Vec<Option<u16>> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let continuation = |index: usize| bytes.get(index).filter(|&&byte| byte & 0xC0 == 0x80).map(|&byte| (byte & 0x3F) as u32);
+    let mut index = 0;
+    while index < bytes.len() {
+        let lead = bytes[index] as u32;
+        let (code_point, len) = match lead {
+            0x00..=0x7F => (Some(lead), 1),
+            0xC0..=0xDF => match continuation(index + 1) {
+                Some(b1) => (Some((lead & 0x1F) << 6 | b1), 2),
+                None => (None, 1),
+            },
+            0xE0..=0xEF => match (continuation(index + 1), continuation(index + 2)) {
+                (Some(b1), Some(b2)) => (Some((lead & 0x0F) << 12 | b1 << 6 | b2), 3),
+                _ => (None, 1),
+            },
+            0xF0..=0xF7 => match (continuation(index + 1), continuation(index + 2), continuation(index + 3)) {
+                (Some(b1), Some(b2), Some(b3)) => (Some((lead & 0x07) << 18 | b1 << 12 | b2 << 6 | b3), 4),
+                _ => (None, 1),
+            },
+            _ => (None, 1),
+        };
+        match code_point.map(char::from_u32) {
+            // Surrogates aren't chars, but are still valid code units.
+            Some(None) => units.push(code_point.map(|unit| unit as u16)),
+            Some(Some(c)) => units.extend(c.encode_utf16(&mut [0; 2]).iter().map(|&unit| Some(unit))),
+            None => units.push(None),
+        }
+        index += len;
+    }
+    units
+}
+
+/// Decodes Modified UTF-8 (or UTF-8). Returns [McError::InvalidModifiedUtf8] if `bytes`
+/// contains an invalid sequence or an unpaired surrogate.
+pub fn decode(bytes: &[u8]) -> McResult<String> {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(text.to_owned());
+    }
+    let units = decode_units(bytes).into_iter()
+        .collect::<Option<Vec<u16>>>()
+        .ok_or(McError::InvalidModifiedUtf8)?;
+    String::from_utf16(&units).map_err(|_| McError::InvalidModifiedUtf8)
+}
+
+/// Decodes Modified UTF-8 (or UTF-8), replacing invalid sequences and unpaired
+/// surrogates with `U+FFFD`.
+pub fn decode_lossy(bytes: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_owned();
+    }
+    let units = decode_units(bytes).into_iter()
+        .map(|unit| unit.unwrap_or(0xFFFD))
+        .collect::<Vec<u16>>();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::io::{NbtRead, NbtSize, NbtWrite, ReadLimits};
+
+    #[test]
+    fn mutf8_test() -> McResult<()> {
+        assert!(matches!(encode("plain text"), Cow::Borrowed(b"plain text")));
+        let text = "nul\0 and a balloon 🎈";
+        let encoded = encode(text);
+        assert_eq!(&encoded[3..5], &[0xC0, 0x80]);
+        // The balloon is a surrogate pair of two three byte sequences.
+        assert_eq!(&encoded[encoded.len() - 6..], &[0xED, 0xA0, 0xBC, 0xED, 0xBE, 0x88]);
+        assert_eq!(encoded.len(), encoded_len(text));
+        assert_eq!(decode(&encoded)?, text);
+        // Plain UTF-8 is accepted too.
+        assert_eq!(decode("🎈".as_bytes())?, "🎈");
+        // An unpaired surrogate.
+        assert!(decode(&[b'a', 0xED, 0xA0, 0xBC]).is_err());
+        assert_eq!(decode_lossy(&[b'a', 0xED, 0xA0, 0xBC, 0xFF, b'b']), "a\u{FFFD}\u{FFFD}b");
+
+        let mut buffer = Vec::new();
+        assert_eq!(text.nbt_write(&mut buffer)?, text.to_owned().nbt_size());
+        assert_eq!(String::nbt_read(&mut buffer.as_slice())?, text);
+        // Lossy decoding only applies to the read that asks for it.
+        let invalid = [&[0u8, 4][..], &[b'a', 0xED, 0xA0, 0xBC]].concat();
+        let lossy = ReadLimits { lossy_strings: true, ..ReadLimits::DEFAULT };
+        assert_eq!(String::nbt_read_limited(&mut invalid.as_slice(), &lossy)?, "a\u{FFFD}");
+        assert!(matches!(String::nbt_read(&mut invalid.as_slice()), Err(McError::InvalidModifiedUtf8)));
+        let long = "a".repeat(u16::MAX as usize + 1);
+        assert!(matches!(long.as_str().nbt_write(&mut Vec::new()), Err(McError::StringTooLong(len)) if len == long.len()));
+        Ok(())
+    }
+}
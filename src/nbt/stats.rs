@@ -0,0 +1,235 @@
+//! What a tag is made of: how many tags of each type it holds, how many bytes each type
+//! takes up when written, and which subtrees are the largest. This is meant to answer
+//! questions like "why is this chunk 8MB?".
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fmt::Display,
+};
+
+use super::{
+    Map,
+    io::NbtSize,
+    tag::{ListTag, Tag, TagID},
+    tagpath::{TagPath, TagPathPart},
+};
+
+/// The number of subtrees that [Tag::stats] keeps.
+pub const DEFAULT_TOP: usize = 10;
+
+/// The tags of one type in [TagStats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    /// The number of tags of this type, including the elements of lists.
+    pub count: usize,
+    /// The bytes that these tags take up when written, not counting the tags within them
+    /// (for compounds, this is the names and type IDs of their entries).
+    pub bytes: usize,
+}
+
+/// One of the largest subtrees found by [Tag::stats].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtree {
+    pub path: TagPath,
+    pub id: TagID,
+    /// The size of the subtree when written (see [NbtSize]).
+    pub size: usize,
+}
+
+/// The composition of a tag. See [Tag::stats].
+#[derive(Debug, Clone, Default)]
+pub struct TagStats {
+    pub types: BTreeMap<TagID, TypeStats>,
+    /// The size of the whole tag (the sum of the bytes of every type).
+    pub total_bytes: usize,
+    /// The largest subtrees (not counting the tag itself), from largest to smallest.
+    /// Subtrees inside of other large subtrees are included.
+    pub largest: Vec<Subtree>,
+}
+
+impl TagStats {
+    pub fn get(&self, id: TagID) -> TypeStats {
+        self.types.get(&id).copied().unwrap_or_default()
+    }
+}
+
+impl Display for TagStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "total: {} bytes", self.total_bytes)?;
+        for (id, stats) in self.types.iter() {
+            writeln!(f, "{:<10} {:>10} tags {:>12} bytes", id.title(), stats.count, stats.bytes)?;
+        }
+        for subtree in self.largest.iter() {
+            writeln!(f, "{:>12} bytes  {} ({})", subtree.size, subtree.path, subtree.id.title())?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks a tag, keeping the `top` largest subtrees in a min-heap.
+struct Collector {
+    stats: TagStats,
+    top: usize,
+    heap: BinaryHeap<Reverse<(usize, TagPath, TagID)>>,
+    path: Vec<TagPathPart>,
+}
+
+impl Collector {
+    fn add(&mut self, id: TagID, count: usize, bytes: usize) {
+        let stats = self.stats.types.entry(id).or_default();
+        stats.count += count;
+        stats.bytes += bytes;
+    }
+
+    fn offer(&mut self, id: TagID, size: usize) {
+        if self.top == 0 {
+            return;
+        }
+        if self.heap.len() == self.top {
+            match self.heap.peek() {
+                Some(Reverse((smallest, _, _))) if *smallest < size => { self.heap.pop(); }
+                _ => return,
+            }
+        }
+        self.heap.push(Reverse((size, TagPath(self.path.clone()), id)));
+    }
+
+    fn tag(&mut self, tag: &Tag) -> usize {
+        let size = match tag {
+            Tag::Compound(map) => self.map(map),
+            Tag::List(list) => self.list(list),
+            other => {
+                let size = other.nbt_size();
+                self.add(other.id(), 1, size);
+                size
+            }
+        };
+        if !self.path.is_empty() && matches!(tag, Tag::Compound(_) | Tag::List(_) | Tag::ByteArray(_) | Tag::IntArray(_) | Tag::LongArray(_) | Tag::String(_)) {
+            self.offer(tag.id(), size);
+        }
+        size
+    }
+
+    fn map(&mut self, map: &Map) -> usize {
+        // The end tag.
+        let mut own = 1;
+        let mut children = 0;
+        for (name, tag) in map.iter() {
+            own += 1 + name.nbt_size();
+            self.path.push(TagPathPart::AtKey(name.clone()));
+            children += self.tag(tag);
+            self.path.pop();
+        }
+        self.add(TagID::Compound, 1, own);
+        own + children
+    }
+
+    /// Counts the elements of a list that can't hold other tags.
+    fn elements<T: NbtSize>(&mut self, id: TagID, elements: &[T], offer: bool) -> usize {
+        let mut total = 0;
+        for (index, element) in elements.iter().enumerate() {
+            let size = element.nbt_size();
+            if offer {
+                self.path.push(TagPathPart::AtIndex(index as i64));
+                self.offer(id, size);
+                self.path.pop();
+            }
+            total += size;
+        }
+        self.add(id, elements.len(), total);
+        total
+    }
+
+    fn list(&mut self, list: &ListTag) -> usize {
+        // The element type and the length.
+        self.add(TagID::List, 1, 5);
+        let elements = match list {
+            ListTag::Empty => 0,
+            ListTag::Byte(values) => self.elements(TagID::Byte, values, false),
+            ListTag::Short(values) => self.elements(TagID::Short, values, false),
+            ListTag::Int(values) => self.elements(TagID::Int, values, false),
+            ListTag::Long(values) => self.elements(TagID::Long, values, false),
+            ListTag::Float(values) => self.elements(TagID::Float, values, false),
+            ListTag::Double(values) => self.elements(TagID::Double, values, false),
+            ListTag::ByteArray(values) => self.elements(TagID::ByteArray, values, true),
+            ListTag::String(values) => self.elements(TagID::String, values, true),
+            ListTag::IntArray(values) => self.elements(TagID::IntArray, values, true),
+            ListTag::LongArray(values) => self.elements(TagID::LongArray, values, true),
+            ListTag::List(lists) => lists.iter().enumerate().map(|(index, list)| {
+                self.path.push(TagPathPart::AtIndex(index as i64));
+                let size = self.list(list);
+                self.offer(TagID::List, size);
+                self.path.pop();
+                size
+            }).sum(),
+            ListTag::Compound(maps) => maps.iter().enumerate().map(|(index, map)| {
+                self.path.push(TagPathPart::AtIndex(index as i64));
+                let size = self.map(map);
+                self.offer(TagID::Compound, size);
+                self.path.pop();
+                size
+            }).sum(),
+        };
+        5 + elements
+    }
+}
+
+impl Tag {
+    /// Counts the tags of each type in this tag and the bytes that they take up when written,
+    /// and finds the [DEFAULT_TOP] largest subtrees.
+    pub fn stats(&self) -> TagStats {
+        self.stats_with_top(DEFAULT_TOP)
+    }
+
+    /// Like [Tag::stats], keeping the `top` largest subtrees.
+    pub fn stats_with_top(&self, top: usize) -> TagStats {
+        let mut collector = Collector {
+            stats: TagStats::default(),
+            top,
+            heap: BinaryHeap::new(),
+            path: Vec::new(),
+        };
+        let total_bytes = collector.tag(self);
+        let mut stats = collector.stats;
+        stats.total_bytes = total_bytes;
+        stats.largest = collector.heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((size, path, id))| Subtree { path, id, size })
+            .collect();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_test() {
+        let section = |y: i8| Map::from([
+            ("Y".to_owned(), Tag::Byte(y)),
+            ("data".to_owned(), Tag::LongArray(vec![0; 256 * (y as usize + 1)])),
+        ]);
+        let root = Tag::Compound(Map::from([
+            ("xPos".to_owned(), Tag::Int(0)),
+            ("Status".to_owned(), Tag::string("minecraft:full")),
+            ("sections".to_owned(), Tag::List(ListTag::Compound((0..3).map(section).collect()))),
+            ("Pos".to_owned(), Tag::List(ListTag::Double(vec![0.0; 3]))),
+        ]));
+        let stats = root.stats_with_top(3);
+        assert_eq!(stats.total_bytes, root.nbt_size());
+        assert_eq!(stats.types.values().map(|stats| stats.bytes).sum::<usize>(), root.nbt_size());
+        assert_eq!(stats.get(TagID::Compound).count, 4);
+        assert_eq!(stats.get(TagID::Double), TypeStats { count: 3, bytes: 24 });
+        assert_eq!(stats.get(TagID::LongArray), TypeStats { count: 3, bytes: (256 + 512 + 768) * 8 + 12 });
+        assert_eq!(stats.get(TagID::Short), TypeStats::default());
+        let largest = stats.largest.iter().map(|subtree| (subtree.path.to_string(), subtree.size)).collect::<Vec<_>>();
+        assert_eq!(largest, [
+            ("sections".to_owned(), 12344),
+            ("sections[2]".to_owned(), 6161),
+            ("sections[2].data".to_owned(), 6148),
+        ]);
+        assert!(stats.to_string().starts_with(&format!("total: {} bytes\n", root.nbt_size())));
+    }
+}
@@ -22,10 +22,16 @@
 ///
 /// tag_info_table!(read_table);
 /// ```
+/// Any tokens after a `;` are passed through to the macro ahead of the table, which
+/// lets the macro receive its own arguments (see [for_each_tag_type]):
+/// ```ignore
+/// tag_info_table!(read_table; @some_arm [extra arguments]);
+/// ```
 #[macro_export]
 macro_rules! tag_info_table {
-    ($macro:path) => {
-        $macro! {
+    (@with [$($macro:tt)+] [$($args:tt)*]) => {
+        $($macro)+! {
+$($args)*
 //ID	Title		Type						[Implementation							]
 0001	Byte		i8							[$crate::nbt::family::Primitive			]
 0002	Short		i16							[$crate::nbt::family::NonBytePrimitive	]
@@ -41,6 +47,77 @@ macro_rules! tag_info_table {
 0012	LongArray	std::vec::Vec::<i64>		[$crate::nbt::family::NonByte			]
         }
     };
+    ($($macro:ident)::+; $($args:tt)*) => {
+        $crate::tag_info_table!{@with [$($macro)::+] [$($args)*]}
+    };
+    ($macro:path) => {
+        $crate::tag_info_table!{@with [$macro] []}
+    };
+}
+
+/// Generates a `match` with one arm for each tag type in the tag info table.
+/// This is the table-dispatch macro used to avoid hand-writing the same 12 arms
+/// for every enum that mirrors [Tag](crate::nbt::tag::Tag).
+///
+/// The source enum and the target are given as enum names without the variant.
+/// The variant (the `$title` column of the table) is filled in for each arm.
+/// Any additional arms (such as `ListTag::Empty`) can be placed after the generated arms.
+///
+/// There are three forms for the arm body:
+/// ```ignore
+/// // Construct the same variant of another enum:
+/// //     Tag::Byte(value) => ValueRef::Byte(value),
+/// for_each_tag_type!(tag; Tag(value) => ValueRef(value))
+/// // Name the same variant of a fieldless enum:
+/// //     Tag::Byte(_) => TagID::Byte,
+/// for_each_tag_type!(tag; Tag(_) => TagID)
+/// // Invoke a macro with the variant name as the first token:
+/// //     ListTag::Byte(list) => my_macro!(Byte list),
+/// for_each_tag_type!(list; ListTag(list) => my_macro!(list), ListTag::Empty => 0)
+/// ```
+#[macro_export]
+macro_rules! for_each_tag_type {
+    (@arms [$value:expr] [$src:ident($bind:pat)] $template:tt [$($extra:tt)*] [$($arms:tt)*]
+        $id:literal $title:ident $type:path [$($impl:path)?] $($rest:tt)*) => {
+        $crate::for_each_tag_type!{
+            @arms [$value] [$src($bind)] $template [$($extra)*]
+            [$($arms)* $src::$title($bind) => $crate::for_each_tag_type!(@arm $title $template),]
+            $($rest)*
+        }
+    };
+    (@arms [$value:expr] $src:tt $template:tt [$($extra:tt)*] [$($arms:tt)*]) => {
+        match $value {
+            $($arms)*
+            $($extra)*
+        }
+    };
+    (@arm $title:ident [$callback:ident!($($args:tt)*)]) => {
+        $callback!($title $($args)*)
+    };
+    (@arm $title:ident [$dst:ident($body:expr)]) => {
+        $dst::$title($body)
+    };
+    (@arm $title:ident [$dst:ident]) => {
+        $dst::$title
+    };
+    ($value:expr; $src:ident($bind:pat) => $callback:ident!($($args:tt)*) $(, $extra_pat:pat => $extra:expr)* $(,)?) => {
+        $crate::tag_info_table!(
+            $crate::for_each_tag_type;
+            @arms [$value] [$src($bind)] [$callback!($($args)*)] [$($extra_pat => $extra,)*] []
+        )
+    };
+    ($value:expr; $src:ident($bind:pat) => $dst:ident($body:expr) $(, $extra_pat:pat => $extra:expr)* $(,)?) => {
+        $crate::tag_info_table!(
+            $crate::for_each_tag_type;
+            @arms [$value] [$src($bind)] [$dst($body)] [$($extra_pat => $extra,)*] []
+        )
+    };
+    ($value:expr; $src:ident($bind:pat) => $dst:ident $(, $extra_pat:pat => $extra:expr)* $(,)?) => {
+        $crate::tag_info_table!(
+            $crate::for_each_tag_type;
+            @arms [$value] [$src($bind)] [$dst] [$($extra_pat => $extra,)*] []
+        )
+    };
 }
 
 // Python: ['Byte', 'Short', 'Int', 'Long', 'Float', 'Double', 'ByteArray', 'String', 'List', 'Compound', 'IntArray', 'LongArray']
@@ -49,4 +126,5 @@ Byte,Short,Int,Long,Float,Double,ByteArray,String,List,Compound,IntArray,LongArr
 */
 
 pub use tag_info_table;
+pub use for_each_tag_type;
 
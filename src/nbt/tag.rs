@@ -4,6 +4,7 @@ use crate::nbt::{
     family::*,
     Map,
     tag_info_table,
+    for_each_tag_type,
 };
 use crate::{McError, McResult};
 
@@ -145,41 +146,14 @@ impl TagID {
 impl Tag {
     #[doc = "Returns the NBT type ID."]
     pub fn id(&self) -> TagID {
-        match self {
-            Tag::Byte(_) => TagID::Byte,
-            Tag::Short(_) => TagID::Short,
-            Tag::Int(_) => TagID::Int,
-            Tag::Long(_) => TagID::Long,
-            Tag::Float(_) => TagID::Float,
-            Tag::Double(_) => TagID::Double,
-            Tag::ByteArray(_) => TagID::ByteArray,
-            Tag::String(_) => TagID::String,
-            Tag::List(_) => TagID::List,
-            Tag::Compound(_) => TagID::Compound,
-            Tag::IntArray(_) => TagID::IntArray,
-            Tag::LongArray(_) => TagID::LongArray,
-        }
+        for_each_tag_type!(self; Tag(_) => TagID)
     }
 }
 
 impl ListTag {
     #[doc = "Returns the list type ID. Returns [TagID::Byte] for [ListTag::Empty]."]
     pub fn id(&self) -> TagID {
-        match self {
-            ListTag::Empty => TagID::Byte,
-            ListTag::Byte(_) => TagID::Byte,
-            ListTag::Short(_) => TagID::Short,
-            ListTag::Int(_) => TagID::Int,
-            ListTag::Long(_) => TagID::Long,
-            ListTag::Float(_) => TagID::Float,
-            ListTag::Double(_) => TagID::Double,
-            ListTag::ByteArray(_) => TagID::ByteArray,
-            ListTag::String(_) => TagID::String,
-            ListTag::List(_) => TagID::List,
-            ListTag::Compound(_) => TagID::Compound,
-            ListTag::IntArray(_) => TagID::IntArray,
-            ListTag::LongArray(_) => TagID::LongArray,
-        }
+        for_each_tag_type!(self; ListTag(_) => TagID, ListTag::Empty => TagID::Byte)
     }
 
     #[doc = "
@@ -187,21 +161,15 @@ impl ListTag {
     Returns `0` for [ListTag::Empty].
     "]
     pub fn len(&self) -> usize {
-        match self {
-            ListTag::Byte(list) => list.len(),
-            ListTag::Short(list) => list.len(),
-            ListTag::Int(list) => list.len(),
-            ListTag::Long(list) => list.len(),
-            ListTag::Float(list) => list.len(),
-            ListTag::Double(list) => list.len(),
-            ListTag::ByteArray(list) => list.len(),
-            ListTag::String(list) => list.len(),
-            ListTag::List(list) => list.len(),
-            ListTag::Compound(list) => list.len(),
-            ListTag::IntArray(list) => list.len(),
-            ListTag::LongArray(list) => list.len(),
-            ListTag::Empty => 0,
+        macro_rules! list_len {
+            ($title:ident $list:ident) => { $list.len() };
         }
+        for_each_tag_type!(self; ListTag(list) => list_len!(list), ListTag::Empty => 0)
+    }
+
+    #[doc = "Returns true if the list has no elements."]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
@@ -708,6 +676,12 @@ mod tests {
             "Fred".to_string(),
         ]);
         println!("{}", list);
+        let Tag::List(list) = list else { unreachable!() };
+        assert_eq!(list.len(), 5);
+        assert!(!list.is_empty());
+        assert!(ListTag::Empty.is_empty());
+        assert!(ListTag::Compound(Vec::new()).is_empty());
+        assert_eq!(ListTag::Int(vec![1, 2]).len(), 2);
     }
 
 }
@@ -80,12 +80,17 @@ pub enum TagPathError {
 pub struct TagPath(pub Vec<TagPathPart>);
 
 impl TagPath {
+    /// Parses a path such as `Items[0].id`.
+    /// Wildcards are only allowed in a [TagPathPattern], so `*` fails with
+    /// [TagPathError::InvalidToken].
     pub fn parse<S: AsRef<str>>(source: S) -> Result<Self, TagPathError> {
-        let tokens = TagPathToken::parse(source)
-            .map_err(TagPathError::TokenizeError)?;
-        let path = tag_path_parser().parse(tokens)
-            .map_err(TagPathError::ParseError)?;
-        Ok(Self(path))
+        TagPathPattern::parse(source)?.0.into_iter()
+            .map(|part| match part {
+                PatternPart::Exact(part) => Ok(part),
+                PatternPart::Any => Err(TagPathError::InvalidToken(TagPathToken::Wildcard)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
     }
 
     pub fn path(&self) -> &[TagPathPart] {
@@ -107,6 +112,60 @@ impl FromStr for TagPath {
     }
 }
 
+/// A part of a [TagPathPattern].
+#[derive(PartialEq, Eq,PartialOrd, Ord, Clone, Hash, Debug)]
+pub enum PatternPart {
+    /// Matches this key or index only.
+    Exact(TagPathPart),
+    /// `*`, which matches any single key or index.
+    Any,
+}
+
+/// A [TagPath] that may contain `*` in place of a key or index, such as `Items[*].id`.
+#[derive(PartialEq, Eq,PartialOrd, Ord, Clone, Hash, Debug)]
+pub struct TagPathPattern(pub Vec<PatternPart>);
+
+impl TagPathPattern {
+    pub fn parse<S: AsRef<str>>(source: S) -> Result<Self, TagPathError> {
+        let tokens = TagPathToken::parse(source)
+            .map_err(TagPathError::TokenizeError)?;
+        let pattern = tag_path_parser().parse(tokens)
+            .map_err(TagPathError::ParseError)?;
+        Ok(Self(pattern))
+    }
+
+    pub fn parts(&self) -> &[PatternPart] {
+        &self.0
+    }
+
+    /// Returns true if the end of `path` matches this pattern.
+    pub fn matches_end(&self, path: &[TagPathPart]) -> bool {
+        if self.0.len() > path.len() {
+            return false;
+        }
+        path[path.len() - self.0.len()..].iter()
+            .zip(self.0.iter())
+            .all(|(part, pattern)| match pattern {
+                PatternPart::Exact(pattern) => part == pattern,
+                PatternPart::Any => true,
+            })
+    }
+}
+
+impl FromStr for TagPathPattern {
+    type Err = TagPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TagPathPattern::parse(s)
+    }
+}
+
+impl From<TagPath> for TagPathPattern {
+    fn from(value: TagPath) -> Self {
+        Self(value.0.into_iter().map(PatternPart::Exact).collect())
+    }
+}
+
 #[derive(PartialEq, Eq,PartialOrd, Ord, Clone, Hash, Debug)]
 pub enum TagPathToken {
     Dot,
@@ -115,6 +174,8 @@ pub enum TagPathToken {
     Integer(String),
     Identifier(String),
     StringLiteral(String),
+    /// `*`, which is only valid in a [TagPathPattern].
+    Wildcard,
 }
 
 // I made it easier to make the lexer. Since there is a lot of boilerplate involved, I wrote
@@ -153,6 +214,7 @@ token_parse_functions!{
     open_bracket => { just('[').to(TagPathToken::OpenBracket).labelled("Open Bracket") }
     dot => { just('.').to(TagPathToken::Dot).labelled("Dot") }
     close_bracket => { just(']').to(TagPathToken::CloseBracket).labelled("Close Bracket") }
+    wildcard => { just('*').to(TagPathToken::Wildcard).labelled("Wildcard") }
     // If I want, I can add binary and hex literals.
     integer => {
         just::<char, _, Simple<char>>('-')
@@ -209,28 +271,31 @@ token_parse_functions!{
     }
 }
 
-/// Returns a parser that takes [TagPathToken] as input and returns a [Tag].
-fn tag_path_parser() -> impl Parser<TagPathToken, Vec<TagPathPart>, Error = Simple<TagPathToken>> {
+/// Returns a parser that takes [TagPathToken] as input and returns the parts of a [TagPathPattern].
+/// [TagPath::parse] rejects the patterns that contain [PatternPart::Any].
+fn tag_path_parser() -> impl Parser<TagPathToken, Vec<PatternPart>, Error = Simple<TagPathToken>> {
     let bracketed = just(TagPathToken::OpenBracket).ignore_then(
-        filter(|token| matches!(token, TagPathToken::Integer(_) | TagPathToken::StringLiteral(_) | TagPathToken::Identifier(_)))
+        filter(|token| matches!(token, TagPathToken::Integer(_) | TagPathToken::StringLiteral(_) | TagPathToken::Identifier(_) | TagPathToken::Wildcard))
             .try_map(|token, span| {
                 match token {
                     TagPathToken::Integer(digits) => {
                         digits.parse::<i64>()
-                            .map(TagPathPart::AtIndex)
+                            .map(|index| PatternPart::Exact(TagPathPart::AtIndex(index)))
                             .map_err(|_| Simple::custom(span, "Failed to parse i64."))
                     },
-                    TagPathToken::Identifier(ident) => Ok(TagPathPart::AtKey(ident)),
-                    TagPathToken::StringLiteral(ident) => Ok(TagPathPart::AtKey(ident)),
+                    TagPathToken::Identifier(ident) => Ok(PatternPart::Exact(TagPathPart::AtKey(ident))),
+                    TagPathToken::StringLiteral(ident) => Ok(PatternPart::Exact(TagPathPart::AtKey(ident))),
+                    TagPathToken::Wildcard => Ok(PatternPart::Any),
                     _ => Err(Simple::custom(span, "Impossible failure.")),
                 }
             })
     ).then_ignore(just(TagPathToken::CloseBracket));
 
-    let ident = filter(|token| matches!(token, TagPathToken::Identifier(_)))
+    let ident = filter(|token| matches!(token, TagPathToken::Identifier(_) | TagPathToken::Wildcard))
         .try_map(|token, span| {
             match token {
-                TagPathToken::Identifier(ident) => Ok(TagPathPart::AtKey(ident)),
+                TagPathToken::Identifier(ident) => Ok(PatternPart::Exact(TagPathPart::AtKey(ident))),
+                TagPathToken::Wildcard => Ok(PatternPart::Any),
                 _ => Err(Simple::custom(span, "Impossible failure.")),
             }
         });
@@ -250,11 +315,14 @@ fn tag_path_parser() -> impl Parser<TagPathToken, Vec<TagPathPart>, Error = Simp
 
 impl Display for TagPath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.iter().try_for_each(|part| {
+        self.0.iter().enumerate().try_for_each(|(i, part)| {
             match part {
                 TagPathPart::AtIndex(index) => write!(f, "[{index}]")?,
                 TagPathPart::AtKey(key) => {
                     if crate::nbt::format::is_identifier(key) {
+                        if i > 0 {
+                            write!(f, ".")?;
+                        }
                         write!(f, "{key}")?;
                     } else {
                         write!(f, "[\"")?;
@@ -303,4 +371,33 @@ impl Display for TagPath {
 pub trait GetChild {
     type ReturnType;
     fn get_child(&self) -> Self::ReturnType;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_test() {
+        let path = TagPath::parse("Items[0].id").unwrap();
+        assert_eq!(path.path(), &[TagPathPart::from("Items"), TagPathPart::from(0), TagPathPart::from("id")]);
+        // Wildcards are only parsed in patterns.
+        assert!(matches!(TagPath::parse("Items[*].id"), Err(TagPathError::InvalidToken(TagPathToken::Wildcard))));
+        assert!(matches!(TagPath::parse("*.id"), Err(TagPathError::InvalidToken(TagPathToken::Wildcard))));
+        assert!(TagPath::parse("a*b").is_err());
+        // A quoted `*` is an ordinary key.
+        assert_eq!(TagPath::parse("[\"*\"]").unwrap().path(), &[TagPathPart::from("*")]);
+
+        let pattern = TagPathPattern::parse("Items[*].id").unwrap();
+        assert_eq!(pattern.parts(), &[
+            PatternPart::Exact(TagPathPart::from("Items")),
+            PatternPart::Any,
+            PatternPart::Exact(TagPathPart::from("id")),
+        ]);
+        assert_eq!(TagPathPattern::parse("*.id").unwrap().parts()[0], PatternPart::Any);
+        assert!(pattern.matches_end(path.path()));
+        assert!(pattern.matches_end(TagPath::parse("block_entities[3].Items[4].id").unwrap().path()));
+        assert!(!pattern.matches_end(TagPath::parse("Items[0].Count").unwrap().path()));
+        assert!(!pattern.matches_end(TagPath::parse("[0].id").unwrap().path()));
+        assert!(TagPathPattern::from(path.clone()).matches_end(path.path()));
+    }
+}
@@ -3,11 +3,14 @@ This is to create a reference representation type of nbt::Tag and nbt::ListTag.
 This is meant to allow for accessing values directly.
 */
 use std::fmt::Display;
+use num_traits::ToPrimitive;
+use crate::{McError, McResult};
 use crate::nbt::tag::*;
 use crate::nbt::tagpath;
 use crate::nbt::tagtype::*;
+use crate::nbt::for_each_tag_type;
 
-use super::tagpath::TagPathPart;
+use super::tagpath::{TagPath, TagPathPart};
 
 
 
@@ -62,7 +65,7 @@ pub enum ValueRefMut<'a> {
 }
 
 macro_rules! get_child_in_array {
-    ($reftype:ident::$variant:ident($([$mut:ident])? $node:ident[$index:expr])) => {
+    ($variant:ident $reftype:ident($([$mut:ident])? $node:ident[$index:expr])) => {
         {
             let index = {
                 if $index >= 0 {
@@ -92,44 +95,22 @@ macro_rules! get_child_dry {
         match $at_val {
             &TagPathPart::AtIndex(index) => {
                 match $self_val {
-                    $self_type::List(node) => match node {
+                    $self_type::List(node) => for_each_tag_type!(node;
+                        ListTag(node) => get_child_in_array!($enum_type($([$mut])? node[index])),
                         ListTag::Empty => None,
-                        ListTag::Byte(node) => get_child_in_array!($enum_type::Byte($([$mut])? node[index])),
-                        ListTag::Short(node) => get_child_in_array!($enum_type::Short($([$mut])? node[index])),
-                        ListTag::Int(node) => get_child_in_array!($enum_type::Int($([$mut])? node[index])),
-                        ListTag::Long(node) => get_child_in_array!($enum_type::Long($([$mut])? node[index])),
-                        ListTag::Float(node) => get_child_in_array!($enum_type::Float($([$mut])? node[index])),
-                        ListTag::Double(node) => get_child_in_array!($enum_type::Double($([$mut])? node[index])),
-                        ListTag::ByteArray(node) => get_child_in_array!($enum_type::ByteArray($([$mut])? node[index])),
-                        ListTag::String(node) => get_child_in_array!($enum_type::String($([$mut])? node[index])),
-                        ListTag::List(node) => get_child_in_array!($enum_type::List($([$mut])? node[index])),
-                        ListTag::Compound(node) => get_child_in_array!($enum_type::Compound($([$mut])? node[index])),
-                        ListTag::IntArray(node) => get_child_in_array!($enum_type::IntArray($([$mut])? node[index])),
-                        ListTag::LongArray(node) => get_child_in_array!($enum_type::LongArray($([$mut])? node[index])),
-                    },
-                    $self_type::ByteArray(node) => get_child_in_array!($enum_type::Byte($([$mut])? node[index])),
-                    $self_type::IntArray(node) => get_child_in_array!($enum_type::Int($([$mut])? node[index])),
-                    $self_type::LongArray(node) =>  get_child_in_array!($enum_type::Long($([$mut])? node[index])),
+                    ),
+                    $self_type::ByteArray(node) => get_child_in_array!(Byte $enum_type($([$mut])? node[index])),
+                    $self_type::IntArray(node) => get_child_in_array!(Int $enum_type($([$mut])? node[index])),
+                    $self_type::LongArray(node) =>  get_child_in_array!(Long $enum_type($([$mut])? node[index])),
                     _ => None,
                 }
             },
             TagPathPart::AtKey(key) => {
                 match $self_val {
                     $self_type::Compound(map) if map.contains_key(key) => {
-                        Some(match get_child_dry!(@map_get;$($mut)?;map key) {
-                            Tag::Byte(child) => $enum_type::Byte(child),
-                            Tag::Short(child) => $enum_type::Short(child),
-                            Tag::Int(child) => $enum_type::Int(child),
-                            Tag::Long(child) => $enum_type::Long(child),
-                            Tag::Float(child) => $enum_type::Float(child),
-                            Tag::Double(child) => $enum_type::Double(child),
-                            Tag::ByteArray(child) => $enum_type::ByteArray(child),
-                            Tag::String(child) => $enum_type::String(child),
-                            Tag::List(child) => $enum_type::List(child),
-                            Tag::Compound(child) => $enum_type::Compound(child),
-                            Tag::IntArray(child) => $enum_type::IntArray(child),
-                            Tag::LongArray(child) => $enum_type::LongArray(child),
-                        })
+                        Some(for_each_tag_type!(get_child_dry!(@map_get;$($mut)?;map key);
+                            Tag(child) => $enum_type(child)
+                        ))
                     },
                     _ => None,
                 }
@@ -172,11 +153,50 @@ impl<'a> ValueRef<'a> {
         }
         walker
     }
+
+    /// Returns the value as an `i64` if this is an integer type (`Byte`, `Short`, `Int`, or `Long`).
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            ValueRef::Byte(&value) => Some(value as i64),
+            ValueRef::Short(&value) => Some(value as i64),
+            ValueRef::Int(&value) => Some(value as i64),
+            ValueRef::Long(&value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an `f64` if this is any numeric type.
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            ValueRef::Float(&value) => Some(value as f64),
+            ValueRef::Double(&value) => Some(value),
+            other => other.as_i64().map(|value| value as f64),
+        }
+    }
+
+    /// Returns the value as a `&str` if this is a `String`.
+    pub fn as_str(self) -> Option<&'a str> {
+        if let ValueRef::String(value) = self {
+            Some(value.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value as a `bool` if this is a `Byte`.
+    /// Minecraft stores booleans as a `Byte` that is `0` or `1`, but any non-zero value is treated as `true`.
+    pub fn as_bool(self) -> Option<bool> {
+        if let ValueRef::Byte(&value) = self {
+            Some(value != 0)
+        } else {
+            None
+        }
+    }
 }
 
 fn _set_child_at_index(node: ValueRefMut<'_>, index: i64, value: Tag) -> Result<(), ()> {
     macro_rules! set_child {
-        ($array:ident[$index:ident] = $variant:ident($value:ident)) => {
+        ($variant:ident $array:ident[$index:ident] = $value:ident) => {
             {
                 let Tag::$variant(value) = $value else { return Err(()) };
                 // If index is negative, we want to index from the end.
@@ -196,28 +216,26 @@ fn _set_child_at_index(node: ValueRefMut<'_>, index: i64, value: Tag) -> Result<
         };
     }
     match node {
-        ValueRefMut::ByteArray(array) if value.id() == TagID::Byte => set_child!(array[index] = Byte(value)),
-        ValueRefMut::IntArray(array) if value.id() == TagID::Int => set_child!(array[index] = Int(value)),
-        ValueRefMut::LongArray(array) if value.id() == TagID::Long => set_child!(array[index] = Long(value)),
-        ValueRefMut::List(list) => match list {
-            ListTag::Byte(array) if value.id() == TagID::Byte => set_child!(array[index] = Byte(value)),
-            ListTag::Short(array) if value.id() == TagID::Short => set_child!(array[index] = Short(value)),
-            ListTag::Int(array) if value.id() == TagID::Int => set_child!(array[index] = Int(value)),
-            ListTag::Long(array) if value.id() == TagID::Long => set_child!(array[index] = Long(value)),
-            ListTag::Float(array) if value.id() == TagID::Float => set_child!(array[index] = Float(value)),
-            ListTag::Double(array) if value.id() == TagID::Double => set_child!(array[index] = Double(value)),
-            ListTag::ByteArray(array) if value.id() == TagID::ByteArray => set_child!(array[index] = ByteArray(value)),
-            ListTag::String(array) if value.id() == TagID::String => set_child!(array[index] = String(value)),
-            ListTag::List(array) if value.id() == TagID::List => set_child!(array[index] = List(value)),
-            ListTag::Compound(array) if value.id() == TagID::Compound => set_child!(array[index] = Compound(value)),
-            ListTag::IntArray(array) if value.id() == TagID::IntArray => set_child!(array[index] = IntArray(value)),
-            ListTag::LongArray(array) if value.id() == TagID::LongArray => set_child!(array[index] = LongArray(value)),
-            _ => Err(()),
-        },
+        ValueRefMut::ByteArray(array) if value.id() == TagID::Byte => set_child!(Byte array[index] = value),
+        ValueRefMut::IntArray(array) if value.id() == TagID::Int => set_child!(Int array[index] = value),
+        ValueRefMut::LongArray(array) if value.id() == TagID::Long => set_child!(Long array[index] = value),
+        ValueRefMut::List(list) => for_each_tag_type!(list;
+            ListTag(array) => set_child!(array[index] = value),
+            ListTag::Empty => Err(()),
+        ),
         _ => Err(()),
     }
 }
 
+/// Resolves a possibly negative index into an index from the start of a sequence.
+fn resolve_index(len: usize, index: i64) -> i64 {
+    if index < 0 {
+        len as i64 + index
+    } else {
+        index
+    }
+}
+
 impl<'a> ValueRefMut<'a> {
     pub fn get_child(self, at: &TagPathPart) -> Option<ValueRef<'a>> {
         // get_child_dry!(self:ValueRefMut at => ValueRef)
@@ -246,6 +264,111 @@ impl<'a> ValueRefMut<'a> {
         walker
     }
 
+    /// Inserts a child into this node.
+    /// For a `Compound`, the key must not already exist.
+    /// For a `List` or array, the value is inserted at the index, shifting the following
+    /// elements. The index may be equal to the length in order to append.
+    /// Inserting into an empty list sets the list's type.
+    pub fn insert_child(self, at: &TagPathPart, value: Tag) -> McResult<()> {
+        macro_rules! insert_item {
+            ($variant:ident $items:ident[$index:ident] = $value:ident) => {
+                {
+                    let index = resolve_index($items.len(), $index);
+                    McError::range_check(index, 0..=$items.len() as i64)?;
+                    let found = $value.id();
+                    let Tag::$variant(value) = $value else {
+                        return Err(McError::TagTypeMismatch(TagID::$variant, found));
+                    };
+                    $items.insert(index as usize, value);
+                    Ok(())
+                }
+            };
+        }
+        match (self, at) {
+            (ValueRefMut::Compound(map), TagPathPart::AtKey(key)) => {
+                if map.contains_key(key) {
+                    return Err(McError::DuplicateKey(key.to_owned()));
+                }
+                map.insert(key.to_owned(), value);
+                Ok(())
+            },
+            (ValueRefMut::ByteArray(array), &TagPathPart::AtIndex(index)) => insert_item!(Byte array[index] = value),
+            (ValueRefMut::IntArray(array), &TagPathPart::AtIndex(index)) => insert_item!(Int array[index] = value),
+            (ValueRefMut::LongArray(array), &TagPathPart::AtIndex(index)) => insert_item!(Long array[index] = value),
+            (ValueRefMut::List(list), &TagPathPart::AtIndex(index)) => {
+                if let ListTag::Empty = list {
+                    McError::range_check(index, -1..=0)?;
+                    *list = for_each_tag_type!(value; Tag(value) => ListTag(vec![value]));
+                    return Ok(());
+                }
+                for_each_tag_type!(list;
+                    ListTag(items) => insert_item!(items[index] = value),
+                    ListTag::Empty => unreachable!("Empty lists are handled above."),
+                )
+            },
+            (_, at) => Err(McError::TagPathNotFound(TagPath(vec![at.clone()]))),
+        }
+    }
+
+    /// Removes a child from this node, returning the removed value.
+    /// Removing from a `List` or array shifts the following elements.
+    pub fn remove_child(self, at: &TagPathPart) -> McResult<Tag> {
+        macro_rules! remove_item {
+            ($variant:ident $items:ident[$index:ident]) => {
+                {
+                    let index = resolve_index($items.len(), $index);
+                    McError::range_check(index, 0..$items.len() as i64)?;
+                    Ok(Tag::$variant($items.remove(index as usize)))
+                }
+            };
+        }
+        match (self, at) {
+            (ValueRefMut::Compound(map), TagPathPart::AtKey(key)) => {
+                map.remove(key).ok_or_else(|| McError::NotFoundInCompound(key.to_owned()))
+            },
+            (ValueRefMut::ByteArray(array), &TagPathPart::AtIndex(index)) => remove_item!(Byte array[index]),
+            (ValueRefMut::IntArray(array), &TagPathPart::AtIndex(index)) => remove_item!(Int array[index]),
+            (ValueRefMut::LongArray(array), &TagPathPart::AtIndex(index)) => remove_item!(Long array[index]),
+            (ValueRefMut::List(list), &TagPathPart::AtIndex(index)) => for_each_tag_type!(list;
+                ListTag(items) => remove_item!(items[index]),
+                ListTag::Empty => Err(McError::OutOfRange),
+            ),
+            (_, at) => Err(McError::TagPathNotFound(TagPath(vec![at.clone()]))),
+        }
+    }
+
+    /// Sets a numeric value, converting `value` to the type of this node.
+    /// Returns [McError::OutOfRange] if `value` does not fit in the node's type,
+    /// or if the node is an integer type and `value` has a fractional part.
+    pub fn set_numeric<T: ToPrimitive>(&mut self, value: T) -> McResult<()> {
+        macro_rules! set_numeric {
+            (integer $node:ident = $value:ident.$convert:ident()) => {
+                {
+                    // Converting a float to an integer truncates, so 3.7 would silently become 3.
+                    if $value.to_f64().is_some_and(|value| value.fract() != 0.0) {
+                        return Err(McError::OutOfRange);
+                    }
+                    set_numeric!($node = $value.$convert())
+                }
+            };
+            ($node:ident = $value:ident.$convert:ident()) => {
+                {
+                    **$node = $value.$convert().ok_or(McError::OutOfRange)?;
+                    Ok(())
+                }
+            };
+        }
+        match self {
+            ValueRefMut::Byte(node) => set_numeric!(integer node = value.to_i8()),
+            ValueRefMut::Short(node) => set_numeric!(integer node = value.to_i16()),
+            ValueRefMut::Int(node) => set_numeric!(integer node = value.to_i32()),
+            ValueRefMut::Long(node) => set_numeric!(integer node = value.to_i64()),
+            ValueRefMut::Float(node) => set_numeric!(node = value.to_f32()),
+            ValueRefMut::Double(node) => set_numeric!(node = value.to_f64()),
+            _ => McError::custom("Value is not numeric."),
+        }
+    }
+
     pub fn set_child<T: Into<Tag>>(self, path: &[TagPathPart], value: T) -> Result<(),()> {
         /*
         First, take all path parts from path except final part.
@@ -256,7 +379,12 @@ impl<'a> ValueRefMut<'a> {
             return Err(())
         }
         let Some((last, first)) = path.split_last() else { return Err(()) };
-        let Some(node) = self.find_child_mut(first) else { return Err(()) };
+        let node = if first.is_empty() {
+            self
+        } else {
+            let Some(node) = self.find_child_mut(first) else { return Err(()) };
+            node
+        };
         let value: Tag = value.into();
         match last {
             &TagPathPart::AtIndex(index) => {
@@ -301,95 +429,86 @@ impl Tag {
 
 impl<'a> From<&'a mut Tag> for ValueRefMut<'a> {
     fn from(value: &'a mut Tag) -> Self {
-        match value {
-            Tag::Byte(val) => ValueRefMut::Byte(val),
-            Tag::Short(val) => ValueRefMut::Short(val),
-            Tag::Int(val) => ValueRefMut::Int(val),
-            Tag::Long(val) => ValueRefMut::Long(val),
-            Tag::Float(val) => ValueRefMut::Float(val),
-            Tag::Double(val) => ValueRefMut::Double(val),
-            Tag::ByteArray(val) => ValueRefMut::ByteArray(val),
-            Tag::String(val) => ValueRefMut::String(val),
-            Tag::List(val) => ValueRefMut::List(val),
-            Tag::Compound(val) => ValueRefMut::Compound(val),
-            Tag::IntArray(val) => ValueRefMut::IntArray(val),
-            Tag::LongArray(val) => ValueRefMut::LongArray(val),
-        }
+        for_each_tag_type!(value; Tag(val) => ValueRefMut(val))
     }
 }
 
 impl<'a> From<&'a Tag> for ValueRef<'a> {
     fn from(value: &'a Tag) -> Self {
-        match value {
-            Tag::Byte(val) => ValueRef::Byte(val),
-            Tag::Short(val) => ValueRef::Short(val),
-            Tag::Int(val) => ValueRef::Int(val),
-            Tag::Long(val) => ValueRef::Long(val),
-            Tag::Float(val) => ValueRef::Float(val),
-            Tag::Double(val) => ValueRef::Double(val),
-            Tag::ByteArray(val) => ValueRef::ByteArray(val),
-            Tag::String(val) => ValueRef::String(val),
-            Tag::List(val) => ValueRef::List(val),
-            Tag::Compound(val) => ValueRef::Compound(val),
-            Tag::IntArray(val) => ValueRef::IntArray(val),
-            Tag::LongArray(val) => ValueRef::LongArray(val),
-        }
+        for_each_tag_type!(value; Tag(val) => ValueRef(val))
     }
 }
 
 impl<'a> From<ValueRefMut<'a>> for ValueRef<'a> {
     fn from(value: ValueRefMut<'a>) -> Self {
-        match value {
-            ValueRefMut::Byte(value) => ValueRef::Byte(value),
-            ValueRefMut::Short(value) => ValueRef::Short(value),
-            ValueRefMut::Int(value) => ValueRef::Int(value),
-            ValueRefMut::Long(value) => ValueRef::Long(value),
-            ValueRefMut::Float(value) => ValueRef::Float(value),
-            ValueRefMut::Double(value) => ValueRef::Double(value),
-            ValueRefMut::ByteArray(value) => ValueRef::ByteArray(value),
-            ValueRefMut::String(value) => ValueRef::String(value),
-            ValueRefMut::List(value) => ValueRef::List(value),
-            ValueRefMut::Compound(value) => ValueRef::Compound(value),
-            ValueRefMut::IntArray(value) => ValueRef::IntArray(value),
-            ValueRefMut::LongArray(value) => ValueRef::LongArray(value),
-        }
+        for_each_tag_type!(value; ValueRefMut(value) => ValueRef(value))
     }
 }
 
 impl<'a> From<ValueRef<'a>> for Tag {
     fn from(value: ValueRef<'a>) -> Self {
-        match value {
-            ValueRef::Byte(val) => Tag::Byte(*val),
-            ValueRef::Short(val) => Tag::Short(*val),
-            ValueRef::Int(val) => Tag::Int(*val),
-            ValueRef::Long(val) => Tag::Long(*val),
-            ValueRef::Float(val) => Tag::Float(*val),
-            ValueRef::Double(val) => Tag::Double(*val),
-            ValueRef::ByteArray(val) => Tag::ByteArray(val.clone()),
-            ValueRef::String(val) => Tag::String(val.clone()),
-            ValueRef::List(val) => Tag::List(val.clone()),
-            ValueRef::Compound(val) => Tag::Compound(val.clone()),
-            ValueRef::IntArray(val) => Tag::IntArray(val.clone()),
-            ValueRef::LongArray(val) => Tag::LongArray(val.clone()),
-        }
+        for_each_tag_type!(value; ValueRef(val) => Tag(val.to_owned()))
     }
 }
 
 impl<'a> From<ValueRefMut<'a>> for Tag {
     fn from(value: ValueRefMut<'a>) -> Self {
-        match value {
-            ValueRefMut::Byte(val) => Tag::Byte(*val),
-            ValueRefMut::Short(val) => Tag::Short(*val),
-            ValueRefMut::Int(val) => Tag::Int(*val),
-            ValueRefMut::Long(val) => Tag::Long(*val),
-            ValueRefMut::Float(val) => Tag::Float(*val),
-            ValueRefMut::Double(val) => Tag::Double(*val),
-            ValueRefMut::ByteArray(val) => Tag::ByteArray(val.clone()),
-            ValueRefMut::String(val) => Tag::String(val.clone()),
-            ValueRefMut::List(val) => Tag::List(val.clone()),
-            ValueRefMut::Compound(val) => Tag::Compound(val.clone()),
-            ValueRefMut::IntArray(val) => Tag::IntArray(val.clone()),
-            ValueRefMut::LongArray(val) => Tag::LongArray(val.clone()),
-        }
+        for_each_tag_type!(value; ValueRefMut(val) => Tag(val.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_ref_test() {
+        assert_eq!(ValueRef::Byte(&-3).as_i64(), Some(-3));
+        assert_eq!(ValueRef::Short(&300).as_i64(), Some(300));
+        assert_eq!(ValueRef::Int(&-70000).as_i64(), Some(-70000));
+        assert_eq!(ValueRef::Long(&i64::MAX).as_i64(), Some(i64::MAX));
+        assert_eq!(ValueRef::Float(&1.5).as_i64(), None);
+        assert_eq!(ValueRef::Float(&1.5).as_f64(), Some(1.5));
+        assert_eq!(ValueRef::Double(&-2.25).as_f64(), Some(-2.25));
+        assert_eq!(ValueRef::Int(&7).as_f64(), Some(7.0));
+        let text = String::from("stone");
+        assert_eq!(ValueRef::String(&text).as_str(), Some("stone"));
+        assert_eq!(ValueRef::String(&text).as_f64(), None);
+        assert_eq!(ValueRef::Int(&1).as_str(), None);
+        assert_eq!(ValueRef::Byte(&0).as_bool(), Some(false));
+        assert_eq!(ValueRef::Byte(&2).as_bool(), Some(true));
+        assert_eq!(ValueRef::Int(&1).as_bool(), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn set_numeric_test() {
+        let mut byte = 0i8;
+        ValueRefMut::Byte(&mut byte).set_numeric(100).unwrap();
+        assert_eq!(byte, 100);
+        assert!(matches!(ValueRefMut::Byte(&mut byte).set_numeric(300), Err(McError::OutOfRange)));
+        assert_eq!(byte, 100);
+
+        let mut int = 0i32;
+        ValueRefMut::Int(&mut int).set_numeric(3.0).unwrap();
+        assert_eq!(int, 3);
+        assert!(matches!(ValueRefMut::Int(&mut int).set_numeric(3.7), Err(McError::OutOfRange)));
+        assert!(matches!(ValueRefMut::Int(&mut int).set_numeric(f64::NAN), Err(McError::OutOfRange)));
+        assert!(matches!(ValueRefMut::Int(&mut int).set_numeric(1e12), Err(McError::OutOfRange)));
+        assert_eq!(int, 3);
+
+        let mut long = 0i64;
+        ValueRefMut::Long(&mut long).set_numeric(u32::MAX).unwrap();
+        assert_eq!(long, u32::MAX as i64);
+        assert!(matches!(ValueRefMut::Long(&mut long).set_numeric(u64::MAX), Err(McError::OutOfRange)));
+
+        let mut float = 0f32;
+        ValueRefMut::Float(&mut float).set_numeric(3.7).unwrap();
+        assert_eq!(float, 3.7);
+        let mut double = 0f64;
+        ValueRefMut::Double(&mut double).set_numeric(-12).unwrap();
+        assert_eq!(double, -12.0);
+
+        let mut text = String::from("stone");
+        assert!(ValueRefMut::String(&mut text).set_numeric(1).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! The types and traits that most code using this crate needs, so that they can be
+//! imported with a single `use mcutil::prelude::*;`.
+//!
+//! [World] is [VirtualJavaWorld] with the default chunk codec. Like the modules that they
+//! come from, the NBT, region, and world types are only exported with the `nbt`, `region`,
+//! and `world` features.
+
+pub use crate::{
+    McError, McResult, McResultExt,
+    ioext::{Readable, Writable},
+};
+
+#[cfg(feature = "nbt")]
+pub use crate::nbt::{
+    Map,
+    io::{NbtRead, NbtSize, NbtWrite},
+    tag::{NamedTag, Tag},
+    tagpath::TagPath,
+};
+
+#[cfg(feature = "region")]
+pub use crate::world::io::region::{RegionCoord, RegionFile, RegionSector, Timestamp};
+
+#[cfg(feature = "world")]
+pub use crate::world::{
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::Chunk,
+    world::{VirtualJavaWorld, VirtualJavaWorld as World},
+};
+
+#[cfg(all(test, feature = "region"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prelude_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        let root = NamedTag::new(Map::from([("DataVersion".to_owned(), Tag::Int(3465))]));
+        region.write_data(RegionCoord::from((1, 2)), &root)?;
+        let read: NamedTag = region.read_data((1, 2))?;
+        assert!(matches!(read.tag(), Tag::Compound(map) if matches!(map.get("DataVersion"), Some(Tag::Int(3465)))));
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! Per-chunk exporters: 16x16 tiles of surface heights, height grayscale, and biome colors.
+//!
+//! Tiles are plain arrays indexed by `z * 16 + x` (local coordinates), so they can be
+//! composed into larger maps or written out by other tools. With the `image` feature,
+//! grayscale and color tiles can be converted to images.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::world::{
+    blockregistry::BlockRegistry,
+    chunk::Chunk,
+};
+
+/// An RGB color.
+pub type Rgb = [u8; 3];
+
+/// A 16x16 grid of values, one for each column of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile<T>(pub [T; 256]);
+
+impl<T: Copy> Tile<T> {
+    pub fn filled(value: T) -> Self {
+        Self([value; 256])
+    }
+
+    /// Gets the value at local column `(x, z)`.
+    pub fn get(&self, x: usize, z: usize) -> T {
+        self.0[z * 16 + x]
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, value: T) {
+        self.0[z * 16 + x] = value;
+    }
+
+    /// Maps every value of the tile.
+    pub fn map<U: Copy, F: FnMut(T) -> U>(&self, mut f: F) -> Tile<U> {
+        Tile(self.0.map(&mut f))
+    }
+}
+
+#[cfg(feature = "image")]
+impl Tile<u8> {
+    pub fn to_image(&self) -> image::GrayImage {
+        image::GrayImage::from_fn(16, 16, |x, z| image::Luma([self.get(x as usize, z as usize)]))
+    }
+}
+
+#[cfg(feature = "image")]
+impl Tile<Rgb> {
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_fn(16, 16, |x, z| image::Rgb(self.get(x as usize, z as usize)))
+    }
+}
+
+/// Blocks that don't count as the surface.
+const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// Finds the Y coordinate of the highest non-air block in each column of `chunk`,
+/// or `None` for columns that are entirely air. This is computed from the blocks
+/// rather than the heightmaps, which may be missing or stale.
+pub fn height_tile(registry: &BlockRegistry, chunk: &Chunk) -> Tile<Option<i64>> {
+    let mut air = HashMap::<u32, bool>::new();
+    let mut is_air = |id: u32| *air.entry(id).or_insert_with(|| {
+        registry.get(id).is_none_or(|state| AIR_BLOCKS.contains(&state.name()))
+    });
+    let mut sections = chunk.sections.sections.iter()
+        .filter(|section| section.blocks.is_some())
+        .collect::<Vec<_>>();
+    sections.sort_by_key(|section| std::cmp::Reverse(section.y));
+    let mut tile = Tile::filled(None);
+    for z in 0..16 {
+        for x in 0..16 {
+            let height = sections.iter().find_map(|section| {
+                (0..16i64).rev()
+                    .find(|&y| section.get_id(x, y, z).is_some_and(|id| !is_air(id)))
+                    .map(|y| section.y as i64 * 16 + y)
+            });
+            tile.set(x as usize, z as usize, height);
+        }
+    }
+    tile
+}
+
+/// Maps heights to grayscale, with the bottom of `range` black and the top white.
+/// Heights outside of `range` are clamped, and empty columns are black.
+pub fn height_grayscale(heights: &Tile<Option<i64>>, range: Range<i64>) -> Tile<u8> {
+    let span = (range.end - range.start).max(1) as f64;
+    heights.map(|height| match height {
+        Some(height) => ((height.clamp(range.start, range.end) - range.start) as f64 / span * 255.0).round() as u8,
+        None => 0,
+    })
+}
+
+/// The biome at the surface of each column (at the height from [height_tile],
+/// or the bottom of the chunk for empty columns).
+pub fn biome_tile<'a>(chunk: &'a Chunk, heights: &Tile<Option<i64>>) -> Tile<Option<&'a str>> {
+    let mut tile = Tile::filled(None);
+    for z in 0..16 {
+        for x in 0..16 {
+            let y = heights.get(x, z).unwrap_or(chunk.height_range().start);
+            let biome = chunk.sections.section_for_y(y)
+                .and_then(|section| section.biome_at(x as i64, y, z as i64));
+            tile.set(x, z, biome);
+        }
+    }
+    tile
+}
+
+/// Colors the surface biome of each column with [biome_color].
+pub fn biome_color_tile(chunk: &Chunk, heights: &Tile<Option<i64>>) -> Tile<Rgb> {
+    biome_tile(chunk, heights).map(|biome| biome.map_or(UNKNOWN_BIOME_COLOR, biome_color))
+}
+
+/// The color of biomes that aren't in [biome_color]'s table.
+pub const UNKNOWN_BIOME_COLOR: Rgb = [128, 128, 128];
+
+/// A map color for a biome, by its namespaced name. These are similar to the
+/// colors used by common map viewers. Unknown biomes are [UNKNOWN_BIOME_COLOR].
+pub fn biome_color(name: &str) -> Rgb {
+    match name.strip_prefix("minecraft:").unwrap_or(name) {
+        "ocean" => [0, 0, 112],
+        "deep_ocean" => [0, 0, 48],
+        "warm_ocean" => [0, 0, 172],
+        "lukewarm_ocean" => [0, 0, 144],
+        "deep_lukewarm_ocean" => [0, 0, 64],
+        "cold_ocean" => [32, 32, 112],
+        "deep_cold_ocean" => [32, 32, 56],
+        "frozen_ocean" => [112, 112, 214],
+        "deep_frozen_ocean" => [64, 64, 144],
+        "river" => [0, 0, 255],
+        "frozen_river" => [160, 160, 255],
+        "beach" => [250, 222, 85],
+        "snowy_beach" => [250, 240, 192],
+        "stony_shore" => [162, 162, 132],
+        "plains" => [141, 179, 96],
+        "sunflower_plains" => [181, 219, 136],
+        "snowy_plains" => [255, 255, 255],
+        "ice_spikes" => [180, 220, 220],
+        "desert" => [250, 148, 24],
+        "swamp" => [7, 249, 178],
+        "mangrove_swamp" => [44, 204, 142],
+        "forest" => [5, 102, 33],
+        "flower_forest" => [45, 142, 73],
+        "birch_forest" => [48, 116, 68],
+        "old_growth_birch_forest" => [88, 156, 108],
+        "dark_forest" => [64, 81, 26],
+        "taiga" => [11, 102, 89],
+        "snowy_taiga" => [49, 85, 74],
+        "old_growth_pine_taiga" => [89, 102, 81],
+        "old_growth_spruce_taiga" => [129, 142, 121],
+        "jungle" => [83, 123, 9],
+        "sparse_jungle" => [98, 139, 23],
+        "bamboo_jungle" => [118, 142, 20],
+        "savanna" => [189, 178, 95],
+        "savanna_plateau" => [167, 157, 100],
+        "windswept_savanna" => [229, 218, 135],
+        "badlands" => [217, 69, 21],
+        "wooded_badlands" => [176, 151, 101],
+        "eroded_badlands" => [255, 109, 61],
+        "windswept_hills" => [96, 96, 96],
+        "windswept_gravelly_hills" => [136, 136, 136],
+        "windswept_forest" => [80, 112, 80],
+        "meadow" => [131, 187, 109],
+        "cherry_grove" => [255, 183, 197],
+        "grove" => [71, 114, 108],
+        "snowy_slopes" => [196, 196, 196],
+        "frozen_peaks" => [160, 160, 220],
+        "jagged_peaks" => [220, 220, 200],
+        "stony_peaks" => [123, 143, 116],
+        "mushroom_fields" => [255, 0, 255],
+        "dripstone_caves" => [134, 96, 67],
+        "lush_caves" => [40, 150, 60],
+        "deep_dark" => [20, 40, 50],
+        "pale_garden" => [200, 205, 200],
+        "nether_wastes" => [191, 59, 59],
+        "soul_sand_valley" => [94, 56, 48],
+        "crimson_forest" => [221, 8, 8],
+        "warped_forest" => [73, 144, 123],
+        "basalt_deltas" => [64, 54, 54],
+        "the_end" => [128, 128, 255],
+        "small_end_islands" => [96, 96, 223],
+        "end_midlands" => [201, 201, 89],
+        "end_highlands" => [181, 181, 54],
+        "end_barrens" => [112, 112, 204],
+        "the_void" => [0, 0, 0],
+        _ => UNKNOWN_BIOME_COLOR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::{Map, tag::{ListTag, Tag}},
+        world::{blockstate::BlockState, chunk::tests::empty_chunk},
+    };
+
+    #[test]
+    fn chunk_tiles_test() -> crate::McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).fill(stone);
+        chunk.set_id((3, 40, 5), stone)?;
+        chunk.sections.get_or_insert(0).biomes = Some(Map::from([
+            ("palette".to_owned(), Tag::List(ListTag::String(vec!["minecraft:desert".to_owned()]))),
+        ]));
+        let heights = height_tile(&registry, &chunk);
+        assert_eq!(heights.get(0, 0), Some(15));
+        assert_eq!(heights.get(3, 5), Some(40));
+        let gray = height_grayscale(&heights, 0..40);
+        assert_eq!((gray.get(3, 5), gray.get(0, 0)), (255, 96));
+        let colors = biome_color_tile(&chunk, &heights);
+        assert_eq!(colors.get(0, 0), biome_color("minecraft:desert"));
+        // Section 2 (y 32..48) has no biomes.
+        assert_eq!(colors.get(3, 5), UNKNOWN_BIOME_COLOR);
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+//! Rendering worlds to 2D images.
+
+pub mod chunk;
+#[cfg(feature = "image")]
+pub mod tiles;
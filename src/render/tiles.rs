@@ -0,0 +1,302 @@
+//! Renders a dimension of a world to a zoomable XYZ tile pyramid, such as for Leaflet.
+//!
+//! Tiles are 256x256 PNGs written to `<output>/<zoom>/<x>/<y>.png`. At the deepest zoom
+//! ([TileOptions::zoom_levels]), each pixel is a block column and tile `(x, y)` covers blocks
+//! `x * 256..` along X and `y * 256..` along Z. Each level above halves the scale, with tile
+//! `(x, y)` made from tiles `(2x..=2x+1, 2y..=2y+1)` of the level below. Tile coordinates can
+//! be negative, so the tiles are meant for a map with a flat CRS (`L.CRS.Simple`).
+//!
+//! Rendering is incremental: the chunk timestamps of each region file are kept in
+//! `<output>/state/`, and later renders only redraw the chunks whose timestamps changed
+//! (and the tiles above them). Region files are rendered in parallel with the `rayon` feature.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+    math::coord::{Dimension, WorldCoord},
+    nbt::tag::NamedTag,
+    world::{
+        blockregistry::BlockRegistry,
+        chunk::decode_chunk,
+        io::region::{RegionFile, coord::RegionCoord, header::TimestampTable},
+        scan::{RegionKind, region_files},
+    },
+};
+
+use super::chunk::{biome_color_tile, height_grayscale, height_tile};
+
+/// The width and height of a tile in pixels.
+pub const TILE_SIZE: u32 = 256;
+/// The number of chunks across a tile at the deepest zoom.
+const TILE_CHUNKS: i64 = TILE_SIZE as i64 / 16;
+const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+/// How each block column is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileStyle {
+    /// The color of the biome at the surface (see [biome_color](super::chunk::biome_color)).
+    Biomes,
+    /// Surface height in grayscale, from the bottom of the chunk (black) to the top (white).
+    Heights,
+}
+
+/// Options for [render_tiles].
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+    pub style: TileStyle,
+    /// The number of zoomed out levels above the deepest zoom. Tiles are written for zoom
+    /// levels `0..=zoom_levels`, with `zoom_levels` being one pixel per block.
+    pub zoom_levels: u8,
+    /// Render every chunk, ignoring the saved timestamps.
+    pub force: bool,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            style: TileStyle::Biomes,
+            zoom_levels: 4,
+            force: false,
+        }
+    }
+}
+
+/// What [render_tiles] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileReport {
+    /// The number of chunks that were drawn (or cleared, if they were removed).
+    pub rendered_chunks: usize,
+    /// The number of tiles that were written, across all zoom levels.
+    pub written_tiles: usize,
+}
+
+/// The path of a tile in the pyramid.
+pub fn tile_path<P: AsRef<Path>>(output: P, zoom: u8, x: i64, y: i64) -> PathBuf {
+    output.as_ref()
+        .join(zoom.to_string())
+        .join(x.to_string())
+        .join(format!("{y}.png"))
+}
+
+fn state_path(output: &Path, region: WorldCoord) -> PathBuf {
+    output.join("state").join(format!("r.{}.{}.timestamps", region.x, region.z))
+}
+
+fn image_error(err: image::ImageError) -> McError {
+    McError::Custom(err.to_string())
+}
+
+/// Loads a tile, or a blank (transparent) one if it hasn't been rendered.
+fn load_tile(path: &Path) -> McResult<RgbaImage> {
+    if !path.is_file() {
+        return Ok(RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, CLEAR));
+    }
+    Ok(image::open(path).map_err(image_error)?.to_rgba8())
+}
+
+fn save_tile(path: &Path, tile: &RgbaImage) -> McResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    tile.save(path).map_err(image_error)
+}
+
+/// Maps `f` over `items`, in parallel with the `rayon` feature.
+#[cfg(feature = "rayon")]
+fn map_each<T: Sync, U: Send, F: Fn(&T) -> McResult<U> + Sync + Send>(items: &[T], f: F) -> McResult<Vec<U>> {
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_each<T, U, F: Fn(&T) -> McResult<U>>(items: &[T], f: F) -> McResult<Vec<U>> {
+    items.iter().map(f).collect()
+}
+
+/// Draws the chunk at `coord` of `region` into the 16x16 pixels of `tile` at `(px, py)`.
+fn draw_chunk(region: &mut RegionFile, registry: &mut BlockRegistry, coord: RegionCoord, style: TileStyle, tile: &mut RgbaImage, px: u32, py: u32) -> McResult<()> {
+    let root: NamedTag = region.read_data(coord)?;
+    let chunk = decode_chunk(registry, root.take_tag())?;
+    let heights = height_tile(registry, &chunk);
+    let colors = match style {
+        TileStyle::Biomes => biome_color_tile(&chunk, &heights).map(|[r, g, b]| Rgba([r, g, b, 255])),
+        TileStyle::Heights => {
+            let gray = height_grayscale(&heights, chunk.height_range());
+            let mut colors = gray.map(|value| Rgba([value, value, value, 255]));
+            (0..256).filter(|&index| heights.0[index].is_none())
+                .for_each(|index| colors.0[index] = CLEAR);
+            colors
+        }
+    };
+    for z in 0..16 {
+        for x in 0..16 {
+            tile.put_pixel(px + x, py + z, colors.get(x as usize, z as usize));
+        }
+    }
+    Ok(())
+}
+
+/// Redraws the changed chunks of one region file.
+/// Returns the deepest zoom tiles that were written and the number of chunks drawn.
+fn render_region(path: &Path, region_coord: WorldCoord, output: &Path, options: &TileOptions) -> McResult<(Vec<(i64, i64)>, usize)> {
+    let state = state_path(output, region_coord);
+    let previous = if state.is_file() && !options.force {
+        BufReader::new(File::open(&state)?).read_value::<TimestampTable>()?
+    } else {
+        TimestampTable::default()
+    };
+    let mut region = RegionFile::open(path)?;
+    // Absent chunks are kept as a zero timestamp, so that removed chunks count as changed.
+    let mut current = TimestampTable::default();
+    (0..1024usize).map(RegionCoord::from)
+        .filter(|&coord| !region.get_sector(coord).is_empty())
+        .for_each(|coord| current[coord] = region.get_timestamp(coord));
+    let changed = (0..1024usize).map(RegionCoord::from)
+        .filter(|&coord| previous[coord] != current[coord])
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    let chunk_x = |coord: RegionCoord| region_coord.x * 32 + coord.x() as i64;
+    let chunk_z = |coord: RegionCoord| region_coord.z * 32 + coord.z() as i64;
+    let tiles = changed.iter()
+        .map(|&coord| (chunk_x(coord).div_euclid(TILE_CHUNKS), chunk_z(coord).div_euclid(TILE_CHUNKS)))
+        .collect::<BTreeSet<_>>();
+    let mut registry = BlockRegistry::with_air();
+    for &(tile_x, tile_y) in &tiles {
+        let tile_path = tile_path(output, options.zoom_levels, tile_x, tile_y);
+        let mut tile = load_tile(&tile_path)?;
+        for &coord in changed.iter().filter(|&&coord| (chunk_x(coord).div_euclid(TILE_CHUNKS), chunk_z(coord).div_euclid(TILE_CHUNKS)) == (tile_x, tile_y)) {
+            let px = chunk_x(coord).rem_euclid(TILE_CHUNKS) as u32 * 16;
+            let py = chunk_z(coord).rem_euclid(TILE_CHUNKS) as u32 * 16;
+            if region.get_sector(coord).is_empty() {
+                (0..16).for_each(|z| (0..16).for_each(|x| tile.put_pixel(px + x, py + z, CLEAR)));
+            } else {
+                draw_chunk(&mut region, &mut registry, coord, options.style, &mut tile, px, py)?;
+            }
+        }
+        save_tile(&tile_path, &tile)?;
+    }
+    // The state is saved last, so an interrupted render is redone the next time.
+    if let Some(parent) = state.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    current.write_to(&mut File::create(&state)?)?;
+    Ok((tiles.into_iter().collect(), changed.len()))
+}
+
+/// Writes the tile at `(x, y)` of `zoom` from the four tiles below it, averaging each 2x2 block of pixels.
+fn render_parent(output: &Path, zoom: u8, x: i64, y: i64) -> McResult<()> {
+    let mut tile = RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, CLEAR);
+    let half = TILE_SIZE / 2;
+    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let child_path = tile_path(output, zoom + 1, x * 2 + dx, y * 2 + dy);
+        if !child_path.is_file() {
+            continue;
+        }
+        let child = load_tile(&child_path)?;
+        for py in 0..half {
+            for px in 0..half {
+                let pixels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(ox, oy)| child.get_pixel(px * 2 + ox, py * 2 + oy).0);
+                // Transparent pixels are left out of the average, so edges don't darken.
+                let opaque = pixels.iter().filter(|pixel| pixel[3] != 0).collect::<Vec<_>>();
+                if opaque.is_empty() {
+                    continue;
+                }
+                let mut sum = [0u32; 4];
+                opaque.iter().for_each(|pixel| (0..4).for_each(|i| sum[i] += pixel[i] as u32));
+                let count = opaque.len() as u32;
+                let color = Rgba(sum.map(|value| (value / count) as u8));
+                tile.put_pixel(dx as u32 * half + px, dy as u32 * half + py, color);
+            }
+        }
+    }
+    save_tile(&tile_path(output, zoom, x, y), &tile)
+}
+
+/// Renders the terrain of `dimension` in the world at `world_directory` to a tile pyramid in `output`.
+/// Only Anvil region files are rendered.
+pub fn render_tiles<P: AsRef<Path>, P2: AsRef<Path>>(world_directory: P, dimension: Dimension, output: P2, options: &TileOptions) -> McResult<TileReport> {
+    let output = output.as_ref();
+    let regions = region_files(world_directory, dimension, RegionKind::Terrain)?;
+    let rendered = map_each(&regions, |(region, path)| render_region(path, *region, output, options))?;
+    let mut report = TileReport::default();
+    let mut dirty = BTreeSet::new();
+    for (tiles, chunks) in rendered {
+        report.rendered_chunks += chunks;
+        report.written_tiles += tiles.len();
+        dirty.extend(tiles);
+    }
+    for zoom in (0..options.zoom_levels).rev() {
+        dirty = dirty.into_iter()
+            .map(|(x, y): (i64, i64)| (x.div_euclid(2), y.div_euclid(2)))
+            .collect();
+        let parents = dirty.iter().copied().collect::<Vec<_>>();
+        map_each(&parents, |&(x, y)| render_parent(output, zoom, x, y))?;
+        report.written_tiles += parents.len();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::tag::Tag,
+        world::{
+            blockstate::BlockState,
+            chunk::{encode_chunk, tests::empty_chunk},
+            scan::region_file_path,
+        },
+    };
+
+    #[test]
+    fn render_tiles_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        let output = dir.path().join("tiles");
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let path = region_file_path(&world, WorldCoord::new(-1, 0, Dimension::Overworld), RegionKind::Terrain)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let write = |x: i32, top: i64, timestamp: u32| -> McResult<()> {
+            let mut chunk = empty_chunk(x, -4, 0);
+            chunk.sections.get_or_insert(0).fill(stone);
+            chunk.set_id((x as i64 * 16, top, 0), stone)?;
+            let mut region = RegionFile::open_or_create(&path)?;
+            region.write_data_timestamped((x.rem_euclid(32) as u16, 0), &NamedTag::new(Tag::Compound(encode_chunk(&registry, &chunk))), timestamp)?;
+            Ok(())
+        };
+        write(-1, 20, 100)?;
+        write(-2, 20, 100)?;
+
+        let options = TileOptions { style: TileStyle::Heights, zoom_levels: 1, force: false };
+        let report = render_tiles(&world, Dimension::Overworld, &output, &options)?;
+        assert_eq!(report, TileReport { rendered_chunks: 2, written_tiles: 2 });
+        let tile = load_tile(&tile_path(&output, 1, -1, 0))?;
+        // Chunk -1 is the last column of chunks in tile -1.
+        assert_eq!(tile.get_pixel(240, 0)[3], 255);
+        assert_eq!(tile.get_pixel(0, 0)[3], 0);
+        let before = tile.get_pixel(240, 0)[0];
+        assert_eq!(load_tile(&tile_path(&output, 0, -1, 0))?.get_pixel(248, 0)[3], 255);
+
+        // Only the chunk that changed is rendered again.
+        assert_eq!(render_tiles(&world, Dimension::Overworld, &output, &options)?, TileReport::default());
+        write(-1, 60, 200)?;
+        let report = render_tiles(&world, Dimension::Overworld, &output, &options)?;
+        assert_eq!(report, TileReport { rendered_chunks: 1, written_tiles: 2 });
+        let tile = load_tile(&tile_path(&output, 1, -1, 0))?;
+        assert!(tile.get_pixel(240, 0)[0] > before);
+        assert_eq!(tile.get_pixel(224, 0)[0], before);
+        Ok(())
+    }
+}
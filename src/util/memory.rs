@@ -0,0 +1,105 @@
+//! Estimates of the memory held by values, for keeping long-running tools within a budget.
+//!
+//! The sizes are estimates: allocator overhead is ignored, and hash tables are counted as
+//! one entry per slot of their capacity plus a control byte.
+
+use crate::nbt::{
+    Map,
+    tag::{ListTag, Tag},
+};
+
+/// The approximate number of bytes that a value holds.
+pub trait MemorySize {
+    /// The bytes allocated on the heap by the value (and by the values that it owns).
+    fn heap_size(&self) -> usize;
+
+    /// The size of the value itself plus [MemorySize::heap_size].
+    fn memory_size(&self) -> usize where Self: Sized {
+        std::mem::size_of::<Self>() + self.heap_size()
+    }
+}
+
+macro_rules! inline_memory_size {
+    ($($type:ty)+) => {
+        $(
+            impl MemorySize for $type {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )+
+    };
+}
+
+inline_memory_size!(bool i8 u8 i16 u16 i32 u32 i64 u64 f32 f64 usize);
+
+impl MemorySize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: MemorySize> MemorySize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * std::mem::size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: MemorySize> MemorySize for Box<[T]> {
+    fn heap_size(&self) -> usize {
+        std::mem::size_of_val(&**self) + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: MemorySize> MemorySize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_size)
+    }
+}
+
+/// The heap size of a hash table with `capacity` slots of `entry` bytes, not counting what
+/// the entries own.
+pub fn table_size(capacity: usize, entry: usize) -> usize {
+    capacity * (entry + 1)
+}
+
+impl MemorySize for Map {
+    fn heap_size(&self) -> usize {
+        table_size(self.capacity(), std::mem::size_of::<(String, Tag)>())
+            + self.iter().map(|(key, tag)| key.heap_size() + tag.heap_size()).sum::<usize>()
+    }
+}
+
+impl MemorySize for Tag {
+    fn heap_size(&self) -> usize {
+        match self {
+            Tag::ByteArray(array) => array.heap_size(),
+            Tag::String(string) => string.heap_size(),
+            Tag::List(list) => list.heap_size(),
+            Tag::Compound(map) => map.heap_size(),
+            Tag::IntArray(array) => array.heap_size(),
+            Tag::LongArray(array) => array.heap_size(),
+            _ => 0,
+        }
+    }
+}
+
+impl MemorySize for ListTag {
+    fn heap_size(&self) -> usize {
+        match self {
+            ListTag::Empty => 0,
+            ListTag::Byte(list) => list.heap_size(),
+            ListTag::Short(list) => list.heap_size(),
+            ListTag::Int(list) => list.heap_size(),
+            ListTag::Long(list) => list.heap_size(),
+            ListTag::Float(list) => list.heap_size(),
+            ListTag::Double(list) => list.heap_size(),
+            ListTag::ByteArray(list) => list.heap_size(),
+            ListTag::String(list) => list.heap_size(),
+            ListTag::List(list) => list.heap_size(),
+            ListTag::Compound(list) => list.heap_size(),
+            ListTag::IntArray(list) => list.heap_size(),
+            ListTag::LongArray(list) => list.heap_size(),
+        }
+    }
+}
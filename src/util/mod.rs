@@ -1,2 +1,11 @@
+#[doc(hidden)]
 pub mod traits;
-pub mod coreext;
\ No newline at end of file
+#[doc(hidden)]
+pub mod coreext;
+#[cfg(feature = "nbt")]
+pub mod uuid;
+pub mod versions;
+#[cfg(feature = "nbt")]
+pub mod memory;
+#[cfg(feature = "zstd")]
+pub mod zstd;
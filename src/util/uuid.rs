@@ -0,0 +1,181 @@
+//! Conversions between the forms that Minecraft uses to store UUIDs.
+//! Since 1.16, UUIDs are stored as an `IntArray` of 4 integers (most significant first).
+//! Before that, they were stored as two `Long` tags named `<name>Most` and `<name>Least`.
+//! Some places also store them as hyphenated strings.
+//!
+//! UUIDs are represented as `u128` here. With the `uuid` feature, there are
+//! also conversions to and from [uuid::Uuid].
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        tag::Tag,
+        tagref::ValueRef,
+    },
+};
+
+/// Converts the `IntArray[4]` form to a UUID.
+pub fn from_int_array(ints: [i32; 4]) -> u128 {
+    ints.into_iter().fold(0u128, |uuid, int| (uuid << 32) | (int as u32 as u128))
+}
+
+/// Converts a UUID to the `IntArray[4]` form.
+pub fn to_int_array(uuid: u128) -> [i32; 4] {
+    [
+        (uuid >> 96) as u32 as i32,
+        (uuid >> 64) as u32 as i32,
+        (uuid >> 32) as u32 as i32,
+        uuid as u32 as i32,
+    ]
+}
+
+/// Converts the legacy Most/Least form to a UUID.
+pub fn from_most_least(most: i64, least: i64) -> u128 {
+    ((most as u64 as u128) << 64) | (least as u64 as u128)
+}
+
+/// Converts a UUID to the legacy (Most, Least) form.
+pub fn to_most_least(uuid: u128) -> (i64, i64) {
+    ((uuid >> 64) as u64 as i64, uuid as u64 as i64)
+}
+
+/// Formats a UUID as a hyphenated string (`xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`).
+pub fn to_string(uuid: u128) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        (uuid >> 96) as u32,
+        (uuid >> 80) as u16,
+        (uuid >> 64) as u16,
+        (uuid >> 48) as u16,
+        uuid & 0xFFFF_FFFF_FFFF,
+    )
+}
+
+/// Parses a UUID from a hyphenated or plain hexadecimal string.
+pub fn parse(text: &str) -> Option<u128> {
+    let digits: String = text.chars().filter(|&c| c != '-').collect();
+    if digits.len() != 32 {
+        return None;
+    }
+    u128::from_str_radix(&digits, 16).ok()
+}
+
+/// Reads a UUID from a value in any of the single-tag forms (`IntArray[4]` or a string).
+pub fn from_value(value: ValueRef<'_>) -> Option<u128> {
+    match value {
+        ValueRef::IntArray(ints) => {
+            let ints: [i32; 4] = ints.as_slice().try_into().ok()?;
+            Some(from_int_array(ints))
+        },
+        ValueRef::String(text) => parse(text),
+        _ => None,
+    }
+}
+
+/// Reads a UUID named `name` from a Compound.
+/// This checks for the `IntArray` (or string) form first, then the legacy `<name>Most`/`<name>Least` form.
+pub fn read_uuid(map: &Map, name: &str) -> Option<u128> {
+    if let Some(uuid) = map.get(name).and_then(|tag| from_value(ValueRef::from(tag))) {
+        return Some(uuid);
+    }
+    match (map.get(&format!("{name}Most")), map.get(&format!("{name}Least"))) {
+        (Some(Tag::Long(most)), Some(Tag::Long(least))) => Some(from_most_least(*most, *least)),
+        _ => None,
+    }
+}
+
+/// Reads a UUID named `name` from a Compound, returning an error if it is not found.
+pub fn read_uuid_err(map: &Map, name: &str) -> McResult<u128> {
+    read_uuid(map, name).ok_or_else(|| McError::NotFoundInCompound(name.to_owned()))
+}
+
+/// Writes a UUID named `name` to a Compound in the `IntArray` form,
+/// removing the legacy Most/Least tags if they are present.
+pub fn write_uuid(map: &mut Map, name: &str, uuid: u128) {
+    map.remove(&format!("{name}Most"));
+    map.remove(&format!("{name}Least"));
+    map.insert(name.to_owned(), Tag::IntArray(to_int_array(uuid).to_vec()));
+}
+
+/// Generates a random (version 4) UUID.
+pub fn random() -> u128 {
+    let uuid = rand::random::<u128>();
+    // Version 4, variant 1 (RFC 4122).
+    (uuid & !(0xF << 76) & !(0b11 << 62)) | (0x4 << 76) | (0b10 << 62)
+}
+
+/// Writes a UUID named `name` to a Compound in the legacy Most/Least form.
+pub fn write_uuid_legacy(map: &mut Map, name: &str, uuid: u128) {
+    let (most, least) = to_most_least(uuid);
+    map.insert(format!("{name}Most"), Tag::Long(most));
+    map.insert(format!("{name}Least"), Tag::Long(least));
+}
+
+/// Returns a matcher for [crate::world::find_replace] that matches a UUID stored
+/// as an `IntArray` or as a string.
+pub fn matcher(uuid: u128) -> impl Fn(ValueRef<'_>) -> bool {
+    move |value| from_value(value) == Some(uuid)
+}
+
+#[cfg(feature = "uuid")]
+mod uuid_impl {
+    use uuid::Uuid;
+
+    use crate::{
+        McError, McResult,
+        nbt::tag::{Tag, EncodeNbt, DecodeNbt},
+        nbt::tagref::ValueRef,
+    };
+
+    /// Converts the `IntArray[4]` form to a [Uuid].
+    pub fn uuid_from_int_array(ints: [i32; 4]) -> Uuid {
+        Uuid::from_u128(super::from_int_array(ints))
+    }
+
+    /// Converts a [Uuid] to the `IntArray[4]` form.
+    pub fn uuid_to_int_array(uuid: Uuid) -> [i32; 4] {
+        super::to_int_array(uuid.as_u128())
+    }
+
+    impl EncodeNbt for Uuid {
+        fn encode_nbt(self) -> Tag {
+            Tag::IntArray(uuid_to_int_array(self).to_vec())
+        }
+    }
+
+    impl DecodeNbt for Uuid {
+        fn decode_nbt(nbt: Tag) -> McResult<Self> {
+            super::from_value(ValueRef::from(&nbt))
+                .map(Uuid::from_u128)
+                .ok_or(McError::NbtDecodeError)
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+pub use uuid_impl::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_forms_test() {
+        let uuid = 0x069a79f4_44e9_4726_a5be_fca90e38aaf5u128;
+        let ints = to_int_array(uuid);
+        assert_eq!(ints, [110_787_060, 1_156_138_790, -1_514_210_135, 238_594_805]);
+        assert_eq!(from_int_array(ints), uuid);
+        let (most, least) = to_most_least(uuid);
+        assert_eq!(from_most_least(most, least), uuid);
+        assert_eq!(to_string(uuid), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(parse("069a79f4-44e9-4726-a5be-fca90e38aaf5"), Some(uuid));
+
+        let mut map = Map::new();
+        write_uuid_legacy(&mut map, "Owner", uuid);
+        assert_eq!(read_uuid(&map, "Owner"), Some(uuid));
+        write_uuid(&mut map, "Owner", uuid);
+        assert_eq!(map.len(), 1);
+        assert_eq!(read_uuid(&map, "Owner"), Some(uuid));
+    }
+}
@@ -0,0 +1,145 @@
+//! The DataVersions of Minecraft releases, and the format changes that they
+//! mark, for deciding how to read (or upgrade) data saved by a given version.
+//!
+//! Every chunk, `level.dat`, and most other files written since 1.9 have a
+//! `DataVersion`. Snapshots have their own DataVersions between those of the
+//! releases around them; [describe] reports those as "after" the previous release.
+
+use std::fmt::Display;
+
+use crate::math::packed::Packing;
+
+/// A Minecraft release and its DataVersion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GameVersion {
+    pub name: &'static str,
+    pub data_version: i32,
+}
+
+impl Display for GameVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.data_version)
+    }
+}
+
+macro_rules! versions {
+    ($($name:literal = $data_version:literal),+$(,)?) => {
+        /// Minecraft releases (Java Edition), sorted by DataVersion.
+        pub const VERSIONS: &[GameVersion] = &[
+            $(
+                GameVersion { name: $name, data_version: $data_version },
+            )+
+        ];
+    };
+}
+
+versions!(
+    "1.9" = 169, "1.9.1" = 175, "1.9.2" = 176, "1.9.3" = 183, "1.9.4" = 184,
+    "1.10" = 510, "1.10.1" = 511, "1.10.2" = 512,
+    "1.11" = 819, "1.11.1" = 921, "1.11.2" = 922,
+    "1.12" = 1139, "1.12.1" = 1241, "1.12.2" = 1343,
+    "1.13" = 1519, "1.13.1" = 1628, "1.13.2" = 1631,
+    "1.14" = 1952, "1.14.1" = 1957, "1.14.2" = 1963, "1.14.3" = 1968, "1.14.4" = 1976,
+    "1.15" = 2225, "1.15.1" = 2227, "1.15.2" = 2230,
+    "1.16" = 2566, "1.16.1" = 2567, "1.16.2" = 2578, "1.16.3" = 2580, "1.16.4" = 2584, "1.16.5" = 2586,
+    "1.17" = 2724, "1.17.1" = 2730,
+    "1.18" = 2860, "1.18.1" = 2865, "1.18.2" = 2975,
+    "1.19" = 3105, "1.19.1" = 3117, "1.19.2" = 3120, "1.19.3" = 3218, "1.19.4" = 3337,
+    "1.20" = 3463, "1.20.1" = 3465, "1.20.2" = 3578, "1.20.3" = 3698, "1.20.4" = 3700,
+    "1.20.5" = 3837, "1.20.6" = 3839,
+    "1.21" = 3953, "1.21.1" = 3955, "1.21.2" = 4080, "1.21.3" = 4082, "1.21.4" = 4189, "1.21.5" = 4325,
+);
+
+/// Gets the release with exactly this DataVersion.
+pub fn release(data_version: i32) -> Option<&'static GameVersion> {
+    VERSIONS.binary_search_by_key(&data_version, |version| version.data_version)
+        .ok()
+        .map(|index| &VERSIONS[index])
+}
+
+/// Gets the latest release with a DataVersion less than or equal to `data_version`.
+pub fn latest_release_at(data_version: i32) -> Option<&'static GameVersion> {
+    let index = VERSIONS.partition_point(|version| version.data_version <= data_version);
+    index.checked_sub(1).map(|index| &VERSIONS[index])
+}
+
+/// Describes a DataVersion for people: `"1.20.1"` for a release, `"after 1.20.1 (3470)"`
+/// for a snapshot (or a release newer than this table), and `"before 1.9 (100)"` for
+/// DataVersions older than the table.
+pub fn describe(data_version: i32) -> String {
+    match latest_release_at(data_version) {
+        Some(version) if version.data_version == data_version => version.name.to_owned(),
+        Some(version) => format!("after {} ({data_version})", version.name),
+        None => format!("before 1.9 ({data_version})"),
+    }
+}
+
+/// Format changes that depend on the DataVersion that data was saved with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VersionFeatures {
+    /// Blocks are stored as block states in palettes rather than numeric ids (1.13+).
+    pub flattened: bool,
+    /// Packed arrays don't span longs (1.16+). See [Packing].
+    pub aligned_packing: bool,
+    /// Entities are stored in `entities/` region files rather than in terrain chunks (1.17+).
+    pub entities_files: bool,
+    /// Chunks are stored without the `Level` compound, with sections from `yPos`, and the
+    /// overworld is 384 blocks tall, from Y -64 (1.18+).
+    pub extended_height: bool,
+    /// Items store data components instead of a `tag` compound (1.20.5+).
+    pub data_components: bool,
+}
+
+impl VersionFeatures {
+    /// The first DataVersion (17w47a) that uses block state palettes.
+    pub const FLATTENED_DATA_VERSION: i32 = 1451;
+    /// The first DataVersion (20w17a) that uses [Packing::Aligned].
+    pub const ALIGNED_PACKING_DATA_VERSION: i32 = Packing::ALIGNED_DATA_VERSION;
+    /// The first DataVersion (20w45a) that stores entities in their own region files.
+    pub const ENTITIES_FILES_DATA_VERSION: i32 = 2681;
+    /// The first DataVersion (21w43a) that uses the 1.18 chunk format.
+    pub const EXTENDED_HEIGHT_DATA_VERSION: i32 = 2844;
+    /// The first DataVersion (1.20.5) that uses item data components.
+    pub const DATA_COMPONENTS_DATA_VERSION: i32 = 3837;
+
+    pub fn for_data_version(data_version: i32) -> Self {
+        Self {
+            flattened: data_version >= Self::FLATTENED_DATA_VERSION,
+            aligned_packing: data_version >= Self::ALIGNED_PACKING_DATA_VERSION,
+            entities_files: data_version >= Self::ENTITIES_FILES_DATA_VERSION,
+            extended_height: data_version >= Self::EXTENDED_HEIGHT_DATA_VERSION,
+            data_components: data_version >= Self::DATA_COMPONENTS_DATA_VERSION,
+        }
+    }
+
+    /// The packing used for block states, biomes, and heightmaps.
+    pub fn packing(self) -> Packing {
+        if self.aligned_packing {
+            Packing::Aligned
+        } else {
+            Packing::Spanning
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_test() {
+        assert!(VERSIONS.windows(2).all(|pair| pair[0].data_version < pair[1].data_version));
+        assert_eq!(release(3465).map(|version| version.name), Some("1.20.1"));
+        assert_eq!(release(3466), None);
+        assert_eq!(describe(2975), "1.18.2");
+        assert_eq!(describe(3000), "after 1.18.2 (3000)");
+        assert_eq!(describe(100), "before 1.9 (100)");
+
+        let features = VersionFeatures::for_data_version(2586);
+        assert!(features.flattened && features.aligned_packing);
+        assert!(!features.entities_files && !features.extended_height);
+        assert_eq!(VersionFeatures::for_data_version(1343), VersionFeatures::default());
+        assert_eq!(VersionFeatures::for_data_version(2230).packing(), Packing::for_data_version(2230));
+        assert!(VersionFeatures::for_data_version(3953).data_components);
+    }
+}
@@ -0,0 +1,146 @@
+//! Zstandard helpers used by the `zstd` feature.
+//!
+//! This covers the custom `minecraft:zstd` region compression scheme,
+//! compressed chunk dumps, and dictionary training for chunk payloads.
+//! Chunk payloads are small and very similar to each other, so a trained
+//! dictionary can improve the compression ratio considerably.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+    world::io::region::{
+        RegionFormat,
+        coord::RegionCoord,
+        timestamp::Timestamp,
+    },
+};
+
+/// The name of the zstd custom compression scheme (scheme ID 127).
+pub const CUSTOM_SCHEME_NAME: &str = "minecraft:zstd";
+/// The compression level used when none is given.
+pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+/// Magic number at the start of a chunk dump.
+const DUMP_MAGIC: u32 = 0x4D43_4344;
+
+/// Compresses `data` into a single zstd frame.
+pub fn compress(data: &[u8], level: i32) -> McResult<Vec<u8>> {
+    Ok(zstd::encode_all(data, level)?)
+}
+
+/// Decompresses a zstd stream.
+pub fn decompress(data: &[u8]) -> McResult<Vec<u8>> {
+    Ok(zstd::decode_all(data)?)
+}
+
+/// Trains a dictionary from sample payloads (such as uncompressed chunk NBT).
+/// `max_size` is the maximum size of the dictionary in bytes; 112KiB is a good default.
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> McResult<Vec<u8>> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// Trains a dictionary from every chunk stored in `region`.
+pub fn train_dictionary_from_region(region: &mut dyn RegionFormat, max_size: usize) -> McResult<Vec<u8>> {
+    let samples = region.chunks().into_iter()
+        .map(|coord| region.read_chunk_bytes(coord))
+        .collect::<McResult<Vec<_>>>()?;
+    train_dictionary(&samples, max_size)
+}
+
+/// Compresses `data` using a trained dictionary.
+pub fn compress_with_dictionary(data: &[u8], level: i32, dictionary: &[u8]) -> McResult<Vec<u8>> {
+    let mut encoder = zstd::stream::write::Encoder::with_dictionary(Vec::new(), level, dictionary)?;
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Decompresses data that was compressed with [compress_with_dictionary].
+pub fn decompress_with_dictionary(data: &[u8], dictionary: &[u8]) -> McResult<Vec<u8>> {
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(data, dictionary)?;
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result)?;
+    Ok(result)
+}
+
+/// Writes every chunk of `region` to a zstd-compressed dump at `path`.
+/// Returns the number of chunks written.
+pub fn dump_region<P: AsRef<Path>>(region: &mut dyn RegionFormat, path: P, level: i32) -> McResult<usize> {
+    let chunks = region.chunks();
+    let mut encoder = zstd::stream::write::Encoder::new(BufWriter::new(File::create(path)?), level)?;
+    encoder.write_value(DUMP_MAGIC)?;
+    encoder.write_value(chunks.len() as u32)?;
+    for &coord in chunks.iter() {
+        let data = region.read_chunk_bytes(coord)?;
+        encoder.write_value(coord.index() as u16)?;
+        encoder.write_value(region.chunk_timestamp(coord).unwrap_or_default())?;
+        encoder.write_value(data.len() as u32)?;
+        encoder.write_all(&data)?;
+    }
+    encoder.finish()?.flush()?;
+    Ok(chunks.len())
+}
+
+/// Reads a chunk dump written by [dump_region].
+pub fn read_dump<P: AsRef<Path>>(path: P) -> McResult<Vec<(RegionCoord, Timestamp, Vec<u8>)>> {
+    let mut decoder = zstd::stream::read::Decoder::new(BufReader::new(File::open(path)?))?;
+    let magic: u32 = decoder.read_value()?;
+    if magic != DUMP_MAGIC {
+        return McError::custom("Not a chunk dump.");
+    }
+    let count: u32 = decoder.read_value()?;
+    (0..count).map(|_| {
+        let index: u16 = decoder.read_value()?;
+        let timestamp: Timestamp = decoder.read_value()?;
+        let length: u32 = decoder.read_value()?;
+        let mut data = vec![0u8; length as usize];
+        decoder.read_exact(&mut data)?;
+        Ok((RegionCoord::from(index), timestamp, data))
+    }).collect()
+}
+
+/// Writes every chunk from the dump at `path` into `region` and flushes it.
+/// Returns the number of chunks restored.
+pub fn restore_dump<P: AsRef<Path>>(path: P, region: &mut dyn RegionFormat) -> McResult<usize> {
+    let chunks = read_dump(path)?;
+    for (coord, timestamp, data) in chunks.iter() {
+        region.write_chunk_bytes(*coord, data, *timestamp)?;
+    }
+    region.flush()?;
+    Ok(chunks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionary_test() -> McResult<()> {
+        let samples = (0..256)
+            .map(|i| format!("{{xPos:{i},zPos:{},Status:\"minecraft:full\",sections:[]}}", i * 7).into_bytes())
+            .collect::<Vec<_>>();
+        let dictionary = train_dictionary(&samples, 4096)?;
+        let compressed = compress_with_dictionary(&samples[3], DEFAULT_LEVEL, &dictionary)?;
+        assert_eq!(decompress_with_dictionary(&compressed, &dictionary)?, samples[3]);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_scheme_test() -> McResult<()> {
+        use crate::{nbt::tag::{NamedTag, Tag}, world::io::region::{RegionFile, RegionFormatExt}};
+        let dir = tempfile::tempdir()?;
+        let mut regionfile = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        let chunk = NamedTag::new(Tag::String("zstd".to_owned()));
+        regionfile.write_data_zstd((1, 2), &chunk, Timestamp::utc_now(), DEFAULT_LEVEL)?;
+        let read: NamedTag = regionfile.read_chunk(RegionCoord::new(1, 2))?;
+        assert!(matches!(read.tag, Tag::String(value) if value == "zstd"));
+        let dump = dir.path().join("chunks.dump");
+        assert_eq!(dump_region(&mut regionfile, &dump, DEFAULT_LEVEL)?, 1);
+        assert_eq!(read_dump(&dump)?.len(), 1);
+        Ok(())
+    }
+}
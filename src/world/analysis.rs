@@ -0,0 +1,428 @@
+//! Analysis passes that summarize a world as plain data, for plotting or reports:
+//! how often blocks occur at each Y level ([block_frequency]), where structures were
+//! generated ([list_structures]), which structure of a kind is nearest to a
+//! position ([nearest_structure]), and which chunks are close to the size limit of
+//! a region file ([find_oversized_chunks]).
+//!
+//! The passes read the root tags of chunks directly (section palettes and structure
+//! starts) rather than decoding whole chunks.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+};
+
+use glam::i64vec3;
+
+use crate::{
+    McResult,
+    math::{
+        bounds::Bounds3,
+        coord::{BlockCoord, WorldCoord},
+        packed::{get_packed, palette_bits},
+    },
+    nbt::{
+        Map,
+        io::NbtSize,
+        tag::{ListTag, NamedTag, Tag},
+        tagpath::{TagPath, TagPathPart},
+    },
+};
+
+use super::{
+    io::region::{RegionCoord, RegionFile},
+    scan::{for_each_chunk, region_files, RegionKind},
+    selection::WorldSelection,
+};
+
+/// Block counts by block ID, then by Y level. Only levels where the block occurs are present.
+pub type BlockFrequency = BTreeMap<String, BTreeMap<i64, u64>>;
+
+/// Counts the blocks that `filter` accepts (given the block ID, such as
+/// `minecraft:diamond_ore`) at each Y level of the selected chunks.
+pub fn block_frequency<P, F>(world_directory: P, selection: &WorldSelection, filter: F) -> McResult<BlockFrequency>
+where
+P: AsRef<Path>,
+F: Fn(&str) -> bool {
+    let mut frequency = BlockFrequency::new();
+    for_each_chunk(world_directory, selection, RegionKind::Terrain, |_, root| {
+        let Tag::Compound(root) = root.tag() else { return Ok(false) };
+        let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else { return Ok(false) };
+        sections.iter().for_each(|section| count_section(section, &filter, &mut frequency));
+        Ok(false)
+    })?;
+    Ok(frequency)
+}
+
+fn count_section<F: Fn(&str) -> bool>(section: &Map, filter: &F, frequency: &mut BlockFrequency) {
+    let Some(Tag::Byte(section_y)) = section.get("Y") else { return };
+    let Some(Tag::Compound(block_states)) = section.get("block_states") else { return };
+    let Some(Tag::List(ListTag::Compound(palette))) = block_states.get("palette") else { return };
+    // The palette indices that are counted, and the ID of each.
+    let counted = palette.iter().map(|state| match state.get("Name") {
+        Some(Tag::String(name)) if filter(name) => Some(name.as_str()),
+        _ => None,
+    }).collect::<Vec<_>>();
+    if counted.iter().all(Option::is_none) {
+        return;
+    }
+    let bottom = *section_y as i64 * 16;
+    let data = match block_states.get("data") {
+        Some(Tag::LongArray(data)) if palette.len() > 1 => Some(data),
+        _ => None,
+    };
+    let Some(data) = data else {
+        // The whole section is the first entry of the palette.
+        if let Some(name) = counted[0] {
+            let levels = frequency.entry(name.to_owned()).or_default();
+            (bottom..bottom + 16).for_each(|y| *levels.entry(y).or_default() += 256);
+        }
+        return;
+    };
+    let bits = palette_bits(palette.len(), 4);
+    (0..16).for_each(|local_y| {
+        let mut counts = vec![0u64; palette.len()];
+        (0..256).for_each(|column| {
+            let index = get_packed(data, bits, local_y * 256 + column) as usize;
+            if let Some(count) = counts.get_mut(index) {
+                *count += 1;
+            }
+        });
+        counts.into_iter().zip(counted.iter()).for_each(|(count, name)| {
+            if let (Some(name), 1..) = (name, count) {
+                *frequency.entry((*name).to_owned()).or_default().entry(bottom + local_y as i64).or_default() += count;
+            }
+        });
+    });
+}
+
+/// A structure that was generated in the world, as recorded by the chunk it started in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructureStart {
+    /// The structure ID, such as `minecraft:village_plains`.
+    pub id: String,
+    /// The chunk that the structure starts in.
+    pub chunk: WorldCoord,
+    /// The box around every piece of the structure.
+    pub bounds: Bounds3,
+}
+
+impl StructureStart {
+    /// The block at the horizontal center of the structure, at the bottom of its bounds.
+    pub fn center(&self) -> BlockCoord {
+        BlockCoord::new(
+            (self.bounds.min.x + self.bounds.max.x).div_euclid(2),
+            self.bounds.min.y,
+            (self.bounds.min.z + self.bounds.max.z).div_euclid(2),
+            self.chunk.dimension,
+        )
+    }
+}
+
+/// Reads a bounding box stored as `[min x, min y, min z, max x, max y, max z]`.
+fn read_bounds(tag: Option<&Tag>) -> Option<Bounds3> {
+    let Some(Tag::IntArray(bounds)) = tag else { return None };
+    let [min_x, min_y, min_z, max_x, max_y, max_z] = bounds.as_slice() else { return None };
+    Some(Bounds3::new(
+        i64vec3(*min_x as i64, *min_y as i64, *min_z as i64),
+        i64vec3(*max_x as i64, *max_y as i64, *max_z as i64),
+    ))
+}
+
+fn union(a: Bounds3, b: Bounds3) -> Bounds3 {
+    Bounds3 {
+        min: a.min.min(b.min),
+        max: a.max.max(b.max),
+    }
+}
+
+/// Reads a structure start. Starts with the ID `INVALID` (which the game writes for
+/// structures that failed to generate) and starts without any pieces are skipped.
+fn read_start(chunk: WorldCoord, key: &str, start: &Map) -> Option<StructureStart> {
+    let id = match start.get("id") {
+        Some(Tag::String(id)) => id.as_str(),
+        _ => key,
+    };
+    if id == "INVALID" {
+        return None;
+    }
+    let bounds = match start.get("Children") {
+        Some(Tag::List(ListTag::Compound(children))) => children.iter()
+            .filter_map(|child| read_bounds(child.get("BB")))
+            .reduce(union),
+        _ => None,
+    };
+    // Starts from before 1.18 also store the box of the whole structure.
+    let bounds = bounds.or_else(|| read_bounds(start.get("BB")))?;
+    Some(StructureStart { id: id.to_owned(), chunk, bounds })
+}
+
+/// Lists the structures that start in the selected chunks, in chunk order.
+pub fn list_structures<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection) -> McResult<Vec<StructureStart>> {
+    let mut structures = Vec::new();
+    for_each_chunk(world_directory, selection, RegionKind::Terrain, |chunk, root| {
+        let Tag::Compound(root) = root.tag() else { return Ok(false) };
+        // `structures` since 1.18, `Level.Structures` before.
+        let structures_tag = match root.get("Level") {
+            Some(Tag::Compound(level)) => level.get("Structures"),
+            _ => root.get("structures"),
+        };
+        let Some(Tag::Compound(structures_tag)) = structures_tag else { return Ok(false) };
+        let Some(Tag::Compound(starts)) = structures_tag.get("starts").or_else(|| structures_tag.get("Starts")) else { return Ok(false) };
+        let mut keys = starts.keys().collect::<Vec<_>>();
+        keys.sort();
+        structures.extend(keys.into_iter().filter_map(|key| match &starts[key] {
+            Tag::Compound(start) => read_start(chunk, key, start),
+            _ => None,
+        }));
+        Ok(false)
+    })?;
+    Ok(structures)
+}
+
+/// Finds the structure with the ID `id` whose center is horizontally nearest to `pos`,
+/// among `structures` in the same dimension as `pos`. Returns the structure and its
+/// horizontal distance from `pos`.
+pub fn nearest_structure<'a>(structures: &'a [StructureStart], id: &str, pos: BlockCoord) -> Option<(&'a StructureStart, f64)> {
+    structures.iter()
+        .filter(|structure| structure.id == id && structure.chunk.dimension == pos.dimension)
+        .map(|structure| {
+            let center = structure.center();
+            let (dx, dz) = ((center.x - pos.x) as f64, (center.z - pos.z) as f64);
+            (structure, (dx * dx + dz * dz).sqrt())
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// The default threshold of [find_oversized_chunks], which is three quarters of the
+/// 255 sectors that a chunk can take up in a region file.
+pub const OVERSIZED_CHUNK_SECTORS: u64 = 192;
+
+/// A chunk that takes up nearly as many sectors as a region file allows. Chunks that
+/// grow past the limit can't be saved (or, before 1.15, are corrupted when they are saved).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OversizedChunk {
+    pub chunk: WorldCoord,
+    /// The number of 4KiB sectors that the chunk takes up in its region file.
+    pub sectors: u64,
+    /// The uncompressed size of the chunk's NBT in bytes.
+    pub size: usize,
+    /// The tags that are responsible for the size, with their sizes in bytes. Each tag is
+    /// the largest child of the one before it, starting from the largest child of the root,
+    /// so the last one is the most specific (such as one chest's `Items`).
+    pub culprits: Vec<(TagPath, usize)>,
+}
+
+/// A tag that may have children.
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Compound(&'a Map),
+    List(&'a ListTag),
+    Leaf,
+}
+
+impl<'a> From<&'a Tag> for Node<'a> {
+    fn from(tag: &'a Tag) -> Self {
+        match tag {
+            Tag::Compound(map) => Node::Compound(map),
+            Tag::List(list) => Node::List(list),
+            _ => Node::Leaf,
+        }
+    }
+}
+
+impl<'a> Node<'a> {
+    /// Finds the largest child, with its size.
+    fn largest_child(self) -> Option<(TagPathPart, usize, Node<'a>)> {
+        match self {
+            Node::Compound(map) => map.iter()
+                .map(|(key, tag)| (TagPathPart::AtKey(key.clone()), tag.nbt_size(), Node::from(tag)))
+                .max_by_key(|(_, size, _)| *size),
+            Node::List(ListTag::Compound(maps)) => maps.iter().enumerate()
+                .map(|(index, map)| (TagPathPart::AtIndex(index as i64), map.nbt_size(), Node::Compound(map)))
+                .max_by_key(|(_, size, _)| *size),
+            Node::List(ListTag::List(lists)) => lists.iter().enumerate()
+                .map(|(index, list)| (TagPathPart::AtIndex(index as i64), list.nbt_size(), Node::List(list)))
+                .max_by_key(|(_, size, _)| *size),
+            _ => None,
+        }
+    }
+}
+
+/// Follows the largest child down from `root` for as long as it makes up at least half
+/// of its parent, which finds the subtree that is responsible for most of the size.
+fn find_culprits(root: &Tag) -> Vec<(TagPath, usize)> {
+    let mut culprits = Vec::new();
+    let mut path = TagPath(Vec::new());
+    let mut node = Node::from(root);
+    let mut parent_size = root.nbt_size();
+    while let Some((part, size, child)) = node.largest_child() {
+        // Past the first level, stop once the size is spread out between children.
+        if !culprits.is_empty() && size * 2 < parent_size {
+            break;
+        }
+        path = path.join(part);
+        culprits.push((path.clone(), size));
+        node = child;
+        parent_size = size;
+    }
+    culprits
+}
+
+/// Finds the selected chunks that take up at least `min_sectors` sectors in their region
+/// file (see [OVERSIZED_CHUNK_SECTORS]), largest first. Region files are opened read-only,
+/// and only the chunks that are flagged are decoded.
+pub fn find_oversized_chunks<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection, kind: RegionKind, min_sectors: u64) -> McResult<Vec<OversizedChunk>> {
+    let mut oversized = Vec::new();
+    for (region, path) in region_files(world_directory, selection.dimension, kind)? {
+        if !selection.contains_region(region) {
+            continue;
+        }
+        let reader = RegionFile::open_read_only(&path)?;
+        for (index, sector) in reader.header().sectors.iter().enumerate() {
+            let coord = RegionCoord::from(index);
+            let chunk = WorldCoord::new(region.x * 32 + coord.x() as i64, region.z * 32 + coord.z() as i64, region.dimension);
+            if sector.is_empty() || sector.sector_count() < min_sectors || !selection.contains(chunk) {
+                continue;
+            }
+            let root: NamedTag = reader.read_data(coord)?;
+            oversized.push(OversizedChunk {
+                chunk,
+                sectors: sector.sector_count(),
+                size: root.tag().nbt_size(),
+                culprits: find_culprits(root.tag()),
+            });
+        }
+    }
+    oversized.sort_by(|a, b| b.sectors.cmp(&a.sectors).then(a.chunk.cmp(&b.chunk)));
+    Ok(oversized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::{coord::Dimension, packed::PackedArray},
+        nbt::tag::NamedTag,
+        world::{
+            io::region::RegionFile,
+            scan::region_file_path,
+        },
+    };
+
+    fn state(name: &str) -> Map {
+        Map::from([("Name".to_owned(), Tag::string(name))])
+    }
+
+    fn start(id: &str, bounds: [i32; 6]) -> Tag {
+        Tag::Compound(Map::from([
+            ("id".to_owned(), Tag::string(id)),
+            ("Children".to_owned(), Tag::List(ListTag::Compound(vec![
+                Map::from([("BB".to_owned(), Tag::IntArray(bounds.to_vec()))]),
+            ]))),
+        ]))
+    }
+
+    #[test]
+    fn analysis_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = region_file_path(dir.path(), WorldCoord::overworld(0, 0), RegionKind::Terrain)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        // Section -1: diamond ore at (0, -16, 0) and (1, -16, 0) and one at y = -9.
+        let mut data = PackedArray::new(4, 4096);
+        [0, 1, 7 * 256].into_iter().for_each(|index| {
+            data.set(index, 1);
+        });
+        let sections = vec![
+            Map::from([
+                ("Y".to_owned(), Tag::Byte(-1)),
+                ("block_states".to_owned(), Tag::Compound(Map::from([
+                    ("palette".to_owned(), Tag::List(ListTag::Compound(vec![state("minecraft:stone"), state("minecraft:diamond_ore")]))),
+                    ("data".to_owned(), Tag::LongArray(data.into_longs())),
+                ]))),
+            ]),
+            Map::from([
+                ("Y".to_owned(), Tag::Byte(0)),
+                ("block_states".to_owned(), Tag::Compound(Map::from([
+                    ("palette".to_owned(), Tag::List(ListTag::Compound(vec![state("minecraft:stone")]))),
+                ]))),
+            ]),
+        ];
+        let chunk = |sections: Vec<Map>, starts: Map| NamedTag::new(Tag::Compound(Map::from([
+            ("sections".to_owned(), Tag::List(ListTag::Compound(sections))),
+            ("structures".to_owned(), Tag::Compound(Map::from([
+                ("starts".to_owned(), Tag::Compound(starts)),
+            ]))),
+        ])));
+        {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &chunk(sections, Map::from([
+                ("minecraft:village_plains".to_owned(), start("minecraft:village_plains", [0, 60, 0, 40, 80, 40])),
+                ("minecraft:mineshaft".to_owned(), Tag::Compound(Map::from([("id".to_owned(), Tag::string("INVALID"))]))),
+            ])))?;
+            region.write_data((8, 8), &chunk(Vec::new(), Map::from([
+                ("minecraft:village_plains".to_owned(), start("minecraft:village_plains", [128, 60, 128, 150, 70, 150])),
+            ])))?;
+        }
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        let frequency = block_frequency(dir.path(), &selection, |name| name.ends_with("_ore"))?;
+        assert_eq!(frequency.len(), 1);
+        assert_eq!(frequency["minecraft:diamond_ore"], BTreeMap::from([(-16, 2), (-9, 1)]));
+        let stone = block_frequency(dir.path(), &selection, |name| name == "minecraft:stone")?;
+        assert_eq!(stone["minecraft:stone"][&-16], 254);
+        assert_eq!(stone["minecraft:stone"][&15], 256);
+
+        let structures = list_structures(dir.path(), &selection)?;
+        assert_eq!(structures.len(), 2);
+        assert_eq!(structures[0].bounds, Bounds3::new(i64vec3(0, 60, 0), i64vec3(40, 80, 40)));
+        let (nearest, distance) = nearest_structure(&structures, "minecraft:village_plains", BlockCoord::overworld(130, 64, 130)).unwrap();
+        assert_eq!(nearest.chunk, WorldCoord::overworld(8, 8));
+        assert!((distance - (9.0f64 * 9.0 * 2.0).sqrt()).abs() < 1e-9);
+        assert!(nearest_structure(&structures, "minecraft:village_plains", BlockCoord::nether(0, 64, 0)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_chunk_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = region_file_path(dir.path(), WorldCoord::overworld(-1, 0), RegionKind::Terrain)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        // Data that doesn't compress well, so that the chunk takes up many sectors.
+        let mut seed = 0x2545F4914F6CDD1Du64;
+        let noise = (0..40_000).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as i64
+        }).collect::<Vec<_>>();
+        let chest = |items: Vec<Map>| Map::from([
+            ("id".to_owned(), Tag::string("minecraft:chest")),
+            ("Items".to_owned(), Tag::List(if items.is_empty() { ListTag::Empty } else { ListTag::Compound(items) })),
+        ]);
+        let root = NamedTag::new(Tag::Compound(Map::from([
+            ("block_entities".to_owned(), Tag::List(ListTag::Compound(vec![
+                chest(Vec::new()),
+                chest(vec![
+                    Map::from([("id".to_owned(), Tag::string("minecraft:stone"))]),
+                    Map::from([("id".to_owned(), Tag::string("minecraft:book")), ("data".to_owned(), Tag::LongArray(noise))]),
+                ]),
+            ]))),
+            ("sections".to_owned(), Tag::List(ListTag::Empty)),
+        ])));
+        {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((31, 2), &root)?;
+            region.write_data((0, 0), &NamedTag::new(Tag::Compound(Map::new())))?;
+        }
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        let oversized = find_oversized_chunks(dir.path(), &selection, RegionKind::Terrain, 16)?;
+        assert_eq!(oversized.len(), 1);
+        assert_eq!(oversized[0].chunk, WorldCoord::overworld(-1, 2));
+        assert!(oversized[0].sectors >= 16);
+        let culprits = oversized[0].culprits.iter().map(|(path, _)| path.to_string()).collect::<Vec<_>>();
+        assert_eq!(culprits.first().map(String::as_str), Some("block_entities"));
+        assert_eq!(culprits.last().map(String::as_str), Some("block_entities[1].Items[1].data"));
+        assert!(find_oversized_chunks(dir.path(), &selection, RegionKind::Terrain, OVERSIZED_CHUNK_SECTORS)?.is_empty());
+        Ok(())
+    }
+}
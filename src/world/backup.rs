@@ -0,0 +1,562 @@
+//! World backups.
+//!
+//! [backup] copies the parts of a world that matter for restoring it (level.dat,
+//! region files, player data, and data packs) into a directory or an archive,
+//! and [restore] copies them back.
+//!
+//! Directory backups can be incremental: when [BackupOptions::previous] points
+//! to an earlier directory backup of the same world, the chunks of each region
+//! file are compared with [diff_region] and region files without changes are
+//! hard linked to the previous backup instead of being copied.
+//!
+//! [export_changed_since] goes further for pipelines that only need what changed (map
+//! renderers, incremental publishing): it copies only the chunks whose timestamps are
+//! newer than a given time, into a sparse world that holds nothing else but level.dat.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+    math::coord::Dimension,
+};
+
+use super::{
+    io::region::{RegionCoord, RegionFile, RegionSector, Timestamp, header::RegionHeader},
+    scan::{region_files, RegionKind},
+    session::{SessionLock, ensure_world_idle},
+};
+#[cfg(feature = "tar")]
+use super::io::region::CompressionScheme;
+
+/// The output of [backup].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupFormat {
+    /// A copy of the world's directory structure.
+    #[default]
+    Directory,
+    /// A `.tar` archive.
+    #[cfg(feature = "tar")]
+    Tar,
+    /// A `.zip` archive.
+    #[cfg(feature = "zip")]
+    Zip,
+}
+
+/// Options for [backup].
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    pub format: BackupFormat,
+    /// An earlier [BackupFormat::Directory] backup of the same world. Region files that
+    /// haven't changed since are hard linked from it rather than copied.
+    /// Only supported with [BackupFormat::Directory].
+    pub previous: Option<PathBuf>,
+    /// Include `playerdata/`.
+    pub playerdata: bool,
+    /// Include `datapacks/`.
+    pub datapacks: bool,
+    /// Compresses a [BackupFormat::Tar] archive, such as with GZip for a `.tar.gz`.
+    /// Zip archives are already compressed, so this is only supported with `Tar`.
+    pub codec: Option<Arc<dyn Codec>>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            format: BackupFormat::Directory,
+            previous: None,
+            playerdata: true,
+            datapacks: true,
+            codec: None,
+        }
+    }
+}
+
+/// What [backup] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupReport {
+    /// The number of files that were copied (or added to the archive).
+    pub copied: usize,
+    /// The number of region files that were unchanged and linked to the previous backup.
+    pub linked: usize,
+    /// The number of chunks that changed since the previous backup, across all copied region files.
+    pub changed_chunks: usize,
+}
+
+fn is_tar(format: BackupFormat) -> bool {
+    #[cfg(feature = "tar")]
+    if format == BackupFormat::Tar {
+        return true;
+    }
+    let _ = format;
+    false
+}
+
+/// Finds the chunks that differ between two region files. A chunk is considered
+/// changed if it was added, removed, or moved to other sectors, if its timestamp
+/// differs, or if its stored (compressed) data differs. Moving, swapping, and
+/// rewriting chunks with an explicit timestamp all keep the timestamp, and can
+/// leave the header as it was, so the data of every chunk is compared too.
+pub fn diff_region<P: AsRef<Path>, P2: AsRef<Path>>(old: P, new: P2) -> McResult<Vec<RegionCoord>> {
+    let open = |path: &Path| -> McResult<(std::io::BufReader<File>, RegionHeader)> {
+        let mut reader = std::io::BufReader::new(File::open(path)?);
+        let header = reader.read_value()?;
+        Ok((reader, header))
+    };
+    let (mut old_reader, old) = open(old.as_ref())?;
+    let (mut new_reader, new) = open(new.as_ref())?;
+    let mut changed = Vec::new();
+    for coord in (0..1024usize).map(RegionCoord::from) {
+        let index = coord.index();
+        let (old_sector, new_sector) = (old.sectors[index], new.sectors[index]);
+        if old_sector != new_sector
+        || old.timestamps[index] != new.timestamps[index]
+        || (!new_sector.is_empty() && stored_chunk(&mut old_reader, old_sector)? != stored_chunk(&mut new_reader, new_sector)?) {
+            changed.push(coord);
+        }
+    }
+    Ok(changed)
+}
+
+/// Reads the stored bytes of a chunk (its length, compression scheme, and compressed data).
+fn stored_chunk<R: Read + Seek>(reader: &mut R, sector: RegionSector) -> McResult<Vec<u8>> {
+    reader.seek(SeekFrom::Start(sector.offset()))?;
+    let length: u32 = reader.read_value()?;
+    // A corrupt length is clamped to the sectors, which are all that the chunk can hold.
+    let length = (length as u64).min(sector.size().saturating_sub(4));
+    let mut data = length.to_be_bytes().to_vec();
+    data.resize(8 + length as usize, 0);
+    reader.read_exact(&mut data[8..])?;
+    Ok(data)
+}
+
+/// Lists the files to back up, as paths relative to the world directory.
+/// Region files are listed separately because they take part in incremental backups.
+fn collect_files(world_directory: &Path, options: &BackupOptions) -> McResult<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    if world_directory.join("level.dat").is_file() {
+        files.push(PathBuf::from("level.dat"));
+    }
+    let folders = [("playerdata", options.playerdata), ("datapacks", options.datapacks)];
+    for (folder, _) in folders.iter().filter(|(_, include)| *include) {
+        collect_recursive(world_directory, Path::new(folder), &mut files)?;
+    }
+    let mut regions = Vec::new();
+    for dimension in [Dimension::Overworld, Dimension::Nether, Dimension::TheEnd] {
+        for kind in RegionKind::ALL {
+            for (_, path) in region_files(world_directory, dimension, kind)? {
+                if let Ok(relative) = path.strip_prefix(world_directory) {
+                    regions.push(relative.to_owned());
+                }
+            }
+        }
+    }
+    Ok((files, regions))
+}
+
+fn collect_recursive(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> McResult<()> {
+    let directory = root.join(relative);
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    let mut entries = std::fs::read_dir(directory)?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_recursive(root, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn copy_file(source: &Path, destination: &Path) -> McResult<()> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(source, destination)?;
+    Ok(())
+}
+
+/// Backs up the world at `world_directory` to `destination`.
+/// For [BackupFormat::Directory], `destination` is the directory to create;
+/// for archives it is the archive file.
+pub fn backup<P: AsRef<Path>, D: AsRef<Path>>(world_directory: P, destination: D, options: &BackupOptions) -> McResult<BackupReport> {
+    let world_directory = world_directory.as_ref();
+    let destination = destination.as_ref();
+    if !world_directory.is_dir() {
+        return Err(McError::WorldDirectoryNotFound(world_directory.to_owned()));
+    }
+    if options.previous.is_some() && options.format != BackupFormat::Directory {
+        return McError::custom("Incremental backups are only supported for BackupFormat::Directory.");
+    }
+    if options.codec.is_some() && !is_tar(options.format) {
+        return McError::custom("Backup compression is only supported for BackupFormat::Tar.");
+    }
+    let (files, regions) = collect_files(world_directory, options)?;
+    let mut report = BackupReport::default();
+    match options.format {
+        BackupFormat::Directory => {
+            std::fs::create_dir_all(destination)?;
+            for file in files.iter() {
+                copy_file(&world_directory.join(file), &destination.join(file))?;
+                report.copied += 1;
+            }
+            for region in regions.iter() {
+                let source = world_directory.join(region);
+                let target = destination.join(region);
+                let previous = options.previous.as_ref()
+                    .map(|previous| previous.join(region))
+                    .filter(|previous| previous.is_file());
+                if let Some(previous) = previous {
+                    let changed = diff_region(&previous, &source)?.len();
+                    let same_size = previous.metadata()?.len() == source.metadata()?.len();
+                    if changed == 0 && same_size {
+                        if let Some(parent) = target.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        // Hard links aren't supported everywhere, so fall back to copying.
+                        if std::fs::hard_link(&previous, &target).is_err() {
+                            std::fs::copy(&previous, &target)?;
+                        }
+                        report.linked += 1;
+                        continue;
+                    }
+                    report.changed_chunks += changed;
+                }
+                copy_file(&source, &target)?;
+                report.copied += 1;
+            }
+        }
+        #[cfg(feature = "tar")]
+        BackupFormat::Tar => {
+            let codec = options.codec.as_deref().unwrap_or(&CompressionScheme::Uncompressed);
+            let mut builder = tar::Builder::new(codec.encoder(Box::new(File::create(destination)?))?);
+            for file in files.iter().chain(regions.iter()) {
+                builder.append_path_with_name(world_directory.join(file), file)?;
+                report.copied += 1;
+            }
+            builder.into_inner()?.finish_write()?;
+        }
+        #[cfg(feature = "zip")]
+        BackupFormat::Zip => {
+            let zip_error = |err: zip::result::ZipError| McError::Custom(err.to_string());
+            let mut writer = zip::ZipWriter::new(File::create(destination)?);
+            let file_options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+            for file in files.iter().chain(regions.iter()) {
+                // Zip entry names always use forward slashes.
+                let name = file.components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                writer.start_file(name, file_options).map_err(zip_error)?;
+                std::io::copy(&mut File::open(world_directory.join(file))?, &mut writer)?;
+                report.copied += 1;
+            }
+            writer.finish().map_err(zip_error)?;
+        }
+    }
+    Ok(report)
+}
+
+/// What [export_changed_since] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportReport {
+    /// The number of region files that chunks were exported to.
+    pub region_files: usize,
+    pub chunks: usize,
+}
+
+/// Copies every chunk (of every region kind) whose timestamp is newer than `since` to
+/// the same place in the world at `destination`, along with level.dat. Chunks keep their
+/// timestamps. The destination may be an earlier export, in which case the chunks are
+/// merged into it; region files are only created for regions with exported chunks.
+pub fn export_changed_since<P: AsRef<Path>, Ts: Into<Timestamp>, D: AsRef<Path>>(world_directory: P, since: Ts, destination: D) -> McResult<ExportReport> {
+    let world_directory = world_directory.as_ref();
+    let destination = destination.as_ref();
+    let since: Timestamp = since.into();
+    if !world_directory.is_dir() {
+        return Err(McError::WorldDirectoryNotFound(world_directory.to_owned()));
+    }
+    let (_, regions) = collect_files(world_directory, &BackupOptions { playerdata: false, datapacks: false, ..Default::default() })?;
+    std::fs::create_dir_all(destination)?;
+    if world_directory.join("level.dat").is_file() {
+        copy_file(&world_directory.join("level.dat"), &destination.join("level.dat"))?;
+    }
+    let mut report = ExportReport::default();
+    let mut data = Vec::new();
+    for region in regions.iter() {
+        let mut source = RegionFile::open(world_directory.join(region))?;
+        let changed = (0..1024usize)
+            .map(RegionCoord::from)
+            .filter(|&coord| !source.get_sector(coord).is_empty() && source.get_timestamp(coord) > since)
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            continue;
+        }
+        let target = destination.join(region);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut target = RegionFile::open_or_create(target)?;
+        for coord in changed {
+            // The chunk is copied as bytes, so that it doesn't need to be (or even be) NBT.
+            data.clear();
+            source.read(coord, |mut decoder| {
+                decoder.read_to_end(&mut data)?;
+                Ok(())
+            })?;
+            target.write_timestamped(coord, source.get_timestamp(coord), |encoder| {
+                encoder.write_all(&data)?;
+                Ok(())
+            })?;
+            report.chunks += 1;
+        }
+        target.close()?;
+        report.region_files += 1;
+    }
+    Ok(report)
+}
+
+/// Restores a backup made with [backup] into `world_directory`, which is created
+/// if it doesn't exist. Files from the backup replace existing files; other files
+/// in the world directory are left alone.
+/// The format is detected from `backup`: a directory, or a file ending in `.tar`,
+/// `.tar.gz` (or `.tgz`), or `.zip` (when the matching feature is enabled).
+/// Tar archives compressed with another codec are restored with [restore_with_codec].
+/// Returns the number of files that were restored.
+/// Returns [McError::WorldLocked] if the world appears to be open in another process
+/// (see [ensure_world_idle]; `lock` is the lock that this process holds, if any);
+/// [restore_unchecked] skips that check.
+pub fn restore<P: AsRef<Path>, W: AsRef<Path>>(backup: P, world_directory: W, lock: Option<&SessionLock>) -> McResult<usize> {
+    ensure_world_idle(&world_directory, lock)?;
+    restore_unchecked(backup, world_directory)
+}
+
+/// Restores a backup like [restore], even if the world appears to be in use.
+pub fn restore_unchecked<P: AsRef<Path>, W: AsRef<Path>>(backup: P, world_directory: W) -> McResult<usize> {
+    let backup = backup.as_ref();
+    let world_directory = world_directory.as_ref();
+    std::fs::create_dir_all(world_directory)?;
+    if backup.is_dir() {
+        let mut files = Vec::new();
+        collect_recursive(backup, Path::new(""), &mut files)?;
+        for file in files.iter() {
+            copy_file(&backup.join(file), &world_directory.join(file))?;
+        }
+        return Ok(files.len());
+    }
+    match backup.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "tar")]
+        Some("tar") => unpack_tar(backup, world_directory, &CompressionScheme::Uncompressed),
+        #[cfg(feature = "tar")]
+        Some("tgz" | "gz") => unpack_tar(backup, world_directory, &CompressionScheme::GZip),
+        #[cfg(feature = "zip")]
+        Some("zip") => {
+            let zip_error = |err: zip::result::ZipError| McError::Custom(err.to_string());
+            let mut archive = zip::ZipArchive::new(File::open(backup)?).map_err(zip_error)?;
+            let count = archive.len();
+            archive.extract(world_directory).map_err(zip_error)?;
+            Ok(count)
+        }
+        _ => McError::custom(format!("Unsupported backup format: {}", backup.display())),
+    }
+}
+
+/// Restores a tar archive that was compressed with `codec` (see [BackupOptions::codec]).
+/// Like [restore], this refuses to restore into a world that another process has open.
+#[cfg(feature = "tar")]
+pub fn restore_with_codec<P: AsRef<Path>, W: AsRef<Path>>(backup: P, world_directory: W, codec: &dyn Codec, lock: Option<&SessionLock>) -> McResult<usize> {
+    ensure_world_idle(&world_directory, lock)?;
+    std::fs::create_dir_all(&world_directory)?;
+    unpack_tar(backup.as_ref(), world_directory.as_ref(), codec)
+}
+
+#[cfg(feature = "tar")]
+fn unpack_tar(backup: &Path, world_directory: &Path, codec: &dyn Codec) -> McResult<usize> {
+    let mut archive = tar::Archive::new(codec.decoder(Box::new(File::open(backup)?))?);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        // unpack_in refuses to write outside of the world directory.
+        count += entry?.unpack_in(world_directory)? as usize;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_world(directory: &Path) -> McResult<()> {
+        std::fs::create_dir_all(directory.join("region"))?;
+        std::fs::create_dir_all(directory.join("DIM-1/region"))?;
+        std::fs::create_dir_all(directory.join("playerdata"))?;
+        std::fs::create_dir_all(directory.join("datapacks/pack"))?;
+        std::fs::write(directory.join("level.dat"), b"level")?;
+        std::fs::write(directory.join("session.lock"), b"lock")?;
+        std::fs::write(directory.join("playerdata/player.dat"), b"player")?;
+        std::fs::write(directory.join("datapacks/pack/pack.mcmeta"), b"{}")?;
+        RegionFile::create(directory.join("region/r.0.0.mca"))?.write_data_timestamped((0, 0), &1i64, 100)?;
+        RegionFile::create(directory.join("DIM-1/region/r.0.0.mca"))?.write_data_timestamped((0, 0), &2i64, 100)?;
+        Ok(())
+    }
+
+    #[test]
+    fn backup_restore_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        make_world(&world)?;
+        let full = dir.path().join("full");
+        let report = backup(&world, &full, &BackupOptions::default())?;
+        assert_eq!(report.copied, 5);
+        assert!(!full.join("session.lock").exists());
+
+        RegionFile::open(world.join("region/r.0.0.mca"))?.write_data_timestamped((1, 0), &3i64, 200)?;
+        let options = BackupOptions {
+            previous: Some(full.clone()),
+            ..Default::default()
+        };
+        let report = backup(&world, dir.path().join("incremental"), &options)?;
+        assert_eq!(report.linked, 1);
+        assert_eq!(report.changed_chunks, 1);
+        assert_eq!(report.copied, 4);
+
+        let restored = dir.path().join("restored");
+        assert_eq!(restore(dir.path().join("incremental"), &restored, None)?, 5);
+        assert_eq!(std::fs::read(restored.join("playerdata/player.dat"))?, b"player");
+        let mut region = RegionFile::open(restored.join("region/r.0.0.mca"))?;
+        assert_eq!(region.read_data::<_, i64>((1, 0))?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_backup_test() -> McResult<()> {
+        use crate::nbt::{Map, tag::{NamedTag, Tag}};
+        let chunk = |value| NamedTag::new(Tag::Compound(Map::from([("value".to_owned(), Tag::Int(value))])));
+        let value = |region: &mut RegionFile, coord: (i32, i32)| -> McResult<i32> {
+            let root: NamedTag = region.read_data(coord)?;
+            let Tag::Compound(map) = root.tag() else { panic!("Expected a compound.") };
+            let Some(Tag::Int(value)) = map.get("value") else { panic!("Expected a value.") };
+            Ok(*value)
+        };
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        make_world(&world)?;
+        let path = world.join("region/r.0.0.mca");
+        let mut region = RegionFile::open(&path)?;
+        region.write_data_timestamped((0, 0), &chunk(1), 100)?;
+        region.write_data_timestamped((1, 0), &chunk(2), 100)?;
+        drop(region);
+        let full = dir.path().join("full");
+        backup(&world, &full, &BackupOptions::default())?;
+
+        // Swapping keeps the timestamps and the size of the file.
+        let size = path.metadata()?.len();
+        RegionFile::open(&path)?.swap_chunks((0, 0), (1, 0))?;
+        assert_eq!(path.metadata()?.len(), size);
+        assert_eq!(diff_region(full.join("region/r.0.0.mca"), &path)?, [RegionCoord::new(0, 0), RegionCoord::new(1, 0)]);
+        let options = BackupOptions {
+            previous: Some(full.clone()),
+            ..Default::default()
+        };
+        let swapped = dir.path().join("swapped");
+        let report = backup(&world, &swapped, &options)?;
+        assert_eq!((report.linked, report.changed_chunks), (1, 2));
+        let mut region = RegionFile::open(swapped.join("region/r.0.0.mca"))?;
+        assert_eq!(value(&mut region, (0, 0))?, 2);
+        assert_eq!(value(&mut region, (1, 0))?, 1);
+
+        // Rewriting a chunk with the same timestamp.
+        RegionFile::open(&path)?.write_data_timestamped((0, 0), &chunk(3), 100)?;
+        assert_eq!(diff_region(swapped.join("region/r.0.0.mca"), &path)?, [RegionCoord::new(0, 0)]);
+        let options = BackupOptions {
+            previous: Some(swapped.clone()),
+            ..Default::default()
+        };
+        let rewritten = dir.path().join("rewritten");
+        assert_eq!(backup(&world, &rewritten, &options)?.changed_chunks, 1);
+        assert_eq!(value(&mut RegionFile::open(rewritten.join("region/r.0.0.mca"))?, (0, 0))?, 3);
+        Ok(())
+    }
+
+    #[cfg(feature = "tar")]
+    #[test]
+    fn tar_backup_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        make_world(&world)?;
+        let archive = dir.path().join("backup.tar");
+        let options = BackupOptions {
+            format: BackupFormat::Tar,
+            ..Default::default()
+        };
+        assert_eq!(backup(&world, &archive, &options)?.copied, 5);
+        let restored = dir.path().join("restored");
+        assert_eq!(restore(&archive, &restored, None)?, 5);
+        assert_eq!(std::fs::read(restored.join("level.dat"))?, b"level");
+
+        let archive = dir.path().join("backup.tar.gz");
+        let options = BackupOptions {
+            format: BackupFormat::Tar,
+            codec: Some(Arc::new(CompressionScheme::GZip)),
+            ..Default::default()
+        };
+        assert_eq!(backup(&world, &archive, &options)?.copied, 5);
+        assert_eq!(std::fs::read(&archive)?[..2], [0x1F, 0x8B]);
+        let restored = dir.path().join("restored_gz");
+        assert_eq!(restore(&archive, &restored, None)?, 5);
+        assert_eq!(std::fs::read(restored.join("playerdata/player.dat"))?, b"player");
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_backup_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        make_world(&world)?;
+        let archive = dir.path().join("backup.zip");
+        let options = BackupOptions {
+            format: BackupFormat::Zip,
+            ..Default::default()
+        };
+        assert_eq!(backup(&world, &archive, &options)?.copied, 5);
+        let restored = dir.path().join("restored");
+        assert_eq!(restore(&archive, &restored, None)?, 5);
+        assert_eq!(std::fs::read(restored.join("datapacks/pack/pack.mcmeta"))?, b"{}");
+        Ok(())
+    }
+
+    #[test]
+    fn export_changed_since_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path().join("world");
+        make_world(&world)?;
+        RegionFile::open(world.join("region/r.0.0.mca"))?.write_data_timestamped((1, 0), &3i64, 200)?;
+        let export = dir.path().join("export");
+        let report = export_changed_since(&world, 150, &export)?;
+        assert_eq!(report, ExportReport { region_files: 1, chunks: 1 });
+        assert!(export.join("level.dat").is_file());
+        assert!(!export.join("DIM-1").exists());
+        let mut region = RegionFile::open(export.join("region/r.0.0.mca"))?;
+        assert!(region.get_sector((0, 0)).is_empty());
+        assert_eq!(region.read_data::<_, i64>((1, 0))?, 3);
+        assert_eq!(region.get_timestamp((1, 0)), Timestamp::from(200));
+
+        // A later export is merged into the earlier one.
+        RegionFile::open(world.join("DIM-1/region/r.0.0.mca"))?.write_data_timestamped((2, 0), &4i64, 300)?;
+        assert_eq!(export_changed_since(&world, 250, &export)?.chunks, 1);
+        assert!(export.join("region/r.0.0.mca").is_file());
+        assert_eq!(RegionFile::open(export.join("DIM-1/region/r.0.0.mca"))?.read_data::<_, i64>((2, 0))?, 4);
+        Ok(())
+    }
+}
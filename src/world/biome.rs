@@ -0,0 +1,188 @@
+//! Biome replacement.
+//!
+//! Since 1.18, each section stores its biomes as a palette of biome names and
+//! packed indices into it, so replacing a biome is usually just renaming one palette
+//! entry. Block data is never touched. Chunks from before 1.18 (which store numeric
+//! biome IDs in a `Biomes` array) are left as they are.
+
+use std::path::Path;
+
+use crate::{
+    McResult,
+    math::packed::{PackedArray, get_packed, palette_bits},
+    nbt::{
+        Map,
+        tag::{ListTag, Tag},
+    },
+};
+
+use super::{
+    components::namespaced,
+    scan::{for_each_chunk, RegionKind},
+    selection::WorldSelection,
+};
+
+/// The number of biome cells (4x4x4 blocks each) in a section.
+const BIOME_CELLS: usize = 64;
+
+/// Replaces the biome `from` with `to` in the `biomes` compound of a section.
+/// If `to` is already in the palette, the two entries are merged and the indices are
+/// repacked. Returns true if the section was changed.
+pub fn replace_section_biome(biomes: &mut Map, from: &str, to: &str) -> bool {
+    let Some(Tag::List(ListTag::String(palette))) = biomes.get_mut("palette") else {
+        return false;
+    };
+    let Some(from_index) = palette.iter().position(|biome| biome == from) else {
+        return false;
+    };
+    let Some(to_index) = palette.iter().position(|biome| biome == to) else {
+        palette[from_index] = to.to_owned();
+        return true;
+    };
+    if from_index == to_index {
+        return false;
+    }
+    let old_bits = palette_bits(palette.len(), 1);
+    palette.remove(from_index);
+    let len = palette.len();
+    // Indices above the removed entry move down by one.
+    let to_index = to_index - (to_index > from_index) as usize;
+    let remap = |index: u64| -> u64 {
+        let index = index as usize;
+        let index = if index == from_index {
+            to_index
+        } else {
+            index - (index > from_index) as usize
+        };
+        index.min(len - 1) as u64
+    };
+    let values = match biomes.get("data") {
+        Some(Tag::LongArray(data)) => (0..BIOME_CELLS).map(|cell| remap(get_packed(data, old_bits, cell))).collect::<Vec<_>>(),
+        _ => vec![remap(0); BIOME_CELLS],
+    };
+    if len == 1 {
+        // A single-entry palette is stored without data.
+        biomes.remove("data");
+    } else {
+        let mut data = PackedArray::new(palette_bits(len, 1), BIOME_CELLS);
+        values.into_iter().enumerate().for_each(|(cell, value)| {
+            data.set(cell, value);
+        });
+        biomes.insert("data".to_owned(), Tag::LongArray(data.into_longs()));
+    }
+    true
+}
+
+/// Replaces the biome `from` with `to` in the sections of a chunk's root tag.
+/// Returns true if any section was changed.
+pub fn replace_chunk_biome(chunk: &mut Tag, from: &str, to: &str) -> bool {
+    let Tag::Compound(root) = chunk else {
+        return false;
+    };
+    let Some(Tag::List(ListTag::Compound(sections))) = root.get_mut("sections") else {
+        return false;
+    };
+    sections.iter_mut().fold(false, |changed, section| {
+        match section.get_mut("biomes") {
+            Some(Tag::Compound(biomes)) => replace_section_biome(biomes, from, to) || changed,
+            _ => changed,
+        }
+    })
+}
+
+/// Replaces the biome `from` with `to` (both biome IDs, with the `minecraft:` namespace
+/// added if they don't have one) in every selected chunk.
+/// Returns the number of chunks that were changed.
+pub fn replace_biome<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection, from: &str, to: &str) -> McResult<usize> {
+    let (from, to) = (namespaced(from), namespaced(to));
+    let mut count = 0;
+    for_each_chunk(world_directory, selection, RegionKind::Terrain, |_, root| {
+        let modified = replace_chunk_biome(root.tag_mut(), &from, &to);
+        count += modified as usize;
+        Ok(modified)
+    })?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::{Dimension, WorldCoord},
+        nbt::tag::NamedTag,
+        world::{
+            io::region::RegionFile,
+            scan::region_file_path,
+        },
+    };
+
+    fn biomes(palette: &[&str], values: &[u64]) -> Map {
+        let mut biomes = Map::from([
+            ("palette".to_owned(), Tag::List(ListTag::String(palette.iter().map(|&biome| biome.to_owned()).collect()))),
+        ]);
+        if palette.len() > 1 {
+            let mut data = PackedArray::new(palette_bits(palette.len(), 1), BIOME_CELLS);
+            values.iter().enumerate().for_each(|(cell, &value)| {
+                data.set(cell, value);
+            });
+            biomes.insert("data".to_owned(), Tag::LongArray(data.into_longs()));
+        }
+        biomes
+    }
+
+    fn read(biomes: &Map) -> Vec<String> {
+        let Some(Tag::List(ListTag::String(palette))) = biomes.get("palette") else { panic!("Expected a palette.") };
+        (0..BIOME_CELLS).map(|cell| {
+            let index = match biomes.get("data") {
+                Some(Tag::LongArray(data)) => get_packed(data, palette_bits(palette.len(), 1), cell) as usize,
+                _ => 0,
+            };
+            palette[index].clone()
+        }).collect()
+    }
+
+    #[test]
+    fn replace_biome_test() -> McResult<()> {
+        // Merging into an entry that is already in the palette repacks the data.
+        let values = (0..BIOME_CELLS as u64).map(|cell| cell % 3).collect::<Vec<_>>();
+        let mut section = biomes(&["minecraft:plains", "minecraft:forest", "minecraft:desert"], &values);
+        let mut expected = read(&section);
+        expected.iter_mut().filter(|biome| *biome == "minecraft:plains").for_each(|biome| *biome = "minecraft:desert".to_owned());
+        assert!(replace_section_biome(&mut section, "minecraft:plains", "minecraft:desert"));
+        assert_eq!(read(&section), expected);
+        assert!(!replace_section_biome(&mut section, "minecraft:plains", "minecraft:desert"));
+
+        let dir = tempfile::tempdir()?;
+        let path = region_file_path(dir.path(), WorldCoord::new(0, 0, Dimension::Overworld), RegionKind::Terrain)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let blocks = Tag::Compound(Map::from([
+            ("palette".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([("Name".to_owned(), Tag::string("minecraft:stone"))])]))),
+        ]));
+        let chunk = |palette: &[&str]| NamedTag::new(Tag::Compound(Map::from([
+            ("sections".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                ("Y".to_owned(), Tag::Byte(0)),
+                ("block_states".to_owned(), blocks.clone()),
+                ("biomes".to_owned(), Tag::Compound(biomes(palette, &[1, 0]))),
+            ])]))),
+        ])));
+        {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &chunk(&["minecraft:plains"]))?;
+            region.write_data((1, 0), &chunk(&["minecraft:plains", "minecraft:river"]))?;
+            region.write_data((2, 0), &chunk(&["minecraft:river"]))?;
+        }
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        assert_eq!(replace_biome(dir.path(), &selection, "plains", "minecraft:river")?, 2);
+        let mut region = RegionFile::open(&path)?;
+        for x in 0..3 {
+            let root: NamedTag = region.read_data((x, 0))?;
+            let Tag::Compound(root) = root.take_tag() else { panic!("Expected a compound.") };
+            let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else { panic!("Expected sections.") };
+            let Some(Tag::Compound(section_biomes)) = sections[0].get("biomes") else { panic!("Expected biomes.") };
+            assert!(read(section_biomes).iter().all(|biome| biome == "minecraft:river"));
+            assert!(!section_biomes.contains_key("data"));
+            assert!(matches!(sections[0].get("block_states"), Some(Tag::Compound(states)) if states.len() == 1));
+        }
+        Ok(())
+    }
+}
@@ -7,8 +7,22 @@ use std::sync::atomic::{
 };
 
 use std::collections::HashMap;
+use std::path::Path;
+
+use flate2::Compression;
+
+use crate::McError;
+use crate::McResult;
+use crate::nbt::Map;
+use crate::nbt::file::{read_nbt_file, write_nbt_file};
+use crate::nbt::tag::{ListTag, NamedTag, Tag};
 
 use super::blockstate::*;
+use super::chunk::decode_palette;
+
+/// The name of the file in the world directory that a world's [BlockRegistry] is saved to,
+/// so that block IDs stay the same between sessions.
+pub const REGISTRY_FILE_NAME: &str = "mcutil_block_registry.dat";
 
 // I'm going to shelve this for another time.
 // #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -112,8 +126,79 @@ impl BlockRegistry {
         self.get_owned(id).unwrap_or_else(f)
     }
 
+    /// Iterates over the registered [BlockState]s in ID order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &BlockState)> {
+        self.states.iter().enumerate().map(|(id, state)| (id as u32, state))
+    }
+
+    /// Loads a registry that was saved with [BlockRegistry::save]. Every
+    /// [BlockState] has the same ID that it had when it was saved.
+    pub fn load<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        let Tag::Compound(mut root) = read_nbt_file(path)?.take_tag() else {
+            return Err(McError::NbtDecodeError);
+        };
+        let states = match root.remove("states") {
+            Some(Tag::List(states @ ListTag::Compound(_))) => decode_palette(states)?,
+            Some(Tag::List(ListTag::Empty)) => Vec::new(),
+            _ => return Err(McError::NbtDecodeError),
+        };
+        let mut registry = Self::new();
+        for state in states {
+            // Duplicates would give the same state two IDs.
+            if registry.find(&state).is_some() {
+                return Err(McError::NbtDecodeError);
+            }
+            registry.register(state);
+        }
+        Ok(registry)
+    }
+
+    /// Saves the registry as a list of the [BlockState]s in ID order.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> McResult<()> {
+        let states = self.states.iter().map(|state| {
+            let mut map = state.clone().to_nbt();
+            // Otherwise a state without properties would be read back with empty properties.
+            if state.properties().is_none() {
+                map.remove("Properties");
+            }
+            map
+        }).collect::<Vec<Map>>();
+        let states = if states.is_empty() { ListTag::Empty } else { ListTag::Compound(states) };
+        let root = Map::from([("states".to_owned(), Tag::List(states))]);
+        write_nbt_file(path, &NamedTag::new(Tag::Compound(root)), Compression::default())?;
+        Ok(())
+    }
+
     // TODO: I need a function to create a subset BlockRegistry.
     // pub fn subset(&self) -> BlockRegistry {
     // 	todo!()
     // }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(REGISTRY_FILE_NAME);
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let log = registry.register(BlockState::new("minecraft:oak_log", [("axis", "y")]));
+        registry.save(&path)?;
+        let loaded = BlockRegistry::load(&path)?;
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded.find(BlockState::air()), Some(0));
+        assert_eq!(loaded.find(BlockState::from("minecraft:stone")), Some(stone));
+        assert_eq!(loaded.get(log).map(|state| state.to_string()), registry.get(log).map(|state| state.to_string()));
+
+        // IDs are kept between sessions of a world.
+        let mut world = crate::world::world::VirtualJavaWorld::open_persistent(dir.path())?;
+        assert_eq!(world.block_registry.find(BlockState::from("minecraft:stone")), Some(stone));
+        let glass = world.block_registry.register(BlockState::from("minecraft:glass"));
+        world.save_all()?;
+        let world = crate::world::world::VirtualJavaWorld::open_persistent(dir.path())?;
+        assert_eq!(world.block_registry.find(BlockState::from("minecraft:glass")), Some(glass));
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+//! The boss data stored in `level.dat`: the state of the ender dragon fight
+//! (`DragonFight`) and the boss bars created with `/bossbar` (`CustomBossEvents`).
+
+use std::collections::BTreeMap;
+
+use crate::{
+    McError, McResult,
+    math::coord::BlockPos,
+    nbt::{
+        Map,
+        tag::{DecodeNbt, EncodeNbt, ListTag, Tag},
+    },
+    util::uuid::{read_uuid, write_uuid, write_uuid_legacy, from_int_array, to_int_array},
+};
+
+/// The number of end gateways that can be spawned by killing the dragon.
+pub const GATEWAY_COUNT: i32 = 20;
+
+fn take_bool(map: &mut Map, name: &str) -> Option<bool> {
+    match map.remove(name) {
+        Some(Tag::Byte(value)) => Some(value != 0),
+        _ => None,
+    }
+}
+
+fn take_int(map: &mut Map, name: &str) -> Option<i32> {
+    match map.remove(name) {
+        Some(Tag::Int(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn take_string(map: &mut Map, name: &str) -> Option<String> {
+    match map.remove(name) {
+        Some(Tag::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// The state of the ender dragon fight.
+/// Tags that are not known are kept in `other` so that they are written back.
+#[derive(Debug, Clone)]
+pub struct DragonFight {
+    /// DragonKilled
+    pub dragon_killed: bool,
+    /// PreviouslyKilled
+    pub previously_killed: bool,
+    /// NeedsStateScanning
+    pub needs_state_scanning: bool,
+    /// Dragon (or DragonUUIDMost/DragonUUIDLeast before 1.16)
+    pub dragon: Option<u128>,
+    /// ExitPortalLocation
+    pub exit_portal_location: Option<BlockPos>,
+    /// Gateways: the end gateways that have not been spawned yet, in the order that they will be spawned.
+    pub gateways: Vec<i32>,
+    pub other: Map,
+    /// Whether the dragon's UUID is stored as DragonUUIDMost/DragonUUIDLeast.
+    legacy_dragon: bool,
+    /// Whether ExitPortalLocation is stored as a Compound of X/Y/Z (older versions)
+    /// rather than an IntArray.
+    legacy_exit_portal: bool,
+}
+
+impl Default for DragonFight {
+    fn default() -> Self {
+        Self {
+            dragon_killed: false,
+            previously_killed: false,
+            needs_state_scanning: true,
+            dragon: None,
+            exit_portal_location: None,
+            gateways: (0..GATEWAY_COUNT).collect(),
+            other: Map::new(),
+            legacy_dragon: false,
+            legacy_exit_portal: false,
+        }
+    }
+}
+
+impl DragonFight {
+    /// Resets the fight to how it is in a new world, so that the dragon spawns again
+    /// and the gateways are spawned again (in order) as it is killed.
+    /// The exit portal location is kept. Terrain in the End is not changed, so
+    /// gateways, crystals, and the egg that already exist are not removed.
+    pub fn reset(&mut self) {
+        *self = Self {
+            exit_portal_location: self.exit_portal_location,
+            other: std::mem::take(&mut self.other),
+            legacy_dragon: self.legacy_dragon,
+            legacy_exit_portal: self.legacy_exit_portal,
+            ..Default::default()
+        };
+    }
+}
+
+impl EncodeNbt for DragonFight {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        map.insert("DragonKilled".to_owned(), Tag::from(self.dragon_killed));
+        map.insert("PreviouslyKilled".to_owned(), Tag::from(self.previously_killed));
+        map.insert("NeedsStateScanning".to_owned(), Tag::from(self.needs_state_scanning));
+        match self.dragon {
+            Some(dragon) if self.legacy_dragon => write_uuid_legacy(&mut map, "DragonUUID", dragon),
+            Some(dragon) => write_uuid(&mut map, "Dragon", dragon),
+            None => (),
+        }
+        if let Some(exit) = self.exit_portal_location {
+            let (x, y, z) = (exit.x as i32, exit.y as i32, exit.z as i32);
+            let exit = if self.legacy_exit_portal {
+                Tag::Compound(Map::from_iter([
+                    ("X".to_owned(), Tag::Int(x)),
+                    ("Y".to_owned(), Tag::Int(y)),
+                    ("Z".to_owned(), Tag::Int(z)),
+                ]))
+            } else {
+                Tag::IntArray(vec![x, y, z])
+            };
+            map.insert("ExitPortalLocation".to_owned(), exit);
+        }
+        map.insert("Gateways".to_owned(), Tag::List(ListTag::Int(self.gateways)));
+        Tag::Compound(map)
+    }
+}
+
+impl DecodeNbt for DragonFight {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        // Before 1.16, the dragon's UUID was stored as DragonUUIDMost/DragonUUIDLeast.
+        let legacy_dragon = map.contains_key("DragonUUIDMost");
+        let dragon = read_uuid(&map, "Dragon").or_else(|| read_uuid(&map, "DragonUUID"));
+        ["Dragon", "DragonUUIDMost", "DragonUUIDLeast"].into_iter().for_each(|name| {
+            map.remove(name);
+        });
+        let (exit_portal_location, legacy_exit_portal) = match map.remove("ExitPortalLocation") {
+            Some(Tag::IntArray(pos)) if pos.len() == 3 => (Some(BlockPos::new(pos[0] as i64, pos[1] as i64, pos[2] as i64)), false),
+            Some(Tag::Compound(mut pos)) => match (take_int(&mut pos, "X"), take_int(&mut pos, "Y"), take_int(&mut pos, "Z")) {
+                (Some(x), Some(y), Some(z)) => (Some(BlockPos::new(x as i64, y as i64, z as i64)), true),
+                _ => (None, true),
+            },
+            _ => (None, false),
+        };
+        let gateways = match map.remove("Gateways") {
+            Some(Tag::List(ListTag::Int(gateways))) => gateways,
+            Some(Tag::IntArray(gateways)) => gateways,
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            dragon_killed: take_bool(&mut map, "DragonKilled").unwrap_or(false),
+            previously_killed: take_bool(&mut map, "PreviouslyKilled").unwrap_or(false),
+            needs_state_scanning: take_bool(&mut map, "NeedsStateScanning").unwrap_or(true),
+            dragon,
+            exit_portal_location,
+            gateways,
+            other: map,
+            legacy_dragon,
+            legacy_exit_portal,
+        })
+    }
+}
+
+/// The color of a boss bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BossBarColor {
+    Pink,
+    Blue,
+    Red,
+    Green,
+    Yellow,
+    Purple,
+    #[default]
+    White,
+}
+
+impl BossBarColor {
+    pub fn name(self) -> &'static str {
+        match self {
+            BossBarColor::Pink => "pink",
+            BossBarColor::Blue => "blue",
+            BossBarColor::Red => "red",
+            BossBarColor::Green => "green",
+            BossBarColor::Yellow => "yellow",
+            BossBarColor::Purple => "purple",
+            BossBarColor::White => "white",
+        }
+    }
+
+    /// Gets the color from its name. Like the game, unknown names are [BossBarColor::White].
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "pink" => BossBarColor::Pink,
+            "blue" => BossBarColor::Blue,
+            "red" => BossBarColor::Red,
+            "green" => BossBarColor::Green,
+            "yellow" => BossBarColor::Yellow,
+            "purple" => BossBarColor::Purple,
+            _ => BossBarColor::White,
+        }
+    }
+}
+
+/// How a boss bar is divided into notches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BossBarOverlay {
+    #[default]
+    Progress,
+    Notched6,
+    Notched10,
+    Notched12,
+    Notched20,
+}
+
+impl BossBarOverlay {
+    pub fn name(self) -> &'static str {
+        match self {
+            BossBarOverlay::Progress => "progress",
+            BossBarOverlay::Notched6 => "notched_6",
+            BossBarOverlay::Notched10 => "notched_10",
+            BossBarOverlay::Notched12 => "notched_12",
+            BossBarOverlay::Notched20 => "notched_20",
+        }
+    }
+
+    /// Gets the overlay from its name. Like the game, unknown names are [BossBarOverlay::Progress].
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "notched_6" => BossBarOverlay::Notched6,
+            "notched_10" => BossBarOverlay::Notched10,
+            "notched_12" => BossBarOverlay::Notched12,
+            "notched_20" => BossBarOverlay::Notched20,
+            _ => BossBarOverlay::Progress,
+        }
+    }
+}
+
+/// A boss bar created with `/bossbar`.
+#[derive(Debug, Clone)]
+pub struct BossEvent {
+    /// Name: the title of the boss bar as a JSON text component.
+    pub name: String,
+    /// Color
+    pub color: BossBarColor,
+    /// Overlay
+    pub overlay: BossBarOverlay,
+    /// Value
+    pub value: i32,
+    /// Max
+    pub max: i32,
+    /// Visible
+    pub visible: bool,
+    /// DarkenScreen
+    pub darken_screen: bool,
+    /// PlayBossMusic
+    pub play_boss_music: bool,
+    /// CreateWorldFog
+    pub create_world_fog: bool,
+    /// Players: the players that can see the boss bar.
+    pub players: Vec<u128>,
+    pub other: Map,
+}
+
+impl BossEvent {
+    /// A boss bar with the defaults that `/bossbar add` uses.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            color: BossBarColor::White,
+            overlay: BossBarOverlay::Progress,
+            value: 0,
+            max: 100,
+            visible: true,
+            darken_screen: false,
+            play_boss_music: false,
+            create_world_fog: false,
+            players: Vec::new(),
+            other: Map::new(),
+        }
+    }
+}
+
+impl EncodeNbt for BossEvent {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        map.insert("Name".to_owned(), Tag::String(self.name));
+        map.insert("Color".to_owned(), Tag::String(self.color.name().to_owned()));
+        map.insert("Overlay".to_owned(), Tag::String(self.overlay.name().to_owned()));
+        map.insert("Value".to_owned(), Tag::Int(self.value));
+        map.insert("Max".to_owned(), Tag::Int(self.max));
+        map.insert("Visible".to_owned(), Tag::from(self.visible));
+        map.insert("DarkenScreen".to_owned(), Tag::from(self.darken_screen));
+        map.insert("PlayBossMusic".to_owned(), Tag::from(self.play_boss_music));
+        map.insert("CreateWorldFog".to_owned(), Tag::from(self.create_world_fog));
+        let players = self.players.into_iter().map(|uuid| to_int_array(uuid).to_vec()).collect();
+        map.insert("Players".to_owned(), Tag::List(ListTag::IntArray(players)));
+        Tag::Compound(map)
+    }
+}
+
+impl DecodeNbt for BossEvent {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        let players = match map.remove("Players") {
+            Some(Tag::List(ListTag::IntArray(players))) => players.into_iter()
+                .filter_map(|ints| <[i32; 4]>::try_from(ints.as_slice()).ok())
+                .map(from_int_array)
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            name: take_string(&mut map, "Name").ok_or(McError::NotFoundInCompound("Name".to_owned()))?,
+            color: take_string(&mut map, "Color").map(|name| BossBarColor::from_name(&name)).unwrap_or_default(),
+            overlay: take_string(&mut map, "Overlay").map(|name| BossBarOverlay::from_name(&name)).unwrap_or_default(),
+            value: take_int(&mut map, "Value").unwrap_or(0),
+            max: take_int(&mut map, "Max").unwrap_or(100),
+            visible: take_bool(&mut map, "Visible").unwrap_or(true),
+            darken_screen: take_bool(&mut map, "DarkenScreen").unwrap_or(false),
+            play_boss_music: take_bool(&mut map, "PlayBossMusic").unwrap_or(false),
+            create_world_fog: take_bool(&mut map, "CreateWorldFog").unwrap_or(false),
+            players,
+            other: map,
+        })
+    }
+}
+
+/// The boss bars created with `/bossbar`, by ID (such as `minecraft:my_bar`).
+#[derive(Debug, Clone, Default)]
+pub struct CustomBossEvents {
+    pub events: BTreeMap<String, BossEvent>,
+}
+
+impl CustomBossEvents {
+    pub fn get(&self, id: &str) -> Option<&BossEvent> {
+        self.events.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut BossEvent> {
+        self.events.get_mut(id)
+    }
+
+    /// Adds or replaces a boss bar, returning the old one.
+    pub fn insert<S: Into<String>>(&mut self, id: S, event: BossEvent) -> Option<BossEvent> {
+        self.events.insert(id.into(), event)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<BossEvent> {
+        self.events.remove(id)
+    }
+}
+
+impl EncodeNbt for CustomBossEvents {
+    fn encode_nbt(self) -> Tag {
+        Tag::Compound(self.events.into_iter()
+            .map(|(id, event)| (id, event.encode_nbt()))
+            .collect())
+    }
+}
+
+impl DecodeNbt for CustomBossEvents {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        Ok(Self {
+            events: map.into_iter()
+                .map(|(id, event)| Ok((id, BossEvent::decode_nbt(event)?)))
+                .collect::<McResult<_>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dragon_fight_test() -> McResult<()> {
+        let legacy = Tag::Compound(Map::from_iter([
+            ("DragonKilled".to_owned(), Tag::Byte(1)),
+            ("PreviouslyKilled".to_owned(), Tag::Byte(1)),
+            ("DragonUUIDMost".to_owned(), Tag::Long(1)),
+            ("DragonUUIDLeast".to_owned(), Tag::Long(2)),
+            ("ExitPortalLocation".to_owned(), Tag::Compound(Map::from_iter([
+                ("X".to_owned(), Tag::Int(0)),
+                ("Y".to_owned(), Tag::Int(63)),
+                ("Z".to_owned(), Tag::Int(0)),
+            ]))),
+            ("Gateways".to_owned(), Tag::List(ListTag::Int(vec![4, 7]))),
+            ("Unknown".to_owned(), Tag::Int(5)),
+        ]));
+        let mut fight = DragonFight::decode_nbt(legacy)?;
+        assert!(fight.dragon_killed);
+        assert_eq!(fight.dragon, Some((1u128 << 64) | 2));
+        assert_eq!(fight.exit_portal_location, Some(BlockPos::new(0, 63, 0)));
+        assert_eq!(fight.gateways, vec![4, 7]);
+        fight.reset();
+        assert!(!fight.dragon_killed && !fight.previously_killed && fight.needs_state_scanning);
+        assert_eq!(fight.gateways.len(), GATEWAY_COUNT as usize);
+        let Tag::Compound(map) = fight.encode_nbt() else {
+            panic!("Expected a Compound.");
+        };
+        assert!(matches!(map.get("ExitPortalLocation"), Some(Tag::Compound(_))));
+        assert!(matches!(map.get("Unknown"), Some(Tag::Int(5))));
+        assert!(!map.contains_key("Dragon"));
+        Ok(())
+    }
+
+    #[test]
+    fn boss_events_test() -> McResult<()> {
+        let mut events = CustomBossEvents::default();
+        let mut event = BossEvent::new("\"Siege\"");
+        event.color = BossBarColor::Red;
+        event.overlay = BossBarOverlay::Notched10;
+        event.value = 40;
+        event.players.push(0x069a79f4_44e9_4726_a5be_fca90e38aaf5);
+        events.insert("minecraft:siege", event);
+        let events = CustomBossEvents::decode_nbt(events.encode_nbt())?;
+        let event = events.get("minecraft:siege").unwrap();
+        assert_eq!(event.color, BossBarColor::Red);
+        assert_eq!(event.overlay, BossBarOverlay::Notched10);
+        assert_eq!((event.value, event.max), (40, 100));
+        assert_eq!(event.players, vec![0x069a79f4_44e9_4726_a5be_fca90e38aaf5]);
+        Ok(())
+    }
+}
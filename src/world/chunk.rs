@@ -1,19 +1,25 @@
 // #![allow(unused)]
 use std::collections::HashMap;
+use std::io::{Read, Write};
 // use std::default;
-use std::ops::Not;
 
 use super::block::HeightmapFlag;
 use super::blockstate::*;
 
 use crate::McError;
 use crate::McResult;
-use crate::math::bit::BitLength;
+use crate::ioext::{Readable, Writable};
+use crate::math::bit::{BitLength, get_nibble, set_nibble};
+use crate::math::coord::{BlockPos, ChunkPos};
+use crate::math::packed::{PackedArray, Packing, get_packed, long_count, palette_bits};
 use crate::nbt::*;
 // use crate::nbt::io::*;
 use crate::nbt::tag::*;
 use crate::nbt::tagtype::*;
+use crate::nbt::io::NbtSize;
 use super::blockregistry::BlockRegistry;
+use super::components::Components;
+use super::height::WorldHeight;
 // use super::world::*;
 
 /// This macro is used to remove an entry from a Map (usually HashMap or IndexMap)
@@ -83,6 +89,29 @@ good example. It may perhaps be more optimal in-engine for the world to
 consider the Chunk struct to be mere data and not suitable for representation
 in engine, so the engine may choose a different representation.
 */
+/// The payloads removed by [Chunk::strip].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StripOptions {
+    /// Section light, `Lights`, and `isLightOn`.
+    pub light: bool,
+    /// `PostProcessing`.
+    pub post_processing: bool,
+    /// `fluid_ticks` and `block_ticks`.
+    pub ticks: bool,
+    /// `Entities` (only stored in chunks before 1.17 and in proto-chunks).
+    pub entities: bool,
+}
+
+impl StripOptions {
+    /// Removes everything that [Chunk::strip] can remove.
+    pub const ALL: Self = Self {
+        light: true,
+        post_processing: true,
+        ticks: true,
+        entities: true,
+    };
+}
+
 #[derive(Clone)]
 pub struct Chunk {
     /// DataVersion
@@ -93,6 +122,10 @@ pub struct Chunk {
     pub y: i32,
     /// zPos
     pub z: i32,
+    /// The number of blocks above `yPos * 16` that the chunk can hold. This isn't saved in
+    /// the chunk; it comes from the dimension (see [WorldHeight](super::height::WorldHeight)).
+    /// A decoded chunk guesses it from `yPos` and its sections (see [WorldHeight::infer]).
+    pub height: u32,
     /// LastUpdate
     pub last_update: i64,
     /// Status
@@ -121,80 +154,249 @@ pub struct Chunk {
     pub entities: Option<ListTag>,
     /// All other unknown tags.
     pub other: Map,
+    /// The order of the chunk's keys when it was read by [decode_chunk_preserving].
+    /// [encode_chunk] writes the keys in this order. (Only the `preserve_order` feature keeps key order.)
+    pub key_order: Option<Vec<String>>,
 }
 
 impl Chunk {
 
+    /// The DataVersion of chunks created with [Chunk::new] (1.20.1).
+    pub const DATA_VERSION: i32 = 3465;
+
+    /// The number of sections that a chunk can hold above `yPos` by default (the height of a 1.18+ overworld).
+    /// Writing to a Y coordinate within [Chunk::height_range] will add the section if it is missing.
+    pub const SECTION_COUNT: i64 = 24;
+
+    /// The range of block Y coordinates that this chunk can hold.
+    pub fn height_range(&self) -> std::ops::Range<i64> {
+        let min = self.y as i64 * 16;
+        min..min + self.height as i64
+    }
+
+    /// The size in bytes of this chunk's NBT (as written by [encode_chunk]) before compression.
+    /// This encodes the chunk, which is much cheaper than compressing it, so it can be used
+    /// to plan sectors with [estimated_sectors](super::io::region::estimated_sectors) or to catch
+    /// [McError::RegionDataTooLarge] with [check_nbt_size](super::io::region::check_nbt_size) first.
+    pub fn estimated_nbt_size(&self, registry: &BlockRegistry) -> usize {
+        NamedTag::new(Tag::Compound(encode_chunk(registry, self))).nbt_size()
+    }
+
+    /// The root tags that the decoder doesn't know about, such as capabilities and
+    /// attachments added by mods. They are written back as they were read.
+    pub fn extras(&self) -> &Map {
+        &self.other
+    }
+
+    pub fn extras_mut(&mut self) -> &mut Map {
+        &mut self.other
+    }
+
+    /// The position of this chunk (xPos/zPos).
+    pub fn pos(&self) -> ChunkPos {
+        ChunkPos::new(self.x as i64, self.z as i64)
+    }
+
+    /// Determines if the absolute block coordinate `coord` is in this chunk's column.
+    /// (Y is not checked; see [Chunk::height_range].)
+    pub fn contains_block<C: Into<BlockPos>>(&self, coord: C) -> bool {
+        coord.into().chunk() == self.pos()
+    }
+
+    /// Returns an error if `coord` is not in this chunk's column.
+    pub fn check_coord<C: Into<BlockPos>>(&self, coord: C) -> McResult<BlockPos> {
+        let coord: BlockPos = coord.into();
+        if self.contains_block(coord) {
+            Ok(coord)
+        } else {
+            Err(McError::CoordOutOfChunk(coord, self.pos()))
+        }
+    }
+
+    /// The block accessors take absolute coordinates and only use the low 4 bits of X and Z,
+    /// so a coordinate from another chunk would silently alias a block in this one.
+    /// In debug builds this catches that mistake.
     #[inline(always)]
-    fn section_index_and_local_coord(&self, coord: (i64, i64, i64)) -> (usize, (i64, i64, i64)) {
-        let lowy = self.sections.sections[0].y;
-        let section_index = chunk_section_index(coord.1, lowy as i64);
-        let local = chunk_local_coord(coord);
-        (section_index, local)
+    fn debug_check_coord(&self, coord: BlockPos) {
+        debug_assert!(
+            self.contains_block(coord),
+            "Block coordinate {coord:?} is not in chunk {:?}.", self.pos()
+        );
+    }
+
+    pub fn blocklight<C: Into<BlockPos>>(&self, coord: C) -> u8 {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        self.sections.section_for_y(coord.y)
+            .map(|section| section.blocklight(x, y, z))
+            .unwrap_or(0)
+    }
+
+    pub fn skylight<C: Into<BlockPos>>(&self, coord: C) -> u8 {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        self.sections.section_for_y(coord.y)
+            .map(|section| section.skylight(x, y, z))
+            .unwrap_or(0)
+    }
+
+    /// Gets the section that contains the block Y coordinate `y`, adding an empty section
+    /// if it is missing. Returns an error if `y` is outside of [Chunk::height_range].
+    fn section_for_write(&mut self, y: i64) -> McResult<&mut ChunkSection> {
+        if self.sections.section_for_y(y).is_none() && !self.height_range().contains(&y) {
+            return Err(McError::YOutOfRange(y));
+        }
+        Ok(self.sections.get_or_insert(y.div_euclid(16) as i8))
     }
 
-    pub fn blocklight(&self, coord: (i64, i64, i64)) -> u8 {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].blocklight(x, y, z)
+    pub fn set_blocklight<C: Into<BlockPos>>(&mut self, coord: C, level: u8) -> McResult<u8> {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        Ok(self.section_for_write(coord.y)?.set_blocklight(x, y, z, level))
     }
 
-    pub fn skylight(&self, coord: (i64, i64, i64)) -> u8 {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].skylight(x, y, z)
+    pub fn set_skylight<C: Into<BlockPos>>(&mut self, coord: C, level: u8) -> McResult<u8> {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        Ok(self.section_for_write(coord.y)?.set_skylight(x, y, z, level))
     }
 
-    pub fn set_blocklight(&mut self, coord: (i64, i64, i64), level: u8) -> u8 {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].set_blocklight(x, y, z, level)
+    /// Sets every block in the section at section Y coordinate `section_y` to `state`,
+    /// adding the section if it is missing. Returns an error if the section is outside
+    /// of [Chunk::height_range].
+    pub fn fill_section<T: std::borrow::Borrow<BlockState>>(&mut self, block_registry: &mut BlockRegistry, section_y: i8, state: T) -> McResult<()> {
+        let id = block_registry.register(state);
+        self.section_for_write(section_y as i64 * 16)?.fill(id);
+        Ok(())
     }
 
-    pub fn set_skylight(&mut self, coord: (i64, i64, i64), level: u8) -> u8 {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].set_skylight(x, y, z, level)
+    /// Removes all light data and unsets `isLightOn` so that the game
+    /// recomputes the light of this chunk when it is loaded.
+    pub fn clear_light(&mut self) {
+        self.sections.sections.iter_mut().for_each(|section| {
+            if section.skylight.is_some() || section.blocklight.is_some() {
+                section.mark_modified();
+            }
+            section.skylight = None;
+            section.blocklight = None;
+        });
+        self.other.insert("isLightOn".to_owned(), Tag::Byte(0));
     }
 
-    pub fn get_id(&self, coord: (i64, i64, i64)) -> Option<u32> {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].get_id(x, y, z)
+    /// Removes the payloads selected by `options`, leaving the blocks, biomes, block entities,
+    /// and structures intact. Lighting is marked for the game to recompute (see [Chunk::clear_light]).
+    /// Ticks and post-processing are not regenerated, so removing them stops any pending
+    /// updates (which is the point when a chunk is stuck in a tick storm).
+    pub fn strip(&mut self, options: StripOptions) {
+        if options.light {
+            self.clear_light();
+            self.lights = None;
+        }
+        if options.post_processing {
+            self.post_processing = ListTag::Empty;
+        }
+        if options.ticks {
+            self.fluid_ticks = ListTag::Empty;
+            self.block_ticks = ListTag::Empty;
+        }
+        if options.entities {
+            self.entities = None;
+        }
     }
 
-    pub fn set_id(&mut self, coord: (i64, i64, i64), id: u32) -> Option<u32> {
-        let (section_index, (x, y, z)) = self.section_index_and_local_coord(coord);
-        self.sections.sections[section_index].set_id(x, y, z, id)
+    /// Gets the block id at `coord`. Returns `None` if the section is missing or has no block data.
+    pub fn get_id<C: Into<BlockPos>>(&self, coord: C) -> Option<u32> {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        self.sections.section_for_y(coord.y)?.get_id(x, y, z)
+    }
+
+    /// Sets the block id at `coord`, returning the old id.
+    /// Missing sections within [Chunk::height_range] are added.
+    pub fn set_id<C: Into<BlockPos>>(&mut self, coord: C, id: u32) -> McResult<Option<u32>> {
+        let coord: BlockPos = coord.into();
+        self.debug_check_coord(coord);
+        let (x, y, z) = coord.section_local();
+        Ok(self.section_for_write(coord.y)?.set_id(x, y, z, id))
+    }
+
+    /// Like [Chunk::get_id], but returns [McError::CoordOutOfChunk] if `coord` is not in this chunk.
+    pub fn get_id_checked<C: Into<BlockPos>>(&self, coord: C) -> McResult<Option<u32>> {
+        let coord = self.check_coord(coord)?;
+        Ok(self.get_id(coord))
+    }
+
+    /// Like [Chunk::set_id], but returns [McError::CoordOutOfChunk] if `coord` is not in this chunk.
+    pub fn set_id_checked<C: Into<BlockPos>>(&mut self, coord: C, id: u32) -> McResult<Option<u32>> {
+        let coord = self.check_coord(coord)?;
+        self.set_id(coord, id)
     }
 
     pub fn to_nbt(&self, block_registry: &BlockRegistry) -> Tag {
         Tag::Compound(encode_chunk(block_registry, self))
     }
 
-    #[allow(unused)]
+    /// Creates an empty chunk with no sections at chunk coordinate (`x`, `z`), whose lowest
+    /// section is at section Y `y` (`yPos`, -4 for a 1.18+ overworld). The chunk is
+    /// [Chunk::DATA_VERSION] and `minecraft:full`, with heightmaps of zeroes.
     pub fn new(x: i32, y: i32, z: i32) -> Self {
-        // I'm not entirely sure how I am supposed to structure the
-        // fields of the chunk for an empty chunk. I guess I should
-        // do some more work researching the structure of chunks.
-        // Also, for simplicity, this library will target some
-        // specific version of minecraft. Let's say, 1.19.
-        // I don't think it would be advisable to attempt to target
-        // a wide range of data versions.
-        todo!()
+        let mut chunk = Self::with_height(x, z, WorldHeight::OVERWORLD);
+        chunk.y = y;
+        chunk
     }
 
-    pub fn get_heightmap(&self, heightmap: HeightmapFlag, x: i64, z: i64) -> i64 {
-        match heightmap {
-            HeightmapFlag::MotionBlocking => self.heightmaps.motion_blocking.get((x, z)),
-            HeightmapFlag::MotionBlockingNoLeaves => self.heightmaps.motion_blocking_no_leaves.get((x, z)),
-            HeightmapFlag::OceanFloor => self.heightmaps.ocean_floor.get((x, z)),
-            HeightmapFlag::WorldSurface => self.heightmaps.world_surface.get((x, z)),
+    /// Creates an empty chunk like [Chunk::new] for a dimension of the given height, with
+    /// `yPos` at its lowest section and heightmaps wide enough for it.
+    pub fn with_height(x: i32, z: i32, height: WorldHeight) -> Self {
+        let heightmap = || Heightmap::new(height.height);
+        Self {
+            data_version: Self::DATA_VERSION,
+            x,
+            y: height.min_section(),
+            z,
+            height: height.height,
+            last_update: 0,
+            status: "minecraft:full".to_owned(),
+            sections: ChunkSections { sections: Vec::new() },
+            block_entities: Vec::new(),
+            heightmaps: Heightmaps {
+                motion_blocking: Some(heightmap()),
+                motion_blocking_no_leaves: Some(heightmap()),
+                ocean_floor: Some(heightmap()),
+                ocean_floor_wg: None,
+                world_surface: Some(heightmap()),
+                world_surface_wg: None,
+                other: Map::new(),
+            },
+            fluid_ticks: ListTag::Empty,
+            block_ticks: ListTag::Empty,
+            inhabited_time: 0,
+            post_processing: ListTag::Empty,
+            structures: Map::new(),
+            carving_masks: None,
+            lights: None,
+            entities: None,
+            other: Map::new(),
+            key_order: None,
         }
     }
 
-    pub fn set_heightmap(&mut self, heightmap: HeightmapFlag, x: i64, z: i64, height: u16) {
-        match heightmap {
-            HeightmapFlag::MotionBlocking => self.heightmaps.motion_blocking.set((x, z), height),
-            HeightmapFlag::MotionBlockingNoLeaves => self.heightmaps.motion_blocking_no_leaves.set((x, z), height),
-            HeightmapFlag::OceanFloor => self.heightmaps.ocean_floor.set((x, z), height),
-            HeightmapFlag::WorldSurface => self.heightmaps.world_surface.set((x, z), height),
-        }
+    /// Gets a height from a heightmap. Returns `None` if the chunk doesn't have the heightmap.
+    pub fn get_heightmap(&self, heightmap: HeightmapFlag, x: i64, z: i64) -> Option<i64> {
+        self.heightmaps.get(heightmap).map(|heightmap| heightmap.get((x, z)))
+    }
+
+    /// Sets a height in a heightmap, adding an empty heightmap for this chunk's height if it is missing.
+    pub fn set_heightmap(&mut self, heightmap: HeightmapFlag, x: i64, z: i64, height: u16) -> McResult<()> {
+        let chunk_height = self.height;
+        self.heightmaps.get_mut(heightmap)
+            .get_or_insert_with(|| Heightmap::new(chunk_height))
+            .set((x, z), height)
     }
 }
 
@@ -272,26 +474,14 @@ impl Into<Vec<u8>> for Lighting {
 
 impl Lighting {
     pub fn get(&self, x: i64, y: i64, z: i64) -> u8 {
-        let index = chunk_yzx_index(x, y, z);
-        let half_index = index.div_euclid(2);
-        let bit_offset = index.rem_euclid(2) * 4;
-        let level = self.levels[half_index];
-        (level & (0xf << bit_offset)) >> bit_offset
+        get_nibble(&self.levels, chunk_yzx_index(x, y, z))
     }
 
     pub fn set(&mut self, x: i64, y: i64, z: i64, level: u8) -> u8 {
         if level > 15 {
             panic!("level must be less than 16.")
         }
-        let index = chunk_yzx_index(x, y, z);
-        let half_index = index.div_euclid(2);
-        let bit_offset = index.rem_euclid(2) * 4;
-        let mask = 0xf << bit_offset;
-        let inv_mask = mask.not();
-        let old_level = self.levels[half_index];
-        let new_level = (old_level & inv_mask) | (level << bit_offset);
-        self.levels[half_index] = new_level;
-        (old_level & mask) >> bit_offset
+        set_nibble(&mut self.levels, chunk_yzx_index(x, y, z), level)
     }
 }
 
@@ -311,17 +501,97 @@ impl EncodeNbt for Lighting {
     }
 }
 
+/// The block ids of a [ChunkSection].
+#[derive(Clone)]
+pub enum SectionBlocks {
+    /// Every block in the section has the same id. This is stored as a
+    /// single-entry palette without a data array.
+    Uniform(u32),
+    /// One id per block, in YZX order.
+    Ids(Box<[u32]>),
+}
+
+impl SectionBlocks {
+    pub fn get(&self, index: usize) -> u32 {
+        match self {
+            SectionBlocks::Uniform(id) => *id,
+            SectionBlocks::Ids(ids) => ids[index],
+        }
+    }
+
+    /// Sets the id at `index`, returning the old id. A uniform section is
+    /// only expanded to 4096 ids if the new id is different.
+    pub fn set(&mut self, index: usize, id: u32) -> u32 {
+        if let SectionBlocks::Uniform(old) = *self {
+            if old == id {
+                return old;
+            }
+            *self = SectionBlocks::Ids(vec![old; 4096].into_boxed_slice());
+        }
+        let SectionBlocks::Ids(ids) = self else {
+            unreachable!()
+        };
+        std::mem::replace(&mut ids[index], id)
+    }
+}
+
+/// A section as it was read by [decode_chunk_preserving].
+#[derive(Clone)]
+pub struct OriginalSection {
+    pub map: Map,
+    /// Set when the section is changed. A modified section is re-encoded, but
+    /// keeps the unknown keys and key order of [OriginalSection::map].
+    pub modified: bool,
+}
+
 #[derive(Clone)]
 pub struct ChunkSection {
     pub y: i8,
-    pub blocks: Option<Box<[u32]>>,
+    pub blocks: Option<SectionBlocks>,
     pub biomes: Option<Map>,
     pub skylight: Option<Lighting>,
     pub blocklight: Option<Lighting>,
+    /// The section as it was read, if it was read by [decode_chunk_preserving].
+    /// An unmodified section is written back exactly as it was read.
+    pub original: Option<OriginalSection>,
+    /// All other unknown tags, such as those added by mods.
+    pub other: Map,
 }
 
 impl ChunkSection {
 
+    /// Creates a section with no block, biome, or light data.
+    pub fn empty(y: i8) -> Self {
+        Self {
+            y,
+            blocks: None,
+            biomes: None,
+            skylight: None,
+            blocklight: None,
+            original: None,
+            other: Map::new(),
+        }
+    }
+
+    /// The tags that the decoder doesn't know about, such as those added by mods.
+    /// They are written back as they were read.
+    pub fn extras(&self) -> &Map {
+        &self.other
+    }
+
+    /// Changing the extras of a section read by [decode_chunk_preserving] needs [ChunkSection::mark_modified].
+    pub fn extras_mut(&mut self) -> &mut Map {
+        &mut self.other
+    }
+
+    /// Marks the section as changed, so that it is re-encoded instead of written back as it was read.
+    /// The methods that change the section do this; call it after changing the fields directly.
+    pub fn mark_modified(&mut self) {
+        if let Some(original) = &mut self.original {
+            original.modified = true;
+        }
+    }
+
     pub fn skylight(&self, x: i64, y: i64, z: i64) -> u8 {
         if let Some(light) = &self.skylight {
             light.get(x, y, z)
@@ -331,6 +601,7 @@ impl ChunkSection {
     }
 
     pub fn set_skylight(&mut self, x: i64, y: i64, z: i64, level: u8) -> u8 {
+        self.mark_modified();
         if let Some(light) = &mut self.skylight {
             light.set(x, y, z, level)
         } else {
@@ -347,6 +618,7 @@ impl ChunkSection {
     }
 
     pub fn set_blocklight(&mut self, x: i64, y: i64, z: i64, level: u8) -> u8 {
+        self.mark_modified();
         if let Some(light) = &mut self.blocklight {
             light.set(x, y, z, level)
         } else {
@@ -357,7 +629,7 @@ impl ChunkSection {
     pub fn get_id(&self, local_x: i64, local_y: i64, local_z: i64) -> Option<u32> {
         if let Some(blocks) = &self.blocks {
             let index = chunk_yzx_index(local_x, local_y, local_z);
-            Some(blocks[index])
+            Some(blocks.get(index))
         } else {
             None
         }
@@ -365,15 +637,78 @@ impl ChunkSection {
 
     pub fn set_id(&mut self, local_x: i64, local_y: i64, local_z: i64, id: u32) -> Option<u32> {
         if self.blocks.is_none() && id != 0 {
-            self.blocks = Some(Box::new([0u32; 4096]));
+            self.blocks = Some(SectionBlocks::Uniform(0));
         }
         let Some(blocks) = &mut self.blocks else {
             return None;
         };
         let index = chunk_yzx_index(local_x, local_y, local_z);
-        let result = blocks[index];
-        blocks[index] = id;
-        Some(result)
+        let old = blocks.set(index, id);
+        if old != id {
+            self.mark_modified();
+        }
+        Some(old)
+    }
+
+    /// Gets the name of the biome at a local block coordinate.
+    /// Biomes are stored in 4x4x4 cells.
+    pub fn biome_at(&self, local_x: i64, local_y: i64, local_z: i64) -> Option<&str> {
+        let biomes = self.biomes.as_ref()?;
+        let Some(Tag::List(ListTag::String(palette))) = biomes.get("palette") else {
+            return None;
+        };
+        let index = match biomes.get("data") {
+            Some(Tag::LongArray(data)) => {
+                let cell = (((local_y & 15) >> 2) << 4) | (((local_z & 15) >> 2) << 2) | ((local_x & 15) >> 2);
+                get_packed(data, palette_bits(palette.len(), 1), cell as usize) as usize
+            }
+            _ => 0,
+        };
+        palette.get(index).map(String::as_str)
+    }
+
+    /// Sets every block in the section to `state_id`. This is stored as a
+    /// single-entry palette, the same way the game stores uniform sections.
+    pub fn fill(&mut self, state_id: u32) {
+        self.mark_modified();
+        self.blocks = Some(SectionBlocks::Uniform(state_id));
+    }
+
+    /// The ids of the distinct blocks in the section and the number of each, in the order
+    /// that they first appear. A uniform section is a single entry. Empty if the section
+    /// has no block data.
+    pub fn palette_ids(&self) -> Vec<(u32, usize)> {
+        match &self.blocks {
+            None => Vec::new(),
+            Some(SectionBlocks::Uniform(id)) => vec![(*id, 4096)],
+            Some(SectionBlocks::Ids(ids)) => {
+                let mut indices = HashMap::new();
+                let mut counts = Vec::<(u32, usize)>::new();
+                for &id in ids.iter() {
+                    let index = *indices.entry(id).or_insert_with(|| {
+                        counts.push((id, 0));
+                        counts.len() - 1
+                    });
+                    counts[index].1 += 1;
+                }
+                counts
+            }
+        }
+    }
+
+    /// The distinct block states in the section and the number of each (see [ChunkSection::palette_ids]).
+    /// Ids that aren't in `block_registry` are left out.
+    pub fn palette_counts(&self, block_registry: &BlockRegistry) -> Vec<(BlockState, usize)> {
+        self.palette_ids().into_iter()
+            .filter_map(|(id, count)| Some((block_registry.get_owned(id)?, count)))
+            .collect()
+    }
+
+    /// The distinct block states in the section (see [ChunkSection::palette_counts]).
+    pub fn palette(&self, block_registry: &BlockRegistry) -> Vec<BlockState> {
+        self.palette_ids().into_iter()
+            .filter_map(|(id, _)| block_registry.get_owned(id))
+            .collect()
     }
 }
 
@@ -382,6 +717,37 @@ pub struct ChunkSections {
     pub sections: Vec<ChunkSection>,
 }
 
+impl ChunkSections {
+    /// Gets the section that contains the block Y coordinate `y`.
+    pub fn section_for_y(&self, y: i64) -> Option<&ChunkSection> {
+        let section_y = y.div_euclid(16);
+        self.sections.iter().find(|section| section.y as i64 == section_y)
+    }
+
+    /// Gets the section that contains the block Y coordinate `y`.
+    pub fn section_for_y_mut(&mut self, y: i64) -> Option<&mut ChunkSection> {
+        let section_y = y.div_euclid(16);
+        self.sections.iter_mut().find(|section| section.y as i64 == section_y)
+    }
+
+    /// Gets the section at section Y coordinate `section_y`, inserting an empty
+    /// section (keeping the sections ordered by Y) if it does not exist.
+    pub fn get_or_insert(&mut self, section_y: i8) -> &mut ChunkSection {
+        let index = match self.sections.iter().position(|section| section.y >= section_y) {
+            Some(index) if self.sections[index].y == section_y => index,
+            Some(index) => {
+                self.sections.insert(index, ChunkSection::empty(section_y));
+                index
+            }
+            None => {
+                self.sections.push(ChunkSection::empty(section_y));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index]
+    }
+}
+
 #[derive(Clone)]
 pub struct BlockEntity {
     pub id: String,
@@ -389,42 +755,92 @@ pub struct BlockEntity {
     pub x: i32,
     pub y: i32,
     pub z: i32,
+    /// Every other tag of the block entity, including any added by mods.
     pub data: Map,
 }
 
+impl BlockEntity {
+    /// The tags other than `id`, `keepPacked`, and the position. These are written back as they were read.
+    pub fn extras(&self) -> &Map {
+        &self.data
+    }
+
+    pub fn extras_mut(&mut self) -> &mut Map {
+        &mut self.data
+    }
+
+    /// Decodes the `components` of the block entity (1.20.5+), if it has any.
+    pub fn components(&self) -> McResult<Option<Components>> {
+        self.data.get("components").cloned().map(Components::decode_nbt).transpose()
+    }
+
+    /// Sets the `components` of the block entity, removing them if `components` is empty.
+    pub fn set_components(&mut self, components: Components) {
+        if components.is_empty() {
+            self.data.remove("components");
+        } else {
+            self.data.insert("components".to_owned(), components.encode_nbt());
+        }
+    }
+}
+
+/// A heightmap of the 16x16 columns of a chunk. Heights are stored relative to
+/// the bottom of the world, using as many bits as are needed to store the height
+/// of the world (9 bits for heights up to 511).
 #[derive(Clone)]
 pub struct Heightmap {
-    pub map: Vec<i64>
+    pub map: PackedArray,
 }
 
 impl Heightmap {
+    /// The number of bits needed for each entry of a heightmap for a world `height` blocks tall.
+    pub fn bits_for_height(height: u32) -> u32 {
+        // Heights range from 0 to `height` inclusive.
+        height.bit_length().max(1)
+    }
+
+    /// Creates a heightmap of zeroes for a world `height` blocks tall.
+    pub fn new(height: u32) -> Self {
+        Self {
+            map: PackedArray::new(Self::bits_for_height(height), 256),
+        }
+    }
+
+    /// Wraps packed heightmap data. The bit width is derived from the number of longs,
+    /// since it is the only width that produces that many longs for 256 entries.
+    pub fn from_longs(data: Vec<i64>) -> McResult<Self> {
+        let bits = (1..=32).find(|&bits| long_count(bits, 256) == data.len())
+            .ok_or(McError::NbtDecodeError)?;
+        Ok(Self {
+            map: PackedArray::from_longs(bits, 256, data)?,
+        })
+    }
+
+    /// The number of bits used for each entry.
+    pub fn bits(&self) -> u32 {
+        self.map.bits()
+    }
+
     pub fn get(&self, coord: (i64, i64)) -> i64 {
-        let index = (coord.1 * 16 + coord.0) as usize;
-        let sub_index = index / 7;
-        let mask_offset = (index % 7) * 9;
-        let mask = 511 << mask_offset;
-        (self.map[sub_index] & mask) >> mask_offset
+        let index = ((coord.1 & 15) * 16 + (coord.0 & 15)) as usize;
+        self.map.get(index) as i64
     }
 
-    /// Height must be less than 512. Will panic otherwise.
-    pub fn set(&mut self, coord: (i64, i64), height: u16) {
-        if height > 511 {
-            panic!("Height should not be greater than 511.");
+    /// Sets the height of a column. Returns an error if the height doesn't fit in [Heightmap::bits].
+    pub fn set(&mut self, coord: (i64, i64), height: u16) -> McResult<()> {
+        if height as u64 > self.map.max_value() {
+            return Err(McError::OutOfRange);
         }
-        let index = (coord.1 * 16 + coord.0) as usize;
-        let sub_index = index / 7;
-        let mask_offset = (index % 7) * 9;
-        let mask = 511i64 << mask_offset;
-        let value = (height & 511) as i64;
-        let invert_mask = mask.not();
-        self.map[sub_index] = (self.map[sub_index] & invert_mask) | (value << mask_offset)
+        let index = ((coord.1 & 15) * 16 + (coord.0 & 15)) as usize;
+        self.map.set(index, height as u64);
+        Ok(())
     }
 }
 
 impl DecodeNbt for Heightmap {
     fn decode_nbt(nbt: Tag) -> McResult<Self> {
         if let Tag::LongArray(data) = nbt {
-            Ok(Heightmap::from(data))
+            Heightmap::from_longs(data)
         } else {
             Err(McError::NbtDecodeError)
         }
@@ -433,49 +849,74 @@ impl DecodeNbt for Heightmap {
 
 impl EncodeNbt for Heightmap {
     fn encode_nbt(self) -> Tag {
-        Tag::LongArray(self.map)
+        Tag::LongArray(self.map.into_longs())
     }
 }
 
-impl From<Vec<i64>> for Heightmap {
-    fn from(value: Vec<i64>) -> Self {
-        Self { map: value }
+impl TryFrom<Vec<i64>> for Heightmap {
+    type Error = McError;
+
+    fn try_from(value: Vec<i64>) -> Result<Self, Self::Error> {
+        Heightmap::from_longs(value)
     }
 }
 
-impl Into<Vec<i64>> for Heightmap {
-    fn into(self) -> Vec<i64> {
-        self.map
+impl From<Heightmap> for Vec<i64> {
+    fn from(value: Heightmap) -> Self {
+        value.map.into_longs()
     }
 }
 
+/// The heightmaps of a chunk. Any of them can be missing: chunks that aren't fully generated
+/// don't have them all, and the game computes the missing ones when the chunk is loaded.
 #[derive(Clone)]
 pub struct Heightmaps {
-    pub motion_blocking: Heightmap,
-    pub motion_blocking_no_leaves: Heightmap,
-    pub ocean_floor: Heightmap,
+    pub motion_blocking: Option<Heightmap>,
+    pub motion_blocking_no_leaves: Option<Heightmap>,
+    pub ocean_floor: Option<Heightmap>,
     pub ocean_floor_wg: Option<Heightmap>,
-    pub world_surface: Heightmap,
+    pub world_surface: Option<Heightmap>,
     pub world_surface_wg: Option<Heightmap>,
+    /// Any other heightmaps, such as those added by mods.
+    pub other: Map,
+}
+
+impl Heightmaps {
+    pub fn get(&self, heightmap: HeightmapFlag) -> Option<&Heightmap> {
+        match heightmap {
+            HeightmapFlag::MotionBlocking => self.motion_blocking.as_ref(),
+            HeightmapFlag::MotionBlockingNoLeaves => self.motion_blocking_no_leaves.as_ref(),
+            HeightmapFlag::OceanFloor => self.ocean_floor.as_ref(),
+            HeightmapFlag::WorldSurface => self.world_surface.as_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self, heightmap: HeightmapFlag) -> &mut Option<Heightmap> {
+        match heightmap {
+            HeightmapFlag::MotionBlocking => &mut self.motion_blocking,
+            HeightmapFlag::MotionBlockingNoLeaves => &mut self.motion_blocking_no_leaves,
+            HeightmapFlag::OceanFloor => &mut self.ocean_floor,
+            HeightmapFlag::WorldSurface => &mut self.world_surface,
+        }
+    }
 }
 
 impl EncodeNbt for Heightmaps {
     fn encode_nbt(self) -> Tag {
         let mut map = Map::new();
-        map_encoder!(map;
-            "MOTION_BLOCKING" = self.motion_blocking;
-            "MOTION_BLOCKING_NO_LEAVES" = self.motion_blocking_no_leaves;
-            "OCEAN_FLOOR" = self.ocean_floor;
-            // "OCEAN_FLOOR_WG" = self.ocean_floor_wg;
-            "WORLD_SURFACE" = self.world_surface;
-            // "WORLD_SURFACE_WG" = self.world_surface_wg;
-        );
-        if let Some(ofwg) = self.ocean_floor_wg {
-            map_encoder!(map; "OCEAN_FLOOR_WG" = ofwg);
-        }
-        if let Some(wswg) = self.world_surface_wg {
-            map_encoder!(map; "WORLD_SURFACE_WG" = wswg);
-        }
+        [
+            ("MOTION_BLOCKING", self.motion_blocking),
+            ("MOTION_BLOCKING_NO_LEAVES", self.motion_blocking_no_leaves),
+            ("OCEAN_FLOOR", self.ocean_floor),
+            ("WORLD_SURFACE", self.world_surface),
+            ("OCEAN_FLOOR_WG", self.ocean_floor_wg),
+            ("WORLD_SURFACE_WG", self.world_surface_wg),
+        ].into_iter().for_each(|(name, heightmap)| {
+            if let Some(heightmap) = heightmap {
+                map.insert(name.to_owned(), heightmap.encode_nbt());
+            }
+        });
+        map.extend(self.other);
         Tag::Compound(map)
     }
 }
@@ -486,12 +927,13 @@ impl DecodeNbt for Heightmaps {
             return Err(McError::NbtDecodeError);
         };
         Ok(Heightmaps {
-            motion_blocking: map_decoder!(map; "MOTION_BLOCKING" -> Heightmap),
-            motion_blocking_no_leaves: map_decoder!(map; "MOTION_BLOCKING_NO_LEAVES" -> Heightmap),
-            ocean_floor: map_decoder!(map; "OCEAN_FLOOR" -> Heightmap),
+            motion_blocking: map_decoder!(map; "MOTION_BLOCKING" -> Option<Heightmap>),
+            motion_blocking_no_leaves: map_decoder!(map; "MOTION_BLOCKING_NO_LEAVES" -> Option<Heightmap>),
+            ocean_floor: map_decoder!(map; "OCEAN_FLOOR" -> Option<Heightmap>),
             ocean_floor_wg: map_decoder!(map; "OCEAN_FLOOR_WG" -> Option<Heightmap>),
-            world_surface: map_decoder!(map; "WORLD_SURFACE" -> Heightmap),
+            world_surface: map_decoder!(map; "WORLD_SURFACE" -> Option<Heightmap>),
             world_surface_wg: map_decoder!(map; "WORLD_SURFACE_WG" -> Option<Heightmap>),
+            other: map,
         })
     }
 }
@@ -528,56 +970,13 @@ impl DecodeNbt for CarvingMasks {
 }
 
 #[inline(always)]
-fn chunk_local_coord(coord: (i64, i64, i64)) -> (i64, i64, i64) {
-    (
-        coord.0 & 0xf,
-        coord.1 & 0xf,
-        coord.2 & 0xf,
-    )
-}
-
-#[inline(always)]
-const fn chunk_section_index(coord_y: i64, chunk_y: i64) -> usize {
-    let section_index = coord_y.div_euclid(16);
-    let adj_index = section_index - chunk_y;
-    adj_index as usize
-}
-
-#[inline(always)]
-fn chunk_yzx_index(x: i64, y: i64, z: i64) -> usize {
+pub(crate) fn chunk_yzx_index(x: i64, y: i64, z: i64) -> usize {
     let local_x = x & 0xf;
     let local_y = y & 0xf;
     let local_z = z & 0xf;
     ((local_y<<8) | (local_z<<4) | local_x) as usize
 }
 
-pub fn extract_palette_index(index: usize, palette_size: usize, states: &[i64]) -> usize {
-    // Subtract 1 because it's the bit length of the largest possible index
-    // If the palette size is 16, the bit length to represent
-    // 16 is 5, but the bit length to represent the largest index (15)
-    // is only 4.
-    let bitsize = (palette_size - 1).bit_length().max(4);
-    // vpl: values-per-long
-    let vpl = (64 / bitsize) as u64;
-    let mask = 2u64.pow(bitsize) - 1;
-    let state_index = index as u64 / vpl;
-    let value_offset = ((index as u64).rem_euclid(vpl)) as u32 * bitsize;
-    let slot = states[state_index as usize] as u64;
-    ((slot & (mask << value_offset)) >> value_offset) as usize
-}
-
-fn inject_palette_index(full_index: usize, palette_size: usize, states: &mut [i64], value: u32) {
-    let bitsize = (palette_size - 1).bit_length().max(4);
-    // vpl: values-per-long
-    let vpl = (64 / bitsize) as u64;
-    let mask = 2u64.pow(bitsize) - 1;
-    let state_index = full_index as u64 / vpl;
-    let value_offset = ((full_index as u64).rem_euclid(vpl) as u32) * bitsize;
-    let state = states[state_index as usize] as u64;
-    let new_value = (state & (mask << value_offset).not()) | ((value as u64) << value_offset);
-    states[state_index as usize] = new_value as i64;
-}
-
 pub fn decode_palette(palette: ListTag) -> Result<Vec<BlockState>, McError> {
     let ListTag::Compound(states) = palette else {
         return Err(McError::NbtDecodeError);
@@ -587,7 +986,47 @@ pub fn decode_palette(palette: ListTag) -> Result<Vec<BlockState>, McError> {
     }).collect::<Result<Vec<BlockState>, McError>>()
 }
 
-pub fn decode_section(block_registry: &mut BlockRegistry, mut section: Map) -> Result<ChunkSection, McError> {
+/// Reads the palette of a section's `block_states` compound as it is stored,
+/// without unpacking its data.
+pub fn read_palette(block_states: &Map) -> McResult<Vec<BlockState>> {
+    let Some(Tag::List(ListTag::Compound(states))) = block_states.get("palette") else {
+        return Err(McError::NbtDecodeError);
+    };
+    states.iter().map(BlockState::try_from_map).collect()
+}
+
+/// Reads the palette of a section's `block_states` compound with the number of blocks of each
+/// entry, by counting the packed palette indices (packed with `packing`) without registering
+/// anything. This is a quick way to find which blocks are in a raw chunk tag.
+pub fn read_palette_counts(block_states: &Map, packing: Packing) -> McResult<Vec<(BlockState, usize)>> {
+    let palette = read_palette(block_states)?;
+    let mut counts = vec![0usize; palette.len()];
+    match block_states.get("data") {
+        Some(Tag::LongArray(data)) => {
+            let bits = palette_bits(palette.len(), 4);
+            if data.len() < packing.long_count(bits, 4096) {
+                return Err(McError::NbtDecodeError);
+            }
+            for index in 0..4096 {
+                let entry = counts.get_mut(packing.get(data, bits, index) as usize).ok_or(McError::NbtDecodeError)?;
+                *entry += 1;
+            }
+        }
+        // Without a data array, every block is the first entry in the palette.
+        _ => if let Some(first) = counts.first_mut() {
+            *first = 4096;
+        },
+    }
+    Ok(palette.into_iter().zip(counts).collect())
+}
+
+pub fn decode_section(block_registry: &mut BlockRegistry, section: Map) -> Result<ChunkSection, McError> {
+    decode_section_with_packing(block_registry, section, Packing::Aligned)
+}
+
+/// Decodes a section whose block states were packed with `packing`.
+/// (See [Packing::for_data_version].)
+pub fn decode_section_with_packing(block_registry: &mut BlockRegistry, mut section: Map, packing: Packing) -> Result<ChunkSection, McError> {
     let y = map_decoder!(section; "Y" -> Byte);
     // The following three may or may not exist.
     let biomes = map_decoder!(section; "biomes" -> Option<Map>);
@@ -607,12 +1046,16 @@ pub fn decode_section(block_registry: &mut BlockRegistry, mut section: Map) -> R
         let palette = palette.iter().map(|state| {
             block_registry.register(state)
         }).collect::<Vec<u32>>();
-        map_decoder!(block_states; "data" -> Option<LongArray>).map(|blocks| {
-            (0..4096).into_iter().map(|full_index| {
-                let index = extract_palette_index(full_index, palette.len(), &blocks);
-                palette[index]
-            }).collect::<Box<[u32]>>()
-        })
+        match map_decoder!(block_states; "data" -> Option<LongArray>) {
+            Some(blocks) => Some(SectionBlocks::Ids(
+                (0..4096).map(|full_index| {
+                    let index = packing.get(&blocks, palette_bits(palette.len(), 4), full_index);
+                    palette[index as usize]
+                }).collect::<Box<[u32]>>()
+            )),
+            // Without a data array, every block is the first entry in the palette.
+            None => palette.first().copied().map(SectionBlocks::Uniform),
+        }
     } else {
         None
     };
@@ -622,29 +1065,61 @@ pub fn decode_section(block_registry: &mut BlockRegistry, mut section: Map) -> R
         blocklight,
         skylight,
         blocks,
+        original: None,
+        other: section,
     })
 }
 
 pub fn decode_chunk(block_registry: &mut BlockRegistry, nbt: Tag) -> McResult<Chunk> {
+    decode_chunk_with(block_registry, nbt, false)
+}
+
+/// Decodes a chunk, keeping what is needed to write it back with as few differences
+/// from the original as possible: the original map of every section (see [ChunkSection::original])
+/// and the order of the chunk's keys (see [Chunk::key_order]). Sections that aren't changed
+/// are written back as they were read, including keys that the decoder doesn't know about.
+pub fn decode_chunk_preserving(block_registry: &mut BlockRegistry, nbt: Tag) -> McResult<Chunk> {
+    decode_chunk_with(block_registry, nbt, true)
+}
+
+fn decode_chunk_with(block_registry: &mut BlockRegistry, nbt: Tag, preserve: bool) -> McResult<Chunk> {
     let Tag::Compound(mut map) = nbt else {
         return Err(McError::NbtDecodeError);
     };
+    let key_order = preserve.then(|| map.keys().cloned().collect::<Vec<String>>());
+    let data_version = map_decoder!(map; "DataVersion" -> i32);
+    let packing = Packing::for_data_version(data_version);
     let sections = if let ListTag::Compound(sections) = map_decoder!(map; "sections" -> ListTag) {
         sections.into_iter()
-            .map(|section| decode_section(block_registry, section))
+            .map(|section| {
+                let original = preserve.then(|| OriginalSection {
+                    map: section.clone(),
+                    modified: false,
+                });
+                Ok(ChunkSection {
+                    original,
+                    ..decode_section_with_packing(block_registry, section, packing)?
+                })
+            })
             .collect::<McResult<Vec<ChunkSection>>>()?
     } else {
         return Err(McError::NbtDecodeError);
     };
+    let y = map_decoder!(map; "yPos" -> i32);
+    let top_section = sections.iter()
+        .filter(|section| section.blocks.is_some())
+        .map(|section| section.y as i32)
+        .max();
     let sections = ChunkSections {
         sections,
     };
     Ok(Chunk {
         sections,
-        data_version: map_decoder!(map; "DataVersion" -> i32),
+        data_version,
         x: map_decoder!(map; "xPos" -> i32),
-        y: map_decoder!(map; "yPos" -> i32),
+        y,
         z: map_decoder!(map; "zPos" -> i32),
+        height: WorldHeight::infer(y, top_section).height,
         last_update: map_decoder!(map; "LastUpdate" -> i64),
         block_entities: map_decoder!(map; "block_entities" -> Vec<BlockEntity>),
         heightmaps: map_decoder!(map; "Heightmaps" -> Heightmaps),
@@ -658,11 +1133,20 @@ pub fn decode_chunk(block_registry: &mut BlockRegistry, nbt: Tag) -> McResult<Ch
         lights: map_decoder!(map; "Lights" -> Option<ListTag>),
         entities: map_decoder!(map; "Entities" -> Option<ListTag>),
         other: map,
+        key_order,
     })
 }
 
-fn encode_block_states(block_registry: &BlockRegistry, blocks: &Option<Box<[u32]>>) -> Map {
-    if let Some(blocks) = blocks {
+fn encode_block_states(block_registry: &BlockRegistry, blocks: &Option<SectionBlocks>, packing: Packing) -> Map {
+    if let Some(SectionBlocks::Uniform(id)) = blocks {
+        if let Some(state) = block_registry.get(*id) {
+            let palette = ListTag::Compound(vec![state.clone().to_nbt()]);
+            return Map::from([
+                ("palette".to_owned(), Tag::List(palette)),
+            ]);
+        }
+    }
+    if let Some(SectionBlocks::Ids(blocks)) = blocks {
         // Collect unique block-ids
         // local_registry holds the mapping from old ids to new ids.
         // This procedure maps out the block-states used into a palette and remaps
@@ -686,28 +1170,16 @@ fn encode_block_states(block_registry: &BlockRegistry, blocks: &Option<Box<[u32]
             }
         }).collect::<Vec<u32>>();
         // Pack 4096 block ids into array of i64.
-        // The buffer size for the long_array is calculated based on
-        // palette size.
-        // `palette.len() - 1`: The `- 1` is because The bitsize is the bit_length of
-        //	the maximum index, which is the same as the length of the palette minus 1.
-        let bitsize = (palette.len() - 1).bit_length().max(4);
-        // vpl: values-per-long
-        let vpl = (64 / bitsize) as u64;
-        // (4096u64.rem_euclid(vpl) != 0 as u64)
-        // The buffer needs to be able to hold 4096 (16*16*16) elements.
-        // To find the packed buffer size, you simply divide 4096 by vpl, and if
-        // there is a remainder, add one.
-        let buffer_size = 4096/vpl + ((4096u64.rem_euclid(vpl) != 0) as u64);
-        let mut packed = vec![0i64; buffer_size as usize];
+        let mut packed = PackedArray::with_packing(palette_bits(palette.len(), 4), 4096, packing);
         local_ids.into_iter().enumerate().for_each(|(i, id)| {
-            inject_palette_index(i, palette.len(), &mut packed, id);
+            packed.set(i, id as u64);
         });
         // Build palette
         let palette = palette.into_iter().map(|state| {
             state.to_nbt()
         }).collect::<Vec<Map>>();
         let palette = Tag::List(ListTag::Compound(palette));
-        let data = Tag::LongArray(packed);
+        let data = Tag::LongArray(packed.into_longs());
         Map::from([
             ("palette".to_owned(), palette),
             ("data".to_owned(), data),
@@ -723,7 +1195,28 @@ fn encode_block_states(block_registry: &BlockRegistry, blocks: &Option<Box<[u32]
     }
 }
 
-fn encode_section(block_registry: &BlockRegistry, section: &ChunkSection) -> Map {
+fn encode_section(block_registry: &BlockRegistry, section: &ChunkSection, packing: Packing) -> Map {
+    match &section.original {
+        Some(original) if !original.modified => original.map.clone(),
+        Some(original) => {
+            // Keep the original key order, with new keys last.
+            let mut encoded = rebuild_section(block_registry, section, packing);
+            let mut map = Map::new();
+            original.map.keys().for_each(|key| {
+                if let Some(value) = encoded.remove(key) {
+                    map.insert(key.clone(), value);
+                }
+            });
+            let mut rest = encoded.into_iter().collect::<Vec<_>>();
+            rest.sort_by(|(a, _), (b, _)| a.cmp(b));
+            map.extend(rest);
+            map
+        }
+        None => rebuild_section(block_registry, section, packing),
+    }
+}
+
+fn rebuild_section(block_registry: &BlockRegistry, section: &ChunkSection, packing: Packing) -> Map {
     let mut map = Map::new();
     map_encoder!(map; "Y" = section.y);
     if let Some(biomes) = &section.biomes {
@@ -738,8 +1231,9 @@ fn encode_section(block_registry: &BlockRegistry, section: &ChunkSection) -> Map
         let skylight = skylight.clone();
         map_encoder!(map; "SkyLight" = skylight);
     }
-    let block_states = encode_block_states(block_registry, &section.blocks);
+    let block_states = encode_block_states(block_registry, &section.blocks, packing);
     map_encoder!(map; "block_states" = block_states);
+    map.extend(section.other.clone());
     map
 }
 
@@ -785,19 +1279,378 @@ pub fn encode_chunk(block_registry: &BlockRegistry, chunk: &Chunk) -> Map {
         let entities = entities.clone();
         map_encoder!(map; "Entities" = entities);
     }
+    let packing = Packing::for_data_version(chunk.data_version);
     let sections = ListTag::Compound(chunk.sections.sections.iter().map(|section| {
-        encode_section(block_registry, section)
+        encode_section(block_registry, section, packing)
     }).collect::<Vec<Map>>());
     map_encoder!(map; "sections" = sections);
     if !chunk.other.is_empty() {
         map.extend(chunk.other.clone());
     }
+    if let Some(order) = &chunk.key_order {
+        // Keys that weren't in the original go last, in the order they were encoded.
+        let mut entries = map.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| order.iter().position(|original| original == key).unwrap_or(usize::MAX));
+        return entries.into_iter().collect();
+    }
     map
 }
 
+/// A [Chunk] and the [BlockRegistry] that its block IDs refer to. This is [Writable], so
+/// a chunk can be written straight to a region file with
+/// [RegionFile::write_data](super::io::region::RegionFile::write_data).
+#[derive(Clone, Copy)]
+pub struct ChunkIo<'a> {
+    pub registry: &'a BlockRegistry,
+    pub chunk: &'a Chunk,
+}
+
+impl<'a> ChunkIo<'a> {
+    pub fn new(registry: &'a BlockRegistry, chunk: &'a Chunk) -> Self {
+        Self { registry, chunk }
+    }
+
+    /// Reads a chunk, registering its block states in `registry`.
+    pub fn read<R: Read>(registry: &mut BlockRegistry, reader: &mut R) -> McResult<Chunk> {
+        let root = NamedTag::read_from(reader)?;
+        decode_chunk(registry, root.take_tag())
+    }
+}
+
+impl Writable for ChunkIo<'_> {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        NamedTag::new(Tag::Compound(encode_chunk(self.registry, self.chunk))).write_to(writer)
+    }
+}
+
+/// A [Chunk] that was read with its own [BlockRegistry]. This is [Readable], so a chunk
+/// can be read straight from a region file with
+/// [RegionFile::read_data](super::io::region::RegionFile::read_data).
+#[derive(Clone)]
+pub struct OwnedChunk {
+    pub registry: BlockRegistry,
+    pub chunk: Chunk,
+}
+
+impl OwnedChunk {
+    /// Borrows the chunk and registry for writing.
+    pub fn as_io(&self) -> ChunkIo<'_> {
+        ChunkIo::new(&self.registry, &self.chunk)
+    }
+}
+
+impl Readable for OwnedChunk {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        let mut registry = BlockRegistry::with_air();
+        let chunk = ChunkIo::read(&mut registry, reader)?;
+        Ok(Self { registry, chunk })
+    }
+}
+
 /*
 TODO: 	Make it so that chunks can be loaded directly from memory.
         This would involve more complicated programming, but it would
         give faster load times. I also need to make it so that there
         is a World block registry to register blocks to.
-*/
\ No newline at end of file
+*/
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Packs heights the way the game does (entries never span longs).
+    fn vanilla_pack(heights: &[u64], bits: u32) -> Vec<i64> {
+        let per_long = 64 / bits as usize;
+        let mut longs = vec![0i64; heights.len().div_ceil(per_long)];
+        heights.iter().enumerate().for_each(|(i, &height)| {
+            longs[i / per_long] |= (height << ((i % per_long) as u32 * bits)) as i64;
+        });
+        longs
+    }
+
+    /// An empty chunk with no sections.
+    pub(crate) fn empty_chunk(x: i32, y: i32, z: i32) -> Chunk {
+        Chunk::new(x, y, z)
+    }
+
+    #[test]
+    fn strip_test() -> McResult<()> {
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).skylight = Some(Lighting::from(vec![0u8; 2048]));
+        chunk.set_skylight((0, 0, 0), 15)?;
+        chunk.block_ticks = ListTag::Compound(vec![Map::new()]);
+        chunk.fluid_ticks = ListTag::Compound(vec![Map::new()]);
+        chunk.entities = Some(ListTag::Compound(vec![Map::new()]));
+        chunk.strip(StripOptions { ticks: true, ..Default::default() });
+        assert!(matches!(chunk.block_ticks, ListTag::Empty) && matches!(chunk.fluid_ticks, ListTag::Empty));
+        assert!(chunk.entities.is_some());
+        assert_eq!(chunk.skylight((0, 0, 0)), 15);
+        chunk.strip(StripOptions::ALL);
+        assert!(chunk.entities.is_none());
+        assert!(chunk.sections.sections.iter().all(|section| section.skylight.is_none()));
+        assert!(matches!(chunk.other.get("isLightOn"), Some(Tag::Byte(0))));
+        Ok(())
+    }
+
+    #[test]
+    fn sections_test() -> McResult<()> {
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(3);
+        chunk.sections.get_or_insert(-4);
+        chunk.sections.get_or_insert(0).fill(1);
+        chunk.sections.get_or_insert(-1);
+        chunk.sections.get_or_insert(3);
+        let ys = chunk.sections.sections.iter().map(|section| section.y).collect::<Vec<_>>();
+        assert_eq!(ys, [-4, -1, 0, 3]);
+        assert_eq!(chunk.sections.section_for_y(-1).map(|section| section.y), Some(-1));
+        assert_eq!(chunk.sections.section_for_y(-16).map(|section| section.y), Some(-1));
+        assert_eq!(chunk.sections.section_for_y(-17).map(|section| section.y), None);
+        assert_eq!(chunk.sections.section_for_y(15).map(|section| section.palette_ids()), Some(vec![(1, 4096)]));
+        assert!(chunk.sections.section_for_y(16).is_none());
+
+        assert!(matches!(chunk.set_id((0, -65, 0), 1), Err(McError::YOutOfRange(-65))));
+        assert!(matches!(chunk.set_id((0, 320, 0), 1), Err(McError::YOutOfRange(320))));
+        chunk.set_id((0, 319, 0), 1)?;
+        assert_eq!(chunk.sections.sections.last().map(|section| section.y), Some(19));
+        Ok(())
+    }
+
+    #[test]
+    fn decoded_height_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut nether = Chunk::with_height(0, 0, WorldHeight::LEGACY);
+        nether.set_id((0, 0, 0), stone)?;
+        let encoded = encode_chunk(&registry, &nether);
+        let mut nether = decode_chunk(&mut registry, Tag::Compound(encoded))?;
+        assert_eq!(nether.height_range(), 0..256);
+        nether.set_id((0, 255, 0), stone)?;
+        assert!(matches!(nether.set_id((0, 256, 0), stone), Err(McError::YOutOfRange(256))));
+        let encoded = encode_chunk(&registry, &empty_chunk(0, -4, 0));
+        let overworld = decode_chunk(&mut registry, Tag::Compound(encoded))?;
+        assert_eq!(overworld.height_range(), -64..320);
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_io_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(3, -4, 5);
+        chunk.set_id((50, 10, 81), stone)?;
+        let mut region = crate::world::io::region::RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        region.write_data((3, 5), &ChunkIo::new(&registry, &chunk))?;
+        let read: OwnedChunk = region.read_data((3, 5))?;
+        assert_eq!((read.chunk.x, read.chunk.z), (3, 5));
+        let id = read.chunk.get_id((50, 10, 81));
+        assert_eq!(id.and_then(|id| read.registry.get(id)).map(BlockState::name), Some("minecraft:stone"));
+        region.write_data((4, 5), &read.as_io())?;
+        let mut registry = BlockRegistry::with_air();
+        let chunk = region.read((4, 5), |mut decoder| ChunkIo::read(&mut registry, &mut decoder))?;
+        assert_eq!(chunk.get_id((50, 10, 81)).and_then(|id| registry.get(id)).map(BlockState::name), Some("minecraft:stone"));
+        Ok(())
+    }
+
+    #[test]
+    fn preserving_roundtrip_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).fill(0);
+        chunk.sections.get_or_insert(1).fill(0);
+        let mut map = encode_chunk(&registry, &chunk);
+        let Some(Tag::List(ListTag::Compound(sections))) = map.get_mut("sections") else { panic!("Expected sections.") };
+        for section in sections.iter_mut() {
+            section.insert("extra".to_owned(), Tag::Int(7));
+            // An unused palette entry, which re-encoding would drop.
+            let Some(Tag::Compound(states)) = section.get_mut("block_states") else { panic!("Expected block_states.") };
+            let Some(Tag::List(ListTag::Compound(palette))) = states.get_mut("palette") else { panic!("Expected a palette.") };
+            palette.push(BlockState::from("minecraft:stone").to_nbt());
+        }
+        let mut chunk = decode_chunk_preserving(&mut registry, Tag::Compound(map))?;
+        chunk.set_id((0, 16, 0), stone)?;
+        let map = encode_chunk(&registry, &chunk);
+        let Some(Tag::List(ListTag::Compound(sections))) = map.get("sections") else { panic!("Expected sections.") };
+        let palette_len = |section: &Map| match section.get("block_states") {
+            Some(Tag::Compound(states)) => match states.get("palette") {
+                Some(Tag::List(ListTag::Compound(palette))) => palette.len(),
+                _ => 0,
+            },
+            _ => 0,
+        };
+        assert!(sections.iter().all(|section| matches!(section.get("extra"), Some(Tag::Int(7)))));
+        assert_eq!(palette_len(&sections[0]), 2);
+        assert_eq!(palette_len(&sections[1]), 2);
+        assert!(matches!(sections[1].get("block_states"), Some(Tag::Compound(states)) if states.contains_key("data")));
+        Ok(())
+    }
+
+    #[test]
+    fn modded_chunk_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).fill(stone);
+        chunk.block_entities.push(BlockEntity {
+            id: "examplemod:machine".to_owned(),
+            keep_packed: 0,
+            x: 1,
+            y: 2,
+            z: 3,
+            data: Map::new(),
+        });
+        // What Forge and Fabric add to a chunk.
+        let caps = Tag::Compound(Map::from([("examplemod:energy".to_owned(), Tag::Int(100))]));
+        let mut map = encode_chunk(&registry, &chunk);
+        map.insert("ForgeCaps".to_owned(), caps.clone());
+        map.insert("fabric:attachments".to_owned(), Tag::Compound(Map::new()));
+        let Some(Tag::List(ListTag::Compound(sections))) = map.get_mut("sections") else { panic!("Expected sections.") };
+        sections[0].insert("examplemod:ores".to_owned(), Tag::LongArray(vec![1, 2, 3]));
+        let Some(Tag::List(ListTag::Compound(entities))) = map.get_mut("block_entities") else { panic!("Expected block entities.") };
+        entities[0].insert("ForgeData".to_owned(), caps.clone());
+        let Some(Tag::Compound(heightmaps)) = map.get_mut("Heightmaps") else { panic!("Expected heightmaps.") };
+        heightmaps.insert("EXAMPLEMOD_SURFACE".to_owned(), Tag::LongArray(vec![0; 37]));
+        let keys = |map: &Map| {
+            let mut keys = map.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        for preserve in [false, true] {
+            let mut chunk = decode_chunk_with(&mut registry, Tag::Compound(map.clone()), preserve)?;
+            assert!(matches!(chunk.extras().get("ForgeCaps"), Some(Tag::Compound(caps)) if caps.len() == 1));
+            assert!(chunk.extras().contains_key("fabric:attachments"));
+            assert!(chunk.sections.sections[0].extras().contains_key("examplemod:ores"));
+            assert!(chunk.block_entities[0].extras().contains_key("ForgeData"));
+            assert!(chunk.heightmaps.other.contains_key("EXAMPLEMOD_SURFACE"));
+            // Modified sections keep their extras too.
+            chunk.set_id((0, 0, 0), 0)?;
+            let encoded = encode_chunk(&registry, &chunk);
+            assert_eq!(keys(&encoded), keys(&map));
+            let (Some(Tag::List(ListTag::Compound(a))), Some(Tag::List(ListTag::Compound(b)))) = (encoded.get("sections"), map.get("sections")) else { panic!("Expected sections.") };
+            assert_eq!(keys(&a[0]), keys(&b[0]));
+            assert!(matches!(a[0].get("examplemod:ores"), Some(Tag::LongArray(ores)) if ores == &[1, 2, 3]));
+            let (Some(Tag::List(ListTag::Compound(a))), Some(Tag::List(ListTag::Compound(b)))) = (encoded.get("block_entities"), map.get("block_entities")) else { panic!("Expected block entities.") };
+            assert_eq!(keys(&a[0]), keys(&b[0]));
+            let (Some(Tag::Compound(a)), Some(Tag::Compound(b))) = (encoded.get("Heightmaps"), map.get("Heightmaps")) else { panic!("Expected heightmaps.") };
+            assert_eq!(keys(a), keys(b));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn checked_coord_test() -> McResult<()> {
+        let mut chunk = empty_chunk(2, -4, -1);
+        assert_eq!(chunk.set_id_checked((33, 0, -16), 5)?, Some(0));
+        assert_eq!(chunk.get_id_checked((33, 0, -16))?, Some(5));
+        assert!(matches!(
+            chunk.set_id_checked((1, 0, -16), 5),
+            Err(McError::CoordOutOfChunk(..))
+        ));
+        assert!(chunk.get_id_checked((33, 0, 0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn heightmap_roundtrip_test() -> McResult<()> {
+        // 1.18+ overworld: 384 blocks tall, 9 bits per entry, 37 longs.
+        let heights = (0..256u64).map(|i| (i * 3) % 385).collect::<Vec<_>>();
+        let longs = vanilla_pack(&heights, 9);
+        assert_eq!(longs.len(), 37);
+        let mut heightmap = Heightmap::decode_nbt(Tag::LongArray(longs.clone()))?;
+        assert_eq!(heightmap.bits(), Heightmap::bits_for_height(384));
+        assert!((0..256).all(|i| heightmap.get((i % 16, i / 16)) == heights[i as usize] as i64));
+        heightmap.set((15, 15), 384)?;
+        assert!(heightmap.set((0, 0), 512).is_err());
+        let Tag::LongArray(encoded) = heightmap.encode_nbt() else { panic!("Expected LongArray.") };
+        let mut expected = heights.clone();
+        expected[255] = 384;
+        assert_eq!(encoded, vanilla_pack(&expected, 9));
+        Ok(())
+    }
+
+    #[test]
+    fn heightmap_extended_height_test() -> McResult<()> {
+        // A datapack dimension 2032 blocks tall needs 11 bits per entry.
+        let mut heightmap = Heightmap::new(2032);
+        assert_eq!(heightmap.bits(), 11);
+        heightmap.set((3, 7), 2032)?;
+        let longs: Vec<i64> = heightmap.into();
+        assert_eq!(longs.len(), 52);
+        assert_eq!(Heightmap::from_longs(longs)?.get((3, 7)), 2032);
+        Ok(())
+    }
+
+    #[test]
+    fn estimated_nbt_size_test() -> McResult<()> {
+        use crate::{nbt::io::NbtWrite, world::io::region::{check_nbt_size, estimated_sectors, RegionFile}};
+        let dir = tempfile::tempdir()?;
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).fill(stone);
+        let size = chunk.estimated_nbt_size(&registry);
+        let root = NamedTag::new(Tag::Compound(encode_chunk(&registry, &chunk)));
+        let mut bytes = Vec::new();
+        root.nbt_write(&mut bytes)?;
+        assert_eq!(size, bytes.len());
+        let sector = RegionFile::create(dir.path().join("r.0.0.mca"))?.write_data((0, 0), &root)?;
+        assert!(estimated_sectors(size).contains(&(sector.sector_count() as u32)));
+        assert!(check_nbt_size(size)?);
+        assert!(!check_nbt_size(2 << 20)?);
+        assert!(matches!(check_nbt_size(2 << 30), Err(McError::RegionDataTooLarge)));
+        Ok(())
+    }
+
+    #[test]
+    fn fill_section_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.fill_section(&mut registry, 2, BlockState::from("minecraft:stone"))?;
+        let map = encode_chunk(&registry, &chunk);
+        let Some(Tag::List(ListTag::Compound(sections))) = map.get("sections") else { panic!("Expected sections.") };
+        let section = sections.iter().find(|section| matches!(section.get("Y"), Some(Tag::Byte(2)))).expect("Expected section 2.");
+        let Some(Tag::Compound(states)) = section.get("block_states") else { panic!("Expected block_states.") };
+        assert!(matches!(states.get("palette"), Some(Tag::List(ListTag::Compound(palette))) if palette.len() == 1));
+        assert!(match states.get("data") {
+            None => true,
+            Some(Tag::LongArray(data)) => data.is_empty(),
+            _ => false,
+        });
+
+        let mut registry = BlockRegistry::with_air();
+        let chunk = decode_chunk(&mut registry, Tag::Compound(map))?;
+        let section = chunk.sections.section_for_y(32).expect("Expected section 2.");
+        assert!(matches!(section.blocks, Some(SectionBlocks::Uniform(_))));
+        for coord in [(0, 32, 0), (15, 47, 15), (7, 40, 3)] {
+            assert_eq!(chunk.get_id(coord).and_then(|id| registry.get(id)).map(BlockState::name), Some("minecraft:stone"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn palette_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let dirt = registry.register(BlockState::from("minecraft:dirt"));
+        let mut section = ChunkSection::empty(0);
+        assert!(section.palette(&registry).is_empty());
+        section.fill(stone);
+        assert_eq!(section.palette_ids(), [(stone, 4096)]);
+        section.set_id(0, 0, 0, dirt);
+        section.set_id(1, 0, 0, 0);
+        assert_eq!(section.palette_ids(), [(dirt, 1), (0, 1), (stone, 4094)]);
+        let names = section.palette(&registry).iter().map(|state| state.name().to_owned()).collect::<Vec<_>>();
+        assert_eq!(names, ["minecraft:dirt", "minecraft:air", "minecraft:stone"]);
+
+        let Some(Tag::Compound(block_states)) = rebuild_section(&registry, &section, Packing::Aligned).remove("block_states") else {
+            panic!("missing block_states");
+        };
+        let counts = read_palette_counts(&block_states, Packing::Aligned)?;
+        let counts = counts.iter().map(|(state, count)| (state.name(), *count)).collect::<Vec<_>>();
+        assert_eq!(counts, [("minecraft:dirt", 1), ("minecraft:air", 1), ("minecraft:stone", 4094)]);
+        let uniform = encode_block_states(&registry, &Some(SectionBlocks::Uniform(stone)), Packing::Aligned);
+        assert_eq!(read_palette_counts(&uniform, Packing::Aligned)?.iter().map(|(state, count)| (state.name(), *count)).collect::<Vec<_>>(), [("minecraft:stone", 4096)]);
+        Ok(())
+    }
+}
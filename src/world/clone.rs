@@ -0,0 +1,366 @@
+//! Copying an area of a world to another place in the same world, like the
+//! `/clone` command, but for chunks that aren't loaded by the game.
+//!
+//! Blocks and block entities are copied (replacing what was at the destination),
+//! and entities whose position is in the area are copied with fresh UUIDs so that
+//! the game doesn't discard them as duplicates. Entities at the destination are left
+//! alone. The destination chunks have their light cleared and their heightmaps removed,
+//! so that the game recomputes both when the chunks are loaded.
+//!
+//! Every chunk that the source or destination touches must already exist.
+//! The changes are committed with a [WorldTransaction], so either every
+//! destination chunk is written or none are.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use glam::{DVec3, I64Vec3};
+
+use crate::{
+    McError, McResult,
+    math::{
+        bounds::Bounds3,
+        coord::{BlockPos, ChunkPos, Dimension},
+    },
+    nbt::{
+        Map,
+        tag::{EncodeNbt, ListTag, NamedTag, Tag},
+    },
+    util::{
+        uuid::{random, read_uuid, write_uuid, write_uuid_legacy},
+        versions::VersionFeatures,
+    },
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    chunk::{Chunk, Heightmap, Heightmaps, decode_chunk, encode_chunk},
+    io::region::RegionFile,
+    scan::{RegionKind, region_file_path},
+    search::read_position,
+    transaction::WorldTransaction,
+};
+
+/// What [clone_area] copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CloneStats {
+    pub blocks: u64,
+    pub block_entities: usize,
+    pub entities: usize,
+}
+
+/// Reads chunks from region files, keeping each region file open.
+struct ChunkReader<'a> {
+    world_directory: &'a Path,
+    dimension: Dimension,
+    regions: HashMap<PathBuf, RegionFile>,
+}
+
+impl<'a> ChunkReader<'a> {
+    fn read(&mut self, kind: RegionKind, chunk: ChunkPos) -> McResult<Option<NamedTag>> {
+        let path = region_file_path(self.world_directory, chunk.in_dimension(self.dimension).region_coord(), kind)?;
+        if !self.regions.contains_key(&path) {
+            if !path.is_file() {
+                return Ok(None);
+            }
+            self.regions.insert(path.clone(), RegionFile::open(&path)?);
+        }
+        let region = self.regions.get_mut(&path).expect("The region file was just opened.");
+        let coord = chunk.region_local();
+        if region.get_sector(coord).is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(region.read_data(coord)?))
+    }
+}
+
+/// The entities of a chunk, wherever they are stored.
+enum EntityStore {
+    /// In the chunk (before 1.17).
+    Terrain,
+    /// In an `entities/` region file (1.17+). The chunk is created if it doesn't exist.
+    File(Map),
+}
+
+fn entities_mut<'a>(store: &'a mut EntityStore, chunk: &'a mut Chunk) -> &'a mut ListTag {
+    let list = match store {
+        EntityStore::Terrain => chunk.entities.get_or_insert(ListTag::Empty),
+        EntityStore::File(root) => {
+            let entities = root.entry("Entities".to_owned()).or_insert(Tag::List(ListTag::Empty));
+            if !matches!(entities, Tag::List(_)) {
+                *entities = Tag::List(ListTag::Empty);
+            }
+            let Tag::List(list) = entities else { unreachable!() };
+            list
+        }
+    };
+    if matches!(list, ListTag::Empty) {
+        *list = ListTag::Compound(Vec::new());
+    }
+    list
+}
+
+fn entity_list(store: &EntityStore, chunk: &Chunk) -> Vec<Map> {
+    let list = match store {
+        EntityStore::Terrain => chunk.entities.as_ref(),
+        EntityStore::File(root) => match root.get("Entities") {
+            Some(Tag::List(list)) => Some(list),
+            _ => None,
+        },
+    };
+    match list {
+        Some(ListTag::Compound(entities)) => entities.clone(),
+        _ => Vec::new(),
+    }
+}
+
+/// Moves an entity (and its passengers) by `offset` and gives them fresh UUIDs.
+fn move_entity(entity: &mut Map, offset: I64Vec3) {
+    if let Some(Tag::List(ListTag::Double(pos))) = entity.get_mut("Pos") {
+        if let [x, y, z] = pos.as_mut_slice() {
+            *x += offset.x as f64;
+            *y += offset.y as f64;
+            *z += offset.z as f64;
+        }
+    }
+    // Hanging entities (paintings, item frames) also store the block that they hang on.
+    [("TileX", offset.x), ("TileY", offset.y), ("TileZ", offset.z)].into_iter().for_each(|(key, offset)| {
+        if let Some(Tag::Int(value)) = entity.get_mut(key) {
+            *value += offset as i32;
+        }
+    });
+    if let Some(Tag::IntArray(pos)) = entity.get_mut("block_pos") {
+        if let [x, y, z] = pos.as_mut_slice() {
+            *x += offset.x as i32;
+            *y += offset.y as i32;
+            *z += offset.z as i32;
+        }
+    }
+    if entity.contains_key("UUIDMost") {
+        write_uuid_legacy(entity, "UUID", random());
+    } else if read_uuid(entity, "UUID").is_some() {
+        write_uuid(entity, "UUID", random());
+    }
+    if let Some(Tag::List(ListTag::Compound(passengers))) = entity.get_mut("Passengers") {
+        passengers.iter_mut().for_each(|passenger| move_entity(passenger, offset));
+    }
+}
+
+/// Placeholder heightmaps for chunks that don't have any.
+fn empty_heightmaps() -> Tag {
+    // 9 bits per entry, which covers every world height up to 511.
+    let heightmap = || Heightmap::new(384);
+    Heightmaps {
+        motion_blocking: Some(heightmap()),
+        motion_blocking_no_leaves: Some(heightmap()),
+        ocean_floor: Some(heightmap()),
+        ocean_floor_wg: None,
+        world_surface: Some(heightmap()),
+        world_surface_wg: None,
+        other: Map::new(),
+    }.encode_nbt()
+}
+
+/// Determines if the entity position `pos` is inside the blocks of `bounds`.
+fn contains_position(bounds: &Bounds3, pos: DVec3) -> bool {
+    bounds.contains((pos.x.floor() as i64, pos.y.floor() as i64, pos.z.floor() as i64))
+}
+
+/// Copies the blocks, block entities, and entities in `source` (in `dimension`) so that
+/// the lowest corner of `source` is at `destination`. The source and destination may overlap.
+pub fn clone_area<P: AsRef<Path>, C: Into<BlockPos>>(world_directory: P, dimension: Dimension, source: Bounds3, destination: C) -> McResult<CloneStats> {
+    let world_directory = world_directory.as_ref();
+    let destination: BlockPos = destination.into();
+    let offset = I64Vec3::new(destination.x, destination.y, destination.z) - source.min;
+    let target = Bounds3 { min: source.min + offset, max: source.max + offset };
+    let chunk_positions = |bounds: &Bounds3| bounds.chunk_bounds()
+        .iter()
+        .map(|pos| ChunkPos::new(pos.x, pos.y))
+        .collect::<Vec<_>>();
+    let source_chunks = chunk_positions(&source);
+    let target_chunks = chunk_positions(&target);
+
+    let mut reader = ChunkReader { world_directory, dimension, regions: HashMap::new() };
+    let mut registry = BlockRegistry::with_air();
+    let mut chunks = BTreeMap::<ChunkPos, Chunk>::new();
+    let mut stores = BTreeMap::<ChunkPos, EntityStore>::new();
+    for &pos in source_chunks.iter().chain(target_chunks.iter()) {
+        if chunks.contains_key(&pos) {
+            continue;
+        }
+        let root = reader.read(RegionKind::Terrain, pos)?
+            .ok_or(McError::ChunkNotFound(pos.in_dimension(dimension)))?;
+        let mut root = root.take_tag();
+        if let Tag::Compound(map) = &mut root {
+            // Heightmaps that were removed by an earlier clone. They are removed again when the chunk is written.
+            map.entry("Heightmaps".to_owned()).or_insert_with(empty_heightmaps);
+        }
+        let chunk = decode_chunk(&mut registry, root)?;
+        let store = if VersionFeatures::for_data_version(chunk.data_version).entities_files {
+            let root = match reader.read(RegionKind::Entities, pos)? {
+                Some(root) => match root.take_tag() {
+                    Tag::Compound(root) => root,
+                    _ => return Err(McError::NbtDecodeError),
+                },
+                None => Map::from([
+                    ("DataVersion".to_owned(), Tag::Int(chunk.data_version)),
+                    ("Position".to_owned(), Tag::IntArray(vec![pos.x as i32, pos.z as i32])),
+                ]),
+            };
+            EntityStore::File(root)
+        } else {
+            EntityStore::Terrain
+        };
+        chunks.insert(pos, chunk);
+        stores.insert(pos, store);
+    }
+    drop(reader);
+
+    // Read everything first, so that overlapping areas copy what was there before.
+    let air = registry.register(super::blockstate::BlockState::air());
+    let blocks = source.iter(Default::default())
+        .map(|pos| {
+            let pos = BlockPos::from(pos);
+            let id = chunks[&pos.chunk()].get_id(pos).unwrap_or(air);
+            (pos, id)
+        })
+        .collect::<Vec<_>>();
+    let block_entities = source_chunks.iter()
+        .flat_map(|pos| chunks[pos].block_entities.iter())
+        .filter(|entity| source.contains((entity.x as i64, entity.y as i64, entity.z as i64)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let entities = source_chunks.iter()
+        .flat_map(|pos| entity_list(&stores[pos], &chunks[pos]))
+        .filter(|entity| read_position(entity).is_some_and(|pos| contains_position(&source, pos)))
+        .collect::<Vec<_>>();
+
+    let mut stats = CloneStats {
+        blocks: blocks.len() as u64,
+        block_entities: block_entities.len(),
+        entities: entities.len(),
+    };
+    for (pos, id) in blocks {
+        let target = BlockPos::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z);
+        chunks.get_mut(&target.chunk()).expect("Target chunks are loaded.").set_id(target, id)?;
+    }
+    target_chunks.iter().for_each(|pos| {
+        let chunk = chunks.get_mut(pos).expect("Target chunks are loaded.");
+        chunk.block_entities.retain(|entity| !target.contains((entity.x as i64, entity.y as i64, entity.z as i64)));
+    });
+    for mut entity in block_entities {
+        entity.x += offset.x as i32;
+        entity.y += offset.y as i32;
+        entity.z += offset.z as i32;
+        let pos = BlockPos::new(entity.x as i64, entity.y as i64, entity.z as i64).chunk();
+        chunks.get_mut(&pos).expect("Target chunks are loaded.").block_entities.push(entity);
+    }
+    let mut changed_stores = Vec::new();
+    for mut entity in entities {
+        move_entity(&mut entity, offset);
+        let Some(position) = read_position(&entity) else { continue };
+        let pos = BlockPos::new(position.x.floor() as i64, 0, position.z.floor() as i64).chunk();
+        let (Some(chunk), Some(store)) = (chunks.get_mut(&pos), stores.get_mut(&pos)) else {
+            // The entity hangs over the edge of the destination, into a chunk that isn't loaded.
+            stats.entities -= 1;
+            continue;
+        };
+        if let ListTag::Compound(list) = entities_mut(store, chunk) {
+            list.push(entity);
+        }
+        changed_stores.push(pos);
+    }
+
+    let mut transaction = WorldTransaction::new(world_directory);
+    for pos in target_chunks.iter() {
+        let chunk = chunks.get_mut(pos).expect("Target chunks are loaded.");
+        chunk.clear_light();
+        let mut root = encode_chunk(&registry, chunk);
+        // The game recomputes heightmaps that are missing.
+        root.remove("Heightmaps");
+        transaction.write_chunk(RegionKind::Terrain, pos.in_dimension(dimension), NamedTag::new(Tag::Compound(root)))?;
+    }
+    changed_stores.sort();
+    changed_stores.dedup();
+    for pos in changed_stores {
+        if let Some(EntityStore::File(root)) = stores.remove(&pos) {
+            transaction.write_chunk(RegionKind::Entities, pos.in_dimension(dimension), NamedTag::new(Tag::Compound(root)))?;
+        } else if !target_chunks.contains(&pos) {
+            // Pre-1.17 entities that landed outside of the target chunks are stored in their chunk.
+            let mut root = encode_chunk(&registry, &chunks[&pos]);
+            root.remove("Heightmaps");
+            transaction.write_chunk(RegionKind::Terrain, pos.in_dimension(dimension), NamedTag::new(Tag::Compound(root)))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        blockstate::BlockState,
+        chunk::{BlockEntity, tests::empty_chunk},
+    };
+
+    #[test]
+    fn clone_area_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        std::fs::create_dir_all(world.join("region"))?;
+        std::fs::create_dir_all(world.join("entities"))?;
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let chest = registry.register(BlockState::from("minecraft:chest"));
+        {
+            let mut region = RegionFile::create(world.join("region/r.0.0.mca"))?;
+            let mut source = empty_chunk(0, -4, 0);
+            source.set_id((1, 64, 1), stone)?;
+            source.set_id((2, 64, 1), chest)?;
+            source.block_entities.push(BlockEntity {
+                id: "minecraft:chest".to_owned(),
+                keep_packed: 0,
+                x: 2,
+                y: 64,
+                z: 1,
+                data: Map::new(),
+            });
+            region.write_data((0, 0), &NamedTag::new(source.to_nbt(&registry)))?;
+            region.write_data((2, 0), &NamedTag::new(empty_chunk(2, -4, 0).to_nbt(&registry)))?;
+            let mut entities = RegionFile::create(world.join("entities/r.0.0.mca"))?;
+            let mut pig = Map::from([
+                ("id".to_owned(), Tag::string("minecraft:pig")),
+                ("Pos".to_owned(), Tag::List(ListTag::Double(vec![1.5, 65.0, 1.5]))),
+            ]);
+            write_uuid(&mut pig, "UUID", 7);
+            entities.write_data((0, 0), &NamedTag::new(Tag::Compound(Map::from([
+                ("DataVersion".to_owned(), Tag::Int(3465)),
+                ("Position".to_owned(), Tag::IntArray(vec![0, 0])),
+                ("Entities".to_owned(), Tag::List(ListTag::Compound(vec![pig]))),
+            ]))))?;
+        }
+
+        let stats = clone_area(world, Dimension::Overworld, Bounds3::new((0, 64, 0), (3, 65, 3)), (32, 64, 0))?;
+        assert_eq!(stats, CloneStats { blocks: 32, block_entities: 1, entities: 1 });
+
+        let mut registry = BlockRegistry::with_air();
+        let mut region = RegionFile::open(world.join("region/r.0.0.mca"))?;
+        let root: NamedTag = region.read_data((2, 0))?;
+        let Tag::Compound(mut map) = root.take_tag() else { panic!("Expected a compound.") };
+        assert!(map.insert("Heightmaps".to_owned(), empty_heightmaps()).is_none());
+        let target = decode_chunk(&mut registry, Tag::Compound(map))?;
+        assert_eq!(target.get_id((33, 64, 1)).map(|id| registry.get(id).unwrap().name().to_owned()), Some("minecraft:stone".to_owned()));
+        assert_eq!(target.block_entities.len(), 1);
+        assert_eq!((target.block_entities[0].x, target.block_entities[0].z), (34, 1));
+
+        let mut entities = RegionFile::open(world.join("entities/r.0.0.mca"))?;
+        let Tag::Compound(root) = entities.read_data::<_, NamedTag>((2, 0))?.take_tag() else { panic!("Expected a compound.") };
+        let Some(Tag::List(ListTag::Compound(list))) = root.get("Entities") else { panic!("Expected entities.") };
+        assert_eq!(read_position(&list[0]), Some(DVec3::new(33.5, 65.0, 1.5)));
+        assert_ne!(read_uuid(&list[0], "UUID"), Some(7));
+        Ok(())
+    }
+}
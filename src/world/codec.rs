@@ -0,0 +1,109 @@
+//! Pluggable chunk models.
+//!
+//! A [ChunkCodec] converts between the root tag of a chunk and some in-memory chunk
+//! type. [Anvil118Codec] is the full [Chunk] model ([decode_chunk]/[encode_chunk]), and
+//! [RawTagCodec] keeps the root tag as is. Other models (such as one that only reads a
+//! chunk's header fields, or one for a modded format) can implement the trait and be used
+//! with [VirtualJavaWorld::with_codec](super::world::VirtualJavaWorld::with_codec) or
+//! [RegionFormatExt::read_chunk_with](super::io::region::RegionFormatExt::read_chunk_with).
+
+use crate::{
+    McResult,
+    nbt::tag::Tag,
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    chunk::{Chunk, decode_chunk, decode_chunk_preserving, encode_chunk},
+};
+
+/// Converts between the root tag of a chunk and an in-memory chunk model.
+pub trait ChunkCodec {
+    type Chunk;
+
+    /// Decodes the root tag of a chunk. Block states are registered in `registry`.
+    fn decode(&self, registry: &mut BlockRegistry, root: Tag) -> McResult<Self::Chunk>;
+
+    /// Encodes a chunk back to its root tag. Block ids are looked up in `registry`.
+    fn encode(&self, registry: &BlockRegistry, chunk: &Self::Chunk) -> McResult<Tag>;
+}
+
+/// The [Chunk] model for 1.18+ Anvil chunks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Anvil118Codec {
+    /// Decode with [decode_chunk_preserving], so that chunks are written back with as
+    /// few differences from the original as possible.
+    pub preserve: bool,
+}
+
+impl Anvil118Codec {
+    pub const fn preserving() -> Self {
+        Self { preserve: true }
+    }
+}
+
+impl ChunkCodec for Anvil118Codec {
+    type Chunk = Chunk;
+
+    fn decode(&self, registry: &mut BlockRegistry, root: Tag) -> McResult<Chunk> {
+        if self.preserve {
+            decode_chunk_preserving(registry, root)
+        } else {
+            decode_chunk(registry, root)
+        }
+    }
+
+    fn encode(&self, registry: &BlockRegistry, chunk: &Chunk) -> McResult<Tag> {
+        Ok(Tag::Compound(encode_chunk(registry, chunk)))
+    }
+}
+
+/// Passes the root tag through untouched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawTagCodec;
+
+impl ChunkCodec for RawTagCodec {
+    type Chunk = Tag;
+
+    fn decode(&self, _: &mut BlockRegistry, root: Tag) -> McResult<Tag> {
+        Ok(root)
+    }
+
+    fn encode(&self, _: &BlockRegistry, chunk: &Tag) -> McResult<Tag> {
+        Ok(chunk.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::tag::NamedTag,
+        world::{
+            blockstate::BlockState,
+            chunk::tests::empty_chunk,
+            io::region::{RegionFile, RegionFormatExt, Timestamp, coord::RegionCoord},
+        },
+    };
+
+    #[test]
+    fn codec_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.set_id((1, 2, 3), stone)?;
+        let coord = RegionCoord::from((0, 0));
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        region.write_chunk_with(coord, &Anvil118Codec::default(), &registry, &chunk, Timestamp::utc_now())?;
+
+        let mut other = BlockRegistry::with_air();
+        let decoded = region.read_chunk_with(coord, &Anvil118Codec::default(), &mut other)?;
+        assert_eq!(decoded.get_id((1, 2, 3)).and_then(|id| other.get(id)).map(BlockState::name), Some("minecraft:stone"));
+        let raw = region.read_chunk_with(coord, &RawTagCodec, &mut other)?;
+        assert!(matches!(&raw, Tag::Compound(map) if map.contains_key("sections")));
+        let root: NamedTag = region.read_chunk(coord)?;
+        assert!(matches!((root.tag(), &raw), (Tag::Compound(a), Tag::Compound(b)) if a.len() == b.len()));
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! Data components, which replaced the `tag` compound of items (and the item NBT
+//! stored in block entities) in Minecraft 1.20.5.
+//!
+//! [Components] has typed fields for the common components and keeps the rest as
+//! they are in [Components::other]. [Components::from_legacy_tag] converts the `tag`
+//! compound of an older item the way the game upgrades it: known keys become
+//! components, and the keys that don't have a component go in `minecraft:custom_data`.
+//! Components are written in the form used by 1.20.5 to 1.21.4.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        tag::{DecodeNbt, EncodeNbt, ListTag, Tag},
+    },
+};
+
+use super::item::ItemStack;
+
+/// Reads an integer that may have been stored as a byte, short, or int.
+pub(crate) fn int_value(tag: &Tag) -> Option<i32> {
+    match tag {
+        Tag::Byte(value) => Some(*value as i32),
+        Tag::Short(value) => Some(*value as i32),
+        Tag::Int(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Adds the `minecraft:` namespace to `id` if it doesn't have one.
+pub(crate) fn namespaced(id: &str) -> String {
+    if id.contains(':') {
+        id.to_owned()
+    } else {
+        format!("minecraft:{id}")
+    }
+}
+
+/// An item in `minecraft:container` (the contents of a shulker box, for example).
+#[derive(Debug, Clone)]
+pub struct ContainerSlot {
+    pub slot: i32,
+    pub item: ItemStack,
+}
+
+/// The data components of an item stack or block entity.
+#[derive(Debug, Clone, Default)]
+pub struct Components {
+    /// `minecraft:damage`
+    pub damage: Option<i32>,
+    /// `minecraft:repair_cost`
+    pub repair_cost: Option<i32>,
+    /// `minecraft:unbreakable`
+    pub unbreakable: bool,
+    /// `minecraft:custom_name`, a JSON text component (or a compound, after 1.21.5).
+    pub custom_name: Option<Tag>,
+    /// `minecraft:lore`, one text component per line.
+    pub lore: Option<Vec<Tag>>,
+    /// `minecraft:enchantments`, enchantment levels by enchantment ID.
+    pub enchantments: Option<BTreeMap<String, i32>>,
+    /// `minecraft:custom_model_data` (when it is a single number, before 1.21.4).
+    pub custom_model_data: Option<i32>,
+    /// `minecraft:container`
+    pub container: Option<Vec<ContainerSlot>>,
+    /// `minecraft:custom_data`, where data that doesn't have a component is kept.
+    pub custom_data: Option<Map>,
+    /// Every other component, as it was read.
+    pub other: Map,
+}
+
+impl Components {
+    /// Returns true if there are no components.
+    pub fn is_empty(&self) -> bool {
+        self.damage.is_none()
+            && self.repair_cost.is_none()
+            && !self.unbreakable
+            && self.custom_name.is_none()
+            && self.lore.is_none()
+            && self.enchantments.is_none()
+            && self.custom_model_data.is_none()
+            && self.container.is_none()
+            && self.custom_data.is_none()
+            && self.other.is_empty()
+    }
+
+    /// Gets a component that doesn't have a typed field, by its namespaced ID.
+    pub fn get(&self, id: &str) -> Option<&Tag> {
+        self.other.get(id)
+    }
+
+    /// Sets a component that doesn't have a typed field, returning the old value.
+    pub fn insert<S: Into<String>, T: Into<Tag>>(&mut self, id: S, value: T) -> Option<Tag> {
+        self.other.insert(id.into(), value.into())
+    }
+
+    /// Converts the `tag` compound of an item from before 1.20.5.
+    pub fn from_legacy_tag(mut tag: Map) -> McResult<Self> {
+        let mut components = Components::default();
+        if let Some(damage) = tag.remove("Damage") {
+            components.damage = int_value(&damage).filter(|&damage| damage != 0);
+        }
+        if let Some(cost) = tag.remove("RepairCost") {
+            components.repair_cost = int_value(&cost).filter(|&cost| cost != 0);
+        }
+        if let Some(unbreakable) = tag.remove("Unbreakable") {
+            components.unbreakable = int_value(&unbreakable).unwrap_or_default() != 0;
+        }
+        if let Some(model) = tag.remove("CustomModelData") {
+            components.custom_model_data = int_value(&model);
+        }
+        if let Some(Tag::List(enchantments)) = tag.remove("Enchantments") {
+            components.enchantments = Some(legacy_enchantments(enchantments));
+        }
+        if let Some(Tag::List(enchantments)) = tag.remove("StoredEnchantments") {
+            components.other.insert("minecraft:stored_enchantments".to_owned(), encode_levels(legacy_enchantments(enchantments)));
+        }
+        if let Some(Tag::Compound(mut display)) = tag.remove("display") {
+            components.custom_name = display.remove("Name");
+            if let Some(Tag::List(ListTag::String(lore))) = display.remove("Lore") {
+                components.lore = Some(lore.into_iter().map(Tag::String).collect());
+            }
+            if let Some(color) = display.remove("color").as_ref().and_then(int_value) {
+                components.other.insert("minecraft:dyed_color".to_owned(), Tag::Compound(Map::from([
+                    ("rgb".to_owned(), Tag::Int(color)),
+                ])));
+            }
+            if !display.is_empty() {
+                tag.insert("display".to_owned(), Tag::Compound(display));
+            }
+        }
+        if let Some(Tag::Compound(mut block_entity)) = tag.remove("BlockEntityTag") {
+            if let Some(Tag::List(ListTag::Compound(items))) = block_entity.remove("Items") {
+                components.container = Some(items.into_iter().map(|mut item| {
+                    let slot = item.remove("Slot").as_ref().and_then(int_value).unwrap_or_default();
+                    Ok(ContainerSlot { slot, item: ItemStack::decode_nbt(Tag::Compound(item))? })
+                }).collect::<McResult<_>>()?);
+            }
+            if !block_entity.is_empty() {
+                components.other.insert("minecraft:block_entity_data".to_owned(), Tag::Compound(block_entity));
+            }
+        }
+        if !tag.is_empty() {
+            components.custom_data = Some(tag);
+        }
+        Ok(components)
+    }
+}
+
+/// Reads a list of `{id, lvl}` compounds.
+fn legacy_enchantments(list: ListTag) -> BTreeMap<String, i32> {
+    let ListTag::Compound(list) = list else {
+        return BTreeMap::new();
+    };
+    list.into_iter()
+        .filter_map(|enchantment| {
+            let Some(Tag::String(id)) = enchantment.get("id") else {
+                return None;
+            };
+            Some((namespaced(id), enchantment.get("lvl").and_then(int_value).unwrap_or(1)))
+        })
+        .collect()
+}
+
+fn encode_levels(levels: BTreeMap<String, i32>) -> Tag {
+    let levels = levels.into_iter()
+        .map(|(id, level)| (id, Tag::Int(level)))
+        .collect::<Map>();
+    Tag::Compound(Map::from([
+        ("levels".to_owned(), Tag::Compound(levels)),
+    ]))
+}
+
+/// Reads enchantment levels, either wrapped in `levels` (before 1.21.5) or not.
+fn decode_levels(tag: Tag) -> McResult<BTreeMap<String, i32>> {
+    let Tag::Compound(mut map) = tag else {
+        return Err(McError::NbtDecodeError);
+    };
+    let levels = match map.remove("levels") {
+        Some(Tag::Compound(levels)) => levels,
+        _ => map,
+    };
+    levels.into_iter()
+        .map(|(id, level)| Ok((id, int_value(&level).ok_or(McError::NbtDecodeError)?)))
+        .collect()
+}
+
+impl DecodeNbt for Components {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        let mut components = Components {
+            damage: map.remove("minecraft:damage").as_ref().and_then(int_value),
+            repair_cost: map.remove("minecraft:repair_cost").as_ref().and_then(int_value),
+            unbreakable: map.remove("minecraft:unbreakable").is_some(),
+            custom_name: map.remove("minecraft:custom_name"),
+            ..Default::default()
+        };
+        if let Some(Tag::List(lore)) = map.remove("minecraft:lore") {
+            components.lore = Some(match lore {
+                ListTag::String(lines) => lines.into_iter().map(Tag::String).collect(),
+                ListTag::Compound(lines) => lines.into_iter().map(Tag::Compound).collect(),
+                _ => Vec::new(),
+            });
+        }
+        if let Some(enchantments) = map.remove("minecraft:enchantments") {
+            components.enchantments = Some(decode_levels(enchantments)?);
+        }
+        // From 1.21.4, custom model data is a compound, which is kept in `other`.
+        match map.remove("minecraft:custom_model_data") {
+            Some(Tag::Int(model)) => components.custom_model_data = Some(model),
+            Some(model) => {
+                map.insert("minecraft:custom_model_data".to_owned(), model);
+            }
+            None => (),
+        }
+        components.container = match map.remove("minecraft:container") {
+            Some(Tag::List(ListTag::Compound(slots))) => Some(slots.into_iter().map(|mut slot| {
+                Ok(ContainerSlot {
+                    slot: slot.remove("slot").as_ref().and_then(int_value).unwrap_or_default(),
+                    item: ItemStack::decode_nbt(slot.remove("item").ok_or(McError::NotFoundInCompound("item".to_owned()))?)?,
+                })
+            }).collect::<McResult<_>>()?),
+            // An empty list.
+            Some(Tag::List(_)) => Some(Vec::new()),
+            _ => None,
+        };
+        if let Some(Tag::Compound(data)) = map.remove("minecraft:custom_data") {
+            components.custom_data = Some(data);
+        }
+        components.other = map;
+        Ok(components)
+    }
+}
+
+impl EncodeNbt for Components {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        if let Some(damage) = self.damage {
+            map.insert("minecraft:damage".to_owned(), Tag::Int(damage));
+        }
+        if let Some(cost) = self.repair_cost {
+            map.insert("minecraft:repair_cost".to_owned(), Tag::Int(cost));
+        }
+        if self.unbreakable {
+            map.insert("minecraft:unbreakable".to_owned(), Tag::Compound(Map::new()));
+        }
+        if let Some(name) = self.custom_name {
+            map.insert("minecraft:custom_name".to_owned(), name);
+        }
+        if let Some(lore) = self.lore {
+            let lore = if lore.iter().all(|line| matches!(line, Tag::String(_))) {
+                ListTag::String(lore.into_iter().filter_map(|line| String::try_from(line).ok()).collect())
+            } else {
+                ListTag::Compound(lore.into_iter().filter_map(|line| Map::try_from(line).ok()).collect())
+            };
+            map.insert("minecraft:lore".to_owned(), Tag::List(lore));
+        }
+        if let Some(enchantments) = self.enchantments {
+            map.insert("minecraft:enchantments".to_owned(), encode_levels(enchantments));
+        }
+        if let Some(model) = self.custom_model_data {
+            map.insert("minecraft:custom_model_data".to_owned(), Tag::Int(model));
+        }
+        if let Some(container) = self.container {
+            let slots = container.into_iter().map(|slot| Map::from([
+                ("slot".to_owned(), Tag::Int(slot.slot)),
+                ("item".to_owned(), slot.item.encode_nbt()),
+            ])).collect::<Vec<_>>();
+            map.insert("minecraft:container".to_owned(), Tag::List(ListTag::Compound(slots)));
+        }
+        if let Some(data) = self.custom_data {
+            map.insert("minecraft:custom_data".to_owned(), Tag::Compound(data));
+        }
+        Tag::Compound(map)
+    }
+}
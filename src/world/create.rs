@@ -0,0 +1,166 @@
+//! Creating new worlds.
+//!
+//! [create_new] writes everything that the game needs to open a world: level.dat (from a
+//! [LevelBuilder]), the region directories, and session.lock. It can also pre-generate a
+//! superflat area around the spawn, so that the world has terrain before it is first opened.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+};
+
+use crate::{
+    McError, McResult,
+    math::coord::WorldCoord,
+};
+
+use super::{
+    block::HeightmapFlag,
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::{Chunk, ChunkIo},
+    height::WorldHeight,
+    io::region::{RegionFile, coord::RegionCoord},
+    level::{FlatLayer, LevelBuilder, write_level_to_file},
+    scan::{RegionKind, region_file_path},
+    session::SessionLock,
+    spawn::level_path,
+};
+
+/// Options for [create_new].
+#[derive(Debug, Clone, Default)]
+pub struct WorldOptions {
+    /// Pre-generates the superflat chunks within this many chunks of the spawn chunk
+    /// (0 for only the spawn chunk). The layers are those of [LevelBuilder::superflat],
+    /// or [FlatLayer::classic] if the world is not superflat.
+    pub pregenerate_radius: Option<u32>,
+    /// Replace the world if `level.dat` already exists in the directory.
+    pub overwrite: bool,
+}
+
+/// Creates a superflat chunk at chunk coordinate (`x`, `z`) of a 1.18+ overworld.
+/// Light is left for the game to compute.
+pub fn flat_chunk(block_registry: &mut BlockRegistry, x: i32, z: i32, layers: &[FlatLayer]) -> McResult<Chunk> {
+    let mut chunk = Chunk::with_height(x, z, WorldHeight::OVERWORLD);
+    let bottom = chunk.height_range().start;
+    let mut y = bottom;
+    for layer in layers {
+        let id = block_registry.register(BlockState::from(layer.block.as_str()));
+        for _ in 0..layer.height {
+            if !chunk.height_range().contains(&y) {
+                return Err(McError::OutOfRange);
+            }
+            for (local_x, local_z) in (0..16).flat_map(|x| (0..16).map(move |z| (x, z))) {
+                chunk.set_id((x as i64 * 16 + local_x, y, z as i64 * 16 + local_z), id)?;
+            }
+            y += 1;
+        }
+    }
+    let height = (y - bottom) as u16;
+    for (local_x, local_z) in (0..16).flat_map(|x| (0..16).map(move |z| (x, z))) {
+        for flag in [HeightmapFlag::MotionBlocking, HeightmapFlag::MotionBlockingNoLeaves, HeightmapFlag::OceanFloor, HeightmapFlag::WorldSurface] {
+            chunk.set_heightmap(flag, local_x, local_z, height)?;
+        }
+    }
+    chunk.clear_light();
+    Ok(chunk)
+}
+
+/// Creates a new world at `directory`: level.dat, the (empty) region directories of each
+/// dimension, and session.lock. Returns [McError::Custom] if there is already a world
+/// there, unless [WorldOptions::overwrite] is set.
+/// Returns the number of chunks that were pre-generated.
+pub fn create_new<P: AsRef<Path>>(directory: P, level: LevelBuilder, options: &WorldOptions) -> McResult<usize> {
+    let directory = directory.as_ref();
+    let level_file = level_path(directory);
+    if level_file.exists() && !options.overwrite {
+        return McError::custom(format!("There is already a world at {}.", directory.display()));
+    }
+    std::fs::create_dir_all(directory)?;
+    // Taking the lock writes session.lock, and keeps the game from opening the world
+    // while it is being created.
+    let _lock = SessionLock::acquire(directory)?;
+    for folder in ["region", "entities", "poi", "data", "DIM-1/region", "DIM1/region"] {
+        std::fs::create_dir_all(directory.join(folder))?;
+    }
+
+    let mut generated = 0;
+    if let Some(radius) = options.pregenerate_radius {
+        let classic = FlatLayer::classic();
+        let layers = level.flat_layers().unwrap_or(&classic);
+        let mut registry = BlockRegistry::with_air();
+        let spawn = level.spawn_pos().chunk();
+        let radius = radius as i64;
+        let mut regions: BTreeMap<WorldCoord, RegionFile> = BTreeMap::new();
+        for x in spawn.x - radius..=spawn.x + radius {
+            for z in spawn.z - radius..=spawn.z + radius {
+                let mut chunk = flat_chunk(&mut registry, x as i32, z as i32, layers)?;
+                chunk.data_version = level.data_version();
+                let coord = WorldCoord::overworld(x, z);
+                let region = match regions.entry(coord.region_coord()) {
+                    std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                    std::collections::btree_map::Entry::Vacant(entry) => {
+                        let path = region_file_path(directory, coord.region_coord(), RegionKind::Terrain)?;
+                        entry.insert(RegionFile::open_or_create(path)?)
+                    }
+                };
+                region.write_data_with_utcnow(RegionCoord::from(coord.xz()), &ChunkIo::new(&registry, &chunk))?;
+                generated += 1;
+            }
+        }
+        regions.into_values().try_for_each(RegionFile::close)?;
+    }
+
+    write_level_to_file(&level_file, &level.build(), flate2::Compression::default())?;
+    Ok(generated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::BlockPos,
+        nbt::tag::NamedTag,
+        world::{
+            chunk::decode_chunk,
+            level::{Difficulty, read_level_from_file},
+        },
+    };
+
+    #[test]
+    fn create_new_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source");
+        create_new(&source, LevelBuilder::new("Source").game_rule("keepInventory", true), &WorldOptions::default())?;
+        let source_level = read_level_from_file(level_path(&source))?;
+        assert_eq!(source_level.game_rule("keepInventory"), Some("true"));
+
+        let world = dir.path().join("world");
+        let builder = LevelBuilder::new("Flat")
+            .difficulty(Difficulty::Peaceful)
+            .game_rules_of(&source_level)
+            .superflat(FlatLayer::classic())
+            .spawn(BlockPos::new(40, -60, 8));
+        let options = WorldOptions { pregenerate_radius: Some(1), overwrite: false };
+        assert_eq!(create_new(&world, builder.clone(), &options)?, 9);
+        assert!(create_new(&world, builder, &options).is_err());
+        assert!(world.join("session.lock").is_file());
+        assert!(world.join("DIM-1/region").is_dir());
+
+        let level = read_level_from_file(level_path(&world))?;
+        assert_eq!(level.level_name(), "Flat");
+        assert_eq!(level.game_rule("keepInventory"), Some("true"));
+        assert_eq!(level.difficulty()?, Difficulty::Peaceful);
+        assert_eq!(level.spawn(), BlockPos::new(40, -60, 8));
+
+        let mut region = RegionFile::open(world.join("region/r.0.0.mca"))?;
+        let mut registry = BlockRegistry::with_air();
+        let root: NamedTag = region.read_data((3, 1))?;
+        let chunk = decode_chunk(&mut registry, root.take_tag())?;
+        let name = |y: i64| chunk.get_id((48, y, 16)).and_then(|id| registry.get(id)).map(BlockState::name);
+        assert_eq!((name(-64), name(-62), name(-61), name(-60)), (Some("minecraft:bedrock"), Some("minecraft:dirt"), Some("minecraft:grass_block"), Some("minecraft:air")));
+        assert_eq!(chunk.get_heightmap(HeightmapFlag::WorldSurface, 5, 5), Some(4));
+        assert!(region.get_sector((5, 1)).is_empty());
+        Ok(())
+    }
+}
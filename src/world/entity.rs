@@ -0,0 +1,502 @@
+//! Typed wrappers for entity NBT.
+//!
+//! [Entity] holds the fields that every entity has and keeps the rest in
+//! [Entity::other]. The wrappers for common entities ([Mob], [Villager], [ItemFrame],
+//! and [ArmorStand]) build on it, so that editing trades or clearing an item frame
+//! doesn't need raw tag edits. Everything that a wrapper doesn't have a field for is
+//! written back as it was read.
+
+use glam::DVec3;
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        tag::{DecodeNbt, EncodeNbt, ListTag, Tag},
+    },
+    util::uuid::{read_uuid, write_uuid},
+};
+
+use super::{
+    components::int_value,
+    item::ItemStack,
+};
+
+/// Reads a boolean stored as a byte.
+fn flag(map: &mut Map, key: &str) -> bool {
+    map.remove(key).as_ref().and_then(int_value).unwrap_or_default() != 0
+}
+
+/// Writes a boolean as a byte, leaving it out when it is false (the default).
+fn set_flag(map: &mut Map, key: &str, value: bool) {
+    if value {
+        map.insert(key.to_owned(), Tag::Byte(1));
+    }
+}
+
+/// Decodes an item that may be stored as an empty compound (an empty slot).
+fn optional_item(tag: Option<Tag>) -> McResult<Option<ItemStack>> {
+    match tag {
+        Some(Tag::Compound(map)) if map.is_empty() => Ok(None),
+        Some(tag) => ItemStack::decode_nbt(tag).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn encode_optional_item(item: Option<ItemStack>) -> Map {
+    match item.map(ItemStack::encode_nbt) {
+        Some(Tag::Compound(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// The fields that every entity has.
+#[derive(Debug, Clone)]
+pub struct Entity {
+    /// The entity type, such as `minecraft:pig`.
+    pub id: String,
+    /// `Pos`
+    pub pos: DVec3,
+    /// `UUID` (read from the legacy `UUIDMost`/`UUIDLeast` form as well).
+    pub uuid: Option<u128>,
+    /// Every other tag of the entity.
+    pub other: Map,
+}
+
+impl Entity {
+    pub fn new<S: Into<String>>(id: S, pos: DVec3) -> Self {
+        Self {
+            id: id.into(),
+            pos,
+            uuid: None,
+            other: Map::new(),
+        }
+    }
+}
+
+impl DecodeNbt for Entity {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        let Some(Tag::String(id)) = map.remove("id") else {
+            return Err(McError::NotFoundInCompound("id".to_owned()));
+        };
+        let pos = match map.remove("Pos") {
+            Some(Tag::List(ListTag::Double(pos))) if pos.len() == 3 => DVec3::new(pos[0], pos[1], pos[2]),
+            _ => return Err(McError::NotFoundInCompound("Pos".to_owned())),
+        };
+        let uuid = read_uuid(&map, "UUID");
+        ["UUID", "UUIDMost", "UUIDLeast"].into_iter().for_each(|key| {
+            map.remove(key);
+        });
+        Ok(Self { id, pos, uuid, other: map })
+    }
+}
+
+impl EncodeNbt for Entity {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        map.insert("id".to_owned(), Tag::String(self.id));
+        map.insert("Pos".to_owned(), Tag::List(ListTag::Double(vec![self.pos.x, self.pos.y, self.pos.z])));
+        if let Some(uuid) = self.uuid {
+            write_uuid(&mut map, "UUID", uuid);
+        }
+        Tag::Compound(map)
+    }
+}
+
+/// The items held and worn by a mob or armor stand. Equipment is read from either the
+/// `HandItems`/`ArmorItems` lists or the `equipment` compound (1.21.5+), and is written
+/// as the lists, to match the item format of [ItemStack].
+#[derive(Debug, Clone, Default)]
+pub struct Equipment {
+    pub mainhand: Option<ItemStack>,
+    pub offhand: Option<ItemStack>,
+    pub head: Option<ItemStack>,
+    pub chest: Option<ItemStack>,
+    pub legs: Option<ItemStack>,
+    pub feet: Option<ItemStack>,
+}
+
+impl Equipment {
+    /// Returns true if nothing is held or worn.
+    pub fn is_empty(&self) -> bool {
+        self.slots().iter().all(|slot| slot.is_none())
+    }
+
+    /// The slots in the order mainhand, offhand, head, chest, legs, feet.
+    pub fn slots(&self) -> [&Option<ItemStack>; 6] {
+        [&self.mainhand, &self.offhand, &self.head, &self.chest, &self.legs, &self.feet]
+    }
+
+    /// Removes every item.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Removes the equipment tags from `map`.
+    fn take(map: &mut Map) -> McResult<Self> {
+        let mut equipment = Self::default();
+        if let Some(Tag::Compound(mut slots)) = map.remove("equipment") {
+            equipment.mainhand = optional_item(slots.remove("mainhand"))?;
+            equipment.offhand = optional_item(slots.remove("offhand"))?;
+            equipment.head = optional_item(slots.remove("head"))?;
+            equipment.chest = optional_item(slots.remove("chest"))?;
+            equipment.legs = optional_item(slots.remove("legs"))?;
+            equipment.feet = optional_item(slots.remove("feet"))?;
+            // Other slots (such as `body` or `saddle`) are kept.
+            if !slots.is_empty() {
+                map.insert("equipment".to_owned(), Tag::Compound(slots));
+            }
+        }
+        if let Some(Tag::List(ListTag::Compound(hands))) = map.remove("HandItems") {
+            let mut hands = hands.into_iter().map(|item| optional_item(Some(Tag::Compound(item))));
+            equipment.mainhand = hands.next().transpose()?.flatten();
+            equipment.offhand = hands.next().transpose()?.flatten();
+        }
+        if let Some(Tag::List(ListTag::Compound(armor))) = map.remove("ArmorItems") {
+            // Armor is stored from the feet up.
+            let mut armor = armor.into_iter().map(|item| optional_item(Some(Tag::Compound(item))));
+            equipment.feet = armor.next().transpose()?.flatten();
+            equipment.legs = armor.next().transpose()?.flatten();
+            equipment.chest = armor.next().transpose()?.flatten();
+            equipment.head = armor.next().transpose()?.flatten();
+        }
+        Ok(equipment)
+    }
+
+    fn put(self, map: &mut Map) {
+        map.insert("HandItems".to_owned(), Tag::List(ListTag::Compound(vec![
+            encode_optional_item(self.mainhand),
+            encode_optional_item(self.offhand),
+        ])));
+        map.insert("ArmorItems".to_owned(), Tag::List(ListTag::Compound(vec![
+            encode_optional_item(self.feet),
+            encode_optional_item(self.legs),
+            encode_optional_item(self.chest),
+            encode_optional_item(self.head),
+        ])));
+    }
+}
+
+/// A mob: any entity that can hold and wear equipment.
+#[derive(Debug, Clone)]
+pub struct Mob {
+    pub entity: Entity,
+    pub equipment: Equipment,
+}
+
+impl DecodeNbt for Mob {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let mut entity = Entity::decode_nbt(nbt)?;
+        let equipment = Equipment::take(&mut entity.other)?;
+        Ok(Self { entity, equipment })
+    }
+}
+
+impl EncodeNbt for Mob {
+    fn encode_nbt(mut self) -> Tag {
+        self.equipment.put(&mut self.entity.other);
+        self.entity.encode_nbt()
+    }
+}
+
+/// A trade of a villager (or wandering trader).
+#[derive(Debug, Clone)]
+pub struct TradeOffer {
+    /// `buy`, the first item that the player pays with.
+    pub buy: ItemStack,
+    /// `buyB`, the optional second item.
+    pub buy_b: Option<ItemStack>,
+    pub sell: ItemStack,
+    pub uses: i32,
+    pub max_uses: i32,
+    /// Every other tag of the offer (experience, price multiplier, demand, and so on).
+    pub other: Map,
+}
+
+impl TradeOffer {
+    pub fn new(buy: ItemStack, sell: ItemStack, max_uses: i32) -> Self {
+        Self {
+            buy,
+            buy_b: None,
+            sell,
+            uses: 0,
+            max_uses,
+            other: Map::new(),
+        }
+    }
+}
+
+impl DecodeNbt for TradeOffer {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        let buy = ItemStack::decode_nbt(map.remove("buy").ok_or_else(|| McError::NotFoundInCompound("buy".to_owned()))?)?;
+        let sell = ItemStack::decode_nbt(map.remove("sell").ok_or_else(|| McError::NotFoundInCompound("sell".to_owned()))?)?;
+        let buy_b = optional_item(map.remove("buyB"))?.filter(|item| item.id != "minecraft:air");
+        let uses = map.remove("uses").as_ref().and_then(int_value).unwrap_or_default();
+        let max_uses = map.remove("maxUses").as_ref().and_then(int_value).unwrap_or(4);
+        Ok(Self { buy, buy_b, sell, uses, max_uses, other: map })
+    }
+}
+
+impl EncodeNbt for TradeOffer {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        map.insert("buy".to_owned(), self.buy.encode_nbt());
+        if let Some(buy_b) = self.buy_b {
+            map.insert("buyB".to_owned(), buy_b.encode_nbt());
+        }
+        map.insert("sell".to_owned(), self.sell.encode_nbt());
+        map.insert("uses".to_owned(), Tag::Int(self.uses));
+        map.insert("maxUses".to_owned(), Tag::Int(self.max_uses));
+        Tag::Compound(map)
+    }
+}
+
+/// A villager (or zombie villager).
+#[derive(Debug, Clone)]
+pub struct Villager {
+    pub mob: Mob,
+    /// The profession from `VillagerData`, such as `minecraft:librarian`.
+    pub profession: String,
+    /// The profession level, from 1 (novice) to 5 (master).
+    pub level: i32,
+    /// The biome type from `VillagerData`, such as `minecraft:plains`.
+    pub villager_type: String,
+    /// The trades from `Offers`.
+    pub offers: Vec<TradeOffer>,
+}
+
+impl Villager {
+    /// Resets the uses of every trade, as the game does when a villager restocks.
+    pub fn restock(&mut self) {
+        self.offers.iter_mut().for_each(|offer| offer.uses = 0);
+    }
+}
+
+impl DecodeNbt for Villager {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let mut mob = Mob::decode_nbt(nbt)?;
+        let other = &mut mob.entity.other;
+        let mut data = match other.remove("VillagerData") {
+            Some(Tag::Compound(data)) => data,
+            _ => Map::new(),
+        };
+        let mut text = |key: &str, default: &str| match data.remove(key) {
+            Some(Tag::String(value)) => value,
+            _ => default.to_owned(),
+        };
+        let profession = text("profession", "minecraft:none");
+        let villager_type = text("type", "minecraft:plains");
+        let level = data.remove("level").as_ref().and_then(int_value).unwrap_or(1);
+        if !data.is_empty() {
+            other.insert("VillagerData".to_owned(), Tag::Compound(data));
+        }
+        let mut offers_tag = match other.remove("Offers") {
+            Some(Tag::Compound(offers)) => offers,
+            _ => Map::new(),
+        };
+        let offers = match offers_tag.remove("Recipes") {
+            Some(Tag::List(ListTag::Compound(recipes))) => recipes.into_iter()
+                .map(|recipe| TradeOffer::decode_nbt(Tag::Compound(recipe)))
+                .collect::<McResult<_>>()?,
+            _ => Vec::new(),
+        };
+        if !offers_tag.is_empty() {
+            other.insert("Offers".to_owned(), Tag::Compound(offers_tag));
+        }
+        Ok(Self { mob, profession, level, villager_type, offers })
+    }
+}
+
+impl EncodeNbt for Villager {
+    fn encode_nbt(mut self) -> Tag {
+        let other = &mut self.mob.entity.other;
+        let mut data = match other.remove("VillagerData") {
+            Some(Tag::Compound(data)) => data,
+            _ => Map::new(),
+        };
+        data.insert("profession".to_owned(), Tag::String(self.profession));
+        data.insert("level".to_owned(), Tag::Int(self.level));
+        data.insert("type".to_owned(), Tag::String(self.villager_type));
+        other.insert("VillagerData".to_owned(), Tag::Compound(data));
+        let mut offers = match other.remove("Offers") {
+            Some(Tag::Compound(offers)) => offers,
+            _ => Map::new(),
+        };
+        let recipes = self.offers.into_iter()
+            .filter_map(|offer| Map::try_from(offer.encode_nbt()).ok())
+            .collect::<Vec<_>>();
+        offers.insert("Recipes".to_owned(), Tag::List(ListTag::Compound(recipes)));
+        other.insert("Offers".to_owned(), Tag::Compound(offers));
+        self.mob.encode_nbt()
+    }
+}
+
+/// An item frame (or glow item frame).
+#[derive(Debug, Clone)]
+pub struct ItemFrame {
+    pub entity: Entity,
+    pub item: Option<ItemStack>,
+    /// `ItemRotation`, in steps of 45 degrees.
+    pub rotation: i8,
+    /// `Fixed`: the frame can't be broken or have its item removed or rotated.
+    pub fixed: bool,
+    pub invisible: bool,
+}
+
+impl ItemFrame {
+    /// Removes the item and resets the rotation.
+    pub fn clear(&mut self) {
+        self.item = None;
+        self.rotation = 0;
+    }
+}
+
+impl DecodeNbt for ItemFrame {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let mut entity = Entity::decode_nbt(nbt)?;
+        let other = &mut entity.other;
+        let item = optional_item(other.remove("Item"))?;
+        let rotation = other.remove("ItemRotation").as_ref().and_then(int_value).unwrap_or_default() as i8;
+        let fixed = flag(other, "Fixed");
+        let invisible = flag(other, "Invisible");
+        Ok(Self { entity, item, rotation, fixed, invisible })
+    }
+}
+
+impl EncodeNbt for ItemFrame {
+    fn encode_nbt(mut self) -> Tag {
+        let other = &mut self.entity.other;
+        if let Some(item) = self.item {
+            other.insert("Item".to_owned(), item.encode_nbt());
+        }
+        other.insert("ItemRotation".to_owned(), Tag::Byte(self.rotation));
+        set_flag(other, "Fixed", self.fixed);
+        set_flag(other, "Invisible", self.invisible);
+        self.entity.encode_nbt()
+    }
+}
+
+/// An armor stand. The pose is kept in [Entity::other].
+#[derive(Debug, Clone)]
+pub struct ArmorStand {
+    pub entity: Entity,
+    pub equipment: Equipment,
+    pub invisible: bool,
+    pub small: bool,
+    pub show_arms: bool,
+    pub no_base_plate: bool,
+    /// A marker has no hitbox.
+    pub marker: bool,
+}
+
+impl DecodeNbt for ArmorStand {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Mob { mut entity, equipment } = Mob::decode_nbt(nbt)?;
+        let other = &mut entity.other;
+        Ok(Self {
+            invisible: flag(other, "Invisible"),
+            small: flag(other, "Small"),
+            show_arms: flag(other, "ShowArms"),
+            no_base_plate: flag(other, "NoBasePlate"),
+            marker: flag(other, "Marker"),
+            entity,
+            equipment,
+        })
+    }
+}
+
+impl EncodeNbt for ArmorStand {
+    fn encode_nbt(mut self) -> Tag {
+        let other = &mut self.entity.other;
+        set_flag(other, "Invisible", self.invisible);
+        set_flag(other, "Small", self.small);
+        set_flag(other, "ShowArms", self.show_arms);
+        set_flag(other, "NoBasePlate", self.no_base_plate);
+        set_flag(other, "Marker", self.marker);
+        Mob { entity: self.entity, equipment: self.equipment }.encode_nbt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, count: i8) -> Map {
+        Map::from([
+            ("id".to_owned(), Tag::string(id)),
+            ("Count".to_owned(), Tag::Byte(count)),
+        ])
+    }
+
+    fn entity(id: &str) -> Map {
+        Map::from([
+            ("id".to_owned(), Tag::string(id)),
+            ("Pos".to_owned(), Tag::List(ListTag::Double(vec![1.5, 64.0, -2.5]))),
+            ("UUID".to_owned(), Tag::IntArray(vec![1, 2, 3, 4])),
+            ("Health".to_owned(), Tag::Float(20.0)),
+        ])
+    }
+
+    #[test]
+    fn entity_wrappers_test() -> McResult<()> {
+        let mut villager = entity("minecraft:villager");
+        villager.insert("VillagerData".to_owned(), Tag::Compound(Map::from([
+            ("profession".to_owned(), Tag::string("minecraft:librarian")),
+            ("level".to_owned(), Tag::Int(2)),
+            ("type".to_owned(), Tag::string("minecraft:taiga")),
+        ])));
+        villager.insert("Offers".to_owned(), Tag::Compound(Map::from([
+            ("Recipes".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                ("buy".to_owned(), Tag::Compound(item("minecraft:emerald", 24))),
+                ("buyB".to_owned(), Tag::Compound(item("minecraft:book", 1))),
+                ("sell".to_owned(), Tag::Compound(item("minecraft:enchanted_book", 1))),
+                ("uses".to_owned(), Tag::Int(3)),
+                ("maxUses".to_owned(), Tag::Int(12)),
+                ("xp".to_owned(), Tag::Int(5)),
+            ])]))),
+        ])));
+        villager.insert("HandItems".to_owned(), Tag::List(ListTag::Compound(vec![item("minecraft:bread", 2), Map::new()])));
+        let mut decoded = Villager::decode_nbt(Tag::Compound(villager))?;
+        assert_eq!((decoded.profession.as_str(), decoded.level, decoded.villager_type.as_str()), ("minecraft:librarian", 2, "minecraft:taiga"));
+        assert_eq!(decoded.offers[0].buy_b.as_ref().map(|item| item.id.as_str()), Some("minecraft:book"));
+        assert_eq!(decoded.mob.equipment.mainhand.as_ref().map(|item| item.count), Some(2));
+        assert_eq!(decoded.mob.entity.uuid, Some(crate::util::uuid::from_int_array([1, 2, 3, 4])));
+        decoded.restock();
+        decoded.offers[0].sell.count = 2;
+        decoded.level = 3;
+
+        let Tag::Compound(encoded) = decoded.encode_nbt() else { panic!("Expected a compound.") };
+        assert!(matches!(encoded.get("Health"), Some(Tag::Float(_))));
+        let decoded = Villager::decode_nbt(Tag::Compound(encoded))?;
+        assert_eq!((decoded.level, decoded.offers[0].uses, decoded.offers[0].sell.count), (3, 0, 2));
+        assert!(matches!(decoded.offers[0].other.get("xp"), Some(Tag::Int(5))));
+
+        let mut frame = entity("minecraft:item_frame");
+        frame.insert("Item".to_owned(), Tag::Compound(item("minecraft:map", 1)));
+        frame.insert("ItemRotation".to_owned(), Tag::Byte(3));
+        frame.insert("Fixed".to_owned(), Tag::Byte(1));
+        let mut frame = ItemFrame::decode_nbt(Tag::Compound(frame))?;
+        assert!(frame.fixed && frame.item.is_some());
+        frame.clear();
+        let Tag::Compound(encoded) = frame.encode_nbt() else { panic!("Expected a compound.") };
+        assert!(!encoded.contains_key("Item"));
+
+        let mut stand = entity("minecraft:armor_stand");
+        stand.insert("equipment".to_owned(), Tag::Compound(Map::from([
+            ("head".to_owned(), Tag::Compound(item("minecraft:carved_pumpkin", 1))),
+        ])));
+        stand.insert("ShowArms".to_owned(), Tag::Byte(1));
+        let stand = ArmorStand::decode_nbt(Tag::Compound(stand))?;
+        assert!(stand.show_arms && !stand.small);
+        assert_eq!(stand.equipment.head.as_ref().map(|item| item.id.as_str()), Some("minecraft:carved_pumpkin"));
+        let Tag::Compound(encoded) = stand.encode_nbt() else { panic!("Expected a compound.") };
+        assert!(matches!(encoded.get("ArmorItems"), Some(Tag::List(ListTag::Compound(armor))) if armor[3].contains_key("id")));
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! World-wide search and replace over raw chunk NBT.
+
+use std::path::Path;
+
+use crate::{
+    McError, McResult,
+    math::coord::WorldCoord,
+    nbt::{
+        tag::Tag,
+        tagpath::{TagPath, TagPathPart, TagPathPattern},
+        tagref::ValueRef,
+    },
+};
+
+use super::{
+    scan::{for_each_chunk, RegionKind},
+    selection::WorldSelection,
+};
+
+/// A value that was found (and possibly replaced) by [find_replace].
+#[derive(Debug, Clone)]
+pub struct NbtReplacement {
+    /// The kind of region file that the chunk is stored in.
+    pub kind: RegionKind,
+    pub chunk: WorldCoord,
+    /// The path of the value from the root of the chunk.
+    pub path: TagPath,
+    pub old: Tag,
+    pub new: Tag,
+}
+
+/// Returns true if the end of `path` matches `pattern`.
+/// A `*` in a pattern matches any single key or index.
+pub fn pattern_matches(path: &[TagPathPart], pattern: &TagPathPattern) -> bool {
+    pattern.matches_end(path)
+}
+
+/// Collects the paths of every node under `node` that matches `pattern` and `matcher`.
+/// The children of a node that matches are not searched, and the elements of
+/// `ByteArray`/`IntArray`/`LongArray` are not visited individually.
+fn collect_matches<M: Fn(ValueRef<'_>) -> bool>(
+    node: ValueRef<'_>,
+    path: &mut Vec<TagPathPart>,
+    pattern: &TagPathPattern,
+    matcher: &M,
+    found: &mut Vec<TagPath>,
+) {
+    if !path.is_empty() && pattern_matches(path, pattern) && matcher(node) {
+        found.push(TagPath(path.clone()));
+        return;
+    }
+    match node {
+        ValueRef::Compound(map) => {
+            let mut keys = map.keys().collect::<Vec<_>>();
+            keys.sort();
+            keys.into_iter().for_each(|key| {
+                path.push(TagPathPart::AtKey(key.to_owned()));
+                collect_matches(ValueRef::from(&map[key]), path, pattern, matcher, found);
+                path.pop();
+            });
+        },
+        ValueRef::List(_) => {
+            (0i64..).map_while(|index| node.get_child(&TagPathPart::AtIndex(index)))
+                .enumerate()
+                .for_each(|(index, child)| {
+                    path.push(TagPathPart::AtIndex(index as i64));
+                    collect_matches(child, path, pattern, matcher, found);
+                    path.pop();
+                });
+        },
+        _ => (),
+    }
+}
+
+/// Searches the selected chunks of a world for values at paths ending with `pattern`
+/// that satisfy `matcher`, and replaces them with the value returned by `replacement`.
+/// Terrain chunks (including block entities) and entity chunks are searched.
+///
+/// When `dry_run` is true, nothing is written and the returned list describes what
+/// would have been replaced.
+///
+/// For example, to rename an item in every container:
+/// ```ignore
+/// find_replace(
+///     world,
+///     &WorldSelection::dimension(Dimension::Overworld),
+///     &TagPathPattern::parse("Items[*].id")?,
+///     |value| value.as_str() == Some("minecraft:old_item"),
+///     |_| Tag::from("minecraft:new_item"),
+///     false,
+/// )?;
+/// ```
+pub fn find_replace<P, M, R>(
+    world_directory: P,
+    selection: &WorldSelection,
+    pattern: &TagPathPattern,
+    matcher: M,
+    replacement: R,
+    dry_run: bool,
+) -> McResult<Vec<NbtReplacement>>
+where
+P: AsRef<Path>,
+M: Fn(ValueRef<'_>) -> bool,
+R: Fn(ValueRef<'_>) -> Tag {
+    let world_directory = world_directory.as_ref();
+    let mut replacements = Vec::new();
+    [RegionKind::Terrain, RegionKind::Entities].into_iter().try_for_each(|kind| {
+        for_each_chunk(world_directory, selection, kind, |chunk, root| {
+            let mut found = Vec::new();
+            collect_matches(ValueRef::from(root.tag()), &mut Vec::new(), pattern, &matcher, &mut found);
+            let modified = !found.is_empty() && !dry_run;
+            found.into_iter().try_for_each(|path| {
+                let Some(old) = root.tag().find_child(path.path()) else {
+                    return Err(McError::TagPathNotFound(path));
+                };
+                let new = replacement(old);
+                let old = Tag::from(old);
+                if !dry_run {
+                    let (old_id, new_id) = (old.id(), new.id());
+                    root.tag_mut().set_child(path.path(), new.clone())
+                        .map_err(|_| McError::TagTypeMismatch(old_id, new_id))?;
+                }
+                replacements.push(NbtReplacement { kind, chunk, path, old, new });
+                McResult::Ok(())
+            })?;
+            Ok(modified)
+        })
+    })?;
+    Ok(replacements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::Dimension,
+        nbt::{Map, tag::{ListTag, NamedTag}},
+        world::io::region::{RegionFile, RegionFormatExt, RegionCoord},
+    };
+
+    fn item(id: &str) -> Map {
+        Map::from([("id".to_owned(), Tag::string(id)), ("Count".to_owned(), Tag::Byte(1))])
+    }
+
+    fn chest(items: Vec<Map>) -> NamedTag {
+        let chest = Map::from([("Items".to_owned(), Tag::List(ListTag::Compound(items)))]);
+        NamedTag::new(Map::from([
+            ("block_entities".to_owned(), Tag::List(ListTag::Compound(vec![chest]))),
+        ]))
+    }
+
+    #[test]
+    fn pattern_matches_test() {
+        let pattern = TagPathPattern::parse("Items[*].id").unwrap();
+        assert!(pattern_matches(TagPath::parse("block_entities[0].Items[2].id").unwrap().path(), &pattern));
+        assert!(!pattern_matches(TagPath::parse("block_entities[0].Items[2]").unwrap().path(), &pattern));
+        assert!(!pattern_matches(TagPath::parse("Item.id").unwrap().path(), &pattern));
+    }
+
+    #[test]
+    fn find_replace_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        std::fs::create_dir_all(world.join("region"))?;
+        {
+            let mut region = RegionFile::create(world.join("region/r.0.0.mca"))?;
+            region.write_data((0, 0), &chest(vec![item("minecraft:old"), item("minecraft:stone")]))?;
+            region.write_data((5, 0), &chest(vec![item("minecraft:old")]))?;
+        }
+        let pattern = TagPathPattern::parse("Items[*].id").unwrap();
+        let matcher = |value: ValueRef<'_>| value.as_str() == Some("minecraft:old");
+        let replacement = |_: ValueRef<'_>| Tag::string("minecraft:new");
+
+        // A selection that only covers the first chunk, as a dry run.
+        let selection = WorldSelection::area(Dimension::Overworld, ((0, 0), (1, 1)));
+        let found = find_replace(world, &selection, &pattern, matcher, replacement, true)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].chunk, WorldCoord::new(0, 0, Dimension::Overworld));
+        assert_eq!(found[0].path, TagPath::parse("block_entities[0].Items[0].id").unwrap());
+        assert!(matches!(&found[0].old, Tag::String(id) if id == "minecraft:old"));
+        let mut region = RegionFile::open(world.join("region/r.0.0.mca"))?;
+        let root: NamedTag = region.read_chunk(RegionCoord::new(0, 0))?;
+        assert!(matches!(root.tag().find_child(found[0].path.path()).and_then(ValueRef::as_str), Some("minecraft:old")));
+        drop(region);
+
+        let found = find_replace(world, &WorldSelection::dimension(Dimension::Overworld), &pattern, matcher, replacement, false)?;
+        assert_eq!(found.len(), 2);
+        let mut region = RegionFile::open(world.join("region/r.0.0.mca"))?;
+        for coord in [RegionCoord::new(0, 0), RegionCoord::new(5, 0)] {
+            let root: NamedTag = region.read_chunk(coord)?;
+            let path = TagPath::parse("block_entities[0].Items[0].id").unwrap();
+            assert_eq!(root.tag().find_child(path.path()).and_then(ValueRef::as_str), Some("minecraft:new"));
+        }
+        let root: NamedTag = region.read_chunk(RegionCoord::new(0, 0))?;
+        let path = TagPath::parse("block_entities[0].Items[1].id").unwrap();
+        assert_eq!(root.tag().find_child(path.path()).and_then(ValueRef::as_str), Some("minecraft:stone"));
+        Ok(())
+    }
+}
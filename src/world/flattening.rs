@@ -0,0 +1,568 @@
+//! The 1.13 "flattening" of numeric block ids (1.12.2 and earlier) into
+//! named [BlockState]s, and the reverse.
+//!
+//! [flatten_block] covers every block id that 1.12.2 has, including the
+//! properties that were stored in metadata (facing, axis, half, age, and so on).
+//! Properties that 1.12 computed from neighbouring blocks (fence and wall connections,
+//! stair shapes, snowy grass, redstone wire connections) aren't in metadata, so they
+//! are left for the game to recompute. Neither are the properties that 1.12 stored in
+//! block entities (skull types, banner and bed colors, flower pot contents), so those
+//! blocks get their default (skeleton skull, white banner, red bed, empty pot).
+//!
+//! Both functions can be passed to [decode_legacy_chunk](super::legacy::decode_legacy_chunk)
+//! and [encode_legacy_chunk](super::legacy::encode_legacy_chunk) to convert chunks saved
+//! before 1.13 rather than the Beta 1.8 blocks that the legacy module covers on its own.
+//!
+//! Block names are the names used by [CONVERTED_DATA_VERSION](super::legacy::CONVERTED_DATA_VERSION)
+//! (1.20.1), so renamed blocks (`grass_path`, `stone_slab`) have their current names.
+
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+};
+
+use super::blockstate::BlockState;
+
+const COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray",
+    "light_gray", "cyan", "purple", "blue", "brown", "green", "red", "black",
+];
+
+const WOOD: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Directions by 3D data value (dispensers, pistons, hoppers, end rods, observers).
+const FACING: [&str; 6] = ["down", "up", "north", "south", "west", "east"];
+
+/// Directions by 2D data value (beds, pumpkins, fence gates, repeaters, and most others).
+const HORIZONTAL: [&str; 4] = ["south", "west", "north", "east"];
+
+const STAIRS_FACING: [&str; 4] = ["east", "west", "south", "north"];
+
+const TRAPDOOR_FACING: [&str; 4] = ["north", "south", "west", "east"];
+
+const DOOR_FACING: [&str; 4] = ["east", "south", "west", "north"];
+
+const AXIS: [&str; 3] = ["y", "x", "z"];
+
+const RAIL_SHAPES: [&str; 10] = [
+    "north_south", "east_west", "ascending_east", "ascending_west", "ascending_north",
+    "ascending_south", "south_east", "south_west", "north_west", "north_east",
+];
+
+const STONE_SLABS: [&str; 8] = [
+    "smooth_stone", "sandstone", "petrified_oak", "cobblestone",
+    "brick", "stone_brick", "nether_brick", "quartz",
+];
+
+const STAIRS: [(u8, &str); 14] = [
+    (53, "oak"), (67, "cobblestone"), (108, "brick"), (109, "stone_brick"),
+    (114, "nether_brick"), (128, "sandstone"), (134, "spruce"), (135, "birch"),
+    (136, "jungle"), (156, "quartz"), (163, "acacia"), (164, "dark_oak"),
+    (180, "red_sandstone"), (203, "purpur"),
+];
+
+/// A state builder, so that each arm of [flatten_block] reads like the table it is.
+struct State {
+    name: &'static str,
+    prefix: String,
+    properties: Vec<(&'static str, String)>,
+}
+
+fn block(name: &'static str) -> State {
+    State { name, prefix: String::new(), properties: Vec::new() }
+}
+
+/// A block whose name is `{prefix}_{name}`, such as `spruce_planks`.
+fn named(prefix: &str, name: &'static str) -> State {
+    State { name, prefix: format!("{prefix}_"), properties: Vec::new() }
+}
+
+impl State {
+    fn with<V: ToString>(mut self, property: &'static str, value: V) -> Self {
+        self.properties.push((property, value.to_string()));
+        self
+    }
+
+    fn flag(self, property: &'static str, data: u8, bit: u8) -> Self {
+        self.with(property, data & bit != 0)
+    }
+
+    fn build(self) -> BlockState {
+        let name = format!("minecraft:{}{}", self.prefix, self.name);
+        if self.properties.is_empty() {
+            BlockState::from(name)
+        } else {
+            BlockState::new(name, self.properties)
+        }
+    }
+}
+
+fn pick<'a>(names: &[&'a str], index: u8) -> &'a str {
+    names[(index as usize).min(names.len() - 1)]
+}
+
+/// Wall-mounted blocks (chests, ladders, signs, furnaces) use 2 to 5 as directions.
+fn wall_facing(data: u8) -> &'static str {
+    match data {
+        2..=5 => FACING[data as usize],
+        _ => "north",
+    }
+}
+
+fn stairs(prefix: &str, data: u8) -> State {
+    named(prefix, "stairs")
+        .with("facing", STAIRS_FACING[(data & 3) as usize])
+        .with("half", if data & 4 != 0 { "top" } else { "bottom" })
+}
+
+fn slab(prefix: &str, data: u8, double: bool) -> State {
+    named(prefix, "slab").with("type", if double {
+        "double"
+    } else if data & 8 != 0 {
+        "top"
+    } else {
+        "bottom"
+    })
+}
+
+fn log(wood: &str, data: u8) -> State {
+    match data >> 2 {
+        3 => named(wood, "wood").with("axis", "y"),
+        axis => named(wood, "log").with("axis", AXIS[axis as usize]),
+    }
+}
+
+fn leaves(wood: &str, data: u8) -> State {
+    named(wood, "leaves")
+        .flag("persistent", data, 4)
+        .with("distance", 7)
+}
+
+fn door(wood: &str, data: u8) -> State {
+    let door = named(wood, "door");
+    if data & 8 != 0 {
+        door.with("half", "upper")
+            .with("hinge", if data & 1 != 0 { "right" } else { "left" })
+            .flag("powered", data, 2)
+    } else {
+        door.with("half", "lower")
+            .with("facing", DOOR_FACING[(data & 3) as usize])
+            .flag("open", data, 4)
+    }
+}
+
+fn trapdoor(wood: &str, data: u8) -> State {
+    named(wood, "trapdoor")
+        .with("facing", TRAPDOOR_FACING[(data & 3) as usize])
+        .flag("open", data, 4)
+        .with("half", if data & 8 != 0 { "top" } else { "bottom" })
+}
+
+fn fence_gate(wood: &str, data: u8) -> State {
+    named(wood, "fence_gate")
+        .with("facing", HORIZONTAL[(data & 3) as usize])
+        .flag("open", data, 4)
+}
+
+fn button(wood: &str, data: u8) -> State {
+    let (face, facing) = match data & 7 {
+        0 => ("ceiling", "north"),
+        5 => ("floor", "north"),
+        direction => ("wall", ["east", "west", "south", "north"][(direction as usize - 1).min(3)]),
+    };
+    named(wood, "button")
+        .with("face", face)
+        .with("facing", facing)
+        .flag("powered", data, 8)
+}
+
+fn torch(prefix: &str, data: u8) -> State {
+    match data {
+        1..=4 => named(prefix, "wall_torch").with("facing", ["east", "west", "south", "north"][data as usize - 1]),
+        _ => named(prefix, "torch"),
+    }
+}
+
+fn rail(name: &'static str, data: u8) -> State {
+    block(name)
+        .with("shape", pick(&RAIL_SHAPES[..6], data & 7))
+        .flag("powered", data, 8)
+}
+
+fn piston(name: &'static str, data: u8) -> State {
+    block(name)
+        .with("facing", pick(&FACING, data & 7))
+        .flag("extended", data, 8)
+}
+
+fn repeater(data: u8, powered: bool) -> State {
+    block("repeater")
+        .with("facing", HORIZONTAL[(data & 3) as usize])
+        .with("delay", (data >> 2) + 1)
+        .with("powered", powered)
+}
+
+fn comparator(data: u8, powered: bool) -> State {
+    block("comparator")
+        .with("facing", HORIZONTAL[(data & 3) as usize])
+        .with("mode", if data & 4 != 0 { "subtract" } else { "compare" })
+        .with("powered", powered || data & 8 != 0)
+}
+
+/// Translates a block id and metadata from 1.12.2 or earlier to a [BlockState].
+/// Returns `None` for ids that 1.12.2 doesn't have.
+pub fn flatten_block(id: u8, data: u8) -> Option<BlockState> {
+    let data = data & 15;
+    let color = COLORS[data as usize];
+    if let Some((_, prefix)) = STAIRS.iter().find(|(stairs_id, _)| *stairs_id == id) {
+        return Some(stairs(prefix, data).build());
+    }
+    Some(match id {
+        0 => block("air"),
+        1 => block(pick(&[
+            "stone", "granite", "polished_granite", "diorite",
+            "polished_diorite", "andesite", "polished_andesite",
+        ], data)),
+        2 => block("grass_block").with("snowy", false),
+        3 => match data {
+            1 => block("coarse_dirt"),
+            2 => block("podzol").with("snowy", false),
+            _ => block("dirt"),
+        },
+        4 => block("cobblestone"),
+        5 => named(pick(&WOOD, data), "planks"),
+        6 => named(pick(&WOOD, data & 7), "sapling").with("stage", (data >> 3) & 1),
+        7 => block("bedrock"),
+        8 | 9 => block("water").with("level", data),
+        10 | 11 => block("lava").with("level", data),
+        12 => block(if data == 1 { "red_sand" } else { "sand" }),
+        13 => block("gravel"),
+        14 => block("gold_ore"),
+        15 => block("iron_ore"),
+        16 => block("coal_ore"),
+        17 => log(WOOD[(data & 3) as usize], data),
+        18 => leaves(WOOD[(data & 3) as usize], data),
+        19 => block(if data == 1 { "wet_sponge" } else { "sponge" }),
+        20 => block("glass"),
+        21 => block("lapis_ore"),
+        22 => block("lapis_block"),
+        23 | 158 => block(if id == 23 { "dispenser" } else { "dropper" })
+            .with("facing", pick(&FACING, data & 7))
+            .flag("triggered", data, 8),
+        24 => named(pick(&["", "chiseled", "cut"], data), "sandstone"),
+        25 => block("note_block"),
+        26 => block("red_bed")
+            .with("facing", HORIZONTAL[(data & 3) as usize])
+            .flag("occupied", data, 4)
+            .with("part", if data & 8 != 0 { "head" } else { "foot" }),
+        27 => rail("powered_rail", data),
+        28 => rail("detector_rail", data),
+        29 => piston("sticky_piston", data),
+        30 => block("cobweb"),
+        31 => block(pick(&["dead_bush", "grass", "fern"], data)),
+        32 => block("dead_bush"),
+        33 => piston("piston", data),
+        34 => block("piston_head")
+            .with("facing", pick(&FACING, data & 7))
+            .with("type", if data & 8 != 0 { "sticky" } else { "normal" }),
+        35 => named(color, "wool"),
+        36 => block("moving_piston").with("facing", pick(&FACING, data & 7)),
+        37 => block("dandelion"),
+        38 => block(pick(&[
+            "poppy", "blue_orchid", "allium", "azure_bluet", "red_tulip",
+            "orange_tulip", "white_tulip", "pink_tulip", "oxeye_daisy",
+        ], data)),
+        39 => block("brown_mushroom"),
+        40 => block("red_mushroom"),
+        41 => block("gold_block"),
+        42 => block("iron_block"),
+        43 => match data {
+            8 => block("smooth_stone"),
+            9 => block("smooth_sandstone"),
+            15 => block("smooth_quartz"),
+            _ => slab(STONE_SLABS[(data & 7) as usize], data, true),
+        },
+        44 => slab(STONE_SLABS[(data & 7) as usize], data, false),
+        45 => block("bricks"),
+        46 => block("tnt"),
+        47 => block("bookshelf"),
+        48 => block("mossy_cobblestone"),
+        49 => block("obsidian"),
+        50 => torch("", data),
+        51 => block("fire").with("age", data),
+        52 => block("spawner"),
+        54 | 146 => block(if id == 54 { "chest" } else { "trapped_chest" })
+            .with("facing", wall_facing(data))
+            .with("type", "single"),
+        55 => block("redstone_wire").with("power", data),
+        56 => block("diamond_ore"),
+        57 => block("diamond_block"),
+        58 => block("crafting_table"),
+        59 => block("wheat").with("age", data & 7),
+        60 => block("farmland").with("moisture", data & 7),
+        61 | 62 => block("furnace")
+            .with("facing", wall_facing(data))
+            .with("lit", id == 62),
+        63 => block("oak_sign").with("rotation", data),
+        64 => door("oak", data),
+        65 => block("ladder").with("facing", wall_facing(data)),
+        66 => block("rail").with("shape", pick(&RAIL_SHAPES, data)),
+        68 => block("oak_wall_sign").with("facing", wall_facing(data)),
+        69 => {
+            let (face, facing) = match data & 7 {
+                0 => ("ceiling", "east"),
+                5 => ("floor", "north"),
+                6 => ("floor", "east"),
+                7 => ("ceiling", "north"),
+                direction => ("wall", ["east", "west", "south", "north"][direction as usize - 1]),
+            };
+            block("lever").with("face", face).with("facing", facing).flag("powered", data, 8)
+        }
+        70 => block("stone_pressure_plate").flag("powered", data, 1),
+        71 => door("iron", data),
+        72 => block("oak_pressure_plate").flag("powered", data, 1),
+        73 | 74 => block("redstone_ore").with("lit", id == 74),
+        75 | 76 => match data {
+            1..=4 => torch("redstone", data).with("lit", id == 76),
+            _ => block("redstone_torch").with("lit", id == 76),
+        },
+        77 => button("stone", data),
+        78 => block("snow").with("layers", (data & 7) + 1),
+        79 => block("ice"),
+        80 => block("snow_block"),
+        81 => block("cactus").with("age", data),
+        82 => block("clay"),
+        83 => block("sugar_cane").with("age", data),
+        84 => block("jukebox").with("has_record", data == 1),
+        85 => block("oak_fence"),
+        86 => block("carved_pumpkin").with("facing", HORIZONTAL[(data & 3) as usize]),
+        87 => block("netherrack"),
+        88 => block("soul_sand"),
+        89 => block("glowstone"),
+        90 => block("nether_portal").with("axis", if data == 2 { "z" } else { "x" }),
+        91 => block("jack_o_lantern").with("facing", HORIZONTAL[(data & 3) as usize]),
+        92 => block("cake").with("bites", (data & 7).min(6)),
+        93 | 94 => repeater(data, id == 94),
+        95 => named(color, "stained_glass"),
+        96 => trapdoor("oak", data),
+        97 => named("infested", pick(&[
+            "stone", "cobblestone", "stone_bricks", "mossy_stone_bricks",
+            "cracked_stone_bricks", "chiseled_stone_bricks",
+        ], data)),
+        98 => block(pick(&[
+            "stone_bricks", "mossy_stone_bricks", "cracked_stone_bricks", "chiseled_stone_bricks",
+        ], data)),
+        99 | 100 => match data {
+            10 | 15 => block("mushroom_stem"),
+            _ => block(if id == 99 { "brown_mushroom_block" } else { "red_mushroom_block" }),
+        },
+        101 => block("iron_bars"),
+        102 => block("glass_pane"),
+        103 => block("melon"),
+        104 => block("pumpkin_stem").with("age", data & 7),
+        105 => block("melon_stem").with("age", data & 7),
+        106 => block("vine")
+            .flag("south", data, 1)
+            .flag("west", data, 2)
+            .flag("north", data, 4)
+            .flag("east", data, 8)
+            .with("up", data == 0),
+        107 => fence_gate("oak", data),
+        110 => block("mycelium").with("snowy", false),
+        111 => block("lily_pad"),
+        112 => block("nether_bricks"),
+        113 => block("nether_brick_fence"),
+        115 => block("nether_wart").with("age", data & 3),
+        116 => block("enchanting_table"),
+        117 => block("brewing_stand")
+            .flag("has_bottle_0", data, 1)
+            .flag("has_bottle_1", data, 2)
+            .flag("has_bottle_2", data, 4),
+        118 => match data & 3 {
+            0 => block("cauldron"),
+            level => block("water_cauldron").with("level", level),
+        },
+        119 => block("end_portal"),
+        120 => block("end_portal_frame")
+            .with("facing", HORIZONTAL[(data & 3) as usize])
+            .flag("eye", data, 4),
+        121 => block("end_stone"),
+        122 => block("dragon_egg"),
+        123 | 124 => block("redstone_lamp").with("lit", id == 124),
+        125 => slab(pick(&WOOD, data & 7), data, true),
+        126 => slab(pick(&WOOD, data & 7), data, false),
+        127 => block("cocoa")
+            .with("facing", HORIZONTAL[(data & 3) as usize])
+            .with("age", (data >> 2).min(2)),
+        129 => block("emerald_ore"),
+        130 => block("ender_chest").with("facing", wall_facing(data)),
+        131 => block("tripwire_hook")
+            .with("facing", HORIZONTAL[(data & 3) as usize])
+            .flag("attached", data, 4)
+            .flag("powered", data, 8),
+        132 => block("tripwire")
+            .flag("powered", data, 1)
+            .flag("attached", data, 4)
+            .flag("disarmed", data, 8),
+        133 => block("emerald_block"),
+        137 => block("command_block")
+            .with("facing", pick(&FACING, data & 7))
+            .flag("conditional", data, 8),
+        138 => block("beacon"),
+        139 => block(if data == 1 { "mossy_cobblestone_wall" } else { "cobblestone_wall" }),
+        140 => block("flower_pot"),
+        141 => block("carrots").with("age", data & 7),
+        142 => block("potatoes").with("age", data & 7),
+        143 => button("oak", data),
+        144 => match data & 7 {
+            2..=5 => block("skeleton_wall_skull").with("facing", FACING[(data & 7) as usize]),
+            _ => block("skeleton_skull").with("rotation", 0),
+        },
+        145 => block(pick(&["anvil", "chipped_anvil", "damaged_anvil"], data >> 2))
+            .with("facing", HORIZONTAL[(data & 3) as usize]),
+        147 => block("light_weighted_pressure_plate").with("power", data),
+        148 => block("heavy_weighted_pressure_plate").with("power", data),
+        149 | 150 => comparator(data, id == 150),
+        151 | 178 => block("daylight_detector")
+            .with("power", data)
+            .with("inverted", id == 178),
+        152 => block("redstone_block"),
+        153 => block("nether_quartz_ore"),
+        154 => block("hopper")
+            .with("facing", if data & 7 == 1 { "down" } else { pick(&FACING, data & 7) })
+            .with("enabled", data & 8 == 0),
+        155 => match data {
+            1 => block("chiseled_quartz_block"),
+            2..=4 => block("quartz_pillar").with("axis", AXIS[data as usize - 2]),
+            _ => block("quartz_block"),
+        },
+        157 => rail("activator_rail", data),
+        159 => named(color, "terracotta"),
+        160 => named(color, "stained_glass_pane"),
+        161 => leaves(pick(&WOOD[4..], data & 3), data),
+        162 => log(pick(&WOOD[4..], data & 3), data),
+        165 => block("slime_block"),
+        166 => block("barrier"),
+        167 => trapdoor("iron", data),
+        168 => block(pick(&["prismarine", "prismarine_bricks", "dark_prismarine"], data)),
+        169 => block("sea_lantern"),
+        170 => block("hay_block").with("axis", pick(&AXIS, data >> 2)),
+        171 => named(color, "carpet"),
+        172 => block("terracotta"),
+        173 => block("coal_block"),
+        174 => block("packed_ice"),
+        175 => if data & 8 != 0 {
+            // The upper half doesn't know its type; 1.12 took it from the lower half.
+            block("sunflower").with("half", "upper")
+        } else {
+            block(pick(&["sunflower", "lilac", "tall_grass", "large_fern", "rose_bush", "peony"], data))
+                .with("half", "lower")
+        },
+        176 => block("white_banner").with("rotation", data),
+        177 => block("white_wall_banner").with("facing", wall_facing(data)),
+        179 => named(pick(&["", "chiseled", "cut"], data), "red_sandstone"),
+        181 => match data {
+            8 => block("smooth_red_sandstone"),
+            _ => slab("red_sandstone", data, true),
+        },
+        182 => slab("red_sandstone", data, false),
+        183..=187 => fence_gate(["spruce", "birch", "jungle", "dark_oak", "acacia"][id as usize - 183], data),
+        188..=192 => named(["spruce", "birch", "jungle", "dark_oak", "acacia"][id as usize - 188], "fence"),
+        193..=197 => door(["spruce", "birch", "jungle", "acacia", "dark_oak"][id as usize - 193], data),
+        198 => block("end_rod").with("facing", pick(&FACING, data & 7)),
+        199 => block("chorus_plant"),
+        200 => block("chorus_flower").with("age", (data & 7).min(5)),
+        201 => block("purpur_block"),
+        202 => block("purpur_pillar").with("axis", pick(&AXIS, data >> 2)),
+        204 => slab("purpur", data, true),
+        205 => slab("purpur", data, false),
+        206 => block("end_stone_bricks"),
+        207 => block("beetroots").with("age", data & 3),
+        208 => block("dirt_path"),
+        209 => block("end_gateway"),
+        210 | 211 => block(if id == 210 { "repeating_command_block" } else { "chain_command_block" })
+            .with("facing", pick(&FACING, data & 7))
+            .flag("conditional", data, 8),
+        212 => block("frosted_ice").with("age", data & 3),
+        213 => block("magma_block"),
+        214 => block("nether_wart_block"),
+        215 => block("red_nether_bricks"),
+        216 => block("bone_block").with("axis", pick(&AXIS, data >> 2)),
+        217 => block("structure_void"),
+        218 => block("observer")
+            .with("facing", pick(&FACING, data & 7))
+            .flag("powered", data, 8),
+        219..=234 => named(COLORS[id as usize - 219], "shulker_box")
+            .with("facing", pick(&FACING, data & 7)),
+        235..=250 => named(COLORS[id as usize - 235], "glazed_terracotta")
+            .with("facing", HORIZONTAL[(data & 3) as usize]),
+        251 => named(color, "concrete"),
+        252 => named(color, "concrete_powder"),
+        255 => block("structure_block").with("mode", pick(&["save", "load", "corner", "data"], data)),
+        _ => return None,
+    }.build())
+}
+
+/// Translates a [BlockState] back to a 1.12.2 block id and metadata.
+/// Returns `None` for blocks that 1.12.2 doesn't have.
+///
+/// States that [flatten_block] produces map back to the id and metadata that
+/// produced them. Other states fall back to the block without its properties,
+/// which is the block's first metadata value.
+pub fn unflatten_block(state: &BlockState) -> Option<(u8, u8)> {
+    static IDS: OnceLock<HashMap<BlockState, (u8, u8)>> = OnceLock::new();
+    static NAMES: OnceLock<HashMap<String, (u8, u8)>> = OnceLock::new();
+    let ids = IDS.get_or_init(|| {
+        let mut ids = HashMap::new();
+        (0..=255u8).for_each(|id| {
+            (0..16).for_each(|data| {
+                if let Some(state) = flatten_block(id, data) {
+                    ids.entry(state).or_insert((id, data));
+                }
+            });
+        });
+        ids
+    });
+    ids.get(state).copied().or_else(|| {
+        let names = NAMES.get_or_init(|| {
+            let mut names = HashMap::new();
+            let mut states = ids.iter().collect::<Vec<_>>();
+            // Prefer the lowest id and metadata for each name.
+            states.sort_by_key(|(_, id)| **id);
+            states.into_iter().for_each(|(state, id)| {
+                names.entry(state.name().to_owned()).or_insert(*id);
+            });
+            names
+        });
+        names.get(state.name()).copied()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattening_test() {
+        assert_eq!(flatten_block(1, 3), Some(BlockState::from("minecraft:diorite")));
+        assert_eq!(flatten_block(17, 6), Some(BlockState::new("minecraft:birch_log", [("axis", "x")])));
+        assert_eq!(flatten_block(251, 14), Some(BlockState::from("minecraft:red_concrete")));
+        assert_eq!(
+            flatten_block(53, 6),
+            Some(BlockState::new("minecraft:oak_stairs", [("facing", "south"), ("half", "top")])),
+        );
+        assert_eq!(flatten_block(253, 0), None);
+
+        // Every state maps back to something that flattens to the same state.
+        (0..=255u8).for_each(|id| {
+            (0..16).for_each(|data| {
+                if let Some(state) = flatten_block(id, data) {
+                    let (id, data) = unflatten_block(&state).expect("Every flattened state unflattens.");
+                    assert_eq!(flatten_block(id, data), Some(state));
+                }
+            });
+        });
+        assert_eq!(unflatten_block(&BlockState::new("minecraft:oak_stairs", [("shape", "outer_left")])), Some((53, 0)));
+        assert_eq!(unflatten_block(&BlockState::from("minecraft:deepslate")), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Force-loaded chunks (the `/forceload` command), stored in `data/chunks.dat`
+//! within each dimension's directory.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use flate2::Compression;
+
+use crate::{
+    McResult,
+    math::coord::{Dimension, WorldCoord},
+    nbt::{
+        Map,
+        file::{read_nbt_file, write_nbt_file},
+        tag::{NamedTag, Tag},
+    },
+};
+
+use super::scan::dimension_directory;
+
+/// Packs a chunk coordinate the way that the game does (`ChunkPos.toLong()`).
+pub fn pack_chunk_pos(x: i32, z: i32) -> i64 {
+    (x as u32 as i64) | ((z as u32 as i64) << 32)
+}
+
+/// Unpacks a chunk coordinate that was packed with [pack_chunk_pos].
+pub fn unpack_chunk_pos(packed: i64) -> (i32, i32) {
+    (packed as i32, (packed >> 32) as i32)
+}
+
+/// The force-loaded chunks of a single dimension.
+#[derive(Debug, Clone)]
+pub struct ForcedChunks {
+    dimension: Dimension,
+    /// The root of the file, which is kept so that unknown data is written back.
+    root: Map,
+    chunks: BTreeSet<(i32, i32)>,
+}
+
+impl ForcedChunks {
+    pub fn new(dimension: Dimension) -> Self {
+        Self {
+            dimension,
+            root: Map::new(),
+            chunks: BTreeSet::new(),
+        }
+    }
+
+    /// Gets the path of `chunks.dat` for a dimension.
+    pub fn path<P: AsRef<Path>>(world_directory: P, dimension: Dimension) -> McResult<PathBuf> {
+        Ok(dimension_directory(world_directory, dimension)?.join("data").join("chunks.dat"))
+    }
+
+    /// Loads the force-loaded chunks of a dimension.
+    /// If there is no `chunks.dat`, there are no force-loaded chunks.
+    pub fn load<P: AsRef<Path>>(world_directory: P, dimension: Dimension) -> McResult<Self> {
+        let path = Self::path(world_directory, dimension)?;
+        if !path.is_file() {
+            return Ok(Self::new(dimension));
+        }
+        let Tag::Compound(root) = read_nbt_file(path)?.take_tag() else {
+            return Ok(Self::new(dimension));
+        };
+        let chunks = match root.get("data") {
+            Some(Tag::Compound(data)) => match data.get("Forced") {
+                Some(Tag::LongArray(forced)) => forced.iter().copied().map(unpack_chunk_pos).collect(),
+                _ => BTreeSet::new(),
+            },
+            _ => BTreeSet::new(),
+        };
+        Ok(Self {
+            dimension,
+            root,
+            chunks,
+        })
+    }
+
+    /// Saves the force-loaded chunks to `chunks.dat`, creating the `data` directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, world_directory: P) -> McResult<()> {
+        let path = Self::path(world_directory, self.dimension)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut root = self.root.clone();
+        let mut data = match root.remove("data") {
+            Some(Tag::Compound(data)) => data,
+            _ => Map::new(),
+        };
+        let forced = self.chunks.iter().map(|&(x, z)| pack_chunk_pos(x, z)).collect();
+        data.insert("Forced".to_owned(), Tag::LongArray(forced));
+        root.insert("data".to_owned(), Tag::Compound(data));
+        write_nbt_file(path, &NamedTag::new(root), Compression::default())?;
+        Ok(())
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// Lists the force-loaded chunks, sorted by (x, z).
+    pub fn list(&self) -> Vec<WorldCoord> {
+        self.chunks.iter()
+            .map(|&(x, z)| WorldCoord::new(x as i64, z as i64, self.dimension))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.chunks.contains(&(x, z))
+    }
+
+    /// Adds a chunk. Returns false if it was already force-loaded.
+    pub fn add(&mut self, x: i32, z: i32) -> bool {
+        self.chunks.insert((x, z))
+    }
+
+    /// Removes a chunk. Returns false if it was not force-loaded.
+    pub fn remove(&mut self, x: i32, z: i32) -> bool {
+        self.chunks.remove(&(x, z))
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_pos_packing_test() {
+        [(0, 0), (1, -1), (-30000000, 30000000), (i32::MIN, i32::MAX)].into_iter().for_each(|(x, z)| {
+            assert_eq!(unpack_chunk_pos(pack_chunk_pos(x, z)), (x, z));
+        });
+        assert_eq!(pack_chunk_pos(1, 2), 0x0000_0002_0000_0001);
+    }
+}
@@ -0,0 +1,577 @@
+//! A consistency check for a whole dimension of a world.
+//!
+//! [fsck] runs the individual checkers (unreadable region files and chunks, stale POI
+//! records, entities stored in the wrong chunk, entities that share a UUID, and
+//! heightmaps that can't be right) and collects what they find in a [FsckReport].
+//! Nothing is changed while checking. Each [Problem] that can be repaired has a [Fix],
+//! and [apply_fixes] applies whichever fixes are passed to it (all of [FsckReport::plan],
+//! or only some of them) in a single [WorldTransaction].
+
+use std::{
+    collections::{BTreeMap, HashMap, hash_map::Entry},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    McError, McResult,
+    math::coord::WorldCoord,
+    nbt::{
+        Map,
+        tag::{ListTag, NamedTag, Tag},
+    },
+    util::uuid::{read_uuid, random, to_string, write_uuid, write_uuid_legacy},
+};
+
+use super::{
+    chunk::Heightmap,
+    io::region::{RegionFile, coord::RegionCoord},
+    poi::{StalePoi, check_poi, remove_records},
+    scan::{Quarantine, RegionKind, for_each_chunk_with, region_file_path},
+    search::read_position,
+    selection::WorldSelection,
+    session::{SessionLock, ensure_world_idle},
+    transaction::WorldTransaction,
+};
+
+/// What is wrong with a heightmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeightmapIssue {
+    /// The heightmap is not a long array of a length that a heightmap can have.
+    Malformed,
+    /// A column is taller than the chunk.
+    OutOfRange { highest: i64, chunk_height: i64 },
+}
+
+/// A problem found by [fsck].
+#[derive(Debug)]
+pub enum Problem {
+    /// A region file that couldn't be opened.
+    UnreadableRegion {
+        kind: RegionKind,
+        region_file: PathBuf,
+        error: McError,
+    },
+    /// A chunk that couldn't be read.
+    CorruptChunk {
+        kind: RegionKind,
+        chunk: WorldCoord,
+        error: McError,
+    },
+    /// A POI record whose block no longer matches its type.
+    StalePoi(StalePoi),
+    /// An entity whose position is outside of the chunk that it is stored in.
+    MisplacedEntity {
+        chunk: WorldCoord,
+        /// The index of the entity in the chunk's `Entities`.
+        index: usize,
+        id: Option<String>,
+        /// The chunk that the entity's position is in.
+        belongs_in: WorldCoord,
+    },
+    /// An entity with the same UUID as an entity that was found before it.
+    DuplicateUuid {
+        uuid: u128,
+        chunk: WorldCoord,
+        index: usize,
+        /// The chunk and index of the first entity with this UUID.
+        first: (WorldCoord, usize),
+    },
+    /// A heightmap of a terrain chunk that can't be right.
+    BadHeightmap {
+        chunk: WorldCoord,
+        /// The name of the heightmap, such as `MOTION_BLOCKING`.
+        name: String,
+        issue: HeightmapIssue,
+    },
+}
+
+impl Problem {
+    /// The fix for this problem, if it has one. An unreadable region file has no fix,
+    /// since deleting it could lose chunks that are still readable.
+    pub fn fix(&self) -> Option<Fix> {
+        match self {
+            Problem::UnreadableRegion { .. } => None,
+            Problem::CorruptChunk { kind, chunk, .. } => Some(Fix::DeleteChunk { kind: *kind, chunk: *chunk }),
+            Problem::StalePoi(stale) => Some(Fix::RemovePoi(stale.clone())),
+            Problem::MisplacedEntity { chunk, index, belongs_in, .. } => Some(Fix::MoveEntity {
+                from: *chunk,
+                index: *index,
+                to: *belongs_in,
+            }),
+            Problem::DuplicateUuid { chunk, index, .. } => Some(Fix::ReassignUuid { chunk: *chunk, index: *index }),
+            Problem::BadHeightmap { chunk, name, .. } => Some(Fix::RemoveHeightmap { chunk: *chunk, name: name.clone() }),
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::UnreadableRegion { region_file, error, .. } => write!(f, "{}: {error}", region_file.display()),
+            Problem::CorruptChunk { kind, chunk, error } => write!(f, "{} chunk ({}, {}): {error}", kind.folder(), chunk.x, chunk.z),
+            Problem::StalePoi(stale) => {
+                let pos = stale.record.pos;
+                write!(f, "POI {} at ({}, {}, {}) is on {}", stale.record.poi_type, pos.x, pos.y, pos.z, stale.found)
+            }
+            Problem::MisplacedEntity { chunk, index, id, belongs_in } => write!(
+                f,
+                "Entity {index} ({}) of chunk ({}, {}) belongs in chunk ({}, {})",
+                id.as_deref().unwrap_or("unknown"), chunk.x, chunk.z, belongs_in.x, belongs_in.z,
+            ),
+            Problem::DuplicateUuid { uuid, chunk, index, first } => write!(
+                f,
+                "Entity {index} of chunk ({}, {}) has the UUID {} of entity {} of chunk ({}, {})",
+                chunk.x, chunk.z, to_string(*uuid), first.1, first.0.x, first.0.z,
+            ),
+            Problem::BadHeightmap { chunk, name, issue } => match issue {
+                HeightmapIssue::Malformed => write!(f, "Heightmap {name} of chunk ({}, {}) is malformed", chunk.x, chunk.z),
+                HeightmapIssue::OutOfRange { highest, chunk_height } => write!(
+                    f,
+                    "Heightmap {name} of chunk ({}, {}) has a height of {highest} in a chunk {chunk_height} blocks tall",
+                    chunk.x, chunk.z,
+                ),
+            },
+        }
+    }
+}
+
+/// A repair for a [Problem].
+#[derive(Debug, Clone)]
+pub enum Fix {
+    /// Deletes a chunk.
+    DeleteChunk { kind: RegionKind, chunk: WorldCoord },
+    /// Removes a POI record.
+    RemovePoi(StalePoi),
+    /// Moves an entity to the chunk that its position is in.
+    MoveEntity { from: WorldCoord, index: usize, to: WorldCoord },
+    /// Gives an entity a new random UUID.
+    ReassignUuid { chunk: WorldCoord, index: usize },
+    /// Removes a heightmap. The game computes missing heightmaps when the chunk is loaded.
+    RemoveHeightmap { chunk: WorldCoord, name: String },
+}
+
+/// The problems found by [fsck].
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub problems: Vec<Problem>,
+}
+
+impl FsckReport {
+    /// Returns true if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The fixes for every problem that has one, in the order that the problems were found.
+    pub fn plan(&self) -> Vec<Fix> {
+        self.problems.iter().filter_map(Problem::fix).collect()
+    }
+}
+
+/// One problem per line, after a count.
+impl std::fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} problem(s).", self.problems.len())?;
+        self.problems.iter().try_for_each(|problem| writeln!(f, "{problem}"))
+    }
+}
+
+/// Moves the failures in `quarantine` to `problems`.
+fn add_quarantined(problems: &mut Vec<Problem>, kind: RegionKind, quarantine: Quarantine) {
+    problems.extend(quarantine.into_entries().into_iter().map(|entry| match entry.chunk {
+        Some(chunk) => Problem::CorruptChunk { kind, chunk, error: entry.error },
+        None => Problem::UnreadableRegion { kind, region_file: entry.region_file, error: entry.error },
+    }));
+}
+
+/// The height of a terrain chunk, from the lowest and highest of its sections.
+/// Returns `None` if the chunk has no sections.
+fn chunk_height(root: &Map) -> Option<i64> {
+    let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else {
+        return None;
+    };
+    let ys = sections.iter().filter_map(|section| match section.get("Y") {
+        Some(Tag::Byte(y)) => Some(*y as i64),
+        _ => None,
+    });
+    let top = ys.clone().max()?;
+    let bottom = match root.get("yPos") {
+        Some(Tag::Int(y)) => *y as i64,
+        _ => ys.min()?,
+    };
+    Some((top + 1 - bottom) * 16)
+}
+
+fn check_heightmaps(chunk: WorldCoord, root: &Map, problems: &mut Vec<Problem>) {
+    let Some(Tag::Compound(heightmaps)) = root.get("Heightmaps") else {
+        return;
+    };
+    let height = chunk_height(root);
+    let mut names = heightmaps.keys().collect::<Vec<_>>();
+    names.sort();
+    names.into_iter().for_each(|name| {
+        let heightmap = match &heightmaps[name] {
+            Tag::LongArray(data) => Heightmap::from_longs(data.clone()).ok(),
+            _ => None,
+        };
+        let issue = match (heightmap, height) {
+            (None, _) => HeightmapIssue::Malformed,
+            (Some(heightmap), Some(chunk_height)) => {
+                let highest = (0..256).map(|i| heightmap.get((i & 15, i >> 4))).max().unwrap_or_default();
+                if highest <= chunk_height {
+                    return;
+                }
+                HeightmapIssue::OutOfRange { highest, chunk_height }
+            }
+            (Some(_), None) => return,
+        };
+        problems.push(Problem::BadHeightmap { chunk, name: name.to_owned(), issue });
+    });
+}
+
+/// The entities of an entity chunk.
+fn entities(root: &Tag) -> &[Map] {
+    match root {
+        Tag::Compound(root) => match root.get("Entities") {
+            Some(Tag::List(ListTag::Compound(entities))) => entities,
+            _ => &[],
+        },
+        _ => &[],
+    }
+}
+
+/// The entities of an entity chunk, creating the list if it is missing or empty.
+fn entities_mut(root: &mut Tag) -> Option<&mut Vec<Map>> {
+    let Tag::Compound(root) = root else {
+        return None;
+    };
+    let list = root.entry("Entities".to_owned()).or_insert(Tag::List(ListTag::Empty));
+    if matches!(list, Tag::List(ListTag::Empty)) {
+        *list = Tag::List(ListTag::Compound(Vec::new()));
+    }
+    match list {
+        Tag::List(ListTag::Compound(entities)) => Some(entities),
+        _ => None,
+    }
+}
+
+/// Checks the selected chunks of a world. Nothing is changed; see [apply_fixes].
+/// Errors listing the region files end the check, but a region file or chunk that
+/// can't be read is reported as a [Problem].
+pub fn fsck<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection) -> McResult<FsckReport> {
+    let world_directory = world_directory.as_ref();
+    let mut problems = Vec::new();
+
+    let mut quarantine = Quarantine::new();
+    for_each_chunk_with(world_directory, selection, RegionKind::Terrain, Some(&mut quarantine), |chunk, root| {
+        if let Tag::Compound(root) = root.tag() {
+            check_heightmaps(chunk, root, &mut problems);
+        }
+        Ok(false)
+    })?;
+    add_quarantined(&mut problems, RegionKind::Terrain, quarantine);
+
+    let mut quarantine = Quarantine::new();
+    let mut uuids: HashMap<u128, (WorldCoord, usize)> = HashMap::new();
+    for_each_chunk_with(world_directory, selection, RegionKind::Entities, Some(&mut quarantine), |chunk, root| {
+        entities(root.tag()).iter().enumerate().for_each(|(index, entity)| {
+            if let Some(pos) = read_position(entity) {
+                let belongs_in = WorldCoord::new((pos.x.floor() as i64) >> 4, (pos.z.floor() as i64) >> 4, chunk.dimension);
+                if belongs_in != chunk {
+                    let id = match entity.get("id") {
+                        Some(Tag::String(id)) => Some(id.to_owned()),
+                        _ => None,
+                    };
+                    problems.push(Problem::MisplacedEntity { chunk, index, id, belongs_in });
+                }
+            }
+            if let Some(uuid) = read_uuid(entity, "UUID") {
+                match uuids.entry(uuid) {
+                    Entry::Occupied(first) => problems.push(Problem::DuplicateUuid { uuid, chunk, index, first: *first.get() }),
+                    Entry::Vacant(entry) => {
+                        entry.insert((chunk, index));
+                    }
+                }
+            }
+        });
+        Ok(false)
+    })?;
+    add_quarantined(&mut problems, RegionKind::Entities, quarantine);
+
+    let mut quarantine = Quarantine::new();
+    // Only used to find the POI chunks that can't be read; check_poi reads them again.
+    for_each_chunk_with(world_directory, selection, RegionKind::Poi, Some(&mut quarantine), |_, _| Ok(false))?;
+    let corrupt_poi = !quarantine.is_empty();
+    add_quarantined(&mut problems, RegionKind::Poi, quarantine);
+    if !corrupt_poi {
+        problems.extend(check_poi(world_directory, selection, false)?.into_iter().map(Problem::StalePoi));
+    }
+
+    Ok(FsckReport { problems })
+}
+
+/// Chunks loaded by [apply_fixes], and whether they were changed.
+struct ChunkCache<'a> {
+    world_directory: &'a Path,
+    regions: HashMap<(RegionKind, WorldCoord), Option<RegionFile>>,
+    chunks: BTreeMap<(RegionKind, WorldCoord), (Option<NamedTag>, bool)>,
+}
+
+impl<'a> ChunkCache<'a> {
+    fn new(world_directory: &'a Path) -> Self {
+        Self {
+            world_directory,
+            regions: HashMap::new(),
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the root of a chunk. Returns `None` if the chunk doesn't exist.
+    /// A chunk is only written back if it is marked with [ChunkCache::mark_changed].
+    fn get_mut(&mut self, kind: RegionKind, chunk: WorldCoord) -> McResult<Option<&mut Tag>> {
+        if let std::collections::btree_map::Entry::Vacant(entry) = self.chunks.entry((kind, chunk)) {
+            let region = chunk.region_coord();
+            let regionfile = match self.regions.entry((kind, region)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let path = region_file_path(self.world_directory, region, kind)?;
+                    entry.insert(path.is_file().then(|| RegionFile::open(path)).transpose()?)
+                }
+            };
+            let coord = RegionCoord::from(chunk.xz());
+            let root = match regionfile {
+                Some(regionfile) if !regionfile.get_sector(coord).is_empty() => Some(regionfile.read_data::<_, NamedTag>(coord)?),
+                _ => None,
+            };
+            entry.insert((root, false));
+        }
+        let (root, _) = self.chunks.get_mut(&(kind, chunk)).unwrap();
+        Ok(root.as_mut().map(NamedTag::tag_mut))
+    }
+
+    /// Marks a chunk that was changed through [ChunkCache::get_mut] to be written back.
+    fn mark_changed(&mut self, kind: RegionKind, chunk: WorldCoord) {
+        if let Some((_, changed)) = self.chunks.get_mut(&(kind, chunk)) {
+            *changed = true;
+        }
+    }
+
+    fn insert(&mut self, kind: RegionKind, chunk: WorldCoord, root: NamedTag) {
+        self.chunks.insert((kind, chunk), (Some(root), true));
+    }
+}
+
+/// Applies `fixes` (usually all or part of [FsckReport::plan]) in a single transaction.
+/// Fixes refer to entities by their index when the world was checked, so they must come
+/// from a check of the world as it is now. Returns the number of fixes that were applied;
+/// a fix is skipped if what it refers to is no longer there.
+/// Returns [McError::WorldLocked] if the world appears to be open in another process
+/// (see [ensure_world_idle]; `lock` is the lock that this process holds, if any);
+/// [apply_fixes_unchecked] skips that check.
+pub fn apply_fixes<P: AsRef<Path>>(world_directory: P, fixes: &[Fix], lock: Option<&SessionLock>) -> McResult<usize> {
+    ensure_world_idle(&world_directory, lock)?;
+    apply_fixes_unchecked(world_directory, fixes)
+}
+
+/// Applies `fixes` like [apply_fixes], even if the world appears to be in use.
+pub fn apply_fixes_unchecked<P: AsRef<Path>>(world_directory: P, fixes: &[Fix]) -> McResult<usize> {
+    let world_directory = world_directory.as_ref();
+    let mut cache = ChunkCache::new(world_directory);
+    let mut applied = 0;
+
+    // UUIDs first, so that entities keep their new UUIDs when they are moved.
+    for fix in fixes {
+        let Fix::ReassignUuid { chunk, index } = fix else { continue };
+        let Some(entity) = cache.get_mut(RegionKind::Entities, *chunk)?
+            .and_then(entities_mut)
+            .and_then(|entities| entities.get_mut(*index)) else { continue };
+        if entity.contains_key("UUIDMost") {
+            write_uuid_legacy(entity, "UUID", random());
+        } else {
+            write_uuid(entity, "UUID", random());
+        }
+        cache.mark_changed(RegionKind::Entities, *chunk);
+        applied += 1;
+    }
+
+    // Entities are copied to their new chunks, then removed from the old ones by descending
+    // index, so that the indices of the fixes stay valid.
+    let mut removed: BTreeMap<WorldCoord, Vec<usize>> = BTreeMap::new();
+    for fix in fixes {
+        let Fix::MoveEntity { from, index, to } = fix else { continue };
+        let Some(root) = cache.get_mut(RegionKind::Entities, *from)? else { continue };
+        let data_version = match root {
+            Tag::Compound(root) => root.get("DataVersion").cloned(),
+            _ => None,
+        };
+        let Some(entity) = entities_mut(root).and_then(|entities| entities.get(*index)).cloned() else { continue };
+        match cache.get_mut(RegionKind::Entities, *to)?.and_then(entities_mut) {
+            Some(entities) => {
+                entities.push(entity);
+                cache.mark_changed(RegionKind::Entities, *to);
+            }
+            None => {
+                let mut root = Map::new();
+                if let Some(data_version) = data_version {
+                    root.insert("DataVersion".to_owned(), data_version);
+                }
+                root.insert("Position".to_owned(), Tag::IntArray(vec![to.x as i32, to.z as i32]));
+                root.insert("Entities".to_owned(), Tag::List(ListTag::Compound(vec![entity])));
+                cache.insert(RegionKind::Entities, *to, NamedTag::new(Tag::Compound(root)));
+            }
+        }
+        removed.entry(*from).or_default().push(*index);
+        applied += 1;
+    }
+    for (chunk, mut indices) in removed {
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices.dedup();
+        if let Some(entities) = cache.get_mut(RegionKind::Entities, chunk)?.and_then(entities_mut) {
+            indices.into_iter().for_each(|index| {
+                entities.remove(index);
+            });
+            cache.mark_changed(RegionKind::Entities, chunk);
+        }
+    }
+
+    let mut stale_poi: BTreeMap<WorldCoord, Vec<StalePoi>> = BTreeMap::new();
+    fixes.iter().for_each(|fix| {
+        if let Fix::RemovePoi(stale) = fix {
+            stale_poi.entry(stale.chunk).or_default().push(stale.clone());
+        }
+    });
+    for (chunk, stale) in stale_poi {
+        if cache.get_mut(RegionKind::Poi, chunk)?.is_some_and(|root| remove_records(root, &stale)) {
+            cache.mark_changed(RegionKind::Poi, chunk);
+            applied += stale.len();
+        }
+    }
+
+    for fix in fixes {
+        let Fix::RemoveHeightmap { chunk, name } = fix else { continue };
+        if let Some(Tag::Compound(root)) = cache.get_mut(RegionKind::Terrain, *chunk)? {
+            if let Some(Tag::Compound(heightmaps)) = root.get_mut("Heightmaps") {
+                if heightmaps.remove(name).is_some() {
+                    cache.mark_changed(RegionKind::Terrain, *chunk);
+                    applied += 1;
+                }
+            }
+        }
+    }
+
+    let mut transaction = WorldTransaction::new(world_directory);
+    let mut deleted = Vec::new();
+    for fix in fixes {
+        let Fix::DeleteChunk { kind, chunk } = fix else { continue };
+        transaction.delete_chunk(*kind, *chunk)?;
+        deleted.push((*kind, *chunk));
+        applied += 1;
+    }
+    for ((kind, chunk), (root, changed)) in cache.chunks {
+        if let (Some(root), true) = (root, changed) {
+            if !deleted.contains(&(kind, chunk)) {
+                transaction.write_chunk(kind, chunk, root)?;
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::Dimension,
+        nbt::tagpath::TagPath,
+        world::{
+            blockregistry::BlockRegistry,
+            chunk::{decode_chunk, encode_chunk, tests::empty_chunk},
+        },
+    };
+
+    fn entity(id: &str, x: f64, z: f64, uuid: u128) -> Map {
+        let mut entity = Map::from([
+            ("id".to_owned(), Tag::string(id)),
+            ("Pos".to_owned(), Tag::List(ListTag::Double(vec![x, 64.0, z]))),
+        ]);
+        write_uuid(&mut entity, "UUID", uuid);
+        entity
+    }
+
+    #[test]
+    fn fsck_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        let overworld = |x, z| WorldCoord::new(x, z, Dimension::Overworld);
+        let region = overworld(0, 0);
+
+        let terrain = region_file_path(world, region, RegionKind::Terrain)?;
+        std::fs::create_dir_all(terrain.parent().unwrap())?;
+        let sector = {
+            let registry = BlockRegistry::with_air();
+            let mut root = encode_chunk(&registry, &empty_chunk(0, -4, 0));
+            let Some(Tag::Compound(heightmaps)) = root.get_mut("Heightmaps") else { panic!("Expected heightmaps.") };
+            heightmaps.insert("MOTION_BLOCKING".to_owned(), Tag::LongArray(vec![0; 3]));
+            let mut regionfile = RegionFile::create(&terrain)?;
+            regionfile.write_data((0, 0), &NamedTag::new(Tag::Compound(root)))?;
+            regionfile.write_data((1, 0), &NamedTag::new(Tag::Compound(encode_chunk(&registry, &empty_chunk(1, -4, 0)))))?
+        };
+        let mut bytes = std::fs::read(&terrain)?;
+        bytes[sector.offset() as usize + 5..sector.offset() as usize + 12].fill(0xFF);
+        std::fs::write(&terrain, bytes)?;
+
+        let entities = region_file_path(world, region, RegionKind::Entities)?;
+        std::fs::create_dir_all(entities.parent().unwrap())?;
+        RegionFile::create(&entities)?.write_data((0, 0), &NamedTag::new(Tag::Compound(Map::from([
+            ("DataVersion".to_owned(), Tag::Int(3465)),
+            ("Position".to_owned(), Tag::IntArray(vec![0, 0])),
+            ("Entities".to_owned(), Tag::List(ListTag::Compound(vec![
+                entity("minecraft:pig", 1.5, 1.5, 7),
+                entity("minecraft:cow", 20.5, 1.5, 7),
+                entity("minecraft:sheep", 2.5, 2.5, 8),
+            ]))),
+        ]))))?;
+
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        let report = fsck(world, &selection)?;
+        assert_eq!(report.problems.len(), 4, "{report}");
+        assert!(matches!(&report.problems[0], Problem::BadHeightmap { name, issue: HeightmapIssue::Malformed, .. } if name == "MOTION_BLOCKING"));
+        assert!(matches!(&report.problems[1], Problem::CorruptChunk { kind: RegionKind::Terrain, chunk, .. } if *chunk == overworld(1, 0)));
+        assert!(matches!(&report.problems[2], Problem::MisplacedEntity { index: 1, belongs_in, .. } if *belongs_in == overworld(1, 0)));
+        assert!(matches!(&report.problems[3], Problem::DuplicateUuid { uuid: 7, index: 1, first: (_, 0), .. }));
+
+        // Apply everything but the deletion of the corrupt chunk.
+        let plan = report.plan();
+        assert_eq!(plan.len(), 4);
+        let selected = plan.into_iter().filter(|fix| !matches!(fix, Fix::DeleteChunk { .. })).collect::<Vec<_>>();
+        assert_eq!(apply_fixes(world, &selected, None)?, 3);
+
+        let report = fsck(world, &selection)?;
+        assert_eq!(report.problems.len(), 1, "{report}");
+        assert!(matches!(report.problems[0], Problem::CorruptChunk { .. }));
+        let mut regionfile = RegionFile::open(&entities)?;
+        let moved: NamedTag = regionfile.read_data((1, 0))?;
+        let moved = super::entities(moved.tag());
+        assert!(matches!(moved[0].get("id"), Some(Tag::String(id)) if id == "minecraft:cow"));
+        assert_ne!(read_uuid(&moved[0], "UUID"), Some(7));
+        let root: NamedTag = regionfile.read_data((0, 0))?;
+        assert_eq!(super::entities(root.tag()).len(), 2);
+        let root: NamedTag = RegionFile::open(&terrain)?.read_data((0, 0))?;
+        assert!(root.tag().find_child(TagPath::parse("Heightmaps.MOTION_BLOCKING").unwrap().path()).is_none());
+        // The chunk can still be read without the heightmap.
+        let chunk = decode_chunk(&mut BlockRegistry::with_air(), root.take_tag())?;
+        assert!(chunk.heightmaps.motion_blocking.is_none() && chunk.heightmaps.world_surface.is_some());
+
+        // Fixes that find nothing to change don't rewrite their chunks.
+        let timestamp = RegionFile::open(&entities)?.get_timestamp((0, 0));
+        let stale = [
+            Fix::ReassignUuid { chunk: overworld(0, 0), index: 10 },
+            Fix::RemoveHeightmap { chunk: overworld(0, 0), name: "MOTION_BLOCKING".to_owned() },
+        ];
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(apply_fixes(world, &stale, None)?, 0);
+        assert_eq!(RegionFile::open(&entities)?.get_timestamp((0, 0)), timestamp);
+        Ok(())
+    }
+}
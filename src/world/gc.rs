@@ -0,0 +1,197 @@
+//! Garbage collection for the region files of a dimension.
+//!
+//! Over time, region folders collect files that hold no chunks (after trimming or pruning a
+//! world), files that end in free sectors (after chunks shrink or are deleted), and files
+//! whose chunks are scattered between free sectors. [gc] deletes the first, truncates the
+//! second, and (with [GcOptions::rewrite_threshold]) rewrites the third with their chunks
+//! packed together.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    McResult, McResultExt,
+    ioext::Readable,
+    math::coord::Dimension,
+};
+
+use super::{
+    io::region::{
+        RegionFile, RegionSector, SectorManager,
+        checksum::sidecar_path,
+        header::RegionHeader,
+    },
+    scan::{RegionKind, region_files},
+    session::{SessionLock, ensure_world_idle},
+};
+
+/// The size of the header (the sector and timestamp tables).
+const HEADER_SIZE: u64 = 4096 * 2;
+
+/// Options for [gc].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcOptions {
+    /// Rewrite files whose [fragmentation](super::io::region::SectorLayout::fragmentation)
+    /// (the fraction of their sectors that are free) is at least this, from 0 to 1.
+    /// `None` never rewrites files.
+    pub rewrite_threshold: Option<f64>,
+    /// Only report what would be done, without changing anything.
+    pub dry_run: bool,
+}
+
+/// What [gc] did (or would do, for a dry run).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of region files that were looked at.
+    pub scanned: usize,
+    /// The region files without any chunks, which were deleted.
+    pub deleted: Vec<PathBuf>,
+    /// The number of region files that ended in free sectors, which were cut off.
+    pub truncated: usize,
+    /// The number of fragmented region files that were rewritten.
+    pub rewritten: usize,
+    /// The size of the region files before.
+    pub bytes_before: u64,
+    /// The size of the region files after.
+    pub bytes_after: u64,
+}
+
+impl GcReport {
+    pub fn bytes_saved(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Collects the garbage in the region files (of every [RegionKind]) of a dimension.
+/// See the [module docs](self).
+/// Files that are too small to hold a header are left alone (they are corrupt, see
+/// [fsck](super::fsck)), except for empty files, which are deleted.
+/// Fragmented files are rewritten with [RegionFile::optimize].
+/// Unless it is a dry run, returns [McError::WorldLocked](crate::McError::WorldLocked) if the world
+/// appears to be open in another process (see [ensure_world_idle]; `lock` is the lock
+/// that this process holds, if any).
+pub fn gc<P: AsRef<Path>>(world_directory: P, dimension: Dimension, options: &GcOptions, lock: Option<&SessionLock>) -> McResult<GcReport> {
+    let world_directory = world_directory.as_ref();
+    if !options.dry_run {
+        ensure_world_idle(world_directory, lock)?;
+    }
+    let mut report = GcReport::default();
+    for kind in RegionKind::ALL {
+        for (_, path) in region_files(world_directory, dimension, kind)? {
+            collect_region(&path, options, &mut report).with_region(&path)?;
+        }
+    }
+    Ok(report)
+}
+
+fn collect_region(path: &Path, options: &GcOptions, report: &mut GcReport) -> McResult<()> {
+    let size = path.metadata()?.len();
+    report.scanned += 1;
+    report.bytes_before += size;
+    if size > 0 && size < HEADER_SIZE {
+        report.bytes_after += size;
+        return Ok(());
+    }
+    let header = if size == 0 {
+        RegionHeader::default()
+    } else {
+        RegionHeader::read_from(&mut BufReader::new(File::open(path)?))?
+    };
+    if header.sectors.iter().all(RegionSector::is_empty) {
+        report.deleted.push(path.to_owned());
+        if !options.dry_run {
+            std::fs::remove_file(path)?;
+            let sidecar = sidecar_path(path);
+            if sidecar.is_file() {
+                std::fs::remove_file(sidecar)?;
+            }
+        }
+        return Ok(());
+    }
+    let layout = SectorManager::from_table(&header.sectors).layout_map();
+    let end = layout.sector_count() as u64 * 4096;
+    if options.rewrite_threshold.is_some_and(|threshold| layout.fragmentation() >= threshold && layout.free_sectors() > 0) {
+        let compacted = end - layout.free_sectors() as u64 * 4096;
+        report.rewritten += 1;
+        report.bytes_after += compacted;
+        if !options.dry_run {
+            let mut region = RegionFile::open(path)?;
+            region.optimize()?;
+            region.close()?;
+        }
+        return Ok(());
+    }
+    if size > end {
+        report.truncated += 1;
+        if !options.dry_run {
+            File::options().write(true).open(path)?.set_len(end)?;
+        }
+    }
+    report.bytes_after += size.min(end);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+    use crate::{McError, world::session::lock};
+
+    fn write_noise(region: &mut RegionFile, coord: (i32, i32)) -> McResult<()> {
+        use rand::RngCore;
+        let mut noise = vec![0u8; 20000];
+        rand::thread_rng().fill_bytes(&mut noise);
+        region.write(coord, |writer| {
+            writer.write_all(&noise)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn gc_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let folder = dir.path().join("region");
+        std::fs::create_dir_all(&folder)?;
+        let mut empty = RegionFile::create(folder.join("r.0.0.mca"))?;
+        empty.write_data((0, 0), &1i64)?;
+        empty.delete_data((0, 0))?;
+        empty.close()?;
+        File::create(folder.join("r.1.0.mca"))?;
+        // Random bytes don't compress, so these take up several sectors.
+        let mut trailing = RegionFile::create(folder.join("r.0.1.mca"))?;
+        trailing.write_data((0, 0), &1i64)?;
+        write_noise(&mut trailing, (1, 0))?;
+        trailing.delete_data((1, 0))?;
+        trailing.close()?;
+        let mut fragmented = RegionFile::create(folder.join("r.1.1.mca"))?;
+        write_noise(&mut fragmented, (0, 0))?;
+        fragmented.write_data((1, 0), &2i64)?;
+        fragmented.delete_data((0, 0))?;
+        fragmented.close()?;
+
+        let options = GcOptions { rewrite_threshold: Some(0.5), dry_run: true };
+        let dry = gc(dir.path(), Dimension::Overworld, &options, None)?;
+        assert_eq!((dry.scanned, dry.deleted.len(), dry.truncated, dry.rewritten), (4, 2, 1, 1));
+        assert!(folder.join("r.0.0.mca").is_file());
+
+        let session = lock(dir.path())?;
+        assert!(matches!(gc(dir.path(), Dimension::Overworld, &GcOptions { dry_run: false, ..options }, None), Err(McError::WorldLocked(_))));
+        let report = gc(dir.path(), Dimension::Overworld, &GcOptions { dry_run: false, ..options }, Some(&session))?;
+        assert_eq!(report, dry);
+        assert!(report.bytes_saved() > 0);
+        assert!(!folder.join("r.0.0.mca").exists() && !folder.join("r.1.0.mca").exists());
+        assert_eq!(std::fs::metadata(folder.join("r.0.1.mca"))?.len(), 4096 * 3);
+        assert_eq!(std::fs::metadata(folder.join("r.1.1.mca"))?.len(), 4096 * 3);
+        let mut region = RegionFile::open(folder.join("r.1.1.mca"))?;
+        assert_eq!(region.get_sector((1, 0)).sector_offset(), 2);
+        assert_eq!(region.read_data::<_, i64>((1, 0))?, 2);
+        assert!(region.get_sector((0, 0)).is_empty());
+        let mut region = RegionFile::open(folder.join("r.0.1.mca"))?;
+        assert_eq!(region.read_data::<_, i64>((0, 0))?, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! The build height of each dimension.
+//!
+//! Since 1.18, the overworld goes from Y -64 to 319, while the nether and the end still go
+//! from 0 to 255. Dimension types from datapacks (and those written inline in level.dat's
+//! `WorldGenSettings`) can set any height, with `min_y` and `height`.
+//! [world_height] finds the height of a dimension from all of these.
+
+use std::path::Path;
+
+use crate::{
+    McResult,
+    math::coord::Dimension,
+    nbt::tag::Tag,
+};
+
+use super::{
+    chunk::{Chunk, Heightmap},
+    level::{Level, read_level_from_file},
+    spawn::level_path,
+};
+
+/// The range of Y coordinates that blocks can be placed at in a dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldHeight {
+    /// The lowest Y coordinate.
+    pub min_y: i32,
+    /// The number of blocks from `min_y` to the top. Always a multiple of 16.
+    pub height: u32,
+}
+
+impl WorldHeight {
+    /// The height of every dimension before 1.18, and of the nether and the end since.
+    pub const LEGACY: Self = Self::new(0, 256);
+    /// The height of the overworld since 1.18.
+    pub const OVERWORLD: Self = Self::new(-64, 384);
+    /// The first DataVersion (21w37a) where the overworld is [WorldHeight::OVERWORLD].
+    pub const EXTENDED_HEIGHT_DATA_VERSION: i32 = 2834;
+
+    pub const fn new(min_y: i32, height: u32) -> Self {
+        Self { min_y, height }
+    }
+
+    /// The height of a vanilla dimension type (such as `minecraft:the_nether`) in a world
+    /// saved with `data_version`. Returns `None` for other dimension types.
+    pub fn vanilla(data_version: i32, dimension_type: &str) -> Option<Self> {
+        match dimension_type {
+            "minecraft:overworld" | "minecraft:overworld_caves" if data_version >= Self::EXTENDED_HEIGHT_DATA_VERSION => Some(Self::OVERWORLD),
+            "minecraft:overworld" | "minecraft:overworld_caves" | "minecraft:the_nether" | "minecraft:the_end" => Some(Self::LEGACY),
+            _ => None,
+        }
+    }
+
+    /// Guesses the height of the dimension that a chunk was saved in, from its lowest section
+    /// (`yPos`) and the highest section that holds blocks, for when the dimension isn't known.
+    /// A `yPos` of -4 is a 1.18+ overworld, and 0 is the nether, the end, or a world from
+    /// before 1.18. Any other `yPos` is from a datapack dimension, which is assumed to end
+    /// at the highest section. The height always reaches the highest section.
+    pub fn infer(min_section: i32, top_section: Option<i32>) -> Self {
+        let height = match min_section {
+            min_section if min_section == Self::OVERWORLD.min_section() => Self::OVERWORLD.height,
+            min_section if min_section == Self::LEGACY.min_section() => Self::LEGACY.height,
+            _ => 0,
+        };
+        let top = top_section.map_or(0, |top| (top - min_section + 1).max(0) as u32 * 16);
+        Self::new(min_section * 16, height.max(top))
+    }
+
+    /// The range of block Y coordinates.
+    pub fn range(&self) -> std::ops::Range<i64> {
+        self.min_y as i64..self.max_y()
+    }
+
+    /// The Y coordinate above the highest block (exclusive).
+    pub fn max_y(&self) -> i64 {
+        self.min_y as i64 + self.height as i64
+    }
+
+    /// The Y of the lowest section (`yPos` of a chunk).
+    pub fn min_section(&self) -> i32 {
+        self.min_y.div_euclid(16)
+    }
+
+    pub fn section_count(&self) -> u32 {
+        self.height / 16
+    }
+
+    /// The number of bits per heightmap entry.
+    pub fn heightmap_bits(&self) -> u32 {
+        Heightmap::bits_for_height(self.height)
+    }
+}
+
+impl Default for WorldHeight {
+    fn default() -> Self {
+        Self::OVERWORLD
+    }
+}
+
+/// The ID of a vanilla dimension, such as `minecraft:the_nether`.
+/// Returns `None` for [Dimension::Other].
+pub fn dimension_id(dimension: Dimension) -> Option<&'static str> {
+    match dimension {
+        Dimension::Overworld => Some("minecraft:overworld"),
+        Dimension::Nether => Some("minecraft:the_nether"),
+        Dimension::TheEnd => Some("minecraft:the_end"),
+        Dimension::Other(_) => None,
+    }
+}
+
+/// Finds the height of the dimension with the ID `dimension_id` (such as `minecraft:overworld`
+/// or `mypack:mining`) in a world. The dimension's type is looked up in level.dat's
+/// `WorldGenSettings` (where it may be written inline), then in the world's datapacks, and
+/// then among the vanilla types. Without level.dat, the world is assumed to be as new as
+/// [Chunk::DATA_VERSION]. Returns `None` if the dimension type can't be found.
+pub fn world_height<P: AsRef<Path>>(world_directory: P, dimension_id: &str) -> McResult<Option<WorldHeight>> {
+    let world_directory = world_directory.as_ref();
+    let path = level_path(world_directory);
+    let level = if path.is_file() { Some(read_level_from_file(path)?) } else { None };
+    let data_version = level.as_ref().map_or(Chunk::DATA_VERSION, Level::data_version);
+    let dimension_type = level.as_ref()
+        .and_then(|level| match level.world_gen_settings().get("dimensions") {
+            Some(Tag::Compound(dimensions)) => match dimensions.get(dimension_id) {
+                Some(Tag::Compound(dimension)) => dimension.get("type"),
+                _ => None,
+            },
+            _ => None,
+        });
+    let type_id = match dimension_type {
+        Some(Tag::Compound(inline)) => {
+            return Ok(match (inline.get("min_y"), inline.get("height")) {
+                (Some(&Tag::Int(min_y)), Some(&Tag::Int(height))) => Some(WorldHeight::new(min_y, height as u32)),
+                _ => None,
+            });
+        }
+        Some(Tag::String(type_id)) => type_id.as_str(),
+        _ => dimension_id,
+    };
+    if let Some(height) = datapack_height(world_directory, type_id)? {
+        return Ok(Some(height));
+    }
+    Ok(WorldHeight::vanilla(data_version, type_id))
+}
+
+/// Reads the height of a dimension type from the (unzipped) datapacks of a world.
+fn datapack_height(world_directory: &Path, type_id: &str) -> McResult<Option<WorldHeight>> {
+    let (namespace, name) = type_id.split_once(':').unwrap_or(("minecraft", type_id));
+    let datapacks = world_directory.join("datapacks");
+    if !datapacks.is_dir() {
+        return Ok(None);
+    }
+    let mut packs = std::fs::read_dir(datapacks)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    packs.sort();
+    for pack in packs {
+        let path = pack.join("data").join(namespace).join("dimension_type").join(format!("{name}.json"));
+        if !path.is_file() {
+            continue;
+        }
+        let json = std::fs::read_to_string(path)?;
+        if let (Some(min_y), Some(height)) = (json_int(&json, "min_y"), json_int(&json, "height")) {
+            return Ok(Some(WorldHeight::new(min_y as i32, height as u32)));
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the integer value of `key` in a JSON object. This only needs to handle the
+/// flat fields of a dimension type, so it doesn't parse the rest of the JSON.
+fn json_int(json: &str, key: &str) -> Option<i64> {
+    let quoted = format!("\"{key}\"");
+    json.match_indices(&quoted).find_map(|(index, _)| {
+        let rest = json[index + quoted.len()..].trim_start().strip_prefix(':')?.trim_start();
+        let end = rest.char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+            .map_or(rest.len(), |(i, _)| i);
+        rest[..end].parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        create::{WorldOptions, create_new},
+        level::LevelBuilder,
+        world::VirtualJavaWorld,
+    };
+
+    #[test]
+    fn world_height_test() -> McResult<()> {
+        assert_eq!(WorldHeight::vanilla(2730, "minecraft:overworld"), Some(WorldHeight::LEGACY));
+        assert_eq!(WorldHeight::OVERWORLD.min_section(), -4);
+        assert_eq!(WorldHeight::OVERWORLD.section_count(), 24);
+        assert_eq!(WorldHeight::OVERWORLD.heightmap_bits(), 9);
+        assert_eq!(WorldHeight::infer(-4, Some(19)), WorldHeight::OVERWORLD);
+        assert_eq!(WorldHeight::infer(0, Some(15)), WorldHeight::LEGACY);
+        assert_eq!(WorldHeight::infer(0, None), WorldHeight::LEGACY);
+        assert_eq!(WorldHeight::infer(-8, Some(23)), WorldHeight::new(-128, 512));
+        // Blocks above the usual height mean a taller dimension.
+        assert_eq!(WorldHeight::infer(0, Some(19)), WorldHeight::new(0, 320));
+
+        let dir = tempfile::tempdir()?;
+        create_new(dir.path(), LevelBuilder::new("Heights"), &WorldOptions::default())?;
+        assert_eq!(world_height(dir.path(), "minecraft:overworld")?, Some(WorldHeight::OVERWORLD));
+        assert_eq!(world_height(dir.path(), "minecraft:the_nether")?, Some(WorldHeight::LEGACY));
+        assert_eq!(world_height(dir.path(), "mypack:mining")?, None);
+        let types = dir.path().join("datapacks/pack/data/mypack/dimension_type");
+        std::fs::create_dir_all(&types)?;
+        std::fs::write(types.join("mining.json"), r#"{ "logical_height": 256, "min_y": -128, "height": 512, "ultrawarm": false }"#)?;
+        assert_eq!(world_height(dir.path(), "mypack:mining")?, Some(WorldHeight::new(-128, 512)));
+
+        let mut world = VirtualJavaWorld::open(dir.path());
+        assert_eq!(world.height(Dimension::Nether)?.range(), 0..256);
+        assert!(world.height(Dimension::Other(0)).is_err());
+        world.set_height(Dimension::Other(0), WorldHeight::new(-128, 512));
+        let chunk = Chunk::with_height(0, 0, world.height(Dimension::Other(0))?);
+        assert_eq!(chunk.height_range(), -128..384);
+        assert_eq!(chunk.heightmaps.world_surface.as_ref().map(Heightmap::bits), Some(10));
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! Checksum sidecar files for region files.
+//!
+//! A sidecar (`r.0.0.mca.sum` next to `r.0.0.mca`) holds a CRC32 of the stored
+//! (compressed) data of every chunk in the region file. When a region file with
+//! a sidecar is opened, each chunk is checked against it as it is read, so corruption
+//! is reported as [McError::ChecksumMismatch] for that chunk instead of turning up
+//! as a decode error (the rest of the file can still be read). The whole file can be
+//! checked with [super::RegionFile::verify_checksums]. [super::RegionFile] keeps the
+//! sidecar up to date as it writes.
+//!
+//! Other programs (such as the game) don't know about sidecars, so a sidecar that was
+//! last modified before its region file is stale and is ignored.
+//!
+//! Sidecar layout (big-endian):
+//! ```text
+//! magic: u32 = 0x4D53554D ("MSUM")
+//! version: u32 = 1
+//! checksums: [u32; 1024] (0 for chunks that are not present)
+//! ```
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+};
+
+use super::{
+    coord::RegionCoord,
+    header::SectorTable,
+    positioned::PositionedIo,
+};
+
+/// The extension that is appended to the region file's name.
+pub const SIDECAR_EXTENSION: &str = "sum";
+
+const MAGIC: u32 = 0x4D53554D;
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 8;
+
+/// The path of the sidecar for the region file at `region_path`.
+pub fn sidecar_path<P: AsRef<Path>>(region_path: P) -> PathBuf {
+    let mut path = region_path.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(SIDECAR_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Computes the checksum of a chunk's stored data (the length, compression scheme, and payload).
+/// Padding is not included.
+pub fn chunk_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// The stored data (without padding) at the start of the sectors of a chunk.
+/// If the length is larger than `sector_data`, all of `sector_data` is returned.
+pub(crate) fn stored_part(sector_data: &[u8]) -> &[u8] {
+    let Some(length) = sector_data.first_chunk::<4>() else {
+        return sector_data;
+    };
+    let size = (u32::from_be_bytes(*length) as usize).saturating_add(4);
+    &sector_data[..size.min(sector_data.len())]
+}
+
+/// Reads the stored data of the chunk at `coord` (without padding).
+/// Returns an empty Vec if the chunk is not present.
+pub(crate) fn read_stored<F: PositionedIo>(file: &F, sectors: &SectorTable, coord: RegionCoord) -> McResult<Vec<u8>> {
+    let sector = sectors[coord.index()];
+    if sector.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut length = [0u8; 4];
+    file.read_exact_at(&mut length, sector.offset())?;
+    let size = u32::from_be_bytes(length) as u64 + 4;
+    if size > sector.size() {
+        return Err(McError::InvalidRegionFile);
+    }
+    let mut data = vec![0u8; size as usize];
+    file.read_exact_at(&mut data, sector.offset())?;
+    Ok(data)
+}
+
+/// The checksums of every chunk in a region file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionChecksums(Box<[u32; 1024]>);
+
+impl Default for RegionChecksums {
+    fn default() -> Self {
+        Self(Box::new([0; 1024]))
+    }
+}
+
+impl RegionChecksums {
+    /// Computes the checksums of all chunks in a region file.
+    pub fn compute<F: PositionedIo>(file: &F, sectors: &SectorTable) -> McResult<Self> {
+        let mut checksums = Self::default();
+        for index in 0..1024 {
+            let coord = RegionCoord::from(index);
+            let data = read_stored(file, sectors, coord)?;
+            if !data.is_empty() {
+                checksums.0[index] = chunk_checksum(&data);
+            }
+        }
+        Ok(checksums)
+    }
+
+    pub fn get(&self, coord: RegionCoord) -> u32 {
+        self.0[coord.index()]
+    }
+
+    pub fn set(&mut self, coord: RegionCoord, checksum: u32) {
+        self.0[coord.index()] = checksum;
+    }
+
+    /// Returns the coordinates of the chunks in the file that don't match their checksum.
+    pub fn verify<F: PositionedIo>(&self, file: &F, sectors: &SectorTable) -> McResult<Vec<RegionCoord>> {
+        let actual = Self::compute(file, sectors)?;
+        Ok((0..1024)
+            .filter(|&index| actual.0[index] != self.0[index])
+            .map(RegionCoord::from)
+            .collect())
+    }
+
+    /// Checks the stored data of the chunk at `coord` against its checksum.
+    /// Returns [McError::ChecksumMismatch] (naming only `coord`) if it doesn't match.
+    pub fn check<P: AsRef<Path>>(&self, region_path: P, coord: RegionCoord, stored: &[u8]) -> McResult<()> {
+        if chunk_checksum(stored) != self.get(coord) {
+            return Err(McError::ChecksumMismatch(region_path.as_ref().to_owned(), vec![coord]));
+        }
+        Ok(())
+    }
+
+    /// Loads the sidecar of the region file at `region_path`, or returns `None` if there isn't one.
+    /// The sidecar is loaded even if it is stale (see [RegionChecksums::load_current_sidecar]).
+    pub fn load_sidecar<P: AsRef<Path>>(region_path: P) -> McResult<Option<Self>> {
+        let path = sidecar_path(region_path);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut reader = std::io::BufReader::with_capacity(IoConfig::default().read_buf, File::open(path)?);
+        Ok(Some(reader.read_value()?))
+    }
+
+    /// Loads the sidecar of the region file at `region_path`, or returns `None` if there isn't one
+    /// or it is stale (it was last modified before the region file).
+    pub fn load_current_sidecar<P: AsRef<Path>>(region_path: P) -> McResult<Option<Self>> {
+        let region_path = region_path.as_ref();
+        let path = sidecar_path(region_path);
+        if !path.is_file() || path.metadata()?.modified()? < region_path.metadata()?.modified()? {
+            return Ok(None);
+        }
+        Self::load_sidecar(region_path)
+    }
+
+    /// Writes the sidecar for the region file at `region_path`.
+    pub fn save_sidecar<P: AsRef<Path>>(&self, region_path: P) -> McResult<()> {
+        atomic_replace(sidecar_path(region_path), |file| {
+            let mut writer = std::io::BufWriter::with_capacity(IoConfig::default().write_buf, file);
+            writer.write_all_value(self)?;
+            writer.flush()?;
+            Ok(())
+        })
+    }
+}
+
+impl Readable for RegionChecksums {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        let magic: u32 = reader.read_value()?;
+        let version: u32 = reader.read_value()?;
+        if magic != MAGIC || version != VERSION {
+            return McError::custom("Invalid checksum sidecar.");
+        }
+        let mut checksums = Self::default();
+        for checksum in checksums.0.iter_mut() {
+            *checksum = reader.read_value()?;
+        }
+        Ok(checksums)
+    }
+}
+
+impl Writable for RegionChecksums {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        writer.write_value(MAGIC)?;
+        writer.write_value(VERSION)?;
+        for checksum in self.0.iter() {
+            writer.write_value(*checksum)?;
+        }
+        Ok(HEADER_SIZE as usize + 4096)
+    }
+}
+
+/// An open sidecar file that is updated as chunks are written.
+pub(crate) struct ChecksumSidecar {
+    pub checksums: RegionChecksums,
+    file: File,
+}
+
+impl ChecksumSidecar {
+    /// Creates (or overwrites) the sidecar for `region_path` with `checksums`.
+    pub fn create<P: AsRef<Path>>(region_path: P, checksums: RegionChecksums) -> McResult<Self> {
+        checksums.save_sidecar(&region_path)?;
+        Self::open(region_path, checksums)
+    }
+
+    /// Opens an existing sidecar whose contents are `checksums`.
+    pub fn open<P: AsRef<Path>>(region_path: P, checksums: RegionChecksums) -> McResult<Self> {
+        let file = File::options().read(true).write(true).open(sidecar_path(region_path))?;
+        Ok(Self { checksums, file })
+    }
+
+    /// Flushes the sidecar to disk.
+    pub fn sync(&self) -> McResult<()> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    /// Updates the checksum for `coord` in memory and on disk.
+    pub fn update(&mut self, coord: RegionCoord, checksum: u32) -> McResult<()> {
+        self.checksums.set(coord, checksum);
+        self.file.write_all_at(&checksum.to_be_bytes(), HEADER_SIZE + coord.index() as u64 * 4)?;
+        Ok(())
+    }
+
+    /// Sets the modification time of the sidecar to now. This is done after the
+    /// region file is written so that the sidecar isn't taken to be stale.
+    pub fn touch(&self) -> McResult<()> {
+        self.file.set_modified(SystemTime::now())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::{RegionFile, RegionReader};
+
+    #[test]
+    fn sidecar_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let sector = {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &1i64)?;
+            region.enable_checksums()?;
+            region.write_data((1, 0), &2i64)?;
+            region.delete_data((0, 0))?;
+            region.write_data((2, 0), &3i64)?
+        };
+        assert!(sidecar_path(&path).is_file());
+        {
+            let mut region = RegionFile::open(&path)?;
+            assert_eq!(region.read_data::<_, i64>((2, 0))?, 3);
+            assert!(region.checksums().is_some_and(|sums| sums.get(RegionCoord::from((0, 0))) == 0));
+        }
+        // Flip a bit in the compressed payload of (2, 0), keeping the modification time
+        // of the region file (as bit rot would).
+        let modified = std::fs::metadata(&path)?.modified()?;
+        let file = File::options().read(true).write(true).open(&path)?;
+        let mut byte = [0u8];
+        file.read_exact_at(&mut byte, sector.offset() + 6)?;
+        file.write_all_at(&[byte[0] ^ 1], sector.offset() + 6)?;
+        file.set_modified(modified)?;
+        drop(file);
+        let mismatch = vec![RegionCoord::from((2, 0))];
+        {
+            let mut region = RegionFile::open(&path)?;
+            let Err(McError::ChecksumMismatch(_, chunks)) = region.read_data::<_, i64>((2, 0)) else {
+                panic!("Expected a checksum mismatch.");
+            };
+            assert_eq!(chunks, mismatch);
+            assert_eq!(region.read_data::<_, i64>((1, 0))?, 2);
+            assert_eq!(region.verify_checksums()?, mismatch);
+        }
+        let reader = RegionReader::open(&path)?;
+        assert!(matches!(reader.read_data::<_, i64>((2, 0)), Err(McError::ChecksumMismatch(..))));
+        assert_eq!(reader.read_data::<_, i64>((1, 0))?, 2);
+        assert_eq!(reader.verify_checksums()?, mismatch);
+        // Another program writing the region file makes the sidecar stale, so it is ignored.
+        let sidecar = File::options().write(true).open(sidecar_path(&path))?;
+        sidecar.set_modified(modified - std::time::Duration::from_secs(60))?;
+        assert!(RegionFile::open(&path)?.checksums().is_none());
+        assert!(RegionReader::open(&path)?.checksums().is_none());
+        Ok(())
+    }
+}
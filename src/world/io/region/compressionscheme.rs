@@ -5,6 +5,7 @@ use crate::{
 };
 
 /// Compression scheme used for writing or reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum CompressionScheme {
     /// GZip compression is used.
@@ -13,25 +14,102 @@ pub enum CompressionScheme {
     ZLib = 2,
     /// Data is uncompressed.
     Uncompressed = 3,
+    /// A custom compression algorithm. The ID is followed by the namespaced
+    /// name of the algorithm (prefixed with its length as a u16), such as
+    /// `minecraft:zstd`.
+    Custom = 127,
 }
 
-impl Writable for CompressionScheme {
-    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
-        match self {
-            CompressionScheme::GZip => writer.write_value(1u8),
-            CompressionScheme::ZLib => writer.write_value(2u8),
-            CompressionScheme::Uncompressed => writer.write_value(3u8),
+impl CompressionScheme {
+    /// The scheme byte that is stored before the chunk's payload.
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Guesses the scheme of a chunk payload (the bytes after the scheme byte) from its first bytes:
+    /// the GZip magic number, a valid ZLib header, the tag ID of an uncompressed root compound,
+    /// or the length-prefixed `namespace:name` of a custom scheme.
+    /// This is for recovering chunks whose scheme byte was corrupted, so it can be wrong.
+    pub fn detect(payload: &[u8]) -> Option<Self> {
+        match payload {
+            [0x1F, 0x8B, ..] => Some(Self::GZip),
+            // Deflate, and the header checksum is a multiple of 31.
+            [cmf, flg, ..] if cmf & 0x0F == 8 && (*cmf as u16 * 256 + *flg as u16).is_multiple_of(31) => Some(Self::ZLib),
+            [10, ..] => Some(Self::Uncompressed),
+            [high, low, rest @ ..] => {
+                let len = u16::from_be_bytes([*high, *low]) as usize;
+                let name = rest.get(..len)?;
+                (len > 0 && name.contains(&b':') && name.iter().all(|byte| byte.is_ascii_graphic())).then_some(Self::Custom)
+            }
+            _ => None,
         }
     }
 }
 
-impl Readable for CompressionScheme {
-    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
-        match reader.read_value::<u8>()? {
+impl TryFrom<u8> for CompressionScheme {
+    type Error = McError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
             1 => Ok(Self::GZip),
             2 => Ok(Self::ZLib),
             3 => Ok(Self::Uncompressed),
+            127 => Ok(Self::Custom),
             unexpected => Err(McError::InvalidCompressionScheme(unexpected)),
         }
     }
+}
+
+impl From<CompressionScheme> for u8 {
+    fn from(value: CompressionScheme) -> Self {
+        value.as_u8()
+    }
+}
+
+impl std::fmt::Display for CompressionScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CompressionScheme::GZip => "GZip",
+            CompressionScheme::ZLib => "ZLib",
+            CompressionScheme::Uncompressed => "Uncompressed",
+            CompressionScheme::Custom => "Custom",
+        })
+    }
+}
+
+impl Writable for CompressionScheme {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        writer.write_value(self.as_u8())
+    }
+}
+
+impl Readable for CompressionScheme {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        Self::try_from(reader.read_value::<u8>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::{GzEncoder, ZlibEncoder}};
+
+    #[test]
+    fn detect_test() -> McResult<()> {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"chunk")?;
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::best());
+        zlib.write_all(b"chunk")?;
+        assert_eq!(CompressionScheme::detect(&gzip.finish()?), Some(CompressionScheme::GZip));
+        assert_eq!(CompressionScheme::detect(&zlib.finish()?), Some(CompressionScheme::ZLib));
+        assert_eq!(CompressionScheme::detect(&[10, 0, 0, 0]), Some(CompressionScheme::Uncompressed));
+        assert_eq!(CompressionScheme::detect(b"\x00\x0eminecraft:zstd\x28\xb5"), Some(CompressionScheme::Custom));
+        assert_eq!(CompressionScheme::detect(&[0, 0, 0]), None);
+        for scheme in [CompressionScheme::GZip, CompressionScheme::ZLib, CompressionScheme::Uncompressed, CompressionScheme::Custom] {
+            assert_eq!(CompressionScheme::try_from(scheme.as_u8())?, scheme);
+        }
+        assert!(matches!(CompressionScheme::try_from(4), Err(McError::InvalidCompressionScheme(4))));
+        assert_eq!(CompressionScheme::ZLib.to_string(), "ZLib");
+        Ok(())
+    }
 }
\ No newline at end of file
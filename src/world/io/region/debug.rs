@@ -0,0 +1,143 @@
+//! Raw diagnostics for a single chunk of a region file, for bug reports about corrupt chunks.
+//!
+//! [SectorDebug] (from [RegionFile::debug_sector](super::RegionFile::debug_sector)) holds
+//! the header entry of a chunk and the first bytes of its sectors, without decompressing
+//! anything. Its [Display](std::fmt::Display) impl prints a summary followed by a hexdump.
+
+use std::fmt::{Display, Formatter, Result};
+
+use super::{
+    CompressionScheme,
+    RegionCoord,
+    RegionSector,
+    Timestamp,
+};
+
+/// The number of bytes that [RegionFile::debug_sector](super::RegionFile::debug_sector) reads.
+pub const DEBUG_BYTES: usize = 256;
+
+/// The raw state of a chunk in a region file.
+#[derive(Debug, Clone)]
+pub struct SectorDebug {
+    pub coord: RegionCoord,
+    /// The entry of the sector table.
+    pub sector: RegionSector,
+    pub timestamp: Timestamp,
+    /// The length of the file, to tell if the sector is past its end.
+    pub file_length: u64,
+    /// The length at the start of the sector (the scheme byte and the payload),
+    /// if the sector holds at least 4 bytes.
+    pub length: Option<u32>,
+    /// The byte after the length.
+    pub scheme_byte: Option<u8>,
+    /// The scheme that [CompressionScheme::detect] finds from the start of the payload.
+    pub detected_scheme: Option<CompressionScheme>,
+    /// The first bytes of the sector (starting with the length), at most the number that was asked for.
+    pub bytes: Vec<u8>,
+}
+
+impl SectorDebug {
+    /// The scheme named by the scheme byte, if it is a valid one.
+    pub fn scheme(&self) -> Option<CompressionScheme> {
+        CompressionScheme::try_from(self.scheme_byte?).ok()
+    }
+
+    /// Describes what is wrong with the chunk's header, if anything can be told from it.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.sector.is_empty() {
+            return problems;
+        }
+        if self.sector.sector_offset() < 2 {
+            problems.push("the sector overlaps the header".to_owned());
+        }
+        if self.sector.end_offset() > self.file_length {
+            problems.push(format!("the sector ends at byte {}, past the end of the file ({} bytes)", self.sector.end_offset(), self.file_length));
+        }
+        match self.length {
+            None => problems.push("the length can't be read".to_owned()),
+            Some(0) => problems.push("the length is 0".to_owned()),
+            Some(length) if length as u64 + 4 > self.sector.size() => {
+                problems.push(format!("the length ({length}) doesn't fit in {} sectors", self.sector.sector_count()));
+            }
+            Some(_) => {}
+        }
+        if let Some(byte) = self.scheme_byte {
+            let scheme = self.scheme();
+            if scheme.is_none() {
+                problems.push(format!("the scheme byte ({byte}) is not a known scheme"));
+            }
+            if let Some(detected) = self.detected_scheme.filter(|&detected| Some(detected) != scheme) {
+                problems.push(format!("the payload looks like {detected}"));
+            }
+        }
+        problems
+    }
+}
+
+/// Writes `bytes` as a hexdump: the offset (starting at `start`), 16 bytes in hex, and the
+/// bytes as ASCII (with `.` for anything that isn't printable).
+pub fn write_hexdump<W: std::fmt::Write>(writer: &mut W, bytes: &[u8], start: u64) -> std::fmt::Result {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        write!(writer, "{:08x} ", start + line as u64 * 16)?;
+        for column in 0..16 {
+            if column == 8 {
+                writer.write_char(' ')?;
+            }
+            match chunk.get(column) {
+                Some(byte) => write!(writer, " {byte:02x}")?,
+                None => writer.write_str("   ")?,
+            }
+        }
+        writer.write_str("  |")?;
+        for &byte in chunk {
+            writer.write_char(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })?;
+        }
+        writeln!(writer, "|")?;
+    }
+    Ok(())
+}
+
+/// Formats `bytes` with [write_hexdump].
+pub fn hexdump(bytes: &[u8], start: u64) -> String {
+    let mut text = String::new();
+    // Writing to a String can't fail.
+    let _ = write_hexdump(&mut text, bytes, start);
+    text
+}
+
+impl Display for SectorDebug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "chunk {} (index {})", self.coord, self.coord.index())?;
+        if self.sector.is_empty() {
+            return writeln!(f, "sector: empty");
+        }
+        writeln!(
+            f,
+            "sector: offset {} ({} sectors, bytes {}..{}), file length {}",
+            self.sector.sector_offset(),
+            self.sector.sector_count(),
+            self.sector.offset(),
+            self.sector.end_offset(),
+            self.file_length,
+        )?;
+        writeln!(f, "timestamp: {}", u32::from(self.timestamp))?;
+        match self.length {
+            Some(length) => writeln!(f, "length: {length}")?,
+            None => writeln!(f, "length: unreadable")?,
+        }
+        match (self.scheme_byte, self.scheme()) {
+            (Some(byte), Some(scheme)) => writeln!(f, "scheme: {byte} ({scheme})")?,
+            (Some(byte), None) => writeln!(f, "scheme: {byte} (unknown)")?,
+            (None, _) => writeln!(f, "scheme: unreadable")?,
+        }
+        if let Some(detected) = self.detected_scheme {
+            writeln!(f, "detected: {detected}")?;
+        }
+        for problem in self.problems() {
+            writeln!(f, "problem: {problem}")?;
+        }
+        writeln!(f, "first {} bytes:", self.bytes.len())?;
+        write_hexdump(f, &self.bytes, self.sector.offset())
+    }
+}
@@ -0,0 +1,262 @@
+//! Abstraction over the on-disk layout of region files.
+//!
+//! The vanilla Anvil format (`.mca`) is implemented by [RegionFile]. Other
+//! layouts (such as the linear format used by some server forks) can implement
+//! [RegionFormat] so that code that only needs to read and write chunks does
+//! not need to know which format it is working with.
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+};
+#[cfg(feature = "world")]
+use crate::{
+    nbt::tag::NamedTag,
+    world::{blockregistry::BlockRegistry, codec::ChunkCodec},
+};
+
+use super::{
+    coord::RegionCoord,
+    name::RegionExtension,
+    timestamp::Timestamp,
+    regionfile::RegionFile,
+    sectormanager::SectorAllocator,
+};
+
+/// Layout constants for the Anvil region format.
+pub struct Anvil;
+
+impl Anvil {
+    /// File extension of Anvil region files, without the leading dot.
+    pub const EXTENSION: &'static str = "mca";
+    /// Size of a sector in bytes.
+    pub const SECTOR_SIZE: u64 = 4096;
+    /// Size of the header (sector table and timestamp table) in bytes.
+    pub const HEADER_SIZE: u64 = Self::SECTOR_SIZE * 2;
+    /// The maximum number of sectors that a single chunk can occupy.
+    pub const MAX_CHUNK_SECTORS: u32 = 255;
+}
+
+/// Layout constants for the MCRegion format (`.mcr`), used from Beta 1.3 until
+/// Anvil replaced it in 1.2. The file layout is the same as Anvil; only the chunk
+/// NBT differs (see [crate::world::legacy]).
+pub struct McRegion;
+
+impl McRegion {
+    /// File extension of MCRegion files, without the leading dot.
+    pub const EXTENSION: &'static str = "mcr";
+}
+
+/// An MCRegion (`.mcr`) file. This is a [RegionFile] that reports the MCRegion extension.
+pub struct McRegionFile(pub RegionFile);
+
+impl McRegionFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Ok(Self(RegionFile::open(path)?))
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Ok(Self(RegionFile::create(path)?))
+    }
+
+    pub fn into_inner(self) -> RegionFile {
+        self.0
+    }
+}
+
+/// A region file backend. Chunk data passed to and returned from a
+/// [RegionFormat] is uncompressed; compression is the backend's concern.
+pub trait RegionFormat {
+    /// The file extension used by this format, without the leading dot.
+    fn extension(&self) -> &'static str;
+    /// The path of the underlying file.
+    fn path(&self) -> &Path;
+    /// Returns true if there is data stored for the chunk at `coord`.
+    fn has_chunk(&self, coord: RegionCoord) -> bool;
+    /// The timestamp of the chunk at `coord`, or `None` if there is no data stored.
+    fn chunk_timestamp(&self, coord: RegionCoord) -> Option<Timestamp>;
+    /// Reads the uncompressed data of the chunk at `coord`.
+    /// Returns [McError::RegionDataNotFound] if there is no data stored.
+    fn read_chunk_bytes(&mut self, coord: RegionCoord) -> McResult<Vec<u8>>;
+    /// Writes uncompressed chunk data to `coord` with the given timestamp.
+    fn write_chunk_bytes(&mut self, coord: RegionCoord, data: &[u8], timestamp: Timestamp) -> McResult<()>;
+    /// Removes the chunk at `coord`. Does nothing if there is no data stored.
+    fn delete_chunk(&mut self, coord: RegionCoord) -> McResult<()>;
+
+    /// Persists any pending changes. Backends that write through do nothing.
+    fn flush(&mut self) -> McResult<()> {
+        Ok(())
+    }
+
+    /// The coordinates of every chunk stored in the region, in index order.
+    fn chunks(&self) -> Vec<RegionCoord> {
+        (0..1024u16).map(RegionCoord::from)
+            .filter(|coord| self.has_chunk(*coord))
+            .collect()
+    }
+}
+
+/// Typed reading and writing on top of [RegionFormat].
+pub trait RegionFormatExt: RegionFormat {
+    fn read_chunk<T: Readable>(&mut self, coord: RegionCoord) -> McResult<T> {
+        let data = self.read_chunk_bytes(coord)?;
+        T::read_from(&mut data.as_slice())
+    }
+
+    fn write_chunk<T: Writable>(&mut self, coord: RegionCoord, value: &T, timestamp: Timestamp) -> McResult<()> {
+        let mut data = Vec::new();
+        value.write_to(&mut data)?;
+        self.write_chunk_bytes(coord, &data, timestamp)
+    }
+
+    /// Reads a chunk and decodes it with `codec`.
+    #[cfg(feature = "world")]
+    fn read_chunk_with<C: ChunkCodec>(&mut self, coord: RegionCoord, codec: &C, registry: &mut BlockRegistry) -> McResult<C::Chunk> {
+        let root: NamedTag = self.read_chunk(coord)?;
+        codec.decode(registry, root.take_tag())
+    }
+
+    /// Encodes a chunk with `codec` and writes it.
+    #[cfg(feature = "world")]
+    fn write_chunk_with<C: ChunkCodec>(&mut self, coord: RegionCoord, codec: &C, registry: &BlockRegistry, chunk: &C::Chunk, timestamp: Timestamp) -> McResult<()> {
+        let root = NamedTag::new(codec.encode(registry, chunk)?);
+        self.write_chunk(coord, &root, timestamp)
+    }
+}
+
+impl<F: RegionFormat + ?Sized> RegionFormatExt for F {}
+
+impl<A: SectorAllocator> RegionFormat for RegionFile<A> {
+    fn extension(&self) -> &'static str {
+        Anvil::EXTENSION
+    }
+
+    fn path(&self) -> &Path {
+        RegionFile::path(self)
+    }
+
+    fn has_chunk(&self, coord: RegionCoord) -> bool {
+        !self.get_sector(coord).is_empty()
+    }
+
+    fn chunk_timestamp(&self, coord: RegionCoord) -> Option<Timestamp> {
+        self.has_chunk(coord).then(|| self.get_timestamp(coord))
+    }
+
+    fn read_chunk_bytes(&mut self, coord: RegionCoord) -> McResult<Vec<u8>> {
+        self.read(coord, |mut decoder| {
+            let mut data = Vec::new();
+            decoder.read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    fn write_chunk_bytes(&mut self, coord: RegionCoord, data: &[u8], timestamp: Timestamp) -> McResult<()> {
+        self.write_timestamped(coord, timestamp, |writer| {
+            writer.write_all(data)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    fn delete_chunk(&mut self, coord: RegionCoord) -> McResult<()> {
+        self.delete_data(coord)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> McResult<()> {
+        RegionFile::flush(self)
+    }
+}
+
+impl RegionFormat for McRegionFile {
+    fn extension(&self) -> &'static str {
+        McRegion::EXTENSION
+    }
+
+    fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    fn has_chunk(&self, coord: RegionCoord) -> bool {
+        self.0.has_chunk(coord)
+    }
+
+    fn chunk_timestamp(&self, coord: RegionCoord) -> Option<Timestamp> {
+        self.0.chunk_timestamp(coord)
+    }
+
+    fn read_chunk_bytes(&mut self, coord: RegionCoord) -> McResult<Vec<u8>> {
+        self.0.read_chunk_bytes(coord)
+    }
+
+    fn write_chunk_bytes(&mut self, coord: RegionCoord, data: &[u8], timestamp: Timestamp) -> McResult<()> {
+        self.0.write_chunk_bytes(coord, data, timestamp)
+    }
+
+    fn delete_chunk(&mut self, coord: RegionCoord) -> McResult<()> {
+        self.0.delete_chunk(coord)
+    }
+
+    fn flush(&mut self) -> McResult<()> {
+        self.0.flush()
+    }
+}
+
+/// Opens a region file, choosing the backend from the file extension.
+pub fn open_region<P: AsRef<Path>>(path: P) -> McResult<Box<dyn RegionFormat>> {
+    let path = path.as_ref();
+    match RegionExtension::of_path(path) {
+        Some(RegionExtension::Anvil) => Ok(Box::new(RegionFile::open(path)?)),
+        Some(RegionExtension::McRegion) => Ok(Box::new(McRegionFile::open(path)?)),
+        #[cfg(feature = "zstd")]
+        Some(RegionExtension::Linear) => Ok(Box::new(super::linear::LinearRegion::open(path)?)),
+        _ => Err(McError::Custom(format!("Unsupported region format: {}", path.display()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::{Map, tag::{NamedTag, Tag}};
+    #[cfg(feature = "zstd")]
+    use super::super::linear::{Linear, LinearRegion};
+
+    /// Writes a chunk through `region`, then reopens the file with [open_region] and reads it back.
+    fn roundtrip(mut region: Box<dyn RegionFormat>, extension: &str) -> McResult<()> {
+        let coord = RegionCoord::new(3, 4);
+        let chunk = NamedTag::new(Tag::Compound(Map::from([("xPos".to_owned(), Tag::Int(3))])));
+        region.write_chunk(coord, &chunk, Timestamp::from(1234u32))?;
+        region.flush()?;
+        let path = region.path().to_owned();
+        drop(region);
+
+        let mut region = open_region(&path)?;
+        assert_eq!(region.extension(), extension);
+        assert_eq!(region.chunks(), [coord]);
+        assert_eq!(region.chunk_timestamp(coord), Some(Timestamp::from(1234u32)));
+        assert_eq!(region.chunk_timestamp(RegionCoord::new(0, 0)), None);
+        let read: NamedTag = region.read_chunk(coord)?;
+        assert!(matches!(read.tag(), Tag::Compound(map) if matches!(map.get("xPos"), Some(Tag::Int(3)))));
+        region.delete_chunk(coord)?;
+        assert!(!region.has_chunk(coord));
+        Ok(())
+    }
+
+    #[test]
+    fn region_format_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        roundtrip(Box::new(RegionFile::create(dir.path().join("r.0.0.mca"))?), Anvil::EXTENSION)?;
+        roundtrip(Box::new(McRegionFile::create(dir.path().join("r.0.0.mcr"))?), McRegion::EXTENSION)?;
+        #[cfg(feature = "zstd")]
+        roundtrip(Box::new(LinearRegion::create(dir.path().join("r.0.0.linear"))), Linear::EXTENSION)?;
+        std::fs::write(dir.path().join("r.0.0.dat"), [])?;
+        assert!(open_region(dir.path().join("r.0.0.dat")).is_err());
+        Ok(())
+    }
+}
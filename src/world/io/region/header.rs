@@ -42,6 +42,9 @@ impl RegionTableItem for Timestamp {
 
 /// A table of 1024 elements that contain information related to
 /// a Minecraft chunk within a Region file.
+///
+/// The elements are stored (and iterated) in the order of their [RegionCoord] index,
+/// which is x-major within each row: `(0, 0), (1, 0), ... (31, 0), (0, 1), ... (31, 31)`.
 #[derive(Debug, Clone)]
 pub struct RegionTable<T: RegionTableItem>(Box<[T; 1024]>);
 
@@ -78,12 +81,17 @@ impl<T: RegionTableItem> RegionTable<T> {
         SeekFrom::Start(Self::OFFSET)
     }
 
-    /// Returns an iterator of the elements in the table.
+    /// Returns an iterator of the elements in the table, in index order.
     pub fn iter(&self) -> std::slice::Iter<T> {
         self.0.iter()
     }
 
-    /// Returns a mutable iterator of the elements in the table.
+    /// Returns an iterator of the elements in the table with their coordinates, in index order.
+    pub fn enumerate(&self) -> impl Iterator<Item = (RegionCoord, &T)> {
+        self.0.iter().enumerate().map(|(index, item)| (RegionCoord::from(index), item))
+    }
+
+    /// Returns a mutable iterator of the elements in the table, in index order.
     pub fn iter_mut(&mut self) -> std::slice::IterMut<T> {
         self.0.iter_mut()
     }
@@ -177,4 +185,21 @@ impl Writable for RegionHeader {
             self.sectors.write_to(writer)? + self.timestamps.write_to(writer)?
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_order_test() {
+        let mut table = TimestampTable::default();
+        table[(3, 1)] = Timestamp::from(7u32);
+        let coords = table.enumerate().map(|(coord, _)| coord).collect::<Vec<_>>();
+        assert!(coords.windows(2).all(|pair| pair[0].index() + 1 == pair[1].index()));
+        assert_eq!((coords[1].x(), coords[1].z()), (1, 0));
+        assert_eq!((coords[32].x(), coords[32].z()), (0, 1));
+        let (coord, _) = table.enumerate().find(|(_, &timestamp)| u32::from(timestamp) == 7).unwrap();
+        assert_eq!((coord.x(), coord.z()), (3, 1));
+    }
+}
@@ -17,8 +17,9 @@ use std::{
         File,
     },
     io::{
-        BufReader, Seek,
+        BufReader, Read, Seek,
     },
+    time::SystemTime,
 };
 
 // /// Tests if a value is a multiple of 4096.
@@ -55,17 +56,11 @@ impl RegionFileInfo {
     pub fn load<P: AsRef<Path>>(path: P) -> McResult<Self> {
         let file = File::open(path.as_ref())?;
         let metadata = std::fs::metadata(path.as_ref())?;
-        let mut reader = BufReader::with_capacity(4096*2, file);
+        let mut reader = BufReader::with_capacity(IoConfig::default().read_buf, file);
         let header = RegionHeader::read_from(&mut reader)?;
         let mut bits = RegionBitmask::new();
         for i in 0..1024 {
-            if !header.sectors[i].is_empty() {
-                reader.seek(header.sectors[i].seeker())?;
-                let length = u32::read_from(&mut reader)?;
-                if length != 0 {
-                    bits.set(i, true);
-                }
-            }
+            bits.set(i, is_present(&mut reader, header.sectors[i])?);
         }
         Ok(Self {
             path: PathBuf::from(path.as_ref()),
@@ -75,6 +70,32 @@ impl RegionFileInfo {
         })
     }
 
+    /// Re-reads the header of the file, and checks the presence of only the chunks whose
+    /// sector changed since it was last read. This is much cheaper than [RegionFileInfo::load]
+    /// when few chunks have changed. Returns the chunks whose sector changed.
+    pub fn refresh(&mut self) -> McResult<Vec<RegionCoord>> {
+        let file = File::open(&self.path)?;
+        self.metadata = file.metadata()?;
+        let mut reader = BufReader::with_capacity(IoConfig::default().read_buf, file);
+        let header = RegionHeader::read_from(&mut reader)?;
+        let mut changed = Vec::new();
+        for i in 0..1024 {
+            let sector = header.sectors[i];
+            if sector != self.header.sectors[i] {
+                self.present_bits.set(i, is_present(&mut reader, sector)?);
+                changed.push(RegionCoord::from(i));
+            }
+        }
+        self.header = header;
+        Ok(changed)
+    }
+
+    /// Checks if the file on disk was modified after `time` (such as the time of the last
+    /// [RegionFileInfo::refresh]), from its metadata.
+    pub fn has_changed_since(&self, time: SystemTime) -> McResult<bool> {
+        Ok(std::fs::metadata(&self.path)?.modified()? > time)
+    }
+
     /// Opens the file that this RegionFileInfo points to.
     pub fn open(&self) -> McResult<File> {
         Ok(File::open(&self.path)?)
@@ -136,6 +157,15 @@ impl RegionFileInfo {
 
 }
 
+/// Checks if a sector holds a chunk (if it isn't empty, and its length isn't 0).
+fn is_present<R: Read + Seek>(reader: &mut R, sector: RegionSector) -> McResult<bool> {
+    if sector.is_empty() {
+        return Ok(false);
+    }
+    reader.seek(sector.seeker())?;
+    Ok(u32::read_from(reader)? != 0)
+}
+
 impl RegionBitmask {
     /// Creates a new bitmask with all bits set to off.
     pub fn new() -> Self {
@@ -262,4 +292,31 @@ impl From<&RegionBitmask> for [u32; 32] {
             });
         bits
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn refresh_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?;
+        region.write_data((0, 0), &1i64)?;
+        region.write_data((1, 0), &2i64)?;
+        let mut info = RegionFileInfo::load(&path)?;
+        assert!(info.has_chunk((0, 0)) && info.has_chunk((1, 0)));
+        assert!(info.refresh()?.is_empty());
+        let before = SystemTime::now() - std::time::Duration::from_secs(1);
+
+        region.delete_data((0, 0))?;
+        region.write_data((5, 3), &3i64)?;
+        assert!(info.has_changed_since(before)?);
+        assert_eq!(info.refresh()?, [RegionCoord::new(0, 0), RegionCoord::new(5, 3)]);
+        assert!(!info.has_chunk((0, 0)));
+        assert!(info.has_chunk((1, 0)) && info.has_chunk((5, 3)));
+        assert_eq!(info.get_offset((5, 3)), region.get_sector((5, 3)));
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! A run-length description of which sectors of a region file are in use,
+//! for seeing (or charting) how fragmented a file is.
+//!
+//! [SectorManager::layout_map](super::SectorManager::layout_map) builds a [SectorLayout]
+//! from the sectors that the manager knows are free. Its [Display] is the compact form
+//! (`H2 U5 F3 U1`), and [SectorLayout::render] draws one character per sector.
+
+use std::fmt::Display;
+
+/// What a run of sectors is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectorUse {
+    /// The 8KiB header (the sector and timestamp tables).
+    Header,
+    /// Sectors that hold chunks.
+    Used,
+    /// Unused sectors between chunks.
+    Free,
+}
+
+impl SectorUse {
+    /// The character used for this kind of sector by [Display] and [SectorLayout::render].
+    pub fn symbol(self) -> char {
+        match self {
+            SectorUse::Header => 'H',
+            SectorUse::Used => 'U',
+            SectorUse::Free => 'F',
+        }
+    }
+}
+
+/// A run of adjacent sectors that are used the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SectorRun {
+    pub usage: SectorUse,
+    /// The first sector of the run.
+    pub start: u32,
+    /// The number of sectors in the run.
+    pub len: u32,
+}
+
+impl SectorRun {
+    /// The sector after the end of the run.
+    pub fn end(&self) -> u32 {
+        self.start + self.len
+    }
+}
+
+/// The layout of the sectors of a region file, front to back, up to the end of the last used sector.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SectorLayout {
+    pub runs: Vec<SectorRun>,
+}
+
+impl SectorLayout {
+    /// The number of sectors that the layout covers.
+    pub fn sector_count(&self) -> u32 {
+        self.runs.last().map_or(0, SectorRun::end)
+    }
+
+    /// The number of free sectors.
+    pub fn free_sectors(&self) -> u32 {
+        self.runs.iter()
+            .filter(|run| run.usage == SectorUse::Free)
+            .map(|run| run.len)
+            .sum()
+    }
+
+    /// The number of runs of free sectors (gaps).
+    pub fn gap_count(&self) -> usize {
+        self.runs.iter()
+            .filter(|run| run.usage == SectorUse::Free)
+            .count()
+    }
+
+    /// The fraction of the sectors after the header that are free (from 0 to 1).
+    pub fn fragmentation(&self) -> f64 {
+        let header = self.runs.iter()
+            .filter(|run| run.usage == SectorUse::Header)
+            .map(|run| run.len)
+            .sum::<u32>();
+        let body = self.sector_count() - header;
+        if body == 0 {
+            0.0
+        } else {
+            self.free_sectors() as f64 / body as f64
+        }
+    }
+
+    /// Draws one character per sector ([SectorUse::symbol]), with `columns` sectors per line.
+    pub fn render(&self, columns: usize) -> String {
+        let columns = columns.max(1);
+        let symbols = self.runs.iter()
+            .flat_map(|run| std::iter::repeat_n(run.usage.symbol(), run.len as usize))
+            .collect::<Vec<_>>();
+        symbols.chunks(columns)
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Display for SectorLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.runs.iter().enumerate().try_for_each(|(index, run)| {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}{}", run.usage.symbol(), run.len)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        McResult,
+        world::io::region::RegionFile,
+    };
+
+    #[test]
+    fn layout_map_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        for x in 0..4u16 {
+            region.write_data((x, 0), &(x as i64))?;
+        }
+        region.delete_data((1, 0))?;
+        let layout = region.sector_manager().layout_map();
+        assert_eq!(layout.to_string(), "H2 U1 F1 U2");
+        assert_eq!(layout.sector_count(), 6);
+        assert_eq!(layout.gap_count(), 1);
+        assert_eq!(layout.render(4), "HHUF\nUU");
+        assert!((layout.fragmentation() - 0.25).abs() < f64::EPSILON);
+        Ok(())
+    }
+}
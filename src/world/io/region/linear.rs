@@ -0,0 +1,298 @@
+//! Support for the linear region format (`.linear`) used by some server forks.
+//!
+//! A linear region file stores all of its chunks in a single zstd frame:
+//!
+//! ```text
+//! superblock: i64
+//! version: u8
+//! newest_timestamp: i64
+//! compression_level: i8
+//! chunk_count: i16
+//! compressed_length: i32
+//! reserved: i64
+//! compressed data (compressed_length bytes)
+//! superblock: i64
+//! ```
+//!
+//! Once decompressed, the data begins with a table of 1024 `(size: i32, timestamp: i32)`
+//! entries followed by the uncompressed NBT of every chunk with a non-zero size,
+//! in index order.
+//!
+//! Because the whole region is one compressed frame, [LinearRegion] keeps every
+//! chunk in memory and only writes to disk on [RegionFormat::flush].
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+};
+
+use super::{
+    coord::RegionCoord,
+    timestamp::Timestamp,
+    format::{RegionFormat, open_region},
+    regionfile::RegionFile,
+};
+
+/// Layout constants for the linear region format.
+pub struct Linear;
+
+impl Linear {
+    /// File extension of linear region files, without the leading dot.
+    pub const EXTENSION: &'static str = "linear";
+    /// The magic number at the start and end of every linear region file.
+    pub const SUPERBLOCK: i64 = -4323716122432332390;
+    /// The format version that is read and written. Files of other versions (such as
+    /// version 2, which groups chunks into buckets) fail with [McError::UnsupportedLinearVersion].
+    pub const VERSION: u8 = 1;
+    /// The zstd compression level used for new files.
+    pub const DEFAULT_COMPRESSION_LEVEL: i8 = 6;
+    /// Size of the header that precedes the compressed data.
+    pub const HEADER_SIZE: usize = 32;
+}
+
+/// A linear region file, held in memory.
+pub struct LinearRegion {
+    path: PathBuf,
+    chunks: Vec<Option<Vec<u8>>>,
+    timestamps: Vec<Timestamp>,
+    compression_level: i8,
+    dirty: bool,
+}
+
+impl LinearRegion {
+    /// Creates an empty linear region that will be written to `path` when flushed.
+    /// Nothing is written to disk until [RegionFormat::flush] is called.
+    pub fn create<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            chunks: vec![None; 1024],
+            timestamps: vec![Timestamp::default(); 1024],
+            compression_level: Linear::DEFAULT_COMPRESSION_LEVEL,
+            dirty: true,
+        }
+    }
+
+    /// Reads a linear region file into memory.
+    pub fn open<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        let path = path.as_ref();
+        let mut reader = BufReader::with_capacity(IoConfig::default().read_buf, File::open(path)?);
+        let superblock: i64 = reader.read_value()?;
+        let version: u8 = reader.read_value()?;
+        if superblock != Linear::SUPERBLOCK {
+            return Err(McError::InvalidRegionFile);
+        }
+        if version != Linear::VERSION {
+            return Err(McError::UnsupportedLinearVersion(version));
+        }
+        let _newest_timestamp: i64 = reader.read_value()?;
+        let compression_level: i8 = reader.read_value()?;
+        let chunk_count: i16 = reader.read_value()?;
+        let compressed_length: i32 = reader.read_value()?;
+        let _reserved: i64 = reader.read_value()?;
+        let mut compressed = vec![0u8; compressed_length.max(0) as usize];
+        reader.read_exact(&mut compressed)?;
+        let footer: i64 = reader.read_value()?;
+        if footer != Linear::SUPERBLOCK {
+            return Err(McError::InvalidRegionFile);
+        }
+        let data = zstd::decode_all(compressed.as_slice())?;
+        let mut data = data.as_slice();
+        let mut sizes = Vec::with_capacity(1024);
+        let mut timestamps = Vec::with_capacity(1024);
+        for _ in 0..1024 {
+            let size: i32 = data.read_value()?;
+            let timestamp: Timestamp = data.read_value()?;
+            sizes.push(size.max(0) as usize);
+            timestamps.push(timestamp);
+        }
+        let mut chunks = Vec::with_capacity(1024);
+        for size in sizes {
+            if size == 0 {
+                chunks.push(None);
+                continue;
+            }
+            let mut chunk = vec![0u8; size];
+            data.read_exact(&mut chunk)?;
+            chunks.push(Some(chunk));
+        }
+        if chunks.iter().flatten().count() != chunk_count as usize {
+            return Err(McError::InvalidRegionFile);
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            chunks,
+            timestamps,
+            compression_level,
+            dirty: false,
+        })
+    }
+
+    /// Opens the linear region file at `path`, or creates an empty one if it does not exist.
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        let path = path.as_ref();
+        if path.is_file() {
+            Self::open(path)
+        } else {
+            Ok(Self::create(path))
+        }
+    }
+
+    pub fn compression_level(&self) -> i8 {
+        self.compression_level
+    }
+
+    pub fn set_compression_level(&mut self, level: i8) {
+        self.compression_level = level;
+        self.dirty = true;
+    }
+
+    /// Returns true if there are changes that have not been flushed to disk.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes the region to `writer`, returning the number of bytes written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        let mut data = Vec::new();
+        for (chunk, timestamp) in self.chunks.iter().zip(self.timestamps.iter()) {
+            let size = chunk.as_ref().map(Vec::len).unwrap_or(0);
+            data.write_value(size as i32)?;
+            data.write_value(*timestamp)?;
+        }
+        self.chunks.iter().flatten().try_for_each(|chunk| data.write_all(chunk))?;
+        let compressed = zstd::encode_all(data.as_slice(), self.compression_level as i32)?;
+        let newest = self.timestamps.iter().max().copied().unwrap_or_default();
+        let chunk_count = self.chunks.iter().flatten().count();
+        let mut size = 0;
+        size += writer.write_value(Linear::SUPERBLOCK)?;
+        size += writer.write_value(Linear::VERSION)?;
+        size += writer.write_value(u32::from(newest) as i64)?;
+        size += writer.write_value(self.compression_level)?;
+        size += writer.write_value(chunk_count as i16)?;
+        size += writer.write_value(compressed.len() as i32)?;
+        size += writer.write_value(0i64)?;
+        writer.write_all(&compressed)?;
+        size += compressed.len();
+        size += writer.write_value(Linear::SUPERBLOCK)?;
+        Ok(size)
+    }
+}
+
+impl RegionFormat for LinearRegion {
+    fn extension(&self) -> &'static str {
+        Linear::EXTENSION
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn has_chunk(&self, coord: RegionCoord) -> bool {
+        self.chunks[coord.index()].is_some()
+    }
+
+    fn chunk_timestamp(&self, coord: RegionCoord) -> Option<Timestamp> {
+        self.has_chunk(coord).then(|| self.timestamps[coord.index()])
+    }
+
+    fn read_chunk_bytes(&mut self, coord: RegionCoord) -> McResult<Vec<u8>> {
+        self.chunks[coord.index()].clone().ok_or(McError::RegionDataNotFound)
+    }
+
+    fn write_chunk_bytes(&mut self, coord: RegionCoord, data: &[u8], timestamp: Timestamp) -> McResult<()> {
+        self.chunks[coord.index()] = Some(data.to_vec());
+        self.timestamps[coord.index()] = timestamp;
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn delete_chunk(&mut self, coord: RegionCoord) -> McResult<()> {
+        if self.chunks[coord.index()].take().is_some() {
+            self.timestamps[coord.index()] = Timestamp::default();
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> McResult<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        atomic_replace(&self.path, |file| {
+            let mut writer = BufWriter::with_capacity(IoConfig::default().write_buf, file);
+            self.write_to(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Copies every chunk (with its timestamp) from `source` into `destination`
+/// and flushes the destination. Returns the number of chunks copied.
+pub fn copy_region(source: &mut dyn RegionFormat, destination: &mut dyn RegionFormat) -> McResult<usize> {
+    let chunks = source.chunks();
+    for &coord in chunks.iter() {
+        let data = source.read_chunk_bytes(coord)?;
+        let timestamp = source.chunk_timestamp(coord).unwrap_or_default();
+        destination.write_chunk_bytes(coord, &data, timestamp)?;
+    }
+    destination.flush()?;
+    Ok(chunks.len())
+}
+
+/// Converts the `.mca` region file at `source` into a new `.linear` file at `destination`.
+pub fn mca_to_linear<P: AsRef<Path>, P2: AsRef<Path>>(source: P, destination: P2) -> McResult<usize> {
+    let mut source = open_region(source)?;
+    let mut destination = LinearRegion::create(destination);
+    copy_region(source.as_mut(), &mut destination)
+}
+
+/// Converts the `.linear` region file at `source` into a new `.mca` file at `destination`.
+pub fn linear_to_mca<P: AsRef<Path>, P2: AsRef<Path>>(source: P, destination: P2) -> McResult<usize> {
+    let mut source = LinearRegion::open(source)?;
+    let mut destination = RegionFile::create(destination)?;
+    copy_region(&mut source, &mut destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::{Map, tag::{NamedTag, Tag}};
+    use crate::world::io::region::RegionFormatExt;
+
+    #[test]
+    fn linear_roundtrip_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut map = Map::new();
+        map.insert("xPos".to_owned(), Tag::Int(3));
+        let chunk = NamedTag::new(Tag::Compound(map));
+        let linear_path = dir.path().join("r.0.0.linear");
+        let mut region = LinearRegion::create(&linear_path);
+        region.write_chunk(RegionCoord::new(3, 4), &chunk, Timestamp::from(1234u32))?;
+        region.flush()?;
+        let mca_path = dir.path().join("r.0.0.mca");
+        assert_eq!(linear_to_mca(&linear_path, &mca_path)?, 1);
+        let back_path = dir.path().join("back.linear");
+        assert_eq!(mca_to_linear(&mca_path, &back_path)?, 1);
+        let mut region = LinearRegion::open(&back_path)?;
+        assert_eq!(region.chunks(), vec![RegionCoord::new(3, 4)]);
+        assert_eq!(region.chunk_timestamp(RegionCoord::new(3, 4)), Some(Timestamp::from(1234u32)));
+        let read: NamedTag = region.read_chunk(RegionCoord::new(3, 4))?;
+        let Tag::Compound(map) = read.tag else { panic!("Expected Compound.") };
+        assert!(matches!(map.get("xPos"), Some(Tag::Int(3))));
+        // Version 2 has a different layout, so it is rejected rather than misread.
+        let mut bytes = std::fs::read(&back_path)?;
+        bytes[8] = 2;
+        std::fs::write(&back_path, bytes)?;
+        assert!(matches!(LinearRegion::open(&back_path), Err(McError::UnsupportedLinearVersion(2))));
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! A listing of the chunks in a region file, with their timestamps and sizes,
+//! for building external indexes and for sync tools that need to know what
+//! changed without decoding any chunks.
+//!
+//! The listing is collected by [RegionFile::manifest](super::RegionFile::manifest),
+//! which reads the 5 byte head (length and compression scheme) of every chunk in the
+//! order that the chunks are stored in the file, so the file is read front to back once.
+
+use std::io::{Read, Write};
+
+use crate::{
+    McResult,
+    ioext::*,
+};
+
+use super::{
+    compressionscheme::CompressionScheme,
+    coord::RegionCoord,
+    header::RegionHeader,
+    positioned::PositionedIo,
+    sector::RegionSector,
+    timestamp::Timestamp,
+};
+
+/// A chunk in a region file, as listed by [RegionFile::manifest](super::RegionFile::manifest).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkManifestEntry {
+    pub coord: RegionCoord,
+    /// The timestamp from the header, as it is stored (seconds since the Unix epoch).
+    pub timestamp: Timestamp,
+    pub sector: RegionSector,
+    /// The length in bytes of the chunk's compressed data (not including the length or scheme).
+    pub compressed_len: u32,
+    /// The compression scheme byte, as it is stored. See [ChunkManifestEntry::compression_scheme].
+    pub scheme: u8,
+}
+
+impl ChunkManifestEntry {
+    /// Parses [ChunkManifestEntry::scheme]. Returns an error for unknown schemes
+    /// (including chunks stored in external `.mcc` files).
+    pub fn compression_scheme(&self) -> McResult<CompressionScheme> {
+        [self.scheme].as_slice().read_value()
+    }
+}
+
+impl Readable for ChunkManifestEntry {
+    fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
+        Ok(Self {
+            coord: RegionCoord::from(reader.read_value::<u16>()?),
+            timestamp: reader.read_value()?,
+            sector: reader.read_value()?,
+            compressed_len: reader.read_value()?,
+            scheme: reader.read_value()?,
+        })
+    }
+}
+
+impl Writable for ChunkManifestEntry {
+    fn write_to<W: Write>(&self, writer: &mut W) -> McResult<usize> {
+        Ok(
+            writer.write_value(self.coord.index() as u16)?
+            + writer.write_value(self.timestamp)?
+            + writer.write_value(self.sector)?
+            + writer.write_value(self.compressed_len)?
+            + writer.write_value(self.scheme)?
+        )
+    }
+}
+
+/// Writes a manifest, preceded by the number of entries.
+pub fn write_manifest<W: Write>(writer: &mut W, manifest: &[ChunkManifestEntry]) -> McResult<usize> {
+    let mut size = writer.write_value(manifest.len() as u32)?;
+    for entry in manifest {
+        size += writer.write_value(*entry)?;
+    }
+    Ok(size)
+}
+
+/// Reads a manifest that was written with [write_manifest].
+pub fn read_manifest<R: Read>(reader: &mut R) -> McResult<Vec<ChunkManifestEntry>> {
+    let count: u32 = reader.read_value()?;
+    (0..count).map(|_| reader.read_value()).collect()
+}
+
+/// Lists the chunks in `file`, in [RegionCoord] order. Chunks whose length is zero are skipped.
+pub(crate) fn collect_manifest<F: PositionedIo>(header: &RegionHeader, file: &F) -> McResult<Vec<ChunkManifestEntry>> {
+    let mut chunks = (0..1024usize)
+        .map(RegionCoord::from)
+        .filter(|coord| !header.sectors[coord.index()].is_empty())
+        .collect::<Vec<_>>();
+    chunks.sort_by_key(|coord| header.sectors[coord.index()].offset());
+    let mut manifest = Vec::with_capacity(chunks.len());
+    for coord in chunks {
+        let sector = header.sectors[coord.index()];
+        let mut head = [0u8; 5];
+        file.read_exact_at(&mut head, sector.offset())?;
+        let mut head = head.as_slice();
+        let length: u32 = head.read_value()?;
+        if length == 0 {
+            continue;
+        }
+        manifest.push(ChunkManifestEntry {
+            coord,
+            timestamp: header.timestamps[coord.index()],
+            sector,
+            compressed_len: length - 1,
+            scheme: head.read_value()?,
+        });
+    }
+    manifest.sort_by_key(|entry| entry.coord);
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn manifest_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        region.write_data_timestamped((3, 1), &vec![7u8; 10000], 1234u32)?;
+        region.write_data_timestamped((0, 0), &5i64, 5678u32)?;
+        let manifest = region.manifest()?;
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].coord, RegionCoord::new(0, 0));
+        assert_eq!(manifest[0].timestamp, Timestamp::from(5678u32));
+        assert_eq!(manifest[1].sector, region.get_sector((3, 1)));
+        assert!(manifest.iter().all(|entry| matches!(entry.compression_scheme(), Ok(CompressionScheme::ZLib))));
+
+        let mut buffer = Vec::new();
+        write_manifest(&mut buffer, &manifest)?;
+        assert_eq!(read_manifest(&mut buffer.as_slice())?, manifest);
+        Ok(())
+    }
+}
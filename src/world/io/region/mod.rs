@@ -1,20 +1,51 @@
-pub mod header;
-pub mod sector;
-pub use sector::RegionSector;
-pub mod timestamp;
-pub use timestamp::Timestamp;
-pub mod coord;
-pub use coord::RegionCoord;
-pub mod info;
+// CompressionScheme is also used by the NBT file helpers, so it is compiled without
+// the `region` feature. Everything else here is region file IO.
 pub mod compressionscheme;
 pub use compressionscheme::CompressionScheme;
-pub mod managedsector;
-pub use managedsector::ManagedSector;
-pub mod sectormanager;
-pub use sectormanager::*;
-pub mod regionfile;
-pub use regionfile::RegionFile;
-pub mod prelude;
+
+use crate::macros::cfg_feature;
+
+cfg_feature!("region" {
+    pub mod header;
+    pub mod sector;
+    pub use sector::RegionSector;
+    pub mod timestamp;
+    pub use timestamp::Timestamp;
+    pub mod coord;
+    pub use coord::RegionCoord;
+    pub mod info;
+    pub(crate) mod managedsector;
+    pub(crate) use managedsector::ManagedSector;
+    pub mod sectormanager;
+    pub use sectormanager::*;
+    pub mod layout;
+    pub use layout::SectorLayout;
+    pub mod positioned;
+    pub use positioned::{PositionedIo, RegionBackend};
+    pub mod checksum;
+    pub use checksum::RegionChecksums;
+    pub mod regionfile;
+    pub use regionfile::{RegionFile, DeleteReport, Durability};
+    pub mod relocate;
+    pub mod debug;
+    pub use debug::SectorDebug;
+    pub mod transform;
+    pub use transform::{Transform, transform};
+    pub mod manifest;
+    pub mod snapshot;
+    pub use manifest::ChunkManifestEntry;
+    pub mod reader;
+    pub use reader::{RegionReader, ReadPlan};
+    pub mod name;
+    pub use name::{RegionFileName, RegionExtension};
+    pub mod format;
+    pub use format::{RegionFormat, RegionFormatExt, McRegionFile, open_region};
+    #[cfg(feature = "zstd")]
+    pub mod linear;
+    #[cfg(feature = "zstd")]
+    pub use linear::LinearRegion;
+    pub mod prelude;
+});
 
 /*	╭──────────────────────────────────────────────────────────────────────────────╮
     │ How do Region Files work?                                                    │
@@ -84,6 +115,31 @@ pub const fn required_sectors(size: u32) -> u32 {
     sub + overflow
 }
 
+/// The range of sectors that a chunk of `nbt_size` uncompressed bytes can take up
+/// once it is compressed, including the length and compression scheme bytes.
+///
+/// The upper bound covers the worst case expansion of zlib and zstd. The lower bound
+/// assumes zlib (the default), which can't compress better than 1032 to 1.
+pub const fn estimated_sectors(nbt_size: usize) -> std::ops::RangeInclusive<u32> {
+    let size = nbt_size as u64;
+    let min = size / 1032 + 5;
+    let max = size + (size >> 8) + 64 + 5;
+    let min = if min > u32::MAX as u64 { u32::MAX } else { min as u32 };
+    let max = if max > u32::MAX as u64 { u32::MAX } else { max as u32 };
+    required_sectors(min)..=required_sectors(max)
+}
+
+/// Checks whether a chunk of `nbt_size` uncompressed bytes might fit in a region file
+/// before compressing it. Returns [McError::RegionDataTooLarge] if it can't possibly fit,
+/// or whether it is sure to fit (`false` means that it depends on how well it compresses).
+pub fn check_nbt_size(nbt_size: usize) -> crate::McResult<bool> {
+    let sectors = estimated_sectors(nbt_size);
+    if *sectors.start() > 255 {
+        return Err(crate::McError::RegionDataTooLarge);
+    }
+    Ok(*sectors.end() <= 255)
+}
+
 /// Returns the 4KiB pad size for the given size.
 /// The pad size is the number of bytes required
 /// to add to the size in order to make it a
@@ -0,0 +1,135 @@
+//! Region file names (`r.<x>.<z>.<extension>`).
+//!
+//! [RegionFileName] parses and formats the names of region files, so that code that finds
+//! region files doesn't build or split the names itself. The name is the same for terrain,
+//! entity, and POI region files; which of them a file holds is told by its folder.
+//!
+//! Names are parsed without regard to case, because worlds that were copied from (or through)
+//! a case-insensitive file system can end up with names like `R.0.0.MCA`. Names with other
+//! spellings of the same coordinates (such as `r.01.+0.mca`) are rejected, so that every
+//! region has a single name.
+
+use std::{
+    fmt::Display,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{McError, McResult};
+
+use super::format::{Anvil, McRegion};
+
+/// The format of a region file, as told by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum RegionExtension {
+    /// `.mca`
+    #[default]
+    Anvil,
+    /// `.mcr`
+    McRegion,
+    /// `.linear`, read with the `zstd` feature.
+    Linear,
+}
+
+impl RegionExtension {
+    /// The extension, without the leading dot.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            RegionExtension::Anvil => Anvil::EXTENSION,
+            RegionExtension::McRegion => McRegion::EXTENSION,
+            RegionExtension::Linear => "linear",
+        }
+    }
+
+    /// Finds the format for an extension (without the leading dot), ignoring case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        [RegionExtension::Anvil, RegionExtension::McRegion, RegionExtension::Linear].into_iter()
+            .find(|format| format.as_str().eq_ignore_ascii_case(extension))
+    }
+
+    /// Finds the format for the extension of `path`.
+    pub fn of_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        Self::from_extension(path.as_ref().extension()?.to_str()?)
+    }
+}
+
+/// The name of a region file. `x` and `z` are in region coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionFileName {
+    pub x: i64,
+    pub z: i64,
+    pub format: RegionExtension,
+}
+
+impl RegionFileName {
+    pub const fn new(x: i64, z: i64, format: RegionExtension) -> Self {
+        Self { x, z, format }
+    }
+
+    /// The name of the Anvil (`.mca`) file for a region.
+    pub const fn anvil(x: i64, z: i64) -> Self {
+        Self::new(x, z, RegionExtension::Anvil)
+    }
+
+    /// Parses a file name, returning `None` if it isn't the name of a region file.
+    pub fn parse(name: &str) -> Option<Self> {
+        if !name.get(..2)?.eq_ignore_ascii_case("r.") {
+            return None;
+        }
+        let mut parts = name[2..].split('.');
+        let x = parts.next()?.parse().ok()?;
+        let z = parts.next()?.parse().ok()?;
+        let format = RegionExtension::from_extension(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        let parsed = Self::new(x, z, format);
+        parsed.file_name().eq_ignore_ascii_case(name).then_some(parsed)
+    }
+
+    /// Parses the file name of `path` (see [RegionFileName::parse]).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        Self::parse(path.as_ref().file_name()?.to_str()?)
+    }
+
+    /// The file name, in the form that the game writes (lowercase).
+    pub fn file_name(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn coords(&self) -> (i64, i64) {
+        (self.x, self.z)
+    }
+}
+
+impl Display for RegionFileName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "r.{}.{}.{}", self.x, self.z, self.format.as_str())
+    }
+}
+
+impl FromStr for RegionFileName {
+    type Err = McError;
+
+    fn from_str(s: &str) -> McResult<Self> {
+        Self::parse(s).ok_or_else(|| McError::Custom(format!("Not a region file name: {s}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_file_name_test() {
+        assert_eq!(RegionFileName::parse("r.-1.2.mca"), Some(RegionFileName::anvil(-1, 2)));
+        assert_eq!(RegionFileName::parse("R.3.-4.MCR"), Some(RegionFileName::new(3, -4, RegionExtension::McRegion)));
+        assert_eq!(RegionFileName::from_path(Path::new("world/poi/r.0.0.linear")), Some(RegionFileName::new(0, 0, RegionExtension::Linear)));
+        for name in ["r.0.0.mca.tmp", "r.01.0.mca", "r.+1.0.mca", "r.0.mca", "r.0.0.dat", "region.mca", "r.0.0.0.mca", ""] {
+            assert_eq!(RegionFileName::parse(name), None, "{name}");
+        }
+        assert_eq!(RegionFileName::anvil(-1, 2).to_string(), "r.-1.2.mca");
+        assert!("r.x.0.mca".parse::<RegionFileName>().is_err());
+        assert_eq!(RegionExtension::of_path("back.LINEAR"), Some(RegionExtension::Linear));
+    }
+}
@@ -0,0 +1,258 @@
+//! Positioned IO for region files.
+//!
+//! Reads and writes take an explicit offset instead of seeking a shared file
+//! cursor, so a file handle can be shared between readers without any of them
+//! disturbing each other.
+//!
+//! On Unix this is `pread`/`pwrite`, and on Windows it is `ReadFile`/`WriteFile`
+//! with an offset. With the `uring` feature enabled on Linux, [UringFile] submits
+//! the reads and writes through io_uring instead.
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+};
+
+/// A file that can be read from and written to at arbitrary offsets through
+/// a shared reference.
+pub trait PositionedIo {
+    /// Reads bytes at `offset` into `buf`, returning how many bytes were read.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+    /// Writes bytes from `buf` at `offset`, returning how many bytes were written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+    /// The length of the file in bytes.
+    fn len(&self) -> io::Result<u64>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Reads exactly `buf.len()` bytes at `offset`.
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(read) => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all of `buf` at `offset`.
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(buf, offset) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(written) => {
+                    buf = &buf[written..];
+                    offset += written as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PositionedIo for File {
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+
+    // Unlike pread/pwrite, these move the file cursor, but nothing in this
+    // crate relies on the cursor of a file used for positioned IO.
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// A file whose reads and writes are submitted through io_uring.
+#[cfg(all(target_os = "linux", feature = "uring"))]
+pub struct UringFile {
+    file: File,
+    /// `None` after a ring had to be thrown away (see [UringFile::submit]); a new one is
+    /// created by the next operation.
+    ring: std::sync::Mutex<Option<io_uring::IoUring>>,
+    /// The `user_data` of the next operation, so that its completion can be told apart
+    /// from any other.
+    next_id: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+impl UringFile {
+    /// The number of entries in the submission queue. Operations are
+    /// submitted one at a time, so this doesn't need to be large.
+    const QUEUE_DEPTH: u32 = 8;
+
+    pub fn new(file: File) -> io::Result<Self> {
+        Ok(Self {
+            file,
+            ring: std::sync::Mutex::new(Some(io_uring::IoUring::new(Self::QUEUE_DEPTH)?)),
+            next_id: std::sync::atomic::AtomicU64::new(0),
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn into_file(self) -> File {
+        self.file
+    }
+
+    /// Flushes the file's data and metadata to disk (see [File::sync_all]).
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    /// Submits a single operation and waits for it to complete.
+    /// The buffer that `entry` points to must outlive this call.
+    ///
+    /// The kernel uses the buffer until the operation completes, so once the kernel has the
+    /// operation, this doesn't return until its completion arrives, even if waiting fails.
+    /// If submitting fails before the kernel has taken the operation, the ring (with the
+    /// operation still in it) is thrown away so that the operation is never submitted later.
+    fn submit(&self, entry: io_uring::squeue::Entry) -> io::Result<usize> {
+        let mut guard = self.ring.lock()
+            .map_err(|_| io::Error::other("io_uring mutex was poisoned."))?;
+        let ring = match &mut *guard {
+            Some(ring) => ring,
+            None => guard.insert(io_uring::IoUring::new(Self::QUEUE_DEPTH)?),
+        };
+        let id = self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // Safety: the buffer outlives the operation, since this function doesn't return
+        // while the kernel may still be using it (see above).
+        unsafe {
+            ring.submission().push(&entry.user_data(id))
+                .map_err(|_| io::Error::other("io_uring submission queue is full."))?;
+        }
+        loop {
+            // Completions of other operations can only be left over from a ring that was in an
+            // unknown state, so they are dropped.
+            if let Some(completion) = ring.completion().find(|completion| completion.user_data() == id) {
+                return match completion.result() {
+                    result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+                    result => Ok(result as usize),
+                };
+            }
+            match ring.submit_and_wait(1) {
+                Ok(_) => (),
+                Err(err) if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock)
+                    || err.raw_os_error() == Some(libc::EBUSY) => (),
+                Err(err) if !ring.submission().is_empty() => {
+                    *guard = None;
+                    return Err(err);
+                }
+                // The operation is in flight, so keep waiting for it.
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "uring"))]
+impl PositionedIo for UringFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        use std::os::fd::AsRawFd;
+        use io_uring::{opcode, types};
+        let len = buf.len().min(u32::MAX as usize) as u32;
+        let entry = opcode::Read::new(types::Fd(self.file.as_raw_fd()), buf.as_mut_ptr(), len)
+            .offset(offset)
+            .build();
+        self.submit(entry)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        use std::os::fd::AsRawFd;
+        use io_uring::{opcode, types};
+        let len = buf.len().min(u32::MAX as usize) as u32;
+        let entry = opcode::Write::new(types::Fd(self.file.as_raw_fd()), buf.as_ptr(), len)
+            .offset(offset)
+            .build();
+        self.submit(entry)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        self.file.len()
+    }
+}
+
+/// The positioned IO backend used by [super::RegionFile] and [super::RegionReader].
+#[cfg(all(target_os = "linux", feature = "uring"))]
+pub type RegionBackend = UringFile;
+
+/// The positioned IO backend used by [super::RegionFile] and [super::RegionReader].
+#[cfg(not(all(target_os = "linux", feature = "uring")))]
+pub type RegionBackend = File;
+
+/// Wraps a file in the [RegionBackend].
+pub(crate) fn backend(file: File) -> io::Result<RegionBackend> {
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    {
+        UringFile::new(file)
+    }
+    #[cfg(not(all(target_os = "linux", feature = "uring")))]
+    {
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positioned_io_test() -> io::Result<()> {
+        let file = backend(tempfile::tempfile()?)?;
+        file.write_all_at(b"world", 6)?;
+        file.write_all_at(b"hello ", 0)?;
+        assert_eq!(file.len()?, 11);
+        let mut buf = [0u8; 5];
+        file.read_exact_at(&mut buf, 6)?;
+        assert_eq!(&buf, b"world");
+        assert!(file.read_exact_at(&mut buf, 8).is_err());
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    #[test]
+    fn uring_stale_completion_test() -> io::Result<()> {
+        let file = UringFile::new(tempfile::tempfile()?)?;
+        file.write_all_at(b"hello", 0)?;
+        {
+            // Leave the completion of an operation that nothing waits for in the queue.
+            let mut guard = file.ring.lock().unwrap();
+            let ring = guard.as_mut().unwrap();
+            unsafe {
+                ring.submission().push(&io_uring::opcode::Nop::new().build().user_data(u64::MAX)).unwrap();
+            }
+            ring.submit_and_wait(1)?;
+        }
+        let mut buf = [0u8; 5];
+        assert_eq!(file.read_at(&mut buf, 0)?, 5);
+        assert_eq!(&buf, b"hello");
+        Ok(())
+    }
+}
@@ -8,4 +8,6 @@ pub use super::{
     coord::*,
     compressionscheme::*,
     regionfile::*,
+    reader::*,
+    format::*,
 };
\ No newline at end of file
@@ -0,0 +1,341 @@
+//! Read-only access to region files, with support for batching reads.
+//!
+//! Reading chunks one at a time means one seek per chunk, in whatever order the
+//! chunks were requested. [RegionReader::plan_reads] sorts the requested chunks
+//! by where they are stored in the file and merges chunks that are stored in
+//! adjacent sectors, so that [RegionReader::read_planned] can read each run of
+//! sectors with a single IO call, front to back.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    McError, McResult,
+    ioext::*,
+};
+
+use super::{
+    coord::RegionCoord,
+    compressionscheme::CompressionScheme,
+    header::RegionHeader,
+    sector::RegionSector,
+    positioned::{PositionedIo, RegionBackend, backend},
+    checksum::{RegionChecksums, stored_part},
+};
+
+/// A chunk that is part of a [ReadRun].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlannedChunk {
+    pub coord: RegionCoord,
+    pub sector: RegionSector,
+}
+
+/// A contiguous range of sectors that can be read with a single IO call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRun {
+    /// The 4KiB sector offset of the start of the run.
+    pub sector_offset: u64,
+    /// The number of 4KiB sectors in the run.
+    pub sector_count: u64,
+    /// The chunks in this run, in file order.
+    pub chunks: Vec<PlannedChunk>,
+}
+
+impl ReadRun {
+    /// The offset in bytes of the start of this run.
+    pub fn offset(&self) -> u64 {
+        self.sector_offset * 4096
+    }
+
+    /// The size in bytes of this run.
+    pub fn size(&self) -> u64 {
+        self.sector_count * 4096
+    }
+}
+
+/// The result of [RegionReader::plan_reads].
+/// Runs are sorted by offset, and chunks that are not present are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadPlan {
+    pub runs: Vec<ReadRun>,
+}
+
+impl ReadPlan {
+    /// The number of chunks that will be read.
+    pub fn chunk_count(&self) -> usize {
+        self.runs.iter().map(|run| run.chunks.len()).sum()
+    }
+
+    /// The number of IO calls that will be made.
+    pub fn io_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Iterates over the planned chunks in the order that they will be read.
+    pub fn chunks(&self) -> impl Iterator<Item = &PlannedChunk> {
+        self.runs.iter().flat_map(|run| run.chunks.iter())
+    }
+}
+
+/// A read-only handle to a region file.
+/// All reads are positioned, so a [RegionReader] can be shared between threads.
+pub struct RegionReader {
+    header: RegionHeader,
+    file_handle: RegionBackend,
+    path: PathBuf,
+    config: IoConfig,
+    checksums: Option<RegionChecksums>,
+}
+
+impl RegionReader {
+    /// Opens the region file at `path` for reading.
+    /// If the file has a current checksum sidecar, each chunk is checked against it as it
+    /// is read (see [checksum](super::checksum)).
+    pub fn open<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Self::open_with_config(path, IoConfig::default())
+    }
+
+    /// Like [RegionReader::open], with the buffer sizes in `config`.
+    pub fn open_with_config<P: AsRef<Path>>(path: P, config: IoConfig) -> McResult<Self> {
+        let path = path.as_ref();
+        let file_handle = backend(File::open(path)?)?;
+        if file_handle.len()? < 8192 {
+            return Err(McError::InvalidRegionFile);
+        }
+        let header = {
+            let mut header_buf = vec![0u8; 4096*2];
+            file_handle.read_exact_at(&mut header_buf, 0)?;
+            RegionHeader::read_from(&mut header_buf.as_slice())?
+        };
+        let checksums = RegionChecksums::load_current_sidecar(path)?;
+        Ok(Self {
+            header,
+            file_handle,
+            path: path.to_owned(),
+            config,
+            checksums,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn header(&self) -> &RegionHeader {
+        &self.header
+    }
+
+    /// The underlying file.
+    pub fn get_ref(&self) -> &RegionBackend {
+        &self.file_handle
+    }
+
+    /// The underlying file.
+    pub fn get_mut(&mut self) -> &mut RegionBackend {
+        &mut self.file_handle
+    }
+
+    /// Splits the reader into its header and file.
+    pub fn into_parts(self) -> (RegionHeader, RegionBackend) {
+        (self.header, self.file_handle)
+    }
+
+    /// Reassembles a reader from the parts returned by [RegionReader::into_parts].
+    /// Chunks are not checked against a checksum sidecar.
+    pub fn from_parts<P: AsRef<Path>>(path: P, header: RegionHeader, file_handle: RegionBackend) -> Self {
+        Self {
+            header,
+            file_handle,
+            path: path.as_ref().to_owned(),
+            config: IoConfig::default(),
+            checksums: None,
+        }
+    }
+
+    /// The checksums that chunks are checked against, if the file has a current sidecar.
+    pub fn checksums(&self) -> Option<&RegionChecksums> {
+        self.checksums.as_ref()
+    }
+
+    /// Checks every chunk against its checksum, returning the coordinates of the chunks that
+    /// don't match. If the file has no current sidecar, nothing is checked.
+    pub fn verify_checksums(&self) -> McResult<Vec<RegionCoord>> {
+        match &self.checksums {
+            Some(checksums) => checksums.verify(&self.file_handle, &self.header.sectors),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Checks the sector data of the chunk at `coord` against its checksum (if there is one), then decodes it.
+    fn decode_checked<T: Readable>(&self, coord: RegionCoord, data: &[u8]) -> McResult<T> {
+        if let Some(checksums) = &self.checksums {
+            checksums.check(&self.path, coord, stored_part(data))?;
+        }
+        decode_sector_data(data)
+    }
+
+    pub fn get_sector<C: Into<RegionCoord>>(&self, coord: C) -> RegionSector {
+        let coord: RegionCoord = coord.into();
+        self.header.sectors[coord.index()]
+    }
+
+    /// Reads the value stored for the chunk at `coord`.
+    pub fn read_data<C: Into<RegionCoord>, T: Readable>(&self, coord: C) -> McResult<T> {
+        let coord: RegionCoord = coord.into();
+        let sector = self.get_sector(coord);
+        if sector.is_empty() {
+            return Err(McError::RegionDataNotFound);
+        }
+        let mut buffer = vec![0u8; sector.size() as usize];
+        self.file_handle.read_exact_at(&mut buffer, sector.offset())?;
+        self.decode_checked(coord, &buffer)
+    }
+
+    /// Plans reads for `coords` so that they can be made in file order.
+    /// Chunks stored in adjacent sectors are merged into a single [ReadRun].
+    /// Duplicate coordinates and chunks that are not present are skipped.
+    pub fn plan_reads<C: Into<RegionCoord>, It: IntoIterator<Item = C>>(&self, coords: It) -> ReadPlan {
+        let mut chunks = coords.into_iter()
+            .map(Into::into)
+            .map(|coord: RegionCoord| PlannedChunk {
+                coord,
+                sector: self.header.sectors[coord.index()],
+            })
+            .filter(|chunk| !chunk.sector.is_empty())
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|chunk| (chunk.sector.sector_offset(), chunk.coord));
+        chunks.dedup_by_key(|chunk| chunk.coord);
+        let mut runs: Vec<ReadRun> = Vec::new();
+        for chunk in chunks {
+            match runs.last_mut() {
+                Some(run) if run.sector_offset + run.sector_count == chunk.sector.sector_offset() => {
+                    run.sector_count += chunk.sector.sector_count();
+                    run.chunks.push(chunk);
+                }
+                _ => runs.push(ReadRun {
+                    sector_offset: chunk.sector.sector_offset(),
+                    sector_count: chunk.sector.sector_count(),
+                    chunks: vec![chunk],
+                }),
+            }
+        }
+        ReadPlan { runs }
+    }
+
+    /// Executes a [ReadPlan], calling `read` with the result for each chunk in file order.
+    /// A chunk that fails to decode (or doesn't match its checksum) is passed to `read` as an error; IO errors and
+    /// errors returned by `read` stop execution.
+    pub fn read_planned<T: Readable, F: FnMut(RegionCoord, McResult<T>) -> McResult<()>>(&self, plan: &ReadPlan, mut read: F) -> McResult<()> {
+        let mut buffer = Vec::with_capacity(self.config.read_buf);
+        for run in plan.runs.iter() {
+            buffer.resize(run.size() as usize, 0);
+            self.file_handle.read_exact_at(&mut buffer, run.offset())?;
+            for chunk in run.chunks.iter() {
+                let start = (chunk.sector.offset() - run.offset()) as usize;
+                let end = start + chunk.sector.size() as usize;
+                read(chunk.coord, self.decode_checked(chunk.coord, &buffer[start..end]))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plans and executes reads for `coords`. See [RegionReader::plan_reads].
+    pub fn read_many<C, It, T, F>(&self, coords: It, read: F) -> McResult<()>
+    where
+        C: Into<RegionCoord>,
+        It: IntoIterator<Item = C>,
+        T: Readable,
+        F: FnMut(RegionCoord, McResult<T>) -> McResult<()>,
+    {
+        let plan = self.plan_reads(coords);
+        self.read_planned(&plan, read)
+    }
+}
+
+/// Decodes a value from the data of a sector (length, compression scheme, payload).
+pub(crate) fn decode_sector_data<T: Readable>(data: &[u8]) -> McResult<T> {
+    let mut reader = data;
+    let length: u32 = reader.read_value()?;
+    if length == 0 {
+        return Err(McError::RegionDataNotFound);
+    }
+    // The compression scheme is included in the length.
+    let payload_length = length as usize - 1;
+    let scheme: CompressionScheme = reader.read_value()?;
+    if payload_length > reader.len() {
+        return Err(McError::InvalidRegionFile);
+    }
+    let payload = &reader[..payload_length];
+    T::read_from(&mut payload_decoder(scheme, payload)?)
+}
+
+/// Creates a decoder for a chunk payload (the bytes after the compression scheme).
+/// A custom scheme name that runs past the end of the payload is [McError::InvalidRegionFile].
+pub(crate) fn payload_decoder(scheme: CompressionScheme, payload: &[u8]) -> McResult<MultiDecoder<&[u8]>> {
+    if scheme == CompressionScheme::Custom {
+        let name_length = payload.get(..2).map(|len| u16::from_be_bytes([len[0], len[1]]) as usize);
+        if name_length.is_none_or(|len| len + 2 > payload.len()) {
+            return Err(McError::InvalidRegionFile);
+        }
+    }
+    MultiDecoder::new(scheme, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn plan_reads_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        {
+            let mut region = RegionFile::create(&path)?;
+            for i in 0..8u16 {
+                region.write_data((i, 0), &(i as i64))?;
+            }
+            // Leave a gap by deleting a chunk in the middle.
+            region.delete_data((4, 0))?;
+        }
+        let reader = RegionReader::open(&path)?;
+        // Chunk 4 was deleted and (9, 9) was never written, so 0..=3 and 5..=7 are the two runs.
+        let plan = reader.plan_reads([(7u16, 0u16), (2, 0), (0, 0), (4, 0), (3, 0), (5, 0), (0, 0), (6, 0), (1, 0), (9, 9)]);
+        assert_eq!(plan.chunk_count(), 7);
+        assert_eq!(plan.io_count(), 2);
+        let order = plan.chunks().map(|chunk| chunk.coord.x()).collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 2, 3, 5, 6, 7]);
+        let mut read = Vec::new();
+        reader.read_planned(&plan, |coord, value: McResult<i64>| {
+            read.push((coord.x(), value?));
+            Ok(())
+        })?;
+        assert_eq!(read.len(), 7);
+        assert!(read.iter().all(|&(x, value)| x as i64 == value));
+        assert_eq!(reader.read_data::<_, i64>((6, 0))?, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn into_parts_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?;
+        region.write_data((1, 2), &42i64)?;
+        let (header, sector_manager, file) = region.into_parts()?;
+        assert!(!header.sectors[RegionCoord::from((1, 2)).index()].is_empty());
+        let mut region = RegionFile::from_parts(&path, header, sector_manager, file)?.deferred();
+        region.write_data((3, 4), &43i64)?;
+        // The deferred header is flushed.
+        let (_, _, file) = region.into_parts()?;
+        let mut header = vec![0u8; 4096 * 2];
+        file.read_exact_at(&mut header, 0)?;
+        let header = RegionHeader::read_from(&mut header.as_slice())?;
+        let reader = RegionReader::from_parts(&path, header, file);
+        assert_eq!(reader.read_data::<_, i64>((1, 2))?, 42);
+        assert_eq!(reader.read_data::<_, i64>((3, 4))?, 43);
+        Ok(())
+    }
+}
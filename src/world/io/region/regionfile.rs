@@ -2,7 +2,7 @@
 
 use std::{
     fs::File, io::{
-        BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Take, Write
+        Cursor, Write
     }, path::{
         Path,
         PathBuf,
@@ -11,23 +11,35 @@ use std::{
 
 use flate2::{
     write::ZlibEncoder,
-    read::{
-        GzDecoder,
-        ZlibDecoder,
-    },
     Compression,
 };
 
 use crate::{
     McResult, McError,
     ioext::*,
+    nbt::{
+        tag::{NamedTag, Tag},
+        tagpath::TagPath,
+    },
+    util::memory::MemorySize,
 };
 
 use super::{
     prelude::*,
+    positioned::{PositionedIo, RegionBackend, backend},
+    manifest::{ChunkManifestEntry, collect_manifest},
+    snapshot::{SnapshotMethod, snapshot_region},
+    checksum::{ChecksumSidecar, RegionChecksums, chunk_checksum, sidecar_path},
+    reader::{RegionReader, payload_decoder},
+    relocate::relocate_chunk,
+    debug::{DEBUG_BYTES, SectorDebug},
     {required_sectors, pad_size},
 };
 
+/// The sector manager and file of a [RegionFile] are only missing after [RegionFile::into_parts],
+/// which consumes the file, so they are never missing while it is used.
+const TAKEN_APART: &str = "RegionFile used after into_parts";
+
 pub trait RegionManager {
     type Sector;
     //	write_data
@@ -35,7 +47,7 @@ pub trait RegionManager {
     //	delete_data
     //	read_data
     //	optimize
-    fn read<'a, C: Into<RegionCoord>, F: FnMut(MultiDecoder<'a>) -> McResult<()>>(self, coord: C, read: F) -> McResult<()>;
+    fn read<'a, C: Into<RegionCoord>, F: FnMut(MultiDecoder<&'a [u8]>) -> McResult<()>>(self, coord: C, read: F) -> McResult<()>;
     fn read_data<C: Into<RegionCoord>, T: Readable>(self, coord: C) -> McResult<T>;
     fn write_data<C: Into<RegionCoord>, T: Writable>(self, coord: C, value: &T) -> McResult<Self::Sector>;
     fn write<C: Into<RegionCoord>, F: Fn()>(self, coord: C, write: F) -> McResult<Self::Sector>;
@@ -45,135 +57,548 @@ pub trait RegionManager {
 
 /// A construct for working with RegionFiles.
 /// Allows for reading and writing data from a RegionFile.
-pub struct RegionFile {
+///
+/// Chunk data and header entries are written to the file as each chunk is written
+/// (header entries are held back in [deferred](RegionFile::deferred) mode until a flush),
+/// but nothing is synced to disk until [RegionFile::close]. Dropping the file flushes a
+/// deferred header on a best-effort basis without syncing, so close the file when its
+/// contents need to survive a crash or when errors need to be handled.
+///
+/// Sectors are allocated by `A`, which is a [SectorManager] unless the file is opened with
+/// [RegionFile::open_with_allocator] or [RegionFile::create_with_allocator].
+pub struct RegionFile<A: SectorAllocator = SectorManager> {
     header: RegionHeader,
-    sector_manager: SectorManager,
+    /// Only taken by [RegionFile::into_parts] (see [RegionFile::allocator]).
+    sector_manager: Option<A>,
     /// This file handle is for both reading and writing.
-    file_handle: File,
+    /// All IO is positioned, so the file cursor is never used.
+    /// Only taken by [RegionFile::into_parts] (see [RegionFile::file]).
+    file_handle: Option<RegionBackend>,
     path: PathBuf,
+    /// Chunk data is read into this buffer before it is decoded.
+    read_buf: Vec<u8>,
+    /// The checksum sidecar, if checksums are enabled for this file.
+    checksums: Option<ChecksumSidecar>,
     /// Because the write size of a value sometimes can't quite be known until
     /// after it has been written, it will be helpful to have a buffer to write
     /// to before writing to the file. This will allow us to know exactly how
     /// many 4KiB blocks are needed to write this data so that a sector can be
     /// allocated.
     write_buf: Cursor<Vec<u8>>,
+    /// Where to snapshot the file before it is rewritten (see [RegionFile::set_snapshot_before_rewrite]).
+    snapshot_before_rewrite: Option<PathBuf>,
+    /// Header table updates are kept in memory until [RegionFile::flush] (see [RegionFile::deferred]).
+    deferred: bool,
+    /// The header in memory has changes that haven't been written to the file.
+    header_dirty: bool,
+    /// Sectors that were freed in [deferred](RegionFile::deferred) mode. The header in the file
+    /// may still point to them, so they are only given to the allocator once it is flushed.
+    freed_since_flush: Vec<RegionSector>,
+    /// When the file is synced to disk.
+    durability: Durability,
     pub compression: Compression,
 }
 
-pub enum MultiDecoder<'a> {
-    GZip(GzDecoder<Take<BufReader<&'a mut File>>>),
-    ZLib(ZlibDecoder<Take<BufReader<&'a mut File>>>),
-    Uncompressed(Take<BufReader<&'a mut File>>),
+/// How much a [RegionFile] does to make its writes survive a crash (see [RegionFile::set_durability]).
+/// Syncing is what makes writes durable, and it is by far the slowest part of a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Durability {
+    /// Nothing is synced, not even by [RegionFile::close]; the OS writes the file back in its
+    /// own time. For files that are thrown away (or rebuilt) after a crash.
+    None,
+    /// Every write syncs the file before it returns, so a write that returned survives a
+    /// crash. In [deferred](RegionFile::deferred) mode, the header is only written (and
+    /// synced) by [RegionFile::flush].
+    FlushOnWrite,
+    /// The file is synced when it is committed, by [RegionFile::commit] or [RegionFile::close].
+    #[default]
+    FsyncOnCommit,
 }
 
-impl<'a> Read for MultiDecoder<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        match self {
-            MultiDecoder::GZip(reader) => reader.read(buf),
-            MultiDecoder::ZLib(reader) => reader.read(buf),
-            MultiDecoder::Uncompressed(reader) => reader.read(buf),
-        }
+/// The result of [RegionFile::delete_many].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeleteReport {
+    /// The chunks that were deleted (or would be, for a dry run), in the order they were given.
+    pub chunks: Vec<RegionCoord>,
+    /// The sectors that were freed (or would be), in the same order as `chunks`.
+    pub sectors: Vec<RegionSector>,
+}
+
+impl DeleteReport {
+    /// The number of 4KiB sectors that were freed.
+    pub fn sector_count(&self) -> u64 {
+        self.sectors.iter().map(RegionSector::sector_count).sum()
+    }
+
+    /// The number of bytes that were freed.
+    pub fn size(&self) -> u64 {
+        self.sectors.iter().map(RegionSector::size).sum()
     }
 }
 
 impl RegionFile {
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Attempts to open a Minecraft region file at the given path, returning an error if it is not found.
+    pub fn open<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Self::open_with_config(path, IoConfig::default())
     }
 
-    pub fn sectors(&self) -> &SectorTable {
-        &self.header.sectors
+    /// Like [RegionFile::open], with the buffer sizes in `config`.
+    pub fn open_with_config<P: AsRef<Path>>(path: P, config: IoConfig) -> McResult<Self> {
+        Self::open_with_allocator(path, config, SectorManager::from_table)
     }
 
-    pub fn timestamps(&self) -> &TimestampTable {
-        &self.header.timestamps
+    /// Opens a region file for reading only, as a [RegionReader], which has no methods
+    /// that write. The file is opened without write access, so this works on read-only
+    /// filesystems, such as mounted backups and snapshots.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> McResult<RegionReader> {
+        RegionReader::open(path)
     }
 
-    pub fn header(&self) -> &RegionHeader {
-        &self.header
+    /// Like [RegionFile::open_read_only], with the buffer sizes in `config`.
+    pub fn open_read_only_with_config<P: AsRef<Path>>(path: P, config: IoConfig) -> McResult<RegionReader> {
+        RegionReader::open_with_config(path, config)
     }
 
-    pub fn get_sector<C: Into<RegionCoord>>(&self, coord: C) -> RegionSector {
-        let coord: RegionCoord = coord.into();
-        self.header.sectors[coord.index()]
+    /// Attempts to create a new Minecraft region file at the given path, returning an error if it already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Self::create_with_config(path, IoConfig::default())
     }
-    
-    pub fn get_timestamp<C: Into<RegionCoord>>(&self, coord: C) -> Timestamp {
-        let coord: RegionCoord = coord.into();
-        self.header.timestamps[coord.index()]
+
+    /// Like [RegionFile::create], with the buffer sizes in `config`.
+    pub fn create_with_config<P: AsRef<Path>>(path: P, config: IoConfig) -> McResult<Self> {
+        Self::create_with_allocator(path, config, SectorManager::from_table)
     }
 
-    // I made RegionFile.compression public, so this isn't likely needed, but it may be useful.
-    pub fn compression(&self) -> Compression {
-        self.compression
+    /// Creates a new [RegionFile] object, opening or creating a Minecraft region file at the given path.
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        Self::open_or_create_with_config(path, IoConfig::default())
     }
 
-    // I made RegionFile.compression public, so this isn't likely needed, but it may be useful.
-    pub fn set_compression(&mut self, compression: Compression) {
-        self.compression = compression;
+    /// Like [RegionFile::open_or_create], with the buffer sizes in `config`.
+    pub fn open_or_create_with_config<P: AsRef<Path>>(path: P, config: IoConfig) -> McResult<Self> {
+        let path = path.as_ref();
+        if path.is_file() {
+            Self::open_with_config(path, config)
+        } else {
+            Self::create_with_config(path, config)
+        }
     }
 
-    /// Attempts to open a Minecraft region file at the given path, returning an error if it is not found.
-    pub fn open<P: AsRef<Path>>(path: P) -> McResult<Self> {
+    /// Removes all unused sectors from the region file.
+    /// See [RegionFile::optimize_with_allocator].
+    pub fn optimize(&mut self) -> McResult<()> {
+        self.optimize_with_allocator(SectorManager::from_table)
+    }
+}
+
+impl<A: SectorAllocator> RegionFile<A> {
+    /// Like [RegionFile::open_with_config], with the sectors allocated by the allocator
+    /// that `allocator` creates from the file's sector table.
+    pub fn open_with_allocator<P: AsRef<Path>, F: FnOnce(&SectorTable) -> A>(path: P, config: IoConfig, allocator: F) -> McResult<Self> {
         let path = path.as_ref();
-        let mut file_handle = File::options()
+        let file_handle = backend(File::options()
             // Need to be able to read and write.
             .read(true).write(true)
-            .open(path)?;
-        // Seek to the end to figure out the size of the file.
-        file_handle.seek(SeekFrom::End(0))?;
-        let file_size = file_handle.stream_position()?;
-        if file_size < 8192 {
+            .open(path)?)?;
+        if file_handle.len()? < 8192 {
             // The size was too small to hold the header, which means it isn't
             // a valid region file.
             return Err(McError::InvalidRegionFile);
         }
-        file_handle.seek(SeekFrom::Start(0))?;
-        let header = {				
-            let mut temp_reader = BufReader::new((&mut file_handle).take(4096*2));
-            RegionHeader::read_from(&mut temp_reader)?
+        let header = {
+            let mut header_buf = vec![0u8; 4096*2];
+            file_handle.read_exact_at(&mut header_buf, 0)?;
+            RegionHeader::read_from(&mut header_buf.as_slice())?
         };
-        let sector_manager = SectorManager::from(header.sectors.iter());
+        let sector_manager = allocator(&header.sectors);
+        // If there's a current checksum sidecar, chunks are checked against it as they are read.
+        let checksums = RegionChecksums::load_current_sidecar(path)?
+            .map(|checksums| ChecksumSidecar::open(path, checksums))
+            .transpose()?;
         Ok(Self {
-            file_handle,
+            file_handle: Some(file_handle),
             header,
             compression: Compression::best(),
-            sector_manager,
-            write_buf: Cursor::new(Vec::with_capacity(4096*2)),
+            sector_manager: Some(sector_manager),
+            write_buf: Cursor::new(Vec::with_capacity(config.write_buf)),
+            read_buf: Vec::with_capacity(config.read_buf),
+            checksums,
+            snapshot_before_rewrite: None,
+            deferred: false,
+            header_dirty: false,
+            freed_since_flush: Vec::new(),
+            durability: Durability::default(),
             path: path.to_owned(),
         })
     }
 
-    /// Attempts to create a new Minecraft region file at the given path, returning an error if it already exists.
-    pub fn create<P: AsRef<Path>>(path: P) -> McResult<Self> {
+    /// Like [RegionFile::create_with_config], with the sectors allocated by the allocator
+    /// that `allocator` creates from the (empty) sector table.
+    pub fn create_with_allocator<P: AsRef<Path>, F: FnOnce(&SectorTable) -> A>(path: P, config: IoConfig, allocator: F) -> McResult<Self> {
         let path = path.as_ref();
         // Create region file with empty header.
-        let mut file_handle = File::options()
+        let file_handle = backend(File::options()
             // Need to be able to read and write.
             .read(true).write(true)
             // The file doesn't exist, so we need to create it.
             .create_new(true)
-            .open(path)?;
+            .open(path)?)?;
         // Write an empty header since this is a new file.
-        file_handle.write_zeroes(4096*2)?;
+        file_handle.write_all_at(&[0u8; 4096*2], 0)?;
+        // A sidecar left over from a previous file at this path would no longer match,
+        // so reset it to match the empty file.
+        let checksums = if sidecar_path(path).is_file() {
+            Some(ChecksumSidecar::create(path, RegionChecksums::default())?)
+        } else {
+            None
+        };
+        let header = RegionHeader::default();
+        let sector_manager = allocator(&header.sectors);
         Ok(Self {
-            file_handle,
+            file_handle: Some(file_handle),
             compression: Compression::best(),
-            write_buf: Cursor::new(Vec::with_capacity(4096*2)),
-            header: RegionHeader::default(),
-            sector_manager: SectorManager::new(),
+            write_buf: Cursor::new(Vec::with_capacity(config.write_buf)),
+            read_buf: Vec::with_capacity(config.read_buf),
+            checksums,
+            snapshot_before_rewrite: None,
+            deferred: false,
+            header_dirty: false,
+            freed_since_flush: Vec::new(),
+            durability: Durability::default(),
+            header,
+            sector_manager: Some(sector_manager),
             path: path.to_owned(),
         })
     }
 
-    /// Creates a new [RegionFile] object, opening or creating a Minecraft region file at the given path.
-    pub fn open_or_create<P: AsRef<Path>>(path: P) -> McResult<Self> {
-        let path = path.as_ref();
-        if path.is_file() {
-            Self::open(path)
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn sectors(&self) -> &SectorTable {
+        &self.header.sectors
+    }
+
+    pub fn timestamps(&self) -> &TimestampTable {
+        &self.header.timestamps
+    }
+
+    pub fn header(&self) -> &RegionHeader {
+        &self.header
+    }
+
+    /// The allocator that manages the sectors of this file.
+    pub fn sector_manager(&self) -> &A {
+        self.sector_manager.as_ref().expect(TAKEN_APART)
+    }
+
+    fn allocator(&mut self) -> &mut A {
+        self.sector_manager.as_mut().expect(TAKEN_APART)
+    }
+
+    fn file(&self) -> &RegionBackend {
+        self.file_handle.as_ref().expect(TAKEN_APART)
+    }
+
+    /// Frees `sector`. In [deferred](RegionFile::deferred) mode, it is held back until the
+    /// header is flushed, so that it isn't overwritten while the file still points to it.
+    fn free_sector(&mut self, sector: RegionSector) -> McResult<()> {
+        if sector.is_empty() {
+            return Ok(());
+        }
+        if self.deferred {
+            self.freed_since_flush.push(sector);
+            Ok(())
+        } else {
+            self.allocator().deallocate(sector)
+        }
+    }
+
+    /// Allocates `size` sectors for the chunk that is stored in `old`, freeing `old`
+    /// (see [RegionFile::free_sector]).
+    fn reallocate_sector(&mut self, old: RegionSector, size: u8) -> McResult<RegionSector> {
+        if self.deferred {
+            let new = self.allocator().allocate_err(size)?;
+            self.free_sector(old)?;
+            Ok(new)
         } else {
-            Self::create(path)
+            self.allocator().reallocate(old, size)
+        }
+    }
+
+    /// Copies this file to `path` (replacing it), as a reflink where the filesystem supports it.
+    /// The checksum sidecar is copied too, if there is one.
+    pub fn snapshot_to<P: AsRef<Path>>(&self, path: P) -> McResult<SnapshotMethod> {
+        snapshot_region(&self.path, path.as_ref())
+    }
+
+    /// Opts in to taking a snapshot (see [RegionFile::snapshot_to]) at `path` before operations that
+    /// rewrite the file, such as [RegionFile::optimize]. Each snapshot replaces the last one.
+    pub fn set_snapshot_before_rewrite(&mut self, path: Option<PathBuf>) {
+        self.snapshot_before_rewrite = path;
+    }
+
+    pub fn snapshot_before_rewrite(&self) -> Option<&Path> {
+        self.snapshot_before_rewrite.as_deref()
+    }
+
+    /// Takes the snapshot that [RegionFile::set_snapshot_before_rewrite] asked for, if any.
+    fn snapshot_for_rewrite(&self) -> McResult<()> {
+        if let Some(path) = &self.snapshot_before_rewrite {
+            snapshot_region(&self.path, path)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the chunks in this file with their timestamps, sectors, and compressed sizes.
+    /// See [manifest](super::manifest).
+    pub fn manifest(&self) -> McResult<Vec<ChunkManifestEntry>> {
+        collect_manifest(&self.header, self.file())
+    }
+
+    /// Keeps header table updates in memory until [RegionFile::flush] (or until the file is dropped),
+    /// which then writes the whole header at once. This saves two small writes per chunk when
+    /// writing many chunks to the same file. Chunk data is still written immediately, but
+    /// chunks written since the last flush aren't visible in the file until the header is.
+    /// Sectors that are freed aren't reused until then either, so the header in the file
+    /// keeps pointing to intact chunks.
+    pub fn deferred(mut self) -> Self {
+        self.deferred = true;
+        self
+    }
+
+    /// Turns deferred header writes (see [RegionFile::deferred]) on or off.
+    /// Turning them off flushes the header.
+    pub fn set_deferred(&mut self, deferred: bool) -> McResult<()> {
+        self.deferred = deferred;
+        if !deferred {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// Returns true if there are header changes that haven't been written to the file.
+    pub fn is_dirty(&self) -> bool {
+        self.header_dirty
+    }
+
+    /// Flushes the header (see [RegionFile::flush]) and syncs the file and its checksum
+    /// sidecar to disk (unless the durability is [Durability::None]). Once this returns,
+    /// everything written to the file is durable.
+    pub fn close(mut self) -> McResult<()> {
+        self.commit()
+    }
+
+    /// Like [RegionFile::close], without closing the file.
+    pub fn commit(&mut self) -> McResult<()> {
+        self.flush()?;
+        if self.durability != Durability::None {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Sets when the file is synced to disk (see [Durability]).
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Sets when the file is synced to disk. The default is [Durability::FsyncOnCommit].
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    /// Syncs the file and its checksum sidecar to disk.
+    fn sync(&self) -> McResult<()> {
+        self.file().sync_all()?;
+        if let Some(sidecar) = &self.checksums {
+            sidecar.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the file after a write with [Durability::FlushOnWrite].
+    fn sync_write(&self) -> McResult<()> {
+        if self.durability == Durability::FlushOnWrite {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the header to the file if it has unwritten changes.
+    pub fn flush(&mut self) -> McResult<()> {
+        if !self.header_dirty {
+            return Ok(());
+        }
+        let mut header = Vec::with_capacity(4096 * 2);
+        self.header.write_to(&mut header)?;
+        self.file().write_all_at(&header, 0)?;
+        self.header_dirty = false;
+        for sector in std::mem::take(&mut self.freed_since_flush) {
+            self.allocator().deallocate(sector)?;
+        }
+        self.touch_checksums()?;
+        self.sync_write()
+    }
+
+    /// Keeps the checksum sidecar (if there is one) from looking stale after the header is written.
+    fn touch_checksums(&self) -> McResult<()> {
+        match &self.checksums {
+            Some(sidecar) => sidecar.touch(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the table entry for `coord` to the header in the file (or marks the header
+    /// as dirty when [deferred](RegionFile::deferred)).
+    /// The table that is written to is determined by the type of `value`.
+    fn write_table_value<T: Writable + RegionTableItem>(&mut self, coord: RegionCoord, value: T) -> McResult<()> {
+        if self.deferred {
+            self.header_dirty = true;
+            return Ok(());
+        }
+        let mut entry = Vec::with_capacity(4);
+        entry.write_value(value)?;
+        self.file().write_all_at(&entry, T::OFFSET + coord.index() as u64 * 4)?;
+        self.touch_checksums()
+    }
+
+    pub fn get_sector<C: Into<RegionCoord>>(&self, coord: C) -> RegionSector {
+        let coord: RegionCoord = coord.into();
+        self.header.sectors[coord.index()]
+    }
+    
+    pub fn get_timestamp<C: Into<RegionCoord>>(&self, coord: C) -> Timestamp {
+        let coord: RegionCoord = coord.into();
+        self.header.timestamps[coord.index()]
+    }
+
+    /// Reads the raw header entry and the first [DEBUG_BYTES] bytes of a chunk's sectors,
+    /// without decompressing anything. The [Display](std::fmt::Display) of the result
+    /// (with a hexdump) is meant for bug reports about corrupt chunks.
+    pub fn debug_sector<C: Into<RegionCoord>>(&self, coord: C) -> McResult<SectorDebug> {
+        self.debug_sector_bytes(coord, DEBUG_BYTES)
+    }
+
+    /// Like [RegionFile::debug_sector], reading up to `count` bytes of the sectors.
+    /// Only the part of the sectors that is within the file is read.
+    pub fn debug_sector_bytes<C: Into<RegionCoord>>(&self, coord: C, count: usize) -> McResult<SectorDebug> {
+        let coord: RegionCoord = coord.into();
+        let sector = self.header.sectors[coord.index()];
+        let file_length = self.file().len()?;
+        let mut bytes = Vec::new();
+        if !sector.is_empty() && sector.offset() < file_length {
+            let end = sector.end_offset().min(file_length).min(sector.offset() + count as u64);
+            bytes.resize((end - sector.offset()) as usize, 0);
+            self.file().read_exact_at(&mut bytes, sector.offset())?;
         }
+        Ok(SectorDebug {
+            coord,
+            sector,
+            timestamp: self.header.timestamps[coord.index()],
+            file_length,
+            length: bytes.get(..4).map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]])),
+            scheme_byte: bytes.get(4).copied(),
+            detected_scheme: bytes.get(5..).and_then(CompressionScheme::detect),
+            bytes,
+        })
+    }
+
+    /// The underlying file.
+    pub fn get_ref(&self) -> &RegionBackend {
+        self.file()
+    }
+
+    /// The underlying file.
+    /// Writing to the file directly bypasses the header and sector manager,
+    /// so it is up to the caller to keep them in agreement with the file.
+    pub fn get_mut(&mut self) -> &mut RegionBackend {
+        self.file_handle.as_mut().expect(TAKEN_APART)
+    }
+
+    /// Splits the region file into its header, sector manager, and file.
+    /// A [deferred](RegionFile::deferred) header is flushed first (but not synced).
+    pub fn into_parts(mut self) -> McResult<(RegionHeader, A, RegionBackend)> {
+        self.flush()?;
+        // The header is clean, so dropping what is left of the file doesn't write anything.
+        Ok((
+            std::mem::take(&mut self.header),
+            self.sector_manager.take().expect(TAKEN_APART),
+            self.file_handle.take().expect(TAKEN_APART),
+        ))
+    }
+
+    /// Reassembles a region file from the parts returned by [RegionFile::into_parts].
+    /// If the file has a current checksum sidecar, it is loaded so that it stays up to date.
+    pub fn from_parts<P: AsRef<Path>>(path: P, header: RegionHeader, sector_manager: A, file_handle: RegionBackend) -> McResult<Self> {
+        let path = path.as_ref();
+        let checksums = RegionChecksums::load_current_sidecar(path)?
+            .map(|checksums| ChecksumSidecar::open(path, checksums))
+            .transpose()?;
+        Ok(Self {
+            header,
+            sector_manager: Some(sector_manager),
+            file_handle: Some(file_handle),
+            path: path.to_owned(),
+            write_buf: Cursor::new(Vec::with_capacity(IoConfig::default().write_buf)),
+            read_buf: Vec::with_capacity(IoConfig::default().read_buf),
+            checksums,
+            snapshot_before_rewrite: None,
+            deferred: false,
+            header_dirty: false,
+            freed_since_flush: Vec::new(),
+            durability: Durability::default(),
+            compression: Compression::best(),
+        })
+    }
+
+    /// The checksums of the chunks in this file, if checksums are enabled.
+    pub fn checksums(&self) -> Option<&RegionChecksums> {
+        self.checksums.as_ref().map(|sidecar| &sidecar.checksums)
+    }
+
+    /// Computes the checksums of all chunks and writes them to a sidecar file
+    /// (see [super::checksum]). The sidecar is kept up to date on every write.
+    pub fn enable_checksums(&mut self) -> McResult<()> {
+        let checksums = RegionChecksums::compute(self.file(), &self.header.sectors)?;
+        self.checksums = Some(ChecksumSidecar::create(&self.path, checksums)?);
+        Ok(())
+    }
+
+    /// Stops updating checksums and removes the sidecar file.
+    pub fn disable_checksums(&mut self) -> McResult<()> {
+        if self.checksums.take().is_some() {
+            std::fs::remove_file(sidecar_path(&self.path))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the coordinates of the chunks that don't match their checksum.
+    /// If checksums are not enabled, nothing is checked.
+    pub fn verify_checksums(&self) -> McResult<Vec<RegionCoord>> {
+        match self.checksums() {
+            Some(checksums) => checksums.verify(self.file(), &self.header.sectors),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // I made RegionFile.compression public, so this isn't likely needed, but it may be useful.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // I made RegionFile.compression public, so this isn't likely needed, but it may be useful.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
     }
 
     pub fn write_with_utcnow<C: Into<RegionCoord>, F: FnMut(&mut ZlibEncoder<&mut Cursor<Vec<u8>>>) -> McResult<()>>(&mut self, coord: C, mut write: F) -> McResult<RegionSector> {
@@ -188,36 +613,37 @@ impl RegionFile {
         self.write_data_timestamped(coord, value, Timestamp::utc_now())
     }
 
-    pub fn read<'a, C: Into<RegionCoord>, R, F: FnMut(MultiDecoder<'a>) -> McResult<R>>(&'a mut self, coord: C, mut read: F) -> McResult<R> {
+    /// Reads the chunk at `coord`, passing a decoder for its payload to `read`.
+    /// If checksums are enabled, the chunk is checked first, and [McError::ChecksumMismatch]
+    /// is returned if it doesn't match.
+    pub fn read<'a, C: Into<RegionCoord>, R, F: FnMut(MultiDecoder<&'a [u8]>) -> McResult<R>>(&'a mut self, coord: C, mut read: F) -> McResult<R> {
         let coord: RegionCoord = coord.into();
         let sector = self.header.sectors[coord.index()];
         if sector.is_empty() {
             return Err(McError::RegionDataNotFound);
         }
-        let mut reader = BufReader::new(&mut self.file_handle);
-        reader.seek(SeekFrom::Start(sector.offset()))?;
-        let length: u32 = reader.read_value()?;
+        // Read the length and the compression scheme.
+        let mut head = [0u8; 5];
+        self.file().read_exact_at(&mut head, sector.offset())?;
+        let mut head = head.as_slice();
+        let length: u32 = head.read_value()?;
         if length == 0 {
             return Err(McError::RegionDataNotFound);
         }
-        let scheme: CompressionScheme = reader.read_value()?;
-        match scheme {
-            CompressionScheme::GZip => {
-                // Subtract 1 from length because the compression scheme is included in the length.
-                let decoder = GzDecoder::new(reader.take((length - 1) as u64));
-                let multi = MultiDecoder::GZip(decoder);
-                read(multi)
-            },
-            CompressionScheme::ZLib => {
-                let decoder = ZlibDecoder::new(reader.take((length - 1) as u64));
-                let multi = MultiDecoder::ZLib(decoder);
-                read(multi)
-            },
-            CompressionScheme::Uncompressed => {
-                let multi = MultiDecoder::Uncompressed(reader.take((length - 1) as u64));
-                read(multi)
-            },
+        let scheme: CompressionScheme = head.read_value()?;
+        // Subtract 1 from length because the compression scheme is included in the length.
+        let payload_length = (length - 1) as u64;
+        if payload_length + 5 > sector.size() {
+            return Err(McError::InvalidRegionFile);
+        }
+        // The head is read again with the payload so that the checksum can cover both.
+        self.read_buf.resize(payload_length as usize + 5, 0);
+        self.file_handle.as_ref().expect(TAKEN_APART).read_exact_at(&mut self.read_buf, sector.offset())?;
+        if let Some(checksums) = self.checksums() {
+            checksums.check(&self.path, coord, &self.read_buf)?;
         }
+        let payload: &'a [u8] = &self.read_buf[5..];
+        read(payload_decoder(scheme, payload)?)
     }
 
     pub fn read_data<C: Into<RegionCoord>, T: Readable>(&mut self, coord: C) -> McResult<T> {
@@ -226,10 +652,62 @@ impl RegionFile {
         })
     }
 
-    pub fn write<C: Into<RegionCoord>, F: FnMut(&mut ZlibEncoder<&mut Cursor<Vec<u8>>>) -> McResult<()>>(&mut self, coord: C, mut write: F) -> McResult<RegionSector> {
-        let coord: RegionCoord = coord.into();
+    /// Reads the chunks at `coords`, decompressing and decoding them in parallel on the rayon
+    /// thread pool. The sectors are read from disk first, in file order, on the calling thread.
+    /// Returns a result for each coordinate in the order of `coords` ([McError::RegionDataNotFound]
+    /// for chunks that are not present, [McError::ChecksumMismatch] for chunks that don't match
+    /// their checksum). IO errors stop the read.
+    #[cfg(feature = "rayon")]
+    pub fn read_many_parallel<C, It, T>(&self, coords: It) -> McResult<Vec<McResult<T>>>
+    where
+        C: Into<RegionCoord>,
+        It: IntoIterator<Item = C>,
+        T: Readable + Send,
+    {
+        use rayon::prelude::*;
+        use super::{checksum::stored_part, reader::decode_sector_data};
+        let coords = coords.into_iter().map(Into::into).collect::<Vec<RegionCoord>>();
+        let sectors = coords.iter()
+            .map(|coord| self.header.sectors[coord.index()])
+            .collect::<Vec<_>>();
+        let mut order = (0..sectors.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| sectors[index].offset());
+        let mut stored = vec![Vec::new(); sectors.len()];
+        for index in order {
+            let sector = sectors[index];
+            if sector.is_empty() {
+                continue;
+            }
+            let mut buffer = vec![0u8; sector.size() as usize];
+            self.file().read_exact_at(&mut buffer, sector.offset())?;
+            stored[index] = buffer;
+        }
+        let (checksums, path) = (self.checksums(), self.path.as_path());
+        Ok(stored.into_par_iter()
+            .zip(coords)
+            .map(|(data, coord)| {
+                if data.is_empty() {
+                    return Err(McError::RegionDataNotFound);
+                }
+                if let Some(checksums) = checksums {
+                    checksums.check(path, coord, stored_part(&data))?;
+                }
+                decode_sector_data(&data)
+            })
+            .collect())
+    }
+
+    pub fn write<C: Into<RegionCoord>, F: FnMut(&mut ZlibEncoder<&mut Cursor<Vec<u8>>>) -> McResult<()>>(&mut self, coord: C, write: F) -> McResult<RegionSector> {
+        let sector = self.write_unsynced(coord.into(), write)?;
+        self.sync_write()?;
+        Ok(sector)
+    }
+
+    /// Writes a chunk like [RegionFile::write], without syncing it for [Durability::FlushOnWrite].
+    fn write_unsynced<F: FnMut(&mut ZlibEncoder<&mut Cursor<Vec<u8>>>) -> McResult<()>>(&mut self, coord: RegionCoord, mut write: F) -> McResult<RegionSector> {
         // Clear the write_buf to prepare it for writing.
         self.write_buf.get_mut().clear();
+        self.write_buf.set_position(0);
         // Gotta write 5 bytes to the buffer so that there's room for the length and the compression scheme.
         // To kill two birds with one stone, I'll write all 2s so that I don't have to go back and write the
         // compression scheme after writing the length.
@@ -259,18 +737,64 @@ impl RegionFile {
         self.write_buf.write_value((length + 1) as u32)?;
         // Allocation
         let old_sector = self.header.sectors[coord.index()];
-        let new_sector = self.sector_manager.reallocate_err(old_sector, required_sectors as u8)?;
+        let new_sector = self.reallocate_sector(old_sector, required_sectors as u8)?;
         self.header.sectors[coord.index()] = new_sector;
         // Writing to file
-        let mut writer = BufWriter::new(&mut self.file_handle);
-        writer.seek(SeekFrom::Start(new_sector.offset()))?;
-        writer.write_all(self.write_buf.get_ref().as_slice())?;
-        writer.seek(coord.sector_table_offset())?;
-        writer.write_value(new_sector)?;
-        writer.flush()?;
+        self.file().write_all_at(self.write_buf.get_ref().as_slice(), new_sector.offset())?;
+        self.write_table_value(coord, new_sector)?;
+        if let Some(sidecar) = self.checksums.as_mut() {
+            // The checksum covers the length, compression scheme, and payload, but not the padding.
+            sidecar.update(coord, chunk_checksum(&self.write_buf.get_ref()[..length + 5]))?;
+        }
         Ok(new_sector)
     }
 
+    /// Writes an already encoded payload to the file. `scheme` is written
+    /// directly after the length, so it must include the compression scheme ID
+    /// (and for custom schemes, the name).
+    #[cfg(feature = "zstd")]
+    fn write_encoded(&mut self, coord: RegionCoord, scheme: &[u8], payload: &[u8]) -> McResult<RegionSector> {
+        let length = scheme.len() + payload.len();
+        // + 4 for the length bytes.
+        let required_sectors = required_sectors((length + 4) as u32);
+        if required_sectors > 255 {
+            return Err(McError::RegionDataTooLarge);
+        }
+        let old_sector = self.header.sectors[coord.index()];
+        let new_sector = self.reallocate_sector(old_sector, required_sectors as u8)?;
+        self.header.sectors[coord.index()] = new_sector;
+        let mut buffer = Vec::with_capacity(new_sector.size() as usize);
+        buffer.write_value(length as u32)?;
+        buffer.write_all(scheme)?;
+        buffer.write_all(payload)?;
+        buffer.write_zeroes(pad_size((length + 4) as u64))?;
+        self.file().write_all_at(&buffer, new_sector.offset())?;
+        self.write_table_value(coord, new_sector)?;
+        if let Some(sidecar) = self.checksums.as_mut() {
+            sidecar.update(coord, chunk_checksum(&buffer[..length + 4]))?;
+        }
+        Ok(new_sector)
+    }
+
+    /// Writes a value using the custom `minecraft:zstd` compression scheme.
+    #[cfg(feature = "zstd")]
+    pub fn write_data_zstd<C: Into<RegionCoord>, T: Writable, Ts: Into<Timestamp>>(&mut self, coord: C, value: &T, timestamp: Ts, level: i32) -> McResult<RegionSector> {
+        use crate::util::zstd::CUSTOM_SCHEME_NAME;
+        let coord: RegionCoord = coord.into();
+        let mut data = Vec::new();
+        value.write_to(&mut data)?;
+        let payload = crate::util::zstd::compress(&data, level)?;
+        let mut scheme = vec![CompressionScheme::Custom as u8];
+        scheme.write_value(CUSTOM_SCHEME_NAME.len() as u16)?;
+        scheme.extend_from_slice(CUSTOM_SCHEME_NAME.as_bytes());
+        let allocation = self.write_encoded(coord, &scheme, &payload)?;
+        let timestamp: Timestamp = timestamp.into();
+        self.header.timestamps[coord.index()] = timestamp;
+        self.write_table_value(coord, timestamp)?;
+        self.sync_write()?;
+        Ok(allocation)
+    }
+
     pub fn write_data<C: Into<RegionCoord>, T: Writable>(&mut self, coord: C, value: &T) -> McResult<RegionSector> {
         self.write(coord, |mut encoder| {
             value.write_to(&mut encoder)?;
@@ -281,15 +805,12 @@ impl RegionFile {
     pub fn write_timestamped<'a, C: Into<RegionCoord>, Ts: Into<Timestamp>, F: FnMut(&mut ZlibEncoder<&mut Cursor<Vec<u8>>>) -> McResult<()>>(&mut self, coord: C, timestamp: Ts, write: F) -> McResult<RegionSector> {
         let coord: RegionCoord = coord.into();
         // let allocation = self.write_data(coord, value)?;
-        let allocation = self.write(coord, write)?;
+        let allocation = self.write_unsynced(coord, write)?;
         let timestamp: Timestamp = timestamp.into();
         self.header.timestamps[coord.index()] = timestamp;
         // Write the timestamp to the file.
-        let mut writer = BufWriter::new(&mut self.file_handle);
-        writer.seek(coord.timestamp_table_offset())?;
-        writer.write_value(timestamp)?;
-        // I'm pretty sure that flush() doesn't do anything, but I'll put it here just in case.
-        writer.flush()?;
+        self.write_table_value(coord, timestamp)?;
+        self.sync_write()?;
         Ok(allocation)
     }
 
@@ -300,41 +821,474 @@ impl RegionFile {
         })
     }
 
+    /// Reads the chunk at `coord`, sets the tag at `path` (relative to the root) to `value`,
+    /// and writes the chunk back with the current time. The parent of `path` must already exist.
+    pub fn update_path<C: Into<RegionCoord>, T: Into<Tag>>(&mut self, coord: C, path: &TagPath, value: T) -> McResult<RegionSector> {
+        let value: Tag = value.into();
+        self.update_paths_at(coord.into(), &[(path.clone(), value)])
+    }
+
+    /// Applies every `(path, value)` in `updates` (as in [RegionFile::update_path]) to each chunk
+    /// in `coords`, reading and writing each chunk once. Chunks that are not present are skipped.
+    /// Returns the number of chunks that were updated.
+    pub fn update_paths<C: Into<RegionCoord>, I: IntoIterator<Item = C>>(&mut self, coords: I, updates: &[(TagPath, Tag)]) -> McResult<usize> {
+        let mut count = 0;
+        for coord in coords {
+            let coord: RegionCoord = coord.into();
+            if self.header.sectors[coord.index()].is_empty() {
+                continue;
+            }
+            self.update_paths_at(coord, updates)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn update_paths_at(&mut self, coord: RegionCoord, updates: &[(TagPath, Tag)]) -> McResult<RegionSector> {
+        let mut root: NamedTag = self.read_data(coord)?;
+        updates.iter().try_for_each(|(path, value)| {
+            root.tag_mut()
+                .set_child(path.path(), value.clone())
+                .map_err(|_| McError::TagPathNotFound(path.clone()))
+        })?;
+        self.write_data_with_utcnow(coord, &root)
+    }
+
+    /// Moves the chunk at `from` to `to`, replacing the chunk at `to` if there is one (see
+    /// [RegionFile::swap_chunks] to keep it), and frees the old sector. The positions stored
+    /// in the chunk are rewritten for its new slot (see [relocate](super::relocate)), so the
+    /// chunk is decoded and encoded again. The chunk keeps its timestamp.
+    /// Returns [McError::RegionDataNotFound] if there is no chunk at `from`.
+    pub fn move_chunk<C: Into<RegionCoord>, D: Into<RegionCoord>>(&mut self, from: C, to: D) -> McResult<RegionSector> {
+        let (from, to): (RegionCoord, RegionCoord) = (from.into(), to.into());
+        let (root, timestamp) = self.read_relocated(from, to)?.ok_or(McError::RegionDataNotFound)?;
+        if from == to {
+            return Ok(self.get_sector(from));
+        }
+        let sector = self.write_data_timestamped(to, &root, timestamp)?;
+        self.delete_data(from)?;
+        Ok(sector)
+    }
+
+    /// Swaps the chunks at `a` and `b`, either of which may be empty. The positions stored
+    /// in the chunks are rewritten as in [RegionFile::move_chunk], and the chunks keep their
+    /// timestamps.
+    pub fn swap_chunks<C: Into<RegionCoord>, D: Into<RegionCoord>>(&mut self, a: C, b: D) -> McResult<()> {
+        let (a, b): (RegionCoord, RegionCoord) = (a.into(), b.into());
+        if a == b {
+            return Ok(());
+        }
+        let chunk_a = self.read_relocated(a, b)?;
+        let chunk_b = self.read_relocated(b, a)?;
+        for (coord, chunk) in [(b, chunk_a), (a, chunk_b)] {
+            match chunk {
+                Some((root, timestamp)) => {
+                    self.write_data_timestamped(coord, &root, timestamp)?;
+                }
+                None => {
+                    self.delete_data(coord)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the chunk at `from` (if there is one) with its positions rewritten for `to`.
+    fn read_relocated(&mut self, from: RegionCoord, to: RegionCoord) -> McResult<Option<(NamedTag, Timestamp)>> {
+        if self.get_sector(from).is_empty() {
+            return Ok(None);
+        }
+        let mut root: NamedTag = self.read_data(from)?;
+        relocate_chunk(root.tag_mut(), to);
+        Ok(Some((root, self.get_timestamp(from))))
+    }
+
     pub fn delete_data<C: Into<RegionCoord>>(&mut self, coord: C) -> McResult<RegionSector> {
         let coord: RegionCoord = coord.into();
         let sector = self.header.sectors[coord.index()];
         if sector.is_empty() {
             return Ok(sector);
         }
-        self.sector_manager.deallocate(sector);
+        self.free_sector(sector)?;
         self.header.sectors[coord.index()] = RegionSector::default();
         self.header.timestamps[coord.index()] = Timestamp::default();
         // Clear the sector from the sector table
-        let mut writer = BufWriter::new(&mut self.file_handle);
-        writer.seek(coord.sector_table_offset())?;
-        writer.write_zeroes(4)?;
+        self.write_table_value(coord, RegionSector::default())?;
         // Clear the timestamp from the timestamp table.
-        writer.seek(coord.timestamp_table_offset())?;
-        writer.write_zeroes(4)?;
-        writer.flush()?;
+        self.write_table_value(coord, Timestamp::default())?;
+        if let Some(sidecar) = self.checksums.as_mut() {
+            sidecar.update(coord, 0)?;
+        }
+        self.sync_write()?;
         Ok(sector)
     }
 
-    ///	Removes all unused sectors from the region file, rearranging it so that it is optimized.
-    ///	This is a costly operation, so it should only be performed when a region file reaches a certain threshhold 
-    ///	of complexity.
-    pub fn optimize(&mut self) -> McResult<()> {
-        //	There is likely an algorithm that can be invented to optimize the file, and as a consequence
-        //	there should be an algorithm that can measure the complexity for solving with the first algorithm.
-        //	Therefore it should be possible to pass a sector table into the complexity measuring algorithm to measure the cost
-        //	of optimization.
-        //		optimization_cost(sector_table)
-        
-        // I had an idea for how I might be able to write the optimization algorithm.
-        // What I can do is I can get information about the sectors:
-        // I would need the gaps, then the upper sectors that need to be moved around to fill in the gaps.
-        
-
-        todo!()
-    }
-}
\ No newline at end of file
+    /// Deletes the chunks at `coords`, freeing their sectors in place (the file isn't rebuilt,
+    /// so freed sectors are reused by later writes). Chunks that are not present are skipped.
+    /// Each chunk's sector and timestamp are cleared, and the header is written once at the
+    /// end (or held back in [deferred](RegionFile::deferred) mode).
+    /// With `dry_run`, nothing is changed, and the report lists what would be freed.
+    pub fn delete_many<C: Into<RegionCoord>, I: IntoIterator<Item = C>>(&mut self, coords: I, dry_run: bool) -> McResult<DeleteReport> {
+        let mut report = DeleteReport::default();
+        let mut seen = [false; 1024];
+        for coord in coords {
+            let coord: RegionCoord = coord.into();
+            let sector = self.header.sectors[coord.index()];
+            if sector.is_empty() || std::mem::replace(&mut seen[coord.index()], true) {
+                continue;
+            }
+            report.chunks.push(coord);
+            report.sectors.push(sector);
+            if dry_run {
+                continue;
+            }
+            self.free_sector(sector)?;
+            self.header.sectors[coord.index()] = RegionSector::default();
+            self.header.timestamps[coord.index()] = Timestamp::default();
+            if let Some(sidecar) = self.checksums.as_mut() {
+                sidecar.update(coord, 0)?;
+            }
+        }
+        if !dry_run && !report.chunks.is_empty() {
+            self.header_dirty = true;
+            if !self.deferred {
+                self.flush()?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Removes all unused sectors from the region file by rewriting it with its chunks (in the
+    /// order that they are stored) packed together after the header. The new file is written
+    /// next to this one and moved into place, so a failure leaves the file as it was. The stored
+    /// bytes are copied as they are, so checksums still match. Afterwards, sectors are allocated
+    /// by the allocator that `allocator` creates from the new sector table.
+    /// This is a costly operation, so it should only be performed when a region file reaches a
+    /// certain threshold of fragmentation (see [SectorManager::layout_map]). If a snapshot was
+    /// asked for with [RegionFile::set_snapshot_before_rewrite], it is taken first.
+    pub fn optimize_with_allocator<F: FnOnce(&SectorTable) -> A>(&mut self, allocator: F) -> McResult<()> {
+        self.snapshot_for_rewrite()?;
+        let mut chunks = self.header.sectors.enumerate()
+            .filter(|(_, sector)| !sector.is_empty())
+            .map(|(coord, sector)| (*sector, coord))
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|(sector, _)| sector.sector_offset());
+        let mut header = self.header.clone();
+        let mut offset = 2;
+        for (sector, coord) in chunks.iter() {
+            header.sectors[*coord] = RegionSector::new(offset, sector.sector_count() as u8);
+            offset += sector.sector_count() as u32;
+        }
+        let source = self.file();
+        let buffer_size = self.write_buf.get_ref().capacity();
+        atomic_replace(&self.path, |file| {
+            let mut writer = std::io::BufWriter::with_capacity(buffer_size, file);
+            header.write_to(&mut writer)?;
+            let mut data = Vec::new();
+            for (sector, _) in chunks.iter() {
+                data.resize(sector.size() as usize, 0);
+                source.read_exact_at(&mut data, sector.offset())?;
+                writer.write_all(&data)?;
+            }
+            writer.flush()?;
+            Ok(())
+        })?;
+        self.file_handle = Some(backend(File::options().read(true).write(true).open(&self.path)?)?);
+        self.header = header;
+        // The new header was written with the file.
+        self.header_dirty = false;
+        // The old sectors are gone, including any that were held back.
+        self.freed_since_flush.clear();
+        self.sector_manager = Some(allocator(&self.header.sectors));
+        self.touch_checksums()
+    }
+}
+
+/// The header tables, the IO buffers, and the free sector list.
+impl MemorySize for RegionFile {
+    fn heap_size(&self) -> usize {
+        let tables = 1024 * (std::mem::size_of::<RegionSector>() + std::mem::size_of::<Timestamp>());
+        let checksums = self.checksums.as_ref().map_or(0, |_| 1024 * std::mem::size_of::<u32>());
+        tables
+            + checksums
+            + self.read_buf.capacity()
+            + self.write_buf.get_ref().capacity()
+            + self.path.capacity()
+            + self.freed_since_flush.capacity() * std::mem::size_of::<RegionSector>()
+            + self.sector_manager().unused_sectors.capacity() * std::mem::size_of::<ManagedSector>()
+    }
+}
+
+/// Flushes a [deferred](RegionFile::deferred) header on a best-effort basis, without syncing
+/// (unless the durability is [Durability::FlushOnWrite]). Errors can't be returned from here,
+/// so a failed flush is a debug assertion (and is lost in release builds). Callers must call
+/// [RegionFile::flush] (or [RegionFile::close]) themselves to know that the header was written.
+impl<A: SectorAllocator> Drop for RegionFile<A> {
+    fn drop(&mut self) {
+        let result = self.flush();
+        if !std::thread::panicking() {
+            debug_assert!(result.is_ok(), "Failed to flush the header of {} when it was dropped: {:?}", self.path.display(), result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nbt::Map, world::io::region::RegionReader};
+
+    #[test]
+    fn debug_sector_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        region.write_data_timestamped((1, 0), &NamedTag::new(Tag::Compound(Map::new())), 1000u32)?;
+        let debug = region.debug_sector((1, 0))?;
+        assert_eq!((debug.scheme(), debug.detected_scheme), (Some(CompressionScheme::ZLib), Some(CompressionScheme::ZLib)));
+        assert_eq!(debug.bytes.len(), DEBUG_BYTES);
+        assert!(debug.problems().is_empty());
+        let text = debug.to_string();
+        assert!(text.starts_with("chunk (1, 0) (index 1)\nsector: offset 2 (1 sectors, bytes 8192..12288)"));
+        assert!(text.contains("timestamp: 1000\n"));
+        assert!(text.contains("\n00002000  00 00 00 "));
+        assert!(region.debug_sector((2, 0))?.to_string().ends_with("sector: empty\n"));
+
+        // Corrupt the scheme byte.
+        let sector = region.get_sector((1, 0));
+        region.get_ref().write_all_at(&[9], sector.offset() + 4)?;
+        let debug = region.debug_sector_bytes((1, 0), 16)?;
+        assert_eq!(debug.bytes.len(), 16);
+        assert_eq!(debug.problems(), ["the scheme byte (9) is not a known scheme", "the payload looks like ZLib"]);
+        Ok(())
+    }
+
+    #[test]
+    fn update_path_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        let chunk = |status: &str| NamedTag::new(Tag::Compound(Map::from([
+            ("Status".to_owned(), Tag::string(status)),
+            ("Level".to_owned(), Tag::Compound(Map::new())),
+        ])));
+        region.write_data((0, 0), &chunk("minecraft:features"))?;
+        region.write_data((1, 0), &chunk("minecraft:noise"))?;
+        let status = TagPath::parse("Status").unwrap();
+        region.update_path((0, 0), &TagPath::parse("Level.Flag").unwrap(), Tag::Byte(1))?;
+        let updates = [(status.clone(), Tag::string("minecraft:full"))];
+        assert_eq!(region.update_paths([(0, 0), (1, 0), (2, 0)], &updates)?, 2);
+        for x in 0..2 {
+            let root: NamedTag = region.read_data((x, 0))?;
+            assert_eq!(root.tag().find_child(status.path()).and_then(|status| status.as_str()), Some("minecraft:full"));
+        }
+        let root: NamedTag = region.read_data((0, 0))?;
+        assert!(matches!(root.tag().find_child(TagPath::parse("Level.Flag").unwrap().path()), Some(crate::nbt::tagref::ValueRef::Byte(1))));
+        assert!(matches!(
+            region.update_path((0, 0), &TagPath::parse("Missing.Flag").unwrap(), Tag::Byte(1)),
+            Err(McError::TagPathNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn open_read_only_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        RegionFile::create(&path)?.write_data_timestamped((3, 4), &7i64, 100)?;
+        let mut permissions = std::fs::metadata(&path)?.permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions)?;
+        let region = RegionFile::open_read_only(&path)?;
+        assert_eq!(region.read_data::<_, i64>((3, 4))?, 7);
+        assert_eq!(region.header().timestamps[RegionCoord::new(3, 4).index()], Timestamp::from(100));
+        Ok(())
+    }
+
+    #[test]
+    fn delete_many_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?;
+        for x in 0..4 {
+            region.write_data_timestamped((x, 0), &(x as i64), 100)?;
+        }
+        let coords = [(1, 0), (3, 0), (5, 0), (1, 0)];
+        let dry = region.delete_many(coords, true)?;
+        assert_eq!(dry.chunks, vec![RegionCoord::new(1, 0), RegionCoord::new(3, 0)]);
+        assert!(!region.get_sector((1, 0)).is_empty());
+        let end = *region.sector_manager().end_sector();
+
+        let report = region.delete_many(coords, false)?;
+        assert_eq!(report, dry);
+        assert_eq!(report.sector_count(), 2);
+        assert_eq!(region.get_timestamp((1, 0)), Timestamp::default());
+        // The freed sectors are reused instead of growing the file.
+        region.write_data((8, 0), &8i64)?;
+        assert!(region.sector_manager().end_sector().start <= end.start);
+        drop(region);
+        let reader = RegionReader::open(&path)?;
+        assert!(reader.get_sector((1, 0)).is_empty() && reader.get_sector((3, 0)).is_empty());
+        assert_eq!(reader.read_data::<_, i64>((2, 0))?, 2);
+        assert_eq!(reader.read_data::<_, i64>((8, 0))?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn deferred_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?.deferred();
+        region.write_data((0, 0), &1i64)?;
+        region.write_data((1, 0), &2i64)?;
+        assert!(region.is_dirty());
+        // The header on disk is still empty.
+        assert!(RegionReader::open(&path)?.get_sector((0, 0)).is_empty());
+        region.flush()?;
+        assert!(!region.is_dirty());
+        assert_eq!(RegionReader::open(&path)?.read_data::<_, i64>((1, 0))?, 2);
+        region.delete_data((0, 0))?;
+        drop(region);
+        assert!(RegionReader::open(&path)?.get_sector((0, 0)).is_empty());
+
+        let mut region = RegionFile::open(&path)?.deferred();
+        region.write_data((2, 0), &3i64)?;
+        region.close()?;
+        assert_eq!(RegionReader::open(&path)?.read_data::<_, i64>((2, 0))?, 3);
+
+        // Sectors that the header in the file still points to aren't reused until it is flushed.
+        let mut region = RegionFile::open(&path)?.deferred();
+        let held = [region.get_sector((1, 0)), region.get_sector((2, 0))];
+        region.write_data((1, 0), &4i64)?;
+        region.delete_data((2, 0))?;
+        for x in 3..6 {
+            assert!(!held.contains(&region.write_data((x, 0), &5i64)?));
+        }
+        let reader = RegionReader::open(&path)?;
+        assert_eq!((reader.read_data::<_, i64>((1, 0))?, reader.read_data::<_, i64>((2, 0))?), (2, 3));
+        region.flush()?;
+        assert!(held.contains(&region.write_data((6, 0), &6i64)?));
+        region.close()?;
+        let reader = RegionReader::open(&path)?;
+        assert_eq!((reader.read_data::<_, i64>((1, 0))?, reader.read_data::<_, i64>((6, 0))?), (4, 6));
+        Ok(())
+    }
+
+    #[test]
+    fn durability_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?.with_durability(Durability::FlushOnWrite);
+        assert_eq!(region.durability(), Durability::FlushOnWrite);
+        region.write_data((0, 0), &1i64)?;
+        region.write_timestamped((1, 0), 1000u32, |encoder| {
+            2i64.write_to(encoder)?;
+            Ok(())
+        })?;
+        region.delete_data((0, 0))?;
+        // Everything is on disk without closing the file.
+        let reader = RegionReader::open(&path)?;
+        assert!(reader.get_sector((0, 0)).is_empty());
+        assert_eq!(reader.read_data::<_, i64>((1, 0))?, 2);
+        region.set_durability(Durability::None);
+        region.write_data((2, 0), &3i64)?;
+        region.close()?;
+
+        let mut region = RegionFile::open(&path)?.deferred();
+        assert_eq!(region.durability(), Durability::FsyncOnCommit);
+        region.write_data((3, 0), &4i64)?;
+        region.commit()?;
+        assert!(!region.is_dirty());
+        let reader = RegionReader::open(&path)?;
+        assert_eq!(reader.read_data::<_, i64>((2, 0))?, 3);
+        assert_eq!(reader.read_data::<_, i64>((3, 0))?, 4);
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn read_many_parallel_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        for x in 0..16i64 {
+            region.write_data((x as u16, 0), &x)?;
+        }
+        // Rewrite a chunk so that the file order differs from the index order.
+        region.write_data((0, 0), &vec![7i64; 2048])?;
+        let coords = [(3, 0), (0, 1), (15, 0), (1, 0)];
+        let results = region.read_many_parallel::<_, _, i64>(coords)?;
+        assert!(matches!(results[0], Ok(3)));
+        assert!(matches!(results[1], Err(McError::RegionDataNotFound)));
+        assert!(matches!(results[2], Ok(15)));
+        assert!(matches!(results[3], Ok(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn move_chunk_test() -> McResult<()> {
+        use crate::{nbt::tag::ListTag, world::io::region::relocate::chunk_position};
+        let dir = tempfile::tempdir()?;
+        // Region (-1, 1), so chunk positions are offset by (-32, 32).
+        let mut region = RegionFile::create(dir.path().join("r.-1.1.mca"))?;
+        let chunk = |x: i32, z: i32| NamedTag::new(Tag::Compound(Map::from([
+            ("xPos".to_owned(), Tag::Int(x)),
+            ("zPos".to_owned(), Tag::Int(z)),
+            ("block_entities".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                ("x".to_owned(), Tag::Int(x * 16 + 3)),
+                ("z".to_owned(), Tag::Int(z * 16 + 4)),
+            ])]))),
+        ])));
+        region.write_data_timestamped((1, 2), &chunk(-31, 34), 1000)?;
+        region.write_data_timestamped((7, 7), &chunk(-25, 39), 2000)?;
+        assert!(matches!(region.move_chunk((0, 0), (3, 3)), Err(McError::RegionDataNotFound)));
+
+        region.move_chunk((1, 2), (30, 5))?;
+        assert!(region.get_sector((1, 2)).is_empty());
+        assert_eq!(region.get_timestamp((30, 5)), Timestamp::from(1000u32));
+        let root: NamedTag = region.read_data((30, 5))?;
+        assert_eq!(chunk_position(root.tag()), Some((-2, 37)));
+        let Tag::Compound(map) = root.tag() else { unreachable!() };
+        let Some(Tag::List(ListTag::Compound(block_entities))) = map.get("block_entities") else { unreachable!() };
+        assert!(matches!((block_entities[0].get("x"), block_entities[0].get("z")), (Some(Tag::Int(-29)), Some(Tag::Int(596)))));
+
+        region.swap_chunks((30, 5), (7, 7))?;
+        region.swap_chunks((0, 0), (7, 7))?;
+        assert!(region.get_sector((7, 7)).is_empty());
+        assert_eq!(chunk_position(region.read_data::<_, NamedTag>((0, 0))?.tag()), Some((-32, 32)));
+        assert_eq!(chunk_position(region.read_data::<_, NamedTag>((30, 5))?.tag()), Some((-2, 37)));
+        assert_eq!(region.get_timestamp((0, 0)), Timestamp::from(1000u32));
+        Ok(())
+    }
+
+    #[test]
+    fn optimize_test() -> McResult<()> {
+        use rand::RngCore;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create(&path)?;
+        region.enable_checksums()?;
+        // Random bytes don't compress, so this takes up several sectors.
+        let mut noise = vec![0u8; 20000];
+        rand::thread_rng().fill_bytes(&mut noise);
+        region.write((0, 0), |writer| Ok(writer.write_all(&noise)?))?;
+        region.write_data_timestamped((1, 0), &1i64, 1000)?;
+        region.write_data((2, 0), &2i64)?;
+        region.delete_data((0, 0))?;
+        let snapshot = dir.path().join("snapshot.mca");
+        region.set_snapshot_before_rewrite(Some(snapshot.clone()));
+
+        region.optimize()?;
+        assert!(snapshot.is_file());
+        assert_eq!(std::fs::metadata(&path)?.len(), 4096 * 4);
+        assert_eq!((region.get_sector((1, 0)).sector_offset(), region.get_sector((2, 0)).sector_offset()), (2, 3));
+        assert_eq!(region.get_timestamp((1, 0)), Timestamp::from(1000u32));
+        assert_eq!(region.read_data::<_, i64>((1, 0))?, 1);
+        assert!(region.verify_checksums()?.is_empty());
+        // New chunks are appended after the packed chunks.
+        assert_eq!(region.write_data((3, 0), &3i64)?.sector_offset(), 4);
+        region.close()?;
+
+        let mut region = RegionFile::open(&path)?;
+        assert!(region.checksums().is_some());
+        assert_eq!(region.read_data::<_, i64>((2, 0))?, 2);
+        assert_eq!(region.read_data::<_, i64>((3, 0))?, 3);
+        Ok(())
+    }
+}
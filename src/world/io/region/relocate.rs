@@ -0,0 +1,101 @@
+//! Rewriting the positions stored in a chunk when it is moved to another slot of its
+//! region file (see [RegionFile::move_chunk](super::RegionFile::move_chunk)).
+//!
+//! Chunks store their own position (`xPos`/`zPos`, or `Position` in entity chunks), and
+//! the block entities, scheduled ticks, and entities in them store absolute coordinates.
+//! All of these are shifted together so that the chunk agrees with its new slot. Both the
+//! 1.18+ layout and the older layout (with everything under `Level`) are handled.
+
+use crate::nbt::{
+    Map,
+    tag::{ListTag, Tag},
+};
+
+use super::coord::RegionCoord;
+
+/// The lists of compounds that have absolute `x` and `z` block coordinates.
+const BLOCK_LISTS: [&str; 6] = ["block_entities", "block_ticks", "fluid_ticks", "TileEntities", "TileTicks", "LiquidTicks"];
+
+/// Returns the chunk position (`xPos`/`zPos`, or `Position` for entity chunks) stored in `root`.
+pub fn chunk_position(root: &Tag) -> Option<(i32, i32)> {
+    let Tag::Compound(root) = root else {
+        return None;
+    };
+    let map = match root.get("Level") {
+        Some(Tag::Compound(level)) => level,
+        _ => root,
+    };
+    match (map.get("xPos"), map.get("zPos"), map.get("Position")) {
+        (Some(&Tag::Int(x)), Some(&Tag::Int(z)), _) => Some((x, z)),
+        (_, _, Some(Tag::IntArray(position))) if position.len() == 2 => Some((position[0], position[1])),
+        _ => None,
+    }
+}
+
+/// Shifts every position stored in the chunk `root` by `dx` and `dz` chunks.
+pub fn shift_chunk(root: &mut Tag, dx: i32, dz: i32) {
+    let Tag::Compound(root) = root else {
+        return;
+    };
+    if let Some(Tag::Compound(level)) = root.get_mut("Level") {
+        shift_map(level, dx, dz);
+    }
+    shift_map(root, dx, dz);
+}
+
+/// Shifts the positions in one of the compounds that hold the chunk's data
+/// (the root, and `Level` in older chunks).
+fn shift_map(map: &mut Map, dx: i32, dz: i32) {
+    let (block_x, block_z) = (dx * 16, dz * 16);
+    shift_ints(map, ["xPos", "zPos"], dx, dz);
+    if let Some(Tag::IntArray(position)) = map.get_mut("Position") {
+        if position.len() == 2 {
+            position[0] += dx;
+            position[1] += dz;
+        }
+    }
+    for key in BLOCK_LISTS {
+        if let Some(Tag::List(ListTag::Compound(list))) = map.get_mut(key) {
+            list.iter_mut().for_each(|item| shift_ints(item, ["x", "z"], block_x, block_z));
+        }
+    }
+    if let Some(Tag::List(ListTag::Compound(entities))) = map.get_mut("Entities") {
+        entities.iter_mut().for_each(|entity| shift_entity(entity, block_x, block_z));
+    }
+}
+
+/// Rewrites the positions in the chunk `root` so that it belongs at `to` in the same region
+/// file. Returns false (and changes nothing) if `root` has no chunk position.
+pub fn relocate_chunk(root: &mut Tag, to: RegionCoord) -> bool {
+    let Some((x, z)) = chunk_position(root) else {
+        return false;
+    };
+    let new_x = x.div_euclid(32) * 32 + to.x();
+    let new_z = z.div_euclid(32) * 32 + to.z();
+    shift_chunk(root, new_x - x, new_z - z);
+    true
+}
+
+fn shift_ints(map: &mut Map, [x_key, z_key]: [&str; 2], dx: i32, dz: i32) {
+    if let Some(Tag::Int(x)) = map.get_mut(x_key) {
+        *x += dx;
+    }
+    if let Some(Tag::Int(z)) = map.get_mut(z_key) {
+        *z += dz;
+    }
+}
+
+/// Shifts an entity (and its passengers) by `dx` and `dz` blocks.
+fn shift_entity(entity: &mut Map, dx: i32, dz: i32) {
+    if let Some(Tag::List(ListTag::Double(pos))) = entity.get_mut("Pos") {
+        if pos.len() == 3 {
+            pos[0] += dx as f64;
+            pos[2] += dz as f64;
+        }
+    }
+    // Paintings and item frames are placed by the block that they hang on.
+    shift_ints(entity, ["TileX", "TileZ"], dx, dz);
+    if let Some(Tag::List(ListTag::Compound(passengers))) = entity.get_mut("Passengers") {
+        passengers.iter_mut().for_each(|passenger| shift_entity(passenger, dx, dz));
+    }
+}
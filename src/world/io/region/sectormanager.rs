@@ -8,25 +8,45 @@ use crate::{
     ioext::*,
 };
 
-use super::prelude::*;
+use super::{
+    prelude::*,
+    layout::{SectorLayout, SectorRun, SectorUse},
+};
 
 pub trait SectorAllocator {
-    fn deallocate(&mut self, sector: RegionSector);
+    /// Frees a sector so that it can be reused. Freeing an empty sector does nothing.
+    /// Returns [McError::SectorOutOfBounds] if the sector overlaps the header or isn't
+    /// within the allocated part of the file, and [McError::SectorDoubleFree] if any of
+    /// it is already free. The allocator is unchanged when an error is returned.
+    fn deallocate(&mut self, sector: RegionSector) -> McResult<()>;
     #[must_use]
     fn allocate(&mut self, size: u8) -> Option<RegionSector>;
+    /// Frees `free` and allocates `new_size` sectors, possibly reusing `free`.
+    /// Returns [McError::RegionAllocationFailure] if the allocation fails, or the
+    /// error from freeing `free` (see [SectorAllocator::deallocate]).
     #[must_use]
-    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> Option<RegionSector>;
+    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> McResult<RegionSector>;
 
     #[must_use]
     #[inline(always)]
     fn allocate_err(&mut self, size: u8) -> McResult<RegionSector> {
         self.allocate(size).ok_or(McError::RegionAllocationFailure)
     }
+}
 
-    #[must_use]
-    #[inline(always)]
-    fn reallocate_err(&mut self, free: RegionSector, new_size: u8) -> McResult<RegionSector> {
-        self.reallocate(free, new_size).ok_or(McError::RegionAllocationFailure)
+/// Lets a boxed allocator (such as a `Box<dyn SectorAllocator + Send>`) be used
+/// wherever an allocator is expected, so that the allocator can be chosen at runtime.
+impl<A: SectorAllocator + ?Sized> SectorAllocator for Box<A> {
+    fn deallocate(&mut self, sector: RegionSector) -> McResult<()> {
+        (**self).deallocate(sector)
+    }
+
+    fn allocate(&mut self, size: u8) -> Option<RegionSector> {
+        (**self).allocate(size)
+    }
+
+    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> McResult<RegionSector> {
+        (**self).reallocate(free, new_size)
     }
 }
 
@@ -49,16 +69,14 @@ pub struct SectorManager {
 impl SectorAllocator for SectorManager {
     
 
-    // TODO: I'm pretty sure that this will cause problems
-    //       if the given sector intersects with an unused sector.
-    //       It's best if you only supply RegionSectors supplied by
-    //       the same instance of a sector manager.
     /// Frees a sector, allowing it to be reused.
-    fn deallocate(&mut self, sector: RegionSector) {
+    /// See [SectorAllocator::deallocate] for the errors.
+    fn deallocate(&mut self, sector: RegionSector) -> McResult<()> {
         // Early return if the sector is empty (nothing to free)
         if sector.size() == 0 {
-            return;
+            return Ok(());
         }
+        self.check_deallocate(sector)?;
         // This method should search through the unused_sectors
         // if there are any and expand the boundaries of any that
         // the given sector is adjacent to.
@@ -154,6 +172,7 @@ impl SectorAllocator for SectorManager {
         } else {
             self.unused_sectors.push(freed_sector);
         }
+        Ok(())
     }
 
     /// Allocate a sector of a specified size.
@@ -197,27 +216,28 @@ impl SectorAllocator for SectorManager {
     /// Most sectors will be 1 block in size, so this function will probably return the
     /// sector passed to it in most cases.
     #[must_use]
-    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> Option<RegionSector> {
+    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> McResult<RegionSector> {
         // There's no need to free the sector if there is no reallocation happening.
         if new_size == 0 {
-            return None;
+            return Err(McError::RegionAllocationFailure);
         }
         // We don't need to do an allocation if our freed sector is big enough to accomodate the new size.
         if free.sector_count() > (new_size as u64) {
             // Use split_left so that when the right side is freed, it can be absorbed
             // into the end_sector if they are adjacent.
             let (new, old) = free.split_left(new_size).unwrap();
-            self.deallocate(old);
-            Some(new)
+            self.deallocate(old)?;
+            Ok(new)
         // No need to reallocate.
         } else if free.sector_count() == (new_size as u64) {
-            Some(free)
+            Ok(free)
         // No need to deallocate.
         } else if free.is_empty() {
             // The sector is empty, so there's nothing to free.
-            self.allocate(new_size)
+            self.allocate_err(new_size)
         } else {
-            self.reallocate_unchecked(free, new_size)
+            self.check_deallocate(free)?;
+            self.reallocate_unchecked(free, new_size).ok_or(McError::RegionAllocationFailure)
         }
     }
 }
@@ -246,7 +266,7 @@ impl SectorManager {
     pub fn from_file(region_file: impl AsRef<Path>) -> McResult<Self> {
         // Read the sector table from the file.
         let sectors = {
-            let mut reader = BufReader::new(File::open(region_file.as_ref())?);
+            let mut reader = BufReader::with_capacity(IoConfig::default().read_buf, File::open(region_file.as_ref())?);
             SectorTable::read_from(&mut reader)?
         };
         Ok(SectorManager::from(sectors))
@@ -277,6 +297,42 @@ impl SectorManager {
             .sum()
     }
 
+    /// Describes which sectors are used and which are free, from the start of the file
+    /// to the end of the last used sector.
+    pub fn layout_map(&self) -> SectorLayout {
+        let mut free = self.unused_sectors.iter()
+            .filter(|sector| sector.start < self.end_sector.start)
+            .copied()
+            .collect::<Vec<_>>();
+        free.sort();
+        let header = ManagedSector::header();
+        let mut runs = vec![SectorRun { usage: SectorUse::Header, start: header.start, len: header.size() }];
+        let mut position = header.end;
+        for sector in free {
+            if sector.start > position {
+                runs.push(SectorRun { usage: SectorUse::Used, start: position, len: sector.start - position });
+            }
+            runs.push(SectorRun { usage: SectorUse::Free, start: sector.start, len: sector.size() });
+            position = sector.end;
+        }
+        if self.end_sector.start > position {
+            runs.push(SectorRun { usage: SectorUse::Used, start: position, len: self.end_sector.start - position });
+        }
+        SectorLayout { runs }
+    }
+
+    /// Checks that a non-empty sector can be freed (see [SectorAllocator::deallocate]).
+    fn check_deallocate(&self, sector: RegionSector) -> McResult<()> {
+        let managed = ManagedSector::from(sector);
+        if managed.start < ManagedSector::header().end || managed.end > self.end_sector.start {
+            return Err(McError::SectorOutOfBounds(sector));
+        }
+        if self.unused_sectors.iter().any(|unused| unused.intersects(&managed)) {
+            return Err(McError::SectorDoubleFree(sector));
+        }
+        Ok(())
+    }
+
     /// This function will only cause the [SectorManager] to change its state if it succeeds in allocating a sector.
     /// Failure is unlikely because you would need a ridiculously large file (which is possible, but unlikely).
     /// This function does not check if the sector being freed is big enough to hold the requested size (hence the `unchecked`).
@@ -391,6 +447,93 @@ impl SectorManager {
     }
 }
 
+/// An allocator that never reuses sectors: every allocation is appended to the end of
+/// the file, and freed sectors are only counted. There is no free list to search or
+/// merge, which makes it cheaper than [SectorManager] for write-heavy workloads, at the
+/// cost of a file that grows with every write. Pair it with [RegionFile::optimize_with_allocator](super::RegionFile::optimize_with_allocator)
+/// (using [AppendOnlyAllocator::freed_count] to decide when) to reclaim the space.
+///
+/// Since freed sectors aren't tracked, [SectorAllocator::deallocate] can only check the
+/// bounds of a sector, not whether it was already freed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppendOnlyAllocator {
+    /// The space beyond all used sectors, where every sector is allocated.
+    end_sector: ManagedSector,
+    /// The number of 4KiB blocks that have been freed.
+    freed: u32,
+}
+
+impl AppendOnlyAllocator {
+    pub fn new() -> Self {
+        Self {
+            end_sector: ManagedSector::end_sector(ManagedSector::header().end),
+            freed: 0,
+        }
+    }
+
+    /// Creates an [AppendOnlyAllocator] that appends after the last used sector in `table`.
+    /// Gaps between the used sectors are never reused.
+    pub fn from_table(table: &SectorTable) -> Self {
+        let end = table.iter()
+            .filter(|sector| !sector.is_empty())
+            .map(|sector| sector.sector_end_offset() as u32)
+            .fold(ManagedSector::header().end, u32::max);
+        Self {
+            end_sector: ManagedSector::end_sector(end),
+            freed: 0,
+        }
+    }
+
+    pub fn end_sector(&self) -> &ManagedSector {
+        &self.end_sector
+    }
+
+    /// The number of 4KiB blocks that have been freed since this allocator was created.
+    /// This is helpful for determining if the region file needs to be optimized.
+    pub fn freed_count(&self) -> u32 {
+        self.freed
+    }
+}
+
+impl Default for AppendOnlyAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SectorAllocator for AppendOnlyAllocator {
+    /// Counts the sector as freed. The space isn't reused.
+    fn deallocate(&mut self, sector: RegionSector) -> McResult<()> {
+        if sector.size() == 0 {
+            return Ok(());
+        }
+        let managed = ManagedSector::from(sector);
+        if managed.start < ManagedSector::header().end || managed.end > self.end_sector.start {
+            return Err(McError::SectorOutOfBounds(sector));
+        }
+        self.freed += managed.size();
+        Ok(())
+    }
+
+    fn allocate(&mut self, size: u8) -> Option<RegionSector> {
+        if size == 0 {
+            return None;
+        }
+        self.end_sector.allocate(size)
+    }
+
+    /// Always allocates a new sector at the end (even if `free` is large enough), then frees `free`.
+    fn reallocate(&mut self, free: RegionSector, new_size: u8) -> McResult<RegionSector> {
+        let before = *self;
+        let sector = self.allocate_err(new_size)?;
+        if let Err(err) = self.deallocate(free) {
+            *self = before;
+            return Err(err);
+        }
+        Ok(sector)
+    }
+}
+
 impl<'a> IntoIterator for &'a SectorManager {
 
     type Item = &'a ManagedSector;
@@ -478,4 +621,80 @@ impl<'a,T: ManagedSectorIteratorItem, It: IntoIterator<Item = T>> From<It> for S
             end_sector: ManagedSector::end_sector(end_sector.end)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn deallocate_test() -> McResult<()> {
+        let mut manager = SectorManager::new();
+        let a = manager.allocate_err(2)?;
+        let b = manager.allocate_err(1)?;
+        let c = manager.allocate_err(3)?;
+        manager.deallocate(b)?;
+        assert!(matches!(manager.deallocate(b), Err(McError::SectorDoubleFree(sector)) if sector == b));
+        // Overlapping a free sector is a double free too.
+        assert!(matches!(manager.deallocate(RegionSector::new(a.sector_offset() as u32 + 1, 2)), Err(McError::SectorDoubleFree(_))));
+        assert!(matches!(manager.deallocate(RegionSector::new(1, 1)), Err(McError::SectorOutOfBounds(_))));
+        assert!(matches!(manager.deallocate(RegionSector::new(c.sector_end_offset() as u32, 1)), Err(McError::SectorOutOfBounds(_))));
+        assert_eq!(manager.unused_sectors(), &vec![ManagedSector::from(b)]);
+        manager.deallocate(a)?;
+        manager.deallocate(c)?;
+        assert!(manager.unused_sectors().is_empty());
+        assert_eq!(manager.end_sector().start, 2);
+        manager.deallocate(RegionSector::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn reallocate_test() -> McResult<()> {
+        let mut manager = SectorManager::new();
+        let a = manager.allocate_err(3)?;
+        let _b = manager.allocate_err(1)?;
+        manager.deallocate(a)?;
+        // Shrinking a sector that was already freed must not lose track of it.
+        assert!(matches!(manager.reallocate(a, 1), Err(McError::SectorDoubleFree(_))));
+        assert!(matches!(manager.reallocate(a, 5), Err(McError::SectorDoubleFree(_))));
+        assert_eq!(manager.unused_sectors(), &vec![ManagedSector::from(a)]);
+        let a = manager.allocate_err(3)?;
+        let shrunk = manager.reallocate(a, 1)?;
+        assert_eq!(shrunk.sector_offset(), a.sector_offset());
+        assert_eq!(manager.unused_sectors().len(), 1);
+        assert!(matches!(manager.reallocate(shrunk, 0), Err(McError::RegionAllocationFailure)));
+
+        let mut append = AppendOnlyAllocator::new();
+        let c = append.allocate_err(1)?;
+        let before = append;
+        assert!(matches!(append.reallocate(RegionSector::new(c.sector_end_offset() as u32 + 10, 1), 2), Err(McError::SectorOutOfBounds(_))));
+        assert_eq!(append, before);
+        Ok(())
+    }
+
+    #[test]
+    fn append_only_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.0.mca");
+        let mut region = RegionFile::create_with_allocator(&path, IoConfig::default(), AppendOnlyAllocator::from_table)?;
+        let first = region.write_data((0, 0), &1i64)?;
+        let second = region.write_data((0, 0), &2i64)?;
+        // The sector isn't reused, even though it's the same size.
+        assert_eq!(second.sector_offset(), first.sector_offset() + 1);
+        region.delete_data((0, 0))?;
+        assert_eq!(region.sector_manager().freed_count(), 2);
+        let third = region.write_data((1, 0), &3i64)?;
+        assert_eq!(third.sector_offset(), second.sector_offset() + 1);
+        assert!(matches!(region.sector_manager().clone().deallocate(RegionSector::new(third.sector_end_offset() as u32, 1)), Err(McError::SectorOutOfBounds(_))));
+        region.close()?;
+
+        // Reopening appends after the last used sector.
+        let allocator = |table: &SectorTable| Box::new(AppendOnlyAllocator::from_table(table)) as Box<dyn SectorAllocator + Send>;
+        let mut region = RegionFile::open_with_allocator(&path, IoConfig::default(), allocator)?;
+        assert_eq!(region.read_data::<_, i64>((1, 0))?, 3);
+        let fourth = region.write_data((2, 0), &4i64)?;
+        assert_eq!(fourth.sector_offset(), third.sector_offset() + 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+//! Cheap copies of region files, taken before operations that rewrite them.
+//!
+//! On filesystems that support it (Btrfs, XFS, and others on Linux), a snapshot is
+//! a reflink: the copy shares the original's data until either of them is written to,
+//! so taking one costs almost nothing. Everywhere else the file is copied.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+use crate::McResult;
+
+use super::checksum::sidecar_path;
+
+/// How a snapshot was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnapshotMethod {
+    /// The copy shares its data with the original (copy-on-write).
+    Reflink,
+    /// The file was copied.
+    Copy,
+}
+
+/// Tries to clone `source` into `destination`. Returns `false` if the filesystem can't.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn reflink(source: &File, destination: &File) -> bool {
+    use std::os::fd::AsRawFd;
+    // Safety: both descriptors are open for the duration of the call.
+    unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) == 0 }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn reflink(_source: &File, _destination: &File) -> bool {
+    false
+}
+
+/// Copies the file at `source` to `destination` (replacing it), as a reflink if the
+/// filesystem supports it. The copy is synced to disk before this returns.
+pub fn snapshot_file<P1: AsRef<Path>, P2: AsRef<Path>>(source: P1, destination: P2) -> McResult<SnapshotMethod> {
+    let mut source = File::open(source)?;
+    let destination = File::create(destination)?;
+    let method = if reflink(&source, &destination) {
+        SnapshotMethod::Reflink
+    } else {
+        // A failed clone leaves the destination empty.
+        let mut writer = BufWriter::new(&destination);
+        std::io::copy(&mut source, &mut writer)?;
+        writer.into_inner().map_err(|err| err.into_error())?;
+        SnapshotMethod::Copy
+    };
+    destination.sync_all()?;
+    Ok(method)
+}
+
+/// Snapshots the region file at `region_path` to `destination`, along with its
+/// checksum sidecar if it has one.
+pub(crate) fn snapshot_region(region_path: &Path, destination: &Path) -> McResult<SnapshotMethod> {
+    let method = snapshot_file(region_path, destination)?;
+    let sidecar = sidecar_path(region_path);
+    if sidecar.is_file() {
+        snapshot_file(sidecar, sidecar_path(destination))?;
+    }
+    Ok(method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn snapshot_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut region = RegionFile::create(dir.path().join("r.0.0.mca"))?;
+        region.write_data((1, 1), &7i64)?;
+        let snapshot_path = dir.path().join("r.0.0.mca.bak");
+        region.snapshot_to(&snapshot_path)?;
+        region.write_data((1, 1), &8i64)?;
+        assert_eq!(RegionFile::open(&snapshot_path)?.read_data::<_, i64>((1, 1))?, 7);
+        assert_eq!(region.read_data::<_, i64>((1, 1))?, 8);
+        Ok(())
+    }
+}
@@ -1,40 +1,92 @@
-
-use std::io::{Read, Write};
-use chrono::{NaiveDateTime, DateTime, Utc, TimeZone};
+use std::{
+    io::{Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc, TimeZone};
 use crate::{
     McResult,
     for_each_int_type,
     ioext::*,
 };
 
-/// A 32-bit Unix timestamp.
+/// A 32-bit Unix timestamp (in seconds).
+///
+/// Times before 1970 and after 2106 (when the seconds no longer fit in 32 bits)
+/// saturate to [Timestamp::MIN] and [Timestamp::MAX] rather than wrapping.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
 pub struct Timestamp(u32);
 
 impl Timestamp {
-    pub fn to_datetime(&self) -> Option<DateTime<Utc>> {
-        DateTime::<Utc>::try_from(*self).ok()
-    }
+    /// The Unix epoch (1970-01-01 00:00:00 UTC).
+    pub const MIN: Timestamp = Timestamp(0);
+    /// The last second that fits in a timestamp (2106-02-07 06:28:15 UTC).
+    pub const MAX: Timestamp = Timestamp(u32::MAX);
 
     /// Get a [Timestamp] for the current time (in Utc).
     pub fn utc_now() -> Timestamp {
-        Timestamp(
-            Utc::now().timestamp() as u32
-        )
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// Creates a [Timestamp] from seconds since the Unix epoch, saturating to the
+    /// range of the timestamp.
+    pub fn from_unix_secs(secs: i64) -> Timestamp {
+        Timestamp(secs.clamp(0, u32::MAX as i64) as u32)
+    }
+
+    /// Creates a [Timestamp] from a [SystemTime], saturating to the range of the timestamp.
+    /// Fractions of a second are dropped.
+    pub fn from_system_time(time: SystemTime) -> Timestamp {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => Timestamp(since.as_secs().min(u32::MAX as u64) as u32),
+            Err(_) => Self::MIN,
+        }
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix_secs(self) -> u32 {
+        self.0
+    }
+
+    pub fn to_system_time(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.0 as u64)
+    }
+
+    /// The time elapsed since this timestamp, or zero if it is in the future.
+    pub fn age(self) -> Duration {
+        self.age_at(SystemTime::now())
+    }
+
+    /// The time elapsed between this timestamp and `now`, or zero if `now` is earlier.
+    pub fn age_at(self, now: SystemTime) -> Duration {
+        now.duration_since(self.to_system_time()).unwrap_or_default()
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        DateTime::<Utc>::try_from(*self).ok()
     }
 }
 
+/// Milliseconds since the Unix epoch, as stored in `session.lock` and `LastPlayed`.
+#[cfg(feature = "world")]
+pub(crate) fn unix_millis_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as i64)
+}
+
+// Integers outside of the range of a u32 saturate (as do timestamps outside the range of
+// a smaller integer).
 macro_rules! __timestamp_impls {
     ($type:ty) => {
         impl From<$type> for Timestamp {
             fn from(value: $type) -> Self {
-                Self(value as u32)
+                Self(u32::try_from(value).unwrap_or(if value <= 0 { 0 } else { u32::MAX }))
             }
         }
 
         impl From<Timestamp> for $type {
             fn from(value: Timestamp) -> Self {
-                value.0 as $type
+                <$type>::try_from(value.0).unwrap_or(<$type>::MAX)
             }
         }
     };
@@ -48,6 +100,18 @@ impl<T: Into<Timestamp> + Copy> From<&T> for Timestamp {
     }
 }
 
+impl From<SystemTime> for Timestamp {
+    fn from(value: SystemTime) -> Self {
+        Self::from_system_time(value)
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(value: Timestamp) -> Self {
+        value.to_system_time()
+    }
+}
+
 impl Readable for Timestamp {
     fn read_from<R: Read>(reader: &mut R) -> McResult<Self> {
         Ok(Self(reader.read_value()?))
@@ -60,21 +124,47 @@ impl Writable for Timestamp {
     }
 }
 
+#[cfg(feature = "chrono")]
 impl From<DateTime<Utc>> for Timestamp {
     fn from(value: DateTime<Utc>) -> Self {
-        Timestamp(value.timestamp() as u32)
+        Self::from_unix_secs(value.timestamp())
     }
 }
 
+#[cfg(feature = "chrono")]
 impl TryFrom<Timestamp> for DateTime<Utc> {
     type Error = ();
 
     fn try_from(value: Timestamp) -> Result<Self, Self::Error> {
-        let naive = NaiveDateTime::from_timestamp_opt(value.0 as i64, 0);
-        if let Some(naive) = naive {
-            Ok(Utc.from_utc_datetime(&naive))
-        } else {
-            Err(())
+        Utc.timestamp_opt(value.0 as i64, 0).single().ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_test() {
+        assert_eq!(Timestamp::from(-5i64), Timestamp::MIN);
+        assert_eq!(Timestamp::from(u32::MAX as u64 + 10), Timestamp::MAX);
+        assert_eq!(Timestamp::from_unix_secs(1 << 40), Timestamp::MAX);
+        assert_eq!(u8::from(Timestamp::from(300u32)), u8::MAX);
+        assert_eq!(i64::from(Timestamp::MAX), u32::MAX as i64);
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let timestamp = Timestamp::from_system_time(time);
+        assert_eq!(timestamp.unix_secs(), 1_700_000_000);
+        assert_eq!(timestamp.age_at(time), Duration::from_millis(500));
+        assert_eq!(timestamp.age_at(UNIX_EPOCH), Duration::ZERO);
+        assert_eq!(Timestamp::from_system_time(UNIX_EPOCH - Duration::from_secs(1)), Timestamp::MIN);
+        assert_eq!(Timestamp::from_system_time(UNIX_EPOCH + Duration::from_secs(1 << 40)), Timestamp::MAX);
+        assert!(Timestamp::utc_now().age() < Duration::from_secs(60));
+        #[cfg(feature = "chrono")]
+        {
+            let datetime = Timestamp::MAX.to_datetime().unwrap();
+            assert_eq!(Timestamp::from(datetime), Timestamp::MAX);
+            assert_eq!(Timestamp::from(datetime + chrono::Duration::days(1)), Timestamp::MAX);
         }
     }
-}
\ No newline at end of file
+}
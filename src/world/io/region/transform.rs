@@ -0,0 +1,498 @@
+//! Rotating and mirroring whole region files, for map-making workflows that need symmetry.
+//!
+//! [transform] moves every chunk of a region file to its transformed slot and transforms
+//! the chunk to match: its position, the blocks, biomes, light, and heightmaps, the block
+//! entities and scheduled ticks, the entities (position, motion, and yaw), and the POI
+//! records. Block states with directional properties (`facing`, `axis`, `rotation`,
+//! `orientation`, rail `shape`, and the `north`/`east`/`south`/`west` connections) are
+//! turned to match, and mirroring swaps stair `shape`s and door `hinge`s.
+//!
+//! Everything is transformed within the region, so the region stays where it is. Data that
+//! isn't listed above (such as structure references) is copied unchanged. Only chunks in the
+//! 1.18+ format can be transformed.
+
+use std::path::Path;
+
+use crate::{
+    McError, McResult,
+    math::packed::{PackedArray, long_count, palette_bits},
+    nbt::{
+        Map,
+        tag::{ListTag, NamedTag, Tag},
+    },
+};
+
+use super::{
+    checksum::sidecar_path,
+    coord::RegionCoord,
+    reader::RegionReader,
+    regionfile::RegionFile,
+};
+
+/// A rotation or mirroring of a region, seen from above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transform {
+    /// Rotates by 90 degrees clockwise (north becomes east).
+    Rotate90,
+    Rotate180,
+    /// Rotates by 270 degrees clockwise (north becomes west).
+    Rotate270,
+    /// Mirrors east and west.
+    Mirror,
+}
+
+/// The transforms that [Transform] is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Rotate,
+    Mirror,
+}
+
+impl Transform {
+    fn steps(self) -> &'static [Step] {
+        match self {
+            Transform::Rotate90 => &[Step::Rotate],
+            Transform::Rotate180 => &[Step::Rotate, Step::Rotate],
+            Transform::Rotate270 => &[Step::Rotate, Step::Rotate, Step::Rotate],
+            Transform::Mirror => &[Step::Mirror],
+        }
+    }
+
+    /// The slot that the chunk at `coord` is moved to.
+    pub fn apply_to_coord(self, coord: RegionCoord) -> RegionCoord {
+        let (x, z) = self.steps().iter().fold((coord.x(), coord.z()), |(x, z), step| step.cell(x, z, 32));
+        RegionCoord::new(x as u16, z as u16)
+    }
+
+    /// Transforms a chunk (the root tag of a terrain, entities, or POI chunk) in place.
+    /// Returns an error for chunks that are older than 1.18.
+    pub fn apply_to_chunk(self, root: &mut Tag) -> McResult<()> {
+        let Tag::Compound(root) = root else {
+            return Err(McError::NbtDecodeError);
+        };
+        if root.contains_key("Level") {
+            return McError::custom("Chunks older than 1.18 can't be transformed.");
+        }
+        self.steps().iter().try_for_each(|&step| step.chunk(root))
+    }
+}
+
+/// Maps a horizontal direction (or `up`/`down`, which are unchanged).
+fn direction(step: Step, name: &str) -> Option<&'static str> {
+    Some(match (step, name) {
+        (Step::Rotate, "north") => "east",
+        (Step::Rotate, "east") => "south",
+        (Step::Rotate, "south") => "west",
+        (Step::Rotate, "west") => "north",
+        (Step::Mirror, "east") => "west",
+        (Step::Mirror, "west") => "east",
+        (_, "north") => "north",
+        (_, "south") => "south",
+        (_, "up") => "up",
+        (_, "down") => "down",
+        _ => return None,
+    })
+}
+
+/// Maps the directions in a name made of directions joined by `_`, such as the
+/// `orientation` `north_up`, or the rail shapes `ascending_east` and `north_west`.
+fn direction_parts(step: Step, name: &str, rail: bool) -> Option<String> {
+    let mut parts = name.split('_')
+        .map(|part| if part == "ascending" { Some(part) } else { direction(step, part) })
+        .collect::<Option<Vec<_>>>()?;
+    // Rail shapes name north or south first.
+    if rail && parts.len() == 2 && parts[0] != "ascending" && matches!(parts[1], "north" | "south") {
+        parts.swap(0, 1);
+    }
+    let joined = parts.join("_");
+    Some(match joined.as_str() {
+        "west_east" => "east_west".to_owned(),
+        _ => joined,
+    })
+}
+
+impl Step {
+    /// Transforms cell (`x`, `z`) of a `size` x `size` grid.
+    fn cell(self, x: i32, z: i32, size: i32) -> (i32, i32) {
+        match self {
+            Step::Rotate => (size - 1 - z, x),
+            Step::Mirror => (size - 1 - x, z),
+        }
+    }
+
+    /// Transforms the index of a value in stacked `side` x `side` layers (indexed by `y`, `z`, then `x`).
+    fn index(self, index: usize, side: usize) -> usize {
+        let layer = side * side;
+        let (y, z, x) = (index / layer, index % layer / side, index % side);
+        let (x, z) = self.cell(x as i32, z as i32, side as i32);
+        y * layer + z as usize * side + x as usize
+    }
+
+    /// Transforms a block or chunk coordinate within its region, which is `size` cells wide.
+    fn absolute(self, x: i32, z: i32, size: i32) -> (i32, i32) {
+        let (local_x, local_z) = (x.rem_euclid(size), z.rem_euclid(size));
+        let (new_x, new_z) = self.cell(local_x, local_z, size);
+        (x - local_x + new_x, z - local_z + new_z)
+    }
+
+    /// Transforms an entity position (in blocks) within its region.
+    fn point(self, x: f64, z: f64) -> (f64, f64) {
+        const SIZE: f64 = 512.0;
+        let (base_x, base_z) = ((x / SIZE).floor() * SIZE, (z / SIZE).floor() * SIZE);
+        let (local_x, local_z) = (x - base_x, z - base_z);
+        let (new_x, new_z) = match self {
+            Step::Rotate => (SIZE - local_z, local_x),
+            Step::Mirror => (SIZE - local_x, local_z),
+        };
+        (base_x + new_x, base_z + new_z)
+    }
+
+    /// Transforms a horizontal vector.
+    fn vector(self, x: f64, z: f64) -> (f64, f64) {
+        match self {
+            Step::Rotate => (-z, x),
+            Step::Mirror => (-x, z),
+        }
+    }
+
+    /// Transforms a yaw in degrees (0 is south, 90 is west).
+    fn yaw(self, yaw: f32) -> f32 {
+        match self {
+            Step::Rotate => (yaw + 90.0 + 180.0).rem_euclid(360.0) - 180.0,
+            Step::Mirror => -yaw,
+        }
+    }
+
+    fn chunk(self, root: &mut Map) -> McResult<()> {
+        self.absolute_ints(root, "xPos", "zPos", 32);
+        if let Some(Tag::IntArray(position)) = root.get_mut("Position") {
+            if let [x, z] = position.as_mut_slice() {
+                (*x, *z) = self.absolute(*x, *z, 32);
+            }
+        }
+        if let Some(Tag::List(ListTag::Compound(sections))) = root.get_mut("sections") {
+            sections.iter_mut().try_for_each(|section| self.section(section))?;
+        }
+        if let Some(Tag::Compound(heightmaps)) = root.get_mut("Heightmaps") {
+            for heightmap in heightmaps.values_mut() {
+                if let Tag::LongArray(data) = heightmap {
+                    self.permute_packed(data, 256, 1, 16)?;
+                }
+            }
+        }
+        for key in ["block_entities", "block_ticks", "fluid_ticks"] {
+            if let Some(Tag::List(ListTag::Compound(list))) = root.get_mut(key) {
+                list.iter_mut().for_each(|item| self.absolute_ints(item, "x", "z", 512));
+            }
+        }
+        if let Some(Tag::List(ListTag::Compound(entities))) = root.get_mut("Entities") {
+            entities.iter_mut().for_each(|entity| self.entity(entity));
+        }
+        // POI chunks.
+        if let Some(Tag::Compound(sections)) = root.get_mut("Sections") {
+            for section in sections.values_mut() {
+                let Tag::Compound(section) = section else { continue };
+                let Some(Tag::List(ListTag::Compound(records))) = section.get_mut("Records") else { continue };
+                for record in records {
+                    if let Some(Tag::IntArray(pos)) = record.get_mut("pos") {
+                        if let [x, _, z] = pos.as_mut_slice() {
+                            (*x, *z) = self.absolute(*x, *z, 512);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn section(self, section: &mut Map) -> McResult<()> {
+        if let Some(Tag::Compound(block_states)) = section.get_mut("block_states") {
+            let palette_len = match block_states.get_mut("palette") {
+                Some(Tag::List(ListTag::Compound(palette))) => {
+                    palette.iter_mut().for_each(|state| self.block_state(state));
+                    palette.len()
+                }
+                _ => 0,
+            };
+            if let Some(Tag::LongArray(data)) = block_states.get_mut("data") {
+                self.permute_packed(data, 4096, palette_bits(palette_len, 4), 16)?;
+            }
+        }
+        if let Some(Tag::Compound(biomes)) = section.get_mut("biomes") {
+            let palette_len = match biomes.get("palette") {
+                Some(Tag::List(ListTag::String(palette))) => palette.len(),
+                _ => 0,
+            };
+            if let Some(Tag::LongArray(data)) = biomes.get_mut("data") {
+                self.permute_packed(data, 64, palette_bits(palette_len, 1), 4)?;
+            }
+        }
+        for key in ["BlockLight", "SkyLight"] {
+            if let Some(Tag::ByteArray(light)) = section.get_mut(key) {
+                if light.len() == 2048 {
+                    let old = light.clone();
+                    light.fill(0);
+                    for index in 0..4096 {
+                        let value = (old[index / 2] as u8 >> (index % 2 * 4)) & 0xF;
+                        let new = self.index(index, 16);
+                        light[new / 2] |= (value << (new % 2 * 4)) as i8;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the values of packed `data` (`len` values with at least `min_bits` bits each,
+    /// in `side` x `side` layers) to their transformed cells.
+    fn permute_packed(self, data: &mut Vec<i64>, len: usize, min_bits: u32, side: usize) -> McResult<()> {
+        let bits = (min_bits.max(1)..=32)
+            .find(|&bits| long_count(bits, len) == data.len())
+            .ok_or(McError::OutOfRange)?;
+        let old = PackedArray::from_longs(bits, len, std::mem::take(data))?;
+        let mut new = PackedArray::new(bits, len);
+        old.iter().enumerate().for_each(|(index, value)| {
+            new.set(self.index(index, side), value);
+        });
+        *data = new.into_longs();
+        Ok(())
+    }
+
+    fn absolute_ints(self, map: &mut Map, x_key: &str, z_key: &str, size: i32) {
+        if let (Some(&Tag::Int(x)), Some(&Tag::Int(z))) = (map.get(x_key), map.get(z_key)) {
+            let (x, z) = self.absolute(x, z, size);
+            map.insert(x_key.to_owned(), Tag::Int(x));
+            map.insert(z_key.to_owned(), Tag::Int(z));
+        }
+    }
+
+    fn entity(self, entity: &mut Map) {
+        if let Some(Tag::List(ListTag::Double(pos))) = entity.get_mut("Pos") {
+            if let [x, _, z] = pos.as_mut_slice() {
+                (*x, *z) = self.point(*x, *z);
+            }
+        }
+        if let Some(Tag::List(ListTag::Double(motion))) = entity.get_mut("Motion") {
+            if let [x, _, z] = motion.as_mut_slice() {
+                (*x, *z) = self.vector(*x, *z);
+            }
+        }
+        if let Some(Tag::List(ListTag::Float(rotation))) = entity.get_mut("Rotation") {
+            if let Some(yaw) = rotation.first_mut() {
+                *yaw = self.yaw(*yaw);
+            }
+        }
+        self.absolute_ints(entity, "TileX", "TileZ", 512);
+        if let Some(Tag::List(ListTag::Compound(passengers))) = entity.get_mut("Passengers") {
+            passengers.iter_mut().for_each(|passenger| self.entity(passenger));
+        }
+    }
+
+    /// Transforms the properties of a block state in a palette.
+    fn block_state(self, state: &mut Map) {
+        let Some(Tag::Compound(properties)) = state.get_mut("Properties") else {
+            return;
+        };
+        let string = |properties: &Map, key: &str| match properties.get(key) {
+            Some(Tag::String(value)) => Some(value.clone()),
+            _ => None,
+        };
+        let mut changes: Vec<(String, String)> = Vec::new();
+        if let Some(facing) = string(properties, "facing").and_then(|facing| direction(self, &facing)) {
+            changes.push(("facing".to_owned(), facing.to_owned()));
+        }
+        if let (Step::Rotate, Some(axis)) = (self, string(properties, "axis")) {
+            match axis.as_str() {
+                "x" => changes.push(("axis".to_owned(), "z".to_owned())),
+                "z" => changes.push(("axis".to_owned(), "x".to_owned())),
+                _ => (),
+            }
+        }
+        if let Some(rotation) = string(properties, "rotation").and_then(|rotation| rotation.parse::<u8>().ok()) {
+            let rotation = match self {
+                Step::Rotate => (rotation + 4) % 16,
+                Step::Mirror => (16 - rotation % 16) % 16,
+            };
+            changes.push(("rotation".to_owned(), rotation.to_string()));
+        }
+        if let Some(orientation) = string(properties, "orientation").and_then(|orientation| direction_parts(self, &orientation, false)) {
+            changes.push(("orientation".to_owned(), orientation));
+        }
+        if let Some(shape) = string(properties, "shape") {
+            let shape = match (self, shape.as_str()) {
+                (Step::Mirror, "inner_left") => Some("inner_right".to_owned()),
+                (Step::Mirror, "inner_right") => Some("inner_left".to_owned()),
+                (Step::Mirror, "outer_left") => Some("outer_right".to_owned()),
+                (Step::Mirror, "outer_right") => Some("outer_left".to_owned()),
+                _ => direction_parts(self, &shape, true),
+            };
+            if let Some(shape) = shape {
+                changes.push(("shape".to_owned(), shape));
+            }
+        }
+        if let (Step::Mirror, Some(hinge)) = (self, string(properties, "hinge")) {
+            let hinge = if hinge == "left" { "right" } else { "left" };
+            changes.push(("hinge".to_owned(), hinge.to_owned()));
+        }
+        // Connections on each side (fences, walls, redstone, vines, and so on).
+        let sides = ["north", "east", "south", "west"].map(|side| (side, properties.remove(side)));
+        for (side, value) in sides {
+            if let (Some(value), Some(new_side)) = (value, direction(self, side)) {
+                properties.insert(new_side.to_owned(), value);
+            }
+        }
+        for (key, value) in changes {
+            properties.insert(key, Tag::String(value));
+        }
+    }
+}
+
+/// Rotates or mirrors the region file at `path` (see the [module documentation](self)).
+/// The transformed region is written to a temporary file next to it, which then replaces
+/// it, so the file is left unchanged if any chunk fails to transform. A checksum sidecar
+/// is recomputed. Returns the number of chunks that were transformed.
+pub fn transform<P: AsRef<Path>>(path: P, transform: Transform) -> McResult<usize> {
+    let path = path.as_ref();
+    let temp_path = path.with_extension("mca.transform");
+    if temp_path.exists() {
+        std::fs::remove_file(&temp_path)?;
+    }
+    let result = (|| -> McResult<usize> {
+        let reader = RegionReader::open(path)?;
+        let mut output = RegionFile::create(&temp_path)?.deferred();
+        let mut count = 0;
+        for index in 0..1024usize {
+            let coord = RegionCoord::from(index);
+            if reader.get_sector(coord).is_empty() {
+                continue;
+            }
+            let mut root: NamedTag = reader.read_data(coord)?;
+            transform.apply_to_chunk(root.tag_mut())?;
+            output.write_data_timestamped(transform.apply_to_coord(coord), &root, reader.header().timestamps[index])?;
+            count += 1;
+        }
+        output.close()?;
+        Ok(count)
+    })();
+    let count = match result {
+        Ok(count) => count,
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+    std::fs::rename(&temp_path, path)?;
+    if sidecar_path(path).is_file() {
+        std::fs::remove_file(sidecar_path(path))?;
+        RegionFile::open(path)?.enable_checksums()?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> Map {
+        let properties = pairs.iter()
+            .map(|&(key, value)| (key.to_owned(), Tag::string(value)))
+            .collect::<Map>();
+        Map::from([
+            ("Name".to_owned(), Tag::string("minecraft:test")),
+            ("Properties".to_owned(), Tag::Compound(properties)),
+        ])
+    }
+
+    fn property<'a>(state: &'a Map, key: &str) -> Option<&'a str> {
+        match state.get("Properties") {
+            Some(Tag::Compound(properties)) => match properties.get(key) {
+                Some(Tag::String(value)) => Some(value.as_str()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn transform_test() -> McResult<()> {
+        // The block at (1, 0, 0) of the section is stone, and the rest is air.
+        let mut data = PackedArray::new(4, 4096);
+        data.set(1, 1);
+        let palette = vec![
+            Map::from([("Name".to_owned(), Tag::string("minecraft:air"))]),
+            properties(&[("facing", "north"), ("axis", "x"), ("rotation", "2"), ("north", "true"), ("shape", "north_east")]),
+        ];
+        let section = Map::from([
+            ("Y".to_owned(), Tag::Byte(0)),
+            ("block_states".to_owned(), Tag::Compound(Map::from([
+                ("palette".to_owned(), Tag::List(ListTag::Compound(palette))),
+                ("data".to_owned(), Tag::LongArray(data.into_longs())),
+            ]))),
+        ]);
+        let chunk = NamedTag::new(Tag::Compound(Map::from([
+            ("xPos".to_owned(), Tag::Int(32)),
+            ("zPos".to_owned(), Tag::Int(-32)),
+            ("sections".to_owned(), Tag::List(ListTag::Compound(vec![section]))),
+            ("block_entities".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                ("x".to_owned(), Tag::Int(512)),
+                ("z".to_owned(), Tag::Int(-510)),
+            ])]))),
+        ])));
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.1.-1.mca");
+        let mut region = RegionFile::create(&path)?;
+        region.write_data_timestamped((0, 0), &chunk, 1234u32)?;
+        region.close()?;
+        assert_eq!(transform(&path, Transform::Rotate90)?, 1);
+
+        let region = RegionReader::open(&path)?;
+        assert!(region.get_sector((0, 0)).is_empty());
+        assert_eq!(u32::from(region.header().timestamps[RegionCoord::new(31, 0).index()]), 1234);
+        let root: NamedTag = region.read_data((31, 0))?;
+        let Tag::Compound(root) = root.tag() else { unreachable!() };
+        assert!(matches!((root.get("xPos"), root.get("zPos")), (Some(Tag::Int(63)), Some(Tag::Int(-32)))));
+        let Some(Tag::List(ListTag::Compound(block_entities))) = root.get("block_entities") else { unreachable!() };
+        assert!(matches!((block_entities[0].get("x"), block_entities[0].get("z")), (Some(Tag::Int(1021)), Some(Tag::Int(-512)))));
+        let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else { unreachable!() };
+        let Some(Tag::Compound(block_states)) = sections[0].get("block_states") else { unreachable!() };
+        let Some(Tag::List(ListTag::Compound(palette))) = block_states.get("palette") else { unreachable!() };
+        let state = &palette[1];
+        assert_eq!(property(state, "facing"), Some("east"));
+        assert_eq!(property(state, "axis"), Some("z"));
+        assert_eq!(property(state, "rotation"), Some("6"));
+        assert_eq!((property(state, "north"), property(state, "east")), (None, Some("true")));
+        assert_eq!(property(state, "shape"), Some("south_east"));
+        let Some(Tag::LongArray(data)) = block_states.get("data") else { unreachable!() };
+        let data = PackedArray::from_longs(4, 4096, data.clone())?;
+        // (1, 0, 0) turns to (15, 0, 1).
+        assert_eq!(data.get(16 + 15), 1);
+        assert_eq!(data.iter().sum::<u64>(), 1);
+
+        // Mirroring twice and rotating four times both give back the original.
+        let mut state = properties(&[("facing", "east"), ("rotation", "4"), ("shape", "inner_left"), ("west", "low")]);
+        Step::Mirror.block_state(&mut state);
+        assert_eq!((property(&state, "facing"), property(&state, "rotation"), property(&state, "shape"), property(&state, "east")), (Some("west"), Some("12"), Some("inner_right"), Some("low")));
+        let summary = |root: &Tag| {
+            let Tag::Compound(root) = root else { unreachable!() };
+            let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else { unreachable!() };
+            let Some(Tag::Compound(block_states)) = sections[0].get("block_states") else { unreachable!() };
+            let Some(Tag::List(ListTag::Compound(palette))) = block_states.get("palette") else { unreachable!() };
+            let Some(Tag::List(ListTag::Compound(block_entities))) = root.get("block_entities") else { unreachable!() };
+            let state = ["facing", "axis", "rotation", "north", "east", "shape"].map(|key| property(&palette[1], key).map(str::to_owned));
+            format!("{:?} {:?} {:?} {:?} {:?} {state:?}", root.get("xPos"), root.get("zPos"), block_states.get("data"), block_entities[0].get("x"), block_entities[0].get("z"))
+        };
+        let mut root = chunk.tag().clone();
+        let original = summary(&root);
+        Transform::Mirror.apply_to_chunk(&mut root)?;
+        assert_ne!(summary(&root), original);
+        Transform::Mirror.apply_to_chunk(&mut root)?;
+        assert_eq!(summary(&root), original);
+        for _ in 0..4 {
+            Transform::Rotate90.apply_to_chunk(&mut root)?;
+        }
+        assert_eq!(summary(&root), original);
+        assert_eq!(Transform::Rotate270.apply_to_coord(Transform::Rotate90.apply_to_coord(RegionCoord::new(3, 9))), RegionCoord::new(3, 9));
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! Item stacks, in both the data component form (1.20.5+) and the older `tag` form.
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        tag::{DecodeNbt, EncodeNbt, ListTag, Tag},
+    },
+};
+
+use super::components::{Components, int_value, namespaced};
+
+/// A stack of items. Stacks from before 1.20.5 (`Count` and `tag`) are converted to
+/// components when they are decoded, and every stack is encoded in the component form.
+#[derive(Debug, Clone)]
+pub struct ItemStack {
+    pub id: String,
+    pub count: i32,
+    pub components: Components,
+    /// Other keys of the stack (such as `Slot` in an inventory).
+    pub other: Map,
+}
+
+impl ItemStack {
+    pub fn new<S: AsRef<str>>(id: S, count: i32) -> Self {
+        Self {
+            id: namespaced(id.as_ref()),
+            count,
+            components: Components::default(),
+            other: Map::new(),
+        }
+    }
+
+    /// Returns true if the stack was stored in the form used before 1.20.5.
+    pub fn is_legacy(map: &Map) -> bool {
+        map.contains_key("Count") || map.contains_key("tag")
+    }
+}
+
+impl DecodeNbt for ItemStack {
+    fn decode_nbt(nbt: Tag) -> McResult<Self> {
+        let Tag::Compound(mut map) = nbt else {
+            return Err(McError::NbtDecodeError);
+        };
+        let Some(Tag::String(id)) = map.remove("id") else {
+            return Err(McError::NotFoundInCompound("id".to_owned()));
+        };
+        let legacy = ItemStack::is_legacy(&map);
+        let count = map.remove(if legacy { "Count" } else { "count" })
+            .as_ref()
+            .and_then(int_value)
+            .unwrap_or(1);
+        let components = if legacy {
+            match map.remove("tag") {
+                Some(Tag::Compound(tag)) => Components::from_legacy_tag(tag)?,
+                _ => Components::default(),
+            }
+        } else {
+            map.remove("components").map(Components::decode_nbt).transpose()?.unwrap_or_default()
+        };
+        Ok(ItemStack {
+            id: namespaced(&id),
+            count,
+            components,
+            other: map,
+        })
+    }
+}
+
+impl EncodeNbt for ItemStack {
+    fn encode_nbt(self) -> Tag {
+        let mut map = self.other;
+        map.insert("id".to_owned(), Tag::String(self.id));
+        map.insert("count".to_owned(), Tag::Int(self.count));
+        if !self.components.is_empty() {
+            map.insert("components".to_owned(), self.components.encode_nbt());
+        }
+        Tag::Compound(map)
+    }
+}
+
+/// Converts every item stack in `tag` (such as a chunk, an entity, or player data) from
+/// the form used before 1.20.5 to the component form. Returns the number of stacks
+/// converted; items stored inside of converted stacks are converted along with them.
+pub fn upgrade_legacy_items(tag: &mut Tag) -> McResult<usize> {
+    match tag {
+        Tag::Compound(map) => upgrade_compound(map),
+        Tag::List(ListTag::Compound(list)) => list.iter_mut().map(upgrade_compound).sum(),
+        Tag::List(ListTag::List(lists)) => lists.iter_mut().map(|list| {
+            let mut tag = Tag::List(std::mem::replace(list, ListTag::Empty));
+            let count = upgrade_legacy_items(&mut tag);
+            if let Tag::List(upgraded) = tag {
+                *list = upgraded;
+            }
+            count
+        }).sum(),
+        _ => Ok(0),
+    }
+}
+
+fn upgrade_compound(map: &mut Map) -> McResult<usize> {
+    if matches!(map.get("id"), Some(Tag::String(_))) && map.contains_key("Count") {
+        let legacy = std::mem::take(map);
+        if let Tag::Compound(upgraded) = ItemStack::decode_nbt(Tag::Compound(legacy))?.encode_nbt() {
+            *map = upgraded;
+        }
+        return Ok(1);
+    }
+    map.values_mut().map(upgrade_legacy_items).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tagpath::TagPath;
+
+    #[test]
+    fn legacy_item_test() -> McResult<()> {
+        let enchantment = |id: &str, level: i16| Map::from([
+            ("id".to_owned(), Tag::string(id)),
+            ("lvl".to_owned(), Tag::Short(level)),
+        ]);
+        let legacy = Tag::Compound(Map::from([
+            ("id".to_owned(), Tag::string("minecraft:shulker_box")),
+            ("Count".to_owned(), Tag::Byte(1)),
+            ("Slot".to_owned(), Tag::Byte(4)),
+            ("tag".to_owned(), Tag::Compound(Map::from([
+                ("Damage".to_owned(), Tag::Int(3)),
+                ("Enchantments".to_owned(), Tag::List(ListTag::Compound(vec![enchantment("minecraft:sharpness", 5), enchantment("unbreaking", 2)]))),
+                ("display".to_owned(), Tag::Compound(Map::from([
+                    ("Name".to_owned(), Tag::string("{\"text\":\"Loot\"}")),
+                ]))),
+                ("BlockEntityTag".to_owned(), Tag::Compound(Map::from([
+                    ("Items".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                        ("Slot".to_owned(), Tag::Byte(2)),
+                        ("id".to_owned(), Tag::string("minecraft:diamond")),
+                        ("Count".to_owned(), Tag::Byte(64)),
+                    ])]))),
+                ]))),
+                ("PluginData".to_owned(), Tag::Int(42)),
+            ]))),
+        ]));
+        let item = ItemStack::decode_nbt(legacy)?;
+        assert_eq!((item.id.as_str(), item.count), ("minecraft:shulker_box", 1));
+        assert!(matches!(item.other.get("Slot"), Some(Tag::Byte(4))));
+        let components = &item.components;
+        assert_eq!(components.damage, Some(3));
+        let enchantments = components.enchantments.as_ref().unwrap();
+        assert_eq!(enchantments.get("minecraft:unbreaking"), Some(&2));
+        assert!(matches!(&components.custom_name, Some(Tag::String(name)) if name.contains("Loot")));
+        let container = components.container.as_ref().unwrap();
+        assert_eq!((container[0].slot, container[0].item.id.as_str(), container[0].item.count), (2, "minecraft:diamond", 64));
+        assert!(matches!(components.custom_data.as_ref().and_then(|data| data.get("PluginData")), Some(Tag::Int(42))));
+
+        // The component form round-trips.
+        let Tag::Compound(encoded) = item.encode_nbt() else { panic!("Expected a compound.") };
+        assert!(!ItemStack::is_legacy(&encoded));
+        let item = ItemStack::decode_nbt(Tag::Compound(encoded))?;
+        assert_eq!(item.components.enchantments.as_ref().unwrap().len(), 2);
+        assert_eq!(item.components.container.as_ref().unwrap()[0].item.count, 64);
+        assert!(item.components.other.is_empty());
+
+        let mut chest = Tag::Compound(Map::from([
+            ("id".to_owned(), Tag::string("minecraft:chest")),
+            ("Items".to_owned(), Tag::List(ListTag::Compound(vec![Map::from([
+                ("Slot".to_owned(), Tag::Byte(0)),
+                ("id".to_owned(), Tag::string("minecraft:stone")),
+                ("Count".to_owned(), Tag::Byte(3)),
+            ])]))),
+        ]));
+        assert_eq!(upgrade_legacy_items(&mut chest)?, 1);
+        assert!(chest.find_child(TagPath::parse("Items[0].count").unwrap().path()).is_some());
+        assert_eq!(upgrade_legacy_items(&mut chest)?, 0);
+        Ok(())
+    }
+}
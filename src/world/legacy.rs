@@ -0,0 +1,419 @@
+//! Support for the pre-Anvil chunk layout used by MCRegion (`.mcr`) files,
+//! from Beta 1.3 until Anvil replaced it in 1.2.
+//!
+//! Legacy chunks store blocks as numeric ids (`Blocks`, one byte per block) with
+//! a separate nibble array of metadata (`Data`), in XZY order, for a world that
+//! is 128 blocks tall. [decode_legacy_chunk] converts them to the modern [Chunk]
+//! model, and [encode_legacy_chunk] converts them back (best-effort).
+//!
+//! Block ids are translated with [beta_block_state], which covers the blocks that
+//! existed in Beta 1.8. Metadata is only used to tell variants apart (wool colors,
+//! wood types, slabs); orientation and other states are not converted. With the
+//! `flattening` feature, `world::flattening::flatten_block` translates every block
+//! up to 1.12.2, including its states.
+
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+};
+
+use crate::{
+    McError, McResult,
+    math::bit::{get_nibble, set_nibble},
+    nbt::{
+        Map,
+        tag::{ListTag, Tag},
+    },
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::{
+        BlockEntity, Chunk, ChunkSection, ChunkSections, Heightmap, Heightmaps,
+        Lighting, SectionBlocks, chunk_yzx_index,
+    },
+};
+
+/// The height of a legacy world.
+pub const LEGACY_HEIGHT: i64 = 128;
+/// The number of blocks in a legacy chunk.
+pub const LEGACY_BLOCK_COUNT: usize = 16 * 16 * LEGACY_HEIGHT as usize;
+/// The DataVersion given to chunks converted from the legacy layout (1.20.1).
+/// Legacy chunks don't have a DataVersion; this is the version that block names are written for.
+pub const CONVERTED_DATA_VERSION: i32 = 3465;
+
+/// The names of the blocks in Beta 1.8 by numeric id, ignoring metadata.
+const BETA_BLOCKS: [&str; 97] = [
+    "air", "stone", "grass_block", "dirt", "cobblestone", "oak_planks", "oak_sapling", "bedrock",
+    "water", "water", "lava", "lava", "sand", "gravel", "gold_ore", "iron_ore",
+    "coal_ore", "oak_log", "oak_leaves", "sponge", "glass", "lapis_ore", "lapis_block", "dispenser",
+    "sandstone", "note_block", "red_bed", "powered_rail", "detector_rail", "sticky_piston", "cobweb", "grass",
+    "dead_bush", "piston", "piston_head", "white_wool", "moving_piston", "dandelion", "poppy", "brown_mushroom",
+    "red_mushroom", "gold_block", "iron_block", "smooth_stone_slab", "smooth_stone_slab", "bricks", "tnt", "bookshelf",
+    "mossy_cobblestone", "obsidian", "torch", "fire", "spawner", "oak_stairs", "chest", "redstone_wire",
+    "diamond_ore", "diamond_block", "crafting_table", "wheat", "farmland", "furnace", "furnace", "oak_sign",
+    "oak_door", "ladder", "rail", "cobblestone_stairs", "oak_wall_sign", "lever", "stone_pressure_plate", "iron_door",
+    "oak_pressure_plate", "redstone_ore", "redstone_ore", "redstone_torch", "redstone_torch", "stone_button", "snow", "ice",
+    "snow_block", "cactus", "clay", "sugar_cane", "jukebox", "oak_fence", "carved_pumpkin", "netherrack",
+    "soul_sand", "glowstone", "nether_portal", "jack_o_lantern", "cake", "repeater", "repeater", "chest",
+    "oak_trapdoor",
+];
+
+const COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray",
+    "light_gray", "cyan", "purple", "blue", "brown", "green", "red", "black",
+];
+
+const WOOD: [&str; 3] = ["oak", "spruce", "birch"];
+
+const SLABS: [&str; 4] = ["smooth_stone", "sandstone", "oak", "cobblestone"];
+
+fn minecraft(name: &str) -> String {
+    format!("minecraft:{name}")
+}
+
+/// Translates a Beta 1.8 block id and metadata to a [BlockState].
+/// Returns `None` for ids that didn't exist in Beta 1.8.
+pub fn beta_block_state(id: u8, data: u8) -> Option<BlockState> {
+    let data = data & 15;
+    let name = *BETA_BLOCKS.get(id as usize)?;
+    Some(match id {
+        6 => BlockState::from(minecraft(&format!("{}_sapling", WOOD[(data & 3).min(2) as usize]))),
+        17 => BlockState::from(minecraft(&format!("{}_log", WOOD[data.min(2) as usize]))),
+        18 => BlockState::from(minecraft(&format!("{}_leaves", WOOD[(data & 3).min(2) as usize]))),
+        31 => BlockState::from(minecraft(["dead_bush", "grass", "fern"][data.min(2) as usize])),
+        35 => BlockState::from(minecraft(&format!("{}_wool", COLORS[data as usize]))),
+        43 | 44 => BlockState::new(
+            minecraft(&format!("{}_slab", SLABS[(data & 3) as usize])),
+            [("type", if id == 43 { "double" } else { "bottom" })],
+        ),
+        8 | 10 if data != 0 => BlockState::new(minecraft(name), [("level", data.to_string())]),
+        62 | 74 | 94 => BlockState::new(minecraft(name), [("lit", "true")]),
+        75 => BlockState::new(minecraft(name), [("lit", "false")]),
+        _ => BlockState::from(minecraft(name)),
+    })
+}
+
+/// Translates a [BlockState] back to a Beta 1.8 block id and metadata.
+/// Returns `None` for blocks that can't be represented.
+pub fn beta_block_id(state: &BlockState) -> Option<(u8, u8)> {
+    static IDS: OnceLock<HashMap<BlockState, (u8, u8)>> = OnceLock::new();
+    let ids = IDS.get_or_init(|| {
+        let mut ids = HashMap::new();
+        (0..BETA_BLOCKS.len() as u8).for_each(|id| {
+            (0..16).for_each(|data| {
+                if let Some(state) = beta_block_state(id, data) {
+                    ids.entry(state).or_insert((id, data));
+                }
+            });
+        });
+        ids
+    });
+    ids.get(state).copied().or_else(|| {
+        // Fall back to the block without its properties.
+        ids.get(&BlockState::from(state.name())).copied()
+    })
+}
+
+/// The index of a block in a legacy chunk's `Blocks` array.
+fn legacy_index(x: i64, y: i64, z: i64) -> usize {
+    (y | (z << 7) | (x << 11)) as usize
+}
+
+fn take_byte_array(map: &mut Map, name: &str, len: usize) -> McResult<Vec<u8>> {
+    match map.remove(name) {
+        Some(Tag::ByteArray(bytes)) if bytes.len() == len => Ok(bytes.into_iter().map(|byte| byte as u8).collect()),
+        Some(_) => Err(McError::NbtDecodeError),
+        None => Err(McError::NotFoundInCompound(name.to_owned())),
+    }
+}
+
+/// Converts a legacy block entity id (such as `Chest`) to its modern id (`minecraft:chest`).
+fn modern_block_entity_id(id: &str) -> String {
+    let name = match id {
+        "Trap" => "dispenser",
+        "MobSpawner" => "mob_spawner",
+        "Music" => "note_block",
+        "RecordPlayer" => "jukebox",
+        "Piston" => "piston",
+        "Cauldron" => "brewing_stand",
+        "EnchantTable" => "enchanting_table",
+        "Airportal" => "end_portal",
+        other if other.contains(':') => return other.to_owned(),
+        other => return minecraft(&other.to_ascii_lowercase()),
+    };
+    minecraft(name)
+}
+
+/// Decodes a legacy chunk (the root tag of a chunk in an `.mcr` file) into a [Chunk],
+/// registering its blocks with `block_registry`. Block ids are translated with `translate`
+/// (such as [beta_block_state]); ids that it doesn't know become air.
+///
+/// The chunk keeps the legacy height (Y 0 to 127), and its sections are not
+/// upgraded in any other way, so it should be treated as a starting point for an upgrade.
+pub fn decode_legacy_chunk<F>(block_registry: &mut BlockRegistry, nbt: Tag, translate: F) -> McResult<Chunk>
+where F: Fn(u8, u8) -> Option<BlockState> {
+    let Tag::Compound(mut root) = nbt else {
+        return Err(McError::NbtDecodeError);
+    };
+    let Some(Tag::Compound(mut level)) = root.remove("Level") else {
+        return Err(McError::NotFoundInCompound("Level".to_owned()));
+    };
+    let blocks = take_byte_array(&mut level, "Blocks", LEGACY_BLOCK_COUNT)?;
+    let data = take_byte_array(&mut level, "Data", LEGACY_BLOCK_COUNT / 2)?;
+    let skylight = take_byte_array(&mut level, "SkyLight", LEGACY_BLOCK_COUNT / 2).ok();
+    let blocklight = take_byte_array(&mut level, "BlockLight", LEGACY_BLOCK_COUNT / 2).ok();
+    let heights = take_byte_array(&mut level, "HeightMap", 256).ok();
+
+    // Translate each distinct (id, data) pair once.
+    let mut translated = HashMap::<(u8, u8), u32>::new();
+    let air = block_registry.register(BlockState::air());
+    let sections = (0..(LEGACY_HEIGHT / 16) as i8).filter_map(|section_y| {
+        let mut ids = vec![air; 4096].into_boxed_slice();
+        let mut sky = vec![0u8; 2048];
+        let mut block = vec![0u8; 2048];
+        let mut empty = true;
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let legacy = legacy_index(x, section_y as i64 * 16 + y, z);
+                    let modern = chunk_yzx_index(x, y, z);
+                    let key = (blocks[legacy], get_nibble(&data, legacy));
+                    let id = *translated.entry(key).or_insert_with(|| {
+                        translate(key.0, key.1).map_or(air, |state| block_registry.register(state))
+                    });
+                    empty &= id == air;
+                    ids[modern] = id;
+                    if let Some(skylight) = &skylight {
+                        set_nibble(&mut sky, modern, get_nibble(skylight, legacy));
+                    }
+                    if let Some(blocklight) = &blocklight {
+                        set_nibble(&mut block, modern, get_nibble(blocklight, legacy));
+                    }
+                }
+            }
+        }
+        (!empty || skylight.is_some()).then(|| ChunkSection {
+            y: section_y,
+            blocks: Some(if empty { SectionBlocks::Uniform(air) } else { SectionBlocks::Ids(ids) }),
+            biomes: None,
+            skylight: skylight.is_some().then(|| Lighting::from(sky)),
+            blocklight: blocklight.is_some().then(|| Lighting::from(block)),
+            original: None,
+            other: Map::new(),
+        })
+    }).collect();
+
+    let mut heightmap = Heightmap::new(LEGACY_HEIGHT as u32);
+    if let Some(heights) = heights {
+        heights.iter().enumerate().try_for_each(|(index, &height)| {
+            heightmap.set(((index & 15) as i64, (index >> 4) as i64), height as u16)
+        })?;
+    }
+    let block_entities = match level.remove("TileEntities") {
+        Some(Tag::List(ListTag::Compound(entities))) => entities.into_iter().map(|mut entity| {
+            let coord = |entity: &mut Map, name: &str| match entity.remove(name) {
+                Some(Tag::Int(value)) => Ok(value),
+                _ => Err(McError::NotFoundInCompound(name.to_owned())),
+            };
+            Ok(BlockEntity {
+                id: match entity.remove("id") {
+                    Some(Tag::String(id)) => modern_block_entity_id(&id),
+                    _ => return Err(McError::NotFoundInCompound("id".to_owned())),
+                },
+                keep_packed: 0,
+                x: coord(&mut entity, "x")?,
+                y: coord(&mut entity, "y")?,
+                z: coord(&mut entity, "z")?,
+                data: entity,
+            })
+        }).collect::<McResult<Vec<_>>>()?,
+        _ => Vec::new(),
+    };
+    let int = |level: &mut Map, name: &str| match level.remove(name) {
+        Some(Tag::Int(value)) => Ok(value),
+        _ => Err(McError::NotFoundInCompound(name.to_owned())),
+    };
+    let x = int(&mut level, "xPos")?;
+    let z = int(&mut level, "zPos")?;
+    let last_update = match level.remove("LastUpdate") {
+        Some(Tag::Long(value)) => value,
+        _ => 0,
+    };
+    let populated = matches!(level.remove("TerrainPopulated"), Some(Tag::Byte(1)));
+    let entities = match level.remove("Entities") {
+        Some(Tag::List(entities)) => Some(entities),
+        _ => None,
+    };
+    Ok(Chunk {
+        data_version: CONVERTED_DATA_VERSION,
+        x,
+        y: 0,
+        z,
+        height: LEGACY_HEIGHT as u32,
+        last_update,
+        status: if populated { "minecraft:full" } else { "minecraft:empty" }.to_owned(),
+        sections: ChunkSections { sections },
+        block_entities,
+        heightmaps: Heightmaps {
+            motion_blocking: Some(heightmap.clone()),
+            motion_blocking_no_leaves: Some(heightmap.clone()),
+            ocean_floor: Some(heightmap.clone()),
+            ocean_floor_wg: None,
+            world_surface: Some(heightmap),
+            world_surface_wg: None,
+            other: Map::new(),
+        },
+        fluid_ticks: ListTag::Empty,
+        block_ticks: ListTag::Empty,
+        inhabited_time: 0,
+        post_processing: ListTag::Empty,
+        structures: Map::new(),
+        carving_masks: None,
+        lights: None,
+        entities,
+        // Anything left over (such as TileTicks) is kept with the chunk.
+        other: level,
+        key_order: None,
+    })
+}
+
+/// Encodes a [Chunk] in the legacy layout (best-effort). Blocks are translated with
+/// `translate` (such as [beta_block_id]); blocks that it can't translate, and blocks outside
+/// of Y 0 to 127, are written as air. Block entity ids are written as they are.
+pub fn encode_legacy_chunk<F>(block_registry: &BlockRegistry, chunk: &Chunk, translate: F) -> Tag
+where F: Fn(&BlockState) -> Option<(u8, u8)> {
+    let mut blocks = vec![0u8; LEGACY_BLOCK_COUNT];
+    let mut data = vec![0u8; LEGACY_BLOCK_COUNT / 2];
+    let mut skylight = vec![0u8; LEGACY_BLOCK_COUNT / 2];
+    let mut blocklight = vec![0u8; LEGACY_BLOCK_COUNT / 2];
+    let mut translated = HashMap::<u32, (u8, u8)>::new();
+    chunk.sections.sections.iter()
+        .filter(|section| (0..(LEGACY_HEIGHT / 16) as i8).contains(&section.y))
+        .for_each(|section| {
+            for y in 0..16 {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        let legacy = legacy_index(x, section.y as i64 * 16 + y, z);
+                        if let Some(id) = section.get_id(x, y, z) {
+                            let (block, meta) = *translated.entry(id).or_insert_with(|| {
+                                block_registry.get(id).and_then(&translate).unwrap_or((0, 0))
+                            });
+                            blocks[legacy] = block;
+                            set_nibble(&mut data, legacy, meta);
+                        }
+                        set_nibble(&mut skylight, legacy, section.skylight(x, y, z));
+                        set_nibble(&mut blocklight, legacy, section.blocklight(x, y, z));
+                    }
+                }
+            }
+        });
+    let heights = (0..256i64).map(|index| {
+        chunk.heightmaps.world_surface.as_ref().map_or(0, |heightmap| heightmap.get((index & 15, index >> 4)).min(LEGACY_HEIGHT)) as i8
+    }).collect();
+    let bytes = |bytes: Vec<u8>| Tag::ByteArray(bytes.into_iter().map(|byte| byte as i8).collect());
+    let tile_entities = chunk.block_entities.iter().map(|entity| {
+        let mut map = entity.data.clone();
+        map.insert("id".to_owned(), Tag::String(entity.id.clone()));
+        map.insert("x".to_owned(), Tag::Int(entity.x));
+        map.insert("y".to_owned(), Tag::Int(entity.y));
+        map.insert("z".to_owned(), Tag::Int(entity.z));
+        map
+    }).collect();
+    let mut level = chunk.other.clone();
+    level.extend([
+        ("xPos".to_owned(), Tag::Int(chunk.x)),
+        ("zPos".to_owned(), Tag::Int(chunk.z)),
+        ("LastUpdate".to_owned(), Tag::Long(chunk.last_update)),
+        ("TerrainPopulated".to_owned(), Tag::Byte((chunk.status == "minecraft:full") as i8)),
+        ("Blocks".to_owned(), bytes(blocks)),
+        ("Data".to_owned(), bytes(data)),
+        ("SkyLight".to_owned(), bytes(skylight)),
+        ("BlockLight".to_owned(), bytes(blocklight)),
+        ("HeightMap".to_owned(), Tag::ByteArray(heights)),
+        ("Entities".to_owned(), Tag::List(chunk.entities.clone().unwrap_or(ListTag::Empty))),
+        ("TileEntities".to_owned(), Tag::List(ListTag::Compound(tile_entities))),
+    ]);
+    Tag::Compound(Map::from_iter([("Level".to_owned(), Tag::Compound(level))]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::tag::NamedTag,
+        world::io::region::{McRegionFile, RegionCoord, RegionFormatExt, open_region, Timestamp},
+    };
+
+    fn legacy_chunk() -> Tag {
+        let mut blocks = vec![0i8; LEGACY_BLOCK_COUNT];
+        let mut data = vec![0u8; LEGACY_BLOCK_COUNT / 2];
+        blocks[legacy_index(0, 0, 0)] = 7;
+        blocks[legacy_index(1, 64, 2)] = 35;
+        set_nibble(&mut data, legacy_index(1, 64, 2), 14);
+        blocks[legacy_index(15, 127, 15)] = 44;
+        set_nibble(&mut data, legacy_index(15, 127, 15), 1);
+        blocks[legacy_index(3, 3, 3)] = 120;
+        let chest = Map::from_iter([
+            ("id".to_owned(), Tag::String("Chest".to_owned())),
+            ("x".to_owned(), Tag::Int(33)),
+            ("y".to_owned(), Tag::Int(64)),
+            ("z".to_owned(), Tag::Int(-30)),
+            ("Items".to_owned(), Tag::List(ListTag::Empty)),
+        ]);
+        let level = Map::from_iter([
+            ("xPos".to_owned(), Tag::Int(2)),
+            ("zPos".to_owned(), Tag::Int(-2)),
+            ("LastUpdate".to_owned(), Tag::Long(500)),
+            ("TerrainPopulated".to_owned(), Tag::Byte(1)),
+            ("Blocks".to_owned(), Tag::ByteArray(blocks)),
+            ("Data".to_owned(), Tag::ByteArray(data.into_iter().map(|b| b as i8).collect())),
+            ("HeightMap".to_owned(), Tag::ByteArray(vec![64; 256])),
+            ("TileEntities".to_owned(), Tag::List(ListTag::Compound(vec![chest]))),
+        ]);
+        Tag::Compound(Map::from_iter([("Level".to_owned(), Tag::Compound(level))]))
+    }
+
+    #[test]
+    fn legacy_chunk_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let chunk = decode_legacy_chunk(&mut registry, legacy_chunk(), beta_block_state)?;
+        let name = |registry: &BlockRegistry, chunk: &Chunk, x: i64, y: i64, z: i64| chunk.get_id((x + 32, y, z - 32))
+            .and_then(|id| registry.get(id))
+            .map(|state| state.name().to_owned());
+        assert_eq!((chunk.x, chunk.z), (2, -2));
+        assert_eq!(name(&registry, &chunk, 0, 0, 0).as_deref(), Some("minecraft:bedrock"));
+        assert_eq!(name(&registry, &chunk, 1, 64, 2).as_deref(), Some("minecraft:red_wool"));
+        assert_eq!(name(&registry, &chunk, 15, 127, 15).as_deref(), Some("minecraft:sandstone_slab"));
+        // Unknown ids become air.
+        assert_eq!(name(&registry, &chunk, 3, 3, 3).as_deref(), Some("minecraft:air"));
+        assert_eq!(chunk.block_entities[0].id, "minecraft:chest");
+        assert_eq!(chunk.heightmaps.world_surface.as_ref().map(|heightmap| heightmap.get((5, 5))), Some(64));
+
+        // Write the chunk back out to an .mcr file and read it again.
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("r.0.-1.mcr");
+        let encoded = encode_legacy_chunk(&registry, &chunk, beta_block_id);
+        McRegionFile::create(&path)?.write_chunk(RegionCoord::new(2, 30), &NamedTag::new(encoded), Timestamp::utc_now())?;
+        let mut region = open_region(&path)?;
+        assert_eq!(region.extension(), "mcr");
+        let root: NamedTag = region.read_chunk(RegionCoord::new(2, 30))?;
+        let chunk = decode_legacy_chunk(&mut registry, root.take_tag(), beta_block_state)?;
+        assert_eq!(name(&registry, &chunk, 1, 64, 2).as_deref(), Some("minecraft:red_wool"));
+        assert_eq!(name(&registry, &chunk, 15, 127, 15).as_deref(), Some("minecraft:sandstone_slab"));
+        assert_eq!(chunk.block_entities[0].data.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn beta_block_id_test() {
+        (0..BETA_BLOCKS.len() as u8).for_each(|id| {
+            let state = beta_block_state(id, 0).unwrap();
+            let (back, _) = beta_block_id(&state).unwrap();
+            assert_eq!(beta_block_state(back, 0), Some(state));
+        });
+        assert_eq!(beta_block_id(&BlockState::from("minecraft:lime_wool")), Some((35, 5)));
+        assert_eq!(beta_block_id(&BlockState::from("minecraft:deepslate")), None);
+    }
+}
@@ -21,52 +21,97 @@
 // C	Player
 //
 
-use std::{fs::File, io::{BufReader, BufWriter, Read, Seek, SeekFrom}, path::Path};
+use std::path::{Path, PathBuf};
 
 use crate::{
-    ioext::ReadExt, nbt::{io::write_named_tag, tag::*, Map}, McError, McResult
+    math::coord::{BlockPos, ChunkPos},
+    ioext::Codec, nbt::{file::{read_nbt_file_detect, write_nbt_file_with, write_nbt_file_with_codec}, tag::*, Map}, McError, McResult
 };
-use flate2::{read::GzDecoder, read::ZlibDecoder, Compression};
-use flate2::write::GzEncoder;
+use super::io::region::CompressionScheme;
+use super::io::region::timestamp::unix_millis_now;
+use super::spawn::{spawn_chunks, LEGACY_SPAWN_CHUNK_RADIUS};
+use super::bosses::{CustomBossEvents, DragonFight};
+use flate2::Compression;
 
+/// Reads level.dat, which may be GZip compressed (as the game writes it), ZLib compressed,
+/// or uncompressed. The compression is kept in [Level::compression_scheme].
 pub fn read_level_from_file<P: AsRef<Path>>(path: P) -> McResult<Level> {
-    let mut file = File::open(path)?;
-    let mut buffer: [u8; 1] = [0];
-    file.read_exact(&mut buffer)?;
-    file.seek(SeekFrom::Start(0))?;
-    let mut reader = BufReader::new(file);
-    // Gzip magic number.
-    match buffer[0] {
-        // GZip
-        0x1f => {
-            let mut decoder = GzDecoder::new(reader);
-            let root: NamedTag = decoder.read_value()?;
-            Level::decode_nbt(root.take_tag())
-        }
-        // ZLib
-        0x78 => {
-            let mut decoder = ZlibDecoder::new(reader);
-            let root: NamedTag = decoder.read_value()?;
-            Level::decode_nbt(root.take_tag())
+    let (root, scheme) = read_nbt_file_detect(path)?;
+    let mut level = Level::decode_nbt(root.take_tag())?;
+    level.compression = scheme;
+    Ok(level)
+}
+
+/// The path of the copy of `level_file` that the game keeps from before the last save
+/// (`level.dat_old` next to `level.dat`).
+pub fn old_level_path<P: AsRef<Path>>(level_file: P) -> PathBuf {
+    let level_file = level_file.as_ref();
+    let mut name = level_file.file_name().unwrap_or_default().to_owned();
+    name.push("_old");
+    level_file.with_file_name(name)
+}
+
+/// Reads level.dat like [read_level_from_file], but if it is missing or can't be read,
+/// reads `level.dat_old` instead (see [old_level_path]). If both fail, the error from
+/// level.dat is returned.
+pub fn read_level_with_fallback<P: AsRef<Path>>(path: P) -> McResult<Level> {
+    let path = path.as_ref();
+    match read_level_from_file(path) {
+        Ok(level) => Ok(level),
+        Err(err) => {
+            let old = old_level_path(path);
+            if old.is_file() {
+                read_level_from_file(old).map_err(|_| err)
+            } else {
+                Err(err)
+            }
         }
-        // No Compression (hopefully)
-        _ => {
-            let root: NamedTag = reader.read_value()?;
-            Level::decode_nbt(root.take_tag())
+    }
+}
+
+/// Options for [write_level_with].
+#[derive(Debug, Clone, Copy)]
+pub struct LevelWriteOptions {
+    /// The compression level (see [write_level_to_file]).
+    pub compression: Compression,
+    /// Keep the previous level.dat as `level.dat_old`, as the game does.
+    pub rotate_old: bool,
+}
+
+impl Default for LevelWriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::default(),
+            rotate_old: true,
         }
     }
 }
 
+/// Writes level.dat like [write_level_to_file]. With [LevelWriteOptions::rotate_old], the
+/// file that is being replaced is first copied to `level.dat_old`.
+pub fn write_level_with<P: AsRef<Path>>(path: P, level: &Level, options: &LevelWriteOptions) -> McResult<usize> {
+    let path = path.as_ref();
+    if options.rotate_old && path.is_file() {
+        std::fs::copy(path, old_level_path(path))?;
+    }
+    write_level_to_file(path, level, options.compression)
+}
+
+/// Writes level.dat with the level's [Level::compression_scheme] (the one it was read with),
+/// or uncompressed if `compression` is [Compression::none()].
 pub fn write_level_to_file<P: AsRef<Path>>(path: P, level: &Level, compression: Compression) -> McResult<usize> {
-    let file = File::create(path)?;
-    let mut writer = BufWriter::new(file);
-    let level_tag = level.encode_nbt();
-    if compression == Compression::none() {
-        write_named_tag(&mut writer, &level_tag, "")
+    let scheme = if compression == Compression::none() {
+        CompressionScheme::Uncompressed
     } else {
-        let mut encoder = GzEncoder::new(writer, compression);
-        write_named_tag(&mut encoder, &level_tag, "")
-    }
+        level.compression
+    };
+    write_nbt_file_with(path, &NamedTag::new(level.encode_nbt()), scheme, compression)
+}
+
+/// Writes level.dat compressed with `codec` instead of the level's [Level::compression_scheme].
+/// The game only reads level.dat that is GZip or ZLib compressed, or uncompressed.
+pub fn write_level_with_codec<P: AsRef<Path>>(path: P, level: &Level, codec: &dyn Codec) -> McResult<usize> {
+    write_nbt_file_with_codec(path, &NamedTag::new(level.encode_nbt()), codec)
 }
 
 /*
@@ -114,6 +159,65 @@ Byte       thundering
 Int        version
 */
 
+/// The number of ticks in a Minecraft day.
+pub const TICKS_PER_DAY: i64 = 24000;
+
+/// Named times of day, matching the values used by `/time set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeOfDay {
+    Day,
+    Noon,
+    Sunset,
+    Night,
+    Midnight,
+    Sunrise,
+}
+
+impl TimeOfDay {
+    /// The time of day in ticks (0 to 23999).
+    pub fn ticks(self) -> i64 {
+        match self {
+            TimeOfDay::Day => 1000,
+            TimeOfDay::Noon => 6000,
+            TimeOfDay::Sunset => 12000,
+            TimeOfDay::Night => 13000,
+            TimeOfDay::Midnight => 18000,
+            TimeOfDay::Sunrise => 23000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(i8)]
+pub enum Difficulty {
+    Peaceful = 0,
+    Easy = 1,
+    Normal = 2,
+    Hard = 3,
+}
+
+impl TryFrom<i8> for Difficulty {
+    type Error = McError;
+
+    fn try_from(value: i8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Difficulty::Peaceful,
+            1 => Difficulty::Easy,
+            2 => Difficulty::Normal,
+            3 => Difficulty::Hard,
+            _ => return Err(McError::OutOfRange),
+        })
+    }
+}
+
+/// The weather that can be set with [Level::set_weather].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Thunder,
+}
+
 pub struct Level {
     /// BorderCenterX
     border_center_x: f64,
@@ -132,7 +236,7 @@ pub struct Level {
     /// BorderWarningTime
     border_warning_time: f64,
     /// CustomBossEvents
-    custom_boss_events: Map,
+    custom_boss_events: CustomBossEvents,
     /// DataPacks
     data_packs: Map,
     /// DataVersion
@@ -144,7 +248,7 @@ pub struct Level {
     ///	DifficultyLocked
     difficulty_locked: i8,
     /// DragonFight
-    dragon_fight: Map,
+    dragon_fight: DragonFight,
     /// GameRules
     game_rules: Map,
     /// GameType
@@ -153,8 +257,8 @@ pub struct Level {
     last_played: i64,
     /// LevelName
     level_name: String,
-    /// Player
-    player: Map,
+    /// Player (only in singleplayer worlds)
+    player: Option<Map>,
     /// ScheduledEvents
     scheduled_events: ListTag,
     /// ServerBrands
@@ -197,6 +301,8 @@ pub struct Level {
     thundering: i8,
     /// version
     version2: i32, // What absolute moron decided to have two variables named "version"?
+    /// The compression of the file that the level was read from (not part of the NBT).
+    compression: CompressionScheme,
 }
 
 /// This macro is used to remove an entry from a Map (usually HashMap or IndexMap)
@@ -236,6 +342,204 @@ macro_rules! map_encoder {
 }
 
 impl Level {
+    /// The DataVersion of the game that last saved the world.
+    pub fn data_version(&self) -> i32 {
+        self.data_version
+    }
+
+    /// The WorldGenSettings compound: the seed and the generator of each dimension.
+    pub fn world_gen_settings(&self) -> &Map {
+        &self.world_gen_settings
+    }
+
+    /// The compression that [write_level_to_file] uses: the one that level.dat was read with,
+    /// or [CompressionScheme::GZip] for a new level.
+    pub fn compression_scheme(&self) -> CompressionScheme {
+        self.compression
+    }
+
+    pub fn set_compression_scheme(&mut self, scheme: CompressionScheme) {
+        self.compression = scheme;
+    }
+
+    /// The world spawn (SpawnX/SpawnY/SpawnZ).
+    pub fn spawn(&self) -> BlockPos {
+        BlockPos::new(self.spawn_x as i64, self.spawn_y as i64, self.spawn_z as i64)
+    }
+
+    /// The angle that players face when they spawn (SpawnAngle).
+    pub fn spawn_angle(&self) -> f32 {
+        self.spawn_angle
+    }
+
+    /// Moves the world spawn. This does not change which chunks are force-loaded;
+    /// see [super::spawn::set_world_spawn] for that.
+    pub fn set_spawn<C: Into<BlockPos>>(&mut self, spawn: C, angle: f32) {
+        let spawn: BlockPos = spawn.into();
+        self.spawn_x = spawn.x as i32;
+        self.spawn_y = spawn.y as i32;
+        self.spawn_z = spawn.z as i32;
+        self.spawn_angle = angle;
+    }
+
+    /// The radius of the spawn chunks in chunks, from the `spawnChunkRadius` game rule.
+    /// Worlds without the game rule use [LEGACY_SPAWN_CHUNK_RADIUS].
+    pub fn spawn_chunk_radius(&self) -> i64 {
+        match self.game_rules.get("spawnChunkRadius") {
+            Some(Tag::String(radius)) => radius.parse().unwrap_or(LEGACY_SPAWN_CHUNK_RADIUS),
+            _ => LEGACY_SPAWN_CHUNK_RADIUS,
+        }
+    }
+
+    /// The spawn chunks around the world spawn, sorted by (x, z).
+    pub fn spawn_chunks(&self) -> Vec<ChunkPos> {
+        spawn_chunks(self.spawn(), self.spawn_chunk_radius())
+    }
+
+    /// The total number of ticks that the world has been running (Time).
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// Sets the total number of ticks that the world has been running.
+    /// Returns [McError::OutOfRange] if `ticks` is negative.
+    pub fn set_time(&mut self, ticks: i64) -> McResult<()> {
+        if ticks < 0 {
+            return Err(McError::OutOfRange);
+        }
+        self.time = ticks;
+        Ok(())
+    }
+
+    /// The time of day in ticks, including the ticks of all previous days (DayTime).
+    pub fn day_time(&self) -> i64 {
+        self.day_time
+    }
+
+    /// Sets the time of day in ticks, including the ticks of all previous days.
+    /// Returns [McError::OutOfRange] if `ticks` is negative.
+    pub fn set_day_time(&mut self, ticks: i64) -> McResult<()> {
+        if ticks < 0 {
+            return Err(McError::OutOfRange);
+        }
+        self.day_time = ticks;
+        Ok(())
+    }
+
+    /// The number of days that have passed.
+    pub fn day(&self) -> i64 {
+        self.day_time.div_euclid(TICKS_PER_DAY)
+    }
+
+    /// Sets the time of day without changing the day (and so the moon phase).
+    pub fn set_time_of_day(&mut self, time: TimeOfDay) {
+        self.day_time = self.day() * TICKS_PER_DAY + time.ticks();
+    }
+
+    pub fn raining(&self) -> bool {
+        self.raining != 0
+    }
+
+    pub fn thundering(&self) -> bool {
+        self.thundering != 0
+    }
+
+    /// The number of ticks until `raining` is toggled (rainTime).
+    pub fn rain_time(&self) -> i32 {
+        self.rain_time
+    }
+
+    /// The number of ticks until `thundering` is toggled (thunderTime).
+    pub fn thunder_time(&self) -> i32 {
+        self.thunder_time
+    }
+
+    /// The number of ticks of clear weather left after `/weather clear` (clearWeatherTime).
+    pub fn clear_weather_time(&self) -> i32 {
+        self.clear_weather_time
+    }
+
+    /// Sets the weather for `duration` ticks, the same way that `/weather` does.
+    /// Returns [McError::OutOfRange] if `duration` is not positive.
+    pub fn set_weather(&mut self, weather: Weather, duration: i32) -> McResult<()> {
+        if duration <= 0 {
+            return Err(McError::OutOfRange);
+        }
+        let (clear_time, weather_time) = match weather {
+            Weather::Clear => (duration, 0),
+            Weather::Rain | Weather::Thunder => (0, duration),
+        };
+        self.clear_weather_time = clear_time;
+        self.rain_time = weather_time;
+        self.thunder_time = weather_time;
+        self.raining = (weather != Weather::Clear) as i8;
+        self.thundering = (weather == Weather::Thunder) as i8;
+        Ok(())
+    }
+
+    /// The difficulty of the world.
+    /// Returns [McError::OutOfRange] if level.dat has an invalid difficulty.
+    pub fn difficulty(&self) -> McResult<Difficulty> {
+        Difficulty::try_from(self.difficulty)
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty as i8;
+    }
+
+    pub fn difficulty_locked(&self) -> bool {
+        self.difficulty_locked != 0
+    }
+
+    pub fn set_difficulty_locked(&mut self, locked: bool) {
+        self.difficulty_locked = locked as i8;
+    }
+
+    /// The name of the world (LevelName).
+    pub fn level_name(&self) -> &str {
+        &self.level_name
+    }
+
+    /// The game rules (GameRules). Every value is stored as a string.
+    pub fn game_rules(&self) -> &Map {
+        &self.game_rules
+    }
+
+    /// Gets the value of a game rule, such as `keepInventory`.
+    pub fn game_rule(&self, name: &str) -> Option<&str> {
+        match self.game_rules.get(name) {
+            Some(Tag::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn set_game_rule<S: Into<String>, V: ToString>(&mut self, name: S, value: V) {
+        self.game_rules.insert(name.into(), Tag::String(value.to_string()));
+    }
+
+    /// The state of the ender dragon fight (DragonFight).
+    pub fn dragon_fight(&self) -> &DragonFight {
+        &self.dragon_fight
+    }
+
+    pub fn dragon_fight_mut(&mut self) -> &mut DragonFight {
+        &mut self.dragon_fight
+    }
+
+    /// Resets the ender dragon fight. See [DragonFight::reset].
+    pub fn reset_dragon_fight(&mut self) {
+        self.dragon_fight.reset();
+    }
+
+    /// The boss bars created with `/bossbar` (CustomBossEvents).
+    pub fn custom_boss_events(&self) -> &CustomBossEvents {
+        &self.custom_boss_events
+    }
+
+    pub fn custom_boss_events_mut(&mut self) -> &mut CustomBossEvents {
+        &mut self.custom_boss_events
+    }
+
     pub fn encode_nbt(&self) -> Tag {
         let mut data = Map::new();
         map_encoder!(data;
@@ -258,7 +562,6 @@ impl Level {
             "GameType" = self.game_type;
             "LastPlayed" = self.last_played;
             "LevelName" = self.level_name.clone();
-            "Player" = self.player.clone();
             "ScheduledEvents" = self.scheduled_events.clone();
             "ServerBrands" = self.server_brands.clone();
             "SpawnAngle" = self.spawn_angle;
@@ -281,10 +584,255 @@ impl Level {
             "thundering" = self.thundering;
             "version" = self.version2;
         );
+        if let Some(player) = &self.player {
+            map_encoder!(data; "Player" = player.clone());
+        }
         Tag::Compound(Map::from([("Data".to_owned(), Tag::Compound(data))]))
     }
 }
 
+/// A layer of a superflat world, from the bottom up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatLayer {
+    /// The block ID, such as `minecraft:grass_block`.
+    pub block: String,
+    pub height: u32,
+}
+
+impl FlatLayer {
+    pub fn new<S: Into<String>>(block: S, height: u32) -> Self {
+        Self {
+            block: block.into(),
+            height,
+        }
+    }
+
+    /// The layers of the "Classic Flat" preset.
+    pub fn classic() -> Vec<FlatLayer> {
+        vec![
+            FlatLayer::new("minecraft:bedrock", 1),
+            FlatLayer::new("minecraft:dirt", 2),
+            FlatLayer::new("minecraft:grass_block", 1),
+        ]
+    }
+}
+
+/// The Y coordinate of the bottom of a 1.18+ overworld.
+const OVERWORLD_BOTTOM: i64 = -64;
+
+fn string_map<const N: usize>(entries: [(&str, Tag); N]) -> Map {
+    entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+}
+
+/// The `WorldGenSettings` of a vanilla 1.18+ world. With `flat_layers`, the overworld is superflat.
+fn world_gen_settings(seed: i64, flat_layers: Option<&[FlatLayer]>) -> Map {
+    let noise = |dimension_type: &str, settings: &str, biome_source: Map| Tag::Compound(string_map([
+        ("type", Tag::string(dimension_type)),
+        ("generator", Tag::Compound(string_map([
+            ("type", Tag::string("minecraft:noise")),
+            ("settings", Tag::string(settings)),
+            ("biome_source", Tag::Compound(biome_source)),
+        ]))),
+    ]));
+    let multi_noise = |preset: &str| string_map([
+        ("type", Tag::string("minecraft:multi_noise")),
+        ("preset", Tag::string(preset)),
+    ]);
+    let overworld = match flat_layers {
+        Some(layers) => Tag::Compound(string_map([
+            ("type", Tag::string("minecraft:overworld")),
+            ("generator", Tag::Compound(string_map([
+                ("type", Tag::string("minecraft:flat")),
+                ("settings", Tag::Compound(string_map([
+                    ("biome", Tag::string("minecraft:plains")),
+                    ("features", Tag::Byte(0)),
+                    ("lakes", Tag::Byte(0)),
+                    ("layers", Tag::List(ListTag::Compound(layers.iter().map(|layer| string_map([
+                        ("block", Tag::string(&layer.block)),
+                        ("height", Tag::Int(layer.height as i32)),
+                    ])).collect()))),
+                ]))),
+            ]))),
+        ])),
+        None => noise("minecraft:overworld", "minecraft:overworld", multi_noise("minecraft:overworld")),
+    };
+    string_map([
+        ("seed", Tag::Long(seed)),
+        ("generate_features", Tag::Byte(1)),
+        ("bonus_chest", Tag::Byte(0)),
+        ("dimensions", Tag::Compound(string_map([
+            ("minecraft:overworld", overworld),
+            ("minecraft:the_nether", noise("minecraft:the_nether", "minecraft:nether", multi_noise("minecraft:nether"))),
+            ("minecraft:the_end", noise("minecraft:the_end", "minecraft:end", string_map([("type", Tag::string("minecraft:the_end"))]))),
+        ]))),
+    ])
+}
+
+/// Builds the [Level] of a new world, with the values that the game gives a new world
+/// unless they are changed. See [super::create::create_new].
+#[derive(Debug, Clone)]
+pub struct LevelBuilder {
+    level_name: String,
+    seed: i64,
+    game_type: i32,
+    difficulty: Difficulty,
+    hardcore: bool,
+    allow_commands: bool,
+    spawn: BlockPos,
+    data_version: i32,
+    version_name: String,
+    game_rules: Map,
+    flat_layers: Option<Vec<FlatLayer>>,
+}
+
+impl LevelBuilder {
+    pub fn new<S: Into<String>>(level_name: S) -> Self {
+        Self {
+            level_name: level_name.into(),
+            seed: 0,
+            game_type: 0,
+            difficulty: Difficulty::Normal,
+            hardcore: false,
+            allow_commands: false,
+            spawn: BlockPos::new(0, 64, 0),
+            data_version: 3465,
+            version_name: "1.20.1".to_owned(),
+            game_rules: Map::new(),
+            flat_layers: None,
+        }
+    }
+
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The game mode (GameType): 0 for survival, 1 for creative, 2 for adventure, 3 for spectator.
+    pub fn game_type(mut self, game_type: i32) -> Self {
+        self.game_type = game_type;
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    pub fn hardcore(mut self, hardcore: bool) -> Self {
+        self.hardcore = hardcore;
+        self
+    }
+
+    pub fn allow_commands(mut self, allow_commands: bool) -> Self {
+        self.allow_commands = allow_commands;
+        self
+    }
+
+    pub fn spawn<C: Into<BlockPos>>(mut self, spawn: C) -> Self {
+        self.spawn = spawn.into();
+        self
+    }
+
+    /// The DataVersion and version name (such as `1.20.1`) written to level.dat.
+    pub fn version<S: Into<String>>(mut self, data_version: i32, name: S) -> Self {
+        self.data_version = data_version;
+        self.version_name = name.into();
+        self
+    }
+
+    pub fn game_rule<S: Into<String>, V: ToString>(mut self, name: S, value: V) -> Self {
+        self.game_rules.insert(name.into(), Tag::String(value.to_string()));
+        self
+    }
+
+    /// Copies every game rule of `level`, so that a new world can be made with the rules of an existing one.
+    pub fn game_rules_of(mut self, level: &Level) -> Self {
+        self.game_rules.extend(level.game_rules.iter().map(|(name, value)| (name.clone(), value.clone())));
+        self
+    }
+
+    /// Makes the overworld superflat with `layers` (from the bottom of the world up), and
+    /// moves the spawn to the top of the layers. Call [LevelBuilder::spawn] after this to
+    /// choose a different spawn.
+    pub fn superflat(mut self, layers: Vec<FlatLayer>) -> Self {
+        let height = layers.iter().map(|layer| layer.height as i64).sum::<i64>();
+        self.spawn.y = OVERWORLD_BOTTOM + height;
+        self.flat_layers = Some(layers);
+        self
+    }
+
+    /// The superflat layers, if the overworld is superflat.
+    pub fn flat_layers(&self) -> Option<&[FlatLayer]> {
+        self.flat_layers.as_deref()
+    }
+
+    pub fn data_version(&self) -> i32 {
+        self.data_version
+    }
+
+    pub fn spawn_pos(&self) -> BlockPos {
+        self.spawn
+    }
+
+    pub fn build(self) -> Level {
+        let last_played = unix_millis_now();
+        Level {
+            border_center_x: 0.0,
+            border_center_z: 0.0,
+            border_damage_per_block: 0.2,
+            border_size: 59999968.0,
+            border_size_lerp_target: 59999968.0,
+            border_size_lerp_time: 0,
+            border_warning_blocks: 5.0,
+            border_warning_time: 15.0,
+            custom_boss_events: CustomBossEvents::default(),
+            data_packs: string_map([
+                ("Enabled", Tag::List(ListTag::String(vec!["vanilla".to_owned()]))),
+                ("Disabled", Tag::List(ListTag::Empty)),
+            ]),
+            data_version: self.data_version,
+            day_time: 0,
+            difficulty: self.difficulty as i8,
+            difficulty_locked: 0,
+            dragon_fight: DragonFight::default(),
+            game_rules: self.game_rules,
+            game_type: self.game_type,
+            last_played,
+            level_name: self.level_name,
+            player: None,
+            scheduled_events: ListTag::Empty,
+            server_brands: ListTag::String(vec!["vanilla".to_owned()]),
+            spawn_angle: 0.0,
+            spawn_x: self.spawn.x as i32,
+            spawn_y: self.spawn.y as i32,
+            spawn_z: self.spawn.z as i32,
+            time: 0,
+            version: string_map([
+                ("Id", Tag::Int(self.data_version)),
+                ("Name", Tag::String(self.version_name)),
+                ("Series", Tag::string("main")),
+                ("Snapshot", Tag::Byte(0)),
+            ]),
+            wandering_trader_spawn_chance: 25,
+            wandering_trader_spawn_delay: 24000,
+            was_modded: 0,
+            world_gen_settings: world_gen_settings(self.seed, self.flat_layers.as_deref()),
+            allow_commands: self.allow_commands as i8,
+            clear_weather_time: 0,
+            hardcore: self.hardcore as i8,
+            // The spawn has been chosen, so the game doesn't search for one.
+            initialized: 1,
+            rain_time: 0,
+            raining: 0,
+            thunder_time: 0,
+            thundering: 0,
+            // The Anvil format.
+            version2: 19133,
+            compression: CompressionScheme::GZip,
+        }
+    }
+}
+
 impl DecodeNbt for Level {
     fn decode_nbt(nbt: Tag) -> McResult<Self> {
         if let Tag::Compound(mut map) = nbt {
@@ -298,18 +846,18 @@ impl DecodeNbt for Level {
                 border_size_lerp_time: map_decoder!(data; "BorderSizeLerpTime" -> i64),
                 border_warning_blocks: map_decoder!(data; "BorderWarningBlocks" -> f64),
                 border_warning_time: map_decoder!(data; "BorderWarningTime" -> f64),
-                custom_boss_events: map_decoder!(data; "CustomBossEvents" -> Map),
+                custom_boss_events: map_decoder!(data; "CustomBossEvents" -> CustomBossEvents),
                 data_packs: map_decoder!(data; "DataPacks" -> Map),
                 data_version: map_decoder!(data; "DataVersion" -> i32),
                 day_time: map_decoder!(data; "DayTime" -> i64),
                 difficulty: map_decoder!(data; "Difficulty" -> i8),
                 difficulty_locked: map_decoder!(data; "DifficultyLocked" -> i8),
-                dragon_fight: map_decoder!(data; "DragonFight" -> Map),
+                dragon_fight: map_decoder!(data; "DragonFight" -> DragonFight),
                 game_rules: map_decoder!(data; "GameRules" -> Map),
                 game_type: map_decoder!(data; "GameType" -> i32),
                 last_played: map_decoder!(data; "LastPlayed" -> i64),
                 level_name: map_decoder!(data; "LevelName" -> String),
-                player: map_decoder!(data; "Player" -> Map),
+                player: map_decoder!(data; "Player" -> Option<Map>),
                 scheduled_events: map_decoder!(data; "ScheduledEvents" -> ListTag),
                 server_brands: map_decoder!(data; "ServerBrands" -> ListTag),
                 spawn_angle: map_decoder!(data; "SpawnAngle" -> f32),
@@ -331,9 +879,68 @@ impl DecodeNbt for Level {
                 thunder_time: map_decoder!(data; "thunderTime" -> i32),
                 thundering: map_decoder!(data; "thundering" -> i8),
                 version2: map_decoder!(data; "version" -> i32),
+                compression: CompressionScheme::GZip,
             })
         } else {
             return Err(McError::NbtDecodeError);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difficulty_test() {
+        assert_eq!(Difficulty::try_from(2).ok(), Some(Difficulty::Normal));
+        assert!(Difficulty::try_from(4).is_err());
+        assert!(Difficulty::try_from(-1).is_err());
+        assert_eq!(Difficulty::Hard as i8, 3);
+        assert!(TimeOfDay::Sunrise.ticks() < TICKS_PER_DAY);
+    }
+
+    #[test]
+    fn compression_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("level.dat");
+        let mut level = LevelBuilder::new("Zlib").build();
+        assert_eq!(level.compression_scheme(), CompressionScheme::GZip);
+        level.set_compression_scheme(CompressionScheme::ZLib);
+        write_level_to_file(&path, &level, Compression::default())?;
+        assert_eq!(std::fs::read(&path)?[0], 0x78);
+
+        // Writing a level that was read keeps its compression.
+        let mut level = read_level_from_file(&path)?;
+        assert_eq!(level.compression_scheme(), CompressionScheme::ZLib);
+        level.set_game_rule("doDaylightCycle", false);
+        write_level_to_file(&path, &level, Compression::best())?;
+        let level = read_level_from_file(&path)?;
+        assert_eq!((level.compression_scheme(), level.level_name()), (CompressionScheme::ZLib, "Zlib"));
+        assert_eq!(level.game_rule("doDaylightCycle"), Some("false"));
+
+        write_level_to_file(&path, &level, Compression::none())?;
+        assert_eq!(std::fs::read(&path)?[0], 10);
+        assert_eq!(read_level_from_file(&path)?.compression_scheme(), CompressionScheme::Uncompressed);
+        Ok(())
+    }
+
+    #[test]
+    fn level_old_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("level.dat");
+        assert_eq!(old_level_path(&path), dir.path().join("level.dat_old"));
+        write_level_with(&path, &LevelBuilder::new("First").build(), &LevelWriteOptions::default())?;
+        assert!(!old_level_path(&path).exists());
+        write_level_with(&path, &LevelBuilder::new("Second").build(), &LevelWriteOptions::default())?;
+        assert_eq!(read_level_from_file(old_level_path(&path))?.level_name(), "First");
+        assert_eq!(read_level_with_fallback(&path)?.level_name(), "Second");
+
+        std::fs::write(&path, b"corrupt")?;
+        assert!(read_level_from_file(&path).is_err());
+        assert_eq!(read_level_with_fallback(&path)?.level_name(), "First");
+        let options = LevelWriteOptions { rotate_old: false, ..Default::default() };
+        write_level_with(&path, &LevelBuilder::new("Third").build(), &options)?;
+        assert_eq!(read_level_from_file(old_level_path(&path))?.level_name(), "First");
+        Ok(())
+    }
+}
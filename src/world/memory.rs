@@ -0,0 +1,214 @@
+//! Memory accounting for loaded world data.
+//!
+//! [MemorySize] is implemented for the chunk model and the block registry here, and
+//! [VirtualJavaWorld::memory_usage](super::world::VirtualJavaWorld::memory_usage) sums them
+//! into a [MemoryReport]. With a limit set by
+//! [VirtualJavaWorld::set_memory_limit](super::world::VirtualJavaWorld::set_memory_limit),
+//! the least recently used chunks are saved and unloaded to stay within it.
+
+use std::{fmt::Display, mem::size_of};
+
+use crate::{
+    math::packed::PackedArray,
+    util::memory::{MemorySize, table_size},
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::{BlockEntity, CarvingMasks, Chunk, ChunkSection, Heightmap, Heightmaps, Lighting, SectionBlocks},
+};
+
+/// The bytes held by the data loaded in a world.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The loaded chunks.
+    pub chunks: usize,
+    pub chunk_count: usize,
+    pub block_registry: usize,
+    /// The open region files (their headers and buffers).
+    pub regions: usize,
+    pub region_count: usize,
+    /// The tables that the world keeps its chunks, regions, and chunk use in.
+    pub caches: usize,
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> usize {
+        self.chunks + self.block_registry + self.regions + self.caches
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "total: {} bytes", self.total())?;
+        writeln!(f, "chunks: {} bytes ({} chunks)", self.chunks, self.chunk_count)?;
+        writeln!(f, "block registry: {} bytes", self.block_registry)?;
+        writeln!(f, "regions: {} bytes ({} files)", self.regions, self.region_count)?;
+        writeln!(f, "caches: {} bytes", self.caches)
+    }
+}
+
+impl MemorySize for BlockState {
+    fn heap_size(&self) -> usize {
+        let properties = self.properties().unwrap_or_default();
+        self.name().len()
+            + std::mem::size_of_val(properties)
+            + properties.iter().map(|property| property.name.len() + property.value.len()).sum::<usize>()
+    }
+}
+
+/// Each state is held twice, in the list of states and as a key of the id table.
+impl MemorySize for BlockRegistry {
+    fn heap_size(&self) -> usize {
+        let states = (0..self.len() as u32)
+            .filter_map(|id| self.get(id))
+            .map(|state| state.heap_size())
+            .sum::<usize>();
+        self.len() * size_of::<BlockState>()
+            + table_size(self.len(), size_of::<(BlockState, u32)>())
+            + states * 2
+    }
+}
+
+impl MemorySize for PackedArray {
+    fn heap_size(&self) -> usize {
+        self.packing().long_count(self.bits(), self.len()) * size_of::<i64>()
+    }
+}
+
+impl MemorySize for Heightmap {
+    fn heap_size(&self) -> usize {
+        self.map.heap_size()
+    }
+}
+
+impl MemorySize for Heightmaps {
+    fn heap_size(&self) -> usize {
+        self.motion_blocking.heap_size()
+            + self.motion_blocking_no_leaves.heap_size()
+            + self.ocean_floor.heap_size()
+            + self.ocean_floor_wg.heap_size()
+            + self.world_surface.heap_size()
+            + self.world_surface_wg.heap_size()
+            + self.other.heap_size()
+    }
+}
+
+/// Light is always one nibble for each of the 4096 blocks of a section.
+impl MemorySize for Lighting {
+    fn heap_size(&self) -> usize {
+        2048
+    }
+}
+
+impl MemorySize for SectionBlocks {
+    fn heap_size(&self) -> usize {
+        match self {
+            SectionBlocks::Uniform(_) => 0,
+            SectionBlocks::Ids(ids) => ids.heap_size(),
+        }
+    }
+}
+
+impl MemorySize for ChunkSection {
+    fn heap_size(&self) -> usize {
+        self.blocks.heap_size()
+            + self.biomes.heap_size()
+            + self.skylight.heap_size()
+            + self.blocklight.heap_size()
+            + self.original.as_ref().map_or(0, |original| original.map.heap_size())
+            + self.other.heap_size()
+    }
+}
+
+impl MemorySize for BlockEntity {
+    fn heap_size(&self) -> usize {
+        self.id.heap_size() + self.data.heap_size()
+    }
+}
+
+impl MemorySize for CarvingMasks {
+    fn heap_size(&self) -> usize {
+        self.air.heap_size() + self.liquid.heap_size()
+    }
+}
+
+impl MemorySize for Chunk {
+    fn heap_size(&self) -> usize {
+        self.status.heap_size()
+            + self.sections.sections.heap_size()
+            + self.block_entities.heap_size()
+            + self.heightmaps.heap_size()
+            + self.fluid_ticks.heap_size()
+            + self.block_ticks.heap_size()
+            + self.post_processing.heap_size()
+            + self.structures.heap_size()
+            + self.carving_masks.heap_size()
+            + self.lights.heap_size()
+            + self.entities.heap_size()
+            + self.other.heap_size()
+            + self.key_order.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        McResult,
+        math::coord::{Dimension, WorldCoord},
+        world::{
+            create::{WorldOptions, create_new},
+            level::LevelBuilder,
+            world::VirtualJavaWorld,
+        },
+    };
+
+    #[test]
+    fn memory_limit_test() -> McResult<()> {
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let mut chunk = Chunk::new(0, -4, 0);
+        let empty = chunk.memory_size();
+        chunk.sections.get_or_insert(0).fill(stone);
+        let uniform = chunk.memory_size();
+        assert!(uniform > empty);
+        chunk.sections.get_or_insert(0).set_id(0, 0, 0, 0);
+        assert!(chunk.memory_size() >= uniform + 4096 * 4);
+
+        let dir = tempfile::tempdir()?;
+        create_new(dir.path(), LevelBuilder::new("Memory"), &WorldOptions { pregenerate_radius: Some(1), ..Default::default() })?;
+        let mut world = VirtualJavaWorld::open(dir.path());
+        let coords = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(x, z)| WorldCoord::new(x, z, Dimension::Overworld));
+        for coord in coords {
+            world.load_chunk(coord)?;
+        }
+        let mut sorted = coords.to_vec();
+        sorted.sort();
+        assert_eq!(world.loaded_chunks(), sorted);
+        let report = world.memory_usage();
+        assert_eq!(report.chunk_count, 4);
+        assert_eq!(report.region_count, 1);
+        assert!(report.chunks > 0 && report.total() > report.chunks);
+
+        // Use the first chunk again, so that the second is the least recently used.
+        world.get_chunk(coords[0]).expect("loaded chunk");
+        // Change the second chunk (without using it), so that it is saved when it is evicted.
+        let stone = world.block_registry.register(BlockState::from("minecraft:stone"));
+        world.chunks[&coords[1]].lock().unwrap().chunk.set_id((16, 0, 0), stone)?;
+        world.chunks[&coords[1]].lock().unwrap().mark_dirty();
+        let limit = report.total() - report.chunks / 8;
+        assert_eq!(world.set_memory_limit(Some(limit))?, 1);
+        assert!(!world.is_chunk_loaded(coords[1]));
+        assert!(world.is_chunk_loaded(coords[0]));
+        assert!(world.memory_usage().total() <= limit);
+        let slot = world.load_chunk(coords[1])?;
+        let id = slot.lock().unwrap().chunk.get_id((16, 0, 0));
+        assert_eq!(id.and_then(|id| world.block_registry.get(id)).map(BlockState::name), Some("minecraft:stone"));
+        // The saved chunk is larger than it was, so loading it evicts at least one chunk.
+        assert!(world.memory_usage().chunk_count <= 3);
+        assert!(world.is_chunk_loaded(coords[1]));
+        Ok(())
+    }
+}
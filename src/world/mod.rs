@@ -1,8 +1,70 @@
+// Without the `world` feature, only region file IO (io) is compiled, which in turn
+// needs the `region` feature (everything besides CompressionScheme is gated in io::region).
 pub mod io;
+
+use crate::macros::cfg_feature;
+
+// blockstate exports the blockstate! macro, which can't be referred to by path
+// if the module is declared by a macro, so it is gated on its own.
+#[cfg(feature = "world")]
 pub mod blockstate;
-pub mod blockregistry;
-pub mod chunk;
-pub mod world;
-pub mod container;
-pub mod block;
-pub mod level;
\ No newline at end of file
+
+cfg_feature!("world" {
+    pub mod blockregistry;
+    pub mod chunk;
+    pub mod codec;
+    pub mod components;
+    pub mod item;
+    pub mod entity;
+    pub mod world;
+    pub mod container;
+    pub mod block;
+    pub mod level;
+    pub mod selection;
+    pub mod scan;
+    pub mod findreplace;
+    pub mod poi;
+    pub mod forced;
+    pub mod storage;
+    pub mod relight;
+    pub mod backup;
+    pub mod search;
+    pub mod text;
+    pub mod spawn;
+    pub mod create;
+    pub mod bosses;
+    pub mod session;
+    pub mod legacy;
+    pub mod transaction;
+    pub mod clone;
+    pub mod terrainhash;
+    pub mod writequeue;
+    pub mod fsck;
+    pub mod biome;
+    pub mod analysis;
+    pub mod height;
+    pub mod servers;
+    pub mod remap;
+    pub mod network;
+    pub mod memory;
+    pub mod gc;
+
+    pub use findreplace::find_replace;
+    pub use relight::relight;
+    pub use backup::{backup, restore, export_changed_since};
+    pub use search::{find_players, find_item};
+    pub use text::extract_text;
+    pub use session::lock;
+    pub use transaction::WorldTransaction;
+    pub use clone::clone_area;
+    pub use create::create_new;
+    pub use fsck::fsck;
+    pub use session::is_world_in_use;
+    pub use biome::replace_biome;
+    pub use remap::remap_ids;
+});
+
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "flattening")]
+pub mod flattening;
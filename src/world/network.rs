@@ -0,0 +1,331 @@
+//! Helpers for sending chunks over the network, for custom servers and proxies.
+//!
+//! The chunk data packet holds the chunk's heightmaps as network NBT (see
+//! [write_network_tag]) and a data section with each section's block count and its block
+//! states and biomes as paletted containers. This is the layout used from 1.20.2 to 1.21.4.
+//! The protocol refers to block states and biomes by the numeric ids of the game's
+//! registries, which depend on the game version, so they are given with [NetworkIds].
+//! Block entities and light data, which follow the data section, aren't written here.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+};
+
+use crate::{
+    McError, McResult,
+    math::packed::{PackedArray, get_packed, palette_bits},
+    nbt::{
+        Map,
+        io::write_network_tag,
+        tag::{ListTag, Tag},
+    },
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::{Chunk, ChunkSection, Heightmaps, SectionBlocks},
+};
+
+/// The biome of sections that don't have any biome data.
+pub const DEFAULT_BIOME: &str = "minecraft:plains";
+
+/// The blocks that aren't counted in a section's block count.
+const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// The protocol ids of block states and biomes.
+///
+/// Palettes that are too large to be indirect hold these ids directly, with as many bits as
+/// the largest id needs, so every id of the registry should be added for those to match the
+/// game's.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkIds {
+    block_states: HashMap<BlockState, u32>,
+    biomes: HashMap<String, u32>,
+}
+
+impl NetworkIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_block_state(&mut self, state: BlockState, id: u32) {
+        self.block_states.insert(state, id);
+    }
+
+    pub fn insert_biome<S: Into<String>>(&mut self, biome: S, id: u32) {
+        self.biomes.insert(biome.into(), id);
+    }
+
+    pub fn block_state(&self, state: &BlockState) -> Option<u32> {
+        self.block_states.get(state).copied()
+    }
+
+    pub fn biome(&self, biome: &str) -> Option<u32> {
+        self.biomes.get(biome).copied()
+    }
+
+    /// The number of bits of a direct block state palette.
+    pub fn direct_block_bits(&self) -> u32 {
+        direct_bits(self.block_states.values())
+    }
+
+    /// The number of bits of a direct biome palette.
+    pub fn direct_biome_bits(&self) -> u32 {
+        direct_bits(self.biomes.values())
+    }
+}
+
+fn direct_bits<'a, I: Iterator<Item = &'a u32>>(ids: I) -> u32 {
+    palette_bits(ids.max().map_or(1, |&max| max as usize + 1), 1)
+}
+
+/// The kinds of paletted containers, which differ in their size and palette widths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContainerKind {
+    /// The 4096 block states of a section.
+    BlockStates,
+    /// The 64 biomes (4x4x4 cells) of a section.
+    Biomes,
+}
+
+impl ContainerKind {
+    /// The number of entries in the container.
+    pub fn entries(self) -> usize {
+        match self {
+            ContainerKind::BlockStates => 4096,
+            ContainerKind::Biomes => 64,
+        }
+    }
+
+    /// The smallest number of bits of an indirect palette.
+    pub fn min_bits(self) -> u32 {
+        match self {
+            ContainerKind::BlockStates => 4,
+            ContainerKind::Biomes => 1,
+        }
+    }
+
+    /// The largest number of bits of an indirect palette. Larger palettes are direct.
+    pub fn max_indirect_bits(self) -> u32 {
+        match self {
+            ContainerKind::BlockStates => 8,
+            ContainerKind::Biomes => 3,
+        }
+    }
+}
+
+/// Writes a protocol VarInt (7 bits per byte, least significant first).
+pub fn write_var_int<W: Write>(writer: &mut W, value: i32) -> McResult<usize> {
+    let mut value = value as u32;
+    let mut bytes = Vec::with_capacity(5);
+    loop {
+        if value < 0x80 {
+            bytes.push(value as u8);
+            break;
+        }
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    writer.write_all(&bytes)?;
+    Ok(bytes.len())
+}
+
+/// Writes a paletted container of protocol ids (in YZX order). The palette is single valued
+/// if every id is the same, indirect if it fits in [ContainerKind::max_indirect_bits], and
+/// direct (with `direct_bits` bits) otherwise.
+pub fn write_paletted_container<W: Write>(writer: &mut W, kind: ContainerKind, ids: &[u32], direct_bits: u32) -> McResult<usize> {
+    if ids.len() != kind.entries() {
+        return Err(McError::OutOfRange);
+    }
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    let entries = ids.iter().map(|&id| {
+        *indices.entry(id).or_insert_with(|| {
+            palette.push(id);
+            palette.len() as u64 - 1
+        })
+    }).collect::<Vec<u64>>();
+    let mut written = 0;
+    if palette.len() == 1 {
+        writer.write_all(&[0])?;
+        written += 1 + write_var_int(writer, palette[0] as i32)?;
+        // An empty data array.
+        return Ok(written + write_var_int(writer, 0)?);
+    }
+    let bits = palette_bits(palette.len(), kind.min_bits());
+    let array = if bits <= kind.max_indirect_bits() {
+        writer.write_all(&[bits as u8])?;
+        written += 1 + write_var_int(writer, palette.len() as i32)?;
+        for &id in palette.iter() {
+            written += write_var_int(writer, id as i32)?;
+        }
+        pack(bits, &entries)
+    } else {
+        writer.write_all(&[direct_bits as u8])?;
+        written += 1;
+        pack(direct_bits, &ids.iter().map(|&id| id as u64).collect::<Vec<_>>())
+    };
+    let longs = array.into_longs();
+    written += write_var_int(writer, longs.len() as i32)?;
+    for long in longs {
+        writer.write_all(&long.to_be_bytes())?;
+        written += 8;
+    }
+    Ok(written)
+}
+
+fn pack(bits: u32, values: &[u64]) -> PackedArray {
+    let mut array = PackedArray::new(bits, values.len());
+    values.iter().enumerate().for_each(|(index, &value)| {
+        array.set(index, value);
+    });
+    array
+}
+
+/// Writes the heightmaps that the client uses (`MOTION_BLOCKING` and `WORLD_SURFACE`) as network NBT.
+pub fn write_heightmaps<W: Write>(writer: &mut W, heightmaps: &Heightmaps) -> McResult<usize> {
+    let map = [("MOTION_BLOCKING", &heightmaps.motion_blocking), ("WORLD_SURFACE", &heightmaps.world_surface)]
+        .into_iter()
+        .filter_map(|(name, heightmap)| Some((name.to_owned(), Tag::LongArray(heightmap.as_ref()?.map.clone().into_longs()))))
+        .collect::<Map>();
+    write_network_tag(writer, &Tag::Compound(map))
+}
+
+/// Writes a section: the number of blocks that aren't air, then the block states and the
+/// biomes. `section` is `None` for a missing section, which is written as air.
+pub fn write_section<W: Write>(writer: &mut W, registry: &BlockRegistry, ids: &NetworkIds, section: Option<&ChunkSection>) -> McResult<usize> {
+    let network_id = |state_id: u32| -> McResult<(u32, bool)> {
+        let state = registry.get(state_id).ok_or(McError::OutOfRange)?;
+        let id = ids.block_state(state)
+            .ok_or_else(|| McError::Custom(format!("No network id for block state {}.", state.name())))?;
+        Ok((id, !AIR_BLOCKS.contains(&state.name())))
+    };
+    let air = || ids.block_state(&BlockState::air())
+        .ok_or_else(|| McError::Custom("No network id for minecraft:air.".to_owned()));
+    let (blocks, count) = match section.and_then(|section| section.blocks.as_ref()) {
+        None => (vec![air()?; 4096], 0),
+        Some(SectionBlocks::Uniform(state_id)) => {
+            let (id, solid) = network_id(*state_id)?;
+            (vec![id; 4096], if solid { 4096 } else { 0 })
+        }
+        Some(SectionBlocks::Ids(state_ids)) => {
+            // Sections hold few distinct states, so look each up once.
+            let mut cache = HashMap::new();
+            let mut count = 0;
+            let mut blocks = Vec::with_capacity(4096);
+            for &state_id in state_ids.iter() {
+                let (id, solid) = match cache.get(&state_id) {
+                    Some(&entry) => entry,
+                    None => {
+                        let entry = network_id(state_id)?;
+                        cache.insert(state_id, entry);
+                        entry
+                    }
+                };
+                count += solid as i16;
+                blocks.push(id);
+            }
+            (blocks, count)
+        }
+    };
+    let biomes = section_biomes(ids, section)?;
+    writer.write_all(&count.to_be_bytes())?;
+    Ok(2
+        + write_paletted_container(writer, ContainerKind::BlockStates, &blocks, ids.direct_block_bits())?
+        + write_paletted_container(writer, ContainerKind::Biomes, &biomes, ids.direct_biome_bits())?)
+}
+
+/// The protocol ids of a section's 64 biome cells. Sections without biomes are [DEFAULT_BIOME].
+fn section_biomes(ids: &NetworkIds, section: Option<&ChunkSection>) -> McResult<Vec<u32>> {
+    let biome_id = |biome: &str| ids.biome(biome)
+        .ok_or_else(|| McError::Custom(format!("No network id for biome {biome}.")));
+    let Some(biomes) = section.and_then(|section| section.biomes.as_ref()) else {
+        return Ok(vec![biome_id(DEFAULT_BIOME)?; 64]);
+    };
+    let Some(Tag::List(ListTag::String(palette))) = biomes.get("palette") else {
+        return Err(McError::NbtDecodeError);
+    };
+    let palette = palette.iter().map(|biome| biome_id(biome)).collect::<McResult<Vec<_>>>()?;
+    let first = *palette.first().ok_or(McError::NbtDecodeError)?;
+    match biomes.get("data") {
+        Some(Tag::LongArray(data)) => {
+            let bits = palette_bits(palette.len(), 1);
+            (0..64).map(|index| palette.get(get_packed(data, bits, index) as usize).copied().ok_or(McError::NbtDecodeError)).collect()
+        }
+        _ => Ok(vec![first; 64]),
+    }
+}
+
+/// Writes the data section of a chunk data packet: every section of the chunk's height (see
+/// [Chunk::height]) from the bottom up, including missing ones. The packet holds this after
+/// its length (see [write_chunk_data]).
+pub fn chunk_sections_data(registry: &BlockRegistry, ids: &NetworkIds, chunk: &Chunk) -> McResult<Vec<u8>> {
+    let mut data = Vec::new();
+    for index in 0..(chunk.height / 16) as i32 {
+        let y = chunk.y + index;
+        let section = chunk.sections.sections.iter().find(|section| section.y as i32 == y);
+        write_section(&mut data, registry, ids, section)?;
+    }
+    Ok(data)
+}
+
+/// Writes the start of a chunk data packet after the chunk's coordinates: the heightmaps,
+/// then the length of the data section and the data section itself.
+pub fn write_chunk_data<W: Write>(writer: &mut W, registry: &BlockRegistry, ids: &NetworkIds, chunk: &Chunk) -> McResult<usize> {
+    let data = chunk_sections_data(registry, ids, chunk)?;
+    let written = write_heightmaps(writer, &chunk.heightmaps)? + write_var_int(writer, data.len() as i32)?;
+    writer.write_all(&data)?;
+    Ok(written + data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{blockstate::BlockProperties, height::WorldHeight};
+
+    #[test]
+    fn chunk_data_test() -> McResult<()> {
+        let mut buf = Vec::new();
+        assert_eq!(write_var_int(&mut buf, 300)?, 2);
+        assert_eq!(write_var_int(&mut buf, -1)?, 5);
+        assert_eq!(buf, [0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+
+        let stone = BlockState::new("minecraft:stone", BlockProperties::none());
+        let dirt = BlockState::new("minecraft:dirt", BlockProperties::none());
+        let mut registry = BlockRegistry::with_air();
+        let stone_id = registry.register(&stone);
+        let dirt_id = registry.register(&dirt);
+        let mut ids = NetworkIds::new();
+        ids.insert_block_state(BlockState::air(), 0);
+        ids.insert_block_state(stone, 1);
+        ids.insert_block_state(dirt, 10);
+        ids.insert_biome(DEFAULT_BIOME, 40);
+        assert_eq!(ids.direct_block_bits(), 4);
+
+        let mut chunk = Chunk::with_height(0, 0, WorldHeight::new(0, 32));
+        chunk.sections.get_or_insert(0).fill(stone_id);
+        chunk.sections.get_or_insert(0).set_id(1, 2, 3, dirt_id);
+        let data = chunk_sections_data(&registry, &ids, &chunk)?;
+        // Section 0: 4096 blocks, a 4 bit indirect palette of [1, 10] (4096 / 16 = 256 longs),
+        // then a single valued biome container.
+        assert_eq!(&data[..6], [0x10, 0x00, 4, 2, 1, 10]);
+        assert_eq!(&data[6..8], [0x80, 0x02]);
+        let longs = &data[8..8 + 256 * 8];
+        let index = (2 << 8) | (3 << 4) | 1;
+        let long = i64::from_be_bytes(longs[index / 16 * 8..index / 16 * 8 + 8].try_into().unwrap());
+        assert_eq!((long >> ((index % 16) * 4)) & 15, 1);
+        let rest = &data[8 + 256 * 8..];
+        assert_eq!(&rest[..3], [0, 40, 0]);
+        // Section 1 is missing, so it is air.
+        assert_eq!(&rest[3..], [0, 0, 0, 0, 0, 0, 40, 0]);
+
+        let mut packet = Vec::new();
+        let written = write_chunk_data(&mut packet, &registry, &ids, &chunk)?;
+        assert_eq!(written, packet.len());
+        assert_eq!(packet[0], 10);
+        assert!(packet.ends_with(&data));
+        Ok(())
+    }
+}
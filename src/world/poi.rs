@@ -0,0 +1,218 @@
+//! Points of interest (POI) stored in the `poi/` region files, and a checker that
+//! finds POI records that no longer have a matching block (for example, after a bed
+//! or workstation was removed by an external edit).
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    path::Path,
+};
+
+use crate::{
+    McResult,
+    math::{
+        coord::{BlockCoord, WorldCoord},
+        packed::{get_packed, palette_bits},
+    },
+    nbt::{
+        Map,
+        tag::{ListTag, NamedTag, Tag},
+    },
+};
+
+use super::{
+    io::region::{RegionFile, coord::RegionCoord},
+    scan::{for_each_chunk, region_file_path, RegionKind},
+    selection::WorldSelection,
+};
+
+/// A single POI record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoiRecord {
+    pub pos: BlockCoord,
+    /// The POI type, such as `minecraft:home`.
+    pub poi_type: String,
+    pub free_tickets: i32,
+}
+
+impl PoiRecord {
+    fn decode(map: &Map, coord: WorldCoord) -> Option<Self> {
+        let Some(Tag::IntArray(pos)) = map.get("pos") else { return None };
+        let [x, y, z] = pos.as_slice() else { return None };
+        let Some(Tag::String(poi_type)) = map.get("type") else { return None };
+        let free_tickets = match map.get("free_tickets") {
+            Some(Tag::Int(tickets)) => *tickets,
+            _ => 0,
+        };
+        Some(Self {
+            pos: BlockCoord::new(*x as i64, *y as i64, *z as i64, coord.dimension),
+            poi_type: poi_type.to_owned(),
+            free_tickets,
+        })
+    }
+}
+
+/// Reads every POI record from the root tag of a POI chunk.
+pub fn read_poi_records(root: &Tag, coord: WorldCoord) -> Vec<PoiRecord> {
+    let Tag::Compound(root) = root else { return Vec::new() };
+    let Some(Tag::Compound(sections)) = root.get("Sections") else { return Vec::new() };
+    let mut keys = sections.keys().collect::<Vec<_>>();
+    keys.sort_by_key(|key| key.parse::<i32>().unwrap_or_default());
+    keys.into_iter()
+        .filter_map(|key| match sections.get(key) {
+            Some(Tag::Compound(section)) => match section.get("Records") {
+                Some(Tag::List(ListTag::Compound(records))) => Some(records),
+                _ => None,
+            },
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|record| PoiRecord::decode(record, coord))
+        .collect()
+}
+
+/// Returns true if `block` (a namespaced block id) is valid for `poi_type`.
+/// Returns `None` if the POI type is not known, in which case it can't be checked.
+pub fn poi_accepts_block(poi_type: &str, block: &str) -> Option<bool> {
+    let block = block.strip_prefix("minecraft:").unwrap_or(block);
+    let accepted = match poi_type.strip_prefix("minecraft:").unwrap_or(poi_type) {
+        "home" => block.ends_with("_bed"),
+        "meeting" => block == "bell",
+        "armorer" => block == "blast_furnace",
+        "butcher" => block == "smoker",
+        "cartographer" => block == "cartography_table",
+        "cleric" => block == "brewing_stand",
+        "farmer" => block == "composter",
+        "fisherman" => block == "barrel",
+        "fletcher" => block == "fletching_table",
+        "leatherworker" => block.ends_with("cauldron"),
+        "librarian" => block == "lectern",
+        "mason" => block == "stonecutter",
+        "shepherd" => block == "loom",
+        "toolsmith" => block == "smithing_table",
+        "weaponsmith" => block == "grindstone",
+        "beehive" => block == "beehive",
+        "bee_nest" => block == "bee_nest",
+        "nether_portal" => block == "nether_portal",
+        "lodestone" => block == "lodestone",
+        "lightning_rod" => block == "lightning_rod",
+        _ => return None,
+    };
+    Some(accepted)
+}
+
+/// Gets the block id at a block coordinate from the root tag of a terrain chunk.
+/// This reads the palette of a single section rather than decoding the whole chunk.
+fn block_name_at(chunk: &Tag, pos: BlockCoord) -> Option<String> {
+    let Tag::Compound(chunk) = chunk else { return None };
+    let Some(Tag::List(ListTag::Compound(sections))) = chunk.get("sections") else { return None };
+    let section_y = pos.y.div_euclid(16);
+    let section = sections.iter().find(|section| {
+        matches!(section.get("Y"), Some(Tag::Byte(y)) if *y as i64 == section_y)
+    })?;
+    let Some(Tag::Compound(block_states)) = section.get("block_states") else { return None };
+    let Some(Tag::List(ListTag::Compound(palette))) = block_states.get("palette") else { return None };
+    let index = match block_states.get("data") {
+        Some(Tag::LongArray(data)) if palette.len() > 1 => {
+            let (x, y, z) = (pos.x & 15, pos.y & 15, pos.z & 15);
+            get_packed(data, palette_bits(palette.len(), 4), ((y << 8) | (z << 4) | x) as usize) as usize
+        },
+        _ => 0,
+    };
+    match palette.get(index)?.get("Name") {
+        Some(Tag::String(name)) => Some(name.to_owned()),
+        _ => None,
+    }
+}
+
+/// A POI record whose block no longer matches its type.
+#[derive(Debug, Clone)]
+pub struct StalePoi {
+    /// The chunk that the record is stored in.
+    pub chunk: WorldCoord,
+    pub record: PoiRecord,
+    /// The block that was found at the record's position.
+    pub found: String,
+}
+
+/// Checks every POI record in the selection against the block at its position.
+/// Records with unknown POI types are skipped, as are records whose block can't be
+/// determined (because the terrain region or chunk is missing or can't be read, or the
+/// chunk doesn't have the section in the current format).
+/// When `fix` is true, stale records are removed from the POI files.
+pub fn check_poi<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection, fix: bool) -> McResult<Vec<StalePoi>> {
+    let world_directory = world_directory.as_ref();
+    let mut terrain: HashMap<WorldCoord, Option<RegionFile>> = HashMap::new();
+    let mut stale = Vec::new();
+    for_each_chunk(world_directory, selection, RegionKind::Poi, |chunk, root| {
+        let region = chunk.region_coord();
+        let regionfile = match terrain.entry(region) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = region_file_path(world_directory, region, RegionKind::Terrain)?;
+                entry.insert(RegionFile::open(path).ok())
+            },
+        };
+        let terrain_chunk = regionfile.as_mut()
+            .and_then(|regionfile| regionfile.read_data::<_, NamedTag>(RegionCoord::from(chunk.xz())).ok());
+        let found_stale = read_poi_records(root.tag(), chunk).into_iter()
+            .filter_map(|record| {
+                let found = terrain_chunk.as_ref().and_then(|terrain| block_name_at(terrain.tag(), record.pos))?;
+                let accepted = poi_accepts_block(&record.poi_type, &found)?;
+                (!accepted).then_some(StalePoi { chunk, record, found })
+            })
+            .collect::<Vec<_>>();
+        let modified = fix && !found_stale.is_empty() && remove_records(root.tag_mut(), &found_stale);
+        stale.extend(found_stale);
+        Ok(modified)
+    })?;
+    Ok(stale)
+}
+
+/// Removes the records in `stale` from a POI chunk. Returns true if anything was removed.
+pub(crate) fn remove_records(root: &mut Tag, stale: &[StalePoi]) -> bool {
+    let Tag::Compound(root) = root else { return false };
+    let Some(Tag::Compound(sections)) = root.get_mut("Sections") else { return false };
+    let mut removed = false;
+    sections.values_mut().for_each(|section| {
+        let Tag::Compound(section) = section else { return };
+        let Some(Tag::List(ListTag::Compound(records))) = section.get_mut("Records") else { return };
+        let count = records.len();
+        records.retain(|record| {
+            !matches!(record.get("pos"), Some(Tag::IntArray(pos)) if stale.iter().any(|stale| {
+                pos.as_slice() == [stale.record.pos.x as i32, stale.record.pos.y as i32, stale.record.pos.z as i32]
+            }))
+        });
+        removed |= records.len() != count;
+    });
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::coord::Dimension;
+
+    #[test]
+    fn missing_terrain_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut record = Map::new();
+        record.insert("pos".to_owned(), Tag::IntArray(vec![1, 64, 2]));
+        record.insert("type".to_owned(), Tag::String("minecraft:home".to_owned()));
+        let mut section = Map::new();
+        section.insert("Records".to_owned(), Tag::List(ListTag::Compound(vec![record])));
+        let mut sections = Map::new();
+        sections.insert("4".to_owned(), Tag::Compound(section));
+        let mut root = Map::new();
+        root.insert("Sections".to_owned(), Tag::Compound(sections));
+        let coord = WorldCoord::overworld(0, 0);
+        let path = region_file_path(dir.path(), coord.region_coord(), RegionKind::Poi)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        RegionFile::create(&path)?.write_data((0, 0), &NamedTag::new(Tag::Compound(root)))?;
+
+        // There is no terrain region, so the block can't be checked and nothing is removed.
+        assert!(check_poi(dir.path(), &WorldSelection::dimension(Dimension::Overworld), true)?.is_empty());
+        let root = RegionFile::open(&path)?.read_data::<_, NamedTag>((0, 0))?;
+        assert_eq!(read_poi_records(root.tag(), coord).len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Relighting of chunks that were edited outside of the game.
+//!
+//! There is no lighting engine in this crate yet, so rather than recomputing the
+//! light arrays, [relight] clears them and marks the chunk as unlit. The game
+//! recomputes light for every chunk whose `isLightOn` flag is unset when the
+//! chunk is loaded, which removes the black patches left behind by external edits.
+
+use std::path::Path;
+
+use crate::{
+    McResult,
+    nbt::tag::{ListTag, Tag},
+};
+
+use super::{
+    scan::{for_each_chunk, RegionKind},
+    selection::WorldSelection,
+};
+
+/// Clears the light data of a chunk's root tag and unsets `isLightOn`, like
+/// [Chunk::clear_light](super::chunk::Chunk::clear_light) without decoding the chunk.
+/// Returns false if the tag is not a chunk.
+pub fn clear_light_tag(chunk: &mut Tag) -> bool {
+    let Tag::Compound(root) = chunk else {
+        return false;
+    };
+    if let Some(Tag::List(ListTag::Compound(sections))) = root.get_mut("sections") {
+        sections.iter_mut().for_each(|section| {
+            section.remove("BlockLight");
+            section.remove("SkyLight");
+        });
+    }
+    root.insert("isLightOn".to_owned(), Tag::Byte(0));
+    true
+}
+
+/// Clears the light of every selected chunk so that the game relights them
+/// when they are next loaded. Returns the number of chunks that were updated.
+pub fn relight<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection) -> McResult<usize> {
+    let mut count = 0;
+    for_each_chunk(world_directory, selection, RegionKind::Terrain, |_, root| {
+        let modified = clear_light_tag(root.tag_mut());
+        count += modified as usize;
+        Ok(modified)
+    })?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::{Dimension, WorldCoord},
+        nbt::{Map, tag::NamedTag},
+        world::{
+            blockregistry::BlockRegistry,
+            chunk::{Lighting, encode_chunk, tests::empty_chunk},
+            io::region::RegionFile,
+            scan::region_file_path,
+        },
+    };
+
+    fn light(root: &Map) -> Vec<bool> {
+        let Some(Tag::List(ListTag::Compound(sections))) = root.get("sections") else { panic!("Expected sections.") };
+        sections.iter().map(|section| section.contains_key("BlockLight") || section.contains_key("SkyLight")).collect()
+    }
+
+    #[test]
+    fn relight_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let terrain = region_file_path(dir.path(), WorldCoord::new(0, 0, Dimension::Overworld), RegionKind::Terrain)?;
+        std::fs::create_dir_all(terrain.parent().unwrap())?;
+        let registry = BlockRegistry::with_air();
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(0).skylight = Some(Lighting::from(vec![0xFFu8; 2048]));
+        chunk.sections.get_or_insert(1).blocklight = Some(Lighting::from(vec![0x11u8; 2048]));
+        chunk.other.insert("isLightOn".to_owned(), Tag::Byte(1));
+        let root = encode_chunk(&registry, &chunk);
+        assert_eq!(light(&root), [true, true]);
+        RegionFile::create(&terrain)?.write_data((0, 0), &NamedTag::new(Tag::Compound(root)))?;
+
+        assert_eq!(relight(dir.path(), &WorldSelection::dimension(Dimension::Overworld))?, 1);
+        let root: NamedTag = RegionFile::open(&terrain)?.read_data((0, 0))?;
+        let Tag::Compound(root) = root.tag() else { panic!("Expected a compound.") };
+        assert_eq!(light(root), [false, false]);
+        assert!(matches!(root.get("isLightOn"), Some(Tag::Byte(0))));
+
+        assert!(!clear_light_tag(&mut Tag::Int(0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Renaming namespaced IDs across a world, such as when a mod renames its content
+//! between versions.
+//!
+//! [remap_ids] rewrites block names in section palettes and block states held by entities
+//! (falling blocks, endermen, display entities), the IDs of block entities and entities,
+//! and the IDs of items wherever they are stored in them (containers, item entities,
+//! equipment, and items nested in components). Scheduled block and fluid ticks are
+//! remapped too. Both the 1.18+ layout and the older layout under `Level` are handled.
+//!
+//! Palette entries aren't merged when two of them end up with the same state; the game
+//! reads such palettes fine, and the next save of the chunk merges them.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+
+use crate::{
+    McResult,
+    nbt::{
+        Map,
+        tag::{ListTag, Tag},
+    },
+};
+
+use super::{
+    components::namespaced,
+    scan::{for_each_chunk, RegionKind},
+    selection::WorldSelection,
+};
+
+/// Keys of compounds that hold a block state (`Name` and `Properties`).
+const BLOCK_STATE_KEYS: [&str; 3] = ["BlockState", "carriedBlockState", "block_state"];
+
+/// An old→new mapping of namespaced IDs. IDs without a namespace are in `minecraft`.
+#[derive(Debug, Clone, Default)]
+pub struct IdMapping {
+    ids: HashMap<String, String>,
+}
+
+impl IdMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `old` to `new`, replacing the previous mapping of `old`.
+    pub fn insert<S: AsRef<str>, S2: AsRef<str>>(&mut self, old: S, new: S2) {
+        self.ids.insert(namespaced(old.as_ref()), namespaced(new.as_ref()));
+    }
+
+    /// The new ID of `old`, if it is remapped.
+    pub fn get(&self, old: &str) -> Option<&str> {
+        match self.ids.get(old) {
+            Some(new) => Some(new),
+            None if !old.contains(':') => self.ids.get(&namespaced(old)).map(String::as_str),
+            None => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Replaces `id` with its new ID. Returns true if it was remapped.
+    fn apply(&self, id: &mut String) -> bool {
+        match self.get(id) {
+            Some(new) if new != id => {
+                *id = new.to_owned();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<S: AsRef<str>, S2: AsRef<str>> FromIterator<(S, S2)> for IdMapping {
+    fn from_iter<T: IntoIterator<Item = (S, S2)>>(iter: T) -> Self {
+        let mut mapping = Self::new();
+        iter.into_iter().for_each(|(old, new)| mapping.insert(old, new));
+        mapping
+    }
+}
+
+/// The number of IDs of each kind that were replaced by [remap_ids].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemapReport {
+    /// The number of chunks that were changed.
+    pub chunks: usize,
+    /// Palette entries, block states, and scheduled ticks.
+    pub blocks: usize,
+    pub block_entities: usize,
+    /// Entities, including passengers.
+    pub entities: usize,
+    /// Every other `id`, which is usually an item.
+    pub items: usize,
+}
+
+impl RemapReport {
+    /// The number of IDs that were replaced.
+    pub fn total(&self) -> usize {
+        self.blocks + self.block_entities + self.entities + self.items
+    }
+}
+
+/// What the `id` of a compound refers to.
+#[derive(Debug, Clone, Copy)]
+enum IdKind {
+    BlockEntity,
+    Entity,
+    Item,
+}
+
+fn remap_block_state(state: &mut Map, mapping: &IdMapping, report: &mut RemapReport) {
+    if let Some(Tag::String(name)) = state.get_mut("Name") {
+        if mapping.apply(name) {
+            report.blocks += 1;
+        }
+    }
+}
+
+/// Remaps the `id` of `map` (as `kind`) and everything within it.
+fn remap_compound(map: &mut Map, kind: IdKind, mapping: &IdMapping, report: &mut RemapReport) {
+    if let Some(Tag::String(id)) = map.get_mut("id") {
+        if mapping.apply(id) {
+            match kind {
+                IdKind::BlockEntity => report.block_entities += 1,
+                IdKind::Entity => report.entities += 1,
+                IdKind::Item => report.items += 1,
+            }
+        }
+    }
+    for (key, tag) in map.iter_mut() {
+        match tag {
+            Tag::Compound(state) if BLOCK_STATE_KEYS.contains(&key.as_str()) => remap_block_state(state, mapping, report),
+            _ if key == "Passengers" => remap_tag(tag, IdKind::Entity, mapping, report),
+            _ if key != "id" => remap_tag(tag, IdKind::Item, mapping, report),
+            _ => (),
+        }
+    }
+}
+
+fn remap_tag(tag: &mut Tag, kind: IdKind, mapping: &IdMapping, report: &mut RemapReport) {
+    match tag {
+        Tag::Compound(map) => remap_compound(map, kind, mapping, report),
+        Tag::List(list) => remap_list(list, kind, mapping, report),
+        _ => (),
+    }
+}
+
+fn remap_list(list: &mut ListTag, kind: IdKind, mapping: &IdMapping, report: &mut RemapReport) {
+    match list {
+        ListTag::Compound(maps) => maps.iter_mut().for_each(|map| remap_compound(map, kind, mapping, report)),
+        ListTag::List(lists) => lists.iter_mut().for_each(|list| remap_list(list, kind, mapping, report)),
+        _ => (),
+    }
+}
+
+/// Remaps the IDs in one of the compounds that hold a chunk's data
+/// (the root, and `Level` in older chunks).
+fn remap_chunk_map(map: &mut Map, mapping: &IdMapping, report: &mut RemapReport) {
+    for sections in ["sections", "Sections"] {
+        let Some(Tag::List(ListTag::Compound(sections))) = map.get_mut(sections) else { continue };
+        for section in sections.iter_mut() {
+            let palette = match section.get_mut("block_states") {
+                Some(Tag::Compound(states)) => states.get_mut("palette"),
+                _ => section.get_mut("Palette"),
+            };
+            if let Some(Tag::List(ListTag::Compound(palette))) = palette {
+                palette.iter_mut().for_each(|state| remap_block_state(state, mapping, report));
+            }
+        }
+    }
+    for ticks in ["block_ticks", "fluid_ticks", "TileTicks", "LiquidTicks"] {
+        let Some(Tag::List(ListTag::Compound(ticks))) = map.get_mut(ticks) else { continue };
+        for tick in ticks.iter_mut() {
+            if let Some(Tag::String(id)) = tick.get_mut("i") {
+                if mapping.apply(id) {
+                    report.blocks += 1;
+                }
+            }
+        }
+    }
+    for (key, kind) in [("block_entities", IdKind::BlockEntity), ("TileEntities", IdKind::BlockEntity), ("Entities", IdKind::Entity)] {
+        if let Some(Tag::List(list)) = map.get_mut(key) {
+            remap_list(list, kind, mapping, report);
+        }
+    }
+}
+
+/// Remaps the IDs in a chunk's root tag (a terrain chunk or an entity chunk).
+/// Returns the number of IDs of each kind that were replaced (`chunks` is left at 0).
+pub fn remap_chunk(root: &mut Tag, mapping: &IdMapping) -> RemapReport {
+    let mut report = RemapReport::default();
+    if let Tag::Compound(root) = root {
+        if let Some(Tag::Compound(level)) = root.get_mut("Level") {
+            remap_chunk_map(level, mapping, &mut report);
+        }
+        remap_chunk_map(root, mapping, &mut report);
+    }
+    report
+}
+
+/// Applies `mapping` to the terrain and entity chunks in `selection`.
+/// Player data and level.dat are not changed.
+pub fn remap_ids<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection, mapping: &IdMapping) -> McResult<RemapReport> {
+    let world_directory = world_directory.as_ref();
+    let mut report = RemapReport::default();
+    if mapping.is_empty() {
+        return Ok(report);
+    }
+    [RegionKind::Terrain, RegionKind::Entities].into_iter().try_for_each(|kind| {
+        for_each_chunk(world_directory, selection, kind, |_, root| {
+            let chunk = remap_chunk(root.tag_mut(), mapping);
+            let modified = chunk.total() > 0;
+            report.chunks += modified as usize;
+            report.blocks += chunk.blocks;
+            report.block_entities += chunk.block_entities;
+            report.entities += chunk.entities;
+            report.items += chunk.items;
+            Ok(modified)
+        })
+    })?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::{Dimension, WorldCoord},
+        nbt::{tag::NamedTag, tagpath::TagPath, tagref::ValueRef},
+        world::{
+            io::region::RegionFile,
+            scan::region_file_path,
+        },
+    };
+
+    fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Map {
+        entries.into_iter().map(|(key, tag)| (key.to_owned(), tag)).collect()
+    }
+
+    fn item(id: &str) -> Map {
+        compound([("id", Tag::string(id)), ("count", Tag::Int(1))])
+    }
+
+    #[test]
+    fn remap_ids_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let overworld = WorldCoord::new(0, 0, Dimension::Overworld);
+        let terrain = compound([
+            ("sections", Tag::List(ListTag::Compound(vec![compound([
+                ("block_states", Tag::Compound(compound([
+                    ("palette", Tag::List(ListTag::Compound(vec![
+                        compound([("Name", Tag::string("minecraft:air"))]),
+                        compound([("Name", Tag::string("oldmod:ore"))]),
+                    ]))),
+                ]))),
+            ])]))),
+            ("block_entities", Tag::List(ListTag::Compound(vec![compound([
+                ("id", Tag::string("oldmod:crate")),
+                ("Items", Tag::List(ListTag::Compound(vec![item("oldmod:gem"), item("minecraft:stone")]))),
+            ])]))),
+            ("block_ticks", Tag::List(ListTag::Compound(vec![compound([("i", Tag::string("oldmod:ore"))])]))),
+        ]);
+        let entities = compound([
+            ("Entities", Tag::List(ListTag::Compound(vec![compound([
+                ("id", Tag::string("oldmod:golem")),
+                ("HandItems", Tag::List(ListTag::Compound(vec![item("oldmod:gem")]))),
+                ("carriedBlockState", Tag::Compound(compound([("Name", Tag::string("oldmod:ore"))]))),
+                ("Passengers", Tag::List(ListTag::Compound(vec![compound([("id", Tag::string("oldmod:golem"))])]))),
+            ])]))),
+        ]);
+        for (kind, root) in [(RegionKind::Terrain, terrain), (RegionKind::Entities, entities)] {
+            let path = region_file_path(dir.path(), overworld, kind)?;
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &NamedTag::new(Tag::Compound(root)))?;
+        }
+
+        let mapping = IdMapping::from_iter([("oldmod:ore", "newmod:ore"), ("oldmod:gem", "newmod:gem"), ("oldmod:crate", "newmod:crate"), ("oldmod:golem", "newmod:golem")]);
+        assert_eq!(mapping.get("stone"), None);
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        let report = remap_ids(dir.path(), &selection, &mapping)?;
+        assert_eq!(report, RemapReport { chunks: 2, blocks: 3, block_entities: 1, entities: 2, items: 2 });
+
+        let read = |kind: RegionKind, path: &str| -> McResult<Option<String>> {
+            let mut region = RegionFile::open(region_file_path(dir.path(), overworld, kind)?)?;
+            let root: NamedTag = region.read_data((0, 0))?;
+            Ok(match root.tag().find_child(TagPath::parse(path).unwrap().path()) {
+                Some(ValueRef::String(value)) => Some(value.to_owned()),
+                _ => None,
+            })
+        };
+        assert_eq!(read(RegionKind::Terrain, "sections[0].block_states.palette[1].Name")?.as_deref(), Some("newmod:ore"));
+        assert_eq!(read(RegionKind::Terrain, "block_entities[0].Items[0].id")?.as_deref(), Some("newmod:gem"));
+        assert_eq!(read(RegionKind::Terrain, "block_entities[0].Items[1].id")?.as_deref(), Some("minecraft:stone"));
+        assert_eq!(read(RegionKind::Entities, "Entities[0].Passengers[0].id")?.as_deref(), Some("newmod:golem"));
+        assert_eq!(remap_ids(dir.path(), &selection, &mapping)?, RemapReport::default());
+        Ok(())
+    }
+}
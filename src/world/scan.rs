@@ -0,0 +1,326 @@
+//! Helpers for walking over every region file and chunk of a world on disk.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    McError, McResult, McResultExt,
+    math::coord::{Dimension, WorldCoord},
+    nbt::tag::NamedTag,
+};
+
+use super::{
+    io::region::{
+        RegionExtension,
+        RegionFileName,
+        RegionFormatExt,
+        open_region,
+        CompressionScheme,
+        coord::RegionCoord,
+        timestamp::Timestamp,
+    },
+    selection::WorldSelection,
+};
+
+/// The kinds of region files stored for each dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RegionKind {
+    /// Terrain data (`region/`).
+    Terrain,
+    /// Entity data (`entities/`), used since Minecraft 1.17.
+    Entities,
+    /// Points of interest (`poi/`).
+    Poi,
+}
+
+impl RegionKind {
+    pub const ALL: [RegionKind; 3] = [RegionKind::Terrain, RegionKind::Entities, RegionKind::Poi];
+
+    /// The name of the folder that this kind of region file is stored in.
+    pub fn folder(self) -> &'static str {
+        match self {
+            RegionKind::Terrain => "region",
+            RegionKind::Entities => "entities",
+            RegionKind::Poi => "poi",
+        }
+    }
+}
+
+/// Gets the directory where the data for `dimension` is stored within the world directory.
+pub fn dimension_directory<P: AsRef<Path>>(world_directory: P, dimension: Dimension) -> McResult<PathBuf> {
+    let world_directory = world_directory.as_ref();
+    match dimension {
+        Dimension::Overworld => Ok(world_directory.to_owned()),
+        Dimension::Nether => Ok(world_directory.join("DIM-1")),
+        Dimension::TheEnd => Ok(world_directory.join("DIM1")),
+        Dimension::Other(id) => McError::custom(format!("No known directory for dimension {id}.")),
+    }
+}
+
+/// Gets the path of the region file of a kind for the region at `region` (in region coordinates).
+pub fn region_file_path<P: AsRef<Path>>(world_directory: P, region: WorldCoord, kind: RegionKind) -> McResult<PathBuf> {
+    Ok(dimension_directory(world_directory, region.dimension)?
+        .join(kind.folder())
+        .join(RegionFileName::anvil(region.x, region.z).file_name()))
+}
+
+/// Lists the (Anvil) region files of a kind for a dimension, sorted by region coordinate.
+/// Names are matched with [RegionFileName], so differences in case are allowed.
+/// Returns an empty list if the directory does not exist.
+pub fn region_files<P: AsRef<Path>>(world_directory: P, dimension: Dimension, kind: RegionKind) -> McResult<Vec<(WorldCoord, PathBuf)>> {
+    let directory = dimension_directory(world_directory, dimension)?.join(kind.folder());
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = std::fs::read_dir(directory)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = RegionFileName::parse(entry.file_name().to_str()?)
+                .filter(|name| name.format == RegionExtension::Anvil)?;
+            Some((WorldCoord::new(name.x, name.z, dimension), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// A chunk (or region file) that failed during a scan with a [Quarantine].
+#[derive(Debug)]
+pub struct QuarantinedChunk {
+    pub region_file: PathBuf,
+    /// The chunk that failed, or `None` if the region file couldn't be opened.
+    pub chunk: Option<WorldCoord>,
+    pub error: McError,
+    /// Where the stored bytes of the chunk were dumped, if they were.
+    pub dump: Option<PathBuf>,
+    /// The scheme that the chunk's payload appears to be compressed with, if that is not
+    /// what its scheme byte says (see [CompressionScheme::detect]). This usually means
+    /// that the scheme byte was corrupted, and the chunk can be recovered by rewriting it.
+    pub detected_scheme: Option<CompressionScheme>,
+}
+
+/// Collects the chunks that fail during a scan, so that one corrupt chunk doesn't end the scan.
+///
+/// With a dump directory, the stored (still compressed) bytes of each failed chunk in
+/// an Anvil or MCRegion file are written to `<region file name>.<chunk x>.<chunk z>.bin`
+/// in that directory, for later inspection or repair.
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    dump_directory: Option<PathBuf>,
+    entries: Vec<QuarantinedChunk>,
+}
+
+impl Quarantine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a quarantine that dumps the stored bytes of failed chunks to `directory`.
+    pub fn with_dump_directory<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            dump_directory: Some(directory.as_ref().to_owned()),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[QuarantinedChunk] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<QuarantinedChunk> {
+        self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of distinct region files with failures.
+    pub fn region_file_count(&self) -> usize {
+        let mut files = self.entries.iter().map(|entry| &entry.region_file).collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files.len()
+    }
+
+    fn add(&mut self, region_file: &Path, chunk: Option<(WorldCoord, RegionCoord)>, error: McError) {
+        // Reading and dumping the stored bytes is best effort; the original error is what gets reported.
+        let stored = chunk.and_then(|(_, local)| read_stored_chunk(region_file, local).ok().flatten());
+        let dump = match (&self.dump_directory, chunk, &stored) {
+            (Some(directory), Some((coord, _)), Some(stored)) => {
+                let name = region_file.file_name().unwrap_or_default().to_string_lossy();
+                let path = directory.join(format!("{name}.{}.{}.bin", coord.x, coord.z));
+                std::fs::create_dir_all(directory)
+                    .and_then(|_| std::fs::write(&path, stored))
+                    .ok()
+                    .map(|_| path)
+            }
+            _ => None,
+        };
+        // The stored bytes are the length (4 bytes), the scheme byte, then the payload.
+        let detected_scheme = stored.as_ref()
+            .filter(|stored| stored.len() > 5)
+            .and_then(|stored| CompressionScheme::detect(&stored[5..]).filter(|scheme| scheme.as_u8() != stored[4]));
+        self.entries.push(QuarantinedChunk {
+            region_file: region_file.to_owned(),
+            chunk: chunk.map(|(coord, _)| coord),
+            error,
+            dump,
+            detected_scheme,
+        });
+    }
+}
+
+/// A summary of the failures, one per line after a count.
+impl std::fmt::Display for Quarantine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} failure(s) in {} region file(s).", self.len(), self.region_file_count())?;
+        self.entries.iter().try_for_each(|entry| {
+            match entry.chunk {
+                Some(chunk) => write!(f, "{} chunk ({}, {}): {}", entry.region_file.display(), chunk.x, chunk.z, entry.error)?,
+                None => write!(f, "{}: {}", entry.region_file.display(), entry.error)?,
+            }
+            if let Some(scheme) = entry.detected_scheme {
+                write!(f, " (looks like {scheme})")?;
+            }
+            if let Some(dump) = &entry.dump {
+                write!(f, " (dumped to {})", dump.display())?;
+            }
+            writeln!(f)
+        })
+    }
+}
+
+/// Reads the stored bytes (sectors) of the chunk at `coord` straight from an Anvil or MCRegion
+/// file, without going through [open_region] (which may be what failed).
+/// Returns `None` for other formats or if there is nothing stored.
+fn read_stored_chunk(region_file: &Path, coord: RegionCoord) -> McResult<Option<Vec<u8>>> {
+    use std::io::{Read, Seek, SeekFrom};
+    if !matches!(RegionExtension::of_path(region_file), Some(RegionExtension::Anvil | RegionExtension::McRegion)) {
+        return Ok(None);
+    }
+    let mut file = std::fs::File::open(region_file)?;
+    let mut location = [0u8; 4];
+    file.seek(SeekFrom::Start(coord.index() as u64 * 4))?;
+    file.read_exact(&mut location)?;
+    let offset = u32::from_be_bytes([0, location[0], location[1], location[2]]) as u64 * 4096;
+    let size = location[3] as u64 * 4096;
+    if offset == 0 || size == 0 {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(size).read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Visits every selected chunk of a kind, in order of region coordinate and then chunk index.
+/// `visit` is given the chunk coordinate and the root tag of the chunk, and returns true if the
+/// chunk was modified and should be written back to the region file.
+/// Errors are wrapped with the region file and chunk that they happened in
+/// (see [McError::region_file] and [McError::chunk]).
+pub fn for_each_chunk<P, F>(world_directory: P, selection: &WorldSelection, kind: RegionKind, visit: F) -> McResult<()>
+where
+P: AsRef<Path>,
+F: FnMut(WorldCoord, &mut NamedTag) -> McResult<bool> {
+    for_each_chunk_with(world_directory, selection, kind, None, visit)
+}
+
+/// Like [for_each_chunk], but with a [Quarantine], a region file that fails to open or
+/// a chunk that fails (to be read, visited, or written back) is added to the quarantine
+/// and the scan continues. Errors listing the region files still end the scan.
+pub fn for_each_chunk_with<P, F>(world_directory: P, selection: &WorldSelection, kind: RegionKind, mut quarantine: Option<&mut Quarantine>, mut visit: F) -> McResult<()>
+where
+P: AsRef<Path>,
+F: FnMut(WorldCoord, &mut NamedTag) -> McResult<bool> {
+    let files = region_files(world_directory, selection.dimension, kind)?;
+    files.into_iter()
+        .filter(|(region, _)| selection.contains_region(*region))
+        .try_for_each(|(region, path)| {
+            let mut regionfile = match (open_region(&path), quarantine.as_deref_mut()) {
+                (Ok(regionfile), _) => regionfile,
+                (Err(err), Some(quarantine)) => {
+                    quarantine.add(&path, None, err);
+                    return Ok(());
+                }
+                (Err(err), None) => return Err(err.with_region(&path)),
+            };
+            (0..1024u16).map(|index| RegionCoord::new(index & 31, index >> 5))
+                .try_for_each(|coord| -> McResult<()> {
+                    let chunk = WorldCoord::new(region.x * 32 + coord.x() as i64, region.z * 32 + coord.z() as i64, region.dimension);
+                    if !regionfile.has_chunk(coord) || !selection.contains(chunk) {
+                        return Ok(());
+                    }
+                    let result = regionfile.read_chunk(coord).and_then(|mut root: NamedTag| {
+                        if visit(chunk, &mut root)? {
+                            regionfile.write_chunk(coord, &root, Timestamp::utc_now())?;
+                        }
+                        Ok(())
+                    });
+                    match (result, quarantine.as_deref_mut()) {
+                        (Err(err), Some(quarantine)) => {
+                            quarantine.add(&path, Some((chunk, coord)), err);
+                            Ok(())
+                        }
+                        (result, _) => result.with_chunk(chunk).with_region(&path),
+                    }
+                })?;
+            regionfile.flush()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::tag::Tag,
+        world::io::region::RegionFile,
+    };
+
+    #[test]
+    fn quarantine_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        let path = region_file_path(world, WorldCoord::new(0, 0, Dimension::Overworld), RegionKind::Terrain)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let sector = {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &NamedTag::new(Tag::Int(1)))?;
+            region.write_data((1, 0), &NamedTag::new(Tag::Int(2)))?
+        };
+        // Corrupt the compressed data of (1, 0).
+        let mut bytes = std::fs::read(&path)?;
+        bytes[sector.offset() as usize + 5..sector.offset() as usize + 12].fill(0xFF);
+        std::fs::write(&path, bytes)?;
+
+        let selection = WorldSelection::dimension(Dimension::Overworld);
+        assert!(for_each_chunk(world, &selection, RegionKind::Terrain, |_, _| Ok(false)).is_err());
+        let mut quarantine = Quarantine::with_dump_directory(world.join("quarantine"));
+        let mut visited = Vec::new();
+        for_each_chunk_with(world, &selection, RegionKind::Terrain, Some(&mut quarantine), |chunk, _| {
+            visited.push(chunk);
+            Ok(false)
+        })?;
+        assert_eq!(visited, vec![WorldCoord::new(0, 0, Dimension::Overworld)]);
+        assert_eq!(quarantine.len(), 1);
+        let entry = &quarantine.entries()[0];
+        assert_eq!(entry.chunk, Some(WorldCoord::new(1, 0, Dimension::Overworld)));
+        assert_eq!(std::fs::read(entry.dump.as_ref().unwrap())?.len() as u64, sector.size());
+        assert!(entry.detected_scheme.is_none());
+        assert!(quarantine.to_string().starts_with("1 failure(s) in 1 region file(s)."));
+
+        // A corrupted scheme byte is detected.
+        let sector = RegionFile::open_or_create(&path)?.write_data((1, 0), &NamedTag::new(Tag::Int(2)))?;
+        let mut bytes = std::fs::read(&path)?;
+        bytes[sector.offset() as usize + 4] = 9;
+        std::fs::write(&path, bytes)?;
+        let mut quarantine = Quarantine::new();
+        for_each_chunk_with(world, &selection, RegionKind::Terrain, Some(&mut quarantine), |_, _| Ok(false))?;
+        assert!(matches!(quarantine.entries()[0].error, McError::InvalidCompressionScheme(9)));
+        assert_eq!(quarantine.entries()[0].detected_scheme, Some(CompressionScheme::ZLib));
+        Ok(())
+    }
+}
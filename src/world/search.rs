@@ -0,0 +1,297 @@
+//! High-level queries over the players and items in a world.
+//!
+//! [find_item] searches player data (inventories and ender chests), block
+//! entities in terrain chunks (chests, barrels, etc.), and entities (item
+//! entities, item frames, minecarts with chests). Items stored inside of
+//! other items, such as the contents of a shulker box, are searched as well.
+
+use std::path::{Path, PathBuf};
+
+use glam::DVec3;
+
+use crate::{
+    McResult,
+    math::coord::{Dimension, WorldCoord},
+    nbt::{
+        Map,
+        file::read_nbt_file,
+        tag::{ListTag, Tag},
+        tagpath::{TagPath, TagPathPart},
+    },
+};
+
+use super::{
+    scan::{for_each_chunk, region_file_path, RegionKind},
+    selection::WorldSelection,
+};
+
+/// The dimensions that are searched.
+const DIMENSIONS: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::TheEnd];
+
+/// Keys whose value is an item stack, or a list of item stacks.
+/// This is used to recognize item stacks that don't have a count.
+const ITEM_KEYS: [&str; 7] = ["Items", "Inventory", "EnderItems", "HandItems", "ArmorItems", "Item", "item"];
+
+/// A player found by [find_players].
+#[derive(Debug, Clone)]
+pub struct PlayerInfo {
+    /// The UUID of the player, taken from the name of the player data file.
+    pub uuid: String,
+    pub file: PathBuf,
+    /// The dimension that the player is in, if it is known.
+    pub dimension: Option<Dimension>,
+    pub position: Option<DVec3>,
+}
+
+/// An item stack found by [find_item].
+#[derive(Debug, Clone)]
+pub struct ItemHit {
+    /// The file that the item is stored in.
+    pub file: PathBuf,
+    /// The chunk that the item is stored in, or `None` for items in player data.
+    pub chunk: Option<WorldCoord>,
+    /// The position of the nearest holder of the item (a block entity, entity, or player).
+    pub position: Option<DVec3>,
+    /// The path of the item stack from the root of the file or chunk.
+    pub path: TagPath,
+}
+
+/// Lists the player data files of a world (`playerdata/<uuid>.dat`), sorted by name.
+fn player_files(world_directory: &Path) -> McResult<Vec<PathBuf>> {
+    let directory = world_directory.join("playerdata");
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = std::fs::read_dir(directory)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            (path.extension()? == "dat").then_some(path)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a position from the `Pos` list of an entity, or the `x`/`y`/`z` of a block entity.
+pub(crate) fn read_position(map: &Map) -> Option<DVec3> {
+    if let Some(Tag::List(ListTag::Double(pos))) = map.get("Pos") {
+        if let [x, y, z] = pos.as_slice() {
+            return Some(DVec3::new(*x, *y, *z));
+        }
+    }
+    match (map.get("x"), map.get("y"), map.get("z")) {
+        (Some(Tag::Int(x)), Some(Tag::Int(y)), Some(Tag::Int(z))) => Some(DVec3::new(*x as f64, *y as f64, *z as f64)),
+        _ => None,
+    }
+}
+
+/// Reads the dimension of a player, which is stored as a name since 1.16 and as an ID before that.
+fn read_dimension(tag: &Tag) -> Option<Dimension> {
+    match tag {
+        Tag::String(name) => match name.as_str() {
+            "minecraft:overworld" => Some(Dimension::Overworld),
+            "minecraft:the_nether" => Some(Dimension::Nether),
+            "minecraft:the_end" => Some(Dimension::TheEnd),
+            _ => None,
+        },
+        Tag::Int(0) => Some(Dimension::Overworld),
+        Tag::Int(-1) => Some(Dimension::Nether),
+        Tag::Int(1) => Some(Dimension::TheEnd),
+        _ => None,
+    }
+}
+
+/// Returns true if `map` is an item stack with the ID `item_id`.
+/// `key` is the key of the nearest compound that the item is stored under.
+fn is_item(map: &Map, key: Option<&str>, item_id: &str) -> bool {
+    let Some(Tag::String(id)) = map.get("id") else {
+        return false;
+    };
+    id == item_id && (
+        map.contains_key("Count")
+        || map.contains_key("count")
+        || key.is_some_and(|key| ITEM_KEYS.contains(&key))
+    )
+}
+
+/// Visits every compound in `tag` (including `tag` itself), depth first with keys in sorted order.
+/// `visit` is given the compound, the key of the nearest compound that it is stored under,
+/// the position of its nearest holder (see [ItemHit::position]), and its path from `tag`.
+pub(crate) fn visit_compounds<F: FnMut(&Map, Option<&str>, Option<DVec3>, &[TagPathPart])>(tag: &Tag, mut visit: F) {
+    visit_tag(tag, None, None, &mut Vec::new(), &mut visit);
+}
+
+fn visit_tag<F: FnMut(&Map, Option<&str>, Option<DVec3>, &[TagPathPart])>(
+    tag: &Tag,
+    key: Option<&str>,
+    position: Option<DVec3>,
+    path: &mut Vec<TagPathPart>,
+    visit: &mut F,
+) {
+    match tag {
+        Tag::Compound(map) => visit_compound(map, key, position, path, visit),
+        Tag::List(ListTag::Compound(list)) => {
+            list.iter().enumerate().for_each(|(index, map)| {
+                path.push(TagPathPart::AtIndex(index as i64));
+                visit_compound(map, key, position, path, visit);
+                path.pop();
+            });
+        },
+        _ => (),
+    }
+}
+
+fn visit_compound<F: FnMut(&Map, Option<&str>, Option<DVec3>, &[TagPathPart])>(
+    map: &Map,
+    key: Option<&str>,
+    position: Option<DVec3>,
+    path: &mut Vec<TagPathPart>,
+    visit: &mut F,
+) {
+    let position = read_position(map).or(position);
+    visit(map, key, position, path);
+    let mut keys = map.keys().collect::<Vec<_>>();
+    keys.sort();
+    keys.into_iter().for_each(|child_key| {
+        path.push(TagPathPart::AtKey(child_key.to_owned()));
+        visit_tag(&map[child_key], Some(child_key), position, path, visit);
+        path.pop();
+    });
+}
+
+/// Searches `tag` and all of its children for items with the ID `item_id`.
+fn collect_items<F: FnMut(Option<DVec3>, TagPath)>(tag: &Tag, item_id: &str, mut found: F) {
+    visit_compounds(tag, |map, key, position, path| {
+        if is_item(map, key, item_id) {
+            found(position, TagPath(path.to_vec()));
+        }
+    });
+}
+
+/// Finds every player that has a player data file in the world.
+pub fn find_players<P: AsRef<Path>>(world_directory: P) -> McResult<Vec<PlayerInfo>> {
+    player_files(world_directory.as_ref())?.into_iter().map(|file| {
+        let root = read_nbt_file(&file)?;
+        let (dimension, position) = match root.tag() {
+            Tag::Compound(map) => (map.get("Dimension").and_then(read_dimension), read_position(map)),
+            _ => (None, None),
+        };
+        Ok(PlayerInfo {
+            uuid: file.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            file,
+            dimension,
+            position,
+        })
+    }).collect()
+}
+
+/// Finds every stack of the item `item_id` (such as `"minecraft:elytra"`) in the world.
+/// Player data is searched first, followed by the terrain and entity chunks of each dimension.
+pub fn find_item<P: AsRef<Path>>(world_directory: P, item_id: &str) -> McResult<Vec<ItemHit>> {
+    let world_directory = world_directory.as_ref();
+    let mut hits = Vec::new();
+    player_files(world_directory)?.into_iter().try_for_each(|file| {
+        let root = read_nbt_file(&file)?;
+        collect_items(root.tag(), item_id, |position, path| {
+            hits.push(ItemHit { file: file.clone(), chunk: None, position, path });
+        });
+        McResult::Ok(())
+    })?;
+    DIMENSIONS.into_iter().try_for_each(|dimension| {
+        let selection = WorldSelection::dimension(dimension);
+        [RegionKind::Terrain, RegionKind::Entities].into_iter().try_for_each(|kind| {
+            for_each_chunk(world_directory, &selection, kind, |chunk, root| {
+                let region = WorldCoord::new(chunk.x.div_euclid(32), chunk.z.div_euclid(32), chunk.dimension);
+                let file = region_file_path(world_directory, region, kind)?;
+                collect_items(root.tag(), item_id, |position, path| {
+                    hits.push(ItemHit { file: file.clone(), chunk: Some(chunk), position, path });
+                });
+                Ok(false)
+            })
+        })
+    })?;
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use crate::{
+        nbt::{file::write_nbt_file, tag::NamedTag},
+        world::io::region::RegionFile,
+    };
+
+    fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Map {
+        entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+    }
+
+    fn stack(id: &str, slot: i8) -> Map {
+        compound([("id", Tag::String(id.to_owned())), ("Count", Tag::Byte(1)), ("Slot", Tag::Byte(slot))])
+    }
+
+    #[test]
+    fn find_item_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        std::fs::create_dir_all(world.join("playerdata"))?;
+        std::fs::create_dir_all(world.join("region"))?;
+        std::fs::create_dir_all(world.join("entities"))?;
+
+        let mut shulker = stack("minecraft:shulker_box", 1);
+        shulker.insert("tag".to_owned(), Tag::Compound(compound([
+            ("BlockEntityTag", Tag::Compound(compound([
+                ("Items", Tag::List(ListTag::Compound(vec![stack("minecraft:elytra", 0)]))),
+            ]))),
+        ])));
+        let player = Tag::Compound(compound([
+            ("Dimension", Tag::String("minecraft:the_nether".to_owned())),
+            ("Pos", Tag::List(ListTag::Double(vec![1.5, 64.0, -2.5]))),
+            ("Inventory", Tag::List(ListTag::Compound(vec![stack("minecraft:stone", 0), shulker]))),
+            ("EnderItems", Tag::List(ListTag::Compound(vec![stack("minecraft:elytra", 3)]))),
+        ]));
+        write_nbt_file(world.join("playerdata/player.dat"), &NamedTag::new(player), Compression::default())?;
+
+        let chest = compound([
+            ("id", Tag::String("minecraft:chest".to_owned())),
+            ("x", Tag::Int(40)), ("y", Tag::Int(70)), ("z", Tag::Int(8)),
+            ("Items", Tag::List(ListTag::Compound(vec![stack("minecraft:elytra", 5)]))),
+        ]);
+        let terrain = Tag::Compound(compound([
+            ("block_entities", Tag::List(ListTag::Compound(vec![chest]))),
+        ]));
+        RegionFile::create(world.join("region/r.0.0.mca"))?.write_data((2, 0), &NamedTag::new(terrain))?;
+
+        let item_entity = compound([
+            ("id", Tag::String("minecraft:item".to_owned())),
+            ("Pos", Tag::List(ListTag::Double(vec![-10.0, 5.0, -20.0]))),
+            ("Item", Tag::Compound(compound([("id", Tag::String("minecraft:elytra".to_owned())), ("count", Tag::Int(1))]))),
+        ]);
+        let entities = Tag::Compound(compound([
+            ("Entities", Tag::List(ListTag::Compound(vec![item_entity]))),
+        ]));
+        RegionFile::create(world.join("entities/r.-1.-1.mca"))?.write_data((31, 30), &NamedTag::new(entities))?;
+
+        let players = find_players(world)?;
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].uuid, "player");
+        assert_eq!(players[0].dimension, Some(Dimension::Nether));
+        assert_eq!(players[0].position, Some(DVec3::new(1.5, 64.0, -2.5)));
+
+        let hits = find_item(world, "minecraft:elytra")?;
+        let paths = hits.iter().map(|hit| hit.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths, vec![
+            TagPath::parse("EnderItems[0]").unwrap(),
+            TagPath::parse("Inventory[1].tag.BlockEntityTag.Items[0]").unwrap(),
+            TagPath::parse("block_entities[0].Items[0]").unwrap(),
+            TagPath::parse("Entities[0].Item").unwrap(),
+        ]);
+        assert!(hits[0].chunk.is_none());
+        assert_eq!(hits[2].chunk, Some(WorldCoord::new(2, 0, Dimension::Overworld)));
+        assert_eq!(hits[2].position, Some(DVec3::new(40.0, 70.0, 8.0)));
+        assert_eq!(hits[3].file, world.join("entities/r.-1.-1.mca"));
+        assert_eq!(hits[3].chunk, Some(WorldCoord::new(-1, -2, Dimension::Overworld)));
+        assert_eq!(hits[3].position, Some(DVec3::new(-10.0, 5.0, -20.0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use glam::i64vec2;
+
+use crate::math::{
+    bounds::Bounds2,
+    coord::{Dimension, WorldCoord},
+};
+
+/// A selection of chunks within a single dimension.
+/// This is what world-wide operations (searching, replacing, etc.) operate on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSelection {
+    pub dimension: Dimension,
+    /// The (inclusive) bounds of the selection in chunk coordinates.
+    /// `None` selects every chunk in the dimension.
+    pub chunks: Option<Bounds2>,
+}
+
+impl WorldSelection {
+    /// Selects every chunk in a dimension.
+    pub fn dimension(dimension: Dimension) -> Self {
+        Self {
+            dimension,
+            chunks: None,
+        }
+    }
+
+    /// Selects the chunks within `bounds` (in chunk coordinates).
+    pub fn area<T: Into<Bounds2>>(dimension: Dimension, bounds: T) -> Self {
+        Self {
+            dimension,
+            chunks: Some(bounds.into()),
+        }
+    }
+
+    /// Returns true if the chunk at `coord` is selected.
+    pub fn contains(&self, coord: WorldCoord) -> bool {
+        if coord.dimension != self.dimension {
+            return false;
+        }
+        let Some(bounds) = self.chunks else {
+            return true;
+        };
+        (bounds.min.x..=bounds.max.x).contains(&coord.x)
+        && (bounds.min.y..=bounds.max.y).contains(&coord.z)
+    }
+
+    /// Returns true if any chunk within the region at `region` (in region coordinates) is selected.
+    pub fn contains_region(&self, region: WorldCoord) -> bool {
+        if region.dimension != self.dimension {
+            return false;
+        }
+        let Some(bounds) = self.chunks else {
+            return true;
+        };
+        let region_bounds = Bounds2::new(
+            i64vec2(region.x * 32, region.z * 32),
+            i64vec2(region.x * 32 + 31, region.z * 32 + 31),
+        );
+        region_bounds.min.x <= bounds.max.x
+        && region_bounds.max.x >= bounds.min.x
+        && region_bounds.min.y <= bounds.max.y
+        && region_bounds.max.y >= bounds.min.y
+    }
+}
+
+impl From<Dimension> for WorldSelection {
+    fn from(value: Dimension) -> Self {
+        Self::dimension(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_test() {
+        let all = WorldSelection::from(Dimension::Overworld);
+        assert!(all.contains(WorldCoord::new(-1000, 1000, Dimension::Overworld)));
+        assert!(!all.contains(WorldCoord::new(0, 0, Dimension::Nether)));
+        assert!(all.contains_region(WorldCoord::new(-5, 7, Dimension::Overworld)));
+
+        // Chunks -2..=33 in x and 0..=3 in z.
+        let area = WorldSelection::area(Dimension::Nether, ((-2, 0), (33, 3)));
+        assert!(area.contains(WorldCoord::new(-2, 0, Dimension::Nether)));
+        assert!(area.contains(WorldCoord::new(33, 3, Dimension::Nether)));
+        assert!(!area.contains(WorldCoord::new(34, 3, Dimension::Nether)));
+        assert!(!area.contains(WorldCoord::new(0, -1, Dimension::Nether)));
+        assert!(!area.contains(WorldCoord::new(0, 0, Dimension::Overworld)));
+        // Regions -1, 0, and 1 overlap the area in x, and only region 0 in z.
+        assert!(area.contains_region(WorldCoord::new(-1, 0, Dimension::Nether)));
+        assert!(area.contains_region(WorldCoord::new(1, 0, Dimension::Nether)));
+        assert!(!area.contains_region(WorldCoord::new(2, 0, Dimension::Nether)));
+        assert!(!area.contains_region(WorldCoord::new(0, 1, Dimension::Nether)));
+        assert!(!area.contains_region(WorldCoord::new(0, -1, Dimension::Nether)));
+        assert!(!area.contains_region(WorldCoord::new(0, 0, Dimension::Overworld)));
+    }
+}
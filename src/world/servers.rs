@@ -0,0 +1,199 @@
+//! The multiplayer server list, stored in `servers.dat` in the game directory
+//! (`.minecraft`, not a world directory). Unlike most NBT files, it is uncompressed.
+
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        file::{read_nbt_file, write_nbt_file},
+        tag::{ListTag, NamedTag, Tag},
+    },
+};
+
+/// The name of the server list file within the game directory.
+pub const SERVERS_DAT: &str = "servers.dat";
+
+/// Whether the game uses a server's resource pack (`acceptTextures`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ResourcePackPolicy {
+    /// Ask the player when joining (no `acceptTextures`).
+    #[default]
+    Prompt,
+    Enabled,
+    Disabled,
+}
+
+/// An entry of the server list.
+#[derive(Debug, Clone)]
+pub struct ServerEntry {
+    /// The name shown in the list.
+    pub name: String,
+    /// The address, with an optional port (`example.com:25566`).
+    pub ip: String,
+    /// The server's icon, a base64 encoded PNG saved from the last ping.
+    pub icon: Option<String>,
+    pub resource_packs: ResourcePackPolicy,
+    /// Hidden entries (such as servers joined with Quick Play) aren't shown in the list.
+    pub hidden: bool,
+    /// Other tags of the entry, which are written back as they were read.
+    other: Map,
+}
+
+impl ServerEntry {
+    pub fn new<S: Into<String>, S2: Into<String>>(name: S, ip: S2) -> Self {
+        Self {
+            name: name.into(),
+            ip: ip.into(),
+            icon: None,
+            resource_packs: ResourcePackPolicy::Prompt,
+            hidden: false,
+            other: Map::new(),
+        }
+    }
+
+    fn decode(mut map: Map) -> Self {
+        let mut string = |key: &str| match map.remove(key) {
+            Some(Tag::String(value)) => Some(value),
+            _ => None,
+        };
+        let name = string("name").unwrap_or_default();
+        let ip = string("ip").unwrap_or_default();
+        let icon = string("icon");
+        let resource_packs = match map.remove("acceptTextures") {
+            Some(Tag::Byte(0)) => ResourcePackPolicy::Disabled,
+            Some(Tag::Byte(_)) => ResourcePackPolicy::Enabled,
+            _ => ResourcePackPolicy::Prompt,
+        };
+        let hidden = matches!(map.remove("hidden"), Some(Tag::Byte(hidden)) if hidden != 0);
+        Self { name, ip, icon, resource_packs, hidden, other: map }
+    }
+
+    fn encode(&self) -> Map {
+        let mut map = self.other.clone();
+        map.insert("name".to_owned(), Tag::string(self.name.as_str()));
+        map.insert("ip".to_owned(), Tag::string(self.ip.as_str()));
+        if let Some(icon) = &self.icon {
+            map.insert("icon".to_owned(), Tag::string(icon.as_str()));
+        }
+        match self.resource_packs {
+            ResourcePackPolicy::Prompt => {}
+            ResourcePackPolicy::Enabled => { map.insert("acceptTextures".to_owned(), Tag::Byte(1)); }
+            ResourcePackPolicy::Disabled => { map.insert("acceptTextures".to_owned(), Tag::Byte(0)); }
+        }
+        if self.hidden {
+            map.insert("hidden".to_owned(), Tag::Byte(1));
+        }
+        map
+    }
+}
+
+/// The contents of `servers.dat`, in the order that the game shows them.
+#[derive(Debug, Clone, Default)]
+pub struct ServerList {
+    pub servers: Vec<ServerEntry>,
+    /// The root of the file, which is kept so that unknown data is written back.
+    root: Map,
+}
+
+impl ServerList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the path of `servers.dat` within the game directory.
+    pub fn path<P: AsRef<Path>>(game_directory: P) -> PathBuf {
+        game_directory.as_ref().join(SERVERS_DAT)
+    }
+
+    /// Reads a server list file. If there is no file, the list is empty.
+    pub fn load<P: AsRef<Path>>(path: P) -> McResult<Self> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(Self::new());
+        }
+        let Tag::Compound(mut root) = read_nbt_file(path)?.take_tag() else {
+            return Err(McError::NbtDecodeError);
+        };
+        let servers = match root.remove("servers") {
+            Some(Tag::List(ListTag::Compound(servers))) => servers.into_iter().map(ServerEntry::decode).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Self { servers, root })
+    }
+
+    /// Writes the server list (uncompressed, as the game does).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> McResult<()> {
+        let mut root = self.root.clone();
+        let servers = self.servers.iter().map(ServerEntry::encode).collect::<Vec<_>>();
+        root.insert("servers".to_owned(), Tag::List(if servers.is_empty() { ListTag::Empty } else { ListTag::Compound(servers) }));
+        write_nbt_file(path, &NamedTag::new(root), Compression::none())?;
+        Ok(())
+    }
+
+    /// Finds the first entry with the address `ip`.
+    pub fn find(&self, ip: &str) -> Option<&ServerEntry> {
+        self.servers.iter().find(|server| server.ip == ip)
+    }
+
+    pub fn find_mut(&mut self, ip: &str) -> Option<&mut ServerEntry> {
+        self.servers.iter_mut().find(|server| server.ip == ip)
+    }
+
+    /// Adds a server to the end of the list.
+    pub fn add(&mut self, server: ServerEntry) {
+        self.servers.push(server);
+    }
+
+    /// Removes every entry with the address `ip`. Returns the number of entries that were removed.
+    pub fn remove(&mut self, ip: &str) -> usize {
+        let before = self.servers.len();
+        self.servers.retain(|server| server.ip != ip);
+        before - self.servers.len()
+    }
+
+    /// The entries that are shown in the list (those that aren't hidden).
+    pub fn visible(&self) -> impl Iterator<Item = &ServerEntry> {
+        self.servers.iter().filter(|server| !server.hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_list_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = ServerList::path(dir.path());
+        let mut list = ServerList::load(&path)?;
+        assert!(list.servers.is_empty());
+        list.add(ServerEntry::new("Survival", "play.example.com"));
+        list.add(ServerEntry {
+            resource_packs: ResourcePackPolicy::Disabled,
+            icon: Some("iVBORw0KGgo=".to_owned()),
+            ..ServerEntry::new("Creative", "creative.example.com:25566")
+        });
+        list.add(ServerEntry { hidden: true, ..ServerEntry::new("Quick Play", "quick.example.com") });
+        list.save(&path)?;
+        // The file is uncompressed, starting with the root compound's tag ID.
+        assert_eq!(std::fs::read(&path)?[0], 10);
+
+        let mut list = ServerList::load(&path)?;
+        assert_eq!(list.visible().map(|server| server.name.as_str()).collect::<Vec<_>>(), ["Survival", "Creative"]);
+        let creative = list.find("creative.example.com:25566").expect("creative server");
+        assert_eq!(creative.resource_packs, ResourcePackPolicy::Disabled);
+        assert_eq!(creative.icon.as_deref(), Some("iVBORw0KGgo="));
+        assert_eq!(list.find("play.example.com").map(|server| server.resource_packs), Some(ResourcePackPolicy::Prompt));
+        assert_eq!(list.remove("quick.example.com"), 1);
+        list.find_mut("play.example.com").expect("survival server").resource_packs = ResourcePackPolicy::Enabled;
+        list.save(&path)?;
+        let list = ServerList::load(&path)?;
+        assert_eq!(list.servers.len(), 2);
+        assert_eq!(list.servers[0].resource_packs, ResourcePackPolicy::Enabled);
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+//! The `session.lock` protocol that keeps more than one process from editing a world at once.
+//!
+//! Since 1.16 the game holds an exclusive OS lock on `session.lock` while the world
+//! is open. Before that, the game wrote the current time (in milliseconds) to the
+//! file and periodically checked that it hadn't been overwritten; if it had, another
+//! process had opened the world and the game stopped saving.
+//!
+//! [SessionLock] does both: it takes the OS lock (so that it fails if the game or a
+//! server has the world open), and it writes a timestamp (so that a foreign relock can
+//! be detected with [SessionLock::check]).
+//!
+//! Operations that rewrite a world on disk ([gc](super::gc::gc), which compacts region files
+//! and deletes empty ones, [restore](super::backup::restore), which merges a backup into a
+//! world, and [apply_fixes](super::fsck::apply_fixes)) refuse to run when another process
+//! holds the lock (see [ensure_world_idle]). A process that holds the lock itself passes its
+//! [SessionLock] to them.
+//!
+//! The lock only works when everything takes it, so [is_world_in_use] also looks for
+//! signs of a game that doesn't: a recent timestamp in `session.lock`, or region files
+//! that were written recently. The operations above check those signs too, unless they are
+//! given a [SessionLock], since the signs are then left by this process's own writes.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{McError, McResult, math::coord::Dimension};
+
+use super::{
+    io::region::{PositionedIo, timestamp::unix_millis_now},
+    scan::{RegionKind, region_files},
+    spawn::level_path,
+};
+
+/// The name of the lock file within the world directory.
+pub const SESSION_LOCK: &str = "session.lock";
+
+/// Takes an exclusive lock on the whole file without blocking.
+/// Returns `Ok(false)` if another process (or another handle in this process) holds a lock.
+#[cfg(unix)]
+fn try_lock_file(file: &File) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+    // Java locks files with fcntl, which doesn't interact with flock, so fcntl is used here too.
+    // Open file description locks (Linux) are used where available so that two
+    // handles in the same process also conflict.
+    #[cfg(target_os = "linux")]
+    const SET_LOCK: libc::c_int = libc::F_OFD_SETLK;
+    #[cfg(not(target_os = "linux"))]
+    const SET_LOCK: libc::c_int = libc::F_SETLK;
+    // Safety: flock is plain data, and zeroed is a valid value for every field.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    // Safety: the file descriptor is valid for the lifetime of `file`.
+    if unsafe { libc::fcntl(file.as_raw_fd(), SET_LOCK, &lock) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EAGAIN) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock_file(file: &File) -> io::Result<bool> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(false),
+        Err(std::fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// A held `session.lock`. The OS lock is released when this is dropped.
+pub struct SessionLock {
+    file: File,
+    path: PathBuf,
+    timestamp: i64,
+}
+
+impl SessionLock {
+    /// Takes the session lock of the world at `world_directory`.
+    /// Returns [McError::WorldLocked] if the world is open in another process.
+    pub fn acquire<P: AsRef<Path>>(world_directory: P) -> McResult<Self> {
+        let path = world_directory.as_ref().join(SESSION_LOCK);
+        let file = File::options().read(true).write(true).create(true).truncate(false).open(&path)?;
+        if !try_lock_file(&file)? {
+            return Err(McError::WorldLocked(path));
+        }
+        let timestamp = unix_millis_now();
+        file.set_len(0)?;
+        file.write_all_at(&timestamp.to_be_bytes(), 0)?;
+        file.sync_all()?;
+        Ok(Self {
+            file,
+            path,
+            timestamp,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The timestamp (milliseconds since the Unix epoch) that was written when the lock was taken.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// Returns false if the timestamp in the file has been overwritten,
+    /// which means that another process has taken the lock.
+    pub fn is_valid(&self) -> McResult<bool> {
+        let mut timestamp = [0u8; 8];
+        // The file is read through the locked handle; opening (and closing)
+        // another handle would release an fcntl lock.
+        match self.file.read_exact_at(&mut timestamp, 0) {
+            Ok(()) => Ok(i64::from_be_bytes(timestamp) == self.timestamp),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns [McError::SessionLockLost] if another process has taken the lock.
+    /// This should be checked before writing to the world.
+    pub fn check(&self) -> McResult<()> {
+        if self.is_valid()? {
+            Ok(())
+        } else {
+            Err(McError::SessionLockLost(self.path.clone()))
+        }
+    }
+}
+
+/// Takes the session lock of a world. See [SessionLock::acquire].
+pub fn lock<P: AsRef<Path>>(world_directory: P) -> McResult<SessionLock> {
+    SessionLock::acquire(world_directory)
+}
+
+/// How recently a world must have been written to for [is_world_in_use] to consider it
+/// active. The game saves every 5 minutes (and when it is closed).
+pub const ACTIVITY_WINDOW: Duration = Duration::from_secs(6 * 60);
+
+/// The reason that [world_activity] considers a world to be in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorldActivity {
+    /// Another process (or another [SessionLock] in this one) holds the lock on `session.lock`.
+    Locked,
+    /// `session.lock` holds a recent timestamp, as written by a game from before 1.16
+    /// (which doesn't take the OS lock) when it opened the world.
+    RecentLock,
+    /// This file (level.dat or a region file) was written recently.
+    RecentWrite(PathBuf),
+}
+
+/// Whether `time` is within `window` of now.
+fn is_recent(time: SystemTime, window: Duration) -> bool {
+    // Times in the future (from a skewed clock) count as recent.
+    !SystemTime::now().duration_since(time).is_ok_and(|age| age > window)
+}
+
+/// Looks for signs that the world at `world_directory` is open in another program.
+/// Returns `None` if there are none. A world without `session.lock` has never been opened
+/// by the game, so it is never considered to be in use.
+///
+/// `lock` is the [SessionLock] that this process holds on the world, if any. If it is given,
+/// the lock is checked with [SessionLock::check] (returning [McError::SessionLockLost] if
+/// another process has taken it). Otherwise the OS lock is probed, which finds a lock held by
+/// another process. The probe would also find a lock held by this process, and outside of
+/// Linux closing the probe releases it, so a held lock must always be passed.
+///
+/// With `recent`, a `session.lock` timestamp (unless `lock` is given, since the timestamp
+/// is then its own), level.dat, or region file from that long ago also counts as a sign.
+pub fn world_activity<P: AsRef<Path>>(world_directory: P, lock: Option<&SessionLock>, recent: Option<Duration>) -> McResult<Option<WorldActivity>> {
+    let world_directory = world_directory.as_ref();
+    let path = world_directory.join(SESSION_LOCK);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match lock {
+        Some(lock) => lock.check()?,
+        None => {
+            // The probe's lock (if it gets one) is released when the file is closed.
+            let file = File::options().read(true).write(true).open(&path)?;
+            if !try_lock_file(&file)? {
+                return Ok(Some(WorldActivity::Locked));
+            }
+        }
+    }
+    let Some(window) = recent else {
+        return Ok(None);
+    };
+    let contents = std::fs::read(&path)?;
+    if let (None, Ok(timestamp)) = (lock, <[u8; 8]>::try_from(contents.as_slice())) {
+        let millis = i64::from_be_bytes(timestamp);
+        let age = unix_millis_now() - millis;
+        if millis > 0 && age < window.as_millis() as i64 {
+            return Ok(Some(WorldActivity::RecentLock));
+        }
+    }
+    let mut files = vec![level_path(world_directory)];
+    for dimension in [Dimension::Overworld, Dimension::Nether, Dimension::TheEnd] {
+        for kind in RegionKind::ALL {
+            files.extend(region_files(world_directory, dimension, kind)?.into_iter().map(|(_, path)| path));
+        }
+    }
+    for file in files {
+        let Ok(modified) = std::fs::metadata(&file).and_then(|metadata| metadata.modified()) else { continue };
+        if is_recent(modified, window) {
+            return Ok(Some(WorldActivity::RecentWrite(file)));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the world at `world_directory` appears to be open in another program (see
+/// [world_activity], without a lock and with [ACTIVITY_WINDOW]). Since this process doesn't
+/// hold the lock, it shouldn't have written to the world recently either.
+pub fn is_world_in_use<P: AsRef<Path>>(world_directory: P) -> McResult<bool> {
+    Ok(world_activity(world_directory, None, Some(ACTIVITY_WINDOW))?.is_some())
+}
+
+/// Returns [McError::WorldLocked] if the world appears to be open in another process
+/// (see [world_activity]). This is checked before operations that rewrite a world on disk.
+///
+/// Without `lock`, recent writes (within [ACTIVITY_WINDOW]) also count, as in [is_world_in_use].
+/// With `lock`, only the lock is checked, since the recent writes may be this process's own.
+pub fn ensure_world_idle<P: AsRef<Path>>(world_directory: P, lock: Option<&SessionLock>) -> McResult<()> {
+    let world_directory = world_directory.as_ref();
+    let recent = lock.is_none().then_some(ACTIVITY_WINDOW);
+    if world_activity(world_directory, lock, recent)?.is_some() {
+        return Err(McError::WorldLocked(world_directory.to_owned()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_lock_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let session = lock(dir.path())?;
+        session.check()?;
+        assert!(matches!(lock(dir.path()), Err(McError::WorldLocked(_))));
+        // A legacy game relocking the world overwrites the timestamp without the OS lock.
+        std::fs::write(dir.path().join(SESSION_LOCK), (session.timestamp() + 1).to_be_bytes())?;
+        assert!(matches!(session.check(), Err(McError::SessionLockLost(_))));
+        drop(session);
+        lock(dir.path())?.check()?;
+        Ok(())
+    }
+
+    #[test]
+    fn world_activity_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let window = Some(ACTIVITY_WINDOW);
+        std::fs::create_dir_all(dir.path().join("region"))?;
+        std::fs::write(dir.path().join("region/r.0.0.mca"), [0u8; 8192])?;
+        // Never opened by the game.
+        assert_eq!(world_activity(dir.path(), None, window)?, None);
+
+        let session = lock(dir.path())?;
+        // The lock is another process's unless it is passed.
+        assert_eq!(world_activity(dir.path(), None, None)?, Some(WorldActivity::Locked));
+        assert!(matches!(ensure_world_idle(dir.path(), None), Err(McError::WorldLocked(_))));
+        ensure_world_idle(dir.path(), Some(&session))?;
+        // Checking didn't release the lock.
+        assert!(matches!(lock(dir.path()), Err(McError::WorldLocked(_))));
+        // The world's recent writes are only signs with a window.
+        assert_eq!(world_activity(dir.path(), Some(&session), window)?, Some(WorldActivity::RecentWrite(dir.path().join("region/r.0.0.mca"))));
+        drop(session);
+        assert_eq!(world_activity(dir.path(), None, None)?, None);
+        assert!(matches!(ensure_world_idle(dir.path(), None), Err(McError::WorldLocked(_))));
+        assert_eq!(world_activity(dir.path(), None, window)?, Some(WorldActivity::RecentLock));
+        assert!(is_world_in_use(dir.path())?);
+
+        // The game writes a snowman since 1.16.
+        std::fs::write(dir.path().join(SESSION_LOCK), "\u{2603}")?;
+        assert_eq!(world_activity(dir.path(), None, window)?, Some(WorldActivity::RecentWrite(dir.path().join("region/r.0.0.mca"))));
+        assert_eq!(world_activity(dir.path(), None, Some(Duration::ZERO))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn ensure_world_idle_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("region"))?;
+        // An unlocked world, last opened long ago, by a game that doesn't write a timestamp.
+        std::fs::write(dir.path().join(SESSION_LOCK), "\u{2603}")?;
+        let region = dir.path().join("region/r.0.0.mca");
+        std::fs::write(&region, [0u8; 8192])?;
+        let old = SystemTime::now() - ACTIVITY_WINDOW * 2;
+        File::options().write(true).open(&region)?.set_modified(old)?;
+        ensure_world_idle(dir.path(), None)?;
+
+        // A game that doesn't take the lock has just saved.
+        File::options().write(true).open(&region)?.set_modified(SystemTime::now())?;
+        assert!(matches!(ensure_world_idle(dir.path(), None), Err(McError::WorldLocked(_))));
+        // The write is this process's own when it holds the lock.
+        let session = lock(dir.path())?;
+        ensure_world_idle(dir.path(), Some(&session))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! The world spawn and the spawn chunks around it.
+//!
+//! The spawn chunks stay loaded while any player is in the overworld. Before
+//! 1.20.5 this was always the 19x19 chunks centered on the world spawn; since
+//! then the size is set with the `spawnChunkRadius` game rule.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    McResult,
+    math::coord::{BlockPos, ChunkPos, Dimension},
+};
+
+use super::{
+    forced::ForcedChunks,
+    level::{Level, LevelWriteOptions, read_level_with_fallback, write_level_with},
+};
+
+/// The spawn chunk radius of worlds from before the `spawnChunkRadius` game rule.
+pub const LEGACY_SPAWN_CHUNK_RADIUS: i64 = 9;
+
+/// Gets the path of `level.dat` within a world directory.
+pub fn level_path<P: AsRef<Path>>(world_directory: P) -> PathBuf {
+    world_directory.as_ref().join("level.dat")
+}
+
+/// The chunks within `radius` chunks of the chunk containing `spawn`, sorted by (x, z).
+/// A radius of 0 or less means there are no spawn chunks.
+pub fn spawn_chunks<C: Into<BlockPos>>(spawn: C, radius: i64) -> Vec<ChunkPos> {
+    if radius <= 0 {
+        return Vec::new();
+    }
+    let center = spawn.into().chunk();
+    (center.x - radius..=center.x + radius)
+        .flat_map(|x| (center.z - radius..=center.z + radius).map(move |z| ChunkPos::new(x, z)))
+        .collect()
+}
+
+/// Moves force-loaded chunks from the `old` spawn chunks to the `new` spawn chunks.
+/// Chunks that are in both sets are left force-loaded.
+pub fn move_forced_spawn_chunks(forced: &mut ForcedChunks, old: &[ChunkPos], new: &[ChunkPos]) {
+    old.iter().for_each(|chunk| {
+        forced.remove(chunk.x as i32, chunk.z as i32);
+    });
+    new.iter().for_each(|chunk| {
+        forced.add(chunk.x as i32, chunk.z as i32);
+    });
+}
+
+/// Moves the world spawn in `level.dat`, and returns the updated [Level].
+///
+/// When `force_load` is true, the new spawn chunks are also marked as force-loaded
+/// in the overworld's `chunks.dat`, and the old spawn chunks are unmarked.
+/// Note that this unmarks old spawn chunks even if they were force-loaded for another reason.
+/// As the game does, the previous `level.dat` is kept as `level.dat_old`, which is also read
+/// if `level.dat` is corrupt.
+pub fn set_world_spawn<P: AsRef<Path>, C: Into<BlockPos>>(world_directory: P, spawn: C, angle: f32, force_load: bool) -> McResult<Level> {
+    let world_directory = world_directory.as_ref();
+    let path = level_path(world_directory);
+    let mut level = read_level_with_fallback(&path)?;
+    let old = level.spawn_chunks();
+    level.set_spawn(spawn, angle);
+    if force_load {
+        let mut forced = ForcedChunks::load(world_directory, Dimension::Overworld)?;
+        move_forced_spawn_chunks(&mut forced, &old, &level.spawn_chunks());
+        forced.save(world_directory)?;
+    }
+    write_level_with(&path, &level, &LevelWriteOptions::default())?;
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_chunks_test() -> McResult<()> {
+        let chunks = spawn_chunks((-1, 64, 40), LEGACY_SPAWN_CHUNK_RADIUS);
+        assert_eq!(chunks.len(), 19 * 19);
+        assert_eq!(chunks.first(), Some(&ChunkPos::new(-10, -7)));
+        assert_eq!(chunks.last(), Some(&ChunkPos::new(8, 11)));
+        assert!(spawn_chunks((0, 0, 0), 0).is_empty());
+
+        let dir = tempfile::tempdir()?;
+        let mut forced = ForcedChunks::new(Dimension::Overworld);
+        forced.add(100, 100);
+        let old = spawn_chunks((0, 64, 0), 2);
+        let new = spawn_chunks((160, 64, 0), 2);
+        move_forced_spawn_chunks(&mut forced, &[], &old);
+        assert_eq!(forced.len(), 26);
+        move_forced_spawn_chunks(&mut forced, &old, &new);
+        forced.save(dir.path())?;
+        let forced = ForcedChunks::load(dir.path(), Dimension::Overworld)?;
+        assert_eq!(forced.len(), 26);
+        assert!(forced.contains(100, 100));
+        assert!(forced.contains(12, 0));
+        assert!(!forced.contains(0, 0));
+        Ok(())
+    }
+}
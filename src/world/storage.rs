@@ -0,0 +1,205 @@
+//! Command storage (the `/data ... storage` commands), stored per namespace in
+//! `data/command_storage_<namespace>.dat` within the world directory.
+//!
+//! A storage ID such as `mypack:state` is stored under the key `state` in the file of
+//! the `mypack` namespace.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use flate2::Compression;
+
+use crate::{
+    McError, McResult,
+    nbt::{
+        Map,
+        file::{read_nbt_file, write_nbt_file},
+        tag::{NamedTag, Tag},
+    },
+};
+
+const FILE_PREFIX: &str = "command_storage_";
+
+/// Splits a storage ID into its namespace and path. IDs without a namespace are in `minecraft`.
+pub fn split_storage_id(id: &str) -> (&str, &str) {
+    id.split_once(':').unwrap_or(("minecraft", id))
+}
+
+/// The command storage of a single namespace.
+#[derive(Debug, Clone)]
+pub struct CommandStorage {
+    namespace: String,
+    /// The root of the file, which is kept so that unknown data is written back.
+    root: Map,
+    contents: BTreeMap<String, Map>,
+}
+
+impl CommandStorage {
+    pub fn new<S: Into<String>>(namespace: S) -> Self {
+        Self {
+            namespace: namespace.into(),
+            root: Map::new(),
+            contents: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the path of the storage file of a namespace.
+    pub fn path<P: AsRef<Path>>(world_directory: P, namespace: &str) -> PathBuf {
+        world_directory.as_ref().join("data").join(format!("{FILE_PREFIX}{namespace}.dat"))
+    }
+
+    /// Lists the namespaces that have a storage file, sorted.
+    pub fn namespaces<P: AsRef<Path>>(world_directory: P) -> McResult<Vec<String>> {
+        let directory = world_directory.as_ref().join("data");
+        if !directory.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut namespaces = std::fs::read_dir(directory)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                let name = name.to_str()?;
+                Some(name.strip_prefix(FILE_PREFIX)?.strip_suffix(".dat")?.to_owned())
+            })
+            .collect::<Vec<_>>();
+        namespaces.sort();
+        Ok(namespaces)
+    }
+
+    /// Loads the storage of a namespace. If there is no file, the storage is empty.
+    pub fn load<P: AsRef<Path>>(world_directory: P, namespace: &str) -> McResult<Self> {
+        let path = Self::path(world_directory, namespace);
+        if !path.is_file() {
+            return Ok(Self::new(namespace));
+        }
+        let Tag::Compound(mut root) = read_nbt_file(path)?.take_tag() else {
+            return Err(McError::NbtDecodeError);
+        };
+        let contents = match root.get_mut("data") {
+            Some(Tag::Compound(data)) => match data.remove("contents") {
+                Some(Tag::Compound(contents)) => contents.into_iter()
+                    .filter_map(|(key, value)| match value {
+                        Tag::Compound(value) => Some((key, value)),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => BTreeMap::new(),
+            },
+            _ => BTreeMap::new(),
+        };
+        Ok(Self {
+            namespace: namespace.to_owned(),
+            root,
+            contents,
+        })
+    }
+
+    /// Saves the storage, creating the `data` directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, world_directory: P) -> McResult<()> {
+        let path = Self::path(world_directory, &self.namespace);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut root = self.root.clone();
+        let mut data = match root.remove("data") {
+            Some(Tag::Compound(data)) => data,
+            _ => Map::new(),
+        };
+        let contents = self.contents.iter()
+            .map(|(key, value)| (key.clone(), Tag::Compound(value.clone())))
+            .collect::<Map>();
+        data.insert("contents".to_owned(), Tag::Compound(contents));
+        root.insert("data".to_owned(), Tag::Compound(data));
+        write_nbt_file(path, &NamedTag::new(root), Compression::default())?;
+        Ok(())
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The paths of the storages in this namespace, sorted.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.contents.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+
+    /// Gets the storage at `path` (the part of the storage ID after the namespace).
+    pub fn get(&self, path: &str) -> Option<&Map> {
+        self.contents.get(path)
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut Map> {
+        self.contents.get_mut(path)
+    }
+
+    /// Gets the storage at `path`, creating it if it doesn't exist.
+    pub fn get_or_create(&mut self, path: &str) -> &mut Map {
+        self.contents.entry(path.to_owned()).or_default()
+    }
+
+    /// Replaces the storage at `path`, returning the old one.
+    pub fn insert<S: Into<String>>(&mut self, path: S, value: Map) -> Option<Map> {
+        self.contents.insert(path.into(), value)
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<Map> {
+        self.contents.remove(path)
+    }
+
+    pub fn clear(&mut self) {
+        self.contents.clear();
+    }
+}
+
+/// Reads the storage with the ID `id` (such as `mypack:state`).
+/// Returns `None` if it doesn't exist.
+pub fn read_storage<P: AsRef<Path>>(world_directory: P, id: &str) -> McResult<Option<Map>> {
+    let (namespace, path) = split_storage_id(id);
+    Ok(CommandStorage::load(world_directory, namespace)?.remove(path))
+}
+
+/// Replaces the storage with the ID `id`, keeping the other storages of its namespace.
+pub fn write_storage<P: AsRef<Path>>(world_directory: P, id: &str, value: Map) -> McResult<()> {
+    let world_directory = world_directory.as_ref();
+    let (namespace, path) = split_storage_id(id);
+    let mut storage = CommandStorage::load(world_directory, namespace)?;
+    storage.insert(path, value);
+    storage.save(world_directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_storage_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        assert!(CommandStorage::namespaces(world)?.is_empty());
+        write_storage(world, "mypack:state", Map::from([("round".to_owned(), Tag::Int(3))]))?;
+        write_storage(world, "mypack:config", Map::from([("enabled".to_owned(), Tag::Byte(1))]))?;
+        write_storage(world, "scores", Map::new())?;
+        assert_eq!(CommandStorage::namespaces(world)?, vec!["minecraft".to_owned(), "mypack".to_owned()]);
+
+        let mut storage = CommandStorage::load(world, "mypack")?;
+        assert_eq!(storage.keys().collect::<Vec<_>>(), vec!["config", "state"]);
+        assert!(matches!(storage.get("state").and_then(|state| state.get("round")), Some(Tag::Int(3))));
+        storage.get_or_create("state").insert("round".to_owned(), Tag::Int(4));
+        storage.remove("config");
+        storage.save(world)?;
+        let state = read_storage(world, "mypack:state")?.unwrap();
+        assert!(matches!(state.get("round"), Some(Tag::Int(4))));
+        assert!(read_storage(world, "mypack:config")?.is_none());
+        assert!(read_storage(world, "other:state")?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+//! Normalized hashes of terrain, for checking that two worlds (or two versions of
+//! the same world) hold the same terrain, such as before and after round-tripping
+//! a world through [decode_chunk] and [encode_chunk](super::chunk::encode_chunk).
+//!
+//! A chunk's hash covers its position, the block state and biome of every block,
+//! and its block entities. It doesn't depend on how the chunk is stored: palette
+//! order, bits per entry, uniform sections, and the order of compound keys all hash
+//! the same. Light, heightmaps, ticks, timestamps, and the rest of the chunk's
+//! bookkeeping are ignored. Entities are not terrain, so they aren't included either.
+//!
+//! Hashes are computed with XXH3, so they are the same across platforms and runs.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hasher,
+    path::Path,
+};
+
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{
+    McResult,
+    nbt::{
+        Map,
+        tag::{ListTag, NamedTag, Tag},
+    },
+};
+
+use super::{
+    blockregistry::BlockRegistry,
+    blockstate::BlockState,
+    chunk::{Chunk, ChunkSection, decode_chunk},
+    io::region::{RegionFile, coord::RegionCoord},
+};
+
+/// Writes a length or count, so that adjacent variable length values can't run together.
+fn write_len(hasher: &mut Xxh3, len: usize) {
+    hasher.write_u64(len as u64);
+}
+
+fn write_str(hasher: &mut Xxh3, value: &str) {
+    write_len(hasher, value.len());
+    hasher.write(value.as_bytes());
+}
+
+fn hash_state(state: &BlockState) -> u64 {
+    let mut hasher = Xxh3::new();
+    write_str(&mut hasher, state.name());
+    let properties = state.properties().unwrap_or_default();
+    write_len(&mut hasher, properties.len());
+    properties.iter().for_each(|property| {
+        write_str(&mut hasher, property.name());
+        write_str(&mut hasher, property.value());
+    });
+    hasher.finish()
+}
+
+/// Hashes `tag` into `hasher`. Compound keys are hashed in sorted order.
+pub fn hash_tag(hasher: &mut Xxh3, tag: &Tag) {
+    hasher.write_u8(tag.id() as u8);
+    match tag {
+        Tag::Byte(value) => hasher.write_i8(*value),
+        Tag::Short(value) => hasher.write_i16(*value),
+        Tag::Int(value) => hasher.write_i32(*value),
+        Tag::Long(value) => hasher.write_i64(*value),
+        Tag::Float(value) => hasher.write_u32(value.to_bits()),
+        Tag::Double(value) => hasher.write_u64(value.to_bits()),
+        Tag::ByteArray(values) => {
+            write_len(hasher, values.len());
+            values.iter().for_each(|&value| hasher.write_i8(value));
+        }
+        Tag::String(value) => write_str(hasher, value),
+        Tag::List(list) => hash_list(hasher, list),
+        Tag::Compound(map) => hash_compound(hasher, map),
+        Tag::IntArray(values) => {
+            write_len(hasher, values.len());
+            values.iter().for_each(|&value| hasher.write_i32(value));
+        }
+        Tag::LongArray(values) => {
+            write_len(hasher, values.len());
+            values.iter().for_each(|&value| hasher.write_i64(value));
+        }
+    }
+}
+
+fn hash_compound(hasher: &mut Xxh3, map: &Map) {
+    let mut keys = map.keys().collect::<Vec<_>>();
+    keys.sort();
+    write_len(hasher, keys.len());
+    keys.into_iter().for_each(|key| {
+        write_str(hasher, key);
+        hash_tag(hasher, &map[key]);
+    });
+}
+
+fn hash_list(hasher: &mut Xxh3, list: &ListTag) {
+    /// Hashes the element type, the length, and then each element.
+    /// Empty lists of any type hash the same, since the type of an empty list isn't kept.
+    fn elements<T>(hasher: &mut Xxh3, list: &ListTag, values: &[T], mut hash: impl FnMut(&mut Xxh3, &T)) {
+        hasher.write_u8(if values.is_empty() { 0 } else { list.id() as u8 });
+        write_len(hasher, values.len());
+        values.iter().for_each(|value| hash(hasher, value));
+    }
+    match list {
+        ListTag::Empty => elements::<()>(hasher, list, &[], |_, _| ()),
+        ListTag::Byte(values) => elements(hasher, list, values, |hasher, &value| hasher.write_i8(value)),
+        ListTag::Short(values) => elements(hasher, list, values, |hasher, &value| hasher.write_i16(value)),
+        ListTag::Int(values) => elements(hasher, list, values, |hasher, &value| hasher.write_i32(value)),
+        ListTag::Long(values) => elements(hasher, list, values, |hasher, &value| hasher.write_i64(value)),
+        ListTag::Float(values) => elements(hasher, list, values, |hasher, value| hasher.write_u32(value.to_bits())),
+        ListTag::Double(values) => elements(hasher, list, values, |hasher, value| hasher.write_u64(value.to_bits())),
+        ListTag::ByteArray(values) => elements(hasher, list, values, |hasher, value| elements(hasher, list, value, |hasher, &value| hasher.write_i8(value))),
+        ListTag::String(values) => elements(hasher, list, values, |hasher, value| write_str(hasher, value)),
+        ListTag::List(values) => elements(hasher, list, values, hash_list),
+        ListTag::Compound(values) => elements(hasher, list, values, hash_compound),
+        ListTag::IntArray(values) => elements(hasher, list, values, |hasher, value| elements(hasher, list, value, |hasher, &value| hasher.write_i32(value))),
+        ListTag::LongArray(values) => elements(hasher, list, values, |hasher, value| elements(hasher, list, value, |hasher, &value| hasher.write_i64(value))),
+    }
+}
+
+/// A section is skipped if it holds nothing but air and has no biomes, since
+/// the game (and the encoder) are free to leave such sections out.
+fn is_blank(section: &ChunkSection, air: u32) -> bool {
+    section.biomes.is_none() && match &section.blocks {
+        None => true,
+        Some(_) => (0..4096i64).all(|index| section.get_id(index & 15, index >> 8, (index >> 4) & 15) == Some(air)),
+    }
+}
+
+/// Computes the normalized hash of `chunk`, whose block ids are from `registry`.
+pub fn hash_chunk(registry: &BlockRegistry, chunk: &Chunk) -> u64 {
+    let air_state = BlockState::air();
+    let air = registry.find(&air_state);
+    let air_hash = hash_state(&air_state);
+    let mut states = HashMap::<u32, u64>::new();
+    let mut state_hash = |id: u32| *states.entry(id).or_insert_with(|| {
+        registry.get(id).map(hash_state).unwrap_or(air_hash)
+    });
+    let mut hasher = Xxh3::new();
+    hasher.write_i32(chunk.x);
+    hasher.write_i32(chunk.z);
+    let mut sections = chunk.sections.sections.iter()
+        .filter(|section| !air.is_some_and(|air| is_blank(section, air)) && (section.blocks.is_some() || section.biomes.is_some()))
+        .collect::<Vec<_>>();
+    sections.sort_by_key(|section| section.y);
+    write_len(&mut hasher, sections.len());
+    for section in sections {
+        hasher.write_i8(section.y);
+        // YZX order, the same as the game.
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let hash = section.get_id(x, y, z).map(&mut state_hash).unwrap_or(air_hash);
+                    hasher.write_u64(hash);
+                }
+            }
+        }
+        for y in (0..16).step_by(4) {
+            for z in (0..16).step_by(4) {
+                for x in (0..16).step_by(4) {
+                    write_str(&mut hasher, section.biome_at(x, y, z).unwrap_or_default());
+                }
+            }
+        }
+    }
+    let mut block_entities = chunk.block_entities.iter().collect::<Vec<_>>();
+    block_entities.sort_by_key(|entity| (entity.y, entity.z, entity.x));
+    write_len(&mut hasher, block_entities.len());
+    for entity in block_entities {
+        write_str(&mut hasher, &entity.id);
+        hasher.write_i32(entity.x);
+        hasher.write_i32(entity.y);
+        hasher.write_i32(entity.z);
+        hash_compound(&mut hasher, &entity.data);
+    }
+    hasher.finish()
+}
+
+/// The terrain hashes of every chunk in a region file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionHash {
+    pub chunks: BTreeMap<RegionCoord, u64>,
+}
+
+impl RegionHash {
+    /// A single hash of every chunk hash (and which chunks are present).
+    pub fn combined(&self) -> u64 {
+        let mut hasher = Xxh3::new();
+        write_len(&mut hasher, self.chunks.len());
+        self.chunks.iter().for_each(|(coord, &hash)| {
+            hasher.write_u16(coord.index() as u16);
+            hasher.write_u64(hash);
+        });
+        hasher.finish()
+    }
+
+    /// The chunks that are only in one of the regions, or whose terrain differs.
+    pub fn differences(&self, other: &RegionHash) -> Vec<RegionCoord> {
+        let mut coords = self.chunks.keys()
+            .chain(other.chunks.keys())
+            .filter(|coord| self.chunks.get(coord) != other.chunks.get(coord))
+            .copied()
+            .collect::<Vec<_>>();
+        coords.sort();
+        coords.dedup();
+        coords
+    }
+}
+
+/// Decodes every chunk in the region file at `path` and hashes it.
+pub fn hash_region<P: AsRef<Path>>(path: P) -> McResult<RegionHash> {
+    let region = RegionFile::open_read_only(path)?;
+    let mut registry = BlockRegistry::with_air();
+    let mut hash = RegionHash::default();
+    for index in 0..1024 {
+        let coord = RegionCoord::from(index);
+        if region.get_sector(coord).is_empty() {
+            continue;
+        }
+        let root: NamedTag = region.read_data(coord)?;
+        let chunk = decode_chunk(&mut registry, root.take_tag())?;
+        hash.chunks.insert(coord, hash_chunk(&registry, &chunk));
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::{encode_chunk, tests::empty_chunk};
+
+    #[test]
+    fn terrain_hash_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let mut registry = BlockRegistry::with_air();
+        let stone = registry.register(BlockState::from("minecraft:stone"));
+        let dirt = registry.register(BlockState::from("minecraft:dirt"));
+        let mut chunk = empty_chunk(0, -4, 0);
+        chunk.sections.get_or_insert(4).fill(stone);
+        chunk.set_id((3, 70, 5), dirt)?;
+        let hash = hash_chunk(&registry, &chunk);
+
+        // Light and bookkeeping don't matter.
+        let mut lit = chunk.clone();
+        lit.last_update = 1234;
+        lit.sections.get_or_insert(4).skylight = Some(vec![0xFFu8; 2048].into());
+        assert_eq!(hash_chunk(&registry, &lit), hash);
+
+        // Neither do registry ids or blank sections.
+        let mut other = BlockRegistry::with_air();
+        let dirt = other.register(BlockState::from("minecraft:dirt"));
+        let stone = other.register(BlockState::from("minecraft:stone"));
+        let mut same = empty_chunk(0, -4, 0);
+        same.sections.get_or_insert(-2);
+        same.sections.get_or_insert(4).fill(stone);
+        same.set_id((3, 70, 5), dirt)?;
+        assert_eq!(hash_chunk(&other, &same), hash);
+        same.set_id((3, 71, 5), dirt)?;
+        assert_ne!(hash_chunk(&other, &same), hash);
+
+        // Round-tripping through the encoder keeps the hash.
+        let path = dir.path().join("r.0.0.mca");
+        {
+            let mut region = RegionFile::create(&path)?;
+            region.write_data((0, 0), &NamedTag::new(Tag::Compound(encode_chunk(&registry, &chunk))))?;
+            region.write_data((1, 0), &NamedTag::new(Tag::Compound(encode_chunk(&other, &same))))?;
+        }
+        let region = hash_region(&path)?;
+        assert_eq!(region.chunks.get(&RegionCoord::from((0, 0))), Some(&hash));
+        let mut expected = region.clone();
+        expected.chunks.insert(RegionCoord::from((1, 0)), hash);
+        assert_eq!(region.differences(&expected), vec![RegionCoord::from((1, 0))]);
+        assert_ne!(region.combined(), expected.combined());
+        Ok(())
+    }
+}
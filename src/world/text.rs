@@ -0,0 +1,247 @@
+//! Extraction of player-written text (signs and books) from a world.
+
+use std::path::Path;
+
+use glam::DVec3;
+
+use crate::{
+    McResult,
+    math::coord::{Dimension, WorldCoord},
+    nbt::{
+        Map,
+        tag::{ListTag, Tag},
+        tagpath::TagPath,
+    },
+};
+
+use super::{
+    scan::{for_each_chunk, RegionKind},
+    search::visit_compounds,
+    selection::WorldSelection,
+};
+
+/// What a [TextRecord] was extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TextSource {
+    Sign,
+    /// A signed book (`minecraft:written_book`).
+    WrittenBook,
+    /// A book and quill (`minecraft:writable_book`).
+    WritableBook,
+}
+
+/// A piece of text found by [extract_text].
+#[derive(Debug, Clone)]
+pub struct TextRecord {
+    pub source: TextSource,
+    pub dimension: Dimension,
+    /// The chunk that the text is stored in.
+    pub chunk: WorldCoord,
+    /// The position of the sign, or of the nearest holder of the book.
+    pub position: Option<DVec3>,
+    /// The lines of a sign (front, then back) or the pages of a book.
+    /// Signs and written books store each line or page as a JSON text component,
+    /// which is returned as it is stored.
+    pub text: Vec<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// The path of the sign or book from the root of the chunk.
+    pub path: TagPath,
+}
+
+/// Gets a string, or the `raw` string of a filterable string (1.20.5+).
+fn read_string(tag: &Tag) -> Option<String> {
+    match tag {
+        Tag::String(value) => Some(value.clone()),
+        Tag::Compound(map) => map.get("raw").and_then(read_string),
+        _ => None,
+    }
+}
+
+/// Gets each string in a list of strings or filterable strings.
+fn read_strings(tag: Option<&Tag>) -> Vec<String> {
+    match tag {
+        Some(Tag::List(ListTag::String(list))) => list.clone(),
+        Some(Tag::List(ListTag::Compound(list))) => list.iter()
+            .filter_map(|map| map.get("raw").and_then(read_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reads the lines of a sign. Signs store `front_text`/`back_text` since 1.20,
+/// and `Text1` to `Text4` before that.
+fn read_sign(map: &Map) -> Option<Vec<String>> {
+    let Some(Tag::String(id)) = map.get("id") else {
+        return None;
+    };
+    if !id.to_ascii_lowercase().ends_with("sign") {
+        return None;
+    }
+    if map.contains_key("front_text") {
+        let side = |key: &str| match map.get(key) {
+            Some(Tag::Compound(side)) => read_strings(side.get("messages")),
+            _ => Vec::new(),
+        };
+        let mut lines = side("front_text");
+        lines.extend(side("back_text"));
+        Some(lines)
+    } else if map.contains_key("Text1") {
+        Some(["Text1", "Text2", "Text3", "Text4"].into_iter()
+            .filter_map(|key| map.get(key).and_then(read_string))
+            .collect())
+    } else {
+        // A sign item rather than a sign block entity.
+        None
+    }
+}
+
+struct Book {
+    source: TextSource,
+    pages: Vec<String>,
+    title: Option<String>,
+    author: Option<String>,
+}
+
+/// Reads the pages, title, and author of a book. Book data is stored in `tag`
+/// before 1.20.5, and in `components` since then.
+fn read_book(map: &Map) -> Option<Book> {
+    let (source, component) = match map.get("id") {
+        Some(Tag::String(id)) if id == "minecraft:written_book" => (TextSource::WrittenBook, "minecraft:written_book_content"),
+        Some(Tag::String(id)) if id == "minecraft:writable_book" => (TextSource::WritableBook, "minecraft:writable_book_content"),
+        _ => return None,
+    };
+    let content = match (map.get("components"), map.get("tag")) {
+        (Some(Tag::Compound(components)), _) => match components.get(component) {
+            Some(Tag::Compound(content)) => Some(content),
+            _ => None,
+        },
+        (_, Some(Tag::Compound(tag))) => Some(tag),
+        _ => None,
+    };
+    let Some(content) = content else {
+        return Some(Book { source, pages: Vec::new(), title: None, author: None });
+    };
+    Some(Book {
+        source,
+        pages: read_strings(content.get("pages")),
+        title: content.get("title").and_then(read_string),
+        author: content.get("author").and_then(read_string),
+    })
+}
+
+/// Extracts the text of every sign and book in the selected chunks, in order of chunk
+/// and then path. Books are found in containers, in item frames, on lecterns, and
+/// as dropped items, including books stored inside of other items (such as shulker boxes).
+pub fn extract_text<P: AsRef<Path>>(world_directory: P, selection: &WorldSelection) -> McResult<Vec<TextRecord>> {
+    let world_directory = world_directory.as_ref();
+    let mut records = Vec::new();
+    [RegionKind::Terrain, RegionKind::Entities].into_iter().try_for_each(|kind| {
+        for_each_chunk(world_directory, selection, kind, |chunk, root| {
+            visit_compounds(root.tag(), |map, _, position, path| {
+                let record = |source, text, title, author| TextRecord {
+                    source,
+                    dimension: chunk.dimension,
+                    chunk,
+                    position,
+                    text,
+                    title,
+                    author,
+                    path: TagPath(path.to_vec()),
+                };
+                if let Some(lines) = read_sign(map) {
+                    records.push(record(TextSource::Sign, lines, None, None));
+                } else if let Some(book) = read_book(map) {
+                    records.push(record(book.source, book.pages, book.title, book.author));
+                }
+            });
+            Ok(false)
+        })
+    })?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        nbt::tag::NamedTag,
+        world::io::region::RegionFile,
+    };
+
+    fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Map {
+        entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+    }
+
+    fn string(value: &str) -> Tag {
+        Tag::String(value.to_owned())
+    }
+
+    #[test]
+    fn extract_text_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        std::fs::create_dir_all(world.join("region"))?;
+
+        let messages = |lines: [&str; 4]| Tag::Compound(compound([
+            ("messages", Tag::List(ListTag::from(lines.to_vec()))),
+        ]));
+        let sign = compound([
+            ("id", string("minecraft:sign")),
+            ("x", Tag::Int(1)), ("y", Tag::Int(64)), ("z", Tag::Int(2)),
+            ("front_text", messages(["\"Hello\"", "\"\"", "\"\"", "\"\""])),
+            ("back_text", messages(["\"Back\"", "\"\"", "\"\"", "\"\""])),
+        ]);
+        let old_sign = compound([
+            ("id", string("Sign")),
+            ("x", Tag::Int(3)), ("y", Tag::Int(64)), ("z", Tag::Int(4)),
+            ("Text1", string("\"Old\"")), ("Text2", string("\"\"")), ("Text3", string("\"\"")), ("Text4", string("\"\"")),
+        ]);
+        let book = compound([
+            ("id", string("minecraft:written_book")),
+            ("Count", Tag::Byte(1)),
+            ("tag", Tag::Compound(compound([
+                ("title", string("Diary")),
+                ("author", string("Alex")),
+                ("pages", Tag::List(ListTag::from(vec!["\"Page 1\"", "\"Page 2\""]))),
+            ]))),
+        ]);
+        let new_book = compound([
+            ("id", string("minecraft:written_book")),
+            ("count", Tag::Int(1)),
+            ("components", Tag::Compound(compound([
+                ("minecraft:written_book_content", Tag::Compound(compound([
+                    ("title", Tag::Compound(compound([("raw", string("Notes"))]))),
+                    ("author", string("Steve")),
+                    ("pages", Tag::List(ListTag::Compound(vec![compound([("raw", string("\"Hi\""))])]))),
+                ]))),
+            ]))),
+        ]);
+        let chest = compound([
+            ("id", string("minecraft:chest")),
+            ("x", Tag::Int(5)), ("y", Tag::Int(60)), ("z", Tag::Int(6)),
+            ("Items", Tag::List(ListTag::Compound(vec![book, new_book]))),
+        ]);
+        let terrain = Tag::Compound(compound([
+            ("block_entities", Tag::List(ListTag::Compound(vec![sign, old_sign, chest]))),
+        ]));
+        RegionFile::create(world.join("region/r.0.0.mca"))?.write_data((0, 0), &NamedTag::new(terrain))?;
+
+        let records = extract_text(world, &WorldSelection::dimension(Dimension::Overworld))?;
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].source, TextSource::Sign);
+        assert_eq!(records[0].text, vec!["\"Hello\"", "\"\"", "\"\"", "\"\"", "\"Back\"", "\"\"", "\"\"", "\"\""]);
+        assert_eq!(records[0].position, Some(DVec3::new(1.0, 64.0, 2.0)));
+        assert_eq!(records[1].text[0], "\"Old\"");
+        assert_eq!(records[2].source, TextSource::WrittenBook);
+        assert_eq!(records[2].title.as_deref(), Some("Diary"));
+        assert_eq!(records[2].author.as_deref(), Some("Alex"));
+        assert_eq!(records[2].text.len(), 2);
+        assert_eq!(records[2].position, Some(DVec3::new(5.0, 60.0, 6.0)));
+        assert_eq!(records[2].path, TagPath::parse("block_entities[2].Items[0]").unwrap());
+        assert_eq!(records[3].title.as_deref(), Some("Notes"));
+        assert_eq!(records[3].text, vec!["\"Hi\""]);
+        assert!(extract_text(world, &WorldSelection::dimension(Dimension::Nether))?.is_empty());
+        Ok(())
+    }
+}
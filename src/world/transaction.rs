@@ -0,0 +1,362 @@
+//! All-or-nothing chunk writes across multiple region files.
+//!
+//! Writes are staged in memory by a [WorldTransaction]. [WorldTransaction::commit]
+//! applies them to copies of the region files that they touch (`.r.x.z.mca.txn`,
+//! reflinked where the filesystem supports it) and syncs the copies and their directories.
+//! It then writes a journal listing those files with their lengths and checksums, which
+//! is the commit point. The copies are then renamed over the originals, the directories
+//! are synced again, and the journal is removed.
+//!
+//! If the process stops before the journal is written, the originals are untouched
+//! and none of the writes are visible. If it stops after, [WorldTransaction::recover]
+//! finishes the renames, so all of the writes become visible. Call it before opening
+//! a world that may have been left in the middle of a commit. If a file doesn't match
+//! the journal, recovery fails with [McError::TransactionMismatch] rather than making
+//! only part of the writes visible.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    McError, McResult,
+    ioext::{atomic_replace, sync_directory},
+    math::coord::WorldCoord,
+    nbt::tag::NamedTag,
+};
+
+use super::{
+    io::region::{
+        RegionFile,
+        checksum::sidecar_path,
+        coord::RegionCoord,
+        snapshot::snapshot_region,
+        timestamp::Timestamp,
+    },
+    scan::{RegionKind, region_file_path},
+};
+
+/// The name of the journal file in the world directory.
+pub const JOURNAL_NAME: &str = "transaction.journal";
+
+/// The path that the copy of `region` is staged at.
+fn staged_path(region: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(region.file_name().unwrap_or_default());
+    name.push(".txn");
+    region.with_file_name(name)
+}
+
+/// A staged change to a chunk.
+enum StagedWrite {
+    Write(NamedTag, Timestamp),
+    Delete,
+}
+
+/// Chunk writes (and deletions) for any number of region files, committed together.
+pub struct WorldTransaction {
+    world_directory: PathBuf,
+    /// Staged writes by region file path, then by chunk.
+    regions: BTreeMap<PathBuf, BTreeMap<RegionCoord, StagedWrite>>,
+}
+
+impl WorldTransaction {
+    pub fn new<P: AsRef<Path>>(world_directory: P) -> Self {
+        Self {
+            world_directory: world_directory.as_ref().to_owned(),
+            regions: BTreeMap::new(),
+        }
+    }
+
+    fn stage(&mut self, kind: RegionKind, chunk: WorldCoord, write: StagedWrite) -> McResult<()> {
+        let path = region_file_path(&self.world_directory, chunk.region_coord(), kind)?;
+        self.regions.entry(path)
+            .or_default()
+            .insert(RegionCoord::from(chunk.xz()), write);
+        Ok(())
+    }
+
+    /// Stages `root` to be written for `chunk`, timestamped with the current time.
+    /// A later write to the same chunk replaces this one.
+    pub fn write_chunk(&mut self, kind: RegionKind, chunk: WorldCoord, root: NamedTag) -> McResult<()> {
+        self.write_chunk_timestamped(kind, chunk, root, Timestamp::utc_now())
+    }
+
+    pub fn write_chunk_timestamped<Ts: Into<Timestamp>>(&mut self, kind: RegionKind, chunk: WorldCoord, root: NamedTag, timestamp: Ts) -> McResult<()> {
+        self.stage(kind, chunk, StagedWrite::Write(root, timestamp.into()))
+    }
+
+    /// Stages `chunk` to be deleted.
+    pub fn delete_chunk(&mut self, kind: RegionKind, chunk: WorldCoord) -> McResult<()> {
+        self.stage(kind, chunk, StagedWrite::Delete)
+    }
+
+    /// The number of staged chunk writes and deletions.
+    pub fn len(&self) -> usize {
+        self.regions.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// The region files that the transaction will change.
+    pub fn region_files(&self) -> impl Iterator<Item = &Path> {
+        self.regions.keys().map(PathBuf::as_path)
+    }
+
+    /// Applies the staged writes to staged copies of the region files, then syncs their
+    /// directories so that the copies are still there after a crash.
+    fn stage_regions(&self) -> McResult<()> {
+        self.regions.iter().try_for_each(|(path, writes)| {
+            let staged = staged_path(path);
+            let mut region = if path.is_file() {
+                snapshot_region(path, &staged)?;
+                RegionFile::open(&staged)?
+            } else {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                // A copy left over from an earlier failed commit.
+                remove_if_exists(&staged)?;
+                RegionFile::create(&staged)?
+            };
+            writes.iter().try_for_each(|(&coord, write)| -> McResult<()> {
+                match write {
+                    StagedWrite::Write(root, timestamp) => {
+                        region.write_data_timestamped(coord, root, *timestamp)?;
+                    }
+                    StagedWrite::Delete => {
+                        if !region.get_sector(coord).is_empty() {
+                            region.delete_data(coord)?;
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+            region.close()
+        })?;
+        parent_directories(self.regions.keys()).iter().try_for_each(sync_directory)
+    }
+
+    /// Writes the journal, listing each region file (relative to the world directory, so that
+    /// the world can still be recovered after it is moved) with the length and checksum of its
+    /// staged copy. This is the commit point.
+    fn write_journal(&self, journal: &Path) -> McResult<()> {
+        let entries = self.regions.keys()
+            .map(|path| Ok((path, fingerprint(&staged_path(path))?)))
+            .collect::<McResult<Vec<_>>>()?;
+        atomic_replace(journal, |file| {
+            entries.iter().try_for_each(|(path, (length, checksum))| {
+                writeln!(file, "{length}\t{checksum:08x}\t{}", path.strip_prefix(&self.world_directory).unwrap_or(path).display())
+            })?;
+            Ok(())
+        })
+    }
+
+    /// Writes every staged change, so that either all of them or none of them become visible.
+    /// Returns the number of chunks that were written or deleted.
+    pub fn commit(self) -> McResult<usize> {
+        if self.regions.is_empty() {
+            return Ok(0);
+        }
+        let journal = self.world_directory.join(JOURNAL_NAME);
+        if let Err(err) = self.stage_regions() {
+            self.regions.keys().for_each(|path| {
+                let staged = staged_path(path);
+                let _ = std::fs::remove_file(sidecar_path(&staged));
+                let _ = std::fs::remove_file(staged);
+            });
+            return Err(err);
+        }
+        self.write_journal(&journal)?;
+        finish_commit(&self.world_directory, &journal)?;
+        Ok(self.len())
+    }
+
+    /// Finishes a commit that was interrupted after its journal was written.
+    /// Returns `true` if there was a commit to finish. Returns [McError::TransactionMismatch]
+    /// (and changes nothing) if a staged copy is missing or damaged, since finishing would
+    /// then only make part of the writes visible.
+    pub fn recover<P: AsRef<Path>>(world_directory: P) -> McResult<bool> {
+        let world_directory = world_directory.as_ref();
+        let journal = world_directory.join(JOURNAL_NAME);
+        if !journal.is_file() {
+            return Ok(false);
+        }
+        finish_commit(world_directory, &journal)?;
+        Ok(true)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> McResult<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The length and checksum of a file's contents.
+fn fingerprint(path: &Path) -> McResult<(u64, u32)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut length = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.update(buffer);
+        let read = buffer.len();
+        length += read as u64;
+        reader.consume(read);
+    }
+    Ok((length, hasher.finalize()))
+}
+
+/// The distinct directories that `paths` are in.
+fn parent_directories<'a, I: IntoIterator<Item = &'a PathBuf>>(paths: I) -> BTreeSet<PathBuf> {
+    paths.into_iter()
+        .filter_map(|path| path.parent())
+        .map(Path::to_owned)
+        .collect()
+}
+
+/// Reads the region files listed in `journal` with the fingerprints of their staged copies.
+fn read_journal(world_directory: &Path, journal: &Path) -> McResult<Vec<(PathBuf, (u64, u32))>> {
+    let malformed = || McError::TransactionMismatch(journal.to_owned());
+    BufReader::new(File::open(journal)?).lines()
+        .filter(|line| !line.as_ref().is_ok_and(String::is_empty))
+        .map(|line| {
+            let line = line?;
+            let mut fields = line.splitn(3, '\t');
+            let (Some(length), Some(checksum), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(malformed());
+            };
+            let length = length.parse().map_err(|_| malformed())?;
+            let checksum = u32::from_str_radix(checksum, 16).map_err(|_| malformed())?;
+            Ok((world_directory.join(path), (length, checksum)))
+        })
+        .collect()
+}
+
+/// Renames the staged copies listed in `journal` over their originals, then removes the journal.
+/// Every entry is checked before anything is renamed: its staged copy must match the journal,
+/// or if there is no staged copy (because it was already renamed), the region file must.
+/// This can be repeated.
+fn finish_commit(world_directory: &Path, journal: &Path) -> McResult<()> {
+    let entries = read_journal(world_directory, journal)?;
+    let mut pending = Vec::new();
+    for (path, expected) in entries.iter() {
+        let staged = staged_path(path);
+        if staged.is_file() {
+            if fingerprint(&staged)? != *expected {
+                return Err(McError::TransactionMismatch(staged));
+            }
+            pending.push((path, staged));
+        } else if !path.is_file() || fingerprint(path)? != *expected {
+            return Err(McError::TransactionMismatch(path.to_owned()));
+        }
+    }
+    for (path, staged) in pending {
+        // The sidecar goes first: the staged region file is what marks this entry as unfinished.
+        let staged_sidecar = sidecar_path(&staged);
+        if staged_sidecar.is_file() {
+            std::fs::rename(staged_sidecar, sidecar_path(path))?;
+        }
+        std::fs::rename(&staged, path)?;
+    }
+    // The renames must be durable before the journal that would redo them is removed.
+    parent_directories(entries.iter().map(|(path, _)| path)).iter().try_for_each(sync_directory)?;
+    std::fs::remove_file(journal)?;
+    sync_directory(world_directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::Dimension,
+        nbt::tag::Tag,
+    };
+
+    fn read(world: &Path, chunk: WorldCoord) -> McResult<Option<NamedTag>> {
+        let path = region_file_path(world, chunk.region_coord(), RegionKind::Terrain)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut region = RegionFile::open(path)?;
+        let coord = RegionCoord::from(chunk.xz());
+        if region.get_sector(coord).is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(region.read_data(coord)?))
+    }
+
+    #[test]
+    fn transaction_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        let a = WorldCoord::new(31, 0, Dimension::Overworld);
+        let b = WorldCoord::new(32, 0, Dimension::Overworld);
+        let mut transaction = WorldTransaction::new(world);
+        transaction.write_chunk(RegionKind::Terrain, a, NamedTag::new(Tag::Int(1)))?;
+        transaction.write_chunk(RegionKind::Terrain, b, NamedTag::new(Tag::Int(2)))?;
+        assert_eq!(transaction.region_files().count(), 2);
+        assert_eq!(transaction.commit()?, 2);
+        assert!(matches!(read(world, a)?.map(NamedTag::take_tag), Some(Tag::Int(1))));
+        assert!(matches!(read(world, b)?.map(NamedTag::take_tag), Some(Tag::Int(2))));
+
+        // A commit that stopped after its journal was written is finished by recover.
+        let mut transaction = WorldTransaction::new(world);
+        transaction.write_chunk(RegionKind::Terrain, a, NamedTag::new(Tag::Int(3)))?;
+        transaction.delete_chunk(RegionKind::Terrain, b)?;
+        transaction.stage_regions()?;
+        let journal = world.join(JOURNAL_NAME);
+        transaction.write_journal(&journal)?;
+        assert!(matches!(read(world, a)?.map(NamedTag::take_tag), Some(Tag::Int(1))));
+        assert!(WorldTransaction::recover(world)?);
+        assert!(!journal.exists());
+        assert!(matches!(read(world, a)?.map(NamedTag::take_tag), Some(Tag::Int(3))));
+        assert!(read(world, b)?.is_none());
+        assert!(!WorldTransaction::recover(world)?);
+        Ok(())
+    }
+
+    #[test]
+    fn lost_staged_file_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let world = dir.path();
+        let a = WorldCoord::new(31, 0, Dimension::Overworld);
+        let b = WorldCoord::new(32, 0, Dimension::Overworld);
+        let mut transaction = WorldTransaction::new(world);
+        transaction.write_chunk(RegionKind::Terrain, a, NamedTag::new(Tag::Int(1)))?;
+        transaction.write_chunk(RegionKind::Terrain, b, NamedTag::new(Tag::Int(2)))?;
+        transaction.commit()?;
+
+        // A crash after the journal was written lost one of the staged copies.
+        let mut transaction = WorldTransaction::new(world);
+        transaction.write_chunk(RegionKind::Terrain, a, NamedTag::new(Tag::Int(3)))?;
+        transaction.write_chunk(RegionKind::Terrain, b, NamedTag::new(Tag::Int(4)))?;
+        transaction.stage_regions()?;
+        let journal = world.join(JOURNAL_NAME);
+        transaction.write_journal(&journal)?;
+        let lost = staged_path(&region_file_path(world, b.region_coord(), RegionKind::Terrain)?);
+        std::fs::remove_file(&lost)?;
+        let region_b = region_file_path(world, b.region_coord(), RegionKind::Terrain)?;
+        assert!(matches!(WorldTransaction::recover(world), Err(McError::TransactionMismatch(path)) if path == region_b));
+        // Nothing was renamed, and the journal is kept.
+        assert!(journal.is_file());
+        assert!(matches!(read(world, a)?.map(NamedTag::take_tag), Some(Tag::Int(1))));
+        assert!(matches!(read(world, b)?.map(NamedTag::take_tag), Some(Tag::Int(2))));
+
+        // A damaged copy is refused too.
+        let region_a = region_file_path(world, a.region_coord(), RegionKind::Terrain)?;
+        File::options().append(true).open(staged_path(&region_a))?.write_all(&[0])?;
+        assert!(matches!(WorldTransaction::recover(world), Err(McError::TransactionMismatch(path)) if path == staged_path(&region_a)));
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Notifications for changes to the region files of a world, such as those made by a running
+//! server, for tools like live map renderers.
+//!
+//! A [RegionWatcher] watches the region folders of a dimension (with [notify]) and turns the
+//! file system events into [RegionEvent]s. The chunks that changed are found by comparing
+//! each modified file's header with the last one that was read (see [RegionFileInfo::refresh]).
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, RecvTimeoutError, channel},
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    McError, McResult,
+    math::coord::{Dimension, WorldCoord},
+};
+
+use super::{
+    io::region::{RegionExtension, RegionFileName, coord::RegionCoord, info::RegionFileInfo},
+    scan::{RegionKind, dimension_directory, region_files},
+};
+
+/// A change to the region files of a dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RegionEvent {
+    /// A region file was created or written to. `pos` is in region coordinates.
+    RegionModified { pos: WorldCoord, kind: RegionKind },
+    /// A region file was deleted.
+    RegionRemoved { pos: WorldCoord, kind: RegionKind },
+    /// A chunk was written or deleted. `coord` is in chunk coordinates.
+    ChunkChanged { coord: WorldCoord, kind: RegionKind },
+}
+
+/// Watches the region folders of a dimension. See the [module docs](self).
+pub struct RegionWatcher {
+    // Dropping the watcher stops the events.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    dimension: Dimension,
+    folders: Vec<(PathBuf, RegionKind)>,
+    /// The last header read from each region file.
+    regions: HashMap<PathBuf, RegionFileInfo>,
+}
+
+fn notify_error(err: notify::Error) -> McError {
+    McError::Custom(err.to_string())
+}
+
+impl RegionWatcher {
+    /// Starts watching the region folders (`region`, `entities`, and `poi`) of a dimension.
+    /// Only folders that exist when the watcher is created are watched.
+    pub fn new<P: AsRef<Path>>(world_directory: P, dimension: Dimension) -> McResult<Self> {
+        let world_directory = world_directory.as_ref();
+        let directory = dimension_directory(world_directory, dimension)?;
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+        let mut folders = Vec::new();
+        let mut regions = HashMap::new();
+        for kind in RegionKind::ALL {
+            let folder = directory.join(kind.folder());
+            if !folder.is_dir() {
+                continue;
+            }
+            watcher.watch(&folder, RecursiveMode::NonRecursive).map_err(notify_error)?;
+            folders.push((folder, kind));
+            for (_, path) in region_files(world_directory, dimension, kind)? {
+                // Files that can't be read yet are diffed against nothing when they change.
+                if let Ok(info) = RegionFileInfo::load(&path) {
+                    regions.insert(path, info);
+                }
+            }
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            dimension,
+            folders,
+            regions,
+        })
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// Returns the changes since the last call, without waiting.
+    pub fn poll(&mut self) -> McResult<Vec<RegionEvent>> {
+        let mut paths = BTreeSet::new();
+        while let Ok(event) = self.events.try_recv() {
+            self.collect(event, &mut paths)?;
+        }
+        Ok(self.process(paths))
+    }
+
+    /// Waits up to `timeout` for a change, then returns every change that has arrived.
+    /// Returns an empty list if nothing changed in that time.
+    pub fn wait(&mut self, timeout: Duration) -> McResult<Vec<RegionEvent>> {
+        let mut paths = BTreeSet::new();
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => self.collect(event, &mut paths)?,
+            Err(RecvTimeoutError::Timeout) => return Ok(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => return McError::custom("The region watcher stopped."),
+        }
+        while let Ok(event) = self.events.try_recv() {
+            self.collect(event, &mut paths)?;
+        }
+        Ok(self.process(paths))
+    }
+
+    fn collect(&self, event: notify::Result<notify::Event>, paths: &mut BTreeSet<PathBuf>) -> McResult<()> {
+        let event = event.map_err(notify_error)?;
+        if event.kind.is_access() {
+            return Ok(());
+        }
+        paths.extend(event.paths);
+        Ok(())
+    }
+
+    /// Finds the region and kind of a region file path in one of the watched folders.
+    fn region_of(&self, path: &Path) -> Option<(WorldCoord, RegionKind)> {
+        let (_, kind) = self.folders.iter().find(|(folder, _)| Some(folder.as_path()) == path.parent())?;
+        let name = RegionFileName::from_path(path).filter(|name| name.format == RegionExtension::Anvil)?;
+        Some((WorldCoord::new(name.x, name.z, self.dimension), *kind))
+    }
+
+    /// Turns the paths that changed into events, once per file.
+    fn process(&mut self, paths: BTreeSet<PathBuf>) -> Vec<RegionEvent> {
+        let mut events = Vec::new();
+        for path in paths {
+            let Some((pos, kind)) = self.region_of(&path) else {
+                continue;
+            };
+            if !path.is_file() {
+                if self.regions.remove(&path).is_some() {
+                    events.push(RegionEvent::RegionRemoved { pos, kind });
+                }
+                continue;
+            }
+            events.push(RegionEvent::RegionModified { pos, kind });
+            // A header that can't be read (such as that of a file that is still being
+            // created) is read again on the next change.
+            let changed = match self.regions.get_mut(&path) {
+                Some(info) => info.refresh().ok(),
+                None => RegionFileInfo::load(&path).ok().map(|info| {
+                    let present = (0..1024usize).filter(|&i| !info.header.sectors[i].is_empty()).map(Into::into).collect();
+                    self.regions.insert(path.clone(), info);
+                    present
+                }),
+            };
+            events.extend(changed.into_iter().flatten().map(|coord: RegionCoord| RegionEvent::ChunkChanged {
+                coord: WorldCoord::new(pos.x * 32 + coord.x() as i64, pos.z * 32 + coord.z() as i64, self.dimension),
+                kind,
+            }));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::io::region::RegionFile;
+
+    #[test]
+    fn watch_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let folder = dir.path().join("region");
+        std::fs::create_dir_all(&folder)?;
+        let mut region = RegionFile::create(folder.join("r.-1.2.mca"))?;
+        region.write_data((0, 0), &1i64)?;
+        let mut watcher = RegionWatcher::new(dir.path(), Dimension::Overworld)?;
+        assert!(watcher.poll()?.is_empty());
+
+        region.write_data((3, 4), &2i64)?;
+        region.close()?;
+        let mut events = Vec::new();
+        for _ in 0..50 {
+            events.extend(watcher.wait(Duration::from_millis(100))?);
+            if events.iter().any(|event| matches!(event, RegionEvent::ChunkChanged { .. })) {
+                break;
+            }
+        }
+        let pos = WorldCoord::new(-1, 2, Dimension::Overworld);
+        assert!(events.contains(&RegionEvent::RegionModified { pos, kind: RegionKind::Terrain }));
+        let chunks = events.iter().filter(|event| matches!(event, RegionEvent::ChunkChanged { .. })).collect::<Vec<_>>();
+        assert_eq!(chunks, [&RegionEvent::ChunkChanged { coord: WorldCoord::new(-29, 68, Dimension::Overworld), kind: RegionKind::Terrain }]);
+        Ok(())
+    }
+}
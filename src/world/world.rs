@@ -11,18 +11,31 @@ use crate::{McResult, McError, nbt::tag::NamedTag, math::bounds::{Bounds2, Bound
 use super::container::*;
 
 use super::{
-    blockregistry::BlockRegistry,
+    blockregistry::{BlockRegistry, REGISTRY_FILE_NAME},
     blockstate::*,
-    chunk::{Chunk, decode_chunk},
+    chunk::Chunk,
+    codec::{Anvil118Codec, ChunkCodec},
     io::region::{
         RegionFile,
+        Timestamp,
         coord::RegionCoord,
         regionfile::{
             RegionManager,
         },
     },
     block::CubeDirection,
+    forced::ForcedChunks,
+    storage::CommandStorage,
+    search::{find_players, find_item, PlayerInfo, ItemHit},
+    level::{Level, LevelWriteOptions, read_level_with_fallback, write_level_with},
+    height::{WorldHeight, dimension_id, world_height},
+    spawn::{level_path, set_world_spawn},
+    session::SessionLock,
+    writequeue::WriteQueue,
+    memory::MemoryReport,
+    scan::{RegionKind, dimension_directory, region_file_path},
 };
+use crate::util::memory::{MemorySize, table_size};
 use crate::math::coord::*;
 
 #[inline(always)]
@@ -75,7 +88,7 @@ impl<T> CubeNeighbors<T> {
 // }
 
 pub struct RegionSlot {
-    region: RegionFile,
+    pub(crate) region: RegionFile,
     load_count: usize,
 }
 
@@ -126,18 +139,64 @@ impl ChunkSlot {
     }
 }
 
-type ArcChunkSlot = Arc<Mutex<ChunkSlot>>;
-type ArcRegionSlot = Arc<Mutex<RegionSlot>>;
+pub type ArcChunkSlot = Arc<Mutex<ChunkSlot>>;
+pub type ArcRegionSlot = Arc<Mutex<RegionSlot>>;
+
+/// When each loaded chunk was last used, and its size when it was loaded or saved.
+/// This is what chunks are evicted by when there is a memory limit.
+#[derive(Default)]
+struct ChunkUse {
+    clock: u64,
+    chunks: HashMap<WorldCoord, (u64, usize)>,
+}
+
+impl ChunkUse {
+    fn touch(&mut self, coord: WorldCoord) {
+        self.clock += 1;
+        if let Some((last, _)) = self.chunks.get_mut(&coord) {
+            *last = self.clock;
+        }
+    }
+
+    fn record(&mut self, coord: WorldCoord, size: usize) {
+        self.clock += 1;
+        self.chunks.insert(coord, (self.clock, size));
+    }
+
+    /// The least recently used chunk other than `keep`.
+    fn least_recent(&self, keep: Option<WorldCoord>) -> Option<(WorldCoord, usize)> {
+        self.chunks.iter()
+            .filter(|(coord, _)| Some(**coord) != keep)
+            .min_by_key(|(_, (last, _))| *last)
+            .map(|(coord, (_, size))| (*coord, *size))
+    }
+}
 
 /*
 VirtualJavaWorld is for testing purposes. I plan on rewriting the entire
 system after I get a better idea of what I'm working with.
 */
-pub struct VirtualJavaWorld {
+pub struct VirtualJavaWorld<Codec: ChunkCodec<Chunk = Chunk> = Anvil118Codec> {
     pub block_registry: BlockRegistry,
+    /// Decodes chunks when they are loaded and encodes them when they are saved.
+    pub codec: Codec,
     pub chunks: HashMap<WorldCoord, ArcChunkSlot>,
     pub regions: HashMap<WorldCoord, ArcRegionSlot>,
     pub directory: PathBuf,
+    /// The session lock, if the world was opened with [VirtualJavaWorld::open_locked].
+    session: Option<SessionLock>,
+    /// The background writer, if it was started with [VirtualJavaWorld::enable_background_writer].
+    writer: Option<WriteQueue>,
+    /// Whether the block registry is saved with [VirtualJavaWorld::save_all], which is
+    /// the case when the world was opened with [VirtualJavaWorld::open_persistent].
+    persist_registry: bool,
+    /// The height of each dimension, found by [VirtualJavaWorld::height].
+    heights: HashMap<Dimension, WorldHeight>,
+    /// The memory limit set by [VirtualJavaWorld::set_memory_limit].
+    memory_limit: Option<usize>,
+    /// The use of each loaded chunk, for evicting chunks when there is a memory limit.
+    /// [VirtualJavaWorld::get_chunk] takes `&self`, so this is behind a lock.
+    chunk_use: Mutex<ChunkUse>,
 }
 
 // I would like to implement a system where I keep track of
@@ -151,28 +210,130 @@ impl VirtualJavaWorld {
             chunks: HashMap::new(),
             regions: HashMap::new(),
             directory: directory.as_ref().to_owned(),
+            session: None,
+            codec: Anvil118Codec::default(),
+            writer: None,
+            persist_registry: false,
+            heights: HashMap::new(),
+            memory_limit: None,
+            chunk_use: Mutex::new(ChunkUse::default()),
         }
     }
 
-    /// Get the directory that the region files are located at for each dimension.
-    pub fn get_region_directory(&self, dimension: Dimension) -> PathBuf {
-        self.directory.join(match dimension {
-            Dimension::Overworld => "region",
-            Dimension::Nether => "Dim-1/region",
-            Dimension::TheEnd => "Dim1/region",
-            Dimension::Other(_) => todo!(),
+    /// Opens a world with the block registry that was saved in the world directory
+    /// (see [REGISTRY_FILE_NAME]), so that block IDs are the same as in previous sessions.
+    /// The registry is saved again by [VirtualJavaWorld::save_all].
+    pub fn open_persistent(directory: impl AsRef<Path>) -> McResult<Self> {
+        let mut world = Self::open(directory);
+        let path = world.directory.join(REGISTRY_FILE_NAME);
+        if path.is_file() {
+            world.block_registry = BlockRegistry::load(path)?;
+        }
+        world.persist_registry = true;
+        Ok(world)
+    }
+
+    /// Opens a world and takes its `session.lock`, so that it can't be opened by the
+    /// game (or another program using the lock) while it is being edited.
+    /// Returns [McError::WorldLocked] if the world is already in use.
+    pub fn open_locked(directory: impl AsRef<Path>) -> McResult<Self> {
+        let session = SessionLock::acquire(&directory)?;
+        Ok(Self {
+            session: Some(session),
+            ..Self::open(directory)
         })
     }
+}
+
+impl<Codec: ChunkCodec<Chunk = Chunk>> VirtualJavaWorld<Codec> {
+    /// Replaces the codec that chunks are loaded and saved with. Chunks that are already
+    /// loaded are kept, and will be saved with the new codec.
+    pub fn with_codec<C: ChunkCodec<Chunk = Chunk>>(self, codec: C) -> VirtualJavaWorld<C> {
+        VirtualJavaWorld {
+            block_registry: self.block_registry,
+            codec,
+            chunks: self.chunks,
+            regions: self.regions,
+            directory: self.directory,
+            session: self.session,
+            writer: self.writer,
+            persist_registry: self.persist_registry,
+            heights: self.heights,
+            memory_limit: self.memory_limit,
+            chunk_use: self.chunk_use,
+        }
+    }
+
+    /// Saves chunks on a worker thread from now on: [VirtualJavaWorld::save_chunk] encodes
+    /// the chunk and queues it, and the worker compresses and writes it. At most `capacity`
+    /// chunks are queued before saving waits for the worker. Use [VirtualJavaWorld::flush_all]
+    /// to wait for queued chunks to be written.
+    pub fn enable_background_writer(&mut self, capacity: usize) -> McResult<()> {
+        if self.writer.is_none() {
+            self.writer = Some(WriteQueue::new(capacity)?);
+        }
+        Ok(())
+    }
+
+    /// Writes the chunks that are still queued and goes back to saving chunks directly.
+    pub fn disable_background_writer(&mut self) -> McResult<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn background_writer(&self) -> Option<&WriteQueue> {
+        self.writer.as_ref()
+    }
+
+    /// Waits until every saved chunk has been written (see [WriteQueue::flush_all]).
+    /// Without a background writer, chunks are written as they are saved, so this does nothing.
+    pub fn flush_all(&self) -> McResult<()> {
+        match &self.writer {
+            Some(writer) => writer.flush_all(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn session_lock(&self) -> Option<&SessionLock> {
+        self.session.as_ref()
+    }
+
+    /// The build height of `dimension`, from the world's DataVersion and the dimension's type
+    /// (see [world_height]). The result is cached. [Dimension::Other] has no dimension ID, so
+    /// its height must be given with [VirtualJavaWorld::set_height].
+    pub fn height(&mut self, dimension: Dimension) -> McResult<WorldHeight> {
+        if let Some(height) = self.heights.get(&dimension) {
+            return Ok(*height);
+        }
+        let Some(id) = dimension_id(dimension) else {
+            return McError::custom(format!("The height of {dimension:?} is unknown."));
+        };
+        let Some(height) = world_height(&self.directory, id)? else {
+            return McError::custom(format!("Dimension type of {id} not found."));
+        };
+        self.heights.insert(dimension, height);
+        Ok(height)
+    }
+
+    /// Sets the build height of `dimension`, replacing the one found by [VirtualJavaWorld::height].
+    pub fn set_height(&mut self, dimension: Dimension, height: WorldHeight) {
+        self.heights.insert(dimension, height);
+    }
+
+    /// Get the directory that the region files are located at for each dimension.
+    /// Returns an error for [Dimension::Other] (see [dimension_directory]).
+    pub fn get_region_directory(&self, dimension: Dimension) -> McResult<PathBuf> {
+        Ok(dimension_directory(&self.directory, dimension)?.join(RegionKind::Terrain.folder()))
+    }
 
     /// Loads a region file into memory so that it IO can be performed.
     pub fn get_or_load_region(&mut self, coord: WorldCoord) -> McResult<ArcRegionSlot> {
         if let Some(slot) = self.regions.get(&coord) {
             Ok(slot.clone())
         } else {
-            let regiondir = self.get_region_directory(coord.dimension);
-            let regname = format!("r.{}.{}.mca", coord.x, coord.z);
-            let regfilepath = regiondir.join(regname);
-            let regionfile = RegionFile::open_or_create(regfilepath)?;
+            let regionfile = RegionFile::open_or_create(region_file_path(&self.directory, coord, RegionKind::Terrain)?)?;
             let slot = RegionSlot::arc_new(regionfile);
             self.regions.insert(coord, slot.clone());
             Ok(slot)
@@ -183,11 +344,16 @@ impl VirtualJavaWorld {
     /// (This forces the loading of a chunk. If the chunk was already
     /// loaded, the old chunk will be discarded.)
     pub fn load_chunk(&mut self, coord: WorldCoord) -> McResult<ArcChunkSlot> {
+        let height = self.height(coord.dimension).ok();
         let region = self.get_or_load_region(coord.region_coord())?;
         let reglock = region.lock();
         if let Ok(mut regionlock) = reglock {
             let root = regionlock.region.read_data::<_, NamedTag>(coord.xz())?;
-            let chunk = decode_chunk(&mut self.block_registry, root.tag)?;
+            let mut chunk = self.codec.decode(&mut self.block_registry, root.take_tag())?;
+            if let Some(height) = height {
+                chunk.height = height.height;
+            }
+            let size = chunk.memory_size();
             let slot = ChunkSlot::arc_new(chunk);
             let old = self.chunks.insert(coord, slot.clone());
             // If there was already a chunk loaded at this coord, there's no need
@@ -195,6 +361,9 @@ impl VirtualJavaWorld {
             if old.is_none() {
                 regionlock.increment();
             }
+            drop(regionlock);
+            self.chunk_use().record(coord, size);
+            self.evict(Some(coord))?;
             Ok(slot)
         } else {
             McError::custom("Failed to lock region file.")
@@ -222,9 +391,14 @@ impl VirtualJavaWorld {
         }
     }
 
-    /// Get a chunk (if it has been loaded).
+    /// Get a chunk (if it has been loaded). This counts as a use of the chunk
+    /// for eviction (see [VirtualJavaWorld::set_memory_limit]).
     pub fn get_chunk(&self, coord: WorldCoord) -> Option<ArcChunkSlot> {
-        self.chunks.get(&coord).map(|slot| slot.clone())
+        let slot = self.chunks.get(&coord).cloned();
+        if slot.is_some() {
+            self.chunk_use().touch(coord);
+        }
+        slot
     }
 
     /// Attempts to save a chunk (assuming the chunk has already been loaded)
@@ -234,13 +408,24 @@ impl VirtualJavaWorld {
                 if !slot.dirty {
                     return Ok(());
                 }
+                if let Some(session) = &self.session {
+                    session.check()?;
+                }
                 let region = self.get_or_load_region(coord.region_coord())?;
+                if let Some(writer) = &self.writer {
+                    let root = NamedTag::new(self.codec.encode(&self.block_registry, &slot.chunk)?);
+                    writer.push(region, coord.xz(), root, Timestamp::utc_now())?;
+                    slot.dirty = false;
+                    return Ok(());
+                }
                 let reglock = region.lock();
                 if let Ok(mut region) = reglock {
-                    let nbt = slot.chunk.to_nbt(&self.block_registry);
-                    let root = NamedTag::new(nbt);
+                    let root = NamedTag::new(self.codec.encode(&self.block_registry, &slot.chunk)?);
                     region.region.write_data_with_utcnow(coord.xz(), &root)?;
                     slot.dirty = false;
+                    if let Some((_, size)) = self.chunk_use().chunks.get_mut(&coord) {
+                        *size = slot.chunk.memory_size();
+                    }
                     return Ok(());
                 }
             }
@@ -259,11 +444,20 @@ impl VirtualJavaWorld {
         })
     }
 
+    /// Saves every loaded chunk, in the order of [VirtualJavaWorld::loaded_chunks].
     pub fn save_all(&mut self) -> McResult<()> {
-        let keys_clone = self.chunks.keys().map(|c| *c).collect::<Box<[WorldCoord]>>();
-        keys_clone.into_iter().try_for_each(|coord| {
-            self.save_chunk(*coord)
-        })
+        self.loaded_chunks().into_iter().try_for_each(|coord| {
+            self.save_chunk(coord)
+        })?;
+        if self.persist_registry {
+            self.save_block_registry()?;
+        }
+        Ok(())
+    }
+
+    /// Saves the block registry to the world directory (see [REGISTRY_FILE_NAME]).
+    pub fn save_block_registry(&self) -> McResult<()> {
+        self.block_registry.save(self.directory.join(REGISTRY_FILE_NAME))
     }
 
     /// Remove a chunk from internal storage.
@@ -273,6 +467,7 @@ impl VirtualJavaWorld {
             return None;
         }
         let removed = self.chunks.remove(&coord);
+        self.chunk_use().chunks.remove(&coord);
         let mut unload_region: bool = false;
         {
             let region = self.regions.get(&coord.region_coord());
@@ -302,13 +497,94 @@ impl VirtualJavaWorld {
     pub fn unload_all(&mut self) {
         self.chunks.clear();
         self.regions.clear();
+        self.chunk_use().chunks.clear();
+    }
+
+    fn chunk_use(&self) -> std::sync::MutexGuard<'_, ChunkUse> {
+        // The use records are only ever replaced whole, so a poisoned lock is still usable.
+        self.chunk_use.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Estimates the memory held by the loaded chunks, the block registry, the open region
+    /// files, and the world's tables (see [MemorySize]).
+    pub fn memory_usage(&self) -> MemoryReport {
+        let chunks = self.chunks.values()
+            .filter_map(|slot| slot.lock().ok().map(|slot| slot.chunk.memory_size()))
+            .sum();
+        self.memory_report(chunks)
+    }
+
+    /// A [MemoryReport] with the size of the chunks given.
+    fn memory_report(&self, chunks: usize) -> MemoryReport {
+        let regions = self.regions.values()
+            .filter_map(|slot| slot.lock().ok().map(|slot| slot.region.memory_size()))
+            .sum();
+        let caches = table_size(self.chunks.capacity(), std::mem::size_of::<(WorldCoord, ArcChunkSlot)>())
+            + self.chunks.len() * std::mem::size_of::<Mutex<ChunkSlot>>()
+            + table_size(self.regions.capacity(), std::mem::size_of::<(WorldCoord, ArcRegionSlot)>())
+            + self.regions.len() * std::mem::size_of::<Mutex<RegionSlot>>()
+            + table_size(self.chunk_use().chunks.capacity(), std::mem::size_of::<(WorldCoord, (u64, usize))>());
+        MemoryReport {
+            chunks,
+            chunk_count: self.chunks.len(),
+            block_registry: self.block_registry.memory_size(),
+            regions,
+            region_count: self.regions.len(),
+            caches,
+        }
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// Limits the memory held by the world (as estimated by [VirtualJavaWorld::memory_usage]).
+    /// When loading a chunk takes the world over the limit, the least recently used chunks
+    /// are saved and unloaded until it is within the limit again. Chunks are used by loading
+    /// them and by [VirtualJavaWorld::get_chunk] (which the block methods go through).
+    /// The limit is applied right away; returns the number of chunks that were unloaded.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) -> McResult<usize> {
+        self.memory_limit = limit;
+        self.evict(None)
+    }
+
+    /// The total of [VirtualJavaWorld::memory_usage], with the chunk sizes recorded in [ChunkUse].
+    fn estimated_usage(&self) -> usize {
+        let chunks = self.chunk_use().chunks.values().map(|(_, size)| size).sum();
+        self.memory_report(chunks).total()
+    }
+
+    /// Saves and unloads the least recently used chunks (other than `keep`) until the world is
+    /// within its memory limit. The sizes of the chunks are those recorded when they were
+    /// loaded or saved, so that this doesn't have to measure every chunk.
+    fn evict(&mut self, keep: Option<WorldCoord>) -> McResult<usize> {
+        let Some(limit) = self.memory_limit else {
+            return Ok(0);
+        };
+        let mut usage = self.estimated_usage();
+        let mut evicted = 0;
+        while usage > limit {
+            let Some((coord, size)) = self.chunk_use().least_recent(keep) else {
+                break;
+            };
+            self.save_chunk(coord)?;
+            let regions = self.regions.len();
+            self.unload_chunk(coord);
+            usage = usage.saturating_sub(size);
+            if self.regions.len() < regions {
+                // The region file was closed with its last chunk.
+                usage = self.estimated_usage();
+            }
+            evicted += 1;
+        }
+        Ok(evicted)
     }
 
     /// Get a block id at the given coordinate.
     pub fn get_id(&self, coord: BlockCoord) -> Option<u32> {
         if let Some(slot) = self.get_chunk(coord.chunk_coord()) {
             if let Ok(slot) = slot.lock() {
-                return slot.chunk.get_id(coord.xyz());
+                return slot.chunk.get_id(coord.pos());
             }
         }
         None
@@ -332,7 +608,9 @@ impl VirtualJavaWorld {
         let Ok(mut slot) = slot.lock() else {
             return None;
         };
-        let old_id = slot.chunk.set_id(coord.xyz(), id);
+        let Ok(old_id) = slot.chunk.set_id(coord.pos(), id) else {
+            return None;
+        };
         if let Some(old_id) = old_id {
             if old_id != id {
                 slot.mark_dirty();
@@ -383,10 +661,77 @@ impl VirtualJavaWorld {
         }
     }
 
+    /// Loads the force-loaded chunks of a dimension.
+    pub fn forced_chunks(&self, dimension: Dimension) -> McResult<ForcedChunks> {
+        ForcedChunks::load(&self.directory, dimension)
+    }
+
+    /// Saves force-loaded chunks that were loaded with [VirtualJavaWorld::forced_chunks].
+    pub fn save_forced_chunks(&self, forced: &ForcedChunks) -> McResult<()> {
+        forced.save(&self.directory)
+    }
+
+    /// Loads the command storage of a namespace.
+    pub fn command_storage(&self, namespace: &str) -> McResult<CommandStorage> {
+        CommandStorage::load(&self.directory, namespace)
+    }
+
+    /// Saves command storage that was loaded with [VirtualJavaWorld::command_storage].
+    pub fn save_command_storage(&self, storage: &CommandStorage) -> McResult<()> {
+        storage.save(&self.directory)
+    }
+
+    /// Finds every player that has a player data file. See [super::search::find_players].
+    pub fn find_players(&self) -> McResult<Vec<PlayerInfo>> {
+        find_players(&self.directory)
+    }
+
+    /// Finds every stack of an item on disk. See [super::search::find_item].
+    /// Chunks that are loaded and haven't been saved are searched as they were last saved.
+    pub fn find_item(&self, item_id: &str) -> McResult<Vec<ItemHit>> {
+        find_item(&self.directory, item_id)
+    }
+
+    /// Reads `level.dat`, or `level.dat_old` if `level.dat` is corrupt.
+    pub fn level(&self) -> McResult<Level> {
+        read_level_with_fallback(level_path(&self.directory))
+    }
+
+    /// Writes `level.dat`, keeping the previous one as `level.dat_old`.
+    pub fn save_level(&self, level: &Level) -> McResult<()> {
+        write_level_with(level_path(&self.directory), level, &LevelWriteOptions::default())?;
+        Ok(())
+    }
+
+    /// Reads the world spawn from `level.dat`.
+    pub fn spawn(&self) -> McResult<BlockPos> {
+        Ok(self.level()?.spawn())
+    }
+
+    /// Moves the world spawn. See [super::spawn::set_world_spawn].
+    pub fn set_spawn<C: Into<BlockPos>>(&self, spawn: C, angle: f32, force_load: bool) -> McResult<()> {
+        set_world_spawn(&self.directory, spawn, angle, force_load)?;
+        Ok(())
+    }
+
     pub fn is_chunk_loaded(&self, coord: WorldCoord) -> bool {
         self.chunks.contains_key(&coord)
     }
 
+    /// The coordinates of the loaded chunks, sorted (by x, then z, then dimension).
+    pub fn loaded_chunks(&self) -> Vec<WorldCoord> {
+        let mut coords = self.chunks.keys().copied().collect::<Vec<_>>();
+        coords.sort();
+        coords
+    }
+
+    /// The region coordinates of the open region files, sorted like [VirtualJavaWorld::loaded_chunks].
+    pub fn loaded_regions(&self) -> Vec<WorldCoord> {
+        let mut coords = self.regions.keys().copied().collect::<Vec<_>>();
+        coords.sort();
+        coords
+    }
+
     pub fn copy_blocks(&self, dimension: Dimension, bounds: Bounds3) -> BlockContainer {
         let size = bounds.size::<I64Vec3>();
         todo!()
@@ -396,7 +741,16 @@ impl VirtualJavaWorld {
         todo!()
     }
 
-    pub fn fill_area_id(&mut self, dimension: Dimension, bounds: Bounds3, id: u32) {
+    /// Sets every block in `bounds` to `id`. The bounds are clamped to the height of the
+    /// dimension (if it is known).
+    pub fn fill_area_id(&mut self, dimension: Dimension, mut bounds: Bounds3, id: u32) {
+        if let Ok(height) = self.height(dimension) {
+            bounds.min.y = bounds.min.y.max(height.range().start);
+            bounds.max.y = bounds.max.y.min(height.range().end - 1);
+            if bounds.min.y > bounds.max.y {
+                return;
+            }
+        }
         bounds.for_each(|coord| {
             let (x,y,z): (i64, i64, i64) = coord.into();
             self.set_id(dimension.blockcoord(x, y, z), id);
@@ -0,0 +1,208 @@
+//! A background writer for chunks.
+//!
+//! [WriteQueue] owns a worker thread. Chunks are queued with their encoded root tag,
+//! and the worker compresses and writes them to their region file while the caller
+//! keeps working. The queue holds a limited number of chunks: once it is full,
+//! [WriteQueue::push] blocks until the worker catches up. [WriteQueue::flush_all]
+//! waits until everything queued before it has been written.
+//!
+//! Region files are shared with the caller through [ArcRegionSlot], so that reads
+//! made while writes are pending see the same header as the worker.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Sender, SyncSender, channel, sync_channel},
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    McError, McResult,
+    nbt::tag::NamedTag,
+};
+
+use super::{
+    io::region::{coord::RegionCoord, timestamp::Timestamp},
+    world::ArcRegionSlot,
+};
+
+enum Job {
+    Write {
+        region: ArcRegionSlot,
+        coord: RegionCoord,
+        root: NamedTag,
+        timestamp: Timestamp,
+    },
+    Flush(Sender<McResult<()>>),
+}
+
+/// Writes chunks to region files on a worker thread.
+pub struct WriteQueue {
+    sender: Option<SyncSender<Job>>,
+    worker: Option<JoinHandle<()>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl WriteQueue {
+    /// Starts the worker thread. At most `capacity` chunks are queued at once
+    /// (a capacity of 0 makes every push wait for the worker).
+    pub fn new(capacity: usize) -> McResult<Self> {
+        let (sender, receiver) = sync_channel::<Job>(capacity);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let worker_pending = pending.clone();
+        let worker = std::thread::Builder::new()
+            .name("mcutil-write-queue".to_owned())
+            .spawn(move || {
+                // Region files written since the last flush, and the first error since then.
+                let mut written: Vec<ArcRegionSlot> = Vec::new();
+                let mut error: Option<McError> = None;
+                for job in receiver {
+                    match job {
+                        Job::Write { region, coord, root, timestamp } => {
+                            let result = match region.lock() {
+                                Ok(mut slot) => slot.region.write_data_timestamped(coord, &root, timestamp).map(|_| ()),
+                                Err(_) => McError::custom("Failed to lock region file."),
+                            };
+                            if let Err(err) = result {
+                                error.get_or_insert(err);
+                            }
+                            if !written.iter().any(|slot| Arc::ptr_eq(slot, &region)) {
+                                written.push(region);
+                            }
+                            worker_pending.fetch_sub(1, Ordering::AcqRel);
+                        }
+                        Job::Flush(reply) => {
+                            for region in written.drain(..) {
+                                let result = match region.lock() {
+                                    Ok(mut slot) => slot.region.flush(),
+                                    Err(_) => McError::custom("Failed to lock region file."),
+                                };
+                                if let Err(err) = result {
+                                    error.get_or_insert(err);
+                                }
+                            }
+                            let _ = reply.send(error.take().map_or(Ok(()), Err));
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            worker: Some(worker),
+            pending,
+        })
+    }
+
+    fn send(&self, job: Job) -> McResult<()> {
+        match &self.sender {
+            Some(sender) => sender.send(job).or_else(|_| McError::custom("The write queue's worker has stopped.")),
+            None => McError::custom("The write queue's worker has stopped."),
+        }
+    }
+
+    /// Queues `root` to be written at `coord` of `region`, blocking while the queue is full.
+    /// Errors from the write are returned by the next [WriteQueue::flush_all].
+    pub fn push<C: Into<RegionCoord>, Ts: Into<Timestamp>>(&self, region: ArcRegionSlot, coord: C, root: NamedTag, timestamp: Ts) -> McResult<()> {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        let result = self.send(Job::Write {
+            region,
+            coord: coord.into(),
+            root,
+            timestamp: timestamp.into(),
+        });
+        if result.is_err() {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+        }
+        result
+    }
+
+    /// The number of chunks that have been queued but not yet written.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Waits until every chunk queued before this call has been written and the region
+    /// files have been flushed. Returns the first error since the last flush, if there was one.
+    pub fn flush_all(&self) -> McResult<()> {
+        let (reply, result) = channel();
+        self.send(Job::Flush(reply))?;
+        result.recv().unwrap_or_else(|_| McError::custom("The write queue's worker has stopped."))
+    }
+
+    /// Writes everything that is queued and stops the worker.
+    pub fn finish(mut self) -> McResult<()> {
+        let result = self.flush_all();
+        self.stop();
+        result
+    }
+
+    fn stop(&mut self) {
+        // Dropping the sender ends the worker's loop once the queue is empty.
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Queued chunks are still written when the queue is dropped, but errors are lost. Use
+/// [WriteQueue::finish] to see them.
+impl Drop for WriteQueue {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::coord::{BlockCoord, Dimension, WorldCoord},
+        nbt::tag::Tag,
+        world::{
+            blockregistry::BlockRegistry,
+            blockstate::BlockState,
+            chunk::{decode_chunk, encode_chunk, tests::empty_chunk},
+            io::region::RegionFile,
+            world::VirtualJavaWorld,
+        },
+    };
+
+    #[test]
+    fn write_queue_test() -> McResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("region").join("r.0.0.mca");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        {
+            let registry = BlockRegistry::with_air();
+            let mut region = RegionFile::create(&path)?;
+            for x in 0..4 {
+                region.write_data((x, 0), &NamedTag::new(Tag::Compound(encode_chunk(&registry, &empty_chunk(x, -4, 0)))))?;
+            }
+        }
+        let mut world = VirtualJavaWorld::open(dir.path());
+        world.enable_background_writer(1)?;
+        let stone = world.block_registry.register(BlockState::from("minecraft:stone"));
+        for x in 0..4 {
+            world.load_chunk(WorldCoord::new(x, 0, Dimension::Overworld))?;
+            world.set_id(BlockCoord::new(x * 16 + 1, 2, 3, Dimension::Overworld), stone);
+        }
+        world.save_all()?;
+        world.flush_all()?;
+        assert_eq!(world.background_writer().map(WriteQueue::pending), Some(0));
+        world.disable_background_writer()?;
+        world.unload_all();
+
+        let mut region = RegionFile::open(&path)?;
+        let mut registry = BlockRegistry::with_air();
+        for x in 0..4 {
+            let root: NamedTag = region.read_data((x, 0))?;
+            let chunk = decode_chunk(&mut registry, root.take_tag())?;
+            let id = chunk.get_id((x as i64 * 16 + 1, 2, 3)).unwrap();
+            assert_eq!(registry.get(id).map(BlockState::name), Some("minecraft:stone"));
+        }
+        Ok(())
+    }
+}